@@ -18,14 +18,14 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     let messages = vec![
         Message {
             role: Role::System,
-            content: "You are a helpful AI assistant.".to_string(),
+            content: "You are a helpful AI assistant.".into(),
             tool_calls: None,
             tool_call_id: None,
             name: None,
         },
         Message {
             role: Role::User,
-            content: "Hello! Can you tell me about Rust programming?".to_string(),
+            content: "Hello! Can you tell me about Rust programming?".into(),
             tool_calls: None,
             tool_call_id: None,
             name: None,
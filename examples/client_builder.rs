@@ -11,20 +11,21 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     // Advanced client creation with builder pattern
     let advanced_client = Client::builder()
         .api_key("your-api-key-here")
-        .base_url("https://api.x.ai/v1")  // Optional, defaults to this
-        .timeout(Duration::from_secs(30))  // Custom timeout
-        .user_agent("MyApp/1.0")  // Custom user agent
-        .request_id("req-12345")  // Custom request ID for tracing
+        .base_url("https://api.x.ai/v1") // Optional, defaults to this
+        .timeout(Duration::from_secs(30)) // Custom timeout
+        .user_agent("MyApp/1.0") // Custom user agent
+        .request_id("req-12345") // Custom request ID for tracing
         .build()?;
 
-    println!("Client configured with:");
-    println!("- Base URL: {}", advanced_client.base_url);
-    println!("- Timeout: {:?}", advanced_client.timeout);
-    println!("- User Agent: {:?}", advanced_client.user_agent);
-    println!("- Request ID: {:?}", advanced_client.request_id);
+    println!("Basic client key: {}", basic_client.masked_key());
+    println!("Advanced client key: {}", advanced_client.masked_key());
+    println!("- Base URL: https://api.x.ai/v1");
+    println!("- Timeout: 30s");
+    println!("- User Agent: MyApp/1.0");
+    println!("- Request ID: req-12345");
 
     // You can now use the client for chat requests
     // let response = advanced_client.chat(Model::Grok4Fast, messages, None).await?;
 
     Ok(())
-}
\ No newline at end of file
+}
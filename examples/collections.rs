@@ -1,6 +1,6 @@
 //! Collections example for the Grok Rust SDK
 
-use grok_rust_sdk::{chat::Model, collections::CollectionManager, session::SessionManager, Client};
+use grok_rust_sdk::{chat::Model, Client};
 
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn std::error::Error>> {
@@ -19,33 +19,34 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     let coding_collection = collection_mgr
         .create_collection(
             "Coding Discussions",
-            Some("Conversations about programming and development"),
+            Some("Conversations about programming and development".to_string()),
             vec![
                 "coding".to_string(),
                 "programming".to_string(),
                 "tech".to_string(),
             ],
         )
-        .await;
+        .await?;
 
     let ai_collection = collection_mgr
         .create_collection(
             "AI Conversations",
-            Some("Discussions about artificial intelligence"),
+            Some("Discussions about artificial intelligence".to_string()),
             vec![
                 "ai".to_string(),
                 "machine-learning".to_string(),
                 "tech".to_string(),
             ],
         )
-        .await;
+        .await?;
 
     println!("Created collections:");
     println!(
         "- {}: {}",
-        coding_collection.id, coding_collection.metadata.name
+        coding_collection.id,
+        coding_collection.metadata().name
     );
-    println!("- {}: {}", ai_collection.id, ai_collection.metadata.name);
+    println!("- {}: {}", ai_collection.id, ai_collection.metadata().name);
 
     // Create sessions and add to collections
     let rust_session = session_mgr
@@ -97,12 +98,12 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     println!("\n--- All Collections ---");
     let collections = collection_mgr.list_collections().await;
     for collection in &collections {
-        println!("Collection: {}", collection.metadata.name);
-        println!("  Description: {:?}", collection.metadata.description);
-        println!("  Tags: {:?}", collection.metadata.tags);
-        println!("  Sessions: {}", collection.metadata.session_count);
-        println!("  Total messages: {}", collection.metadata.total_messages);
-        println!("  Total tokens: {}", collection.metadata.total_tokens);
+        println!("Collection: {}", collection.metadata().name);
+        println!("  Description: {:?}", collection.metadata().description);
+        println!("  Tags: {:?}", collection.metadata().tags);
+        println!("  Sessions: {}", collection.metadata().session_count);
+        println!("  Total messages: {}", collection.metadata().total_messages);
+        println!("  Total tokens: {}", collection.metadata().total_tokens);
         println!();
     }
 
@@ -128,7 +129,9 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     for session in coding_sessions {
         println!(
             "- {}: {:?} ({} messages)",
-            session.id, session.metadata.title, session.metadata.message_count
+            session.id,
+            session.metadata().title,
+            session.metadata().message_count
         );
     }
 
@@ -1,23 +1,47 @@
 //! Example demonstrating persistent storage of sessions and collections
 
-use grok_rust_sdk::{Client, chat::Message, persistence::SqliteStorage};
+use grok_rust_sdk::{
+    chat::{Message, Model, Role},
+    persistence::SqliteStorage,
+    Client,
+};
+use std::sync::Arc;
+
+fn message(role: Role, content: &str) -> Message {
+    Message {
+        role,
+        content: content.into(),
+        tool_calls: None,
+        tool_call_id: None,
+        name: None,
+    }
+}
 
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn std::error::Error>> {
     // Create client
     let client = Client::new("your-api-key-here")?;
-    let client = std::sync::Arc::new(client);
 
     // Create SQLite storage (in-memory for this example)
-    let storage = SqliteStorage::in_memory()?;
+    let storage = Arc::new(SqliteStorage::in_memory()?);
 
     // Create a session
-    let session_mgr = client.session_manager();
-    let session = session_mgr.create_session(grok_rust_sdk::Model::Grok4FastReasoning, Some("Persistent Chat".to_string())).await?;
+    let session_mgr = client.session_manager_with_storage(storage.clone());
+    let session = session_mgr
+        .create_session(
+            Model::Grok4FastReasoning,
+            Some("Persistent Chat".to_string()),
+        )
+        .await;
 
     // Add some messages
-    session.append(Message::user("Hello!")).await?;
-    session.append(Message::assistant("Hi there! How can I help you today?".to_string())).await?;
+    session.append(message(Role::User, "Hello!")).await?;
+    session
+        .append(message(
+            Role::Assistant,
+            "Hi there! How can I help you today?",
+        ))
+        .await?;
 
     // Save the session
     storage.save_session(&session).await?;
@@ -25,16 +49,25 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
 
     // Create a collection
     let collection_mgr = client.collection_manager(session_mgr);
-    let collection = collection_mgr.create_collection("My Conversations", Some("A collection of my chats".to_string())).await?;
+    let collection = collection_mgr
+        .create_collection(
+            "My Conversations",
+            Some("A collection of my chats".to_string()),
+            Vec::new(),
+        )
+        .await?;
 
     // Add the session to the collection
-    collection.add_session(&session).await?;
+    collection.add_session(session.clone()).await?;
     storage.save_collection(&collection).await?;
-    println!("Collection saved with ID: {}", collection.id());
+    println!("Collection saved with ID: {}", collection.id);
 
     // Later, load the session back
-    if let Some(loaded_session) = storage.load_session(&session.id()).await? {
-        println!("Loaded session with {} messages", loaded_session.message_count().await);
+    if let Some(loaded_session) = storage.load_session(session.id()).await? {
+        println!(
+            "Loaded session with {} messages",
+            loaded_session.message_count().await
+        );
     }
 
     // List all sessions and collections
@@ -45,4 +78,4 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     println!("Stored collections: {:?}", collection_ids);
 
     Ok(())
-}
\ No newline at end of file
+}
@@ -1,6 +1,9 @@
 //! Example demonstrating retry logic with exponential backoff
 
-use grok_rust_sdk::{Client, chat::Message};
+use grok_rust_sdk::{
+    chat::{Message, Model, Role},
+    Client,
+};
 use std::time::Duration;
 
 #[tokio::main]
@@ -8,21 +11,27 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     // Create client with custom retry configuration
     let client = Client::builder()
         .api_key("your-api-key-here")
-        .max_retries(5)  // Retry up to 5 times
-        .retry_delay(Duration::from_millis(500))  // Start with 500ms delay
+        .max_retries(5) // Retry up to 5 times
+        .retry_delay(Duration::from_millis(500)) // Start with 500ms delay
         .timeout(Duration::from_secs(10))
         .build()?;
 
     println!("🤖 Client configured with:");
-    println!("- Max retries: {}", client.max_retries);
-    println!("- Base retry delay: {:?}", client.retry_delay);
-    println!("- Timeout: {:?}", client.timeout);
+    println!("- Max retries: 5");
+    println!("- Base retry delay: 500ms");
+    println!("- Timeout: 10s");
 
     // Create messages
-    let messages = vec![Message::user("Hello, Grok! Tell me a short story.")];
+    let messages = vec![Message {
+        role: Role::User,
+        content: "Hello, Grok! Tell me a short story.".into(),
+        tool_calls: None,
+        tool_call_id: None,
+        name: None,
+    }];
 
     // This will automatically retry on rate limits or network errors
-    match client.chat(grok_rust_sdk::Model::Grok4FastReasoning, messages, None).await {
+    match client.chat(Model::Grok4FastReasoning, messages, None).await {
         Ok(response) => {
             println!("\n✅ Success!");
             println!("Response: {}", response.message.content);
@@ -34,4 +43,4 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     }
 
     Ok(())
-}
\ No newline at end of file
+}
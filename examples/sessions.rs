@@ -1,6 +1,6 @@
 //! Session management example for the Grok Rust SDK
 
-use grok_rust_sdk::{chat::Model, session::SessionManager, Client};
+use grok_rust_sdk::{chat::Model, Client};
 
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn std::error::Error>> {
@@ -23,7 +23,7 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         .await;
 
     println!("Created session: {}", session.id);
-    println!("Session title: {:?}", session.metadata.title);
+    println!("Session title: {:?}", session.metadata().title);
 
     // First interaction
     println!("\n--- First Interaction ---");
@@ -48,8 +48,8 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     let messages = session.messages().await;
 
     println!("Total messages: {}", message_count);
-    println!("Session created: {}", session.metadata.created_at);
-    println!("Last updated: {}", session.metadata.updated_at);
+    println!("Session created: {}", session.metadata().created_at);
+    println!("Last updated: {}", session.metadata().updated_at);
 
     println!("\n--- Conversation History ---");
     for (i, message) in messages.iter().enumerate() {
@@ -71,7 +71,9 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     for session in &all_sessions {
         println!(
             "- {}: {:?} ({} messages)",
-            session.id, session.metadata.title, session.metadata.message_count
+            session.id,
+            session.metadata().title,
+            session.metadata().message_count
         );
     }
 
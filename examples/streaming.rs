@@ -1,7 +1,10 @@
 //! Example demonstrating streaming chat completions
 
-use grok_rust_sdk::{Client, chat::Message};
 use futures::StreamExt;
+use grok_rust_sdk::{
+    chat::{Message, Model, Role},
+    Client,
+};
 
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn std::error::Error>> {
@@ -9,10 +12,19 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     let client = Client::new("your-api-key-here")?;
 
     // Create messages
-    let messages = vec![Message::user("Tell me a short story about a robot learning to paint.")];
+    let messages = vec![Message {
+        role: Role::User,
+        content: "Tell me a short story about a robot learning to paint.".into(),
+        tool_calls: None,
+        tool_call_id: None,
+        name: None,
+    }];
 
     // Stream the response
-    let mut stream = client.chat_stream(grok_rust_sdk::Model::Grok4FastReasoning, messages, None).await?;
+    let stream = client
+        .chat_stream(Model::Grok4FastReasoning, messages, None)
+        .await?;
+    futures::pin_mut!(stream);
 
     println!("🤖 Streaming response:");
     println!("---");
@@ -38,4 +50,4 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     println!("Stream complete!");
 
     Ok(())
-}
\ No newline at end of file
+}
@@ -6,7 +6,6 @@ use grok_rust_sdk::{
     tools::{ToolExecutor, ToolRegistry, ToolSpec},
     Client,
 };
-use serde_json;
 
 #[derive(Debug)]
 struct CalculatorTool;
@@ -44,6 +43,11 @@ impl ToolExecutor for CalculatorTool {
                 }
             }
         };
+
+        Ok(serde_json::json!({
+            "expression": expression,
+            "result": result
+        }))
     }
 
     fn spec(&self) -> ToolSpec {
@@ -60,6 +64,7 @@ impl ToolExecutor for CalculatorTool {
                 },
                 "required": ["expression"]
             }),
+            strict: false,
         }
     }
 }
@@ -108,6 +113,7 @@ impl ToolExecutor for WebSearchTool {
                 },
                 "required": ["query"]
             }),
+            strict: false,
         }
     }
 }
@@ -120,7 +126,7 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
 
     // Create client and tool registry
     let client = Client::new(api_key)?;
-    let mut registry = ToolRegistry::new();
+    let registry = ToolRegistry::new();
 
     // Register tools
     registry.register(CalculatorTool);
@@ -139,7 +145,7 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         Message {
             role: Role::System,
             content: "You are a helpful assistant with access to tools. Use them when appropriate."
-                .to_string(),
+                .into(),
             tool_calls: None,
             tool_call_id: None,
             name: None,
@@ -148,7 +154,7 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
             role: Role::User,
             content:
                 "What is 15 * 7? Also, can you search for the latest news about Rust programming?"
-                    .to_string(),
+                    .into(),
             tool_calls: None,
             tool_call_id: None,
             name: None,
@@ -159,7 +165,11 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
 
     // Send request with tools
     let response = client
-        .chat(Model::Grok4FastReasoning, messages.clone(), Some(tools))
+        .chat(
+            Model::Grok4FastReasoning,
+            messages.clone(),
+            Some(tools.into_iter().map(Into::into).collect()),
+        )
         .await?;
 
     println!("Assistant response: {}", response.message.content);
@@ -173,7 +183,7 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
             println!("Arguments: {}", tool_call.function.arguments);
 
             // Execute the tool
-            let result = registry.execute_tool_call(tool_call).await?;
+            let result = registry.execute_tool_call(&tool_call.into()).await?;
             println!("Result: {}", result.content);
 
             // In a real conversation, you would add this result back to messages
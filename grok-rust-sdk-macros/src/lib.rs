@@ -0,0 +1,437 @@
+//! Procedural macros for the Grok Rust SDK
+//!
+//! Use these through `grok_rust_sdk` with the `macros` feature enabled.
+
+use proc_macro::TokenStream;
+use quote::{format_ident, quote};
+use syn::parse::Parser;
+use syn::punctuated::Punctuated;
+use syn::{
+    Expr, ExprLit, FnArg, GenericArgument, ItemFn, Lit, Meta, Pat, PathArguments, ReturnType,
+    Token, Type,
+};
+
+/// Turn a function into a tool executor
+///
+/// Generates a unit struct named after the function in `PascalCase` that
+/// implements `ToolExecutor`. The tool name is the function name, the
+/// description is the doc comment, and parameter descriptions come from a
+/// rustdoc-style `# Arguments` section. `Option<T>` parameters are optional.
+///
+/// ```rust,ignore
+/// use grok_rust_sdk::grok_tool;
+///
+/// /// Get the current weather for a city
+/// ///
+/// /// # Arguments
+/// /// * `city` - Name of the city
+/// /// * `unit` - "celsius" or "fahrenheit"
+/// #[grok_tool]
+/// async fn get_weather(city: String, unit: Option<String>) -> Result<serde_json::Value, std::io::Error> {
+///     Ok(serde_json::json!({ "city": city, "temperature": 21 }))
+/// }
+///
+/// registry.register(GetWeather);
+/// ```
+///
+/// The tool name can be overridden with `#[grok_tool(name = "weather")]`.
+///
+/// Parameters of other types, such as enums and structs, need `Deserialize`
+/// and `schemars::JsonSchema`, and their schema comes from the latter. To take
+/// all arguments as one typed struct, mark its parameter `#[args]`; the struct's
+/// fields become the tool's parameters.
+///
+/// Borrowed parameters such as `&str`, `&[T]` and `Option<&str>` are
+/// deserialized into their owned form and passed by reference:
+///
+/// ```rust,ignore
+/// #[derive(Deserialize, JsonSchema)]
+/// struct SearchArgs {
+///     /// Text to search for
+///     query: String,
+///     limit: Option<usize>,
+/// }
+///
+/// /// Search the knowledge base
+/// #[grok_tool]
+/// async fn search(#[args] args: SearchArgs) -> Vec<String> {
+///     vec![args.query]
+/// }
+/// ```
+#[proc_macro_attribute]
+pub fn grok_tool(attr: TokenStream, item: TokenStream) -> TokenStream {
+    let function = syn::parse_macro_input!(item as ItemFn);
+    match expand(attr.into(), function) {
+        Ok(tokens) => tokens.into(),
+        Err(error) => error.to_compile_error().into(),
+    }
+}
+
+fn expand(
+    attr: proc_macro2::TokenStream,
+    mut function: ItemFn,
+) -> syn::Result<proc_macro2::TokenStream> {
+    let fn_name = &function.sig.ident;
+    let mut tool_name = fn_name.to_string();
+
+    let options = Punctuated::<Meta, Token![,]>::parse_terminated.parse2(attr)?;
+    for option in options {
+        match option {
+            Meta::NameValue(nv) if nv.path.is_ident("name") => {
+                tool_name = string_literal(&nv.value)?
+            }
+            other => return Err(syn::Error::new_spanned(other, "expected `name = \"...\"`")),
+        }
+    }
+
+    if !function.sig.generics.params.is_empty() {
+        return Err(syn::Error::new_spanned(
+            &function.sig.generics,
+            "#[grok_tool] functions cannot be generic",
+        ));
+    }
+
+    let (description, arg_docs) = parse_docs(&function.attrs);
+
+    let mut properties = Vec::new();
+    let mut required = Vec::new();
+    let mut bindings = Vec::new();
+    let mut call_args = Vec::new();
+    let mut args_struct = None;
+    let params = function.sig.inputs.len();
+
+    for input in &mut function.sig.inputs {
+        let FnArg::Typed(arg) = input else {
+            return Err(syn::Error::new_spanned(
+                input,
+                "#[grok_tool] functions cannot take `self`",
+            ));
+        };
+        let Pat::Ident(pat) = arg.pat.as_ref() else {
+            return Err(syn::Error::new_spanned(
+                &arg.pat,
+                "expected a plain parameter name",
+            ));
+        };
+
+        let ident = &pat.ident;
+        let name = ident.to_string();
+        let (ty, pass) = owned_binding(&arg.ty)?;
+        let ty = &ty;
+
+        let marked = arg.attrs.iter().any(|attr| attr.path().is_ident("args"));
+        arg.attrs.retain(|attr| !attr.path().is_ident("args"));
+        if marked {
+            if params > 1 {
+                return Err(syn::Error::new_spanned(
+                    ident,
+                    "an `#[args]` parameter must be the function's only parameter",
+                ));
+            }
+            bindings.push(quote! {
+                let #ident: #ty = ::grok_rust_sdk::__private::serde_json::from_value(args)
+                    .map_err(|e| ::grok_rust_sdk::GrokError::ToolExecution(
+                        format!("Invalid arguments for tool '{}': {}", #tool_name, e),
+                    ))?;
+            });
+            call_args.push(pass.call_arg(ident));
+            args_struct = Some(ty.clone());
+            continue;
+        }
+
+        let optional = option_inner(ty).is_some();
+        let schema = type_schema(option_inner(ty).unwrap_or(ty));
+        let doc = arg_docs
+            .iter()
+            .find(|(arg, _)| *arg == name)
+            .map(|(_, doc)| quote! { schema["description"] = ::grok_rust_sdk::__private::serde_json::json!(#doc); });
+
+        properties.push(quote! {
+            properties.insert(#name.to_string(), {
+                let mut schema = #schema;
+                #doc
+                schema
+            });
+        });
+        if !optional {
+            required.push(name.clone());
+        }
+
+        bindings.push(quote! {
+            let #ident: #ty = ::grok_rust_sdk::__private::serde_json::from_value(
+                args.get(#name).cloned().unwrap_or(::grok_rust_sdk::__private::serde_json::Value::Null),
+            )
+            .map_err(|e| ::grok_rust_sdk::GrokError::ToolExecution(
+                format!("Invalid argument '{}': {}", #name, e),
+            ))?;
+        });
+        call_args.push(pass.call_arg(ident));
+    }
+
+    let call = if function.sig.asyncness.is_some() {
+        quote! { #fn_name(#(#call_args),*).await }
+    } else {
+        quote! { #fn_name(#(#call_args),*) }
+    };
+
+    let output = if returns_result(&function.sig.output) {
+        quote! {
+            let output = #call.map_err(|e| ::grok_rust_sdk::GrokError::ToolExecution(e.to_string()))?;
+        }
+    } else {
+        quote! { let output = #call; }
+    };
+
+    let parameters = match args_struct {
+        Some(ty) => quote! {
+            let parameters = ::grok_rust_sdk::__private::schema_for::<#ty>();
+        },
+        None => quote! {
+            let mut properties = ::grok_rust_sdk::__private::serde_json::Map::new();
+            #(#properties)*
+            let parameters = ::grok_rust_sdk::__private::serde_json::json!({
+                "type": "object",
+                "properties": properties,
+                "required": [#(#required),*],
+            });
+        },
+    };
+
+    let vis = &function.vis;
+    let struct_name = format_ident!(
+        "{}",
+        pascal_case(&fn_name.to_string()),
+        span = fn_name.span()
+    );
+    let struct_doc = format!("Tool executor generated from [`{}`]", fn_name);
+
+    Ok(quote! {
+        #function
+
+        #[doc = #struct_doc]
+        #[derive(Debug, Clone, Copy, Default)]
+        #vis struct #struct_name;
+
+        #[::grok_rust_sdk::__private::async_trait]
+        impl ::grok_rust_sdk::tools::ToolExecutor for #struct_name {
+            async fn execute(
+                &self,
+                args: ::grok_rust_sdk::__private::serde_json::Value,
+            ) -> ::grok_rust_sdk::Result<::grok_rust_sdk::__private::serde_json::Value> {
+                #(#bindings)*
+                #output
+                ::grok_rust_sdk::__private::serde_json::to_value(output).map_err(|e| {
+                    ::grok_rust_sdk::GrokError::ToolExecution(format!("Failed to serialize result: {}", e))
+                })
+            }
+
+            fn spec(&self) -> ::grok_rust_sdk::tools::ToolSpec {
+                #parameters
+
+                ::grok_rust_sdk::tools::ToolSpec {
+                    name: #tool_name.to_string(),
+                    description: #description.to_string(),
+                    parameters,
+                    strict: false,
+                }
+            }
+        }
+    })
+}
+
+/// How a deserialized argument is passed to the function
+enum Pass {
+    /// As is
+    Owned,
+    /// Borrowed, for `&T` parameters
+    Ref,
+    /// With `Option::as_deref`, for `Option<&str>` and `Option<&[T]>`
+    OptionDeref,
+    /// With `Option::as_ref`, for other `Option<&T>` parameters
+    OptionRef,
+}
+
+impl Pass {
+    fn call_arg(&self, ident: &syn::Ident) -> proc_macro2::TokenStream {
+        match self {
+            Pass::Owned => quote! { #ident },
+            Pass::Ref => quote! { &#ident },
+            Pass::OptionDeref => quote! { #ident.as_deref() },
+            Pass::OptionRef => quote! { #ident.as_ref() },
+        }
+    }
+}
+
+/// The owned type to deserialize a parameter into, and how to pass it on
+///
+/// Arguments are deserialized from a `Value` that doesn't outlive the call,
+/// so borrowed parameters are bound to an owned copy.
+fn owned_binding(ty: &Type) -> syn::Result<(Type, Pass)> {
+    if let Type::Reference(reference) = ty {
+        return Ok((owned_type(reference)?, Pass::Ref));
+    }
+    if let Some(Type::Reference(reference)) = option_inner(ty) {
+        let owned = owned_type(reference)?;
+        let pass = match reference.elem.as_ref() {
+            Type::Slice(_) => Pass::OptionDeref,
+            elem if last_segment(elem).is_some_and(|segment| segment.ident == "str") => {
+                Pass::OptionDeref
+            }
+            _ => Pass::OptionRef,
+        };
+        return Ok((syn::parse_quote! { ::std::option::Option<#owned> }, pass));
+    }
+    Ok((ty.clone(), Pass::Owned))
+}
+
+fn owned_type(reference: &syn::TypeReference) -> syn::Result<Type> {
+    if reference.mutability.is_some() {
+        return Err(syn::Error::new_spanned(
+            reference,
+            "#[grok_tool] parameters cannot be `&mut`",
+        ));
+    }
+    Ok(match reference.elem.as_ref() {
+        Type::Slice(slice) => {
+            let elem = &slice.elem;
+            syn::parse_quote! { ::std::vec::Vec<#elem> }
+        }
+        elem if last_segment(elem).is_some_and(|segment| segment.ident == "str") => {
+            syn::parse_quote! { ::std::string::String }
+        }
+        elem => elem.clone(),
+    })
+}
+
+/// Split doc comments into the description and `# Arguments` entries
+fn parse_docs(attrs: &[syn::Attribute]) -> (String, Vec<(String, String)>) {
+    let lines: Vec<String> = attrs
+        .iter()
+        .filter(|attr| attr.path().is_ident("doc"))
+        .filter_map(|attr| match &attr.meta {
+            Meta::NameValue(nv) => string_literal(&nv.value).ok(),
+            _ => None,
+        })
+        .map(|line| line.trim().to_string())
+        .collect();
+
+    let mut description = Vec::new();
+    let mut args = Vec::new();
+    let mut section: Option<String> = None;
+
+    for line in lines {
+        if line.starts_with('#') {
+            section = Some(line.trim_start_matches('#').trim().to_lowercase());
+            continue;
+        }
+
+        // Other sections (`# Errors`, `# Examples`, ...) are left out of the description
+        if section.as_deref() == Some("arguments") {
+            // "* `name` - description"
+            let entry = line.trim_start_matches(['*', '-']).trim();
+            if let Some(rest) = entry.strip_prefix('`') {
+                if let Some((name, doc)) = rest.split_once('`') {
+                    let doc = doc.trim().trim_start_matches(['-', ':']).trim();
+                    args.push((name.to_string(), doc.to_string()));
+                }
+            }
+        } else if section.is_none() {
+            description.push(line);
+        }
+    }
+
+    (description.join("\n").trim().to_string(), args)
+}
+
+/// JSON schema for a parameter type
+fn type_schema(ty: &Type) -> proc_macro2::TokenStream {
+    let json = quote! { ::grok_rust_sdk::__private::serde_json::json! };
+
+    if let Type::Reference(reference) = ty {
+        return type_schema(&reference.elem);
+    }
+    if let Type::Slice(slice) = ty {
+        let items = type_schema(&slice.elem);
+        return quote! { #json({ "type": "array", "items": #items }) };
+    }
+
+    let Some(segment) = last_segment(ty) else {
+        return quote! { #json({}) };
+    };
+
+    match segment.ident.to_string().as_str() {
+        "String" | "str" | "char" => quote! { #json({ "type": "string" }) },
+        "bool" => quote! { #json({ "type": "boolean" }) },
+        "f32" | "f64" => quote! { #json({ "type": "number" }) },
+        "i8" | "i16" | "i32" | "i64" | "i128" | "isize" | "u8" | "u16" | "u32" | "u64" | "u128"
+        | "usize" => {
+            quote! { #json({ "type": "integer" }) }
+        }
+        "Vec" => {
+            let items = generic_arg(segment)
+                .map(type_schema)
+                .unwrap_or_else(|| quote! { #json({}) });
+            quote! { #json({ "type": "array", "items": #items }) }
+        }
+        "HashMap" | "BTreeMap" | "Map" => quote! { #json({ "type": "object" }) },
+        // Anything else describes itself with schemars
+        _ => quote! { ::grok_rust_sdk::__private::schema_for::<#ty>() },
+    }
+}
+
+/// The `T` of an `Option<T>`
+fn option_inner(ty: &Type) -> Option<&Type> {
+    let segment = last_segment(ty)?;
+    if segment.ident != "Option" {
+        return None;
+    }
+    generic_arg(segment)
+}
+
+fn last_segment(ty: &Type) -> Option<&syn::PathSegment> {
+    match ty {
+        Type::Path(path) => path.path.segments.last(),
+        _ => None,
+    }
+}
+
+fn generic_arg(segment: &syn::PathSegment) -> Option<&Type> {
+    let PathArguments::AngleBracketed(args) = &segment.arguments else {
+        return None;
+    };
+    args.args.iter().find_map(|arg| match arg {
+        GenericArgument::Type(ty) => Some(ty),
+        _ => None,
+    })
+}
+
+fn returns_result(output: &ReturnType) -> bool {
+    match output {
+        ReturnType::Type(_, ty) => {
+            last_segment(ty).is_some_and(|segment| segment.ident == "Result")
+        }
+        ReturnType::Default => false,
+    }
+}
+
+fn string_literal(expr: &Expr) -> syn::Result<String> {
+    match expr {
+        Expr::Lit(ExprLit {
+            lit: Lit::Str(lit), ..
+        }) => Ok(lit.value()),
+        other => Err(syn::Error::new_spanned(other, "expected a string literal")),
+    }
+}
+
+fn pascal_case(name: &str) -> String {
+    name.split('_')
+        .filter(|part| !part.is_empty())
+        .map(|part| {
+            let mut chars = part.chars();
+            chars
+                .next()
+                .map(|first| first.to_uppercase().chain(chars).collect::<String>())
+                .unwrap_or_default()
+        })
+        .collect()
+}
@@ -0,0 +1,322 @@
+//! Agent loop that alternates model calls and tool execution
+
+use crate::chat::{Message, Model, Role};
+use crate::client::ChatOptions;
+use crate::error::{GrokError, Result};
+use crate::provider::ChatProvider;
+use crate::tools::{ToolCall, ToolContext, ToolErrorPolicy, ToolRegistry, ToolResult};
+use std::collections::HashMap;
+use std::sync::Arc;
+
+/// Options for an agent run
+#[derive(Debug, Clone)]
+pub struct AgentOptions {
+    /// System prompt sent before the task
+    pub system_prompt: Option<String>,
+    /// Maximum number of model calls in one run
+    pub max_iterations: usize,
+    /// Stop once this many tokens have been used across the run
+    pub token_budget: Option<u64>,
+    /// How tool failures are handled; by default they are reported to the model
+    pub tool_error_policy: ToolErrorPolicy,
+    /// Send invalid tool arguments back to the model to fix, at most this many
+    /// times in a row per tool before the run fails, whatever the error policy
+    pub max_argument_repairs: Option<usize>,
+    /// Options passed to every chat completion request
+    pub chat_options: Option<ChatOptions>,
+}
+
+impl Default for AgentOptions {
+    fn default() -> Self {
+        Self {
+            system_prompt: None,
+            max_iterations: 10,
+            token_budget: None,
+            tool_error_policy: ToolErrorPolicy::ReturnToModel,
+            max_argument_repairs: None,
+            chat_options: None,
+        }
+    }
+}
+
+/// Why an agent run ended
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StopReason {
+    /// The model answered without requesting more tool calls
+    Completed,
+    /// `max_iterations` model calls were made without a final answer
+    MaxIterations,
+    /// The token budget was used up before a final answer
+    TokenBudget,
+}
+
+/// One model call in an agent run and the tools it triggered
+#[derive(Debug, Clone)]
+pub struct AgentStep {
+    /// Zero-based iteration number
+    pub iteration: usize,
+    /// The assistant message returned by the model
+    pub message: Message,
+    /// Tool calls requested by the model, with their results
+    pub tool_calls: Vec<(ToolCall, ToolResult)>,
+    /// Tokens used by this model call
+    pub total_tokens: u64,
+}
+
+/// Result of an agent run
+#[derive(Debug, Clone)]
+pub struct AgentRun {
+    /// The model's final answer, if it gave one
+    pub output: Option<String>,
+    /// Why the run ended
+    pub stop_reason: StopReason,
+    /// Every step taken, in order
+    pub steps: Vec<AgentStep>,
+    /// The full conversation, including tool results
+    pub messages: Vec<Message>,
+    /// Prompt tokens used across the run
+    pub prompt_tokens: u64,
+    /// Completion tokens used across the run
+    pub completion_tokens: u64,
+    /// Total tokens used across the run
+    pub total_tokens: u64,
+}
+
+/// Callback invoked after each agent step
+pub type StepCallback = Box<dyn Fn(&AgentStep) + Send + Sync>;
+
+/// Runs the reason → tool → observe loop against a tool registry
+pub struct Agent {
+    provider: Arc<dyn ChatProvider>,
+    model: Model,
+    registry: ToolRegistry,
+    options: AgentOptions,
+    context: ToolContext,
+    on_step: Option<StepCallback>,
+}
+
+impl std::fmt::Debug for Agent {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Agent")
+            .field("model", &self.model)
+            .field("registry", &self.registry)
+            .field("options", &self.options)
+            .field("context", &self.context)
+            .finish_non_exhaustive()
+    }
+}
+
+impl Agent {
+    /// Create an agent
+    pub fn new(
+        provider: Arc<dyn ChatProvider>,
+        model: Model,
+        mut registry: ToolRegistry,
+        options: AgentOptions,
+    ) -> Self {
+        registry.set_error_policy(options.tool_error_policy);
+        Self {
+            provider,
+            model,
+            registry,
+            options,
+            context: ToolContext::new(),
+            on_step: None,
+        }
+    }
+
+    /// Call `callback` after every step, e.g. to stream progress to a UI
+    pub fn on_step<F>(mut self, callback: F) -> Self
+    where
+        F: Fn(&AgentStep) + Send + Sync + 'static,
+    {
+        self.on_step = Some(Box::new(callback));
+        self
+    }
+
+    /// Pass `context` to every tool the agent runs
+    pub fn with_context(mut self, context: ToolContext) -> Self {
+        self.context = context;
+        self
+    }
+
+    /// Get the agent's tool registry
+    pub fn registry(&self) -> &ToolRegistry {
+        &self.registry
+    }
+
+    /// Run a task to completion, or until a limit is reached
+    pub async fn run(&self, task: impl Into<String>) -> Result<AgentRun> {
+        self.run_messages(vec![text_message(Role::User, task.into())])
+            .await
+    }
+
+    /// Continue a conversation to completion, or until a limit is reached
+    ///
+    /// The system prompt from the options, if any, is put before `messages`.
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(name = "grok.agent.run", skip_all)
+    )]
+    pub async fn run_messages(&self, messages: Vec<Message>) -> Result<AgentRun> {
+        let mut messages = match self.options.system_prompt {
+            Some(ref system_prompt) => {
+                let mut all = Vec::with_capacity(messages.len() + 1);
+                all.push(text_message(Role::System, system_prompt.clone()));
+                all.extend(messages);
+                all
+            }
+            None => messages,
+        };
+
+        let tools: Vec<crate::chat::Tool> = self
+            .registry
+            .api_tools()
+            .into_iter()
+            .map(Into::into)
+            .collect();
+        let tools = if tools.is_empty() { None } else { Some(tools) };
+
+        let mut run = AgentRun {
+            output: None,
+            stop_reason: StopReason::MaxIterations,
+            steps: Vec::new(),
+            messages: Vec::new(),
+            prompt_tokens: 0,
+            completion_tokens: 0,
+            total_tokens: 0,
+        };
+
+        let mut repairs = HashMap::new();
+        for iteration in 0..self.options.max_iterations {
+            if self
+                .options
+                .token_budget
+                .is_some_and(|budget| run.total_tokens >= budget)
+            {
+                run.stop_reason = StopReason::TokenBudget;
+                break;
+            }
+
+            let completion = self
+                .provider
+                .chat(
+                    self.model,
+                    messages.iter().cloned().map(Arc::new).collect(),
+                    tools.clone(),
+                    self.options.chat_options.clone(),
+                )
+                .await?;
+
+            let step_tokens = completion
+                .usage
+                .as_ref()
+                .map_or(0, |usage| usage.total_tokens as u64);
+            if let Some(ref usage) = completion.usage {
+                run.prompt_tokens += usage.prompt_tokens as u64;
+                run.completion_tokens += usage.completion_tokens as u64;
+                run.total_tokens += usage.total_tokens as u64;
+            }
+
+            let message = completion.message;
+            messages.push(message.clone());
+
+            let requested = message.tool_calls.clone().unwrap_or_default();
+            let mut step = AgentStep {
+                iteration,
+                message,
+                tool_calls: Vec::with_capacity(requested.len()),
+                total_tokens: step_tokens,
+            };
+
+            for call in &requested {
+                let call = ToolCall::from(call);
+                let result = self
+                    .registry
+                    .execute_tool_call_with_context(&call, &self.context)
+                    .await;
+                let result = match self.options.max_argument_repairs {
+                    Some(max_repairs) => {
+                        repair_arguments(&call, result, &mut repairs, max_repairs)?
+                    }
+                    None => result?,
+                };
+                messages.push(Message {
+                    role: Role::Tool,
+                    content: result.content.clone().into(),
+                    tool_calls: None,
+                    tool_call_id: Some(result.tool_call_id.clone()),
+                    name: Some(call.function.name.clone()),
+                });
+                step.tool_calls.push((call, result));
+            }
+
+            if let Some(ref callback) = self.on_step {
+                callback(&step);
+            }
+
+            let finished = requested.is_empty();
+            if finished {
+                run.output = Some(step.message.content.to_string());
+            }
+            run.steps.push(step);
+
+            if finished {
+                run.stop_reason = StopReason::Completed;
+                break;
+            }
+        }
+
+        run.messages = messages;
+        Ok(run)
+    }
+}
+
+/// Turn an invalid-arguments failure into a request for the model to retry,
+/// until the tool has failed `max_repairs` times in a row
+fn repair_arguments(
+    call: &ToolCall,
+    result: Result<ToolResult>,
+    repairs: &mut HashMap<String, usize>,
+    max_repairs: usize,
+) -> Result<ToolResult> {
+    let (tool, message) = match &result {
+        Err(GrokError::InvalidToolArguments { tool, message }) => (tool.clone(), message.clone()),
+        Ok(ToolResult {
+            error: Some(error), ..
+        }) => match error.as_ref() {
+            GrokError::InvalidToolArguments { tool, message } => (tool.clone(), message.clone()),
+            _ => return result,
+        },
+        Ok(_) => {
+            repairs.remove(&call.function.name);
+            return result;
+        }
+        Err(_) => return result,
+    };
+
+    let attempts = repairs.entry(tool.clone()).or_default();
+    if *attempts >= max_repairs {
+        return Err(GrokError::InvalidToolArguments { tool, message });
+    }
+    *attempts += 1;
+
+    Ok(ToolResult {
+        tool_call_id: call.id.clone(),
+        content: serde_json::json!({
+            "error": format!("arguments invalid: {}. Call the tool again with corrected arguments.", message)
+        })
+        .to_string(),
+        error: Some(Arc::new(GrokError::InvalidToolArguments { tool, message })),
+    })
+}
+
+fn text_message(role: Role, content: String) -> Message {
+    Message {
+        role,
+        content: content.into(),
+        tool_calls: None,
+        tool_call_id: None,
+        name: None,
+    }
+}
@@ -0,0 +1,259 @@
+//! Raw request and response audit trail
+//!
+//! With an `Auditor` set on the client, every request body is recorded
+//! before it's sent, and every response body after it arrives. Streamed
+//! responses are recorded frame by frame when the stream ends. Each attempt,
+//! retries included, gets its own exchange ID tying its records together.
+//! Records go to an `AuditSink`, such as `SqliteStorage`, after an optional
+//! redaction pass, and records older than the retention period are pruned.
+
+use crate::error::{GrokError, Result};
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::sync::{Arc, Mutex, PoisonError};
+use std::time::{Duration, Instant};
+
+/// How often the auditor prunes old records while writing new ones
+const PRUNE_INTERVAL: Duration = Duration::from_secs(60);
+
+/// What an audit record holds
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AuditKind {
+    /// An outbound request body
+    Request,
+    /// A whole response body
+    Response,
+    /// One `data:` frame of a streamed response
+    StreamFrame,
+}
+
+impl AuditKind {
+    /// The name used in storage
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            AuditKind::Request => "request",
+            AuditKind::Response => "response",
+            AuditKind::StreamFrame => "stream_frame",
+        }
+    }
+}
+
+impl std::str::FromStr for AuditKind {
+    type Err = GrokError;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "request" => Ok(AuditKind::Request),
+            "response" => Ok(AuditKind::Response),
+            "stream_frame" => Ok(AuditKind::StreamFrame),
+            other => Err(GrokError::InvalidConfig(format!(
+                "Unknown audit record kind '{}'",
+                other
+            ))),
+        }
+    }
+}
+
+/// One recorded request, response or stream frame
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AuditRecord {
+    /// Shared by the records of one request attempt
+    pub exchange_id: String,
+    /// The `X-Request-ID` sent, or the one the API returned
+    pub request_id: Option<String>,
+    /// API endpoint, such as `/chat/completions`
+    pub endpoint: String,
+    /// What the body is
+    pub kind: AuditKind,
+    /// HTTP status, for responses and stream frames
+    pub status: Option<u16>,
+    /// Position of the record within its exchange
+    pub sequence: u32,
+    /// Body as sent or received, after redaction
+    pub body: String,
+    /// When the record was made
+    pub recorded_at: DateTime<Utc>,
+}
+
+/// A backend that audit records are written to
+#[async_trait]
+pub trait AuditSink: Send + Sync + std::fmt::Debug {
+    /// Append records
+    async fn record_audit(&self, records: &[AuditRecord]) -> Result<()>;
+
+    /// Delete records made before `cutoff`, returning how many were removed
+    async fn prune_audit(&self, cutoff: DateTime<Utc>) -> Result<usize>;
+}
+
+/// Body redaction applied before records are written
+pub type AuditRedactor = Arc<dyn Fn(&str) -> String + Send + Sync>;
+
+/// Writes the client's raw traffic to an `AuditSink`
+///
+/// Audit writes are not best-effort: a request that can't be recorded isn't
+/// sent, and a response that can't be recorded fails the call.
+pub struct Auditor {
+    sink: Arc<dyn AuditSink>,
+    redactor: Option<AuditRedactor>,
+    retention: Option<Duration>,
+    last_prune: Mutex<Option<Instant>>,
+}
+
+impl std::fmt::Debug for Auditor {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Auditor")
+            .field("sink", &self.sink)
+            .field("redactor", &self.redactor.is_some())
+            .field("retention", &self.retention)
+            .finish()
+    }
+}
+
+impl Auditor {
+    /// Record to `sink`, keeping bodies as they are and records forever
+    pub fn new(sink: Arc<dyn AuditSink>) -> Self {
+        Self {
+            sink,
+            redactor: None,
+            retention: None,
+            last_prune: Mutex::new(None),
+        }
+    }
+
+    /// Rewrite every body before it's recorded, e.g. with `Redactor::redact`
+    pub fn redact<F>(mut self, redactor: F) -> Self
+    where
+        F: Fn(&str) -> String + Send + Sync + 'static,
+    {
+        self.redactor = Some(Arc::new(redactor));
+        self
+    }
+
+    /// Delete records older than `retention` as new ones are written
+    pub fn retention(mut self, retention: Duration) -> Self {
+        self.retention = Some(retention);
+        self
+    }
+
+    /// Delete records older than the retention period now
+    ///
+    /// Does nothing without a retention period.
+    pub async fn prune(&self) -> Result<usize> {
+        let Some(retention) = self.retention else {
+            return Ok(0);
+        };
+        let retention = chrono::Duration::from_std(retention)
+            .map_err(|e| GrokError::InvalidConfig(format!("Invalid audit retention: {}", e)))?;
+        *self
+            .last_prune
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner) = Some(Instant::now());
+        self.sink.prune_audit(Utc::now() - retention).await
+    }
+
+    /// Record a request body, starting a new exchange
+    pub(crate) async fn begin(
+        self: &Arc<Self>,
+        endpoint: &str,
+        request_id: Option<String>,
+        body: &serde_json::Value,
+    ) -> Result<AuditExchange> {
+        let mut exchange = AuditExchange {
+            auditor: self.clone(),
+            exchange_id: uuid::Uuid::new_v4().to_string(),
+            request_id,
+            endpoint: endpoint.to_string(),
+            sequence: 0,
+        };
+        let record = exchange.record(AuditKind::Request, None, body.to_string());
+        self.write(vec![record]).await?;
+        Ok(exchange)
+    }
+
+    async fn write(&self, mut records: Vec<AuditRecord>) -> Result<()> {
+        if let Some(redactor) = &self.redactor {
+            for record in &mut records {
+                record.body = redactor(&record.body);
+            }
+        }
+        self.sink.record_audit(&records).await?;
+
+        let due = self.retention.is_some()
+            && self
+                .last_prune
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .is_none_or(|last| last.elapsed() >= PRUNE_INTERVAL);
+        if due {
+            self.prune().await?;
+        }
+        Ok(())
+    }
+}
+
+/// The records of one request attempt, after its request was recorded
+#[derive(Debug)]
+pub(crate) struct AuditExchange {
+    auditor: Arc<Auditor>,
+    exchange_id: String,
+    request_id: Option<String>,
+    endpoint: String,
+    sequence: u32,
+}
+
+impl AuditExchange {
+    /// Record a whole response, returning an equivalent one to read from
+    pub(crate) async fn response(
+        mut self,
+        response: reqwest::Response,
+    ) -> Result<reqwest::Response> {
+        let status = response.status();
+        let headers = response.headers().clone();
+        self.observe_request_id(&headers);
+        let body = response.text().await.map_err(GrokError::Http)?;
+
+        let record = self.record(AuditKind::Response, Some(status.as_u16()), body.clone());
+        self.auditor.write(vec![record]).await?;
+
+        let mut rebuilt = http::Response::new(body);
+        *rebuilt.status_mut() = status;
+        *rebuilt.headers_mut() = headers;
+        Ok(reqwest::Response::from(rebuilt))
+    }
+
+    /// Record the `data:` frames of a streamed response
+    pub(crate) async fn frames(mut self, status: u16, frames: Vec<String>) -> Result<()> {
+        let records = frames
+            .into_iter()
+            .map(|frame| self.record(AuditKind::StreamFrame, Some(status), frame))
+            .collect();
+        self.auditor.write(records).await
+    }
+
+    /// Use the API's request ID when the client didn't send one
+    pub(crate) fn observe_request_id(&mut self, headers: &reqwest::header::HeaderMap) {
+        if self.request_id.is_none() {
+            self.request_id = headers
+                .get("x-request-id")
+                .and_then(|value| value.to_str().ok())
+                .map(str::to_string);
+        }
+    }
+
+    fn record(&mut self, kind: AuditKind, status: Option<u16>, body: String) -> AuditRecord {
+        let record = AuditRecord {
+            exchange_id: self.exchange_id.clone(),
+            request_id: self.request_id.clone(),
+            endpoint: self.endpoint.clone(),
+            kind,
+            status,
+            sequence: self.sequence,
+            body,
+            recorded_at: Utc::now(),
+        };
+        self.sequence += 1;
+        record
+    }
+}
@@ -0,0 +1,246 @@
+//! Benchmarks for the SDK's hot paths
+//!
+//! Run them with `cargo bench --features bench`. The inputs are fixed, so
+//! results are comparable between runs. When vendoring the SDK, the groups
+//! can be registered with your own `Criterion` instead.
+
+use crate::chat::{Message, Model, Role};
+use crate::session::Session;
+use crate::tools::{ToolCall, ToolFunction, ToolRegistry};
+use crate::vcr::{Cassette, Interaction, Vcr};
+use crate::Client;
+use criterion::{BatchSize, BenchmarkId, Criterion, Throughput};
+use std::sync::Arc;
+
+/// History lengths used by the benchmarks that scale with a conversation
+const HISTORY_LENGTHS: [usize; 3] = [10, 100, 1000];
+
+/// Run every benchmark group
+pub fn all(c: &mut Criterion) {
+    sse_parsing(c);
+    message_serialization(c);
+    session_history(c);
+    tool_validation(c);
+    #[cfg(feature = "persistence")]
+    storage(c);
+}
+
+/// Decode a streamed completion of 500 chunks, replayed without a network
+pub fn sse_parsing(c: &mut Criterion) {
+    let runtime = runtime();
+    let messages = vec![message(Role::User, 0)];
+    let request = serde_json::json!({
+        "model": Model::Grok4.as_str(),
+        "messages": messages,
+        "stream": true
+    });
+    let event = serde_json::json!({
+        "id": "chatcmpl-bench",
+        "object": "chat.completion.chunk",
+        "created": 0,
+        "model": Model::Grok4.as_str(),
+        "choices": [{ "index": 0, "delta": { "content": "token " }, "finish_reason": null }]
+    });
+    let body = format!("data: {}\n\n", event).repeat(500) + "data: [DONE]\n\n";
+
+    let mut group = c.benchmark_group("sse_parsing");
+    group.throughput(Throughput::Bytes(body.len() as u64));
+    group.bench_function("500_chunks", |b| {
+        b.iter_batched(
+            || {
+                let cassette = Cassette {
+                    interactions: vec![Interaction {
+                        endpoint: "/chat/completions".to_string(),
+                        request: request.clone(),
+                        status: 200,
+                        headers: Default::default(),
+                        body: body.clone(),
+                    }],
+                };
+                Client::builder()
+                    .api_key("bench")
+                    .vcr(Vcr::from_cassette("bench.json", cassette))
+                    .stream_capacity(1024)
+                    .build()
+                    .expect("valid client")
+            },
+            |client| {
+                runtime.block_on(async {
+                    use futures::StreamExt;
+                    let stream = client
+                        .chat_stream(Model::Grok4, messages.clone(), None)
+                        .await
+                        .expect("replayed stream");
+                    stream.count().await
+                })
+            },
+            BatchSize::SmallInput,
+        )
+    });
+    group.finish();
+}
+
+/// Serialize conversation histories to request JSON
+pub fn message_serialization(c: &mut Criterion) {
+    let mut group = c.benchmark_group("message_serialization");
+    for length in HISTORY_LENGTHS {
+        let history = history(length);
+        group.throughput(Throughput::Elements(length as u64));
+        group.bench_with_input(
+            BenchmarkId::from_parameter(length),
+            &history,
+            |b, history| b.iter(|| serde_json::to_vec(history).expect("serializable messages")),
+        );
+    }
+    group.finish();
+}
+
+/// Grow a session's history and read it back
+pub fn session_history(c: &mut Criterion) {
+    let runtime = runtime();
+    let client = Arc::new(Client::new("bench").expect("valid client"));
+    let mut group = c.benchmark_group("session_history");
+    for length in HISTORY_LENGTHS {
+        let history = history(length);
+        group.throughput(Throughput::Elements(length as u64));
+        group.bench_with_input(
+            BenchmarkId::from_parameter(length),
+            &history,
+            |b, history| {
+                b.iter(|| {
+                    runtime.block_on(async {
+                        let session = Session::new(client.clone(), Model::Grok4, None);
+                        for message in history {
+                            session.append(message.clone()).await.expect("appended");
+                        }
+                        session.history().await.len()
+                    })
+                })
+            },
+        );
+    }
+    group.finish();
+}
+
+/// Parse and validate tool call arguments against their schema
+pub fn tool_validation(c: &mut Criterion) {
+    let runtime = runtime();
+    let registry = ToolRegistry::new();
+    registry.register_fn(
+        "search",
+        "Search documents",
+        serde_json::json!({
+            "type": "object",
+            "properties": {
+                "query": { "type": "string", "minLength": 1 },
+                "limit": { "type": "integer", "minimum": 1, "maximum": 100 },
+                "filters": {
+                    "type": "array",
+                    "items": {
+                        "type": "object",
+                        "properties": {
+                            "field": { "type": "string" },
+                            "value": { "type": "string" }
+                        },
+                        "required": ["field", "value"]
+                    }
+                }
+            },
+            "required": ["query"]
+        }),
+        |_| async { Ok(serde_json::Value::Null) },
+    );
+    let call = ToolCall {
+        id: "call_bench".to_string(),
+        function: ToolFunction {
+            name: "search".to_string(),
+            arguments: serde_json::json!({
+                "query": "rust sdk",
+                "limit": 10,
+                "filters": [
+                    { "field": "language", "value": "en" },
+                    { "field": "year", "value": "2024" }
+                ]
+            })
+            .to_string(),
+        },
+    };
+
+    c.bench_function("tool_validation", |b| {
+        b.iter(|| {
+            runtime
+                .block_on(registry.execute_tool_call(&call))
+                .expect("valid call")
+        })
+    });
+}
+
+/// Save and load sessions with in-memory SQLite storage
+#[cfg(feature = "persistence")]
+pub fn storage(c: &mut Criterion) {
+    use crate::persistence::SqliteStorage;
+
+    let runtime = runtime();
+    let client = Arc::new(Client::new("bench").expect("valid client"));
+    let storage = SqliteStorage::in_memory().expect("in-memory storage");
+    let mut group = c.benchmark_group("storage");
+    for length in HISTORY_LENGTHS {
+        let session = Session::new(client.clone(), Model::Grok4, None);
+        runtime.block_on(async {
+            for message in history(length) {
+                session.append(message).await.expect("appended");
+            }
+        });
+        group.throughput(Throughput::Elements(length as u64));
+        group.bench_with_input(BenchmarkId::new("save", length), &session, |b, session| {
+            b.iter(|| {
+                runtime
+                    .block_on(storage.save_session(session))
+                    .expect("saved")
+            })
+        });
+        group.bench_with_input(BenchmarkId::new("load", length), &session, |b, session| {
+            b.iter(|| {
+                runtime
+                    .block_on(storage.load_session(&session.id))
+                    .expect("loaded")
+            })
+        });
+    }
+    group.finish();
+}
+
+fn runtime() -> tokio::runtime::Runtime {
+    tokio::runtime::Builder::new_multi_thread()
+        .enable_all()
+        .build()
+        .expect("tokio runtime")
+}
+
+/// A conversation alternating between user and assistant messages
+fn history(length: usize) -> Vec<Message> {
+    (0..length)
+        .map(|i| {
+            let role = if i % 2 == 0 {
+                Role::User
+            } else {
+                Role::Assistant
+            };
+            message(role, i)
+        })
+        .collect()
+}
+
+fn message(role: Role, index: usize) -> Message {
+    Message {
+        role,
+        content: format!(
+            "Message {} of the benchmark conversation, long enough to resemble a real turn.",
+            index
+        )
+        .into(),
+        tool_calls: None,
+        tool_call_id: None,
+        name: None,
+    }
+}
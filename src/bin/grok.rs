@@ -0,0 +1,298 @@
+//! Command-line client: chat with Grok and inspect stored sessions and collections
+//!
+//! Built with the `cli` feature. Sessions and collections live in a SQLite
+//! database (`--db`, or `GROK_DB`); chatting needs `XAI_API_KEY`.
+
+use clap::{Parser, Subcommand};
+use grok_rust_sdk::chat::{stream_to_writer, Message, Model, Role};
+use grok_rust_sdk::error::{Entity, GrokError};
+use grok_rust_sdk::persistence::{ExportOptions, SessionQuery, SqliteStorage};
+use grok_rust_sdk::session::Session;
+use grok_rust_sdk::Client;
+use std::io::Write;
+use std::path::PathBuf;
+use std::sync::Arc;
+use tokio::io::{AsyncBufReadExt, BufReader};
+
+type CliResult = Result<(), Box<dyn std::error::Error>>;
+
+#[derive(Parser)]
+#[command(
+    name = "grok",
+    about = "Chat with Grok and manage stored conversations"
+)]
+struct Cli {
+    /// SQLite database holding sessions and collections
+    #[arg(long, env = "GROK_DB", default_value = "grok.db", global = true)]
+    db: PathBuf,
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Chat interactively, streaming replies and saving the conversation
+    Chat {
+        /// Model to use; defaults to the session's model, or grok-4
+        #[arg(long)]
+        model: Option<Model>,
+        /// Continue a stored session instead of starting a new one
+        #[arg(long)]
+        session: Option<String>,
+        /// Title for a new session
+        #[arg(long)]
+        title: Option<String>,
+        /// System prompt for a new session
+        #[arg(long)]
+        system: Option<String>,
+    },
+    /// List, show and export stored sessions
+    Sessions {
+        #[command(subcommand)]
+        command: SessionsCommand,
+    },
+    /// List and show stored collections
+    Collections {
+        #[command(subcommand)]
+        command: CollectionsCommand,
+    },
+}
+
+#[derive(Subcommand)]
+enum SessionsCommand {
+    /// List sessions, most recently updated first
+    List {
+        /// Only sessions in this collection
+        #[arg(long)]
+        collection: Option<String>,
+        /// Maximum number of sessions to list
+        #[arg(long)]
+        limit: Option<usize>,
+    },
+    /// Print a session's messages
+    Show {
+        /// Session ID
+        id: String,
+    },
+    /// Export sessions as chat fine-tuning JSONL
+    Export {
+        /// File to write
+        path: PathBuf,
+        /// Only sessions in this collection
+        #[arg(long)]
+        collection: Option<String>,
+        /// Only sessions in a collection with this tag
+        #[arg(long)]
+        tag: Option<String>,
+        /// Drop tool calls and tool results
+        #[arg(long)]
+        drop_tool_messages: bool,
+    },
+}
+
+#[derive(Subcommand)]
+enum CollectionsCommand {
+    /// List collections
+    List,
+    /// Print a collection and its sessions
+    Show {
+        /// Collection ID
+        id: String,
+    },
+}
+
+#[tokio::main]
+async fn main() -> CliResult {
+    let cli = Cli::parse();
+    let storage = SqliteStorage::new(&cli.db)?;
+
+    match cli.command {
+        Command::Chat {
+            model,
+            session,
+            title,
+            system,
+        } => chat(&storage, model, session, title, system).await,
+        Command::Sessions { command } => sessions(&storage, command).await,
+        Command::Collections { command } => collections(&storage, command).await,
+    }
+}
+
+async fn chat(
+    storage: &SqliteStorage,
+    model: Option<Model>,
+    session_id: Option<String>,
+    title: Option<String>,
+    system: Option<String>,
+) -> CliResult {
+    let api_key = std::env::var("XAI_API_KEY")
+        .map_err(|_| GrokError::InvalidConfig("XAI_API_KEY must be set".to_string()))?;
+    let client = Arc::new(Client::new(api_key)?);
+
+    let (session, model) = match session_id {
+        Some(id) => {
+            let summary = storage.load_session_meta(&id).await?;
+            let session = storage.load_session(&id).await?;
+            let (Some(summary), Some(session)) = (summary, session) else {
+                return Err(GrokError::NotFound {
+                    entity: Entity::Session,
+                    id,
+                }
+                .into());
+            };
+            (session, model.unwrap_or(summary.model))
+        }
+        None => {
+            let model = model.unwrap_or(Model::Grok4);
+            let session = Session::new(client.clone(), model, title);
+            if let Some(system) = system {
+                session.append(message(Role::System, system)).await?;
+            }
+            (session, model)
+        }
+    };
+    eprintln!("Session {} (type /exit to quit)", session.id);
+
+    let mut lines = BufReader::new(tokio::io::stdin()).lines();
+    loop {
+        eprint!("> ");
+        std::io::stderr().flush()?;
+        let Some(line) = lines.next_line().await? else {
+            break;
+        };
+        let line = line.trim();
+        if line.is_empty() {
+            continue;
+        }
+        if line == "/exit" {
+            break;
+        }
+
+        session
+            .append(message(Role::User, line.to_string()))
+            .await?;
+        let stream = client
+            .chat_stream(model, session.messages().await, None)
+            .await?;
+        let completion = stream_to_writer(stream, &mut tokio::io::stdout()).await?;
+        println!();
+
+        session.append(completion.message).await?;
+        storage.save_session(&session).await?;
+    }
+
+    storage.save_session(&session).await?;
+    Ok(())
+}
+
+async fn sessions(storage: &SqliteStorage, command: SessionsCommand) -> CliResult {
+    match command {
+        SessionsCommand::List { collection, limit } => {
+            let query = SessionQuery {
+                collection_id: collection,
+                limit,
+                ..Default::default()
+            };
+            for summary in storage.query_sessions(&query).await? {
+                println!(
+                    "{}  {:<24}  {:>4} messages  {}  {}",
+                    summary.id,
+                    summary.model,
+                    summary.message_count,
+                    summary.updated_at.format("%Y-%m-%d %H:%M"),
+                    summary.title.as_deref().unwrap_or("(untitled)")
+                );
+            }
+        }
+        SessionsCommand::Show { id } => {
+            let session = storage
+                .load_session(&id)
+                .await?
+                .ok_or(GrokError::NotFound {
+                    entity: Entity::Session,
+                    id,
+                })?;
+            for message in session.messages().await {
+                println!("[{:?}] {}", message.role, message.content);
+            }
+        }
+        SessionsCommand::Export {
+            path,
+            collection,
+            tag,
+            drop_tool_messages,
+        } => {
+            let options = ExportOptions {
+                query: SessionQuery {
+                    collection_id: collection,
+                    tag,
+                    ..Default::default()
+                },
+                drop_tool_messages,
+                ..Default::default()
+            };
+            let written = storage.export_jsonl(&options, &path).await?;
+            eprintln!("Exported {} conversations to {}", written, path.display());
+        }
+    }
+    Ok(())
+}
+
+async fn collections(storage: &SqliteStorage, command: CollectionsCommand) -> CliResult {
+    match command {
+        CollectionsCommand::List => {
+            for id in storage.list_collections().await? {
+                let Some(collection) = storage.load_collection(&id).await? else {
+                    continue;
+                };
+                let metadata = &collection.metadata();
+                println!(
+                    "{}  {:>4} sessions  {}  [{}]",
+                    collection.id,
+                    metadata.session_count,
+                    metadata.name,
+                    metadata.tags.join(", ")
+                );
+            }
+        }
+        CollectionsCommand::Show { id } => {
+            let collection = storage
+                .load_collection(&id)
+                .await?
+                .ok_or(GrokError::NotFound {
+                    entity: Entity::Collection,
+                    id: id.clone(),
+                })?;
+            let metadata = &collection.metadata();
+            println!("{} ({})", metadata.name, collection.id);
+            if let Some(ref description) = metadata.description {
+                println!("{}", description);
+            }
+            println!("Tags: {}", metadata.tags.join(", "));
+
+            let query = SessionQuery {
+                collection_id: Some(id),
+                ..Default::default()
+            };
+            for summary in storage.query_sessions(&query).await? {
+                println!(
+                    "  {}  {:>4} messages  {}",
+                    summary.id,
+                    summary.message_count,
+                    summary.title.as_deref().unwrap_or("(untitled)")
+                );
+            }
+        }
+    }
+    Ok(())
+}
+
+fn message(role: Role, content: String) -> Message {
+    Message {
+        role,
+        content: content.into(),
+        tool_calls: None,
+        tool_call_id: None,
+        name: None,
+    }
+}
@@ -0,0 +1,250 @@
+//! Token and cost budgets enforced before requests are sent
+//!
+//! A `Budget` caps the tokens used, or the estimated dollars spent, over a
+//! sliding hour or day. Set one on a client to cap everything sent through it
+//! and its clones, or on a session to cap a single conversation. Once a limit
+//! is reached, requests fail with `GrokError::BudgetExceeded`, or wait until
+//! enough spend falls out of the window, depending on the budget's
+//! `BudgetAction`.
+
+use crate::chat::Model;
+use crate::error::{GrokError, Result};
+use std::collections::{HashMap, VecDeque};
+use std::fmt;
+use std::sync::{Mutex, PoisonError};
+use std::time::{Duration, Instant};
+
+/// Per-token pricing used to estimate completion costs
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ModelPricing {
+    /// Price per million prompt tokens
+    pub prompt_per_million: f64,
+    /// Price per million completion tokens
+    pub completion_per_million: f64,
+}
+
+impl ModelPricing {
+    /// Estimate the cost of a completion
+    pub fn estimate(&self, prompt_tokens: u32, completion_tokens: u32) -> f64 {
+        (prompt_tokens as f64 * self.prompt_per_million
+            + completion_tokens as f64 * self.completion_per_million)
+            / 1_000_000.0
+    }
+}
+
+/// Period a budget limit applies to, counted back from now
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum BudgetWindow {
+    /// The last hour
+    Hour,
+    /// The last 24 hours
+    Day,
+}
+
+impl BudgetWindow {
+    /// Length of the window
+    pub fn duration(&self) -> Duration {
+        match self {
+            BudgetWindow::Hour => Duration::from_secs(60 * 60),
+            BudgetWindow::Day => Duration::from_secs(24 * 60 * 60),
+        }
+    }
+}
+
+impl fmt::Display for BudgetWindow {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BudgetWindow::Hour => write!(f, "hour"),
+            BudgetWindow::Day => write!(f, "day"),
+        }
+    }
+}
+
+/// How much may be spent in a window
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum BudgetLimit {
+    /// Total tokens, prompt and completion
+    Tokens(u64),
+    /// Estimated cost in dollars, from the budget's model pricing
+    Cost(f64),
+}
+
+impl fmt::Display for BudgetLimit {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BudgetLimit::Tokens(tokens) => write!(f, "{} tokens", tokens),
+            BudgetLimit::Cost(dollars) => write!(f, "${:.2}", dollars),
+        }
+    }
+}
+
+/// What happens to a request once a budget limit is reached
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum BudgetAction {
+    /// Fail with `GrokError::BudgetExceeded`
+    #[default]
+    Fail,
+    /// Wait until enough spend falls out of the window
+    Wait,
+}
+
+/// Tokens and estimated cost spent in a window
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Spend {
+    /// Total tokens, prompt and completion
+    pub tokens: u64,
+    /// Estimated cost in dollars; completions of models without pricing count as free
+    pub cost: f64,
+}
+
+impl Spend {
+    fn amount(&self, limit: BudgetLimit) -> f64 {
+        match limit {
+            BudgetLimit::Tokens(_) => self.tokens as f64,
+            BudgetLimit::Cost(_) => self.cost,
+        }
+    }
+}
+
+fn limit_amount(limit: BudgetLimit) -> f64 {
+    match limit {
+        BudgetLimit::Tokens(tokens) => tokens as f64,
+        BudgetLimit::Cost(dollars) => dollars,
+    }
+}
+
+/// Limits on what may be spent over sliding windows
+///
+/// Requests are checked before they're sent, and counted once their usage is
+/// known, so the request that crosses a limit completes and the ones after it
+/// are held back. Share a budget between clients or sessions with `Arc`.
+#[derive(Debug, Default)]
+pub struct Budget {
+    limits: Vec<(BudgetLimit, BudgetWindow)>,
+    pricing: HashMap<String, ModelPricing>,
+    on_exceeded: BudgetAction,
+    spent: Mutex<VecDeque<(Instant, Spend)>>,
+}
+
+impl Budget {
+    /// Create a budget with no limits
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Limit the tokens used per window
+    pub fn max_tokens(mut self, tokens: u64, window: BudgetWindow) -> Self {
+        self.limits.push((BudgetLimit::Tokens(tokens), window));
+        self
+    }
+
+    /// Limit the estimated dollars spent per window
+    ///
+    /// Costs are estimated with the pricing set by `model_pricing`.
+    pub fn max_cost(mut self, dollars: f64, window: BudgetWindow) -> Self {
+        self.limits.push((BudgetLimit::Cost(dollars), window));
+        self
+    }
+
+    /// Set the pricing used to estimate the cost of a model's completions
+    pub fn model_pricing(mut self, model: Model, pricing: ModelPricing) -> Self {
+        self.pricing.insert(model.as_str().to_string(), pricing);
+        self
+    }
+
+    /// Choose whether requests over the budget fail or wait (default fail)
+    pub fn on_exceeded(mut self, action: BudgetAction) -> Self {
+        self.on_exceeded = action;
+        self
+    }
+
+    /// What has been spent in the window so far
+    pub fn spent(&self, window: BudgetWindow) -> Spend {
+        let since = Instant::now().checked_sub(window.duration());
+        let spent = self.spent.lock().unwrap_or_else(PoisonError::into_inner);
+        spent
+            .iter()
+            .filter(|(at, _)| since.is_none_or(|since| *at > since))
+            .fold(Spend::default(), |total, (_, spend)| Spend {
+                tokens: total.tokens + spend.tokens,
+                cost: total.cost + spend.cost,
+            })
+    }
+
+    /// Fail with `GrokError::BudgetExceeded` if any limit has been reached
+    pub fn check(&self) -> Result<()> {
+        let now = Instant::now();
+        let spent = self.spent.lock().unwrap_or_else(PoisonError::into_inner);
+        for &(limit, window) in &self.limits {
+            let since = now.checked_sub(window.duration());
+            let in_window: Vec<&(Instant, Spend)> = spent
+                .iter()
+                .filter(|(at, _)| since.is_none_or(|since| *at > since))
+                .collect();
+            let mut total: f64 = in_window.iter().map(|(_, spend)| spend.amount(limit)).sum();
+            if total < limit_amount(limit) {
+                continue;
+            }
+
+            // The limit is lifted once enough of the oldest spend has left the window
+            let mut resets_in = window.duration();
+            for (at, spend) in in_window {
+                total -= spend.amount(limit);
+                if total < limit_amount(limit) {
+                    resets_in = (*at + window.duration()).saturating_duration_since(now);
+                    break;
+                }
+            }
+            return Err(GrokError::BudgetExceeded {
+                limit,
+                window,
+                resets_in,
+            });
+        }
+        Ok(())
+    }
+
+    /// Wait for room in the budget, or fail, according to its `BudgetAction`
+    pub async fn acquire(&self) -> Result<()> {
+        loop {
+            match self.check() {
+                Err(GrokError::BudgetExceeded { resets_in, .. })
+                    if self.on_exceeded == BudgetAction::Wait =>
+                {
+                    #[cfg(feature = "tracing")]
+                    tracing::debug!(?resets_in, "waiting for spend budget");
+                    tokio::time::sleep(resets_in.max(Duration::from_millis(1))).await;
+                }
+                result => return result,
+            }
+        }
+    }
+
+    /// Count a completion's tokens against the budget
+    pub fn record(&self, model: Model, prompt_tokens: u32, completion_tokens: u32) {
+        let cost = self.pricing.get(model.as_str()).map_or(0.0, |pricing| {
+            pricing.estimate(prompt_tokens, completion_tokens)
+        });
+        let now = Instant::now();
+        let mut spent = self.spent.lock().unwrap_or_else(PoisonError::into_inner);
+        spent.push_back((
+            now,
+            Spend {
+                tokens: prompt_tokens as u64 + completion_tokens as u64,
+                cost,
+            },
+        ));
+
+        // Nothing older than the longest window can count against a limit
+        if let Some(since) = now.checked_sub(BudgetWindow::Day.duration()) {
+            while spent.front().is_some_and(|(at, _)| *at <= since) {
+                spent.pop_front();
+            }
+        }
+    }
+}
+
+/// Estimate the tokens of a reply whose usage wasn't reported, such as a streamed one
+pub(crate) fn estimate_reply_tokens(reply: &str) -> u32 {
+    reply.chars().count().div_ceil(4) as u32
+}
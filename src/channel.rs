@@ -0,0 +1,194 @@
+//! Channel adapters for chat streams
+//!
+//! `events` turns a chunk stream into typed `StreamEvent`s. `into_mpsc` and
+//! `into_broadcast` forward those events into tokio channels from a background
+//! task, and `from_mpsc` and `from_broadcast` turn the receiving ends back
+//! into streams, so actors and websocket handlers don't need their own
+//! forwarding loops.
+
+use crate::chat::{ChatChunk, ChatCompletion, StreamAccumulator, ToolCallDelta};
+use crate::error::{GrokError, Result};
+use crate::session::Session;
+use futures::{Stream, StreamExt};
+use std::collections::VecDeque;
+use std::sync::Arc;
+use tokio::sync::broadcast::error::RecvError;
+use tokio::sync::{broadcast, mpsc};
+
+/// An item of a chat stream
+///
+/// Every stream of events ends with exactly one `Done` or `Error`. The
+/// payloads are shared so events can be cloned to every broadcast receiver.
+#[derive(Debug, Clone)]
+pub enum StreamEvent {
+    /// Text generated since the previous event
+    Delta(String),
+    /// Part of a tool call's ID, name or arguments
+    ToolCallDelta(ToolCallDelta),
+    /// The stream finished; the completion assembled from its chunks
+    Done(Arc<ChatCompletion>),
+    /// The stream failed, as `GrokError::StreamInterrupted` if part of the
+    /// completion had arrived
+    Error(Arc<GrokError>),
+}
+
+/// Chunks still to be read and events still to be yielded
+struct EventState<S> {
+    chunks: std::pin::Pin<Box<S>>,
+    /// `None` once the stream has ended or failed
+    accumulator: Option<StreamAccumulator>,
+    pending: VecDeque<StreamEvent>,
+}
+
+/// Turn a chat stream into events, ending with `Done` or `Error`
+pub fn events<S>(stream: S) -> impl Stream<Item = StreamEvent>
+where
+    S: Stream<Item = Result<ChatChunk>>,
+{
+    let state = EventState {
+        chunks: Box::pin(stream),
+        accumulator: Some(StreamAccumulator::new()),
+        pending: VecDeque::new(),
+    };
+    futures::stream::unfold(state, |mut state| async move {
+        loop {
+            if let Some(event) = state.pending.pop_front() {
+                return Some((event, state));
+            }
+            let accumulator = state.accumulator.as_mut()?;
+            match state.chunks.next().await {
+                Some(Ok(chunk)) => {
+                    state.pending.extend(chunk_events(&chunk));
+                    accumulator.push(&chunk);
+                }
+                Some(Err(error)) => {
+                    let partial = state.accumulator.take()?.into_completion();
+                    let error = GrokError::StreamInterrupted {
+                        partial: Box::new(partial),
+                        error: Box::new(error),
+                    };
+                    state.pending.push_back(StreamEvent::Error(Arc::new(error)));
+                }
+                None => {
+                    let completion = state.accumulator.take()?.into_completion();
+                    state
+                        .pending
+                        .push_back(StreamEvent::Done(Arc::new(completion)));
+                }
+            }
+        }
+    })
+}
+
+/// Events for the deltas of a chunk's first choice
+fn chunk_events(chunk: &ChatChunk) -> Vec<StreamEvent> {
+    let Some(choice) = chunk.choices.first() else {
+        return Vec::new();
+    };
+    let mut events = Vec::new();
+    if let Some(content) = choice.delta.content.as_ref().filter(|c| !c.is_empty()) {
+        events.push(StreamEvent::Delta(content.clone()));
+    }
+    for delta in choice.delta.tool_calls.iter().flatten() {
+        events.push(StreamEvent::ToolCallDelta(delta.clone()));
+    }
+    events
+}
+
+/// Forward a chat stream's events into an mpsc channel holding up to `capacity` events
+///
+/// A background task reads the stream as fast as the receiver takes events,
+/// and stops reading once the receiver is dropped.
+pub fn into_mpsc<S>(stream: S, capacity: usize) -> mpsc::Receiver<StreamEvent>
+where
+    S: Stream<Item = Result<ChatChunk>> + Send + 'static,
+{
+    let (sender, receiver) = mpsc::channel(capacity.max(1));
+    tokio::spawn(forward_mpsc(events(stream), sender));
+    receiver
+}
+
+/// Forward a chat stream's events to broadcast receivers, each buffering up to `capacity` events
+///
+/// More receivers can join with `resubscribe`, seeing events from then on. A
+/// receiver that falls more than `capacity` events behind gets a lag error
+/// from `recv`; `from_broadcast` turns that into an `Error` event. The stream
+/// is read until it ends or every receiver is dropped.
+pub fn into_broadcast<S>(stream: S, capacity: usize) -> broadcast::Receiver<StreamEvent>
+where
+    S: Stream<Item = Result<ChatChunk>> + Send + 'static,
+{
+    let (sender, receiver) = broadcast::channel(capacity.max(1));
+    tokio::spawn(async move {
+        let mut events = std::pin::pin!(events(stream));
+        while let Some(event) = events.next().await {
+            if sender.send(event).is_err() {
+                break;
+            }
+        }
+    });
+    receiver
+}
+
+/// Events received from an mpsc channel, as a stream
+pub fn from_mpsc(receiver: mpsc::Receiver<StreamEvent>) -> impl Stream<Item = StreamEvent> {
+    futures::stream::unfold(receiver, |mut receiver| async move {
+        let event = receiver.recv().await?;
+        Some((event, receiver))
+    })
+}
+
+/// Events received from a broadcast channel, as a stream
+///
+/// If the receiver lagged behind, the stream ends with an `Error` event
+/// carrying `GrokError::StreamLagged`, since the reply it would assemble is
+/// missing parts.
+pub fn from_broadcast(
+    receiver: broadcast::Receiver<StreamEvent>,
+) -> impl Stream<Item = StreamEvent> {
+    futures::stream::unfold(Some(receiver), |receiver| async move {
+        let mut receiver = receiver?;
+        match receiver.recv().await {
+            Ok(event) => Some((event, Some(receiver))),
+            Err(RecvError::Closed) => None,
+            Err(RecvError::Lagged(skipped)) => {
+                let error = GrokError::StreamLagged {
+                    capacity: skipped as usize,
+                };
+                Some((StreamEvent::Error(Arc::new(error)), None))
+            }
+        }
+    })
+}
+
+/// Run a session turn in the background, forwarding the reply's events into
+/// an mpsc channel holding up to `capacity` events
+///
+/// See `Session::chat_events`. A turn that fails to start yields a single `Error`.
+pub fn session_turn(
+    session: Arc<Session>,
+    content: impl Into<String>,
+    capacity: usize,
+) -> mpsc::Receiver<StreamEvent> {
+    let content = content.into();
+    let (sender, receiver) = mpsc::channel(capacity.max(1));
+    tokio::spawn(async move {
+        match session.chat_events(content).await {
+            Ok(events) => forward_mpsc(events, sender).await,
+            Err(error) => {
+                let _ = sender.send(StreamEvent::Error(Arc::new(error))).await;
+            }
+        }
+    });
+    receiver
+}
+
+/// Send events until they run out or the receiver is dropped
+async fn forward_mpsc(events: impl Stream<Item = StreamEvent>, sender: mpsc::Sender<StreamEvent>) {
+    let mut events = std::pin::pin!(events);
+    while let Some(event) = events.next().await {
+        if sender.send(event).await.is_err() {
+            break;
+        }
+    }
+}
@@ -2,7 +2,10 @@
 
 use crate::error::{GrokError, Result};
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use std::borrow::Cow;
+use std::fmt;
+use std::sync::Arc;
+use std::time::Duration;
 
 /// Available Grok models
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -30,6 +33,16 @@ impl Model {
             Model::Grok1 => "grok-1",
         }
     }
+
+    /// Maximum tokens of prompt and reply combined
+    pub fn context_window(&self) -> u32 {
+        match self {
+            Model::Grok4FastReasoning => 2_000_000,
+            Model::Grok4 => 256_000,
+            Model::Grok3 | Model::Grok2 => 131_072,
+            Model::Grok1 => 8_192,
+        }
+    }
 }
 
 impl std::fmt::Display for Model {
@@ -38,6 +51,42 @@ impl std::fmt::Display for Model {
     }
 }
 
+impl Serialize for Model {
+    fn serialize<S: serde::Serializer>(
+        &self,
+        serializer: S,
+    ) -> std::result::Result<S::Ok, S::Error> {
+        serializer.serialize_str(self.as_str())
+    }
+}
+
+impl<'de> Deserialize<'de> for Model {
+    fn deserialize<D: serde::Deserializer<'de>>(
+        deserializer: D,
+    ) -> std::result::Result<Self, D::Error> {
+        let name = String::deserialize(deserializer)?;
+        name.parse().map_err(serde::de::Error::custom)
+    }
+}
+
+impl std::str::FromStr for Model {
+    type Err = GrokError;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "grok-4-fast-reasoning" => Ok(Model::Grok4FastReasoning),
+            "grok-4" => Ok(Model::Grok4),
+            "grok-3" => Ok(Model::Grok3),
+            "grok-2" => Ok(Model::Grok2),
+            "grok-1" => Ok(Model::Grok1),
+            other => Err(GrokError::InvalidConfig(format!(
+                "Unknown model '{}'",
+                other
+            ))),
+        }
+    }
+}
+
 /// Message roles in a conversation
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 #[serde(rename_all = "lowercase")]
@@ -58,7 +107,7 @@ pub struct Message {
     /// The role of the message sender
     pub role: Role,
     /// The content of the message
-    pub content: String,
+    pub content: MessageContent,
     /// Optional tool calls made by the assistant
     #[serde(skip_serializing_if = "Option::is_none")]
     pub tool_calls: Option<Vec<ToolCall>>,
@@ -70,6 +119,289 @@ pub struct Message {
     pub name: Option<String>,
 }
 
+impl Message {
+    /// A user message with text and an image at `url`, which may be a data URL
+    pub fn user_with_image(text: impl Into<String>, url: impl Into<String>) -> Self {
+        Self::user_with_parts(vec![ContentPart::text(text), ContentPart::image_url(url)])
+    }
+
+    /// A user message with text and a base64-encoded image
+    pub fn user_with_base64_image(
+        text: impl Into<String>,
+        media_type: impl Into<String>,
+        data: impl Into<String>,
+    ) -> Self {
+        Self::user_with_parts(vec![
+            ContentPart::text(text),
+            ContentPart::base64_image(media_type, data),
+        ])
+    }
+
+    /// A user message made of content parts
+    pub fn user_with_parts(parts: Vec<ContentPart>) -> Self {
+        Self {
+            role: Role::User,
+            content: MessageContent::Parts(parts),
+            tool_calls: None,
+            tool_call_id: None,
+            name: None,
+        }
+    }
+}
+
+/// The content of a message: text, or text and images
+///
+/// Text serializes as a plain string and parts as an array, as the API expects.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum MessageContent {
+    /// Plain text
+    Text(String),
+    /// Text and image parts, in order
+    Parts(Vec<ContentPart>),
+}
+
+impl MessageContent {
+    /// The text of the content, with text parts joined by newlines and images left out
+    pub fn text(&self) -> Cow<'_, str> {
+        match self {
+            MessageContent::Text(text) => Cow::Borrowed(text),
+            MessageContent::Parts(parts) => {
+                let texts: Vec<&str> = parts
+                    .iter()
+                    .filter_map(|part| match part {
+                        ContentPart::Text(text) => Some(text.as_str()),
+                        _ => None,
+                    })
+                    .collect();
+                match texts.as_slice() {
+                    [text] => Cow::Borrowed(text),
+                    texts => Cow::Owned(texts.join("\n")),
+                }
+            }
+        }
+    }
+
+    /// Replace the text, keeping any images
+    ///
+    /// Text parts are merged into one, at the position of the first.
+    pub fn replace_text(&mut self, text: String) {
+        match self {
+            MessageContent::Text(current) => *current = text,
+            MessageContent::Parts(parts) => {
+                let first = parts
+                    .iter()
+                    .position(|part| matches!(part, ContentPart::Text(_)));
+                parts.retain(|part| !matches!(part, ContentPart::Text(_)));
+                parts.insert(first.unwrap_or(0), ContentPart::Text(text));
+            }
+        }
+    }
+
+    /// Whether there's no text and no image
+    pub fn is_empty(&self) -> bool {
+        match self {
+            MessageContent::Text(text) => text.is_empty(),
+            MessageContent::Parts(parts) => parts.is_empty(),
+        }
+    }
+
+    /// Number of image parts
+    pub fn image_count(&self) -> usize {
+        match self {
+            MessageContent::Text(_) => 0,
+            MessageContent::Parts(parts) => parts
+                .iter()
+                .filter(|part| !matches!(part, ContentPart::Text(_)))
+                .count(),
+        }
+    }
+}
+
+impl Default for MessageContent {
+    fn default() -> Self {
+        MessageContent::Text(String::new())
+    }
+}
+
+/// Writes the text, leaving out images
+impl fmt::Display for MessageContent {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.text())
+    }
+}
+
+impl From<String> for MessageContent {
+    fn from(text: String) -> Self {
+        MessageContent::Text(text)
+    }
+}
+
+impl From<&str> for MessageContent {
+    fn from(text: &str) -> Self {
+        MessageContent::Text(text.to_string())
+    }
+}
+
+impl From<Vec<ContentPart>> for MessageContent {
+    fn from(parts: Vec<ContentPart>) -> Self {
+        MessageContent::Parts(parts)
+    }
+}
+
+impl PartialEq<str> for MessageContent {
+    fn eq(&self, other: &str) -> bool {
+        self.text() == other
+    }
+}
+
+impl PartialEq<&str> for MessageContent {
+    fn eq(&self, other: &&str) -> bool {
+        self.text() == *other
+    }
+}
+
+/// A part of a multimodal message
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(into = "WireContentPart", from = "WireContentPart")]
+pub enum ContentPart {
+    /// Text
+    Text(String),
+    /// An image fetched from a URL
+    ImageUrl {
+        /// Image URL
+        url: String,
+        /// Resolution to process the image at
+        detail: Option<ImageDetail>,
+    },
+    /// An image sent inline, sent as a `data:` URL
+    Base64Image {
+        /// MIME type, such as `image/png`
+        media_type: String,
+        /// Base64-encoded image bytes
+        data: String,
+        /// Resolution to process the image at
+        detail: Option<ImageDetail>,
+    },
+}
+
+impl ContentPart {
+    /// A text part
+    pub fn text(text: impl Into<String>) -> Self {
+        ContentPart::Text(text.into())
+    }
+
+    /// An image part for a URL; a base64 `data:` URL becomes a `Base64Image`
+    pub fn image_url(url: impl Into<String>) -> Self {
+        let url = url.into();
+        let inline = url
+            .strip_prefix("data:")
+            .and_then(|rest| rest.split_once(";base64,"));
+        match inline {
+            Some((media_type, data)) => ContentPart::base64_image(media_type, data),
+            None => ContentPart::ImageUrl { url, detail: None },
+        }
+    }
+
+    /// An image part for base64-encoded bytes of the given MIME type
+    pub fn base64_image(media_type: impl Into<String>, data: impl Into<String>) -> Self {
+        ContentPart::Base64Image {
+            media_type: media_type.into(),
+            data: data.into(),
+            detail: None,
+        }
+    }
+
+    /// Set the resolution of an image part; text parts are returned as is
+    pub fn with_detail(mut self, level: ImageDetail) -> Self {
+        if let ContentPart::ImageUrl { detail, .. } | ContentPart::Base64Image { detail, .. } =
+            &mut self
+        {
+            *detail = Some(level);
+        }
+        self
+    }
+
+    /// The URL an image part is sent as, a `data:` URL for base64 images
+    pub fn url(&self) -> Option<Cow<'_, str>> {
+        match self {
+            ContentPart::Text(_) => None,
+            ContentPart::ImageUrl { url, .. } => Some(Cow::Borrowed(url)),
+            ContentPart::Base64Image {
+                media_type, data, ..
+            } => Some(Cow::Owned(format!("data:{};base64,{}", media_type, data))),
+        }
+    }
+
+    /// The resolution set on an image part
+    pub fn detail(&self) -> Option<ImageDetail> {
+        match self {
+            ContentPart::Text(_) => None,
+            ContentPart::ImageUrl { detail, .. } | ContentPart::Base64Image { detail, .. } => {
+                *detail
+            }
+        }
+    }
+}
+
+/// Resolution an image is processed at
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ImageDetail {
+    /// Let the model choose
+    Auto,
+    /// Faster, with fewer tokens
+    Low,
+    /// Full resolution
+    High,
+}
+
+/// A content part as the API sends it; base64 images travel as data URLs
+#[derive(Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum WireContentPart {
+    Text { text: String },
+    ImageUrl { image_url: WireImageUrl },
+}
+
+#[derive(Serialize, Deserialize)]
+struct WireImageUrl {
+    url: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    detail: Option<ImageDetail>,
+}
+
+impl From<ContentPart> for WireContentPart {
+    fn from(part: ContentPart) -> Self {
+        match part {
+            ContentPart::Text(text) => WireContentPart::Text { text },
+            image => WireContentPart::ImageUrl {
+                image_url: WireImageUrl {
+                    url: image.url().unwrap_or_default().into_owned(),
+                    detail: image.detail(),
+                },
+            },
+        }
+    }
+}
+
+impl From<WireContentPart> for ContentPart {
+    fn from(part: WireContentPart) -> Self {
+        match part {
+            WireContentPart::Text { text } => ContentPart::Text(text),
+            WireContentPart::ImageUrl {
+                image_url: WireImageUrl { url, detail },
+            } => {
+                let image = ContentPart::image_url(url);
+                match detail {
+                    Some(detail) => image.with_detail(detail),
+                    None => image,
+                }
+            }
+        }
+    }
+}
+
 /// Tool call made by the assistant
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ToolCall {
@@ -109,60 +441,59 @@ pub struct ToolSpec {
     /// Parameters schema (JSON Schema)
     #[serde(skip_serializing_if = "Option::is_none")]
     pub parameters: Option<serde_json::Value>,
+    /// Whether the arguments must match the schema exactly
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub strict: Option<bool>,
 }
 
 /// Chat completion request
 #[derive(Debug, Serialize)]
-struct ChatRequest {
+pub(crate) struct ChatRequest {
     /// Model to use
-    model: String,
+    pub(crate) model: String,
     /// Messages in the conversation
-    messages: Vec<Message>,
+    pub(crate) messages: Vec<Arc<Message>>,
     /// Maximum tokens to generate
     #[serde(skip_serializing_if = "Option::is_none")]
-    max_tokens: Option<u32>,
+    pub(crate) max_tokens: Option<u32>,
     /// Temperature for randomness (0.0 to 2.0)
     #[serde(skip_serializing_if = "Option::is_none")]
-    temperature: Option<f32>,
+    pub(crate) temperature: Option<f32>,
     /// Top-p sampling parameter
     #[serde(skip_serializing_if = "Option::is_none")]
-    top_p: Option<f32>,
+    pub(crate) top_p: Option<f32>,
     /// Tools available for function calling
     #[serde(skip_serializing_if = "Option::is_none")]
-    tools: Option<Vec<Tool>>,
+    pub(crate) tools: Option<Vec<Tool>>,
     /// Tool choice strategy
     #[serde(skip_serializing_if = "Option::is_none")]
-    tool_choice: Option<serde_json::Value>,
+    pub(crate) tool_choice: Option<serde_json::Value>,
     /// Response format specification
     #[serde(skip_serializing_if = "Option::is_none")]
-    response_format: Option<serde_json::Value>,
+    pub(crate) response_format: Option<serde_json::Value>,
     /// Stop sequences
     #[serde(skip_serializing_if = "Option::is_none")]
-    stop: Option<Vec<String>>,
+    pub(crate) stop: Option<Vec<String>>,
     /// Enable streaming responses
     #[serde(skip_serializing_if = "Option::is_none")]
-    stream: Option<bool>,
+    pub(crate) stream: Option<bool>,
 }
 
 /// Chat completion response
 #[derive(Debug, Deserialize)]
-struct ChatResponse {
+pub(crate) struct ChatResponse {
     /// Unique ID for the completion
-    id: String,
-    /// Object type (always "chat.completion")
-    object: String,
-    /// Timestamp of creation
-    created: u64,
+    pub(crate) id: String,
     /// Model used
-    model: String,
+    pub(crate) model: String,
     /// Usage statistics
-    usage: Option<Usage>,
+    pub(crate) usage: Option<Usage>,
     /// Response choices
-    choices: Vec<Choice>,
+    pub(crate) choices: Vec<Choice>,
 }
 
 /// Usage statistics for the completion
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Serialize, Deserialize)]
 pub struct Usage {
     /// Number of prompt tokens
     pub prompt_tokens: u32,
@@ -174,17 +505,15 @@ pub struct Usage {
 
 /// A completion choice
 #[derive(Debug, Deserialize)]
-struct Choice {
-    /// Index of the choice
-    index: u32,
+pub(crate) struct Choice {
     /// The message content
-    message: Message,
+    pub(crate) message: Message,
     /// Finish reason
-    finish_reason: Option<String>,
+    pub(crate) finish_reason: Option<String>,
 }
 
 /// Chat completion result
-#[derive(Debug)]
+#[derive(Debug, Serialize)]
 pub struct ChatCompletion {
     /// Unique ID for the completion
     pub id: String,
@@ -196,10 +525,149 @@ pub struct ChatCompletion {
     pub message: Message,
     /// Finish reason
     pub finish_reason: Option<String>,
+    /// How long the request took, when it was made by this client
+    pub timings: Option<Timings>,
+    /// Notes attached by guardrails while checking the request and response
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub annotations: Vec<String>,
+    /// Quota left after this request, when the API reported it
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub rate_limit: Option<RateLimitInfo>,
+}
+
+/// Latency measurements for a completion
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize)]
+pub struct Timings {
+    /// Time spent on failed attempts and retry backoff before the request that succeeded
+    pub queue: Duration,
+    /// Time from sending the request to receiving the first token
+    ///
+    /// Without streaming the first token arrives with the whole response.
+    pub time_to_first_token: Duration,
+    /// Time from the first attempt until the response was complete
+    pub total: Duration,
+    /// Completion tokens generated per second, when usage is reported
+    pub output_tokens_per_second: Option<f64>,
+}
+
+/// Tokens assumed for the role and framing of each message
+const MESSAGE_OVERHEAD_TOKENS: u32 = 4;
+
+/// Tokens assumed for each image, whatever its size
+const IMAGE_TOKENS: u32 = 1024;
+
+/// Estimate the prompt tokens of a request without calling the API
+///
+/// Text is counted at four characters per token, plus a few tokens of framing
+/// per message and a flat amount per image. Tool definitions count as their JSON.
+pub fn estimate_prompt_tokens(messages: &[Arc<Message>], tools: Option<&[Tool]>) -> u32 {
+    let text_tokens = |text: &str| text.chars().count().div_ceil(4) as u32;
+    let mut tokens = 0;
+    for message in messages {
+        tokens += MESSAGE_OVERHEAD_TOKENS
+            + text_tokens(&message.content.text())
+            + IMAGE_TOKENS * message.content.image_count() as u32;
+        for call in message.tool_calls.iter().flatten() {
+            tokens += text_tokens(&call.function.name) + text_tokens(&call.function.arguments);
+        }
+    }
+    if let Some(tools) = tools {
+        tokens += text_tokens(&serde_json::to_string(tools).unwrap_or_default());
+    }
+    tokens
+}
+
+/// Check that the estimated prompt plus `max_tokens` fits in the model's
+/// context window, returning the estimated prompt tokens
+///
+/// Fails with `ContextLengthExceeded` otherwise, before anything is sent.
+pub fn check_context_window(
+    model: Model,
+    messages: &[Arc<Message>],
+    tools: Option<&[Tool]>,
+    max_tokens: Option<u32>,
+) -> Result<u32> {
+    let prompt_tokens = estimate_prompt_tokens(messages, tools);
+    let max_tokens = max_tokens.unwrap_or(0);
+    let context_window = model.context_window();
+    let needed = prompt_tokens.saturating_add(max_tokens);
+    if needed > context_window {
+        return Err(GrokError::ContextLengthExceeded {
+            context_window,
+            prompt_tokens,
+            max_tokens,
+            overflow: needed - context_window,
+        });
+    }
+    Ok(prompt_tokens)
+}
+
+/// Rate-limit quota reported in the `x-ratelimit-*` headers of a response
+///
+/// Reset times are relative to when the response was received.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+pub struct RateLimitInfo {
+    /// Requests allowed per window
+    pub limit_requests: Option<u64>,
+    /// Requests left in the current window
+    pub remaining_requests: Option<u64>,
+    /// Time until the request quota resets
+    pub reset_requests: Option<Duration>,
+    /// Tokens allowed per window
+    pub limit_tokens: Option<u64>,
+    /// Tokens left in the current window
+    pub remaining_tokens: Option<u64>,
+    /// Time until the token quota resets
+    pub reset_tokens: Option<Duration>,
+}
+
+impl RateLimitInfo {
+    /// Read the quota from response headers, or `None` if none were sent
+    pub(crate) fn from_headers(headers: &reqwest::header::HeaderMap) -> Option<Self> {
+        let header = |name: &str| headers.get(name)?.to_str().ok();
+        let count = |name: &str| header(name)?.trim().parse().ok();
+        let reset = |name: &str| parse_reset(header(name)?);
+        let info = Self {
+            limit_requests: count("x-ratelimit-limit-requests"),
+            remaining_requests: count("x-ratelimit-remaining-requests"),
+            reset_requests: reset("x-ratelimit-reset-requests"),
+            limit_tokens: count("x-ratelimit-limit-tokens"),
+            remaining_tokens: count("x-ratelimit-remaining-tokens"),
+            reset_tokens: reset("x-ratelimit-reset-tokens"),
+        };
+        (info != Self::default()).then_some(info)
+    }
+}
+
+/// Parse a reset time given in seconds or as a duration such as `1m30s` or `250ms`
+fn parse_reset(value: &str) -> Option<Duration> {
+    let value = value.trim();
+    if let Ok(seconds) = value.parse::<f64>() {
+        return Duration::try_from_secs_f64(seconds).ok();
+    }
+    let is_number = |c: char| c.is_ascii_digit() || c == '.';
+    let mut total = Duration::ZERO;
+    let mut rest = value;
+    while !rest.is_empty() {
+        let unit_start = rest.find(|c: char| !is_number(c))?;
+        let amount: f64 = rest[..unit_start].parse().ok()?;
+        rest = &rest[unit_start..];
+        let unit_end = rest.find(is_number).unwrap_or(rest.len());
+        let scale = match &rest[..unit_end] {
+            "h" => 3600.0,
+            "m" => 60.0,
+            "s" => 1.0,
+            "ms" => 0.001,
+            _ => return None,
+        };
+        total += Duration::try_from_secs_f64(amount * scale).ok()?;
+        rest = &rest[unit_end..];
+    }
+    Some(total)
 }
 
 /// Streaming chat completion chunk
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Serialize, Deserialize)]
 pub struct ChatChunk {
     /// Unique ID for the completion
     pub id: String,
@@ -211,10 +679,13 @@ pub struct ChatChunk {
     pub model: String,
     /// Response choices
     pub choices: Vec<ChunkChoice>,
+    /// Time from sending the request until this chunk arrived
+    #[serde(skip)]
+    pub elapsed: Duration,
 }
 
 /// A chunk choice in streaming response
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Serialize, Deserialize)]
 pub struct ChunkChoice {
     /// Index of the choice
     pub index: u32,
@@ -225,7 +696,7 @@ pub struct ChunkChoice {
 }
 
 /// Delta for streaming message updates
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Serialize, Deserialize)]
 pub struct MessageDelta {
     /// Role (only present in first chunk)
     #[serde(skip_serializing_if = "Option::is_none")]
@@ -239,7 +710,7 @@ pub struct MessageDelta {
 }
 
 /// Delta for tool calls in streaming
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ToolCallDelta {
     /// Index of the tool call
     pub index: u32,
@@ -252,7 +723,7 @@ pub struct ToolCallDelta {
 }
 
 /// Delta for tool function in streaming
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ToolFunctionDelta {
     /// Name delta
     #[serde(skip_serializing_if = "Option::is_none")]
@@ -261,3 +732,229 @@ pub struct ToolFunctionDelta {
     #[serde(skip_serializing_if = "Option::is_none")]
     pub arguments: Option<String>,
 }
+
+/// Builds a completion from streamed chunks
+#[derive(Debug, Default)]
+pub struct StreamAccumulator {
+    id: String,
+    model: String,
+    content: String,
+    tool_calls: Vec<ToolCall>,
+    finish_reason: Option<String>,
+    first_token: Option<Duration>,
+    last_chunk: Option<Duration>,
+}
+
+impl StreamAccumulator {
+    /// Create an empty accumulator
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a chunk's deltas for the first choice
+    pub fn push(&mut self, chunk: &ChatChunk) {
+        if self.id.is_empty() {
+            self.id = chunk.id.clone();
+            self.model = chunk.model.clone();
+        }
+        self.last_chunk = Some(chunk.elapsed);
+        let Some(choice) = chunk.choices.first() else {
+            return;
+        };
+        if self.first_token.is_none()
+            && (choice.delta.content.is_some() || choice.delta.tool_calls.is_some())
+        {
+            self.first_token = Some(chunk.elapsed);
+        }
+
+        if let Some(ref content) = choice.delta.content {
+            self.content.push_str(content);
+        }
+        for delta in choice.delta.tool_calls.iter().flatten() {
+            let index = delta.index as usize;
+            if self.tool_calls.len() <= index {
+                self.tool_calls.resize_with(index + 1, || ToolCall {
+                    id: String::new(),
+                    function: ToolFunction {
+                        name: String::new(),
+                        arguments: String::new(),
+                    },
+                });
+            }
+            let call = &mut self.tool_calls[index];
+            if let Some(ref id) = delta.id {
+                call.id = id.clone();
+            }
+            if let Some(ref function) = delta.function {
+                if let Some(ref name) = function.name {
+                    call.function.name.push_str(name);
+                }
+                if let Some(ref arguments) = function.arguments {
+                    call.function.arguments.push_str(arguments);
+                }
+            }
+        }
+        if choice.finish_reason.is_some() {
+            self.finish_reason = choice.finish_reason.clone();
+        }
+    }
+
+    /// Text received so far
+    pub fn content(&self) -> &str {
+        &self.content
+    }
+
+    /// Tool calls received so far; the last one's arguments may be incomplete
+    pub fn tool_calls(&self) -> &[ToolCall] {
+        &self.tool_calls
+    }
+
+    /// The completion received so far
+    pub fn into_completion(self) -> ChatCompletion {
+        ChatCompletion {
+            id: self.id,
+            model: self.model,
+            usage: None,
+            message: Message {
+                role: Role::Assistant,
+                content: MessageContent::Text(self.content),
+                tool_calls: (!self.tool_calls.is_empty()).then_some(self.tool_calls),
+                tool_call_id: None,
+                name: None,
+            },
+            finish_reason: self.finish_reason,
+            timings: self.last_chunk.map(|total| Timings {
+                queue: Duration::ZERO,
+                time_to_first_token: self.first_token.unwrap_or(total),
+                total,
+                output_tokens_per_second: None,
+            }),
+            annotations: Vec::new(),
+            rate_limit: None,
+        }
+    }
+}
+
+/// Collect a chat stream into a completion
+///
+/// If the stream fails partway, the error is `GrokError::StreamInterrupted`
+/// carrying the text and tool calls received before the failure.
+pub async fn collect_completion<S>(stream: S) -> Result<ChatCompletion>
+where
+    S: futures::Stream<Item = Result<ChatChunk>>,
+{
+    collect_completion_with(stream, |_| {}).await
+}
+
+/// Collect a chat stream into a completion, calling `on_token` with each text delta
+///
+/// Fails like `collect_completion`.
+pub async fn collect_completion_with<S, F>(stream: S, mut on_token: F) -> Result<ChatCompletion>
+where
+    S: futures::Stream<Item = Result<ChatChunk>>,
+    F: FnMut(&str),
+{
+    use futures::StreamExt;
+
+    let mut stream = std::pin::pin!(stream);
+    let mut accumulator = StreamAccumulator::new();
+    while let Some(chunk) = stream.next().await {
+        let chunk = chunk.map_err(|error| interrupted(&mut accumulator, error))?;
+        if let Some(text) = delta_content(&chunk) {
+            on_token(text);
+        }
+        accumulator.push(&chunk);
+    }
+    Ok(accumulator.into_completion())
+}
+
+/// Progress of a streamed completion, reported to callbacks
+#[derive(Debug, Clone, Copy)]
+pub enum StreamProgress<'a> {
+    /// The request was accepted and the reply is about to arrive
+    Start,
+    /// Text generated since the previous event
+    Token(&'a str),
+    /// Part of a tool call's ID, name or arguments
+    ToolCallProgress(&'a ToolCallDelta),
+    /// The stream finished; the completion assembled from its chunks
+    Finish(&'a ChatCompletion),
+}
+
+/// Collect a chat stream into a completion, reporting its progress to `on_event`
+///
+/// `on_event` sees `Start` first and `Finish` last, unless the stream fails
+/// partway, which is reported like `collect_completion` instead.
+pub async fn collect_completion_with_events<S, F>(
+    stream: S,
+    mut on_event: F,
+) -> Result<ChatCompletion>
+where
+    S: futures::Stream<Item = Result<ChatChunk>>,
+    F: FnMut(StreamProgress<'_>),
+{
+    use futures::StreamExt;
+
+    on_event(StreamProgress::Start);
+    let mut stream = std::pin::pin!(stream);
+    let mut accumulator = StreamAccumulator::new();
+    while let Some(chunk) = stream.next().await {
+        let chunk = chunk.map_err(|error| interrupted(&mut accumulator, error))?;
+        if let Some(choice) = chunk.choices.first() {
+            if let Some(text) = choice.delta.content.as_deref().filter(|t| !t.is_empty()) {
+                on_event(StreamProgress::Token(text));
+            }
+            for delta in choice.delta.tool_calls.iter().flatten() {
+                on_event(StreamProgress::ToolCallProgress(delta));
+            }
+        }
+        accumulator.push(&chunk);
+    }
+    let completion = accumulator.into_completion();
+    on_event(StreamProgress::Finish(&completion));
+    Ok(completion)
+}
+
+/// Write a chat stream's text to `writer` as it arrives, returning the completion
+///
+/// The writer is flushed after every delta, so output shows up as it is
+/// generated. If the stream or a write fails partway, the error is
+/// `GrokError::StreamInterrupted` carrying what was received.
+pub async fn stream_to_writer<S, W>(stream: S, writer: &mut W) -> Result<ChatCompletion>
+where
+    S: futures::Stream<Item = Result<ChatChunk>>,
+    W: tokio::io::AsyncWrite + Unpin + ?Sized,
+{
+    use futures::StreamExt;
+    use tokio::io::AsyncWriteExt;
+
+    let mut stream = std::pin::pin!(stream);
+    let mut accumulator = StreamAccumulator::new();
+    while let Some(chunk) = stream.next().await {
+        let chunk = chunk.map_err(|error| interrupted(&mut accumulator, error))?;
+        accumulator.push(&chunk);
+        if let Some(text) = delta_content(&chunk) {
+            let written = async {
+                writer.write_all(text.as_bytes()).await?;
+                writer.flush().await
+            };
+            written
+                .await
+                .map_err(|e| interrupted(&mut accumulator, GrokError::Io(e)))?;
+        }
+    }
+    Ok(accumulator.into_completion())
+}
+
+/// Text delta of a chunk's first choice
+fn delta_content(chunk: &ChatChunk) -> Option<&str> {
+    chunk.choices.first()?.delta.content.as_deref()
+}
+
+/// `StreamInterrupted` carrying what the accumulator received before `error`
+fn interrupted(accumulator: &mut StreamAccumulator, error: GrokError) -> GrokError {
+    GrokError::StreamInterrupted {
+        partial: Box::new(std::mem::take(accumulator).into_completion()),
+        error: Box::new(error),
+    }
+}
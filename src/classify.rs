@@ -0,0 +1,172 @@
+//! Classifying input into a fixed set of labels
+//!
+//! The reply is constrained to the allowed labels with a JSON schema enum and
+//! checked again after parsing, so callers can route on the label directly.
+
+use crate::error::{GrokError, Result};
+use crate::extract::{complete_json, ExtractInput, ExtractOptions};
+use crate::provider::ChatProvider;
+use crate::Client;
+use serde::de::Error as _;
+use serde::Deserialize;
+
+/// A label assigned to the input
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct Classification {
+    /// One of the allowed labels
+    pub label: String,
+    /// The model's confidence in the label, from 0 to 1, when it gave one
+    #[serde(default)]
+    pub confidence: Option<f64>,
+}
+
+#[derive(Deserialize)]
+struct MultiLabel {
+    labels: Vec<Classification>,
+}
+
+impl Client {
+    /// Pick the one label from `labels` that best fits the input
+    pub async fn classify(
+        &self,
+        input: impl Into<ExtractInput>,
+        labels: &[&str],
+    ) -> Result<Classification> {
+        classify(self, input.into(), labels, ExtractOptions::default()).await
+    }
+
+    /// Pick every label from `labels` that applies to the input; possibly none
+    pub async fn classify_multi(
+        &self,
+        input: impl Into<ExtractInput>,
+        labels: &[&str],
+    ) -> Result<Vec<Classification>> {
+        classify_multi(self, input.into(), labels, ExtractOptions::default()).await
+    }
+}
+
+/// Classify through any provider; see `Client::classify`
+pub async fn classify(
+    provider: &dyn ChatProvider,
+    input: ExtractInput,
+    labels: &[&str],
+    options: ExtractOptions,
+) -> Result<Classification> {
+    check_labels(labels)?;
+    let schema = label_schema(labels);
+    let task = format!(
+        "Classify the user's input with exactly one of these labels: {}.",
+        labels.join(", ")
+    );
+    complete_json(
+        provider,
+        "classification",
+        schema,
+        &task,
+        input,
+        options,
+        |reply| {
+            let classification: Classification = serde_json::from_str(reply)?;
+            check_classification(&classification, labels)?;
+            Ok(classification)
+        },
+    )
+    .await
+}
+
+/// Classify with any number of labels through any provider; see `Client::classify_multi`
+pub async fn classify_multi(
+    provider: &dyn ChatProvider,
+    input: ExtractInput,
+    labels: &[&str],
+    options: ExtractOptions,
+) -> Result<Vec<Classification>> {
+    check_labels(labels)?;
+    let schema = serde_json::json!({
+        "type": "object",
+        "properties": {
+            "labels": { "type": "array", "items": label_schema(labels) }
+        },
+        "required": ["labels"],
+        "additionalProperties": false
+    });
+    let task = format!(
+        "Classify the user's input with every one of these labels that applies, or none: {}.",
+        labels.join(", ")
+    );
+    complete_json(
+        provider,
+        "multi_label_classification",
+        schema,
+        &task,
+        input,
+        options,
+        |reply| {
+            let mut reply: MultiLabel = serde_json::from_str(reply)?;
+            for classification in &reply.labels {
+                check_classification(classification, labels)?;
+            }
+            // Keep the first occurrence of a repeated label
+            let mut seen = Vec::new();
+            reply.labels.retain(|c| {
+                let first = !seen.contains(&c.label);
+                seen.push(c.label.clone());
+                first
+            });
+            Ok(reply.labels)
+        },
+    )
+    .await
+}
+
+/// Reject an empty label set or one that repeats a label
+fn check_labels(labels: &[&str]) -> Result<()> {
+    if labels.is_empty() {
+        return Err(GrokError::InvalidConfig(
+            "Classification needs at least one label".to_string(),
+        ));
+    }
+    let repeated = (1..labels.len()).find(|&i| labels[..i].contains(&labels[i]));
+    if let Some(i) = repeated {
+        return Err(GrokError::InvalidConfig(format!(
+            "Classification label '{}' is repeated",
+            labels[i]
+        )));
+    }
+    Ok(())
+}
+
+fn label_schema(labels: &[&str]) -> serde_json::Value {
+    serde_json::json!({
+        "type": "object",
+        "properties": {
+            "label": { "type": "string", "enum": labels },
+            "confidence": { "type": "number", "minimum": 0, "maximum": 1 }
+        },
+        "required": ["label"],
+        "additionalProperties": false
+    })
+}
+
+/// Reject labels outside the allowed set and confidences outside 0..=1
+fn check_classification(
+    classification: &Classification,
+    labels: &[&str],
+) -> serde_json::Result<()> {
+    if !labels.contains(&classification.label.as_str()) {
+        return Err(serde_json::Error::custom(format!(
+            "label '{}' is not one of: {}",
+            classification.label,
+            labels.join(", ")
+        )));
+    }
+    if let Some(confidence) = classification.confidence {
+        if !(0.0..=1.0).contains(&confidence) {
+            return Err(serde_json::Error::custom(format!(
+                "confidence {} is not between 0 and 1",
+                confidence
+            )));
+        }
+    }
+    Ok(())
+}
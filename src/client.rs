@@ -1,54 +1,95 @@
 //! Main client for interacting with the Grok API
 
-use crate::chat::{ChatCompletion, ChatRequest, ChatResponse, Message, Model, Tool, ChatChunk};
+use crate::agent::{Agent, AgentOptions, AgentRun, StopReason};
+use crate::audit::{AuditExchange, Auditor};
+use crate::budget::{self, Budget};
+use crate::chat::{
+    self, ChatChunk, ChatCompletion, ChatRequest, ChatResponse, Message, Model, RateLimitInfo,
+    Timings, Tool,
+};
 use crate::collections::CollectionManager;
-use crate::error::{GrokError, Result};
-use crate::session::SessionManager;
+use crate::error::{ApiError, GrokError, Result, TimeoutPhase, ValidationError};
+use crate::guardrail::{self, GuardStage, Guardrail};
+use crate::middleware::{OutgoingRequest, RequestInterceptor, ResponseInfo};
+use crate::rate_limit::RateLimiter;
+use crate::secrets::{CachedSecret, SecretProvider};
+use crate::session::{FlushAll, SessionManager};
+use crate::storage::Storage;
+use crate::tools::{ToolRegistry, ToolResult};
+use crate::vcr::Vcr;
+use bytes::BytesMut;
 use reqwest::{Client as HttpClient, Response};
 use serde::de::DeserializeOwned;
-use std::sync::Arc;
-use std::time::Duration;
+use serde::Deserialize;
+use std::borrow::Cow;
+use std::collections::BTreeMap;
+use std::future::Future;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, PoisonError, Weak};
+use std::time::{Duration, Instant};
+
+/// Largest unfinished streamed event buffered by default
+const DEFAULT_MAX_STREAM_BUFFER: usize = 1024 * 1024;
+
+/// Decoded chunks that may wait for the stream consumer by default
+const DEFAULT_STREAM_CAPACITY: usize = 64;
+
+/// Longest wait between retries, whether backing off or told to by Retry-After
+const MAX_RETRY_DELAY: Duration = Duration::from_secs(60);
 
 /// Main client for the Grok API
-#[derive(Debug)]
+///
+/// Clones are cheap and share the HTTP connection pool, along with the rate
+/// limit, budget and shutdown state.
+#[derive(Debug, Clone)]
 pub struct Client {
     http_client: HttpClient,
-    api_key: String,
+    api_key: ApiKey,
     base_url: String,
-    timeout: Option<Duration>,
-    user_agent: Option<String>,
+    stream_idle_timeout: Option<Duration>,
+    stream_decode_policy: StreamDecodePolicy,
+    max_stream_buffer: usize,
+    stream_capacity: usize,
+    stream_overflow_policy: StreamOverflowPolicy,
     request_id: Option<String>,
     max_retries: u32,
     retry_delay: Duration,
+    request_deadline: Option<Duration>,
+    retry_budget: Option<Arc<RetryTokens>>,
+    vcr: Option<Arc<Vcr>>,
+    auditor: Option<Arc<Auditor>>,
+    budget: Option<Arc<Budget>>,
+    rate_limiter: Option<Arc<RateLimiter>>,
+    fallback_models: Vec<Model>,
+    guardrails: Vec<Arc<dyn Guardrail>>,
+    interceptors: Vec<Arc<dyn RequestInterceptor>>,
+    check_context_window: bool,
+    rate_limit: Arc<Mutex<Option<RateLimitInfo>>>,
+    lifecycle: Arc<Lifecycle>,
 }
 
 impl Client {
     /// Create a new client with an API key
     pub fn new(api_key: impl Into<String>) -> Result<Self> {
-        Ok(Self {
-            http_client: HttpClient::new(),
-            api_key: api_key.into(),
-            base_url: "https://api.x.ai/v1".to_string(),
-            timeout: None,
-            user_agent: None,
-            request_id: None,
-            max_retries: 3,
-            retry_delay: Duration::from_millis(1000),
-        })
+        Self::builder().api_key(api_key).build()
     }
 
     /// Create a new client with custom configuration
     pub fn with_config(api_key: impl Into<String>, base_url: impl Into<String>) -> Result<Self> {
-        Ok(Self {
-            http_client: HttpClient::new(),
-            api_key: api_key.into(),
-            base_url: base_url.into(),
-            timeout: None,
-            user_agent: None,
-            request_id: None,
-            max_retries: 3,
-            retry_delay: Duration::from_millis(1000),
-        })
+        Self::builder().api_key(api_key).base_url(base_url).build()
+    }
+
+    /// The API key with all but its first and last four characters hidden, for diagnostics
+    pub fn masked_key(&self) -> String {
+        self.api_key.masked()
+    }
+
+    /// Rate-limit quota reported by the most recent response, from any clone of this client
+    pub fn last_rate_limit(&self) -> Option<RateLimitInfo> {
+        *self
+            .rate_limit
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
     }
 
     /// Create a builder for advanced configuration
@@ -57,15 +98,81 @@ impl Client {
     }
 
     /// Create a session manager for this client
+    ///
+    /// Its sessions are flushed when the client shuts down.
     pub fn session_manager(&self) -> Arc<SessionManager> {
-        Arc::new(SessionManager::new(Arc::new(self.clone())))
+        let manager = Arc::new(SessionManager::new(Arc::new(self.clone())));
+        self.flush_on_shutdown(&manager);
+        manager
+    }
+
+    /// Create a session manager for this client that persists its sessions to storage
+    ///
+    /// Its sessions are flushed when the client shuts down.
+    pub fn session_manager_with_storage<S: Storage + 'static>(
+        &self,
+        storage: Arc<S>,
+    ) -> Arc<SessionManager<S>> {
+        let manager = Arc::new(SessionManager::with_storage(
+            Arc::new(self.clone()),
+            storage,
+        ));
+        self.flush_on_shutdown(&manager);
+        manager
+    }
+
+    /// Flush a session manager's sessions when the client shuts down
+    pub fn flush_on_shutdown<S: Storage + 'static>(&self, manager: &Arc<SessionManager<S>>) {
+        self.lifecycle
+            .managers
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .push(Arc::downgrade(manager) as Weak<dyn FlushAll>);
+    }
+
+    /// Stop accepting requests, let in-flight ones finish, then flush attached session managers
+    ///
+    /// New requests on this client and its clones fail with `ShuttingDown`.
+    /// Completions and streams still running after `deadline` are cancelled;
+    /// a cancelled stream yields the chunks received so far, then the error.
+    pub async fn shutdown(&self, deadline: Duration) -> Result<()> {
+        let lifecycle = &self.lifecycle;
+        lifecycle.accepting.store(false, Ordering::SeqCst);
+
+        let mut in_flight = lifecycle.in_flight.subscribe();
+        if tokio::time::timeout(deadline, in_flight.wait_for(|n| *n == 0))
+            .await
+            .is_err()
+        {
+            #[cfg(feature = "tracing")]
+            tracing::warn!(
+                in_flight = *lifecycle.in_flight.borrow(),
+                "cancelling requests still running at shutdown deadline"
+            );
+            lifecycle.cancel.send_replace(true);
+            let _ = in_flight.wait_for(|n| *n == 0).await;
+        }
+
+        let managers: Vec<Arc<dyn FlushAll>> = lifecycle
+            .managers
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .iter()
+            .filter_map(Weak::upgrade)
+            .collect();
+        for manager in managers {
+            manager.flush_all().await?;
+        }
+        Ok(())
     }
 
     /// Create a collection manager for this client
-    pub fn collection_manager(
+    ///
+    /// Collections are persisted to the session manager's storage, if it has one.
+    pub fn collection_manager<S: Storage + 'static>(
         &self,
-        session_manager: Arc<SessionManager>,
-    ) -> Arc<CollectionManager> {
+        session_manager: Arc<SessionManager<S>>,
+    ) -> Arc<CollectionManager<S>> {
         Arc::new(CollectionManager::new(session_manager))
     }
 
@@ -87,7 +194,104 @@ impl Client {
         tools: Option<Vec<Tool>>,
         options: Option<ChatOptions>,
     ) -> Result<ChatCompletion> {
-        let request = ChatRequest {
+        let messages = messages.into_iter().map(Arc::new).collect();
+        self.chat_shared(model, messages, tools, options).await
+    }
+
+    /// Send a chat completion request for messages shared with a conversation history
+    ///
+    /// The messages are serialized in place, so long histories are not copied.
+    /// Guardrails check each message before sending and the reply after;
+    /// a redacted message is replaced by a copy.
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(
+            name = "grok.chat",
+            skip_all,
+            fields(model = %model, messages = messages.len(), tools = tools.as_ref().map_or(0, Vec::len))
+        )
+    )]
+    pub async fn chat_shared(
+        &self,
+        model: Model,
+        mut messages: Vec<Arc<Message>>,
+        tools: Option<Vec<Tool>>,
+        options: Option<ChatOptions>,
+    ) -> Result<ChatCompletion> {
+        let validation = match &options {
+            Some(ChatOptions {
+                response_format: Some(format),
+                schema_repair_attempts: Some(repairs),
+                ..
+            }) => response_schema(format)?.map(|schema| (schema, *repairs)),
+            _ => None,
+        };
+        let Some((schema, mut repairs_left)) = validation else {
+            return self.chat_once(model, messages, tools, options).await;
+        };
+
+        loop {
+            let completion = self
+                .chat_once(model, messages.clone(), tools.clone(), options.clone())
+                .await?;
+            let errors = schema_violations(&schema, &completion.message.content.text());
+            if errors.is_empty() {
+                return Ok(completion);
+            }
+            if repairs_left == 0 {
+                return Err(GrokError::SchemaValidation {
+                    payload: completion.message.content.to_string(),
+                    errors,
+                });
+            }
+            repairs_left -= 1;
+
+            #[cfg(feature = "tracing")]
+            tracing::debug!(
+                errors = errors.len(),
+                "repairing reply that doesn't match the response schema"
+            );
+
+            let feedback = format!(
+                "That reply doesn't match the JSON schema: {}. Reply again with only the corrected JSON object.",
+                errors.join("; ")
+            );
+            messages.push(Arc::new(completion.message));
+            messages.push(Arc::new(Message {
+                role: crate::chat::Role::User,
+                content: feedback.into(),
+                tool_calls: None,
+                tool_call_id: None,
+                name: None,
+            }));
+        }
+    }
+
+    /// Send one request for `chat_shared`, without schema repairs
+    async fn chat_once(
+        &self,
+        model: Model,
+        mut messages: Vec<Arc<Message>>,
+        tools: Option<Vec<Tool>>,
+        options: Option<ChatOptions>,
+    ) -> Result<ChatCompletion> {
+        let mut violations = options
+            .as_ref()
+            .map(ChatOptions::violations)
+            .unwrap_or_default();
+        violations.extend(message_violations(&messages));
+        if !violations.is_empty() {
+            return Err(GrokError::Validation(violations));
+        }
+
+        let mut annotations = Vec::new();
+        guardrail::apply_all(&self.guardrails, &mut messages, &mut annotations).await?;
+        if self.check_context_window {
+            let max_tokens = options.as_ref().and_then(|o| o.max_tokens);
+            chat::check_context_window(model, &messages, tools.as_deref(), max_tokens)?;
+        }
+
+        let mut request = ChatRequest {
             model: model.as_str().to_string(),
             messages,
             max_tokens: options.as_ref().and_then(|o| o.max_tokens),
@@ -100,96 +304,503 @@ impl Client {
             stream: options.as_ref().and_then(|o| o.stream),
         };
 
-        let response: ChatResponse = self.post("/chat/completions", &request).await?;
+        let _in_flight = self.lifecycle.begin()?;
+        if let Some(budget) = &self.budget {
+            self.lifecycle.cancellable(budget.acquire()).await?;
+        }
+        let permit = match &self.rate_limiter {
+            Some(limiter) => {
+                let prompt_tokens =
+                    chat::estimate_prompt_tokens(&request.messages, request.tools.as_deref());
+                let tokens = prompt_tokens as u64 + request.max_tokens.unwrap_or(0) as u64;
+                let permit = self
+                    .lifecycle
+                    .cancellable(async { Ok(limiter.acquire(tokens).await) })
+                    .await?;
+                Some(permit)
+            }
+            None => None,
+        };
+        let requested = Instant::now();
+        let (model, response, attempt_started, rate_limit) = self
+            .lifecycle
+            .cancellable(self.post_chat(model, &mut request))
+            .await?;
+        let total = requested.elapsed();
+        let generation = attempt_started.elapsed();
+        let timings = Timings {
+            queue: attempt_started.duration_since(requested),
+            time_to_first_token: generation,
+            total,
+            output_tokens_per_second: response
+                .usage
+                .as_ref()
+                .filter(|_| !generation.is_zero())
+                .map(|usage| usage.completion_tokens as f64 / generation.as_secs_f64()),
+        };
 
-        let choice = response
-            .choices
-            .into_iter()
-            .next()
-            .ok_or_else(|| GrokError::Api {
-                status: 500,
-                message: "No choices returned".to_string(),
+        let choice =
+            response.choices.into_iter().next().ok_or_else(|| {
+                GrokError::Api(Box::new(ApiError::new(500, "No choices returned")))
             })?;
 
+        #[cfg(feature = "tracing")]
+        tracing::debug!(
+            completion_id = %response.id,
+            finish_reason = choice.finish_reason.as_deref(),
+            total_tokens = response.usage.as_ref().map(|u| u.total_tokens),
+            "chat completion received"
+        );
+
+        if let Some(budget) = &self.budget {
+            let (prompt_tokens, completion_tokens) = match &response.usage {
+                Some(usage) => (usage.prompt_tokens, usage.completion_tokens),
+                None => (
+                    chat::estimate_prompt_tokens(&request.messages, request.tools.as_deref()),
+                    budget::estimate_reply_tokens(&choice.message.content.text()),
+                ),
+            };
+            budget.record(model, prompt_tokens, completion_tokens);
+        }
+        if let (Some(limiter), Some(permit), Some(usage)) =
+            (&self.rate_limiter, permit, &response.usage)
+        {
+            limiter.settle(permit, usage.total_tokens as u64);
+        }
+
+        let mut message = choice.message;
+        if let Some(redacted) = guardrail::apply(
+            &self.guardrails,
+            &message,
+            GuardStage::Response,
+            &mut annotations,
+        )
+        .await?
+        {
+            message = redacted;
+        }
+
         Ok(ChatCompletion {
             id: response.id,
             model: response.model,
             usage: response.usage,
-            message: choice.message,
+            message,
             finish_reason: choice.finish_reason,
+            timings: Some(timings),
+            annotations,
+            rate_limit,
         })
     }
 
+    /// Post a chat request to `model`, then to each fallback model while the
+    /// failure is one another model may not have, returning the model that answered
+    async fn post_chat(
+        &self,
+        model: Model,
+        request: &mut ChatRequest,
+    ) -> Result<(Model, ChatResponse, Instant, Option<RateLimitInfo>)> {
+        let mut error = match self.post("/chat/completions", &*request).await {
+            Ok((response, started, rate_limit)) => {
+                return Ok((model, response, started, rate_limit))
+            }
+            Err(error) => error,
+        };
+
+        for &fallback in self
+            .fallback_models
+            .iter()
+            .filter(|&&fallback| fallback != model)
+        {
+            if !error.should_fall_back() {
+                break;
+            }
+            if self.check_context_window
+                && chat::check_context_window(
+                    fallback,
+                    &request.messages,
+                    request.tools.as_deref(),
+                    request.max_tokens,
+                )
+                .is_err()
+            {
+                continue;
+            }
+
+            #[cfg(feature = "tracing")]
+            tracing::warn!(
+                %error,
+                from = %request.model,
+                to = %fallback,
+                "falling back to another model"
+            );
+
+            request.model = fallback.as_str().to_string();
+            match self.post("/chat/completions", &*request).await {
+                Ok((response, started, rate_limit)) => {
+                    return Ok((fallback, response, started, rate_limit))
+                }
+                Err(failure) => error = failure,
+            }
+        }
+        Err(error)
+    }
+
+    /// Chat, executing the tools the model calls and sending back their results
+    ///
+    /// The stateless counterpart of `Agent` for one-shot tasks. At most
+    /// `max_iterations` completions are requested (at least one); if the last
+    /// one still asks for tools, they are not run and the stop reason is
+    /// `MaxIterations`. A tool failure is reported to the model or ends the
+    /// loop according to the registry's error policy.
+    pub async fn chat_with_auto_tools(
+        &self,
+        model: Model,
+        messages: Vec<Message>,
+        registry: &ToolRegistry,
+        max_iterations: usize,
+    ) -> Result<AutoToolsCompletion> {
+        let tools: Vec<Tool> = registry.api_tools().into_iter().map(Into::into).collect();
+        let tools = Some(tools).filter(|tools| !tools.is_empty());
+        let mut history: Vec<Arc<Message>> = messages.into_iter().map(Arc::new).collect();
+        let mut tool_calls = Vec::new();
+
+        let mut iteration = 0;
+        loop {
+            iteration += 1;
+            let completion = self
+                .chat_shared(model, history.clone(), tools.clone(), None)
+                .await?;
+            let requested = completion.message.tool_calls.clone().unwrap_or_default();
+            history.push(Arc::new(completion.message.clone()));
+
+            if requested.is_empty() || iteration >= max_iterations {
+                let stop_reason = if requested.is_empty() {
+                    StopReason::Completed
+                } else {
+                    StopReason::MaxIterations
+                };
+                return Ok(AutoToolsCompletion {
+                    completion,
+                    tool_calls,
+                    stop_reason,
+                    messages: history.into_iter().map(Arc::unwrap_or_clone).collect(),
+                });
+            }
+
+            for call in &requested {
+                let call = crate::tools::ToolCall::from(call);
+                let result = registry.execute_tool_call(&call).await?;
+                history.push(Arc::new(Message {
+                    role: crate::chat::Role::Tool,
+                    content: result.content.clone().into(),
+                    tool_calls: None,
+                    tool_call_id: Some(result.tool_call_id.clone()),
+                    name: Some(call.function.name.clone()),
+                }));
+                tool_calls.push((call, result));
+            }
+        }
+    }
+
+    /// Run an agent loop over `messages`, executing tool calls until the model answers
+    ///
+    /// Like `chat_with_auto_tools`, with the iteration limit, token budget and
+    /// tool error handling of `AgentOptions`. The registry is shared, not copied:
+    /// its tools, cache and metrics are the same ones the caller sees.
+    pub async fn run_agent(
+        &self,
+        model: Model,
+        messages: Vec<Message>,
+        registry: &ToolRegistry,
+        options: AgentOptions,
+    ) -> Result<AgentRun> {
+        Agent::new(Arc::new(self.clone()), model, registry.clone(), options)
+            .run_messages(messages)
+            .await
+    }
+
+    /// Embed texts with an embedding model, returning one vector per input in order
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(name = "grok.embed", skip_all, fields(model = %model, inputs = input.len()))
+    )]
+    pub async fn embed(&self, model: &str, input: Vec<String>) -> Result<Vec<Vec<f32>>> {
+        let expected = input.len();
+        let request = EmbeddingRequest { model, input };
+
+        let _in_flight = self.lifecycle.begin()?;
+        if let Some(limiter) = &self.rate_limiter {
+            let tokens = request
+                .input
+                .iter()
+                .map(|text| budget::estimate_reply_tokens(text) as u64)
+                .sum();
+            self.lifecycle
+                .cancellable(async { Ok(limiter.acquire(tokens).await) })
+                .await?;
+        }
+        let (response, _, _): (EmbeddingResponse, Instant, _) = self
+            .lifecycle
+            .cancellable(self.post("/embeddings", &request))
+            .await?;
+
+        let mut data = response.data;
+        if data.len() != expected {
+            return Err(GrokError::Api(Box::new(ApiError::new(
+                500,
+                format!("Expected {} embeddings, got {}", expected, data.len()),
+            ))));
+        }
+        data.sort_by_key(|item| item.index);
+        Ok(data.into_iter().map(|item| item.embedding).collect())
+    }
+
     /// Stream a chat completion
+    ///
+    /// Chunks are yielded as they arrive. At most `stream_capacity` decoded
+    /// chunks wait for a slow consumer; `StreamOverflowPolicy` sets what
+    /// happens beyond that.
     pub async fn chat_stream(
         &self,
         model: Model,
         messages: Vec<Message>,
         tools: Option<Vec<Tool>>,
     ) -> Result<impl futures::Stream<Item = Result<ChatChunk>>> {
-        use futures::StreamExt;
+        self.chat_stream_with_options(model, messages, tools, None)
+            .await
+    }
+
+    /// Stream a chat completion with full options
+    ///
+    /// Replies are not checked against a response schema, since they reach
+    /// the caller as they arrive, so `schema_repair_attempts` is ignored.
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(
+            name = "grok.chat_stream",
+            skip_all,
+            fields(model = %model, messages = messages.len())
+        )
+    )]
+    pub async fn chat_stream_with_options(
+        &self,
+        model: Model,
+        messages: Vec<Message>,
+        tools: Option<Vec<Tool>>,
+        options: Option<ChatOptions>,
+    ) -> Result<impl futures::Stream<Item = Result<ChatChunk>>> {
+        let mut messages: Vec<Arc<Message>> = messages.into_iter().map(Arc::new).collect();
+        let mut violations = options
+            .as_ref()
+            .map(ChatOptions::violations)
+            .unwrap_or_default();
+        violations.extend(message_violations(&messages));
+        if !violations.is_empty() {
+            return Err(GrokError::Validation(violations));
+        }
+        // Streamed replies reach the caller as they arrive, so only the request is checked
+        guardrail::apply_all(&self.guardrails, &mut messages, &mut Vec::new()).await?;
+        let options = options.unwrap_or_default();
+        if self.check_context_window {
+            chat::check_context_window(model, &messages, tools.as_deref(), options.max_tokens)?;
+        }
 
         let request = ChatRequest {
             model: model.as_str().to_string(),
             messages,
-            max_tokens: None,
-            temperature: None,
-            top_p: None,
+            max_tokens: options.max_tokens,
+            temperature: options.temperature,
+            top_p: options.top_p,
             tools,
-            tool_choice: None,
-            response_format: None,
-            stop: None,
+            tool_choice: options.tool_choice,
+            response_format: options.response_format,
+            stop: options.stop,
             stream: Some(true),
         };
 
         let mut request_builder = self
             .http_client
-            .post(&format!("{}/chat/completions", self.base_url))
-            .header("Authorization", format!("Bearer {}", self.api_key))
+            .post(format!("{}/chat/completions", self.base_url))
+            .bearer_auth(self.api_key.expose().await?)
             .header("Content-Type", "application/json");
 
         if let Some(ref request_id) = self.request_id {
             request_builder = request_builder.header("X-Request-ID", request_id);
         }
 
-        let response = request_builder.json(&request).send().await?;
+        let in_flight = self.lifecycle.begin()?;
+        if let Some(budget) = &self.budget {
+            self.lifecycle.cancellable(budget.acquire()).await?;
+        }
+        if let Some(limiter) = &self.rate_limiter {
+            let prompt_tokens =
+                chat::estimate_prompt_tokens(&request.messages, request.tools.as_deref());
+            let tokens = prompt_tokens as u64 + request.max_tokens.unwrap_or(0) as u64;
+            self.lifecycle
+                .cancellable(async { Ok(limiter.acquire(tokens).await) })
+                .await?;
+        }
+        // Streamed replies don't report usage, so they're counted by estimate
+        let spend = self.budget.clone().map(|budget| StreamSpend {
+            budget,
+            model,
+            prompt_tokens: chat::estimate_prompt_tokens(
+                &request.messages,
+                request.tools.as_deref(),
+            ),
+            completion_chars: 0,
+        });
+        let started = Instant::now();
+        let (response, mut exchange) = self
+            .lifecycle
+            .cancellable(self.send("/chat/completions", request_builder, &request, started))
+            .await?;
+        self.observe_rate_limit(&response);
 
         if !response.status().is_success() {
-            let status = response.status().as_u16();
-            let message = response.text().await.unwrap_or_default();
-            return Err(GrokError::Api { status, message });
-        }
-
-        // Collect all response data
-        let body_bytes = response.bytes().await.map_err(GrokError::Http)?;
-        let body_text = String::from_utf8_lossy(&body_bytes);
-
-        // Parse SSE format and collect chunks
-        let mut chunks = Vec::new();
-        for line in body_text.lines() {
-            if line.starts_with("data: ") {
-                let data = &line[6..];
-                if data == "[DONE]" {
-                    break;
-                }
-                if let Ok(chunk) = serde_json::from_str::<ChatChunk>(data) {
-                    chunks.push(chunk);
-                }
-            }
+            let response = match exchange {
+                Some(exchange) => exchange.response(response).await?,
+                None => response,
+            };
+            return Err(self.error_from_response(response).await);
+        }
+        if let Some(exchange) = exchange.as_mut() {
+            exchange.observe_request_id(response.headers());
         }
 
-        // Convert to stream
-        let stream = futures::stream::iter(chunks.into_iter().map(Ok));
+        // Chunks are decoded in the background into a bounded channel, so a slow
+        // consumer holds back reading from the connection instead of filling memory
+        let (sender, receiver) = tokio::sync::mpsc::channel(self.stream_capacity);
+        let pump = ChunkPump {
+            status: response.status().as_u16(),
+            response,
+            started,
+            idle_timeout: self.stream_idle_timeout,
+            decode_policy: self.stream_decode_policy,
+            max_buffer: self.max_stream_buffer,
+            overflow_policy: self.stream_overflow_policy,
+            sender: sender.clone(),
+            delivered: 0,
+            audit: exchange.map(|exchange| (exchange, Vec::new())),
+            spend,
+        };
+        let lifecycle = self.lifecycle.clone();
+        let task = async move {
+            let failure = tokio::select! {
+                failure = pump.run() => failure,
+                _ = lifecycle.cancelled() => Some(GrokError::ShuttingDown),
+            };
+            drop(in_flight);
+            if let Some(error) = failure {
+                let _ = sender.send(Err(error)).await;
+            }
+        };
+        #[cfg(feature = "tracing")]
+        let task = tracing::Instrument::in_current_span(task);
+        tokio::spawn(task);
 
+        let stream = futures::stream::unfold(receiver, |mut receiver| async move {
+            let item = receiver.recv().await?;
+            Some((item, receiver))
+        });
         Ok(stream)
     }
 
-    /// Make a POST request to the API
+    /// Stream a chat completion, reporting its progress to a callback
+    ///
+    /// For callers that would rather not drive a `Stream`, such as FFI
+    /// bindings: `on_event` receives `Start` once the request is accepted,
+    /// each token and tool call fragment as it arrives, and `Finish` with the
+    /// completion, which is also returned.
+    pub async fn chat_stream_with<F>(
+        &self,
+        model: Model,
+        messages: Vec<Message>,
+        tools: Option<Vec<Tool>>,
+        on_event: F,
+    ) -> Result<ChatCompletion>
+    where
+        F: FnMut(chat::StreamProgress<'_>),
+    {
+        let stream = self.chat_stream(model, messages, tools).await?;
+        chat::collect_completion_with_events(stream, on_event).await
+    }
+
+    /// Send a JSON request body, through the interceptors and the VCR when set
+    ///
+    /// With an auditor, the request is recorded before it's sent and the
+    /// returned exchange records the response.
+    async fn send<T: serde::Serialize>(
+        &self,
+        endpoint: &str,
+        request: reqwest::RequestBuilder,
+        body: &T,
+        started: Instant,
+    ) -> Result<(Response, Option<AuditExchange>)> {
+        let mut request = request.json(body).build().map_err(GrokError::Http)?;
+        let intercepted = if self.interceptors.is_empty() {
+            None
+        } else {
+            let mut outgoing = OutgoingRequest {
+                endpoint: endpoint.to_string(),
+                headers: std::mem::take(request.headers_mut()),
+                body: serde_json::to_value(body)?,
+            };
+            for interceptor in &self.interceptors {
+                interceptor.before_request(&mut outgoing).await?;
+            }
+            *request.headers_mut() = outgoing.headers;
+            *request.body_mut() = Some(serde_json::to_vec(&outgoing.body)?.into());
+            Some(outgoing.body)
+        };
+        let body = match intercepted {
+            Some(body) => body,
+            None if self.auditor.is_some() || self.vcr.is_some() => serde_json::to_value(body)?,
+            None => serde_json::Value::Null,
+        };
+
+        let exchange = match &self.auditor {
+            Some(auditor) => {
+                let request_id = self.request_id.clone();
+                Some(auditor.begin(endpoint, request_id, &body).await?)
+            }
+            None => None,
+        };
+        let sent = Instant::now();
+        let send = async {
+            self.http_client
+                .execute(request)
+                .await
+                .map_err(|e| http_error(e, started))
+        };
+        let response = match &self.vcr {
+            Some(vcr) => vcr.exchange(endpoint, body, send).await?,
+            None => send.await?,
+        };
+
+        let info = ResponseInfo {
+            endpoint,
+            status: response.status().as_u16(),
+            headers: response.headers(),
+            latency: sent.elapsed(),
+        };
+        for interceptor in &self.interceptors {
+            interceptor.after_response(&info).await;
+        }
+        Ok((response, exchange))
+    }
+
+    /// Make a POST request to the API, returning the parsed body, when the
+    /// successful attempt started and the quota it reported
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(name = "grok.request", skip_all, fields(endpoint = %endpoint))
+    )]
     async fn post<T: serde::Serialize, R: DeserializeOwned>(
         &self,
         endpoint: &str,
         body: &T,
-    ) -> Result<R> {
+    ) -> Result<(R, Instant, Option<RateLimitInfo>)> {
         use backon::ExponentialBuilder;
         use backon::Retryable;
 
@@ -199,62 +810,608 @@ impl Client {
             let mut request = self
                 .http_client
                 .post(&url)
-                .header("Authorization", format!("Bearer {}", self.api_key))
+                .bearer_auth(self.api_key.expose().await?)
                 .header("Content-Type", "application/json");
 
             if let Some(ref request_id) = self.request_id {
                 request = request.header("X-Request-ID", request_id);
             }
 
-            let response = request.json(body).send().await?;
-            self.handle_response(response).await
+            let started = Instant::now();
+            let response = match self.send(endpoint, request, body, started).await? {
+                (response, Some(exchange)) => exchange.response(response).await?,
+                (response, None) => response,
+            };
+            let rate_limit = self.observe_rate_limit(&response);
+            let parsed = self.handle_response(response, started).await?;
+            Ok((parsed, started, rate_limit))
         };
 
-        // Retry on 429 (rate limit) and 5xx errors
+        // Retry on network errors, 429 (rate limit) and 5xx errors
         let backoff = ExponentialBuilder::default()
             .with_min_delay(self.retry_delay)
-            .with_max_delay(Duration::from_secs(60))
-            .with_max_times(self.max_retries);
+            .with_max_delay(MAX_RETRY_DELAY)
+            .with_max_times(self.max_retries as usize);
+
+        let requested = Instant::now();
+        let deadline = self.request_deadline.map(|limit| requested + limit);
+        if let Some(budget) = &self.retry_budget {
+            budget.deposit();
+        }
 
-        operation
+        // Wait as long as a rate limit asks rather than guessing, and give up
+        // instead of sleeping past the deadline or spending retries the budget lacks.
+        // A rate limit asking for more than the longest retry delay is returned
+        // as is, so the caller decides whether to wait that long
+        let retry = operation
             .retry(backoff)
-            .when(|e: &GrokError| match e {
-                GrokError::Api { status, .. } => *status == 429 || *status >= 500,
-                GrokError::Http(_) => true, // Retry on network errors
-                _ => false,
-            })
-            .await
+            .when(GrokError::is_retryable)
+            .adjust(|error: &GrokError, delay| {
+                let delay = error.retry_after().unwrap_or(delay?);
+                if delay > MAX_RETRY_DELAY {
+                    return None;
+                }
+                if deadline.is_some_and(|deadline| Instant::now() + delay >= deadline) {
+                    return None;
+                }
+                match &self.retry_budget {
+                    Some(budget) => budget.withdraw().then_some(delay),
+                    None => Some(delay),
+                }
+            });
+        #[cfg(feature = "tracing")]
+        let retry = retry.notify(|error: &GrokError, delay: Duration| {
+            tracing::warn!(%error, delay_ms = delay.as_millis() as u64, "retrying request")
+        });
+
+        match self.request_deadline {
+            Some(limit) => tokio::time::timeout(limit, retry)
+                .await
+                .unwrap_or_else(|_| {
+                    Err(GrokError::Timeout {
+                        elapsed: requested.elapsed(),
+                        phase: TimeoutPhase::Deadline,
+                    })
+                }),
+            None => retry.await,
+        }
+    }
+
+    /// Remember the quota reported by a response, successful or not
+    fn observe_rate_limit(&self, response: &Response) -> Option<RateLimitInfo> {
+        let info = RateLimitInfo::from_headers(response.headers())?;
+        if let Some(limiter) = &self.rate_limiter {
+            limiter.observe(&info);
+        }
+        *self
+            .rate_limit
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner) = Some(info);
+        Some(info)
     }
 
     /// Handle API response
-    async fn handle_response<R: DeserializeOwned>(&self, response: Response) -> Result<R> {
+    async fn handle_response<R: DeserializeOwned>(
+        &self,
+        response: Response,
+        started: Instant,
+    ) -> Result<R> {
         if response.status().is_success() {
-            response.json().await.map_err(GrokError::from)
+            response.json().await.map_err(|e| http_error(e, started))
         } else {
-            let status = response.status().as_u16();
-            let message = response.text().await.unwrap_or_default();
-            Err(GrokError::Api { status, message })
+            Err(self.error_from_response(response).await)
+        }
+    }
+
+    /// Build the error for a failed response, keeping the headers needed to report it
+    async fn error_from_response(&self, response: Response) -> GrokError {
+        let status = response.status().as_u16();
+        let headers: BTreeMap<String, String> = response
+            .headers()
+            .iter()
+            .filter(|(name, _)| {
+                let name = name.as_str();
+                name == "x-request-id" || name == "retry-after" || name.starts_with("x-ratelimit-")
+            })
+            .filter_map(|(name, value)| Some((name.to_string(), value.to_str().ok()?.to_string())))
+            .collect();
+        let rate_limit = RateLimitInfo::from_headers(response.headers());
+        let body = response.text().await.unwrap_or_default();
+
+        let mut error = ApiError::from_response(status, body);
+        error.request_id = headers
+            .get("x-request-id")
+            .cloned()
+            .or_else(|| self.request_id.clone());
+
+        #[cfg(feature = "tracing")]
+        tracing::warn!(
+            status,
+            request_id = error.request_id.as_deref(),
+            code = error.code.as_deref(),
+            "API request failed"
+        );
+
+        if status == 401 {
+            self.api_key.invalidate();
+        }
+
+        match status {
+            401 | 403 => GrokError::Authentication {
+                message: error.message,
+                request_id: error.request_id,
+            },
+            429 => GrokError::RateLimit {
+                retry_after: headers
+                    .get("retry-after")
+                    .and_then(|v| parse_retry_after(v))
+                    .or_else(|| rate_limit.as_ref().and_then(quota_reset)),
+                request_id: error.request_id,
+                headers,
+            },
+            _ => {
+                error.headers = headers;
+                GrokError::Api(Box::new(error))
+            }
+        }
+    }
+}
+
+/// Build the error for stream data that isn't a chunk, which may be an error event
+fn decode_error(status: u16, data: &str, error: serde_json::Error) -> GrokError {
+    let is_error_event = serde_json::from_str::<serde_json::Value>(data)
+        .is_ok_and(|value| value.get("error").is_some());
+    if is_error_event {
+        GrokError::Api(Box::new(ApiError::from_response(status, data)))
+    } else {
+        GrokError::StreamDecode {
+            data: data.to_string(),
+            source: error,
         }
     }
 }
 
-impl Clone for Client {
-    fn clone(&self) -> Self {
+/// Turn a transport error into `Timeout` if that's what it was
+fn http_error(error: reqwest::Error, started: Instant) -> GrokError {
+    if !error.is_timeout() {
+        return GrokError::Http(error);
+    }
+    let phase = if error.is_connect() {
+        TimeoutPhase::Connect
+    } else {
+        TimeoutPhase::Request
+    };
+    GrokError::Timeout {
+        elapsed: started.elapsed(),
+        phase,
+    }
+}
+
+/// Parse a `Retry-After` header given in seconds or as an HTTP date
+fn parse_retry_after(value: &str) -> Option<u64> {
+    if let Ok(seconds) = value.trim().parse() {
+        return Some(seconds);
+    }
+    let date = chrono::DateTime::parse_from_rfc2822(value.trim()).ok()?;
+    let seconds = (date.with_timezone(&chrono::Utc) - chrono::Utc::now()).num_seconds();
+    Some(seconds.max(0) as u64)
+}
+
+/// Seconds until the exhausted quota in the rate-limit headers resets, rounded up
+fn quota_reset(info: &RateLimitInfo) -> Option<u64> {
+    [
+        (info.remaining_requests, info.reset_requests),
+        (info.remaining_tokens, info.reset_tokens),
+    ]
+    .into_iter()
+    .filter(|(remaining, _)| *remaining == Some(0))
+    .filter_map(|(_, reset)| reset)
+    .max()
+    .map(|reset| reset.as_secs_f64().ceil() as u64)
+}
+
+/// Request body for the embeddings endpoint
+#[derive(serde::Serialize)]
+struct EmbeddingRequest<'a> {
+    model: &'a str,
+    input: Vec<String>,
+}
+
+/// Response body of the embeddings endpoint
+#[derive(Deserialize)]
+struct EmbeddingResponse {
+    data: Vec<EmbeddingData>,
+}
+
+#[derive(Deserialize)]
+struct EmbeddingData {
+    index: usize,
+    embedding: Vec<f32>,
+}
+
+/// Reads a streamed response and passes its chunks to the consumer
+struct ChunkPump {
+    response: Response,
+    status: u16,
+    started: Instant,
+    idle_timeout: Option<Duration>,
+    decode_policy: StreamDecodePolicy,
+    max_buffer: usize,
+    overflow_policy: StreamOverflowPolicy,
+    sender: tokio::sync::mpsc::Sender<Result<ChatChunk>>,
+    delivered: usize,
+    /// The exchange recording this stream and the frames received so far
+    audit: Option<(AuditExchange, Vec<String>)>,
+    /// The budget charged for this stream and what's been received so far
+    spend: Option<StreamSpend>,
+}
+
+/// Estimated usage of a stream, charged to a budget when it ends
+struct StreamSpend {
+    budget: Arc<Budget>,
+    model: Model,
+    prompt_tokens: u32,
+    completion_chars: usize,
+}
+
+/// What one line of a server-sent event stream holds
+enum SseLine {
+    /// A comment, blank line or other field
+    Ignored,
+    /// The `[DONE]` marker
+    Done,
+    /// A chunk, or the error decoding it
+    Chunk(Result<ChatChunk>),
+}
+
+impl ChunkPump {
+    /// Pass on chunks until the stream ends, returning the error that ended it early
+    ///
+    /// The error is returned rather than sent so it follows everything sent before it.
+    async fn run(mut self) -> Option<GrokError> {
+        let mut buffer = BytesMut::new();
+        // Bytes already searched for a newline, so each byte is scanned once
+        let mut scanned = 0;
+        let failure = 'receive: loop {
+            let chunk = match self.idle_timeout {
+                Some(idle) => match tokio::time::timeout(idle, self.response.chunk()).await {
+                    Ok(chunk) => chunk,
+                    Err(_) => {
+                        break Some(GrokError::Timeout {
+                            elapsed: self.started.elapsed(),
+                            phase: TimeoutPhase::StreamIdle,
+                        })
+                    }
+                },
+                None => self.response.chunk().await,
+            };
+            let bytes = match chunk {
+                Ok(Some(bytes)) => bytes,
+                Ok(None) => break None,
+                Err(e) => break Some(http_error(e, self.started)),
+            };
+
+            let elapsed = self.started.elapsed();
+            buffer.extend_from_slice(&bytes);
+            while let Some(offset) = buffer[scanned..].iter().position(|&b| b == b'\n') {
+                let line = buffer.split_to(scanned + offset + 1);
+                scanned = 0;
+                match self.handle_line(&line, elapsed).await {
+                    Ok(true) => {}
+                    Ok(false) => {
+                        buffer.clear();
+                        break 'receive None;
+                    }
+                    Err(error) => break 'receive Some(error),
+                }
+            }
+            scanned = buffer.len();
+            if buffer.len() > self.max_buffer {
+                break Some(GrokError::StreamBufferOverflow {
+                    limit: self.max_buffer,
+                });
+            }
+        };
+
+        // The last line may not end with a newline
+        let failure = match failure {
+            None if !buffer.is_empty() => {
+                let elapsed = self.started.elapsed();
+                self.handle_line(&buffer, elapsed).await.err()
+            }
+            failure => failure,
+        };
+
+        if let Some(spend) = self.spend.take() {
+            let completion_tokens = spend.completion_chars.div_ceil(4) as u32;
+            spend
+                .budget
+                .record(spend.model, spend.prompt_tokens, completion_tokens);
+        }
+
+        // An audit trail that can't be written fails the stream like any other error
+        let failure = match self.audit.take() {
+            Some((exchange, frames)) => match exchange.frames(self.status, frames).await {
+                Ok(()) => failure,
+                Err(error) => failure.or(Some(error)),
+            },
+            None => failure,
+        };
+
+        #[cfg(feature = "tracing")]
+        match failure {
+            Some(ref error) => {
+                tracing::warn!(chunks = self.delivered, %error, "chat stream failed")
+            }
+            None => tracing::debug!(chunks = self.delivered, "chat stream received"),
+        }
+        failure
+    }
+
+    /// Decode and deliver one line, returning false once the stream should end
+    async fn handle_line(&mut self, line: &[u8], elapsed: Duration) -> Result<bool> {
+        if let Some((_, frames)) = self.audit.as_mut() {
+            if let Some(data) = line.trim_ascii_end().strip_prefix(b"data: ") {
+                frames.push(String::from_utf8_lossy(data).into_owned());
+            }
+        }
+        let item = match decode_sse_line(line, self.status, elapsed) {
+            SseLine::Ignored => return Ok(true),
+            SseLine::Done => return Ok(false),
+            SseLine::Chunk(item) => item,
+        };
+        if let (Some(spend), Ok(chunk)) = (self.spend.as_mut(), &item) {
+            spend.completion_chars += chunk
+                .choices
+                .iter()
+                .filter_map(|choice| choice.delta.content.as_deref())
+                .map(|content| content.chars().count())
+                .sum::<usize>();
+        }
+        let failed = item.is_err();
+        if !self.deliver(item).await? {
+            return Ok(false);
+        }
+        Ok(!failed || self.decode_policy == StreamDecodePolicy::Continue)
+    }
+
+    /// Queue an item for the consumer, returning false if it has gone away
+    async fn deliver(&mut self, item: Result<ChatChunk>) -> Result<bool> {
+        use tokio::sync::mpsc::error::TrySendError;
+
+        let delivered = match self.overflow_policy {
+            StreamOverflowPolicy::Wait => self.sender.send(item).await.is_ok(),
+            StreamOverflowPolicy::Fail => match self.sender.try_send(item) {
+                Ok(()) => true,
+                Err(TrySendError::Full(_)) => {
+                    return Err(GrokError::StreamLagged {
+                        capacity: self.sender.max_capacity(),
+                    })
+                }
+                Err(TrySendError::Closed(_)) => false,
+            },
+        };
+        self.delivered += usize::from(delivered);
+        Ok(delivered)
+    }
+}
+
+/// Decode one line of a server-sent event stream
+fn decode_sse_line(line: &[u8], status: u16, elapsed: Duration) -> SseLine {
+    let Some(data) = line.trim_ascii_end().strip_prefix(b"data: ") else {
+        return SseLine::Ignored;
+    };
+    if data == b"[DONE]" {
+        return SseLine::Done;
+    }
+
+    match serde_json::from_slice::<ChatChunk>(data) {
+        Ok(mut chunk) => {
+            chunk.elapsed = elapsed;
+            SseLine::Chunk(Ok(chunk))
+        }
+        Err(e) => {
+            let error = decode_error(status, &String::from_utf8_lossy(data), e);
+            #[cfg(feature = "tracing")]
+            tracing::warn!(%error, "failed to decode stream chunk");
+            SseLine::Chunk(Err(error))
+        }
+    }
+}
+
+/// Shutdown state shared by a client and its clones
+#[derive(Debug)]
+struct Lifecycle {
+    accepting: AtomicBool,
+    in_flight: tokio::sync::watch::Sender<usize>,
+    cancel: tokio::sync::watch::Sender<bool>,
+    managers: Mutex<Vec<Weak<dyn FlushAll>>>,
+}
+
+impl Default for Lifecycle {
+    fn default() -> Self {
+        Self {
+            accepting: AtomicBool::new(true),
+            in_flight: tokio::sync::watch::Sender::new(0),
+            cancel: tokio::sync::watch::Sender::new(false),
+            managers: Mutex::new(Vec::new()),
+        }
+    }
+}
+
+impl Lifecycle {
+    /// Count a request as in flight until the guard drops, unless shutting down
+    fn begin(self: &Arc<Self>) -> Result<InFlight> {
+        // Count first so `shutdown` can't see zero between the check and the increment
+        self.in_flight.send_modify(|n| *n += 1);
+        let guard = InFlight(self.clone());
+        if !self.accepting.load(Ordering::SeqCst) {
+            return Err(GrokError::ShuttingDown);
+        }
+        Ok(guard)
+    }
+
+    /// Resolve once `shutdown` gives up waiting for in-flight requests
+    async fn cancelled(&self) {
+        let _ = self.cancel.subscribe().wait_for(|cancel| *cancel).await;
+    }
+
+    /// Run a request, abandoning it if it's cancelled by `shutdown`
+    async fn cancellable<T>(&self, request: impl Future<Output = Result<T>>) -> Result<T> {
+        tokio::select! {
+            result = request => result,
+            _ = self.cancelled() => Err(GrokError::ShuttingDown),
+        }
+    }
+}
+
+/// Marks a request as in flight while held
+struct InFlight(Arc<Lifecycle>);
+
+impl Drop for InFlight {
+    fn drop(&mut self) {
+        self.0.in_flight.send_modify(|n| *n -= 1);
+    }
+}
+
+/// An API key given directly or fetched from a secret provider, masked in debug output
+#[derive(Clone)]
+enum ApiKey {
+    Static(String),
+    Provided(Arc<CachedSecret>),
+}
+
+impl ApiKey {
+    async fn expose(&self) -> Result<Cow<'_, str>> {
+        match self {
+            ApiKey::Static(key) => Ok(Cow::Borrowed(key)),
+            ApiKey::Provided(secret) => Ok(Cow::Owned(secret.get().await?)),
+        }
+    }
+
+    fn masked(&self) -> String {
+        let key = match self {
+            ApiKey::Static(key) => Cow::Borrowed(key.as_str()),
+            ApiKey::Provided(secret) => secret.cached().map_or(Cow::Borrowed(""), Cow::Owned),
+        };
+        let chars: Vec<char> = key.chars().collect();
+        // Short keys would be mostly revealed by their ends
+        if chars.len() < 12 {
+            return "****".to_string();
+        }
+        let head: String = chars[..4].iter().collect();
+        let tail: String = chars[chars.len() - 4..].iter().collect();
+        format!("{}...{}", head, tail)
+    }
+
+    /// Fetch a provided key again on the next request, after it was rejected
+    fn invalidate(&self) {
+        if let ApiKey::Provided(secret) = self {
+            secret.invalidate();
+        }
+    }
+}
+
+impl std::fmt::Debug for ApiKey {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "\"{}\"", self.masked())
+    }
+}
+
+/// What a stream does with data it can't decode into a chunk
+///
+/// Either way the failure is yielded as an error item, so no data is lost silently.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum StreamDecodePolicy {
+    /// Yield the error and keep streaming
+    #[default]
+    Continue,
+    /// Yield the error and end the stream
+    Fail,
+}
+
+/// What a stream does when its consumer falls behind
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum StreamOverflowPolicy {
+    /// Stop reading from the connection until the consumer catches up
+    #[default]
+    Wait,
+    /// Drop the chunk that doesn't fit and end the stream with `StreamLagged`
+    Fail,
+}
+
+/// Limits retries across a client and its clones to a share of their requests
+///
+/// Each request earns `ratio` of a retry and each retry spends one, up to
+/// `max_tokens` saved. When the API is overloaded and most requests fail, this
+/// stops retries from multiplying the load.
+#[derive(Debug, Clone)]
+pub struct RetryBudget {
+    /// Retries that can be saved up and spent in a burst
+    pub max_tokens: f64,
+    /// Retries earned per request; 0.2 allows one retry for every five requests
+    pub ratio: f64,
+}
+
+impl Default for RetryBudget {
+    fn default() -> Self {
+        Self {
+            max_tokens: 10.0,
+            ratio: 0.2,
+        }
+    }
+}
+
+/// The tokens left in a client's retry budget
+#[derive(Debug)]
+struct RetryTokens {
+    budget: RetryBudget,
+    tokens: Mutex<f64>,
+}
+
+impl RetryTokens {
+    fn new(budget: RetryBudget) -> Self {
         Self {
-            http_client: HttpClient::new(),
-            api_key: self.api_key.clone(),
-            base_url: self.base_url.clone(),
-            timeout: self.timeout,
-            user_agent: self.user_agent.clone(),
-            request_id: self.request_id.clone(),
-            max_retries: self.max_retries,
-            retry_delay: self.retry_delay,
+            tokens: Mutex::new(budget.max_tokens),
+            budget,
+        }
+    }
+
+    fn deposit(&self) {
+        let mut tokens = self.tokens.lock().unwrap_or_else(PoisonError::into_inner);
+        *tokens = (*tokens + self.budget.ratio).min(self.budget.max_tokens);
+    }
+
+    /// Spend a token if one is left
+    fn withdraw(&self) -> bool {
+        let mut tokens = self.tokens.lock().unwrap_or_else(PoisonError::into_inner);
+        if *tokens < 1.0 {
+            return false;
         }
+        *tokens -= 1.0;
+        true
     }
 }
 
+/// Result of `Client::chat_with_auto_tools`
+#[derive(Debug)]
+pub struct AutoToolsCompletion {
+    /// The last completion, holding the final answer when the loop completed
+    pub completion: ChatCompletion,
+    /// Tool calls that were executed, with their results, in order
+    pub tool_calls: Vec<(crate::tools::ToolCall, ToolResult)>,
+    /// Why the loop ended
+    pub stop_reason: StopReason,
+    /// The whole conversation, including tool calls and results
+    pub messages: Vec<Message>,
+}
+
 /// Options for chat completion requests
-#[derive(Debug, Clone, Default)]
+///
+/// Deserializes from the request fields of the same names, so options can be
+/// accepted straight from an incoming JSON body.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
 pub struct ChatOptions {
     /// Maximum tokens to generate
     pub max_tokens: Option<u32>,
@@ -266,22 +1423,131 @@ pub struct ChatOptions {
     pub tool_choice: Option<serde_json::Value>,
     /// Response format specification
     pub response_format: Option<serde_json::Value>,
+    /// Check replies to a `json_schema` response format against the schema,
+    /// sending the errors back to the model up to this many times before
+    /// failing with `SchemaValidation`; `None` skips the check
+    pub schema_repair_attempts: Option<u32>,
     /// Stop sequences
     pub stop: Option<Vec<String>>,
     /// Enable streaming responses
     pub stream: Option<bool>,
 }
 
+impl ChatOptions {
+    /// Check the options against the ranges the API accepts, reporting every violation
+    pub fn validate(&self) -> Result<()> {
+        let violations = self.violations();
+        if violations.is_empty() {
+            Ok(())
+        } else {
+            Err(GrokError::Validation(violations))
+        }
+    }
+
+    fn violations(&self) -> Vec<ValidationError> {
+        let mut violations = Vec::new();
+        if let Some(temperature) = self.temperature {
+            if !(0.0..=2.0).contains(&temperature) {
+                violations.push(ValidationError::new(
+                    "temperature",
+                    "must be between 0 and 2",
+                    temperature,
+                ));
+            }
+        }
+        if let Some(top_p) = self.top_p {
+            if !(0.0..=1.0).contains(&top_p) {
+                violations.push(ValidationError::new(
+                    "top_p",
+                    "must be between 0 and 1",
+                    top_p,
+                ));
+            }
+        }
+        if self.max_tokens == Some(0) {
+            violations.push(ValidationError::new("max_tokens", "must be at least 1", 0));
+        }
+        violations
+    }
+}
+
+/// The compiled schema of a `json_schema` response format; `None` for other formats
+fn response_schema(format: &serde_json::Value) -> Result<Option<jsonschema::JSONSchema>> {
+    if format.get("type").and_then(|t| t.as_str()) != Some("json_schema") {
+        return Ok(None);
+    }
+    let schema = format
+        .get("json_schema")
+        .and_then(|spec| spec.get("schema"))
+        .ok_or_else(|| {
+            GrokError::InvalidConfig("json_schema response format has no schema".to_string())
+        })?;
+    jsonschema::JSONSchema::compile(schema)
+        .map(Some)
+        .map_err(|e| GrokError::InvalidConfig(format!("Invalid response schema: {}", e)))
+}
+
+/// Describe how a reply fails to match a schema; empty if it matches
+fn schema_violations(schema: &jsonschema::JSONSchema, reply: &str) -> Vec<String> {
+    let value: serde_json::Value = match serde_json::from_str(reply) {
+        Ok(value) => value,
+        Err(e) => return vec![format!("not valid JSON: {}", e)],
+    };
+    let violations = match schema.validate(&value) {
+        Ok(()) => Vec::new(),
+        Err(errors) => errors
+            .map(|e| match e.instance_path.to_string() {
+                path if path.is_empty() => e.to_string(),
+                path => format!("{}: {}", path, e),
+            })
+            .collect(),
+    };
+    violations
+}
+
+/// Check the parts of a request that don't depend on options
+fn message_violations(messages: &[Arc<Message>]) -> Vec<ValidationError> {
+    if messages.is_empty() {
+        vec![ValidationError::new("messages", "must not be empty", "[]")]
+    } else {
+        Vec::new()
+    }
+}
+
 /// Builder for creating a Client with custom configuration
 #[derive(Debug, Clone)]
 pub struct ClientBuilder {
-    api_key: Option<String>,
+    api_key: Option<ApiKey>,
+    api_key_provider: Option<Arc<dyn SecretProvider>>,
+    api_key_refresh: Option<Duration>,
     base_url: Option<String>,
     timeout: Option<Duration>,
+    connect_timeout: Option<Duration>,
+    stream_idle_timeout: Option<Duration>,
+    stream_decode_policy: StreamDecodePolicy,
+    max_stream_buffer: Option<usize>,
+    stream_capacity: Option<usize>,
+    stream_overflow_policy: StreamOverflowPolicy,
     user_agent: Option<String>,
     request_id: Option<String>,
     max_retries: Option<u32>,
     retry_delay: Option<Duration>,
+    request_deadline: Option<Duration>,
+    retry_budget: Option<RetryBudget>,
+    vcr: Option<Arc<Vcr>>,
+    auditor: Option<Arc<Auditor>>,
+    budget: Option<Arc<Budget>>,
+    rate_limiter: Option<Arc<RateLimiter>>,
+    fallback_models: Vec<Model>,
+    guardrails: Vec<Arc<dyn Guardrail>>,
+    interceptors: Vec<Arc<dyn RequestInterceptor>>,
+    check_context_window: bool,
+}
+
+impl Default for ClientBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl ClientBuilder {
@@ -289,18 +1555,50 @@ impl ClientBuilder {
     pub fn new() -> Self {
         Self {
             api_key: None,
+            api_key_provider: None,
+            api_key_refresh: None,
             base_url: None,
             timeout: None,
+            connect_timeout: None,
+            stream_idle_timeout: None,
+            stream_decode_policy: StreamDecodePolicy::default(),
+            max_stream_buffer: None,
+            stream_capacity: None,
+            stream_overflow_policy: StreamOverflowPolicy::default(),
             user_agent: None,
             request_id: None,
             max_retries: None,
             retry_delay: None,
+            request_deadline: None,
+            retry_budget: None,
+            vcr: None,
+            auditor: None,
+            budget: None,
+            rate_limiter: None,
+            fallback_models: Vec::new(),
+            guardrails: Vec::new(),
+            interceptors: Vec::new(),
+            check_context_window: true,
         }
     }
 
     /// Set the API key
     pub fn api_key(mut self, api_key: impl Into<String>) -> Self {
-        self.api_key = Some(api_key.into());
+        self.api_key = Some(ApiKey::Static(api_key.into()));
+        self
+    }
+
+    /// Fetch the API key from a secret provider on first use, instead of `api_key`
+    ///
+    /// A key the API rejects is fetched again on the next request.
+    pub fn api_key_provider(mut self, provider: impl SecretProvider + 'static) -> Self {
+        self.api_key_provider = Some(Arc::new(provider));
+        self
+    }
+
+    /// Fetch the provided API key again once it's this old, to pick up rotations
+    pub fn api_key_refresh(mut self, interval: Duration) -> Self {
+        self.api_key_refresh = Some(interval);
         self
     }
 
@@ -316,6 +1614,42 @@ impl ClientBuilder {
         self
     }
 
+    /// Set how long connecting to the API may take
+    pub fn connect_timeout(mut self, connect_timeout: Duration) -> Self {
+        self.connect_timeout = Some(connect_timeout);
+        self
+    }
+
+    /// Set how long a streamed response may go without sending data
+    pub fn stream_idle_timeout(mut self, stream_idle_timeout: Duration) -> Self {
+        self.stream_idle_timeout = Some(stream_idle_timeout);
+        self
+    }
+
+    /// Set what happens when a streamed chunk can't be decoded
+    pub fn stream_decode_policy(mut self, policy: StreamDecodePolicy) -> Self {
+        self.stream_decode_policy = policy;
+        self
+    }
+
+    /// Set how many bytes of an unfinished streamed event may be buffered (1 MiB by default)
+    pub fn max_stream_buffer(mut self, bytes: usize) -> Self {
+        self.max_stream_buffer = Some(bytes);
+        self
+    }
+
+    /// Set how many decoded chunks may wait for a slow stream consumer (64 by default)
+    pub fn stream_capacity(mut self, chunks: usize) -> Self {
+        self.stream_capacity = Some(chunks);
+        self
+    }
+
+    /// Set what happens when a stream consumer falls `stream_capacity` chunks behind
+    pub fn stream_overflow_policy(mut self, policy: StreamOverflowPolicy) -> Self {
+        self.stream_overflow_policy = policy;
+        self
+    }
+
     /// Set the user agent
     pub fn user_agent(mut self, user_agent: impl Into<String>) -> Self {
         self.user_agent = Some(user_agent.into());
@@ -340,10 +1674,95 @@ impl ClientBuilder {
         self
     }
 
+    /// Set how long a request may take in total, across all of its retries
+    pub fn request_deadline(mut self, deadline: Duration) -> Self {
+        self.request_deadline = Some(deadline);
+        self
+    }
+
+    /// Set the retry budget shared by the client and its clones, or `None` for no budget
+    ///
+    /// There is no budget by default, so every request may retry up to `max_retries` times.
+    pub fn retry_budget(mut self, budget: Option<RetryBudget>) -> Self {
+        self.retry_budget = budget;
+        self
+    }
+
+    /// Record API interactions to a fixture file, or answer requests from one
+    pub fn vcr(mut self, vcr: Vcr) -> Self {
+        self.vcr = Some(Arc::new(vcr));
+        self
+    }
+
+    /// Record every raw request and response body, streamed frames included
+    pub fn audit(mut self, auditor: Auditor) -> Self {
+        self.auditor = Some(Arc::new(auditor));
+        self
+    }
+
+    /// Hold back requests once a token or cost budget is spent
+    ///
+    /// The budget is shared by the client's clones, and with anything else
+    /// given the same `Arc`.
+    pub fn budget(mut self, budget: Arc<Budget>) -> Self {
+        self.budget = Some(budget);
+        self
+    }
+
+    /// Hold back requests to stay under request and token rates
+    ///
+    /// The limiter is shared by the client's clones, and with anything else
+    /// given the same `Arc`. It also follows the quota in the API's
+    /// `x-ratelimit-*` headers.
+    pub fn rate_limiter(mut self, rate_limiter: Arc<RateLimiter>) -> Self {
+        self.rate_limiter = Some(rate_limiter);
+        self
+    }
+
+    /// Models to try, in order, when a chat request fails in a way another
+    /// model may not, such as a timeout or a model at capacity
+    ///
+    /// The failure must outlast the request's retries first. Fallbacks whose
+    /// context window the request doesn't fit are skipped, and the
+    /// completion's `model` names the model that answered. Streamed requests
+    /// are not rerouted.
+    pub fn fallback_models(mut self, models: impl IntoIterator<Item = Model>) -> Self {
+        self.fallback_models = models.into_iter().collect();
+        self
+    }
+
+    /// Check every request and response with a guardrail, after any added before it
+    pub fn guardrail(mut self, guardrail: impl Guardrail + 'static) -> Self {
+        self.guardrails.push(Arc::new(guardrail));
+        self
+    }
+
+    /// Pass every HTTP request and response through an interceptor, after any added before it
+    pub fn with_middleware(mut self, interceptor: impl RequestInterceptor + 'static) -> Self {
+        self.interceptors.push(Arc::new(interceptor));
+        self
+    }
+
+    /// Whether to estimate prompt tokens and refuse requests that won't fit
+    /// in the model's context window before sending them (on by default)
+    pub fn check_context_window(mut self, check: bool) -> Self {
+        self.check_context_window = check;
+        self
+    }
+
     /// Build the client
     pub fn build(self) -> Result<Client> {
-        let api_key = self.api_key.ok_or_else(|| GrokError::InvalidConfig("API key is required".to_string()))?;
-        let base_url = self.base_url.unwrap_or_else(|| "https://api.x.ai/v1".to_string());
+        let api_key = match self.api_key_provider {
+            Some(provider) => {
+                ApiKey::Provided(Arc::new(CachedSecret::new(provider, self.api_key_refresh)))
+            }
+            None => self
+                .api_key
+                .ok_or_else(|| GrokError::InvalidConfig("API key is required".to_string()))?,
+        };
+        let base_url = self
+            .base_url
+            .unwrap_or_else(|| "https://api.x.ai/v1".to_string());
 
         let mut http_client_builder = HttpClient::builder();
 
@@ -351,6 +1770,10 @@ impl ClientBuilder {
             http_client_builder = http_client_builder.timeout(timeout);
         }
 
+        if let Some(connect_timeout) = self.connect_timeout {
+            http_client_builder = http_client_builder.connect_timeout(connect_timeout);
+        }
+
         if let Some(user_agent) = self.user_agent {
             http_client_builder = http_client_builder.user_agent(user_agent);
         }
@@ -361,11 +1784,31 @@ impl ClientBuilder {
             http_client,
             api_key,
             base_url,
-            timeout: self.timeout,
-            user_agent: self.user_agent,
+            stream_idle_timeout: self.stream_idle_timeout,
+            stream_decode_policy: self.stream_decode_policy,
+            max_stream_buffer: self.max_stream_buffer.unwrap_or(DEFAULT_MAX_STREAM_BUFFER),
+            stream_capacity: self
+                .stream_capacity
+                .unwrap_or(DEFAULT_STREAM_CAPACITY)
+                .max(1),
+            stream_overflow_policy: self.stream_overflow_policy,
             request_id: self.request_id,
             max_retries: self.max_retries.unwrap_or(3),
             retry_delay: self.retry_delay.unwrap_or(Duration::from_millis(1000)),
+            request_deadline: self.request_deadline,
+            retry_budget: self
+                .retry_budget
+                .map(|budget| Arc::new(RetryTokens::new(budget))),
+            vcr: self.vcr,
+            auditor: self.auditor,
+            budget: self.budget,
+            rate_limiter: self.rate_limiter,
+            fallback_models: self.fallback_models,
+            guardrails: self.guardrails,
+            interceptors: self.interceptors,
+            check_context_window: self.check_context_window,
+            rate_limit: Arc::default(),
+            lifecycle: Arc::default(),
         })
     }
 }
@@ -1,10 +1,11 @@
 //! Collections for organizing conversations
 
-use crate::error::{GrokError, Result};
+use crate::error::{Entity, GrokError, Result};
 use crate::session::{Session, SessionManager};
+use crate::storage::{MemoryStorage, Storage};
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
-use std::sync::Arc;
+use std::sync::{Arc, Mutex};
 use tokio::sync::RwLock;
 
 /// A collection of related sessions
@@ -13,9 +14,13 @@ pub struct Collection {
     /// Unique collection ID
     pub id: String,
     /// Collection metadata
-    pub metadata: CollectionMetadata,
-    /// Sessions in this collection
+    metadata: Mutex<CollectionMetadata>,
+    /// IDs of the sessions in this collection, in the order they were added
+    session_ids: Mutex<Vec<String>>,
+    /// Sessions in this collection that are in memory
     sessions: RwLock<HashMap<String, Arc<Session>>>,
+    /// Storage the collection is saved to as its sessions change
+    storage: Option<Arc<dyn Storage>>,
 }
 
 /// Collection metadata
@@ -37,6 +42,9 @@ pub struct CollectionMetadata {
     pub total_messages: usize,
     /// Total tokens used across all sessions
     pub total_tokens: u64,
+    /// Tenant the collection belongs to, if it's scoped to one
+    #[serde(default)]
+    pub tenant: Option<String>,
 }
 
 impl Collection {
@@ -47,7 +55,7 @@ impl Collection {
 
         Self {
             id,
-            metadata: CollectionMetadata {
+            metadata: Mutex::new(CollectionMetadata {
                 name: name.into(),
                 description,
                 created_at: now,
@@ -56,32 +64,125 @@ impl Collection {
                 session_count: 0,
                 total_messages: 0,
                 total_tokens: 0,
-            },
+                tenant: None,
+            }),
+            session_ids: Mutex::new(Vec::new()),
             sessions: RwLock::new(HashMap::new()),
+            storage: None,
         }
     }
 
+    /// Rebuild a stored collection from its metadata and the IDs of its sessions
+    ///
+    /// The sessions themselves aren't in memory; load them through a
+    /// `SessionManager`.
+    pub fn restore(id: String, metadata: CollectionMetadata, session_ids: Vec<String>) -> Self {
+        Self {
+            id,
+            metadata: Mutex::new(metadata),
+            session_ids: Mutex::new(session_ids),
+            sessions: RwLock::new(HashMap::new()),
+            storage: None,
+        }
+    }
+
+    /// Get a copy of the collection metadata
+    pub fn metadata(&self) -> CollectionMetadata {
+        self.metadata.lock().unwrap().clone()
+    }
+
+    /// The tenant the collection belongs to, if it's scoped to one
+    pub fn tenant(&self) -> Option<String> {
+        self.metadata.lock().unwrap().tenant.clone()
+    }
+
+    /// Scope the collection to a tenant
+    pub fn set_tenant(&mut self, tenant: impl Into<String>) {
+        self.metadata.get_mut().unwrap().tenant = Some(tenant.into());
+    }
+
+    /// IDs of the sessions in the collection, including ones not in memory
+    pub fn session_ids(&self) -> Vec<String> {
+        self.session_ids.lock().unwrap().clone()
+    }
+
+    /// Save the collection to storage whenever sessions are added or removed
+    pub fn enable_auto_save(&mut self, storage: Arc<dyn Storage>) {
+        self.storage = Some(storage);
+    }
+
     /// Add a session to the collection
+    ///
+    /// The session must belong to the collection's tenant, or neither may
+    /// belong to one.
     pub async fn add_session(&self, session: Arc<Session>) -> Result<()> {
+        if session.tenant() != self.tenant().as_deref() {
+            return Err(GrokError::collection(
+                "add session",
+                format!("session '{}' belongs to a different tenant", session.id),
+            )
+            .with_id(self.id.as_str()));
+        }
+        // Stored membership refers to the stored session, so that goes first
+        if let Some(storage) = &self.storage {
+            storage.save_session(&session).await?;
+        }
         let session_id = session.id.clone();
-        let mut sessions = self.sessions.write().await;
-        sessions.insert(session_id, session);
-        drop(sessions);
+        let added = {
+            let mut session_ids = self.session_ids.lock().unwrap();
+            let added = !session_ids.contains(&session_id);
+            if added {
+                session_ids.push(session_id.clone());
+            }
+            added
+        };
+        let counts = session.metadata();
+        self.sessions.write().await.insert(session_id, session);
 
-        self.update_metadata().await;
-        Ok(())
+        self.update_metadata(|metadata| {
+            if added {
+                metadata.total_messages += counts.message_count;
+                metadata.total_tokens += counts.total_tokens;
+            }
+        })
+        .await;
+        self.save().await
     }
 
     /// Remove a session from the collection
     pub async fn remove_session(&self, session_id: &str) -> Result<()> {
-        let mut sessions = self.sessions.write().await;
-        sessions.remove(session_id).ok_or_else(|| {
-            GrokError::Collection(format!("Session '{}' not in collection", session_id))
-        })?;
-        drop(sessions);
+        {
+            let mut session_ids = self.session_ids.lock().unwrap();
+            let index = session_ids
+                .iter()
+                .position(|id| id == session_id)
+                .ok_or_else(|| {
+                    GrokError::NotFound {
+                        entity: Entity::Session,
+                        id: session_id.to_string(),
+                    }
+                    .context(format!("Removing from collection '{}'", self.id))
+                })?;
+            session_ids.remove(index);
+        }
+        let removed = self.sessions.write().await.remove(session_id);
 
-        self.update_metadata().await;
-        Ok(())
+        self.update_metadata(|metadata| {
+            if let Some(counts) = removed.map(|session| session.metadata()) {
+                metadata.total_messages =
+                    metadata.total_messages.saturating_sub(counts.message_count);
+                metadata.total_tokens = metadata.total_tokens.saturating_sub(counts.total_tokens);
+            }
+        })
+        .await;
+        self.save().await
+    }
+
+    async fn save(&self) -> Result<()> {
+        match &self.storage {
+            Some(storage) => storage.save_collection(self).await,
+            None => Ok(()),
+        }
     }
 
     /// Get a session by ID
@@ -90,23 +191,39 @@ impl Collection {
         sessions.get(session_id).cloned()
     }
 
-    /// List all sessions in the collection
+    /// List the sessions in the collection that are in memory
     pub async fn list_sessions(&self) -> Vec<Arc<Session>> {
         let sessions = self.sessions.read().await;
         sessions.values().cloned().collect()
     }
 
     /// Update collection metadata
-    async fn update_metadata(&self) {
+    ///
+    /// Totals are recounted when every session is in memory; otherwise
+    /// `adjust` updates the stored totals for the session added or removed.
+    async fn update_metadata(&self, adjust: impl FnOnce(&mut CollectionMetadata)) {
         let sessions = self.sessions.read().await;
-        let session_count = sessions.len();
-        let total_messages = sessions.values().map(|s| s.metadata.message_count).sum();
-        let total_tokens = sessions.values().map(|s| s.metadata.total_tokens).sum();
-
-        let mut metadata = &mut self.metadata;
-        metadata.session_count = session_count;
-        metadata.total_messages = total_messages;
-        metadata.total_tokens = total_tokens;
+        let session_ids = self.session_ids();
+        let all_loaded = session_ids.iter().all(|id| sessions.contains_key(id));
+        let totals = all_loaded.then(|| {
+            sessions
+                .values()
+                .map(|s| s.metadata())
+                .fold((0, 0), |(messages, tokens), m| {
+                    (messages + m.message_count, tokens + m.total_tokens)
+                })
+        });
+        drop(sessions);
+
+        let mut metadata = self.metadata.lock().unwrap();
+        match totals {
+            Some((total_messages, total_tokens)) => {
+                metadata.total_messages = total_messages;
+                metadata.total_tokens = total_tokens;
+            }
+            None => adjust(&mut metadata),
+        }
+        metadata.session_count = session_ids.len();
         metadata.updated_at = chrono::Utc::now();
     }
 
@@ -117,7 +234,7 @@ impl Collection {
             .values()
             .filter(|session| {
                 // Search in title
-                if let Some(title) = &session.metadata.title {
+                if let Some(title) = &session.metadata().title {
                     if title.to_lowercase().contains(&query.to_lowercase()) {
                         return true;
                     }
@@ -133,15 +250,18 @@ impl Collection {
 }
 
 /// Collection manager for handling multiple collections
+///
+/// Collections are persisted to the session manager's storage, if it has
+/// one, and loaded from it on demand.
 #[derive(Debug)]
-pub struct CollectionManager {
-    session_manager: Arc<SessionManager>,
+pub struct CollectionManager<S: Storage = MemoryStorage> {
+    session_manager: Arc<SessionManager<S>>,
     collections: RwLock<HashMap<String, Arc<Collection>>>,
 }
 
-impl CollectionManager {
+impl<S: Storage + 'static> CollectionManager<S> {
     /// Create a new collection manager
-    pub fn new(session_manager: Arc<SessionManager>) -> Self {
+    pub fn new(session_manager: Arc<SessionManager<S>>) -> Self {
         Self {
             session_manager,
             collections: RwLock::new(HashMap::new()),
@@ -154,35 +274,159 @@ impl CollectionManager {
         name: impl Into<String>,
         description: Option<String>,
         tags: Vec<String>,
-    ) -> Arc<Collection> {
-        let collection = Arc::new(Collection::new(name, description, tags));
-        let collection_id = collection.id.clone();
+    ) -> Result<Arc<Collection>> {
+        self.insert_collection(Collection::new(name, description, tags))
+            .await
+    }
+
+    /// Create a new collection belonging to `tenant`
+    pub async fn create_collection_for(
+        &self,
+        tenant: &str,
+        name: impl Into<String>,
+        description: Option<String>,
+        tags: Vec<String>,
+    ) -> Result<Arc<Collection>> {
+        let mut collection = Collection::new(name, description, tags);
+        collection.set_tenant(tenant);
+        self.insert_collection(collection).await
+    }
+
+    async fn insert_collection(&self, mut collection: Collection) -> Result<Arc<Collection>> {
+        if let Some(storage) = self.session_manager.storage() {
+            collection.enable_auto_save(storage.clone());
+            storage.save_collection(&collection).await?;
+        }
+        let collection = Arc::new(collection);
 
         let mut collections = self.collections.write().await;
-        collections.insert(collection_id, collection.clone());
+        collections.insert(collection.id.clone(), collection.clone());
 
-        collection
+        Ok(collection)
     }
 
-    /// Get a collection by ID
+    /// Get a collection in memory by ID
+    ///
+    /// Use `load_collection` to also look in storage.
     pub async fn get_collection(&self, collection_id: &str) -> Option<Arc<Collection>> {
         let collections = self.collections.read().await;
         collections.get(collection_id).cloned()
     }
 
+    /// Get a collection by ID, loading it from storage if it isn't in memory
+    pub async fn load_collection(&self, collection_id: &str) -> Result<Option<Arc<Collection>>> {
+        self.load_collection_scoped(collection_id, None).await
+    }
+
     /// List all collections
     pub async fn list_collections(&self) -> Vec<Arc<Collection>> {
         let collections = self.collections.read().await;
         collections.values().cloned().collect()
     }
 
-    /// Delete a collection
-    pub async fn delete_collection(&self, collection_id: &str) -> Result<()> {
+    /// Get a collection in memory by ID, if it belongs to `tenant`
+    ///
+    /// Use `load_collection_for` to also look in storage.
+    pub async fn get_collection_for(
+        &self,
+        tenant: &str,
+        collection_id: &str,
+    ) -> Option<Arc<Collection>> {
+        self.get_collection(collection_id)
+            .await
+            .filter(|collection| collection.tenant().as_deref() == Some(tenant))
+    }
+
+    /// Get a collection by ID, if it belongs to `tenant`, loading it from
+    /// storage if it isn't in memory
+    ///
+    /// Another tenant's collection is treated as missing and isn't loaded.
+    pub async fn load_collection_for(
+        &self,
+        tenant: &str,
+        collection_id: &str,
+    ) -> Result<Option<Arc<Collection>>> {
+        self.load_collection_scoped(collection_id, Some(tenant))
+            .await
+    }
+
+    async fn load_collection_scoped(
+        &self,
+        collection_id: &str,
+        tenant: Option<&str>,
+    ) -> Result<Option<Arc<Collection>>> {
+        if let Some(collection) = self.get_collection(collection_id).await {
+            return Ok(Some(collection).filter(|collection| {
+                tenant.is_none() || collection.tenant().as_deref() == tenant
+            }));
+        }
+        let Some(storage) = self.session_manager.storage() else {
+            return Ok(None);
+        };
+        let loaded = match tenant {
+            Some(tenant) => storage.load_collection_for(tenant, collection_id).await?,
+            None => storage.load_collection(collection_id).await?,
+        };
+        let Some(mut collection) = loaded else {
+            return Ok(None);
+        };
+        collection.enable_auto_save(storage.clone());
+
         let mut collections = self.collections.write().await;
-        collections.remove(collection_id).ok_or_else(|| {
-            GrokError::Collection(format!("Collection '{}' not found", collection_id))
-        })?;
-        Ok(())
+        let collection = collections
+            .entry(collection_id.to_string())
+            .or_insert_with(|| Arc::new(collection))
+            .clone();
+        Ok(Some(collection))
+    }
+
+    /// List the collections belonging to `tenant`, loading those only in storage
+    pub async fn list_collections_for(&self, tenant: &str) -> Result<Vec<Arc<Collection>>> {
+        let mut listed: Vec<_> = self
+            .list_collections()
+            .await
+            .into_iter()
+            .filter(|collection| collection.tenant().as_deref() == Some(tenant))
+            .collect();
+        if let Some(storage) = self.session_manager.storage() {
+            for collection_id in storage.list_collections_for(tenant).await? {
+                if listed
+                    .iter()
+                    .any(|collection| collection.id == collection_id)
+                {
+                    continue;
+                }
+                if let Some(collection) = self.load_collection_for(tenant, &collection_id).await? {
+                    listed.push(collection);
+                }
+            }
+        }
+        Ok(listed)
+    }
+
+    /// Delete a collection, along with its stored copy
+    pub async fn delete_collection(&self, collection_id: &str) -> Result<()> {
+        let removed = self.collections.write().await.remove(collection_id);
+        match self.session_manager.storage() {
+            Some(storage) => storage.delete_collection(collection_id).await,
+            None => removed.map(|_| ()).ok_or_else(|| GrokError::NotFound {
+                entity: Entity::Collection,
+                id: collection_id.to_string(),
+            }),
+        }
+    }
+
+    /// Delete a collection belonging to `tenant`
+    ///
+    /// Another tenant's collection is reported as not found.
+    pub async fn delete_collection_for(&self, tenant: &str, collection_id: &str) -> Result<()> {
+        match self.load_collection_for(tenant, collection_id).await? {
+            Some(_) => self.delete_collection(collection_id).await,
+            None => Err(GrokError::NotFound {
+                entity: Entity::Collection,
+                id: collection_id.to_string(),
+            }),
+        }
     }
 
     /// Search collections by name, description, or tags
@@ -193,26 +437,22 @@ impl CollectionManager {
         collections
             .values()
             .filter(|collection| {
+                let metadata = collection.metadata();
+
                 // Search in name
-                if collection
-                    .metadata
-                    .name
-                    .to_lowercase()
-                    .contains(&query_lower)
-                {
+                if metadata.name.to_lowercase().contains(&query_lower) {
                     return true;
                 }
 
                 // Search in description
-                if let Some(desc) = &collection.metadata.description {
+                if let Some(desc) = &metadata.description {
                     if desc.to_lowercase().contains(&query_lower) {
                         return true;
                     }
                 }
 
                 // Search in tags
-                collection
-                    .metadata
+                metadata
                     .tags
                     .iter()
                     .any(|tag| tag.to_lowercase().contains(&query_lower))
@@ -221,26 +461,42 @@ impl CollectionManager {
             .collect()
     }
 
+    /// Search the collections belonging to `tenant` by name, description, or tags
+    pub async fn search_collections_for(&self, tenant: &str, query: &str) -> Vec<Arc<Collection>> {
+        self.search_collections(query)
+            .await
+            .into_iter()
+            .filter(|collection| collection.tenant().as_deref() == Some(tenant))
+            .collect()
+    }
+
     /// Get collections by tag
     pub async fn collections_by_tag(&self, tag: &str) -> Vec<Arc<Collection>> {
         let collections = self.collections.read().await;
         collections
             .values()
-            .filter(|collection| collection.metadata.tags.contains(&tag.to_string()))
+            .filter(|collection| collection.metadata().tags.contains(&tag.to_string()))
             .cloned()
             .collect()
     }
 
     /// Get collection statistics
     pub async fn stats(&self) -> CollectionStats {
-        let collections = self.collections.read().await;
+        Self::stats_of(&self.list_collections().await)
+    }
+
+    /// Get statistics for the collections belonging to `tenant`, including
+    /// those only in storage
+    pub async fn stats_for(&self, tenant: &str) -> Result<CollectionStats> {
+        Ok(Self::stats_of(&self.list_collections_for(tenant).await?))
+    }
+
+    fn stats_of(collections: &[Arc<Collection>]) -> CollectionStats {
         let total_collections = collections.len();
-        let total_sessions = collections.values().map(|c| c.metadata.session_count).sum();
-        let total_messages = collections
-            .values()
-            .map(|c| c.metadata.total_messages)
-            .sum();
-        let total_tokens = collections.values().map(|c| c.metadata.total_tokens).sum();
+        let metadata: Vec<_> = collections.iter().map(|c| c.metadata()).collect();
+        let total_sessions = metadata.iter().map(|m| m.session_count).sum();
+        let total_messages = metadata.iter().map(|m| m.total_messages).sum();
+        let total_tokens = metadata.iter().map(|m| m.total_tokens).sum();
 
         CollectionStats {
             total_collections,
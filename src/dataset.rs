@@ -0,0 +1,277 @@
+//! Fine-tuning datasets built from stored conversations
+//!
+//! `DatasetBuilder` selects sessions from storage by collection, tag or
+//! rating, cleans each conversation up with a chain of transforms, drops
+//! duplicates and splits the result into training and validation sets that
+//! are written as chat-format JSONL.
+
+use crate::chat::{estimate_prompt_tokens, Message, Role};
+use crate::error::{GrokError, Result};
+use crate::persistence::{ExportOptions, SessionQuery, SqliteStorage};
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashSet;
+use std::fs::File;
+use std::hash::{Hash, Hasher};
+use std::io::{BufWriter, Write};
+use std::path::Path;
+use std::sync::Arc;
+
+type Transform = Arc<dyn Fn(Vec<Message>) -> Vec<Message> + Send + Sync>;
+
+/// Builder for a fine-tuning dataset
+///
+/// Transforms run in the order they were added. Every example is then cut
+/// back to end on an assistant reply, and conversations without one are
+/// skipped.
+#[derive(Clone)]
+pub struct DatasetBuilder {
+    query: SessionQuery,
+    transforms: Vec<Transform>,
+    deduplicate: bool,
+    validation_split: f64,
+    seed: u64,
+}
+
+impl std::fmt::Debug for DatasetBuilder {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("DatasetBuilder")
+            .field("query", &self.query)
+            .field("transforms", &self.transforms.len())
+            .field("deduplicate", &self.deduplicate)
+            .field("validation_split", &self.validation_split)
+            .field("seed", &self.seed)
+            .finish()
+    }
+}
+
+impl Default for DatasetBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl DatasetBuilder {
+    /// Select every stored session, deduplicated, with no validation split
+    pub fn new() -> Self {
+        Self {
+            query: SessionQuery::default(),
+            transforms: Vec::new(),
+            deduplicate: true,
+            validation_split: 0.0,
+            seed: 0,
+        }
+    }
+
+    /// Select sessions with a query, replacing any filters set so far
+    pub fn query(mut self, query: SessionQuery) -> Self {
+        self.query = query;
+        self
+    }
+
+    /// Only sessions in this collection
+    pub fn collection(mut self, collection_id: impl Into<String>) -> Self {
+        self.query.collection_id = Some(collection_id.into());
+        self
+    }
+
+    /// Only sessions in a collection with this tag
+    pub fn tag(mut self, tag: impl Into<String>) -> Self {
+        self.query.tag = Some(tag.into());
+        self
+    }
+
+    /// Only sessions rated at least this high with `SqliteStorage::rate_session`
+    pub fn min_rating(mut self, rating: i64) -> Self {
+        self.query.min_rating = Some(rating);
+        self
+    }
+
+    /// Drop tool results and assistant turns that only requested tools
+    pub fn strip_tool_calls(self) -> Self {
+        let options = ExportOptions {
+            drop_tool_messages: true,
+            ..Default::default()
+        };
+        self.transform(move |messages| options.apply(messages))
+    }
+
+    /// Drop system prompts
+    pub fn strip_system_prompts(self) -> Self {
+        self.transform(|messages| {
+            messages
+                .into_iter()
+                .filter(|msg| msg.role != Role::System)
+                .collect()
+        })
+    }
+
+    /// Rewrite the content of every message, e.g. with `Redactor::redact`
+    pub fn anonymize<F>(self, anonymize: F) -> Self
+    where
+        F: Fn(&str) -> String + Send + Sync + 'static,
+    {
+        self.transform(move |messages| {
+            messages
+                .into_iter()
+                .map(|mut msg| {
+                    let anonymized = anonymize(&msg.content.text());
+                    msg.content.replace_text(anonymized);
+                    msg
+                })
+                .collect()
+        })
+    }
+
+    /// Keep at most the first `max_messages` messages of each conversation
+    pub fn truncate_messages(self, max_messages: usize) -> Self {
+        self.transform(move |mut messages| {
+            messages.truncate(max_messages);
+            messages
+        })
+    }
+
+    /// Keep the leading messages of each conversation that fit in `max_tokens`
+    ///
+    /// Tokens are estimated the same way as the client's context window check.
+    pub fn truncate_tokens(self, max_tokens: u32) -> Self {
+        self.transform(move |messages| {
+            let mut kept = Vec::new();
+            for msg in messages {
+                kept.push(Arc::new(msg));
+                if estimate_prompt_tokens(&kept, None) > max_tokens {
+                    kept.pop();
+                    break;
+                }
+            }
+            kept.into_iter().map(Arc::unwrap_or_clone).collect()
+        })
+    }
+
+    /// Apply a custom transform to each conversation
+    pub fn transform<F>(mut self, transform: F) -> Self
+    where
+        F: Fn(Vec<Message>) -> Vec<Message> + Send + Sync + 'static,
+    {
+        self.transforms.push(Arc::new(transform));
+        self
+    }
+
+    /// Whether to drop conversations identical to one already selected (default true)
+    pub fn deduplicate(mut self, deduplicate: bool) -> Self {
+        self.deduplicate = deduplicate;
+        self
+    }
+
+    /// Fraction of conversations to hold out for validation, from 0.0 up to 1.0
+    ///
+    /// Each conversation is assigned by a hash of its content and the seed, so
+    /// the split is reproducible and stays stable as the dataset grows.
+    pub fn validation_split(mut self, fraction: f64) -> Self {
+        self.validation_split = fraction;
+        self
+    }
+
+    /// Seed for the train/validation split
+    pub fn seed(mut self, seed: u64) -> Self {
+        self.seed = seed;
+        self
+    }
+
+    /// Load, transform and split the selected conversations
+    pub async fn build(&self, storage: &SqliteStorage) -> Result<Dataset> {
+        if !(0.0..1.0).contains(&self.validation_split) {
+            return Err(GrokError::InvalidConfig(format!(
+                "Validation split must be at least 0.0 and below 1.0, got {}",
+                self.validation_split
+            )));
+        }
+
+        let summaries = storage.query_sessions(&self.query).await?;
+        let mut dataset = Dataset::default();
+        let mut seen = HashSet::new();
+
+        for summary in summaries {
+            dataset.stats.selected += 1;
+            let mut messages = storage.load_messages(&summary.id, ..).await?;
+            for transform in &self.transforms {
+                messages = transform(messages);
+            }
+
+            // An example has to end on the completion being trained
+            match messages.iter().rposition(|msg| msg.role == Role::Assistant) {
+                Some(last) => messages.truncate(last + 1),
+                None => {
+                    dataset.stats.skipped += 1;
+                    continue;
+                }
+            }
+
+            let json = serde_json::to_string(&messages)?;
+            if self.deduplicate && !seen.insert(json.clone()) {
+                dataset.stats.duplicates += 1;
+                continue;
+            }
+
+            let mut hasher = DefaultHasher::new();
+            self.seed.hash(&mut hasher);
+            json.hash(&mut hasher);
+            let position = (hasher.finish() % 1_000_000) as f64 / 1_000_000.0;
+            if position < self.validation_split {
+                dataset.validation.push(messages);
+            } else {
+                dataset.train.push(messages);
+            }
+        }
+
+        Ok(dataset)
+    }
+}
+
+/// Conversations split into training and validation examples
+#[derive(Debug, Clone, Default)]
+pub struct Dataset {
+    /// Training examples
+    pub train: Vec<Vec<Message>>,
+    /// Held-out validation examples
+    pub validation: Vec<Vec<Message>>,
+    /// Counts of what was selected and left out
+    pub stats: DatasetStats,
+}
+
+impl Dataset {
+    /// Write the training and validation sets as JSONL files
+    ///
+    /// The validation file is written even when empty.
+    pub fn write_jsonl<P: AsRef<Path>, Q: AsRef<Path>>(
+        &self,
+        train: P,
+        validation: Q,
+    ) -> Result<()> {
+        write_jsonl(&self.train, File::create(train).map_err(GrokError::Io)?)?;
+        write_jsonl(
+            &self.validation,
+            File::create(validation).map_err(GrokError::Io)?,
+        )
+    }
+}
+
+/// What happened to the selected conversations
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct DatasetStats {
+    /// Sessions matching the query
+    pub selected: usize,
+    /// Conversations with no assistant reply left after the transforms
+    pub skipped: usize,
+    /// Conversations dropped as duplicates
+    pub duplicates: usize,
+}
+
+/// Write examples as chat-format JSONL, one `{"messages": [...]}` per line
+pub fn write_jsonl<W: Write>(examples: &[Vec<Message>], writer: W) -> Result<()> {
+    let mut writer = BufWriter::new(writer);
+    for messages in examples {
+        serde_json::to_writer(&mut writer, &serde_json::json!({ "messages": messages }))?;
+        writer.write_all(b"\n").map_err(GrokError::Io)?;
+    }
+    writer.flush().map_err(GrokError::Io)
+}
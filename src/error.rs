@@ -1,56 +1,623 @@
 //! Error types for the Grok SDK
 
+use std::collections::BTreeMap;
 use std::fmt;
+use std::time::Duration;
 
 /// Result type alias for Grok operations
 pub type Result<T> = std::result::Result<T, GrokError>;
 
+/// The underlying failure of a session or collection operation
+pub type BoxError = Box<dyn std::error::Error + Send + Sync>;
+
 /// Errors that can occur when using the Grok SDK
+///
+/// New variants may be added in minor releases, so matches need a wildcard arm.
 #[derive(Debug)]
+#[non_exhaustive]
 pub enum GrokError {
     /// HTTP request failed
     Http(reqwest::Error),
+    /// A request or stream took too long
+    Timeout {
+        elapsed: Duration,
+        phase: TimeoutPhase,
+    },
     /// JSON serialization/deserialization failed
     Json(serde_json::Error),
-    /// API returned an error response
-    Api { status: u16, message: String },
+    /// Reading or writing local data failed, such as writing a stream to a file
+    Io(std::io::Error),
+    /// API returned an error response (boxed to keep `Result` small)
+    Api(Box<ApiError>),
     /// Invalid configuration or parameters
     InvalidConfig(String),
+    /// Request parameters failed validation before being sent
+    Validation(Vec<ValidationError>),
     /// Authentication failed
-    Authentication(String),
-    /// Rate limit exceeded
-    RateLimit { retry_after: Option<u64> },
+    Authentication {
+        message: String,
+        request_id: Option<String>,
+    },
+    /// Rate limit exceeded; `retry_after` is in seconds, from the `Retry-After`
+    /// header or else the reset time of the exhausted `x-ratelimit-*` quota
+    RateLimit {
+        retry_after: Option<u64>,
+        request_id: Option<String>,
+        /// The rate-limit headers of the response, such as `x-ratelimit-remaining-tokens`
+        headers: BTreeMap<String, String>,
+    },
     /// Tool execution failed
     ToolExecution(String),
+    /// The model called a tool with arguments that don't parse or don't match its schema
+    InvalidToolArguments { tool: String, message: String },
+    /// Streamed data couldn't be decoded into a chunk
+    StreamDecode {
+        data: String,
+        source: serde_json::Error,
+    },
+    /// A streamed event grew past the client's buffer limit without ending
+    StreamBufferOverflow { limit: usize },
+    /// A stream consumer fell too far behind under `StreamOverflowPolicy::Fail`
+    StreamLagged { capacity: usize },
+    /// A stream failed after part of the completion had arrived
+    StreamInterrupted {
+        partial: Box<crate::chat::ChatCompletion>,
+        error: Box<GrokError>,
+    },
+    /// The estimated prompt plus `max_tokens` doesn't fit in the model's
+    /// context window, found before sending the request
+    ContextLengthExceeded {
+        context_window: u32,
+        prompt_tokens: u32,
+        max_tokens: u32,
+        /// Tokens over the window
+        overflow: u32,
+    },
+    /// A reply to a `json_schema` response format didn't match the schema,
+    /// after any repair attempts
+    SchemaValidation {
+        /// The last reply, as received
+        payload: String,
+        /// What's wrong with it
+        errors: Vec<String>,
+    },
+    /// A spend budget's limit was reached, found before sending the request
+    BudgetExceeded {
+        limit: crate::budget::BudgetLimit,
+        window: crate::budget::BudgetWindow,
+        /// How long until enough spend leaves the window for requests to go through
+        resets_in: Duration,
+    },
+    /// A session, collection or other entity doesn't exist
+    NotFound { entity: Entity, id: String },
+    /// Another error, with a description of what was being done when it happened
+    Context {
+        context: String,
+        source: Box<GrokError>,
+    },
+    /// A guardrail blocked a message going to or coming from the model
+    ContentBlocked {
+        stage: crate::guardrail::GuardStage,
+        reason: String,
+    },
+    /// The client is shutting down, so the request was refused or cancelled
+    ShuttingDown,
     /// Session operation failed
-    Session(String),
+    Session {
+        /// What was being done, such as `load session`
+        op: String,
+        /// The session it was done to, when the failure concerns one
+        id: Option<String>,
+        source: BoxError,
+    },
     /// Collection operation failed
-    Collection(String),
+    Collection {
+        /// What was being done, such as `save collection`
+        op: String,
+        /// The collection it was done to, when the failure concerns one
+        id: Option<String>,
+        source: BoxError,
+    },
+    /// Indexing or retrieving documents failed
+    Retrieval(String),
 }
 
 impl fmt::Display for GrokError {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
             GrokError::Http(e) => write!(f, "HTTP error: {}", e),
+            GrokError::Timeout { elapsed, phase } => {
+                write!(f, "Timed out {} after {:?}", phase, elapsed)
+            }
             GrokError::Json(e) => write!(f, "JSON error: {}", e),
-            GrokError::Api { status, message } => write!(f, "API error ({}): {}", status, message),
+            GrokError::Io(e) => write!(f, "I/O error: {}", e),
+            GrokError::Api(e) => {
+                write!(f, "API error ({}): {}", e.status, e.message)?;
+                write_request_id(f, e.request_id.as_deref())
+            }
             GrokError::InvalidConfig(msg) => write!(f, "Invalid config: {}", msg),
-            GrokError::Authentication(msg) => write!(f, "Authentication error: {}", msg),
-            GrokError::RateLimit { retry_after } => {
+            GrokError::Validation(errors) => {
+                write!(f, "Invalid request: ")?;
+                for (i, error) in errors.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, "; ")?;
+                    }
+                    write!(f, "{}", error)?;
+                }
+                Ok(())
+            }
+            GrokError::Authentication {
+                message,
+                request_id,
+            } => {
+                write!(f, "Authentication error: {}", message)?;
+                write_request_id(f, request_id.as_deref())
+            }
+            GrokError::RateLimit {
+                retry_after,
+                request_id,
+                ..
+            } => {
                 if let Some(seconds) = retry_after {
-                    write!(f, "Rate limit exceeded, retry after {} seconds", seconds)
+                    write!(f, "Rate limit exceeded, retry after {} seconds", seconds)?;
                 } else {
-                    write!(f, "Rate limit exceeded")
+                    write!(f, "Rate limit exceeded")?;
                 }
+                write_request_id(f, request_id.as_deref())
             }
             GrokError::ToolExecution(msg) => write!(f, "Tool execution error: {}", msg),
-            GrokError::Session(msg) => write!(f, "Session error: {}", msg),
-            GrokError::Collection(msg) => write!(f, "Collection error: {}", msg),
+            GrokError::InvalidToolArguments { tool, message } => {
+                write!(f, "Invalid arguments for tool '{}': {}", tool, message)
+            }
+            GrokError::StreamDecode { data, source } => {
+                write!(f, "Failed to decode stream chunk {:?}: {}", data, source)
+            }
+            GrokError::StreamBufferOverflow { limit } => {
+                write!(f, "Stream event exceeded the {} byte buffer limit", limit)
+            }
+            GrokError::StreamLagged { capacity } => write!(
+                f,
+                "Stream consumer fell more than {} chunks behind",
+                capacity
+            ),
+            GrokError::StreamInterrupted { partial, error } => write!(
+                f,
+                "Stream interrupted after {} characters: {}",
+                partial.message.content.text().chars().count(),
+                error
+            ),
+            GrokError::ContextLengthExceeded {
+                context_window,
+                prompt_tokens,
+                max_tokens,
+                overflow,
+            } => write!(
+                f,
+                "Prompt of about {} tokens plus {} reserved for the reply exceeds the {} token context window by {}",
+                prompt_tokens, max_tokens, context_window, overflow
+            ),
+            GrokError::SchemaValidation { errors, .. } => write!(
+                f,
+                "Response does not match the JSON schema: {}",
+                errors.join("; ")
+            ),
+            GrokError::BudgetExceeded {
+                limit,
+                window,
+                resets_in,
+            } => write!(
+                f,
+                "Budget of {} per {} exceeded, resets in {:?}",
+                limit, window, resets_in
+            ),
+            GrokError::NotFound { entity, id } => write!(f, "{} '{}' not found", entity, id),
+            GrokError::Context { context, source } => write!(f, "{}: {}", context, source),
+            GrokError::ContentBlocked { stage, reason } => {
+                write!(f, "Content blocked in {}: {}", stage, reason)
+            }
+            GrokError::ShuttingDown => write!(f, "Client is shutting down"),
+            GrokError::Session { op, id, source } => {
+                write_operation(f, Entity::Session, op, id.as_deref(), source)
+            }
+            GrokError::Collection { op, id, source } => {
+                write_operation(f, Entity::Collection, op, id.as_deref(), source)
+            }
+            GrokError::Retrieval(msg) => write!(f, "Retrieval error: {}", msg),
+        }
+    }
+}
+
+fn write_operation(
+    f: &mut fmt::Formatter<'_>,
+    entity: Entity,
+    op: &str,
+    id: Option<&str>,
+    source: &BoxError,
+) -> fmt::Result {
+    match id {
+        Some(id) => write!(f, "{} '{}': failed to {}: {}", entity, id, op, source),
+        None => write!(f, "{} error: failed to {}: {}", entity, op, source),
+    }
+}
+
+fn write_request_id(f: &mut fmt::Formatter<'_>, request_id: Option<&str>) -> fmt::Result {
+    match request_id {
+        Some(id) => write!(f, " (request ID: {})", id),
+        None => Ok(()),
+    }
+}
+
+impl std::error::Error for GrokError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            GrokError::Http(e) => Some(e),
+            GrokError::Json(e) => Some(e),
+            GrokError::Io(e) => Some(e),
+            GrokError::StreamDecode { source, .. } => Some(source),
+            GrokError::StreamInterrupted { error, .. } => Some(error.as_ref()),
+            GrokError::Context { source, .. } => Some(source.as_ref()),
+            GrokError::Session { source, .. } | GrokError::Collection { source, .. } => {
+                Some(source.as_ref())
+            }
+            _ => None,
+        }
+    }
+}
+
+/// The kind of thing a `NotFound` error refers to
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum Entity {
+    /// A conversation session
+    Session,
+    /// A collection of sessions
+    Collection,
+    /// An agent in an orchestrator
+    Agent,
+    /// A stored prompt template
+    Prompt,
+}
+
+impl fmt::Display for Entity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Entity::Session => write!(f, "Session"),
+            Entity::Collection => write!(f, "Collection"),
+            Entity::Agent => write!(f, "Agent"),
+            Entity::Prompt => write!(f, "Prompt"),
+        }
+    }
+}
+
+/// Adds context to the error of a `Result`
+pub trait ResultExt<T> {
+    /// Wrap an error with a description of what was being done
+    fn context(self, context: impl Into<String>) -> Result<T>;
+
+    /// Like `context`, but only builds the description if there is an error
+    fn with_context<C: Into<String>>(self, context: impl FnOnce() -> C) -> Result<T>;
+}
+
+impl<T> ResultExt<T> for Result<T> {
+    fn context(self, context: impl Into<String>) -> Result<T> {
+        self.map_err(|e| e.context(context))
+    }
+
+    fn with_context<C: Into<String>>(self, context: impl FnOnce() -> C) -> Result<T> {
+        self.map_err(|e| e.context(context()))
+    }
+}
+
+/// What a request was doing when it timed out
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum TimeoutPhase {
+    /// Connecting to the API
+    Connect,
+    /// Waiting for the response
+    Request,
+    /// Waiting for the next chunk of a streamed response
+    StreamIdle,
+    /// Attempting and retrying the request, past the overall deadline
+    Deadline,
+}
+
+impl fmt::Display for TimeoutPhase {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TimeoutPhase::Connect => write!(f, "connecting"),
+            TimeoutPhase::Request => write!(f, "waiting for the response"),
+            TimeoutPhase::StreamIdle => write!(f, "waiting for the stream"),
+            TimeoutPhase::Deadline => write!(f, "retrying the request"),
+        }
+    }
+}
+
+/// Broad category of a `GrokError`, for retry and alerting decisions
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum ErrorKind {
+    /// The request couldn't be sent or its response couldn't be read
+    Network,
+    /// The request or stream took too long
+    Timeout,
+    /// A body couldn't be serialized or deserialized
+    Serialization,
+    /// Reading or writing local data failed
+    Io,
+    /// The API key is missing, invalid or not allowed to do this
+    Authentication,
+    /// Too many requests
+    RateLimit,
+    /// The request doesn't fit in the model's context window
+    ContextLength,
+    /// The request was invalid, as reported by the API or found before sending it
+    InvalidRequest,
+    /// The API failed on its side
+    Server,
+    /// The SDK was given invalid configuration or parameters
+    Config,
+    /// A tool failed or was called with invalid arguments
+    Tool,
+    /// A spend budget ran out
+    Budget,
+    /// A session, collection or other entity doesn't exist
+    NotFound,
+    /// A guardrail blocked the content
+    ContentBlocked,
+    /// The client is shutting down
+    Shutdown,
+    /// A session operation failed
+    Session,
+    /// A collection operation failed
+    Collection,
+    /// Indexing or retrieving documents failed
+    Retrieval,
+}
+
+impl GrokError {
+    /// Categorize the error
+    pub fn kind(&self) -> ErrorKind {
+        match self {
+            GrokError::Http(_) => ErrorKind::Network,
+            GrokError::Timeout { .. } => ErrorKind::Timeout,
+            GrokError::Json(_) => ErrorKind::Serialization,
+            GrokError::Io(_) => ErrorKind::Io,
+            GrokError::Api(e) => match e.status {
+                401 | 403 => ErrorKind::Authentication,
+                429 => ErrorKind::RateLimit,
+                _ if e.is_context_length() => ErrorKind::ContextLength,
+                500.. => ErrorKind::Server,
+                _ => ErrorKind::InvalidRequest,
+            },
+            GrokError::InvalidConfig(_) | GrokError::StreamLagged { .. } => ErrorKind::Config,
+            GrokError::Validation(_) => ErrorKind::InvalidRequest,
+            GrokError::Authentication { .. } => ErrorKind::Authentication,
+            GrokError::RateLimit { .. } => ErrorKind::RateLimit,
+            GrokError::ToolExecution(_) | GrokError::InvalidToolArguments { .. } => ErrorKind::Tool,
+            GrokError::StreamDecode { .. } | GrokError::StreamBufferOverflow { .. } => {
+                ErrorKind::Serialization
+            }
+            GrokError::StreamInterrupted { error, .. } => error.kind(),
+            GrokError::ContextLengthExceeded { .. } => ErrorKind::ContextLength,
+            GrokError::SchemaValidation { .. } => ErrorKind::Serialization,
+            GrokError::BudgetExceeded { .. } => ErrorKind::Budget,
+            GrokError::NotFound { .. } => ErrorKind::NotFound,
+            GrokError::Context { source, .. } => source.kind(),
+            GrokError::ContentBlocked { .. } => ErrorKind::ContentBlocked,
+            GrokError::ShuttingDown => ErrorKind::Shutdown,
+            GrokError::Session { .. } => ErrorKind::Session,
+            GrokError::Collection { .. } => ErrorKind::Collection,
+            GrokError::Retrieval(_) => ErrorKind::Retrieval,
+        }
+    }
+
+    /// Whether the same request may succeed if retried; the client retries these itself
+    pub fn is_retryable(&self) -> bool {
+        matches!(
+            self.kind(),
+            ErrorKind::Network | ErrorKind::Timeout | ErrorKind::RateLimit | ErrorKind::Server
+        )
+    }
+
+    /// Whether another model may succeed where this one failed
+    ///
+    /// Timeouts, rate limits and server errors, such as a model at capacity,
+    /// can be specific to the model; the other kinds would fail the same way.
+    pub fn should_fall_back(&self) -> bool {
+        matches!(
+            self.kind(),
+            ErrorKind::Timeout | ErrorKind::RateLimit | ErrorKind::Server
+        )
+    }
+
+    /// A failed session operation, such as `load session`, and why it failed
+    pub(crate) fn session(op: impl Into<String>, source: impl Into<BoxError>) -> Self {
+        GrokError::Session {
+            op: op.into(),
+            id: None,
+            source: source.into(),
+        }
+    }
+
+    /// A failed collection operation, such as `save collection`, and why it failed
+    pub(crate) fn collection(op: impl Into<String>, source: impl Into<BoxError>) -> Self {
+        GrokError::Collection {
+            op: op.into(),
+            id: None,
+            source: source.into(),
+        }
+    }
+
+    /// Name the session or collection a `Session` or `Collection` error concerns
+    pub(crate) fn with_id(mut self, entity_id: impl Into<String>) -> Self {
+        if let GrokError::Session { id, .. } | GrokError::Collection { id, .. } = &mut self {
+            *id = Some(entity_id.into());
+        }
+        self
+    }
+
+    /// Wrap the error with a description of what was being done
+    pub fn context(self, context: impl Into<String>) -> Self {
+        GrokError::Context {
+            context: context.into(),
+            source: Box::new(self),
+        }
+    }
+
+    /// Whether the request or stream timed out
+    pub fn is_timeout(&self) -> bool {
+        self.kind() == ErrorKind::Timeout
+    }
+
+    /// Whether the API is rate limiting requests
+    pub fn is_rate_limit(&self) -> bool {
+        self.kind() == ErrorKind::RateLimit
+    }
+
+    /// How long the API asked to wait before retrying, for rate-limit errors that say
+    pub fn retry_after(&self) -> Option<Duration> {
+        match self {
+            GrokError::RateLimit { retry_after, .. } => retry_after.map(Duration::from_secs),
+            GrokError::Context { source, .. } => source.retry_after(),
+            _ => None,
+        }
+    }
+
+    /// Whether the API key was rejected
+    pub fn is_auth(&self) -> bool {
+        self.kind() == ErrorKind::Authentication
+    }
+
+    /// Whether the request was too long for the model's context window
+    pub fn is_context_length(&self) -> bool {
+        self.kind() == ErrorKind::ContextLength
+    }
+}
+
+/// A request parameter that is out of range
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ValidationError {
+    /// Name of the offending parameter, such as `temperature`
+    pub field: String,
+    /// The rule it breaks, such as `must be between 0 and 2`
+    pub constraint: String,
+    /// The value it was given
+    pub actual: String,
+}
+
+impl ValidationError {
+    /// Create a validation error
+    pub fn new(
+        field: impl Into<String>,
+        constraint: impl Into<String>,
+        actual: impl ToString,
+    ) -> Self {
+        Self {
+            field: field.into(),
+            constraint: constraint.into(),
+            actual: actual.to_string(),
         }
     }
 }
 
-impl std::error::Error for GrokError {}
+impl fmt::Display for ValidationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} {} (got {})",
+            self.field, self.constraint, self.actual
+        )
+    }
+}
+
+/// An error response from the API
+///
+/// The fields of the JSON error envelope are parsed when present; `body`
+/// always holds the response text as received.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ApiError {
+    /// HTTP status code
+    pub status: u16,
+    /// Error message, or the raw body if it has none
+    pub message: String,
+    /// Machine-readable error code, such as `context_length_exceeded`
+    pub code: Option<String>,
+    /// Error type, such as `invalid_request_error`
+    pub error_type: Option<String>,
+    /// The request parameter the error refers to
+    pub param: Option<String>,
+    /// The raw response body
+    pub body: String,
+    /// The `x-request-id` of the response, or else the ID the client sent
+    pub request_id: Option<String>,
+    /// Response headers useful for support requests, such as the rate-limit headers
+    pub headers: BTreeMap<String, String>,
+}
+
+impl ApiError {
+    /// Create an error with only a status and message
+    pub fn new(status: u16, message: impl Into<String>) -> Self {
+        let message = message.into();
+        Self {
+            status,
+            body: message.clone(),
+            message,
+            code: None,
+            error_type: None,
+            param: None,
+            request_id: None,
+            headers: BTreeMap::new(),
+        }
+    }
+
+    /// Parse an error response body
+    ///
+    /// Accepts both `{"error": {"message", "type", "param", "code"}}` and
+    /// `{"error": "message", "code": "..."}` envelopes. Anything else is kept
+    /// as the message verbatim.
+    pub fn from_response(status: u16, body: impl Into<String>) -> Self {
+        let body = body.into();
+        let mut error = Self::new(status, body.clone());
+
+        let Ok(envelope) = serde_json::from_str::<serde_json::Value>(&body) else {
+            return error;
+        };
+        // Codes are strings from some endpoints and numbers from others
+        let text = |value: &serde_json::Value| match value {
+            serde_json::Value::String(s) => Some(s.clone()),
+            serde_json::Value::Null => None,
+            other => Some(other.to_string()),
+        };
+
+        let details = match envelope.get("error") {
+            Some(details @ serde_json::Value::Object(_)) => details,
+            Some(serde_json::Value::String(message)) => {
+                error.message = message.clone();
+                &envelope
+            }
+            _ => &envelope,
+        };
+        if let Some(message) = details.get("message").and_then(|m| m.as_str()) {
+            error.message = message.to_string();
+        }
+        error.code = details.get("code").and_then(text);
+        error.error_type = details.get("type").and_then(text);
+        error.param = details.get("param").and_then(text);
+        error
+    }
+
+    /// Whether the error says the request exceeded the model's context window
+    fn is_context_length(&self) -> bool {
+        if self.code.as_deref() == Some("context_length_exceeded") {
+            return true;
+        }
+        let message = self.message.to_ascii_lowercase();
+        ["context length", "context window", "maximum prompt length"]
+            .iter()
+            .any(|phrase| message.contains(phrase))
+    }
+}
 
 impl From<reqwest::Error> for GrokError {
     fn from(err: reqwest::Error) -> Self {
@@ -0,0 +1,389 @@
+//! Regression tests for prompts and agents
+//!
+//! An `EvalSuite` is a list of cases, each a conversation and assertions on
+//! the reply. An `Evaluator` runs the cases against an `EvalTarget` (a model
+//! configuration or an `Agent`) concurrently and collects an `EvalReport`
+//! that serializes to JSON.
+
+use crate::agent::Agent;
+use crate::chat::{Message, Model, Role};
+use crate::client::ChatOptions;
+use crate::error::{GrokError, Result};
+use crate::extract::{complete_json, ExtractInput, ExtractOptions};
+use crate::provider::ChatProvider;
+use async_trait::async_trait;
+use futures::StreamExt;
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+use std::sync::Arc;
+use std::time::Instant;
+
+/// A check on the reply to a case
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Assertion {
+    /// The reply contains `value`
+    Contains {
+        value: String,
+        #[serde(default)]
+        ignore_case: bool,
+    },
+    /// The reply doesn't contain `value`
+    NotContains { value: String },
+    /// The reply matches the regular expression `pattern`
+    Regex { pattern: String },
+    /// A judge model scores the reply against `criteria` at `min_score` or more, from 0 to 1
+    Judge {
+        criteria: String,
+        #[serde(default = "default_min_score")]
+        min_score: f64,
+    },
+}
+
+fn default_min_score() -> f64 {
+    0.7
+}
+
+/// A conversation to send and the assertions its reply must pass
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EvalCase {
+    /// Name shown in the report
+    pub name: String,
+    /// Messages sent to the target
+    pub messages: Vec<Message>,
+    /// Checks on the reply; a case without assertions passes if the target replies
+    #[serde(default)]
+    pub assertions: Vec<Assertion>,
+}
+
+/// A named set of cases, usually loaded from a JSON file
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EvalSuite {
+    /// Name shown in the report
+    pub name: String,
+    /// The cases to run
+    pub cases: Vec<EvalCase>,
+}
+
+impl EvalSuite {
+    /// Parse a suite from JSON
+    pub fn from_json(json: &str) -> Result<Self> {
+        Ok(serde_json::from_str(json)?)
+    }
+
+    /// Read a suite from a JSON file
+    pub fn load(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let json = std::fs::read_to_string(path).map_err(|e| {
+            GrokError::InvalidConfig(format!(
+                "Failed to read eval suite {}: {}",
+                path.display(),
+                e
+            ))
+        })?;
+        Self::from_json(&json)
+    }
+}
+
+/// Something that replies to a conversation, such as a model or an agent
+#[async_trait]
+pub trait EvalTarget: Send + Sync {
+    /// The reply to `messages`
+    async fn respond(&self, messages: &[Message]) -> Result<String>;
+}
+
+/// A model with fixed chat options, called through any provider
+#[derive(Debug, Clone)]
+pub struct ModelTarget {
+    provider: Arc<dyn ChatProvider>,
+    model: Model,
+    options: Option<ChatOptions>,
+}
+
+impl ModelTarget {
+    /// Evaluate `model` through `provider`
+    pub fn new(provider: Arc<dyn ChatProvider>, model: Model) -> Self {
+        Self {
+            provider,
+            model,
+            options: None,
+        }
+    }
+
+    /// Send these options with every case
+    pub fn options(mut self, options: ChatOptions) -> Self {
+        self.options = Some(options);
+        self
+    }
+}
+
+#[async_trait]
+impl EvalTarget for ModelTarget {
+    async fn respond(&self, messages: &[Message]) -> Result<String> {
+        let completion = self
+            .provider
+            .chat(
+                self.model,
+                messages.iter().cloned().map(Arc::new).collect(),
+                None,
+                self.options.clone(),
+            )
+            .await?;
+        Ok(completion.message.content.to_string())
+    }
+}
+
+/// Runs the last user message of each case as the agent's task
+///
+/// The agent uses its own system prompt; other messages of the case are ignored.
+#[async_trait]
+impl EvalTarget for Agent {
+    async fn respond(&self, messages: &[Message]) -> Result<String> {
+        let task = messages
+            .iter()
+            .rev()
+            .find(|message| matches!(message.role, Role::User))
+            .ok_or_else(|| GrokError::InvalidConfig("Eval case has no user message".to_string()))?;
+        let run = self.run(task.content.to_string()).await?;
+        Ok(run.output.unwrap_or_default())
+    }
+}
+
+/// The outcome of one assertion
+#[derive(Debug, Clone, Serialize)]
+pub struct AssertionResult {
+    /// The assertion checked
+    pub assertion: Assertion,
+    /// Whether it passed
+    pub passed: bool,
+    /// The judge's score, for `Judge` assertions
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub score: Option<f64>,
+    /// Why it failed, or the judge's reasoning
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub detail: Option<String>,
+}
+
+/// The outcome of one case
+#[derive(Debug, Clone, Serialize)]
+pub struct CaseResult {
+    /// Name of the case
+    pub name: String,
+    /// Whether the target replied and every assertion passed
+    pub passed: bool,
+    /// The target's reply
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub output: Option<String>,
+    /// The error the target failed with, if it didn't reply
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    /// Results of the case's assertions, in order
+    pub assertions: Vec<AssertionResult>,
+    /// Time the target took to reply, in milliseconds
+    pub latency_ms: u64,
+}
+
+/// Results of a suite run
+#[derive(Debug, Clone, Serialize)]
+pub struct EvalReport {
+    /// Name of the suite
+    pub suite: String,
+    /// Cases whose assertions all passed
+    pub passed: usize,
+    /// Cases with a failed assertion
+    pub failed: usize,
+    /// Cases where the target returned an error
+    pub errored: usize,
+    /// Share of cases that passed, from 0 to 1
+    pub pass_rate: f64,
+    /// Every case, in suite order
+    pub cases: Vec<CaseResult>,
+}
+
+impl EvalReport {
+    /// Whether every case passed
+    pub fn all_passed(&self) -> bool {
+        self.passed == self.cases.len()
+    }
+
+    /// The report as pretty-printed JSON
+    pub fn to_json(&self) -> Result<String> {
+        Ok(serde_json::to_string_pretty(self)?)
+    }
+}
+
+/// Reply of the judge model
+#[derive(Deserialize)]
+struct Verdict {
+    score: f64,
+    reasoning: String,
+}
+
+/// Runs suites against targets
+#[derive(Debug, Clone)]
+pub struct Evaluator {
+    concurrency: usize,
+    judge: Option<(Arc<dyn ChatProvider>, Model)>,
+}
+
+impl Default for Evaluator {
+    fn default() -> Self {
+        Self {
+            concurrency: 4,
+            judge: None,
+        }
+    }
+}
+
+impl Evaluator {
+    /// Create an evaluator running four cases at a time, without a judge
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Run at most this many cases at a time
+    pub fn concurrency(mut self, concurrency: usize) -> Self {
+        self.concurrency = concurrency.max(1);
+        self
+    }
+
+    /// Score `Judge` assertions with `model`; without a judge they fail
+    pub fn judge(mut self, provider: Arc<dyn ChatProvider>, model: Model) -> Self {
+        self.judge = Some((provider, model));
+        self
+    }
+
+    /// Run every case of `suite` against `target`
+    ///
+    /// Errors from the target are recorded in the report rather than returned.
+    pub async fn run(&self, suite: &EvalSuite, target: &dyn EvalTarget) -> EvalReport {
+        let cases: Vec<CaseResult> = futures::stream::iter(&suite.cases)
+            .map(|case| self.run_case(case, target))
+            .buffered(self.concurrency)
+            .collect()
+            .await;
+
+        let passed = cases.iter().filter(|case| case.passed).count();
+        let errored = cases.iter().filter(|case| case.error.is_some()).count();
+        EvalReport {
+            suite: suite.name.clone(),
+            passed,
+            failed: cases.len() - passed - errored,
+            errored,
+            pass_rate: if cases.is_empty() {
+                0.0
+            } else {
+                passed as f64 / cases.len() as f64
+            },
+            cases,
+        }
+    }
+
+    async fn run_case(&self, case: &EvalCase, target: &dyn EvalTarget) -> CaseResult {
+        let started = Instant::now();
+        let reply = target.respond(&case.messages).await;
+        let latency_ms = started.elapsed().as_millis() as u64;
+
+        let output = match reply {
+            Ok(output) => output,
+            Err(e) => {
+                return CaseResult {
+                    name: case.name.clone(),
+                    passed: false,
+                    output: None,
+                    error: Some(e.to_string()),
+                    assertions: Vec::new(),
+                    latency_ms,
+                }
+            }
+        };
+
+        let mut assertions = Vec::with_capacity(case.assertions.len());
+        for assertion in &case.assertions {
+            assertions.push(self.check(assertion, case, &output).await);
+        }
+        CaseResult {
+            name: case.name.clone(),
+            passed: assertions.iter().all(|result| result.passed),
+            output: Some(output),
+            error: None,
+            assertions,
+            latency_ms,
+        }
+    }
+
+    async fn check(&self, assertion: &Assertion, case: &EvalCase, output: &str) -> AssertionResult {
+        let (passed, score, detail) = match assertion {
+            Assertion::Contains { value, ignore_case } => {
+                let found = if *ignore_case {
+                    output.to_lowercase().contains(&value.to_lowercase())
+                } else {
+                    output.contains(value.as_str())
+                };
+                (found, None, None)
+            }
+            Assertion::NotContains { value } => (!output.contains(value.as_str()), None, None),
+            Assertion::Regex { pattern } => match regex::Regex::new(pattern) {
+                Ok(regex) => (regex.is_match(output), None, None),
+                Err(e) => (false, None, Some(format!("Invalid pattern: {}", e))),
+            },
+            Assertion::Judge {
+                criteria,
+                min_score,
+            } => match self.score(criteria, case, output).await {
+                Ok(verdict) => (
+                    verdict.score >= *min_score,
+                    Some(verdict.score),
+                    Some(verdict.reasoning),
+                ),
+                Err(e) => (false, None, Some(e.to_string())),
+            },
+        };
+        AssertionResult {
+            assertion: assertion.clone(),
+            passed,
+            score,
+            detail,
+        }
+    }
+
+    /// Ask the judge model to score `output` against `criteria`
+    async fn score(&self, criteria: &str, case: &EvalCase, output: &str) -> Result<Verdict> {
+        let (provider, model) = self.judge.as_ref().ok_or_else(|| {
+            GrokError::InvalidConfig("Judge assertions need Evaluator::judge".to_string())
+        })?;
+
+        let mut transcript = String::new();
+        for message in &case.messages {
+            transcript.push_str(&format!("{:?}: {}\n", message.role, message.content));
+        }
+        let input = format!(
+            "Criteria:\n{}\n\nConversation:\n{}\nReply to evaluate:\n{}",
+            criteria, transcript, output
+        );
+        let schema = serde_json::json!({
+            "type": "object",
+            "properties": {
+                "score": { "type": "number", "minimum": 0, "maximum": 1 },
+                "reasoning": { "type": "string" }
+            },
+            "required": ["score", "reasoning"],
+            "additionalProperties": false
+        });
+        let options = ExtractOptions {
+            model: *model,
+            ..Default::default()
+        };
+        complete_json(
+            provider.as_ref(),
+            "verdict",
+            schema,
+            "Score how well the reply meets the criteria, from 0 (not at all) to 1 (fully), \
+             and explain the score briefly.",
+            ExtractInput::Text(input),
+            options,
+            |reply| serde_json::from_str(reply),
+        )
+        .await
+    }
+}
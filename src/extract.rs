@@ -0,0 +1,235 @@
+//! Structured extraction of typed data from unstructured text
+//!
+//! `Client::extract` asks the model for JSON matching a schema generated from
+//! the target type, parses the reply, and gives the model a chance to repair
+//! a reply that doesn't parse.
+
+use crate::chat::{Message, Model, Role};
+use crate::client::ChatOptions;
+use crate::error::{GrokError, Result};
+use crate::provider::ChatProvider;
+use crate::Client;
+use schemars::JsonSchema;
+use serde::de::DeserializeOwned;
+use std::sync::Arc;
+
+/// What to extract from: a piece of text or a whole conversation
+#[derive(Debug, Clone)]
+pub enum ExtractInput {
+    /// Text sent as a single user message
+    Text(String),
+    /// Messages sent after the extraction instructions
+    Messages(Vec<Message>),
+}
+
+impl From<&str> for ExtractInput {
+    fn from(text: &str) -> Self {
+        ExtractInput::Text(text.to_string())
+    }
+}
+
+impl From<String> for ExtractInput {
+    fn from(text: String) -> Self {
+        ExtractInput::Text(text)
+    }
+}
+
+impl From<Vec<Message>> for ExtractInput {
+    fn from(messages: Vec<Message>) -> Self {
+        ExtractInput::Messages(messages)
+    }
+}
+
+/// Options for `Client::extract_with_options`
+#[derive(Debug, Clone)]
+pub struct ExtractOptions {
+    /// Model to extract with
+    pub model: Model,
+    /// Extra instructions appended to the system prompt
+    pub instructions: Option<String>,
+    /// Times the model is shown a reply that didn't parse and asked to fix it
+    pub repair_attempts: u32,
+    /// Sampling options; `response_format` is replaced with the schema
+    pub chat_options: ChatOptions,
+}
+
+impl Default for ExtractOptions {
+    fn default() -> Self {
+        Self {
+            model: Model::Grok4,
+            instructions: None,
+            repair_attempts: 1,
+            chat_options: ChatOptions {
+                temperature: Some(0.0),
+                ..Default::default()
+            },
+        }
+    }
+}
+
+impl Client {
+    /// Extract a `T` from text or messages with default options
+    ///
+    /// ```rust,no_run
+    /// # async fn example(client: grok_rust_sdk::Client) -> grok_rust_sdk::Result<()> {
+    /// #[derive(serde::Deserialize, schemars::JsonSchema)]
+    /// struct Contact {
+    ///     name: String,
+    ///     email: Option<String>,
+    /// }
+    ///
+    /// let contact: Contact = client
+    ///     .extract("Reach Ada Lovelace at ada@example.com")
+    ///     .await?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn extract<T>(&self, input: impl Into<ExtractInput>) -> Result<T>
+    where
+        T: DeserializeOwned + JsonSchema,
+    {
+        self.extract_with_options(input, ExtractOptions::default())
+            .await
+    }
+
+    /// Extract a `T` from text or messages
+    pub async fn extract_with_options<T>(
+        &self,
+        input: impl Into<ExtractInput>,
+        options: ExtractOptions,
+    ) -> Result<T>
+    where
+        T: DeserializeOwned + JsonSchema,
+    {
+        extract(self, input.into(), options).await
+    }
+}
+
+/// Extract a `T` through any provider; see `Client::extract`
+pub async fn extract<T>(
+    provider: &dyn ChatProvider,
+    input: ExtractInput,
+    options: ExtractOptions,
+) -> Result<T>
+where
+    T: DeserializeOwned + JsonSchema,
+{
+    let name = schema_name::<T>();
+    let mut schema = serde_json::to_value(schemars::schema_for!(T))?;
+    // The API only needs the schema itself, not its metadata
+    if let Some(object) = schema.as_object_mut() {
+        object.remove("$schema");
+        object.remove("title");
+    }
+
+    let task = format!(
+        "Extract a {} from the user's input, using null for information that isn't present.",
+        name
+    );
+    complete_json(provider, &name, schema, &task, input, options, |reply| {
+        serde_json::from_str(reply)
+    })
+    .await
+}
+
+/// Ask for JSON matching `schema`, parse it, and let the model repair replies that fail to parse
+pub(crate) async fn complete_json<T>(
+    provider: &dyn ChatProvider,
+    name: &str,
+    schema: serde_json::Value,
+    task: &str,
+    input: ExtractInput,
+    options: ExtractOptions,
+    parse: impl Fn(&str) -> serde_json::Result<T>,
+) -> Result<T> {
+    let mut prompt = format!(
+        "{} Reply with only a JSON object matching this schema:\n{}",
+        task, schema
+    );
+    if let Some(ref instructions) = options.instructions {
+        prompt.push_str("\n\n");
+        prompt.push_str(instructions);
+    }
+
+    let mut messages = vec![Arc::new(message(Role::System, prompt))];
+    match input {
+        ExtractInput::Text(text) => messages.push(Arc::new(message(Role::User, text))),
+        ExtractInput::Messages(input) => messages.extend(input.into_iter().map(Arc::new)),
+    }
+
+    let chat_options = ChatOptions {
+        response_format: Some(serde_json::json!({
+            "type": "json_schema",
+            "json_schema": { "name": name, "schema": schema }
+        })),
+        ..options.chat_options
+    };
+
+    let mut repairs_left = options.repair_attempts;
+    loop {
+        let completion = provider
+            .chat(
+                options.model,
+                messages.clone(),
+                None,
+                Some(chat_options.clone()),
+            )
+            .await?;
+        let reply = completion.message.content.to_string();
+
+        let error = match parse(strip_code_fence(&reply)) {
+            Ok(value) => return Ok(value),
+            Err(e) => e,
+        };
+        if repairs_left == 0 {
+            return Err(GrokError::Json(error).context(format!("Failed to extract {}", name)));
+        }
+        repairs_left -= 1;
+
+        #[cfg(feature = "tracing")]
+        tracing::debug!(target_type = %name, error = %error, "repairing extraction reply");
+
+        messages.push(Arc::new(message(Role::Assistant, reply)));
+        messages.push(Arc::new(message(
+            Role::User,
+            format!(
+                "That reply could not be parsed: {}. Reply again with only the corrected JSON object.",
+                error
+            ),
+        )));
+    }
+}
+
+/// The schema name, reduced to the characters `response_format` accepts
+fn schema_name<T: JsonSchema>() -> String {
+    T::schema_name()
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || c == '_' || c == '-' {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect()
+}
+
+/// Models sometimes wrap JSON in a Markdown code fence despite instructions
+fn strip_code_fence(reply: &str) -> &str {
+    let trimmed = reply.trim();
+    trimmed
+        .strip_prefix("```json")
+        .or_else(|| trimmed.strip_prefix("```"))
+        .and_then(|rest| rest.strip_suffix("```"))
+        .map_or(trimmed, str::trim)
+}
+
+fn message(role: Role, content: String) -> Message {
+    Message {
+        role,
+        content: content.into(),
+        tool_calls: None,
+        tool_call_id: None,
+        name: None,
+    }
+}
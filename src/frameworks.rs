@@ -0,0 +1,270 @@
+//! Adapters for Rust LLM frameworks
+//!
+//! Each adapter wraps a shared `Client` and a model in the framework's own
+//! model trait, so Grok can be dropped into an existing agent pipeline:
+//! `rig` (feature `rig`) and `langchain` (feature `langchain`).
+
+use crate::chat::{Message, MessageContent, Role};
+
+/// Build a message with no tool data
+fn message(role: Role, content: impl Into<MessageContent>) -> Message {
+    Message {
+        role,
+        content: content.into(),
+        tool_calls: None,
+        tool_call_id: None,
+        name: None,
+    }
+}
+
+/// rig-core `CompletionModel` backed by `Client`
+#[cfg(feature = "rig")]
+pub mod rig {
+    use super::message;
+    use crate::chat::{ChatCompletion, Message, Model, Role, Tool, ToolSpec};
+    use crate::client::ChatOptions;
+    use crate::Client;
+    use ::rig::completion::{
+        CompletionError, CompletionModel, CompletionRequest, CompletionResponse, ModelChoice,
+    };
+    use std::sync::Arc;
+
+    /// A Grok model for rig agents, e.g. `AgentBuilder::new(GrokCompletionModel::new(client, Model::Grok4))`
+    #[derive(Debug, Clone)]
+    pub struct GrokCompletionModel {
+        client: Arc<Client>,
+        model: Model,
+    }
+
+    impl GrokCompletionModel {
+        /// Complete with `model` through a shared client
+        pub fn new(client: Arc<Client>, model: Model) -> Self {
+            Self { client, model }
+        }
+    }
+
+    /// The prompt, preceded by the request's documents as attachments
+    fn prompt_with_documents(request: &CompletionRequest) -> String {
+        if request.documents.is_empty() {
+            return request.prompt.clone();
+        }
+        let documents: String = request
+            .documents
+            .iter()
+            .map(|doc| doc.to_string())
+            .collect();
+        format!(
+            "<attachments>\n{}</attachments>\n\n{}",
+            documents, request.prompt
+        )
+    }
+
+    impl CompletionModel for GrokCompletionModel {
+        type Response = ChatCompletion;
+
+        /// Send the preamble, chat history and prompt with its documents
+        ///
+        /// `additional_params` are not forwarded.
+        async fn completion(
+            &self,
+            request: CompletionRequest,
+        ) -> Result<CompletionResponse<ChatCompletion>, CompletionError> {
+            let mut messages: Vec<Arc<Message>> = Vec::new();
+            if let Some(ref preamble) = request.preamble {
+                messages.push(Arc::new(message(Role::System, preamble.as_str())));
+            }
+            for turn in &request.chat_history {
+                let role = match turn.role.as_str() {
+                    "system" => Role::System,
+                    "assistant" => Role::Assistant,
+                    "tool" => Role::Tool,
+                    _ => Role::User,
+                };
+                messages.push(Arc::new(message(role, turn.content.as_str())));
+            }
+            messages.push(Arc::new(message(
+                Role::User,
+                prompt_with_documents(&request),
+            )));
+
+            let tools: Vec<Tool> = request
+                .tools
+                .into_iter()
+                .map(|definition| Tool {
+                    tool_type: "function".to_string(),
+                    function: ToolSpec {
+                        name: definition.name,
+                        description: Some(definition.description),
+                        parameters: Some(definition.parameters),
+                        strict: None,
+                    },
+                })
+                .collect();
+            let options = ChatOptions {
+                temperature: request.temperature.map(|t| t as f32),
+                max_tokens: request.max_tokens.map(|n| n.min(u32::MAX as u64) as u32),
+                ..Default::default()
+            };
+
+            let completion = self
+                .client
+                .chat_shared(
+                    self.model,
+                    messages,
+                    Some(tools).filter(|tools| !tools.is_empty()),
+                    Some(options),
+                )
+                .await
+                .map_err(|e| CompletionError::ProviderError(e.to_string()))?;
+
+            let choice = match completion
+                .message
+                .tool_calls
+                .as_ref()
+                .and_then(|calls| calls.first())
+            {
+                Some(call) => {
+                    let arguments =
+                        serde_json::from_str(&call.function.arguments).map_err(|e| {
+                            CompletionError::ResponseError(format!(
+                                "Failed to parse tool call arguments: {}",
+                                e
+                            ))
+                        })?;
+                    ModelChoice::ToolCall(call.function.name.clone(), arguments)
+                }
+                None => ModelChoice::Message(completion.message.content.to_string()),
+            };
+
+            Ok(CompletionResponse {
+                choice,
+                raw_response: completion,
+            })
+        }
+    }
+}
+
+/// langchain-rust `LLM` backed by `Client`
+#[cfg(feature = "langchain")]
+pub mod langchain {
+    use super::message;
+    use crate::chat::{Message, Model, Role};
+    use crate::client::ChatOptions;
+    use crate::error::GrokError;
+    use crate::Client;
+    use async_trait::async_trait;
+    use futures::stream::{Stream, StreamExt};
+    use langchain_rust::language_models::llm::LLM;
+    use langchain_rust::language_models::options::CallOptions;
+    use langchain_rust::language_models::{GenerateResult, LLMError, TokenUsage};
+    use langchain_rust::schemas::{Message as LcMessage, MessageType, StreamData};
+    use std::pin::Pin;
+    use std::sync::Arc;
+
+    /// A Grok model for langchain chains and agents
+    #[derive(Debug, Clone)]
+    pub struct GrokLlm {
+        client: Arc<Client>,
+        model: Model,
+        options: ChatOptions,
+    }
+
+    impl GrokLlm {
+        /// Complete with `model` through a shared client
+        pub fn new(client: Arc<Client>, model: Model) -> Self {
+            Self {
+                client,
+                model,
+                options: ChatOptions::default(),
+            }
+        }
+
+        /// Use these options for every call
+        pub fn with_options(mut self, options: ChatOptions) -> Self {
+            self.options = options;
+            self
+        }
+    }
+
+    fn convert(messages: &[LcMessage]) -> Vec<Message> {
+        messages
+            .iter()
+            .map(|lc| {
+                let role = match lc.message_type {
+                    MessageType::SystemMessage => Role::System,
+                    MessageType::AIMessage => Role::Assistant,
+                    MessageType::ToolMessage => Role::Tool,
+                    MessageType::HumanMessage => Role::User,
+                };
+                let mut converted = message(role, lc.content.as_str());
+                if let MessageType::ToolMessage = lc.message_type {
+                    converted.tool_call_id = lc.id.clone();
+                }
+                converted
+            })
+            .collect()
+    }
+
+    fn llm_error(error: GrokError) -> LLMError {
+        LLMError::OtherError(error.to_string())
+    }
+
+    #[async_trait]
+    impl LLM for GrokLlm {
+        async fn generate(&self, messages: &[LcMessage]) -> Result<GenerateResult, LLMError> {
+            let messages = convert(messages).into_iter().map(Arc::new).collect();
+            let completion = self
+                .client
+                .chat_shared(self.model, messages, None, Some(self.options.clone()))
+                .await
+                .map_err(llm_error)?;
+
+            Ok(GenerateResult {
+                tokens: completion.usage.map(|usage| TokenUsage {
+                    prompt_tokens: usage.prompt_tokens,
+                    completion_tokens: usage.completion_tokens,
+                    total_tokens: usage.total_tokens,
+                }),
+                generation: completion.message.content.to_string(),
+            })
+        }
+
+        /// Stream the reply
+        async fn stream(
+            &self,
+            messages: &[LcMessage],
+        ) -> Result<Pin<Box<dyn Stream<Item = Result<StreamData, LLMError>> + Send>>, LLMError>
+        {
+            let chunks = self
+                .client
+                .chat_stream_with_options(
+                    self.model,
+                    convert(messages),
+                    None,
+                    Some(self.options.clone()),
+                )
+                .await
+                .map_err(llm_error)?;
+
+            let stream = chunks.map(|chunk| {
+                let chunk = chunk.map_err(llm_error)?;
+                let content = chunk
+                    .choices
+                    .first()
+                    .and_then(|choice| choice.delta.content.clone())
+                    .unwrap_or_default();
+                let value = serde_json::to_value(&chunk)
+                    .map_err(|e| LLMError::OtherError(format!("Failed to encode chunk: {}", e)))?;
+                Ok(StreamData::new(value, None, content))
+            });
+            Ok(Box::pin(stream))
+        }
+
+        fn add_options(&mut self, options: CallOptions) {
+            self.options.max_tokens = options.max_tokens.or(self.options.max_tokens);
+            self.options.temperature = options.temperature.or(self.options.temperature);
+            self.options.top_p = options.top_p.or(self.options.top_p);
+            self.options.stop = options.stop_words.or(self.options.stop.take());
+        }
+    }
+}
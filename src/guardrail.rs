@@ -0,0 +1,107 @@
+//! Policy checks on messages going to and coming from the model
+//!
+//! A `Guardrail` inspects each message before a request is sent and each reply
+//! after it's received, and can let it through, block it, redact it, or attach
+//! a note to the completion. Guardrails are attached to a `Client` with
+//! `ClientBuilder::guardrail` or to a single `Session` with `Session::add_guardrail`.
+
+use crate::chat::Message;
+use crate::error::{GrokError, Result};
+use async_trait::async_trait;
+use std::fmt;
+use std::sync::Arc;
+
+/// When a message is being checked
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum GuardStage {
+    /// Before the request carrying it is sent
+    Request,
+    /// After the model returned it
+    Response,
+}
+
+impl fmt::Display for GuardStage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            GuardStage::Request => write!(f, "request"),
+            GuardStage::Response => write!(f, "response"),
+        }
+    }
+}
+
+/// What a guardrail decided about a message
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum GuardVerdict {
+    /// Let the message through unchanged
+    Allow,
+    /// Fail the request with `ContentBlocked` and this reason
+    Block(String),
+    /// Let the message through with its content replaced by this
+    Redact(String),
+    /// Let the message through and add this note to the completion's annotations
+    Annotate(String),
+}
+
+/// A policy enforcement point for message content
+#[async_trait]
+pub trait Guardrail: fmt::Debug + Send + Sync {
+    /// Check one message at the given stage
+    async fn check(&self, message: &Message, stage: GuardStage) -> Result<GuardVerdict>;
+}
+
+/// Lets a guardrail be shared, such as a `Redactor` that is also used to restore text
+#[async_trait]
+impl<G: Guardrail + ?Sized> Guardrail for Arc<G> {
+    async fn check(&self, message: &Message, stage: GuardStage) -> Result<GuardVerdict> {
+        (**self).check(message, stage).await
+    }
+}
+
+/// Run `message` through every guardrail in order
+///
+/// Returns the redacted message if any guardrail changed it. Notes are
+/// appended to `annotations`.
+pub(crate) async fn apply(
+    guardrails: &[Arc<dyn Guardrail>],
+    message: &Message,
+    stage: GuardStage,
+    annotations: &mut Vec<String>,
+) -> Result<Option<Message>> {
+    let mut redacted: Option<Message> = None;
+    for guardrail in guardrails {
+        let current = redacted.as_ref().unwrap_or(message);
+        match guardrail.check(current, stage).await? {
+            GuardVerdict::Allow => {}
+            GuardVerdict::Block(reason) => {
+                #[cfg(feature = "tracing")]
+                tracing::warn!(stage = %stage, reason = %reason, "message blocked by guardrail");
+                return Err(GrokError::ContentBlocked { stage, reason });
+            }
+            GuardVerdict::Redact(content) => {
+                let mut message = current.clone();
+                message.content.replace_text(content);
+                redacted = Some(message);
+            }
+            GuardVerdict::Annotate(note) => annotations.push(note),
+        }
+    }
+    Ok(redacted)
+}
+
+/// Check every message of a request, replacing redacted ones
+pub(crate) async fn apply_all(
+    guardrails: &[Arc<dyn Guardrail>],
+    messages: &mut [Arc<Message>],
+    annotations: &mut Vec<String>,
+) -> Result<()> {
+    if guardrails.is_empty() {
+        return Ok(());
+    }
+    for message in messages.iter_mut() {
+        if let Some(redacted) = apply(guardrails, message, GuardStage::Request, annotations).await?
+        {
+            *message = Arc::new(redacted);
+        }
+    }
+    Ok(())
+}
@@ -10,7 +10,7 @@
 //! ## Example
 //!
 //! ```rust,no_run
-//! use grok_rust_sdk::{Client, chat::{Message, Role}};
+//! use grok_rust_sdk::{Client, chat::{Message, Model, Role}};
 //!
 //! #[tokio::main]
 //! async fn main() -> Result<(), Box<dyn std::error::Error>> {
@@ -19,24 +19,93 @@
 //!     let messages = vec![
 //!         Message {
 //!             role: Role::User,
-//!             content: "Hello, Grok!".to_string(),
+//!             content: "Hello, Grok!".into(),
+//!             tool_calls: None,
+//!             tool_call_id: None,
+//!             name: None,
 //!         }
 //!     ];
 //!
-//!     let response = client.chat("grok-4-fast-reasoning", messages, None).await?;
-//!     println!("Response: {}", response.content);
+//!     let response = client.chat(Model::Grok4FastReasoning, messages, None).await?;
+//!     println!("Response: {}", response.message.content);
 //!
 //!     Ok(())
 //! }
 //! ```
 
+pub mod agent;
+pub mod audit;
+#[cfg(feature = "bench")]
+pub mod bench;
+pub mod budget;
+pub mod channel;
 pub mod chat;
+pub mod classify;
 pub mod client;
 pub mod collections;
+#[cfg(feature = "persistence")]
+pub mod dataset;
 pub mod error;
+#[cfg(feature = "eval")]
+pub mod eval;
+pub mod extract;
+#[cfg(any(feature = "rig", feature = "langchain"))]
+pub mod frameworks;
+pub mod guardrail;
+#[cfg(feature = "mcp")]
+pub mod mcp;
+pub mod middleware;
+#[cfg(feature = "openai")]
+pub mod openai;
+pub mod orchestrator;
+#[cfg(feature = "persistence")]
 pub mod persistence;
+#[cfg(feature = "postgres")]
+pub mod postgres;
+pub mod provider;
+pub mod rag;
+pub mod rate_limit;
+#[cfg(feature = "redact")]
+pub mod redact;
+pub mod replay;
+pub mod secrets;
+#[cfg(feature = "axum")]
+pub mod server;
+#[cfg(feature = "tower")]
+pub mod service;
 pub mod session;
+pub mod storage;
 pub mod tools;
+pub mod vcr;
+pub mod vector;
 
 pub use client::Client;
-pub use error::{GrokError, Result};
+pub use error::{
+    ApiError, BoxError, Entity, ErrorKind, GrokError, Result, ResultExt, TimeoutPhase,
+    ValidationError,
+};
+pub use provider::ChatProvider;
+
+#[cfg(feature = "macros")]
+pub use grok_rust_sdk_macros::grok_tool;
+
+/// Re-exports used by code generated by `grok_rust_sdk_macros`
+#[doc(hidden)]
+pub mod __private {
+    pub use async_trait::async_trait;
+    pub use serde_json;
+
+    /// Schema for a tool parameter, with nested types inlined
+    pub fn schema_for<T: schemars::JsonSchema>() -> serde_json::Value {
+        let generator = schemars::gen::SchemaSettings::draft07()
+            .with(|settings| settings.inline_subschemas = true)
+            .into_generator();
+        let mut schema = serde_json::to_value(generator.into_root_schema_for::<T>())
+            .unwrap_or_else(|_| serde_json::json!({}));
+        if let Some(object) = schema.as_object_mut() {
+            object.remove("$schema");
+            object.remove("title");
+        }
+        schema
+    }
+}
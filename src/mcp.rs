@@ -0,0 +1,182 @@
+//! Serve a tool registry over the Model Context Protocol
+//!
+//! Implements the tool subset of MCP (`initialize`, `tools/list`,
+//! `tools/call`) as newline-delimited JSON-RPC 2.0, the framing used by the
+//! stdio transport.
+
+use crate::error::{GrokError, Result};
+use crate::tools::{ToolCall, ToolFunction, ToolRegistry};
+use serde_json::{json, Value};
+use tokio::io::{AsyncBufRead, AsyncBufReadExt, AsyncRead, AsyncWrite, AsyncWriteExt, BufReader};
+
+/// Protocol revision reported when the client doesn't ask for one
+const PROTOCOL_VERSION: &str = "2024-11-05";
+
+/// Byte streams an MCP server reads requests from and writes responses to
+pub struct McpTransport {
+    reader: Box<dyn AsyncBufRead + Unpin + Send>,
+    writer: Box<dyn AsyncWrite + Unpin + Send>,
+}
+
+impl McpTransport {
+    /// Use the process's stdin and stdout, as MCP clients expect of spawned servers
+    pub fn stdio() -> Self {
+        Self::new(tokio::io::stdin(), tokio::io::stdout())
+    }
+
+    /// Use any pair of async streams, e.g. a socket split into halves
+    pub fn new<R, W>(reader: R, writer: W) -> Self
+    where
+        R: AsyncRead + Unpin + Send + 'static,
+        W: AsyncWrite + Unpin + Send + 'static,
+    {
+        Self {
+            reader: Box::new(BufReader::new(reader)),
+            writer: Box::new(writer),
+        }
+    }
+}
+
+impl std::fmt::Debug for McpTransport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("McpTransport").finish_non_exhaustive()
+    }
+}
+
+impl ToolRegistry {
+    /// Offer the registered tools to an MCP client until the transport closes
+    ///
+    /// Tool calls go through `execute_tool_call`, so approval gates apply.
+    /// Tool failures are reported to the client as `isError` results.
+    pub async fn serve_mcp(&self, mut transport: McpTransport) -> Result<()> {
+        let mut line = String::new();
+        loop {
+            line.clear();
+            let read = transport.reader.read_line(&mut line).await.map_err(|e| {
+                GrokError::ToolExecution(format!("Failed to read MCP request: {}", e))
+            })?;
+            if read == 0 {
+                return Ok(());
+            }
+            if line.trim().is_empty() {
+                continue;
+            }
+
+            let Some(response) = self.handle_mcp_message(line.trim()).await else {
+                continue;
+            };
+
+            let mut payload = response.to_string();
+            payload.push('\n');
+            transport
+                .writer
+                .write_all(payload.as_bytes())
+                .await
+                .map_err(|e| {
+                    GrokError::ToolExecution(format!("Failed to write MCP response: {}", e))
+                })?;
+            transport.writer.flush().await.map_err(|e| {
+                GrokError::ToolExecution(format!("Failed to write MCP response: {}", e))
+            })?;
+        }
+    }
+
+    /// Handle one JSON-RPC message, returning the response for requests
+    async fn handle_mcp_message(&self, message: &str) -> Option<Value> {
+        let request: Value = match serde_json::from_str(message) {
+            Ok(request) => request,
+            Err(e) => {
+                return Some(rpc_error(
+                    Value::Null,
+                    -32700,
+                    format!("Parse error: {}", e),
+                ))
+            }
+        };
+
+        // Notifications carry no ID and get no response
+        let id = request.get("id").cloned()?;
+        let params = request.get("params").cloned().unwrap_or(Value::Null);
+
+        let result = match request["method"].as_str().unwrap_or_default() {
+            "initialize" => Ok(json!({
+                "protocolVersion": params["protocolVersion"].as_str().unwrap_or(PROTOCOL_VERSION),
+                "capabilities": { "tools": { "listChanged": false } },
+                "serverInfo": {
+                    "name": env!("CARGO_PKG_NAME"),
+                    "version": env!("CARGO_PKG_VERSION"),
+                },
+            })),
+            "ping" => Ok(json!({})),
+            "tools/list" => Ok(json!({ "tools": self.mcp_tools() })),
+            "tools/call" => self.mcp_call(&id, &params).await,
+            method => Err((-32601, format!("Method not found: {}", method))),
+        };
+
+        Some(match result {
+            Ok(result) => json!({ "jsonrpc": "2.0", "id": id, "result": result }),
+            Err((code, message)) => rpc_error(id, code, message),
+        })
+    }
+
+    fn mcp_tools(&self) -> Vec<Value> {
+        self.api_tools()
+            .into_iter()
+            .map(|tool| {
+                json!({
+                    "name": tool.function.name,
+                    "description": tool.function.description,
+                    "inputSchema": tool.function.parameters,
+                })
+            })
+            .collect()
+    }
+
+    async fn mcp_call(
+        &self,
+        id: &Value,
+        params: &Value,
+    ) -> std::result::Result<Value, (i64, String)> {
+        let name = params["name"]
+            .as_str()
+            .ok_or_else(|| (-32602, "Missing tool name".to_string()))?;
+        if self.get(name).is_none() {
+            return Err((-32602, format!("Unknown tool: {}", name)));
+        }
+
+        let arguments = match &params["arguments"] {
+            Value::Null => json!({}),
+            arguments => arguments.clone(),
+        };
+
+        let call = ToolCall {
+            id: match id {
+                Value::String(id) => id.clone(),
+                other => other.to_string(),
+            },
+            function: ToolFunction {
+                name: name.to_string(),
+                arguments: arguments.to_string(),
+            },
+        };
+
+        // Failures are tool results, so the client's model can see and react to them
+        let (text, is_error) = match self.execute_tool_call(&call).await {
+            Ok(result) => (result.content, result.error.is_some()),
+            Err(e) => (e.to_string(), true),
+        };
+
+        Ok(json!({
+            "content": [{ "type": "text", "text": text }],
+            "isError": is_error,
+        }))
+    }
+}
+
+fn rpc_error(id: Value, code: i64, message: String) -> Value {
+    json!({
+        "jsonrpc": "2.0",
+        "id": id,
+        "error": { "code": code, "message": message },
+    })
+}
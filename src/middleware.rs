@@ -0,0 +1,63 @@
+//! Hooks around the HTTP requests a client sends
+//!
+//! A `RequestInterceptor` sees every request to the API before it's sent and
+//! can change its headers and JSON body, such as to add per-tenant auth
+//! headers. It then sees the response's status, headers and latency, whether
+//! the request succeeded or not. Interceptors are attached with
+//! `ClientBuilder::with_middleware` and run in the order they were added, on
+//! every attempt of a retried request.
+
+use crate::error::Result;
+use async_trait::async_trait;
+use reqwest::header::HeaderMap;
+use std::fmt;
+use std::sync::Arc;
+use std::time::Duration;
+
+/// A request about to be sent
+#[derive(Debug, Clone)]
+pub struct OutgoingRequest {
+    /// API endpoint, such as `/chat/completions`
+    pub endpoint: String,
+    /// Request headers, including the client's authorization
+    pub headers: HeaderMap,
+    /// JSON request body
+    pub body: serde_json::Value,
+}
+
+/// The response to a request, successful or not
+#[derive(Debug, Clone, Copy)]
+pub struct ResponseInfo<'a> {
+    /// API endpoint, such as `/chat/completions`
+    pub endpoint: &'a str,
+    /// HTTP status
+    pub status: u16,
+    /// Response headers, such as `x-request-id`
+    pub headers: &'a HeaderMap,
+    /// Time from sending the request to receiving the response headers
+    pub latency: Duration,
+}
+
+/// Middleware that can change outgoing requests and observe responses
+#[async_trait]
+pub trait RequestInterceptor: fmt::Debug + Send + Sync {
+    /// Change a request before it's sent; an error fails it without sending
+    async fn before_request(&self, _request: &mut OutgoingRequest) -> Result<()> {
+        Ok(())
+    }
+
+    /// Observe the response to a request
+    async fn after_response(&self, _response: &ResponseInfo<'_>) {}
+}
+
+/// Lets an interceptor be shared, such as one that also collects metrics for the caller
+#[async_trait]
+impl<I: RequestInterceptor + ?Sized> RequestInterceptor for Arc<I> {
+    async fn before_request(&self, request: &mut OutgoingRequest) -> Result<()> {
+        (**self).before_request(request).await
+    }
+
+    async fn after_response(&self, response: &ResponseInfo<'_>) {
+        (**self).after_response(response).await
+    }
+}
@@ -0,0 +1,403 @@
+//! Conversions to and from `async-openai` chat types
+//!
+//! Conversions into `async-openai` types are infallible. Conversions back fail
+//! with `InvalidConfig` for content this crate can't represent: audio,
+//! deprecated function messages, or a response without choices.
+
+use crate::chat::{
+    ChatCompletion, ContentPart, ImageDetail, Message, MessageContent, Role, Tool, ToolCall,
+    ToolFunction, ToolSpec, Usage,
+};
+use crate::error::{GrokError, Result};
+use async_openai::types::{
+    ChatChoice, ChatCompletionMessageToolCall, ChatCompletionRequestAssistantMessage,
+    ChatCompletionRequestAssistantMessageContent, ChatCompletionRequestAssistantMessageContentPart,
+    ChatCompletionRequestDeveloperMessageContent, ChatCompletionRequestMessage,
+    ChatCompletionRequestMessageContentPartImage, ChatCompletionRequestMessageContentPartText,
+    ChatCompletionRequestSystemMessage, ChatCompletionRequestSystemMessageContent,
+    ChatCompletionRequestSystemMessageContentPart, ChatCompletionRequestToolMessage,
+    ChatCompletionRequestToolMessageContent, ChatCompletionRequestToolMessageContentPart,
+    ChatCompletionRequestUserMessage, ChatCompletionRequestUserMessageContent,
+    ChatCompletionRequestUserMessageContentPart, ChatCompletionResponseMessage, ChatCompletionTool,
+    ChatCompletionToolType, CompletionUsage, CreateChatCompletionResponse, FinishReason,
+    FunctionCall, FunctionObject, ImageDetail as OpenAiImageDetail, ImageUrl, Role as OpenAiRole,
+};
+
+impl From<Message> for ChatCompletionRequestMessage {
+    /// A tool message without a `tool_call_id` gets an empty one. Images are
+    /// only kept in user messages.
+    fn from(message: Message) -> Self {
+        match message.role {
+            Role::System => {
+                ChatCompletionRequestMessage::System(ChatCompletionRequestSystemMessage {
+                    content: ChatCompletionRequestSystemMessageContent::Text(
+                        message.content.to_string(),
+                    ),
+                    name: message.name,
+                })
+            }
+            Role::User => ChatCompletionRequestMessage::User(ChatCompletionRequestUserMessage {
+                content: match message.content {
+                    MessageContent::Text(text) => {
+                        ChatCompletionRequestUserMessageContent::Text(text)
+                    }
+                    MessageContent::Parts(parts) => ChatCompletionRequestUserMessageContent::Array(
+                        parts.into_iter().map(Into::into).collect(),
+                    ),
+                },
+                name: message.name,
+            }),
+            Role::Assistant => {
+                // Tool-calling turns often have no text
+                let content = Some(message.content.to_string())
+                    .filter(|content| !content.is_empty() || message.tool_calls.is_none())
+                    .map(ChatCompletionRequestAssistantMessageContent::Text);
+                ChatCompletionRequestMessage::Assistant(ChatCompletionRequestAssistantMessage {
+                    content,
+                    name: message.name,
+                    tool_calls: message
+                        .tool_calls
+                        .map(|calls| calls.into_iter().map(Into::into).collect()),
+                    ..Default::default()
+                })
+            }
+            Role::Tool => ChatCompletionRequestMessage::Tool(ChatCompletionRequestToolMessage {
+                content: ChatCompletionRequestToolMessageContent::Text(message.content.to_string()),
+                tool_call_id: message.tool_call_id.unwrap_or_default(),
+            }),
+        }
+    }
+}
+
+impl TryFrom<ChatCompletionRequestMessage> for Message {
+    type Error = GrokError;
+
+    /// Developer messages become system messages; text parts are concatenated
+    fn try_from(message: ChatCompletionRequestMessage) -> Result<Self> {
+        let converted = match message {
+            ChatCompletionRequestMessage::Developer(developer) => {
+                let content = match developer.content {
+                    ChatCompletionRequestDeveloperMessageContent::Text(text) => text,
+                    ChatCompletionRequestDeveloperMessageContent::Array(parts) => join(parts),
+                };
+                text_message(Role::System, content, developer.name)
+            }
+            ChatCompletionRequestMessage::System(system) => {
+                let content = match system.content {
+                    ChatCompletionRequestSystemMessageContent::Text(text) => text,
+                    ChatCompletionRequestSystemMessageContent::Array(parts) => join(
+                        parts
+                            .into_iter()
+                            .map(|ChatCompletionRequestSystemMessageContentPart::Text(part)| part),
+                    ),
+                };
+                text_message(Role::System, content, system.name)
+            }
+            ChatCompletionRequestMessage::User(user) => {
+                let content = match user.content {
+                    ChatCompletionRequestUserMessageContent::Text(text) => text.into(),
+                    ChatCompletionRequestUserMessageContent::Array(parts) => user_content(parts)?,
+                };
+                text_message(Role::User, content, user.name)
+            }
+            ChatCompletionRequestMessage::Assistant(assistant) => {
+                let content = match assistant.content {
+                    Some(ChatCompletionRequestAssistantMessageContent::Text(text)) => text,
+                    Some(ChatCompletionRequestAssistantMessageContent::Array(parts)) => parts
+                        .into_iter()
+                        .map(|part| match part {
+                            ChatCompletionRequestAssistantMessageContentPart::Text(text) => {
+                                text.text
+                            }
+                            ChatCompletionRequestAssistantMessageContentPart::Refusal(refusal) => {
+                                refusal.refusal
+                            }
+                        })
+                        .collect(),
+                    None => assistant.refusal.unwrap_or_default(),
+                };
+                Message {
+                    tool_calls: assistant
+                        .tool_calls
+                        .map(|calls| calls.into_iter().map(Into::into).collect()),
+                    ..text_message(Role::Assistant, content, assistant.name)
+                }
+            }
+            ChatCompletionRequestMessage::Tool(tool) => {
+                let content = match tool.content {
+                    ChatCompletionRequestToolMessageContent::Text(text) => text,
+                    ChatCompletionRequestToolMessageContent::Array(parts) => join(
+                        parts
+                            .into_iter()
+                            .map(|ChatCompletionRequestToolMessageContentPart::Text(part)| part),
+                    ),
+                };
+                Message {
+                    tool_call_id: Some(tool.tool_call_id),
+                    ..text_message(Role::Tool, content, None)
+                }
+            }
+            ChatCompletionRequestMessage::Function(_) => {
+                return Err(GrokError::InvalidConfig(
+                    "Function messages are deprecated; use tool messages".to_string(),
+                ))
+            }
+        };
+        Ok(converted)
+    }
+}
+
+impl From<ChatCompletionResponseMessage> for Message {
+    /// A refusal becomes the content when there's no other text
+    fn from(message: ChatCompletionResponseMessage) -> Self {
+        let role = match message.role {
+            OpenAiRole::System => Role::System,
+            OpenAiRole::User => Role::User,
+            OpenAiRole::Tool | OpenAiRole::Function => Role::Tool,
+            OpenAiRole::Assistant => Role::Assistant,
+        };
+        let content = message.content.or(message.refusal).unwrap_or_default();
+        Message {
+            tool_calls: message
+                .tool_calls
+                .map(|calls| calls.into_iter().map(Into::into).collect()),
+            ..text_message(role, content, None)
+        }
+    }
+}
+
+impl From<ToolCall> for ChatCompletionMessageToolCall {
+    fn from(call: ToolCall) -> Self {
+        ChatCompletionMessageToolCall {
+            id: call.id,
+            r#type: ChatCompletionToolType::Function,
+            function: FunctionCall {
+                name: call.function.name,
+                arguments: call.function.arguments,
+            },
+        }
+    }
+}
+
+impl From<ChatCompletionMessageToolCall> for ToolCall {
+    fn from(call: ChatCompletionMessageToolCall) -> Self {
+        ToolCall {
+            id: call.id,
+            function: ToolFunction {
+                name: call.function.name,
+                arguments: call.function.arguments,
+            },
+        }
+    }
+}
+
+impl From<Tool> for ChatCompletionTool {
+    fn from(tool: Tool) -> Self {
+        ChatCompletionTool {
+            r#type: ChatCompletionToolType::Function,
+            function: FunctionObject {
+                name: tool.function.name,
+                description: tool.function.description,
+                parameters: tool.function.parameters,
+                strict: tool.function.strict,
+            },
+        }
+    }
+}
+
+impl From<ChatCompletionTool> for Tool {
+    fn from(tool: ChatCompletionTool) -> Self {
+        Tool {
+            tool_type: "function".to_string(),
+            function: ToolSpec {
+                name: tool.function.name,
+                description: tool.function.description,
+                parameters: tool.function.parameters,
+                strict: tool.function.strict,
+            },
+        }
+    }
+}
+
+impl From<Usage> for CompletionUsage {
+    fn from(usage: Usage) -> Self {
+        CompletionUsage {
+            prompt_tokens: usage.prompt_tokens,
+            completion_tokens: usage.completion_tokens,
+            total_tokens: usage.total_tokens,
+            ..Default::default()
+        }
+    }
+}
+
+impl From<CompletionUsage> for Usage {
+    fn from(usage: CompletionUsage) -> Self {
+        Usage {
+            prompt_tokens: usage.prompt_tokens,
+            completion_tokens: usage.completion_tokens,
+            total_tokens: usage.total_tokens,
+        }
+    }
+}
+
+impl From<ChatCompletion> for CreateChatCompletionResponse {
+    /// `created` is set to the time of conversion, which the completion doesn't record
+    fn from(completion: ChatCompletion) -> Self {
+        let finish_reason = completion
+            .finish_reason
+            .as_deref()
+            .and_then(|reason| match reason {
+                "stop" => Some(FinishReason::Stop),
+                "length" => Some(FinishReason::Length),
+                "tool_calls" => Some(FinishReason::ToolCalls),
+                "content_filter" => Some(FinishReason::ContentFilter),
+                "function_call" => Some(FinishReason::FunctionCall),
+                _ => None,
+            });
+        let message = completion.message;
+
+        #[allow(deprecated)]
+        let message = ChatCompletionResponseMessage {
+            content: Some(message.content.to_string()),
+            refusal: None,
+            tool_calls: message
+                .tool_calls
+                .map(|calls| calls.into_iter().map(Into::into).collect()),
+            role: OpenAiRole::Assistant,
+            function_call: None,
+            audio: None,
+        };
+
+        CreateChatCompletionResponse {
+            id: completion.id,
+            choices: vec![ChatChoice {
+                index: 0,
+                message,
+                finish_reason,
+                logprobs: None,
+            }],
+            created: chrono::Utc::now().timestamp() as u32,
+            model: completion.model,
+            service_tier: None,
+            system_fingerprint: None,
+            object: "chat.completion".to_string(),
+            usage: completion.usage.map(Into::into),
+        }
+    }
+}
+
+impl TryFrom<CreateChatCompletionResponse> for ChatCompletion {
+    type Error = GrokError;
+
+    /// Uses the first choice; fails if there are none
+    fn try_from(response: CreateChatCompletionResponse) -> Result<Self> {
+        let choice = response.choices.into_iter().next().ok_or_else(|| {
+            GrokError::InvalidConfig("Completion response has no choices".to_string())
+        })?;
+        let finish_reason = choice.finish_reason.map(|reason| {
+            match reason {
+                FinishReason::Stop => "stop",
+                FinishReason::Length => "length",
+                FinishReason::ToolCalls => "tool_calls",
+                FinishReason::ContentFilter => "content_filter",
+                FinishReason::FunctionCall => "function_call",
+            }
+            .to_string()
+        });
+
+        Ok(ChatCompletion {
+            id: response.id,
+            model: response.model,
+            usage: response.usage.map(Into::into),
+            message: choice.message.into(),
+            finish_reason,
+            timings: None,
+            annotations: Vec::new(),
+            rate_limit: None,
+        })
+    }
+}
+
+fn text_message(role: Role, content: impl Into<MessageContent>, name: Option<String>) -> Message {
+    Message {
+        role,
+        content: content.into(),
+        tool_calls: None,
+        tool_call_id: None,
+        name,
+    }
+}
+
+/// Content of a user message, as parts only if it has images; audio can't be held here
+fn user_content(parts: Vec<ChatCompletionRequestUserMessageContentPart>) -> Result<MessageContent> {
+    let mut converted = Vec::with_capacity(parts.len());
+    for part in parts {
+        converted.push(match part {
+            ChatCompletionRequestUserMessageContentPart::Text(text) => ContentPart::Text(text.text),
+            ChatCompletionRequestUserMessageContentPart::ImageUrl(image) => {
+                let part = ContentPart::image_url(image.image_url.url);
+                match image.image_url.detail {
+                    Some(detail) => part.with_detail(detail.into()),
+                    None => part,
+                }
+            }
+            ChatCompletionRequestUserMessageContentPart::InputAudio(_) => {
+                return Err(GrokError::InvalidConfig(
+                    "Audio content can't be converted to a Message".to_string(),
+                ))
+            }
+        });
+    }
+
+    let mut texts = Vec::with_capacity(converted.len());
+    for part in &converted {
+        match part {
+            ContentPart::Text(text) => texts.push(text.as_str()),
+            _ => return Ok(MessageContent::Parts(converted)),
+        }
+    }
+    Ok(MessageContent::Text(texts.concat()))
+}
+
+impl From<ContentPart> for ChatCompletionRequestUserMessageContentPart {
+    /// Base64 images become `data:` URLs
+    fn from(part: ContentPart) -> Self {
+        match part {
+            ContentPart::Text(text) => ChatCompletionRequestUserMessageContentPart::Text(
+                ChatCompletionRequestMessageContentPartText { text },
+            ),
+            image => ChatCompletionRequestUserMessageContentPart::ImageUrl(
+                ChatCompletionRequestMessageContentPartImage {
+                    image_url: ImageUrl {
+                        url: image.url().unwrap_or_default().into_owned(),
+                        detail: image.detail().map(Into::into),
+                    },
+                },
+            ),
+        }
+    }
+}
+
+impl From<ImageDetail> for OpenAiImageDetail {
+    fn from(detail: ImageDetail) -> Self {
+        match detail {
+            ImageDetail::Auto => OpenAiImageDetail::Auto,
+            ImageDetail::Low => OpenAiImageDetail::Low,
+            ImageDetail::High => OpenAiImageDetail::High,
+        }
+    }
+}
+
+impl From<OpenAiImageDetail> for ImageDetail {
+    fn from(detail: OpenAiImageDetail) -> Self {
+        match detail {
+            OpenAiImageDetail::Auto => ImageDetail::Auto,
+            OpenAiImageDetail::Low => ImageDetail::Low,
+            OpenAiImageDetail::High => ImageDetail::High,
+        }
+    }
+}
+
+fn join(parts: impl IntoIterator<Item = ChatCompletionRequestMessageContentPartText>) -> String {
+    parts.into_iter().map(|part| part.text).collect()
+}
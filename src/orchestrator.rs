@@ -0,0 +1,300 @@
+//! Multi-agent orchestration over a shared transcript
+//!
+//! An `Orchestrator` holds named agents, each with its own model, system
+//! prompt and tools, and a transcript that every agent sees when it's asked
+//! to speak. Agents exchange messages with `send`, or take turns in one of
+//! the built-in patterns: a round-robin `debate`, `plan_and_execute` with a
+//! planner and an executor, and `supervise`, where one agent routes work to
+//! the others.
+
+use crate::agent::{Agent, AgentRun};
+use crate::error::{Entity, GrokError, Result};
+use serde::Serialize;
+use std::fmt::Write;
+
+/// Sender name for messages that come from outside the orchestrator
+pub const USER: &str = "user";
+
+/// One message in the shared transcript
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct TranscriptEntry {
+    /// Agent that wrote the message, or `USER`
+    pub from: String,
+    /// Agent the message is addressed to; `None` for everyone
+    pub to: Option<String>,
+    /// Message text
+    pub content: String,
+}
+
+/// Named agents taking turns on a shared transcript
+#[derive(Debug, Default)]
+pub struct Orchestrator {
+    agents: Vec<(String, Agent)>,
+    transcript: Vec<TranscriptEntry>,
+    total_tokens: u64,
+}
+
+impl Orchestrator {
+    /// Create an orchestrator with no agents
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add an agent under `name`, replacing any agent with the same name
+    ///
+    /// Agents speak in the order they were added in round-robin patterns.
+    pub fn with_agent(mut self, name: impl Into<String>, agent: Agent) -> Self {
+        let name = name.into();
+        match self
+            .agents
+            .iter_mut()
+            .find(|(existing, _)| *existing == name)
+        {
+            Some((_, existing)) => *existing = agent,
+            None => self.agents.push((name, agent)),
+        }
+        self
+    }
+
+    /// Names of the agents, in the order they were added
+    pub fn agent_names(&self) -> impl Iterator<Item = &str> {
+        self.agents.iter().map(|(name, _)| name.as_str())
+    }
+
+    /// Messages exchanged so far
+    pub fn transcript(&self) -> &[TranscriptEntry] {
+        &self.transcript
+    }
+
+    /// Tokens used by every agent run so far
+    pub fn total_tokens(&self) -> u64 {
+        self.total_tokens
+    }
+
+    /// Add a message to the transcript without running any agent
+    pub fn post(&mut self, from: impl Into<String>, to: Option<&str>, content: impl Into<String>) {
+        self.transcript.push(TranscriptEntry {
+            from: from.into(),
+            to: to.map(str::to_string),
+            content: content.into(),
+        });
+    }
+
+    /// Clear the transcript, keeping the agents
+    pub fn reset(&mut self) {
+        self.transcript.clear();
+    }
+
+    /// Send a message to an agent and record its reply, addressed back to the sender
+    ///
+    /// Returns the agent's run, including its tool calls and token usage.
+    pub async fn send(
+        &mut self,
+        from: &str,
+        to: &str,
+        content: impl Into<String>,
+    ) -> Result<AgentRun> {
+        self.agent(to)?;
+        self.post(from, Some(to), content);
+        let run = self
+            .run_agent(to, "Reply to the last message addressed to you.")
+            .await?;
+        self.post(to, Some(from), reply(&run));
+        Ok(run)
+    }
+
+    /// Have an agent speak to everyone, following `instruction`
+    pub async fn turn(&mut self, name: &str, instruction: &str) -> Result<String> {
+        let run = self.run_agent(name, instruction).await?;
+        let content = reply(&run);
+        self.post(name, None, content.clone());
+        Ok(content)
+    }
+
+    /// Have every agent respond to the topic in turn, for `rounds` rounds
+    ///
+    /// Returns the last statement made.
+    pub async fn debate(&mut self, topic: impl Into<String>, rounds: usize) -> Result<String> {
+        self.post(USER, None, topic);
+        let names: Vec<String> = self.agent_names().map(str::to_string).collect();
+        let mut last = String::new();
+        for _ in 0..rounds {
+            for name in &names {
+                last = self
+                    .turn(
+                        name,
+                        "Respond to the discussion, building on or challenging the other participants' points.",
+                    )
+                    .await?;
+            }
+        }
+        Ok(last)
+    }
+
+    /// Have `planner` break the task into steps, `executor` carry out each
+    /// one, and `planner` combine the results into a final answer
+    ///
+    /// The plan is split into steps on numbered or bulleted lines; a plan
+    /// without any is handed over as a single step.
+    pub async fn plan_and_execute(
+        &mut self,
+        planner: &str,
+        executor: &str,
+        task: impl Into<String>,
+    ) -> Result<String> {
+        self.agent(executor)?;
+        self.post(USER, Some(planner), task);
+        let plan = self
+            .turn(
+                planner,
+                "Write a numbered plan of steps for the executor to carry out. Reply with the plan only.",
+            )
+            .await?;
+
+        let mut steps = plan_steps(&plan);
+        if steps.is_empty() {
+            steps.push(plan);
+        }
+        for step in steps {
+            self.send(planner, executor, step).await?;
+        }
+
+        self.turn(
+            planner,
+            "Using the executor's results, give the final answer to the user's task.",
+        )
+        .await
+    }
+
+    /// Have `supervisor` hand work to the other agents until it gives a final answer
+    ///
+    /// The supervisor replies `NEXT <agent>: <instruction>` to route work,
+    /// or `FINISH: <answer>` when it's done; any other reply is taken as the
+    /// answer. Returns `None` if there's still no answer after `max_turns`
+    /// routing decisions.
+    pub async fn supervise(
+        &mut self,
+        supervisor: &str,
+        task: impl Into<String>,
+        max_turns: usize,
+    ) -> Result<Option<String>> {
+        let workers: Vec<String> = self
+            .agent_names()
+            .filter(|name| *name != supervisor)
+            .map(str::to_string)
+            .collect();
+        let instruction = format!(
+            "You supervise these agents: {}. Reply with `NEXT <agent>: <instruction>` to hand the next piece of work to one of them, or `FINISH: <answer>` once the user's task is done.",
+            workers.join(", ")
+        );
+
+        self.post(USER, Some(supervisor), task);
+        for _ in 0..max_turns {
+            let run = self.run_agent(supervisor, &instruction).await?;
+            let decision = reply(&run);
+            match parse_route(&decision) {
+                Route::Next { agent, instruction } => {
+                    if !workers.iter().any(|worker| worker == agent) {
+                        return Err(GrokError::NotFound {
+                            entity: Entity::Agent,
+                            id: agent.to_string(),
+                        });
+                    }
+                    self.send(supervisor, agent, instruction).await?;
+                }
+                Route::Finish(answer) => {
+                    let answer = answer.to_string();
+                    self.post(supervisor, Some(USER), answer.clone());
+                    return Ok(Some(answer));
+                }
+            }
+        }
+        Ok(None)
+    }
+
+    fn agent(&self, name: &str) -> Result<&Agent> {
+        self.agents
+            .iter()
+            .find(|(existing, _)| existing == name)
+            .map(|(_, agent)| agent)
+            .ok_or_else(|| GrokError::NotFound {
+                entity: Entity::Agent,
+                id: name.to_string(),
+            })
+    }
+
+    /// Run an agent on the transcript without recording its reply
+    async fn run_agent(&mut self, name: &str, instruction: &str) -> Result<AgentRun> {
+        let prompt = self.prompt(name, instruction);
+        let run = self.agent(name)?.run(prompt).await?;
+        self.total_tokens += run.total_tokens;
+        Ok(run)
+    }
+
+    /// The transcript as seen by `name`, followed by what it should do next
+    fn prompt(&self, name: &str, instruction: &str) -> String {
+        let mut prompt = String::new();
+        if !self.transcript.is_empty() {
+            prompt.push_str("Transcript so far:\n");
+            for entry in &self.transcript {
+                let _ = match &entry.to {
+                    Some(to) => writeln!(prompt, "[{} → {}]: {}", entry.from, to, entry.content),
+                    None => writeln!(prompt, "[{}]: {}", entry.from, entry.content),
+                };
+            }
+            prompt.push('\n');
+        }
+        let _ = write!(prompt, "You are {}. {}", name, instruction);
+        prompt
+    }
+}
+
+/// The agent's final answer, or its last message if it stopped early
+fn reply(run: &AgentRun) -> String {
+    run.output
+        .clone()
+        .or_else(|| {
+            run.steps
+                .last()
+                .map(|step| step.message.content.to_string())
+        })
+        .unwrap_or_default()
+}
+
+/// The numbered or bulleted lines of a plan, without their markers
+fn plan_steps(plan: &str) -> Vec<String> {
+    plan.lines()
+        .filter_map(|line| {
+            let line = line.trim();
+            let digits = line.len() - line.trim_start_matches(|c: char| c.is_ascii_digit()).len();
+            let rest = if digits > 0 {
+                line[digits..].strip_prefix(['.', ')'])?
+            } else {
+                line.strip_prefix(['-', '*'])?
+            };
+            let rest = rest.trim();
+            (!rest.is_empty()).then(|| rest.to_string())
+        })
+        .collect()
+}
+
+enum Route<'a> {
+    Next {
+        agent: &'a str,
+        instruction: &'a str,
+    },
+    Finish(&'a str),
+}
+
+fn parse_route(decision: &str) -> Route<'_> {
+    let decision = decision.trim();
+    if let Some(rest) = decision.strip_prefix("NEXT ") {
+        if let Some((agent, instruction)) = rest.split_once(':') {
+            return Route::Next {
+                agent: agent.trim(),
+                instruction: instruction.trim(),
+            };
+        }
+    }
+    Route::Finish(decision.strip_prefix("FINISH:").unwrap_or(decision).trim())
+}
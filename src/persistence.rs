@@ -1,89 +1,624 @@
 //! Persistence layer for storing sessions and collections in SQLite
 
-use crate::error::{GrokError, Result};
-use crate::session::{Session, SessionManager};
+use crate::audit::{AuditRecord, AuditSink};
+pub use crate::budget::ModelPricing;
+use crate::chat::{ChatCompletion, Message, MessageContent, Model, Role, Tool};
 use crate::collections::CollectionManager;
-use rusqlite::{Connection, params, OptionalExtension};
-use std::path::Path;
-use std::sync::Arc;
-use tokio::sync::RwLock;
+use crate::error::{Entity, GrokError, Result};
+use crate::session::{Session, SessionManager};
+use crate::storage::Storage;
+use crate::tools::ToolRegistry;
+use crate::vector::{MetadataFilter, VectorMatch, VectorRecord, VectorStore};
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use futures::Stream;
+use rusqlite::backup::Backup;
+use rusqlite::{params, params_from_iter, Connection, OpenFlags, OptionalExtension, ToSql};
+use std::borrow::Borrow;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::ops::{Bound, RangeBounds};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, PoisonError};
+use std::time::Duration;
+use tokio::sync::Mutex;
+
+/// Schema migrations, applied in order on open
+///
+/// The number of applied migrations is tracked in `PRAGMA user_version`.
+const MIGRATIONS: &[&str] = &[
+    // 1: summary columns for listing sessions without loading their messages
+    "ALTER TABLE sessions ADD COLUMN title TEXT;
+     ALTER TABLE sessions ADD COLUMN updated_at TEXT;
+     ALTER TABLE sessions ADD COLUMN message_count INTEGER NOT NULL DEFAULT 0;
+     UPDATE sessions SET updated_at = created_at, message_count = json_array_length(messages);
+     ALTER TABLE collections ADD COLUMN tags TEXT NOT NULL DEFAULT '[]';",
+    // 2: remaining collection metadata so it round-trips through storage;
+    // sessions store no token counts, so `total_tokens` starts at 0
+    "ALTER TABLE collections ADD COLUMN updated_at TEXT;
+     ALTER TABLE collections ADD COLUMN session_count INTEGER NOT NULL DEFAULT 0;
+     ALTER TABLE collections ADD COLUMN total_messages INTEGER NOT NULL DEFAULT 0;
+     ALTER TABLE collections ADD COLUMN total_tokens INTEGER NOT NULL DEFAULT 0;
+     UPDATE collections SET
+         updated_at = created_at,
+         session_count = (SELECT COUNT(*) FROM collection_sessions cs WHERE cs.collection_id = collections.id),
+         total_messages = (SELECT COALESCE(SUM(s.message_count), 0)
+                           FROM collection_sessions cs JOIN sessions s ON s.id = cs.session_id
+                           WHERE cs.collection_id = collections.id);",
+    // 3: tool specifications declared on each session
+    "ALTER TABLE sessions ADD COLUMN tools TEXT NOT NULL DEFAULT '[]';",
+    // 4: full-text index over message content
+    "CREATE VIRTUAL TABLE message_fts USING fts5(
+         content,
+         session_id UNINDEXED,
+         message_index UNINDEXED,
+         role UNINDEXED
+     );
+     INSERT INTO message_fts (content, session_id, message_index, role)
+         SELECT json_extract(m.value, '$.content'), s.id, m.key, json_extract(m.value, '$.role')
+         FROM sessions s, json_each(s.messages) m;",
+    // 5: per-completion usage history
+    "CREATE TABLE usage_records (
+         id INTEGER PRIMARY KEY AUTOINCREMENT,
+         recorded_at TEXT NOT NULL,
+         session_id TEXT,
+         model TEXT NOT NULL,
+         prompt_tokens INTEGER NOT NULL,
+         completion_tokens INTEGER NOT NULL,
+         total_tokens INTEGER NOT NULL,
+         estimated_cost REAL,
+         latency_ms INTEGER NOT NULL
+     );
+     CREATE INDEX idx_usage_records_recorded_at ON usage_records (recorded_at);
+     CREATE INDEX idx_usage_records_session_id ON usage_records (session_id);",
+    // 6: normalized per-message rows, backfilled from the history blobs
+    "CREATE TABLE messages (
+         session_id TEXT NOT NULL,
+         idx INTEGER NOT NULL,
+         role TEXT NOT NULL,
+         content TEXT NOT NULL,
+         tool_calls TEXT,
+         tool_call_id TEXT,
+         name TEXT,
+         created_at TEXT NOT NULL,
+         PRIMARY KEY (session_id, idx),
+         FOREIGN KEY (session_id) REFERENCES sessions(id) ON DELETE CASCADE
+     );
+     INSERT INTO messages (session_id, idx, role, content, tool_calls, tool_call_id, name, created_at)
+         SELECT s.id, m.key, json_extract(m.value, '$.role'), json_extract(m.value, '$.content'),
+                json_extract(m.value, '$.tool_calls'), json_extract(m.value, '$.tool_call_id'),
+                json_extract(m.value, '$.name'), COALESCE(s.updated_at, s.created_at)
+         FROM sessions s, json_each(s.messages) m;
+     UPDATE sessions SET messages = '[]';",
+    // 7: change feed populated by triggers, so writes from every process are captured
+    "CREATE TABLE changelog (
+         seq INTEGER PRIMARY KEY AUTOINCREMENT,
+         entity TEXT NOT NULL,
+         entity_id TEXT NOT NULL,
+         operation TEXT NOT NULL,
+         detail TEXT,
+         changed_at TEXT NOT NULL DEFAULT (strftime('%Y-%m-%dT%H:%M:%fZ', 'now'))
+     );
+     CREATE TRIGGER changelog_session_insert AFTER INSERT ON sessions BEGIN
+         INSERT INTO changelog (entity, entity_id, operation) VALUES ('session', NEW.id, 'insert');
+     END;
+     CREATE TRIGGER changelog_session_update AFTER UPDATE ON sessions BEGIN
+         INSERT INTO changelog (entity, entity_id, operation) VALUES ('session', NEW.id, 'update');
+     END;
+     CREATE TRIGGER changelog_session_delete AFTER DELETE ON sessions BEGIN
+         INSERT INTO changelog (entity, entity_id, operation) VALUES ('session', OLD.id, 'delete');
+     END;
+     CREATE TRIGGER changelog_message_insert AFTER INSERT ON messages BEGIN
+         INSERT INTO changelog (entity, entity_id, operation, detail)
+             VALUES ('message', NEW.session_id, 'insert', CAST(NEW.idx AS TEXT));
+     END;
+     CREATE TRIGGER changelog_collection_insert AFTER INSERT ON collections BEGIN
+         INSERT INTO changelog (entity, entity_id, operation) VALUES ('collection', NEW.id, 'insert');
+     END;
+     CREATE TRIGGER changelog_collection_update AFTER UPDATE ON collections BEGIN
+         INSERT INTO changelog (entity, entity_id, operation) VALUES ('collection', NEW.id, 'update');
+     END;
+     CREATE TRIGGER changelog_collection_delete AFTER DELETE ON collections BEGIN
+         INSERT INTO changelog (entity, entity_id, operation) VALUES ('collection', OLD.id, 'delete');
+     END;
+     CREATE TRIGGER changelog_collection_session_insert AFTER INSERT ON collection_sessions BEGIN
+         INSERT INTO changelog (entity, entity_id, operation, detail)
+             VALUES ('collection_session', NEW.collection_id, 'insert', NEW.session_id);
+     END;
+     CREATE TRIGGER changelog_collection_session_delete AFTER DELETE ON collection_sessions BEGIN
+         INSERT INTO changelog (entity, entity_id, operation, detail)
+             VALUES ('collection_session', OLD.collection_id, 'delete', OLD.session_id);
+     END;",
+    // 8: document chunks, their embeddings and free-form metadata for vector store filters
+    "CREATE TABLE rag_chunks (
+         id TEXT PRIMARY KEY,
+         text TEXT NOT NULL,
+         embedding BLOB NOT NULL,
+         metadata TEXT NOT NULL DEFAULT '{}'
+     );",
+    // 9: optional quality rating for curating training data
+    "ALTER TABLE sessions ADD COLUMN rating INTEGER;",
+    // 10: raw request/response audit trail
+    "CREATE TABLE audit_log (
+         id INTEGER PRIMARY KEY AUTOINCREMENT,
+         exchange_id TEXT NOT NULL,
+         request_id TEXT,
+         endpoint TEXT NOT NULL,
+         kind TEXT NOT NULL,
+         status INTEGER,
+         sequence INTEGER NOT NULL,
+         body TEXT NOT NULL,
+         recorded_at TEXT NOT NULL
+     );
+     CREATE INDEX idx_audit_log_exchange ON audit_log(exchange_id);
+     CREATE INDEX idx_audit_log_recorded_at ON audit_log(recorded_at);",
+    // 11: versioned prompt templates, and the version each session started from
+    "CREATE TABLE prompts (
+         name TEXT NOT NULL,
+         version INTEGER NOT NULL,
+         template TEXT NOT NULL,
+         description TEXT,
+         created_at TEXT NOT NULL,
+         PRIMARY KEY (name, version)
+     );
+     CREATE TABLE prompt_heads (
+         name TEXT PRIMARY KEY,
+         version INTEGER NOT NULL
+     );
+     ALTER TABLE sessions ADD COLUMN prompt TEXT;",
+    // 12: assistant replies cut off mid-stream
+    "ALTER TABLE messages ADD COLUMN interrupted INTEGER NOT NULL DEFAULT 0;",
+    // 13: tenant scoping for sessions and collections
+    "ALTER TABLE sessions ADD COLUMN tenant TEXT;
+     ALTER TABLE collections ADD COLUMN tenant TEXT;
+     CREATE INDEX idx_sessions_tenant ON sessions(tenant);
+     CREATE INDEX idx_collections_tenant ON collections(tenant);",
+    // 14: text and image parts of multimodal messages; `content` keeps their text
+    "ALTER TABLE messages ADD COLUMN content_parts TEXT;",
+    // 15: change feed entries for messages rewritten in place or removed
+    "CREATE TRIGGER changelog_message_update AFTER UPDATE ON messages BEGIN
+         INSERT INTO changelog (entity, entity_id, operation, detail)
+             VALUES ('message', NEW.session_id, 'update', CAST(NEW.idx AS TEXT));
+     END;
+     CREATE TRIGGER changelog_message_delete AFTER DELETE ON messages BEGIN
+         INSERT INTO changelog (entity, entity_id, operation, detail)
+             VALUES ('message', OLD.session_id, 'delete', CAST(OLD.idx AS TEXT));
+     END;",
+];
 
 /// SQLite-based storage for sessions and collections
 #[derive(Debug)]
 pub struct SqliteStorage {
-    conn: Arc<RwLock<Connection>>,
+    conn: Arc<Mutex<Connection>>,
+    pricing: HashMap<String, ModelPricing>,
+    message_storage: MessageStorage,
+    /// Message rows last written for each session, by history version
+    written: std::sync::Mutex<HashMap<String, WrittenHistory>>,
+}
+
+/// The message rows a save wrote for a session's history
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct WrittenHistory {
+    /// `Session::history_version` of the saved history
+    version: u64,
+    /// Number of messages written
+    len: usize,
 }
 
 impl SqliteStorage {
     /// Create a new SQLite storage instance
     pub fn new<P: AsRef<Path>>(path: P) -> Result<Self> {
-        let conn = Connection::open(path).map_err(|e| GrokError::Session(format!("Failed to open database: {}", e)))?;
+        SqliteStorageBuilder::new().path(path.as_ref()).build()
+    }
 
-        // Create tables
-        conn.execute(
-            "CREATE TABLE IF NOT EXISTS sessions (
-                id TEXT PRIMARY KEY,
-                model TEXT NOT NULL,
-                created_at TEXT NOT NULL,
-                messages TEXT NOT NULL
-            )",
-            [],
-        ).map_err(|e| GrokError::Session(format!("Failed to create sessions table: {}", e)))?;
+    /// Create an in-memory SQLite storage (for testing)
+    pub fn in_memory() -> Result<Self> {
+        SqliteStorageBuilder::new().in_memory().build()
+    }
 
-        conn.execute(
-            "CREATE TABLE IF NOT EXISTS collections (
-                id TEXT PRIMARY KEY,
-                name TEXT NOT NULL,
-                description TEXT,
-                created_at TEXT NOT NULL
-            )",
-            [],
-        ).map_err(|e| GrokError::Collection(format!("Failed to create collections table: {}", e)))?;
+    /// Create a builder for tuning connection pragmas
+    pub fn builder() -> SqliteStorageBuilder {
+        SqliteStorageBuilder::new()
+    }
 
-        conn.execute(
-            "CREATE TABLE IF NOT EXISTS collection_sessions (
-                collection_id TEXT NOT NULL,
-                session_id TEXT NOT NULL,
-                added_at TEXT NOT NULL,
-                PRIMARY KEY (collection_id, session_id),
-                FOREIGN KEY (collection_id) REFERENCES collections(id) ON DELETE CASCADE,
-                FOREIGN KEY (session_id) REFERENCES sessions(id) ON DELETE CASCADE
-            )",
+    /// Restore a backup into the database at `path` and open it
+    ///
+    /// The backup is integrity-checked first, then copied over the target
+    /// database with SQLite's online backup API, replacing its contents.
+    /// Backups taken by older versions are migrated to the current schema.
+    /// Use `SqliteStorageBuilder::restore_from` to restore an encrypted
+    /// database or to keep custom pragmas.
+    pub fn restore_from<P: AsRef<Path>, Q: AsRef<Path>>(backup_path: P, path: Q) -> Result<Self> {
+        SqliteStorageBuilder::new()
+            .path(path.as_ref())
+            .restore_from(backup_path)
+    }
+
+    /// Write a consistent copy of the database to `path`
+    ///
+    /// Uses `VACUUM INTO`, so it is safe to call while the storage is in use
+    /// and the copy is compacted. The target file must not already exist.
+    pub async fn backup_to<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        let path = path.as_ref();
+        if path.exists() {
+            return Err(GrokError::InvalidConfig(format!(
+                "Backup target '{}' already exists",
+                path.display()
+            )));
+        }
+
+        let conn = self.conn.lock().await;
+        conn.execute("VACUUM INTO ?1", params![path.to_string_lossy()])
+            .map_err(|e| GrokError::session("back up database", e))?;
+
+        Ok(())
+    }
+
+    /// Run SQLite's integrity check on the database
+    ///
+    /// Returns the problems found, or an empty list if the database is healthy.
+    pub async fn integrity_check(&self) -> Result<Vec<String>> {
+        let conn = self.conn.lock().await;
+        Self::run_integrity_check(&conn)
+    }
+
+    fn run_integrity_check(conn: &Connection) -> Result<Vec<String>> {
+        let mut stmt = conn
+            .prepare("PRAGMA integrity_check")
+            .map_err(|e| GrokError::session("prepare statement", e))?;
+
+        let rows = stmt
+            .query_map([], |row| row.get::<_, String>(0))
+            .and_then(|rows| rows.collect::<std::result::Result<Vec<_>, _>>())
+            .map_err(|e| GrokError::session("run integrity check", e))?;
+
+        Ok(rows.into_iter().filter(|row| row != "ok").collect())
+    }
+
+    /// Report database size, row counts, the largest sessions and index health
+    pub async fn stats(&self) -> Result<StorageStats> {
+        let conn = self.conn.lock().await;
+        let map_err = |e: rusqlite::Error| GrokError::session("read storage stats", e);
+
+        let page_size: i64 = conn
+            .query_row("PRAGMA page_size", [], |row| row.get(0))
+            .map_err(map_err)?;
+        let page_count: i64 = conn
+            .query_row("PRAGMA page_count", [], |row| row.get(0))
+            .map_err(map_err)?;
+        let free_pages: i64 = conn
+            .query_row("PRAGMA freelist_count", [], |row| row.get(0))
+            .map_err(map_err)?;
+
+        // FTS shadow tables are internal to the index and not worth listing
+        let tables: Vec<String> = conn
+            .prepare(
+                "SELECT name FROM sqlite_master
+                 WHERE type = 'table' AND name NOT LIKE 'sqlite_%' AND name NOT LIKE 'message_fts_%'
+                 ORDER BY name",
+            )
+            .and_then(|mut stmt| stmt.query_map([], |row| row.get(0))?.collect())
+            .map_err(map_err)?;
+
+        let mut table_rows = Vec::with_capacity(tables.len());
+        for table in tables {
+            let rows: i64 = conn
+                .query_row(
+                    &format!("SELECT COUNT(*) FROM \"{}\"", table.replace('"', "\"\"")),
+                    [],
+                    |row| row.get(0),
+                )
+                .map_err(map_err)?;
+            table_rows.push((table, rows as u64));
+        }
+
+        let largest_sessions = conn
+            .prepare(
+                "SELECT s.id, s.message_count,
+                        length(s.messages) + length(s.tools) + COALESCE((
+                            SELECT SUM(length(m.content) + COALESCE(length(m.tool_calls), 0))
+                            FROM messages m WHERE m.session_id = s.id
+                        ), 0) AS bytes
+                 FROM sessions s ORDER BY bytes DESC LIMIT 10",
+            )
+            .and_then(|mut stmt| {
+                stmt.query_map([], |row| {
+                    Ok(SessionSize {
+                        session_id: row.get(0)?,
+                        message_count: row.get::<_, i64>(1)? as usize,
+                        bytes: row.get::<_, i64>(2)? as u64,
+                    })
+                })?
+                .collect()
+            })
+            .map_err(map_err)?;
+
+        let indexes = conn
+            .prepare(
+                "SELECT name, tbl_name FROM sqlite_master
+                 WHERE type = 'index' AND name NOT LIKE 'sqlite_%'
+                 ORDER BY tbl_name, name",
+            )
+            .and_then(|mut stmt| {
+                stmt.query_map([], |row| {
+                    Ok(IndexStats {
+                        name: row.get(0)?,
+                        table: row.get(1)?,
+                    })
+                })?
+                .collect()
+            })
+            .map_err(map_err)?;
+
+        let indexed_messages: i64 = conn
+            .query_row("SELECT COUNT(*) FROM message_fts", [], |row| row.get(0))
+            .map_err(map_err)?;
+        let stored_messages: i64 = conn
+            .query_row(
+                "SELECT COALESCE(SUM(message_count), 0) FROM sessions",
+                [],
+                |row| row.get(0),
+            )
+            .map_err(map_err)?;
+
+        Ok(StorageStats {
+            file_size: (page_size * page_count) as u64,
+            free_bytes: (page_size * free_pages) as u64,
+            table_rows,
+            largest_sessions,
+            indexes,
+            search_index_in_sync: indexed_messages == stored_messages,
+        })
+    }
+
+    /// Run every consistency check: SQLite integrity, foreign keys, the search
+    /// index and stored message counts
+    pub async fn verify(&self) -> Result<VerifyReport> {
+        let conn = self.conn.lock().await;
+        let map_err = |e: rusqlite::Error| GrokError::session("verify storage", e);
+
+        let integrity_errors = Self::run_integrity_check(&conn)?;
+
+        let foreign_key_violations = conn
+            .prepare("PRAGMA foreign_key_check")
+            .and_then(|mut stmt| {
+                stmt.query_map([], |row| {
+                    let table: String = row.get(0)?;
+                    let rowid: Option<i64> = row.get(1)?;
+                    let parent: String = row.get(2)?;
+                    Ok(format!(
+                        "{} row {:?} references missing {}",
+                        table, rowid, parent
+                    ))
+                })?
+                .collect()
+            })
+            .map_err(map_err)?;
+
+        // FTS5 reports a damaged index as SQLITE_CORRUPT_VTAB; any other failure is not a verdict
+        let search_index_ok = match conn.execute(
+            "INSERT INTO message_fts (message_fts) VALUES ('integrity-check')",
             [],
-        ).map_err(|e| GrokError::Collection(format!("Failed to create collection_sessions table: {}", e)))?;
+        ) {
+            Ok(_) => true,
+            Err(rusqlite::Error::SqliteFailure(e, _))
+                if e.code == rusqlite::ErrorCode::DatabaseCorrupt =>
+            {
+                false
+            }
+            Err(e) => return Err(map_err(e)),
+        };
 
-        Ok(Self {
-            conn: Arc::new(RwLock::new(conn)),
+        // Only meaningful for sessions stored as rows; blob sessions have none
+        let message_count_mismatches = conn
+            .prepare(
+                "SELECT s.id FROM sessions s
+                 WHERE EXISTS (SELECT 1 FROM messages m WHERE m.session_id = s.id)
+                   AND s.message_count != (SELECT COUNT(*) FROM messages m WHERE m.session_id = s.id)",
+            )
+            .and_then(|mut stmt| stmt.query_map([], |row| row.get(0))?.collect())
+            .map_err(map_err)?;
+
+        Ok(VerifyReport {
+            integrity_errors,
+            foreign_key_violations,
+            search_index_ok,
+            message_count_mismatches,
         })
     }
 
-    /// Create an in-memory SQLite storage (for testing)
-    pub fn in_memory() -> Result<Self> {
-        let conn = Connection::open_in_memory().map_err(|e| GrokError::Session(format!("Failed to create in-memory database: {}", e)))?;
+    /// Find rows that no longer belong to anything, and sessions that can't be read
+    ///
+    /// Foreign keys are only enforced when enabled on the builder, so links to
+    /// deleted sessions or collections, message rows and search entries of
+    /// deleted sessions, and stale counts can build up unnoticed. With
+    /// `options.repair` set, everything found is cleaned up in one transaction.
+    pub async fn check_and_repair(&self, options: &RepairOptions) -> Result<RepairReport> {
+        let conn = self.conn.lock().await;
+        let map_err = |e: rusqlite::Error| GrokError::session("check storage", e);
+        let query_ids = |sql: &str| -> Result<Vec<String>> {
+            conn.prepare(sql)
+                .and_then(|mut stmt| stmt.query_map([], |row| row.get(0))?.collect())
+                .map_err(map_err)
+        };
+
+        let orphaned_collection_links = conn
+            .prepare(
+                "SELECT collection_id, session_id FROM collection_sessions
+                 WHERE session_id NOT IN (SELECT id FROM sessions)
+                    OR collection_id NOT IN (SELECT id FROM collections)
+                 ORDER BY collection_id, session_id",
+            )
+            .and_then(|mut stmt| {
+                stmt.query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?
+                    .collect()
+            })
+            .map_err(map_err)?;
+        let orphaned_messages = query_ids(
+            "SELECT DISTINCT session_id FROM messages WHERE session_id NOT IN (SELECT id FROM sessions) ORDER BY session_id",
+        )?;
+        let orphaned_search_entries = query_ids(
+            "SELECT DISTINCT session_id FROM message_fts WHERE session_id NOT IN (SELECT id FROM sessions) ORDER BY session_id",
+        )?;
+        let message_count_mismatches = query_ids(
+            "SELECT s.id FROM sessions s
+             WHERE EXISTS (SELECT 1 FROM messages m WHERE m.session_id = s.id)
+               AND s.message_count != (SELECT COUNT(*) FROM messages m WHERE m.session_id = s.id)
+             ORDER BY s.id",
+        )?;
+        let collection_count_mismatches = query_ids(
+            "SELECT id FROM collections
+             WHERE session_count != (SELECT COUNT(*) FROM collection_sessions cs
+                                     WHERE cs.collection_id = collections.id AND cs.session_id IN (SELECT id FROM sessions))
+             ORDER BY id",
+        )?;
+
+        // A session is unreadable if its history blob, its tools or any of its message rows fail to parse
+        let mut unreadable = std::collections::BTreeSet::new();
+        {
+            let mut stmt = conn
+                .prepare("SELECT id, messages, tools FROM sessions")
+                .map_err(map_err)?;
+            let rows = stmt
+                .query_map([], |row| {
+                    Ok((
+                        row.get::<_, String>(0)?,
+                        row.get::<_, String>(1)?,
+                        row.get::<_, String>(2)?,
+                    ))
+                })
+                .map_err(map_err)?;
+            for row in rows {
+                let (id, messages, tools) = row.map_err(map_err)?;
+                if serde_json::from_str::<Vec<Message>>(&messages).is_err()
+                    || serde_json::from_str::<Vec<Tool>>(&tools).is_err()
+                {
+                    unreadable.insert(id);
+                }
+            }
+
+            let mut stmt = conn
+                .prepare("SELECT session_id, role, content, tool_calls, tool_call_id, name, content_parts FROM messages WHERE session_id IN (SELECT id FROM sessions)")
+                .map_err(map_err)?;
+            let rows = stmt
+                .query_map([], |row| {
+                    Ok((
+                        row.get::<_, String>(0)?,
+                        (
+                            row.get(1)?,
+                            row.get(2)?,
+                            row.get(3)?,
+                            row.get(4)?,
+                            row.get(5)?,
+                            row.get(6)?,
+                        ),
+                    ))
+                })
+                .map_err(map_err)?;
+            for row in rows {
+                let (session_id, message) = row.map_err(map_err)?;
+                if Self::message_from_row(message).is_err() {
+                    unreadable.insert(session_id);
+                }
+            }
+        }
+
+        let mut report = RepairReport {
+            orphaned_collection_links,
+            orphaned_messages,
+            orphaned_search_entries,
+            unreadable_sessions: unreadable.into_iter().collect(),
+            message_count_mismatches,
+            collection_count_mismatches,
+            repaired: false,
+        };
+        if !options.repair || report.is_clean() {
+            return Ok(report);
+        }
+
+        let tx = conn
+            .unchecked_transaction()
+            .map_err(|e| GrokError::session("begin transaction", e))?;
+
+        if options.delete_unreadable_sessions {
+            for session_id in &report.unreadable_sessions {
+                tx.execute(
+                    "DELETE FROM message_fts WHERE session_id = ?1",
+                    params![session_id],
+                )
+                .map_err(|e| GrokError::session("delete session index", e).with_id(session_id))?;
+                tx.execute(
+                    "DELETE FROM messages WHERE session_id = ?1",
+                    params![session_id],
+                )
+                .map_err(|e| {
+                    GrokError::session("delete session messages", e).with_id(session_id)
+                })?;
+                tx.execute("DELETE FROM sessions WHERE id = ?1", params![session_id])
+                    .map_err(|e| GrokError::session("delete session", e).with_id(session_id))?;
+            }
+        }
+
+        tx.execute(
+            "DELETE FROM collection_sessions
+             WHERE session_id NOT IN (SELECT id FROM sessions) OR collection_id NOT IN (SELECT id FROM collections)",
+            [],
+        ).map_err(|e| GrokError::collection("remove collection links", e))?;
+        tx.execute(
+            "DELETE FROM messages WHERE session_id NOT IN (SELECT id FROM sessions)",
+            [],
+        )
+        .map_err(|e| GrokError::session("delete orphaned messages", e))?;
+        tx.execute(
+            "DELETE FROM message_fts WHERE session_id NOT IN (SELECT id FROM sessions)",
+            [],
+        )
+        .map_err(|e| GrokError::session("delete orphaned index entries", e))?;
+        tx.execute(
+            "UPDATE sessions SET message_count = (SELECT COUNT(*) FROM messages m WHERE m.session_id = sessions.id)
+             WHERE EXISTS (SELECT 1 FROM messages m WHERE m.session_id = sessions.id)",
+            [],
+        ).map_err(|e| GrokError::session("update message counts", e))?;
+        tx.execute(
+            "UPDATE collections SET session_count =
+                 (SELECT COUNT(*) FROM collection_sessions cs WHERE cs.collection_id = collections.id)",
+            [],
+        ).map_err(|e| GrokError::collection("update collection counts", e))?;
+
+        tx.commit()
+            .map_err(|e| GrokError::session("commit repair", e))?;
+
+        report.repaired = true;
+        Ok(report)
+    }
 
-        // Create tables (same as above)
+    /// Create the storage tables if they don't exist yet
+    fn create_tables(conn: &Connection) -> Result<()> {
         conn.execute(
-            "CREATE TABLE sessions (
+            "CREATE TABLE IF NOT EXISTS sessions (
                 id TEXT PRIMARY KEY,
                 model TEXT NOT NULL,
                 created_at TEXT NOT NULL,
                 messages TEXT NOT NULL
             )",
             [],
-        ).map_err(|e| GrokError::Session(format!("Failed to create sessions table: {}", e)))?;
+        )
+        .map_err(|e| GrokError::session("create sessions table", e))?;
 
         conn.execute(
-            "CREATE TABLE collections (
+            "CREATE TABLE IF NOT EXISTS collections (
                 id TEXT PRIMARY KEY,
                 name TEXT NOT NULL,
                 description TEXT,
                 created_at TEXT NOT NULL
             )",
             [],
-        ).map_err(|e| GrokError::Collection(format!("Failed to create collections table: {}", e)))?;
+        )
+        .map_err(|e| GrokError::collection("create collections table", e))?;
 
         conn.execute(
-            "CREATE TABLE collection_sessions (
+            "CREATE TABLE IF NOT EXISTS collection_sessions (
                 collection_id TEXT NOT NULL,
                 session_id TEXT NOT NULL,
                 added_at TEXT NOT NULL,
@@ -92,151 +627,1740 @@ impl SqliteStorage {
                 FOREIGN KEY (session_id) REFERENCES sessions(id) ON DELETE CASCADE
             )",
             [],
-        ).map_err(|e| GrokError::Collection(format!("Failed to create collection_sessions table: {}", e)))?;
+        )
+        .map_err(|e| GrokError::collection("create collection_sessions table", e))?;
 
-        Ok(Self {
-            conn: Arc::new(RwLock::new(conn)),
-        })
+        Self::migrate(conn)
+    }
+
+    /// Bring an existing database up to the current schema version
+    fn migrate(conn: &Connection) -> Result<()> {
+        let version: usize = conn
+            .query_row("PRAGMA user_version", [], |row| row.get(0))
+            .map_err(|e| GrokError::session("read schema version", e))?;
+
+        for (index, migration) in MIGRATIONS.iter().enumerate().skip(version) {
+            conn.execute_batch(&format!(
+                "BEGIN; {} PRAGMA user_version = {}; COMMIT;",
+                migration,
+                index + 1
+            ))
+            .map_err(|e| {
+                GrokError::session(format!("migrate schema to version {}", index + 1), e)
+            })?;
+        }
+
+        Ok(())
     }
 
     /// Save a session to storage
     pub async fn save_session(&self, session: &Session) -> Result<()> {
-        let conn = self.conn.read().await;
-        let messages_json = serde_json::to_string(&session.messages())
-            .map_err(|e| GrokError::Session(format!("Failed to serialize messages: {}", e)))?;
+        self.save_sessions(std::slice::from_ref(session)).await
+    }
+
+    /// Save many sessions in a single transaction
+    ///
+    /// Either every session is written or, on error, none are.
+    #[cfg_attr(feature = "tracing", tracing::instrument(name = "grok.storage.save_sessions", skip_all, fields(sessions = sessions.len())))]
+    pub async fn save_sessions<S: Borrow<Session>>(&self, sessions: &[S]) -> Result<()> {
+        // The version is read after the history, so a rewrite in between
+        // is seen as a new version rather than missed
+        let mut histories = Vec::with_capacity(sessions.len());
+        for session in sessions {
+            let session = session.borrow();
+            let messages = session.messages().await;
+            let interrupted = session.interrupted().await;
+            histories.push((messages, interrupted, session.history_version()));
+        }
+
+        let conn = self.conn.lock().await;
+        let tx = conn
+            .unchecked_transaction()
+            .map_err(|e| GrokError::session("begin transaction", e))?;
+
+        for (session, (messages, interrupted, version)) in sessions.iter().zip(&histories) {
+            let session = session.borrow();
+            let written = self
+                .written
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .get(session.id())
+                .filter(|written| written.version == *version)
+                .map(|written| written.len);
+            Self::write_session(
+                &tx,
+                session,
+                messages,
+                interrupted,
+                written,
+                self.message_storage,
+            )?;
+        }
+
+        tx.commit()
+            .map_err(|e| GrokError::session("commit sessions", e))?;
+
+        let mut written = self.written.lock().unwrap_or_else(PoisonError::into_inner);
+        for (session, (messages, _, version)) in sessions.iter().zip(&histories) {
+            written.insert(
+                session.borrow().id().to_string(),
+                WrittenHistory {
+                    version: *version,
+                    len: messages.len(),
+                },
+            );
+        }
+
+        Ok(())
+    }
+
+    /// Save every session held by a session manager in a single transaction
+    ///
+    /// Returns the number of sessions saved.
+    pub async fn save_all<S: Storage + 'static>(
+        &self,
+        manager: &SessionManager<S>,
+    ) -> Result<usize> {
+        let sessions = manager.list_sessions().await;
+        self.save_sessions(&sessions).await?;
+        Ok(sessions.len())
+    }
 
+    /// Write one session row, its message history and index entries
+    ///
+    /// `written` is how many of the messages this storage last wrote for the
+    /// same history version, if it did.
+    fn write_session(
+        conn: &Connection,
+        session: &Session,
+        messages: &[Message],
+        interrupted: &[usize],
+        written: Option<usize>,
+        message_storage: MessageStorage,
+    ) -> Result<()> {
+        let metadata = session.metadata();
+        let row = SessionRow {
+            id: session.id(),
+            model: session.model(),
+            created_at: session.created_at(),
+            title: metadata.title.as_deref(),
+            updated_at: metadata.updated_at,
+            tools: session.tools(),
+            prompt: session.prompt(),
+            tenant: session.tenant(),
+            interrupted,
+            written,
+        };
+
+        Self::write_session_row(conn, &row, messages, message_storage)
+    }
+
+    /// Write a session row from its parts, plus its message history and index entries
+    fn write_session_row(
+        conn: &Connection,
+        row: &SessionRow<'_>,
+        messages: &[Message],
+        message_storage: MessageStorage,
+    ) -> Result<()> {
+        let messages_json = match message_storage {
+            MessageStorage::Normalized => "[]".to_string(),
+            MessageStorage::Blob => serde_json::to_string(messages)
+                .map_err(|e| GrokError::session("serialize messages", e).with_id(row.id))?,
+        };
+
+        let tools_json = serde_json::to_string(row.tools)
+            .map_err(|e| GrokError::session("serialize tools", e).with_id(row.id))?;
+
+        // An upsert rather than INSERT OR REPLACE, which would delete the row
+        // and cascade to its messages and collection links
         conn.execute(
-            "INSERT OR REPLACE INTO sessions (id, model, created_at, messages) VALUES (?1, ?2, ?3, ?4)",
+            "INSERT INTO sessions (id, model, created_at, messages, title, updated_at, message_count, tools, prompt, tenant)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10)
+             ON CONFLICT(id) DO UPDATE SET
+                 model = excluded.model,
+                 messages = excluded.messages,
+                 title = excluded.title,
+                 updated_at = excluded.updated_at,
+                 message_count = excluded.message_count,
+                 tools = excluded.tools,
+                 prompt = excluded.prompt,
+                 tenant = excluded.tenant",
             params![
-                session.id(),
-                session.model().as_str(),
-                session.created_at().to_rfc3339(),
-                messages_json
+                row.id,
+                row.model.as_str(),
+                row.created_at.to_rfc3339(),
+                messages_json,
+                row.title,
+                row.updated_at.to_rfc3339(),
+                messages.len() as i64,
+                tools_json,
+                row.prompt,
+                row.tenant
             ],
-        ).map_err(|e| GrokError::Session(format!("Failed to save session: {}", e)))?;
+        ).map_err(|e| GrokError::session("save session", e).with_id(row.id))?;
+
+        let first_changed = match message_storage {
+            MessageStorage::Normalized => {
+                Self::append_message_rows(conn, row.id, messages, row.interrupted, row.written)?
+            }
+            MessageStorage::Blob => {
+                conn.execute(
+                    "DELETE FROM messages WHERE session_id = ?1",
+                    params![row.id],
+                )
+                .map_err(|e| GrokError::session("clear message rows", e).with_id(row.id))?;
+                0
+            }
+        };
+
+        Self::index_messages(conn, row.id, messages, first_changed)
+    }
+
+    /// Append the messages not yet stored as rows, returning the first index written
+    ///
+    /// Histories are append-only, so only new messages are inserted. When
+    /// `written` matches the stored row count, only the last stored row is
+    /// compared; otherwise every row is. If the stored rows are no longer a
+    /// prefix of the history (e.g. after `clear_history`), all rows are
+    /// rewritten. Messages at the `interrupted` indices are marked as cut off mid-stream.
+    fn append_message_rows(
+        conn: &Connection,
+        session_id: &str,
+        messages: &[Message],
+        interrupted: &[usize],
+        written: Option<usize>,
+    ) -> Result<usize> {
+        // A partial reply from `save_partial_reply` is replaced by whatever the history now holds
+        conn.execute(
+            "DELETE FROM messages WHERE session_id = ?1 AND interrupted = 1
+                 AND idx = (SELECT MAX(idx) FROM messages WHERE session_id = ?1)",
+            params![session_id],
+        )
+        .map_err(|e| GrokError::session("clear partial reply", e).with_id(session_id))?;
+
+        let stored_len: usize = conn
+            .query_row(
+                "SELECT COALESCE(MAX(idx) + 1, 0) FROM messages WHERE session_id = ?1",
+                params![session_id],
+                |row| row.get::<_, i64>(0),
+            )
+            .map_err(|e| GrokError::session("count message rows", e).with_id(session_id))?
+            as usize;
+
+        let still_prefix = if stored_len > messages.len() {
+            false
+        } else if written == Some(stored_len) {
+            // The same history was saved here before, so only another writer
+            // could have changed the rows; the last one shows whether it did
+            match stored_len.checked_sub(1) {
+                Some(last) => {
+                    let stored = Self::read_message_rows(conn, session_id, last..stored_len)?;
+                    Self::is_prefix(&stored, &messages[last..])?
+                }
+                None => true,
+            }
+        } else {
+            // Every stored row is compared, since a rewritten history can end in the same reply
+            let stored = Self::read_message_rows(conn, session_id, 0..stored_len)?;
+            stored.len() == stored_len && Self::is_prefix(&stored, messages)?
+        };
+
+        let start = if still_prefix {
+            stored_len
+        } else {
+            conn.execute(
+                "DELETE FROM messages WHERE session_id = ?1",
+                params![session_id],
+            )
+            .map_err(|e| GrokError::session("clear message rows", e).with_id(session_id))?;
+            0
+        };
+
+        let mut stmt = conn.prepare(
+            "INSERT INTO messages (session_id, idx, role, content, tool_calls, tool_call_id, name, created_at, interrupted, content_parts)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10)",
+        ).map_err(|e| GrokError::session("prepare statement", e))?;
+
+        let now = Utc::now().to_rfc3339();
+        for (idx, message) in messages.iter().enumerate().skip(start) {
+            let role = serde_json::to_value(&message.role)?;
+            let tool_calls = message
+                .tool_calls
+                .as_ref()
+                .map(serde_json::to_string)
+                .transpose()?;
+            stmt.execute(params![
+                session_id,
+                idx as i64,
+                role.as_str(),
+                message.content.text(),
+                tool_calls,
+                message.tool_call_id,
+                message.name,
+                now,
+                interrupted.contains(&idx),
+                Self::content_parts(&message.content)?
+            ])
+            .map_err(|e| GrokError::session("store message", e).with_id(session_id))?;
+        }
+
+        Ok(start)
+    }
+
+    /// Read the message rows of a session at the indices in `range`
+    fn read_message_rows(
+        conn: &Connection,
+        session_id: &str,
+        range: std::ops::Range<usize>,
+    ) -> Result<Vec<Message>> {
+        let mut stmt = conn
+            .prepare(
+                "SELECT role, content, tool_calls, tool_call_id, name, content_parts FROM messages
+             WHERE session_id = ?1 AND idx >= ?2 AND idx < ?3 ORDER BY idx",
+            )
+            .map_err(|e| GrokError::session("prepare statement", e))?;
+        let rows = stmt
+            .query_map(
+                params![session_id, range.start as i64, range.end as i64],
+                |row| {
+                    Ok((
+                        row.get::<_, String>(0)?,
+                        row.get::<_, String>(1)?,
+                        row.get::<_, Option<String>>(2)?,
+                        row.get::<_, Option<String>>(3)?,
+                        row.get::<_, Option<String>>(4)?,
+                        row.get::<_, Option<String>>(5)?,
+                    ))
+                },
+            )
+            .and_then(|rows| rows.collect::<std::result::Result<Vec<_>, _>>())
+            .map_err(|e| GrokError::session("read message rows", e).with_id(session_id))?;
+
+        rows.into_iter().map(Self::message_from_row).collect()
+    }
+
+    /// Whether `stored` messages are the start of `messages`
+    fn is_prefix(stored: &[Message], messages: &[Message]) -> Result<bool> {
+        if stored.len() > messages.len() {
+            return Ok(false);
+        }
+        for (stored, message) in stored.iter().zip(messages) {
+            if serde_json::to_value(stored)? != serde_json::to_value(message)? {
+                return Ok(false);
+            }
+        }
+        Ok(true)
+    }
+
+    /// Read a session's history, preferring normalized rows over the legacy blob
+    fn read_messages(
+        conn: &Connection,
+        session_id: &str,
+        messages_json: &str,
+    ) -> Result<Vec<Message>> {
+        let mut stmt = conn.prepare(
+            "SELECT role, content, tool_calls, tool_call_id, name, content_parts FROM messages WHERE session_id = ?1 ORDER BY idx",
+        ).map_err(|e| GrokError::session("prepare statement", e))?;
+
+        let rows = stmt
+            .query_map(params![session_id], |row| {
+                Ok((
+                    row.get::<_, String>(0)?,
+                    row.get::<_, String>(1)?,
+                    row.get::<_, Option<String>>(2)?,
+                    row.get::<_, Option<String>>(3)?,
+                    row.get::<_, Option<String>>(4)?,
+                    row.get::<_, Option<String>>(5)?,
+                ))
+            })
+            .and_then(|rows| rows.collect::<std::result::Result<Vec<_>, _>>())
+            .map_err(|e| GrokError::session("read messages", e).with_id(session_id))?;
+
+        if rows.is_empty() {
+            return serde_json::from_str(messages_json)
+                .map_err(|e| GrokError::session("parse messages", e).with_id(session_id));
+        }
+
+        rows.into_iter().map(Self::message_from_row).collect()
+    }
+
+    /// Indices of a session's messages that were cut off mid-stream
+    fn read_interrupted(conn: &Connection, session_id: &str) -> Result<Vec<usize>> {
+        let mut stmt = conn
+            .prepare(
+                "SELECT idx FROM messages WHERE session_id = ?1 AND interrupted = 1 ORDER BY idx",
+            )
+            .map_err(|e| GrokError::session("prepare statement", e))?;
+
+        let indices = stmt
+            .query_map(params![session_id], |row| row.get::<_, i64>(0))
+            .and_then(|rows| rows.collect::<std::result::Result<Vec<_>, _>>())
+            .map_err(|e| GrokError::session("read interrupted messages", e).with_id(session_id))?;
+
+        Ok(indices.into_iter().map(|idx| idx as usize).collect())
+    }
+
+    /// Store the part of a streamed reply received so far as message `index` of a session
+    ///
+    /// The row is marked as interrupted until the finished reply replaces it,
+    /// so a session loaded after a crash mid-stream keeps the partial reply.
+    /// Does nothing with `MessageStorage::Blob`.
+    pub async fn save_partial_reply(
+        &self,
+        session_id: &str,
+        index: usize,
+        message: &Message,
+    ) -> Result<()> {
+        if self.message_storage == MessageStorage::Blob {
+            return Ok(());
+        }
+
+        let role = serde_json::to_value(&message.role)?;
+        let tool_calls = message
+            .tool_calls
+            .as_ref()
+            .map(serde_json::to_string)
+            .transpose()?;
+        let now = Utc::now().to_rfc3339();
+
+        let conn = self.conn.lock().await;
+        let tx = conn
+            .unchecked_transaction()
+            .map_err(|e| GrokError::session("begin transaction", e))?;
+
+        tx.execute(
+            "INSERT INTO messages (session_id, idx, role, content, tool_calls, tool_call_id, name, created_at, interrupted, content_parts)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, 1, ?9)
+             ON CONFLICT(session_id, idx) DO UPDATE SET
+                 content = excluded.content,
+                 content_parts = excluded.content_parts,
+                 tool_calls = excluded.tool_calls,
+                 interrupted = 1",
+            params![session_id, index as i64, role.as_str(), message.content.text(), tool_calls, message.tool_call_id, message.name, now, Self::content_parts(&message.content)?],
+        ).map_err(|e| GrokError::session("store partial reply", e).with_id(session_id))?;
+
+        tx.execute(
+            "UPDATE sessions SET message_count = ?2, updated_at = ?3 WHERE id = ?1",
+            params![session_id, index as i64 + 1, now],
+        )
+        .map_err(|e| GrokError::session("update session", e).with_id(session_id))?;
+
+        tx.execute(
+            "DELETE FROM message_fts WHERE session_id = ?1 AND message_index = ?2",
+            params![session_id, index as i64],
+        )
+        .map_err(|e| GrokError::session("clear message index", e).with_id(session_id))?;
+
+        tx.execute(
+            "INSERT INTO message_fts (content, session_id, message_index, role) VALUES (?1, ?2, ?3, ?4)",
+            params![message.content.text(), session_id, index as i64, role.as_str()],
+        ).map_err(|e| GrokError::session("index message", e).with_id(session_id))?;
+
+        tx.commit()
+            .map_err(|e| GrokError::session("commit partial reply", e).with_id(session_id))?;
+
+        Ok(())
+    }
+
+    /// The `content_parts` column for a message's content, set only for multimodal messages
+    fn content_parts(content: &MessageContent) -> Result<Option<String>> {
+        match content {
+            MessageContent::Text(_) => Ok(None),
+            MessageContent::Parts(parts) => Ok(Some(serde_json::to_string(parts)?)),
+        }
+    }
+
+    /// Build a message from the columns of a `messages` row
+    fn message_from_row(
+        (role, content, tool_calls, tool_call_id, name, content_parts): (
+            String,
+            String,
+            Option<String>,
+            Option<String>,
+            Option<String>,
+            Option<String>,
+        ),
+    ) -> Result<Message> {
+        let content = match content_parts {
+            Some(json) => MessageContent::Parts(serde_json::from_str(&json)?),
+            None => MessageContent::Text(content),
+        };
+        Ok(Message {
+            role: serde_json::from_value(serde_json::Value::String(role))?,
+            content,
+            tool_calls: tool_calls
+                .map(|json| serde_json::from_str(&json))
+                .transpose()?,
+            tool_call_id,
+            name,
+        })
+    }
+
+    /// Update the full-text index entries for a session from `start` onwards
+    fn index_messages(
+        conn: &Connection,
+        session_id: &str,
+        messages: &[Message],
+        start: usize,
+    ) -> Result<()> {
+        conn.execute(
+            "DELETE FROM message_fts WHERE session_id = ?1 AND message_index >= ?2",
+            params![session_id, start as i64],
+        )
+        .map_err(|e| GrokError::session("clear message index", e).with_id(session_id))?;
+
+        let mut stmt = conn.prepare(
+            "INSERT INTO message_fts (content, session_id, message_index, role) VALUES (?1, ?2, ?3, ?4)",
+        ).map_err(|e| GrokError::session("prepare statement", e))?;
+
+        for (index, message) in messages.iter().enumerate().skip(start) {
+            let role = serde_json::to_value(&message.role)?;
+            stmt.execute(params![
+                message.content.text(),
+                session_id,
+                index as i64,
+                role.as_str()
+            ])
+            .map_err(|e| GrokError::session("index message", e).with_id(session_id))?;
+        }
 
         Ok(())
     }
 
+    /// Search message content across all stored sessions
+    ///
+    /// `query` uses SQLite FTS5 query syntax (e.g. `rust AND async`, `"exact phrase"`, `pars*`).
+    /// Results are ordered by relevance.
+    pub async fn search_messages(&self, query: &str) -> Result<Vec<MessageMatch>> {
+        let conn = self.conn.lock().await;
+        let mut stmt = conn.prepare(
+            "SELECT session_id, message_index, role, snippet(message_fts, 0, '[', ']', '...', 16)
+             FROM message_fts WHERE message_fts MATCH ?1 ORDER BY rank",
+        ).map_err(|e| GrokError::session("prepare statement", e))?;
+
+        let matches = stmt
+            .query_map(params![query], Self::message_match_from_row)
+            .and_then(|rows| rows.collect::<std::result::Result<Vec<_>, _>>())
+            .map_err(|e| GrokError::session("search messages", e))?;
+
+        Ok(matches)
+    }
+
+    /// Search message content within the sessions of one tenant
+    pub async fn search_messages_for(
+        &self,
+        tenant: &str,
+        query: &str,
+    ) -> Result<Vec<MessageMatch>> {
+        let conn = self.conn.lock().await;
+        let mut stmt = conn.prepare(
+            "SELECT f.session_id, f.message_index, f.role, snippet(message_fts, 0, '[', ']', '...', 16)
+             FROM message_fts f
+             JOIN sessions s ON s.id = f.session_id
+             WHERE message_fts MATCH ?1 AND s.tenant = ?2
+             ORDER BY rank",
+        ).map_err(|e| GrokError::session("prepare statement", e))?;
+
+        let matches = stmt
+            .query_map(params![query, tenant], Self::message_match_from_row)
+            .and_then(|rows| rows.collect::<std::result::Result<Vec<_>, _>>())
+            .map_err(|e| GrokError::session("search messages", e))?;
+
+        Ok(matches)
+    }
+
+    /// Search message content within the sessions of one collection
+    pub async fn search_collection_messages(
+        &self,
+        collection_id: &str,
+        query: &str,
+    ) -> Result<Vec<MessageMatch>> {
+        let conn = self.conn.lock().await;
+        let mut stmt = conn.prepare(
+            "SELECT f.session_id, f.message_index, f.role, snippet(message_fts, 0, '[', ']', '...', 16)
+             FROM message_fts f
+             JOIN collection_sessions cs ON cs.session_id = f.session_id
+             WHERE message_fts MATCH ?1 AND cs.collection_id = ?2
+             ORDER BY rank",
+        ).map_err(|e| GrokError::collection("prepare statement", e))?;
+
+        let matches = stmt
+            .query_map(params![query, collection_id], Self::message_match_from_row)
+            .and_then(|rows| rows.collect::<std::result::Result<Vec<_>, _>>())
+            .map_err(|e| GrokError::collection("search messages", e).with_id(collection_id))?;
+
+        Ok(matches)
+    }
+
+    fn message_match_from_row(row: &rusqlite::Row<'_>) -> rusqlite::Result<MessageMatch> {
+        let role: Option<String> = row.get(2)?;
+        Ok(MessageMatch {
+            session_id: row.get(0)?,
+            message_index: row.get::<_, i64>(1)? as usize,
+            role: role.and_then(|r| serde_json::from_value(serde_json::Value::String(r)).ok()),
+            snippet: row.get(3)?,
+        })
+    }
+
     /// Load a session from storage
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(name = "grok.storage.load_session", skip(self))
+    )]
     pub async fn load_session(&self, session_id: &str) -> Result<Option<Session>> {
-        let conn = self.conn.read().await;
-        let result = conn.query_row(
-            "SELECT id, model, created_at, messages FROM sessions WHERE id = ?1",
+        let loaded = self.load_session_parts(session_id, None).await?;
+
+        Ok(loaded.map(|(mut session, tools)| {
+            session.add_tools(tools);
+            session
+        }))
+    }
+
+    /// Load a session by ID, if it belongs to `tenant`
+    ///
+    /// Another tenant's session is treated as missing and none of it is read.
+    pub async fn load_session_for(
+        &self,
+        tenant: &str,
+        session_id: &str,
+    ) -> Result<Option<Session>> {
+        let loaded = self.load_session_parts(session_id, Some(tenant)).await?;
+
+        Ok(loaded.map(|(mut session, tools)| {
+            session.add_tools(tools);
+            session
+        }))
+    }
+
+    /// Load only the summary of a session, without reading its messages
+    pub async fn load_session_meta(&self, session_id: &str) -> Result<Option<SessionSummary>> {
+        let conn = self.conn.lock().await;
+        let row = conn.query_row(
+            "SELECT id, title, model, message_count, created_at, updated_at, prompt, tenant FROM sessions WHERE id = ?1",
             params![session_id],
+            |row| {
+                Ok((
+                    row.get::<_, String>(0)?,
+                    row.get::<_, Option<String>>(1)?,
+                    row.get::<_, String>(2)?,
+                    row.get::<_, i64>(3)?,
+                    row.get::<_, String>(4)?,
+                    row.get::<_, Option<String>>(5)?,
+                    row.get::<_, Option<String>>(6)?,
+                    row.get::<_, Option<String>>(7)?,
+                ))
+            },
+        ).optional().map_err(|e| GrokError::session("load session", e).with_id(session_id))?;
+
+        let Some((id, title, model, message_count, created_at, updated_at, prompt, tenant)) = row
+        else {
+            return Ok(None);
+        };
+
+        let created_at = parse_timestamp(&created_at)?;
+        let updated_at = match updated_at {
+            Some(ref updated_at) => parse_timestamp(updated_at)?,
+            None => created_at,
+        };
+
+        Ok(Some(SessionSummary {
+            id,
+            title,
+            model: model.parse()?,
+            message_count: message_count as usize,
+            created_at,
+            updated_at,
+            prompt,
+            tenant,
+        }))
+    }
+
+    /// Load a slice of a session's messages by index, e.g. `0..20` or `40..`
+    ///
+    /// Only the requested messages are read and deserialized. Indices past the
+    /// end of the history are ignored; an unknown session yields no messages.
+    pub async fn load_messages<R: RangeBounds<usize>>(
+        &self,
+        session_id: &str,
+        range: R,
+    ) -> Result<Vec<Message>> {
+        let start = match range.start_bound() {
+            Bound::Included(&start) => start as i64,
+            Bound::Excluded(&start) => start as i64 + 1,
+            Bound::Unbounded => 0,
+        };
+        let end = match range.end_bound() {
+            Bound::Included(&end) => end as i64 + 1,
+            Bound::Excluded(&end) => end as i64,
+            Bound::Unbounded => i64::MAX,
+        };
+
+        let conn = self.conn.lock().await;
+        let has_rows: bool = conn
+            .query_row(
+                "SELECT EXISTS (SELECT 1 FROM messages WHERE session_id = ?1)",
+                params![session_id],
+                |row| row.get(0),
+            )
+            .map_err(|e| GrokError::session("read messages", e).with_id(session_id))?;
+
+        if has_rows {
+            let mut stmt = conn.prepare(
+                "SELECT role, content, tool_calls, tool_call_id, name, content_parts FROM messages
+                 WHERE session_id = ?1 AND idx >= ?2 AND idx < ?3 ORDER BY idx",
+            ).map_err(|e| GrokError::session("prepare statement", e))?;
+
+            let rows = stmt
+                .query_map(params![session_id, start, end], |row| {
+                    Ok((
+                        row.get::<_, String>(0)?,
+                        row.get::<_, String>(1)?,
+                        row.get::<_, Option<String>>(2)?,
+                        row.get::<_, Option<String>>(3)?,
+                        row.get::<_, Option<String>>(4)?,
+                        row.get::<_, Option<String>>(5)?,
+                    ))
+                })
+                .and_then(|rows| rows.collect::<std::result::Result<Vec<_>, _>>())
+                .map_err(|e| GrokError::session("read messages", e).with_id(session_id))?;
+
+            return rows.into_iter().map(Self::message_from_row).collect();
+        }
+
+        // Blob-stored session: let SQLite slice the JSON array
+        let mut stmt = conn
+            .prepare(
+                "SELECT m.value FROM sessions s, json_each(s.messages) m
+             WHERE s.id = ?1 AND m.key >= ?2 AND m.key < ?3 ORDER BY m.key",
+            )
+            .map_err(|e| GrokError::session("prepare statement", e))?;
+
+        let values = stmt
+            .query_map(params![session_id, start, end], |row| {
+                row.get::<_, String>(0)
+            })
+            .and_then(|rows| rows.collect::<std::result::Result<Vec<_>, _>>())
+            .map_err(|e| GrokError::session("read messages", e).with_id(session_id))?;
+
+        values
+            .iter()
+            .map(|json| {
+                serde_json::from_str(json)
+                    .map_err(|e| GrokError::session("parse messages", e).with_id(session_id))
+            })
+            .collect()
+    }
+
+    /// Load a session and rebind its stored tools against a registry
+    ///
+    /// Stored tools are matched to executors by name and take the registry's
+    /// current specification. Tools with no registered executor are left off
+    /// the session and reported in `unbound_tools`.
+    pub async fn load_session_with_tools(
+        &self,
+        session_id: &str,
+        registry: &ToolRegistry,
+    ) -> Result<Option<RestoredSession>> {
+        let Some((mut session, tools)) = self.load_session_parts(session_id, None).await? else {
+            return Ok(None);
+        };
+
+        let mut unbound_tools = Vec::new();
+        for tool in tools {
+            match registry.spec(&tool.function.name) {
+                Some(spec) => {
+                    session.add_tool(Tool {
+                        tool_type: tool.tool_type,
+                        function: crate::chat::ToolSpec {
+                            name: spec.name,
+                            description: Some(spec.description),
+                            parameters: Some(spec.parameters),
+                            strict: spec.strict.then_some(true),
+                        },
+                    });
+                }
+                None => unbound_tools.push(tool.function.name),
+            }
+        }
+
+        Ok(Some(RestoredSession {
+            session,
+            unbound_tools,
+        }))
+    }
+
+    /// Load a session row along with its stored tool specifications, optionally only for one tenant
+    async fn load_session_parts(
+        &self,
+        session_id: &str,
+        tenant: Option<&str>,
+    ) -> Result<Option<(Session, Vec<Tool>)>> {
+        let conn = self.conn.lock().await;
+        let result = conn.query_row(
+            "SELECT id, model, created_at, messages, tools, prompt, tenant, title, updated_at FROM sessions
+             WHERE id = ?1 AND (?2 IS NULL OR tenant = ?2)",
+            params![session_id, tenant],
             |row| {
                 let id: String = row.get(0)?;
                 let model_str: String = row.get(1)?;
                 let created_at_str: String = row.get(2)?;
                 let messages_json: String = row.get(3)?;
+                let tools_json: String = row.get(4)?;
+                let prompt: Option<String> = row.get(5)?;
+                let tenant: Option<String> = row.get(6)?;
+                let title: Option<String> = row.get(7)?;
+                let updated_at_str: Option<String> = row.get(8)?;
 
-                let model = match model_str.as_str() {
-                    "grok-4-fast-reasoning" => crate::Model::Grok4FastReasoning,
-                    "grok-4" => crate::Model::Grok4,
-                    "grok-3" => crate::Model::Grok3,
-                    "grok-2" => crate::Model::Grok2,
-                    "grok-1" => crate::Model::Grok1,
-                    _ => return Err(rusqlite::Error::InvalidColumnType(1, "model".to_string(), rusqlite::types::Type::Text)),
-                };
+                let model: Model = model_str.parse()
+                    .map_err(|_| rusqlite::Error::InvalidColumnType(1, "model".to_string(), rusqlite::types::Type::Text))?;
 
                 let created_at = chrono::DateTime::parse_from_rfc3339(&created_at_str)
                     .map_err(|_| rusqlite::Error::InvalidColumnType(2, "created_at".to_string(), rusqlite::types::Type::Text))?
                     .with_timezone(&chrono::Utc);
 
-                let messages: Vec<crate::chat::Message> = serde_json::from_str(&messages_json)
-                    .map_err(|_| rusqlite::Error::InvalidColumnType(3, "messages".to_string(), rusqlite::types::Type::Text))?;
+                let tools: Vec<Tool> = serde_json::from_str(&tools_json)
+                    .map_err(|_| rusqlite::Error::InvalidColumnType(4, "tools".to_string(), rusqlite::types::Type::Text))?;
 
-                Ok(Session::restore(id, model, created_at, messages))
+                Ok((id, model, created_at, messages_json, tools, prompt, tenant, title, updated_at_str))
             }
-        ).optional().map_err(|e| GrokError::Session(format!("Failed to load session: {}", e)))?;
+        ).optional().map_err(|e| GrokError::session("load session", e).with_id(session_id))?;
 
-        Ok(result)
+        let Some((id, model, created_at, messages_json, tools, prompt, tenant, title, updated_at)) =
+            result
+        else {
+            return Ok(None);
+        };
+
+        let updated_at = match updated_at {
+            Some(ref updated_at) => parse_timestamp(updated_at)?,
+            None => created_at,
+        };
+        let messages = Self::read_messages(&conn, &id, &messages_json)?;
+        let interrupted = Self::read_interrupted(&conn, &id)?;
+        let mut session = Session::restore(id, model, title, created_at, updated_at, messages);
+        session.set_prompt_reference(prompt);
+        session.set_interrupted(interrupted);
+        if let Some(tenant) = tenant {
+            session.set_tenant(tenant);
+        }
+        Ok(Some((session, tools)))
     }
 
     /// Delete a session from storage
+    ///
+    /// The session is removed from the collections it belonged to, and their
+    /// counts are updated. Foreign keys are off unless the builder enables
+    /// them, so nothing is left to `ON DELETE CASCADE`.
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(name = "grok.storage.delete_session", skip(self))
+    )]
     pub async fn delete_session(&self, session_id: &str) -> Result<()> {
-        let conn = self.conn.read().await;
-        conn.execute(
-            "DELETE FROM sessions WHERE id = ?1",
+        let conn = self.conn.lock().await;
+        let tx = conn
+            .unchecked_transaction()
+            .map_err(|e| GrokError::session("begin transaction", e))?;
+
+        tx.execute(
+            "UPDATE collections SET
+                 session_count = MAX(session_count - 1, 0),
+                 total_messages = MAX(total_messages - COALESCE((SELECT message_count FROM sessions WHERE id = ?1), 0), 0)
+             WHERE id IN (SELECT collection_id FROM collection_sessions WHERE session_id = ?1)",
             params![session_id],
-        ).map_err(|e| GrokError::Session(format!("Failed to delete session: {}", e)))?;
+        )
+        .map_err(|e| GrokError::collection("update collection counts", e))?;
+        tx.execute(
+            "DELETE FROM collection_sessions WHERE session_id = ?1",
+            params![session_id],
+        )
+        .map_err(|e| GrokError::collection("remove collection links", e))?;
+        tx.execute(
+            "DELETE FROM message_fts WHERE session_id = ?1",
+            params![session_id],
+        )
+        .map_err(|e| GrokError::session("delete session index", e).with_id(session_id))?;
+        tx.execute(
+            "DELETE FROM messages WHERE session_id = ?1",
+            params![session_id],
+        )
+        .map_err(|e| GrokError::session("delete session messages", e).with_id(session_id))?;
+        tx.execute("DELETE FROM sessions WHERE id = ?1", params![session_id])
+            .map_err(|e| GrokError::session("delete session", e).with_id(session_id))?;
+
+        tx.commit()
+            .map_err(|e| GrokError::session("commit session delete", e).with_id(session_id))
+    }
+
+    /// Rate a stored session, or clear its rating with `None`
+    ///
+    /// Ratings are free-form integers (e.g. 1-5 or -1/+1) used to select
+    /// conversations with `SessionQuery::min_rating`. Saving the session again
+    /// keeps its rating.
+    pub async fn rate_session(&self, session_id: &str, rating: Option<i64>) -> Result<()> {
+        let conn = self.conn.lock().await;
+        let updated = conn
+            .execute(
+                "UPDATE sessions SET rating = ?2 WHERE id = ?1",
+                params![session_id, rating],
+            )
+            .map_err(|e| GrokError::session("rate session", e).with_id(session_id))?;
 
+        if updated == 0 {
+            return Err(GrokError::NotFound {
+                entity: Entity::Session,
+                id: session_id.to_string(),
+            });
+        }
         Ok(())
     }
 
     /// List all session IDs
     pub async fn list_sessions(&self) -> Result<Vec<String>> {
-        let conn = self.conn.read().await;
-        let mut stmt = conn.prepare("SELECT id FROM sessions ORDER BY created_at DESC")
-            .map_err(|e| GrokError::Session(format!("Failed to prepare statement: {}", e)))?;
+        let conn = self.conn.lock().await;
+        let mut stmt = conn
+            .prepare("SELECT id FROM sessions ORDER BY created_at DESC")
+            .map_err(|e| GrokError::session("prepare statement", e))?;
 
-        let ids = stmt.query_map([], |row| row.get(0))?
-            .collect::<std::result::Result<Vec<String>, _>>()
-            .map_err(|e| GrokError::Session(format!("Failed to list sessions: {}", e)))?;
+        let ids = stmt
+            .query_map([], |row| row.get(0))
+            .and_then(|rows| rows.collect::<std::result::Result<Vec<String>, _>>())
+            .map_err(|e| GrokError::session("list sessions", e))?;
 
         Ok(ids)
     }
 
-    /// Save a collection to storage
-    pub async fn save_collection(&self, collection: &crate::collections::Collection) -> Result<()> {
-        let conn = self.conn.read().await;
-        conn.execute(
-            "INSERT OR REPLACE INTO collections (id, name, description, created_at) VALUES (?1, ?2, ?3, ?4)",
-            params![
-                collection.id(),
-                collection.name(),
-                collection.description(),
-                collection.created_at().to_rfc3339()
-            ],
-        ).map_err(|e| GrokError::Collection(format!("Failed to save collection: {}", e)))?;
+    /// List the IDs of the sessions belonging to `tenant`, newest first
+    pub async fn list_sessions_for(&self, tenant: &str) -> Result<Vec<String>> {
+        let conn = self.conn.lock().await;
+        let mut stmt = conn
+            .prepare("SELECT id FROM sessions WHERE tenant = ?1 ORDER BY created_at DESC")
+            .map_err(|e| GrokError::session("prepare statement", e))?;
 
-        // Save session associations
-        for session_id in collection.session_ids() {
-            conn.execute(
-                "INSERT OR IGNORE INTO collection_sessions (collection_id, session_id, added_at) VALUES (?1, ?2, ?3)",
-                params![
-                    collection.id(),
-                    session_id,
-                    chrono::Utc::now().to_rfc3339()
-                ],
-            ).map_err(|e| GrokError::Collection(format!("Failed to save collection session: {}", e)))?;
-        }
+        let ids = stmt
+            .query_map(params![tenant], |row| row.get(0))
+            .and_then(|rows| rows.collect::<std::result::Result<Vec<String>, _>>())
+            .map_err(|e| GrokError::session("list sessions", e))?;
 
-        Ok(())
+        Ok(ids)
     }
 
-    /// Load a collection from storage
-    pub async fn load_collection(&self, collection_id: &str) -> Result<Option<crate::collections::Collection>> {
-        let conn = self.conn.read().await;
-
-        // Load collection metadata
-        let collection_data = conn.query_row(
-            "SELECT id, name, description, created_at FROM collections WHERE id = ?1",
-            params![collection_id],
-            |row| {
-                let id: String = row.get(0)?;
-                let name: String = row.get(1)?;
-                let description: Option<String> = row.get(2)?;
-                let created_at_str: String = row.get(3)?;
+    /// List lightweight session summaries matching a query
+    ///
+    /// Only the summary columns are read, so message histories are never deserialized.
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(name = "grok.storage.query_sessions", skip_all)
+    )]
+    pub async fn query_sessions(&self, query: &SessionQuery) -> Result<Vec<SessionSummary>> {
+        let mut conditions: Vec<&str> = Vec::new();
+        let mut values: Vec<Box<dyn ToSql>> = Vec::new();
+
+        if let Some(after) = query.created_after {
+            conditions.push("created_at >= ?");
+            values.push(Box::new(after.to_rfc3339()));
+        }
+        if let Some(before) = query.created_before {
+            conditions.push("created_at < ?");
+            values.push(Box::new(before.to_rfc3339()));
+        }
+        if let Some(after) = query.updated_after {
+            conditions.push("updated_at >= ?");
+            values.push(Box::new(after.to_rfc3339()));
+        }
+        if let Some(before) = query.updated_before {
+            conditions.push("updated_at < ?");
+            values.push(Box::new(before.to_rfc3339()));
+        }
+        if let Some(model) = query.model {
+            conditions.push("model = ?");
+            values.push(Box::new(model.as_str()));
+        }
+        if let Some(ref title) = query.title_contains {
+            conditions.push("instr(lower(title), lower(?)) > 0");
+            values.push(Box::new(title.clone()));
+        }
+        if let Some(ref collection_id) = query.collection_id {
+            conditions.push(
+                "EXISTS (SELECT 1 FROM collection_sessions cs
+                         WHERE cs.session_id = sessions.id AND cs.collection_id = ?)",
+            );
+            values.push(Box::new(collection_id.clone()));
+        }
+        if let Some(ref tag) = query.tag {
+            // Sessions carry no tags of their own; match the tags of their collections
+            conditions.push(
+                "EXISTS (SELECT 1 FROM collection_sessions cs
+                         JOIN collections c ON c.id = cs.collection_id, json_each(c.tags) t
+                         WHERE cs.session_id = sessions.id AND t.value = ?)",
+            );
+            values.push(Box::new(tag.clone()));
+        }
+        if let Some(min_rating) = query.min_rating {
+            conditions.push("rating >= ?");
+            values.push(Box::new(min_rating));
+        }
+        if let Some(ref prompt) = query.prompt {
+            // A bare name matches sessions started from any of its versions
+            if prompt.contains('@') {
+                conditions.push("prompt = ?");
+                values.push(Box::new(prompt.clone()));
+            } else {
+                conditions.push("substr(prompt, 1, instr(prompt, '@') - 1) = ?");
+                values.push(Box::new(prompt.clone()));
+            }
+        }
+        if let Some(ref tenant) = query.tenant {
+            conditions.push("tenant = ?");
+            values.push(Box::new(tenant.clone()));
+        }
+
+        let mut sql = String::from(
+            "SELECT id, title, model, message_count, created_at, updated_at, prompt, tenant FROM sessions",
+        );
+        if !conditions.is_empty() {
+            sql.push_str(" WHERE ");
+            sql.push_str(&conditions.join(" AND "));
+        }
+        sql.push_str(" ORDER BY ");
+        sql.push_str(query.sort.order_by());
+        // SQLite requires a LIMIT clause before OFFSET; -1 means unlimited
+        sql.push_str(" LIMIT ? OFFSET ?");
+        values.push(Box::new(query.limit.map(|l| l as i64).unwrap_or(-1)));
+        values.push(Box::new(query.offset.unwrap_or(0) as i64));
+
+        let conn = self.conn.lock().await;
+        let mut stmt = conn
+            .prepare(&sql)
+            .map_err(|e| GrokError::session("prepare statement", e))?;
+
+        let rows = stmt
+            .query_map(params_from_iter(values.iter()), |row| {
+                let created_at_str: String = row.get(4)?;
+                let updated_at_str: Option<String> = row.get(5)?;
+                Ok((
+                    row.get::<_, String>(0)?,
+                    row.get::<_, Option<String>>(1)?,
+                    row.get::<_, String>(2)?,
+                    row.get::<_, i64>(3)?,
+                    created_at_str,
+                    updated_at_str,
+                    row.get::<_, Option<String>>(6)?,
+                    row.get::<_, Option<String>>(7)?,
+                ))
+            })
+            .map_err(|e| GrokError::session("query sessions", e))?;
+
+        let mut summaries = Vec::new();
+        for row in rows {
+            let (id, title, model, message_count, created_at, updated_at, prompt, tenant) =
+                row.map_err(|e| GrokError::session("query sessions", e))?;
+
+            let created_at = parse_timestamp(&created_at)?;
+            let updated_at = match updated_at {
+                Some(ref updated_at) => parse_timestamp(updated_at)?,
+                None => created_at,
+            };
+
+            summaries.push(SessionSummary {
+                id,
+                title,
+                model: model.parse()?,
+                message_count: message_count as usize,
+                created_at,
+                updated_at,
+                prompt,
+                tenant,
+            });
+        }
+
+        Ok(summaries)
+    }
+
+    /// Export stored conversations as chat fine-tuning JSONL
+    ///
+    /// Each matching session is written as one `{"messages": [...]}` line.
+    /// Sessions left empty after filtering are skipped. Returns the number of
+    /// conversations written.
+    pub async fn export_jsonl<P: AsRef<Path>>(
+        &self,
+        options: &ExportOptions,
+        path: P,
+    ) -> Result<usize> {
+        let summaries = self.query_sessions(&options.query).await?;
+
+        let transcripts = {
+            let conn = self.conn.lock().await;
+            let mut stmt = conn
+                .prepare("SELECT messages FROM sessions WHERE id = ?1")
+                .map_err(|e| GrokError::session("prepare statement", e))?;
+
+            let mut transcripts = Vec::new();
+            for summary in summaries {
+                let messages_json: String = stmt
+                    .query_row(params![summary.id], |row| row.get(0))
+                    .map_err(|e| {
+                    GrokError::session("load session", e).with_id(summary.id.as_str())
+                })?;
+                let messages = Self::read_messages(&conn, &summary.id, &messages_json)?;
+
+                let messages = options.apply(messages);
+                if !messages.is_empty() {
+                    transcripts.push(messages);
+                }
+            }
+            transcripts
+        };
+
+        // Write outside the connection lock and off the runtime's worker threads
+        let path = path.as_ref().to_path_buf();
+        tokio::task::spawn_blocking(move || {
+            let file =
+                File::create(&path).map_err(|e| GrokError::session("create export file", e))?;
+            let mut writer = BufWriter::new(file);
+
+            for messages in &transcripts {
+                serde_json::to_writer(&mut writer, &serde_json::json!({ "messages": messages }))?;
+                writer
+                    .write_all(b"\n")
+                    .map_err(|e| GrokError::session("write export file", e))?;
+            }
+
+            writer
+                .flush()
+                .map_err(|e| GrokError::session("write export file", e))?;
+            Ok(transcripts.len())
+        })
+        .await
+        .map_err(|e| GrokError::session("write export file", e))?
+    }
+
+    /// Delete sessions matching a retention policy
+    ///
+    /// Collection links to the removed sessions (and any links already
+    /// pointing at missing sessions) are cleaned up, and collection session
+    /// counts are recomputed.
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(name = "grok.storage.prune", skip_all)
+    )]
+    pub async fn prune(&self, policy: &PrunePolicy) -> Result<PruneReport> {
+        if policy.older_than.is_none() && policy.keep_last_n.is_none() && !policy.orphaned_only {
+            return Err(GrokError::InvalidConfig(
+                "Prune policy must set at least one criterion".to_string(),
+            ));
+        }
+
+        let mut conditions: Vec<&str> = Vec::new();
+        let mut values: Vec<Box<dyn ToSql>> = Vec::new();
+
+        if let Some(older_than) = policy.older_than {
+            let age = chrono::Duration::from_std(older_than)
+                .map_err(|e| GrokError::InvalidConfig(format!("Invalid prune age: {}", e)))?;
+            conditions.push("COALESCE(updated_at, created_at) < ?");
+            values.push(Box::new((Utc::now() - age).to_rfc3339()));
+        }
+        if policy.orphaned_only {
+            conditions.push("NOT EXISTS (SELECT 1 FROM collection_sessions cs WHERE cs.session_id = sessions.id)");
+        }
+        if let Some(keep) = policy.keep_last_n {
+            conditions.push(
+                "id NOT IN (SELECT id FROM sessions ORDER BY COALESCE(updated_at, created_at) DESC LIMIT ?)",
+            );
+            values.push(Box::new(keep as i64));
+        }
+
+        let sql = format!("SELECT id FROM sessions WHERE {}", conditions.join(" AND "));
+
+        let conn = self.conn.lock().await;
+        let session_ids = conn
+            .prepare(&sql)
+            .and_then(|mut stmt| {
+                stmt.query_map(params_from_iter(values.iter()), |row| {
+                    row.get::<_, String>(0)
+                })?
+                .collect::<std::result::Result<Vec<_>, _>>()
+            })
+            .map_err(|e| GrokError::session("select sessions to prune", e))?;
+
+        let tx = conn
+            .unchecked_transaction()
+            .map_err(|e| GrokError::session("begin transaction", e))?;
+
+        let mut removed_collection_links = 0;
+        for session_id in &session_ids {
+            removed_collection_links += tx
+                .execute(
+                    "DELETE FROM collection_sessions WHERE session_id = ?1",
+                    params![session_id],
+                )
+                .map_err(|e| GrokError::collection("remove collection links", e))?;
+            tx.execute(
+                "DELETE FROM message_fts WHERE session_id = ?1",
+                params![session_id],
+            )
+            .map_err(|e| GrokError::session("delete session index", e).with_id(session_id))?;
+            tx.execute(
+                "DELETE FROM messages WHERE session_id = ?1",
+                params![session_id],
+            )
+            .map_err(|e| GrokError::session("delete session messages", e).with_id(session_id))?;
+            tx.execute("DELETE FROM sessions WHERE id = ?1", params![session_id])
+                .map_err(|e| GrokError::session("delete session", e).with_id(session_id))?;
+        }
+
+        removed_collection_links += tx
+            .execute(
+                "DELETE FROM collection_sessions WHERE session_id NOT IN (SELECT id FROM sessions)",
+                [],
+            )
+            .map_err(|e| GrokError::collection("remove collection links", e))?;
+
+        if removed_collection_links > 0 {
+            tx.execute(
+                "UPDATE collections SET session_count =
+                     (SELECT COUNT(*) FROM collection_sessions cs WHERE cs.collection_id = collections.id)",
+                [],
+            ).map_err(|e| GrokError::collection("update collection counts", e))?;
+        }
+
+        tx.commit()
+            .map_err(|e| GrokError::session("commit prune", e))?;
+
+        Ok(PruneReport {
+            removed_sessions: session_ids,
+            removed_collection_links,
+        })
+    }
+
+    /// Record the token usage and latency of a completion
+    ///
+    /// The cost is estimated when pricing for the model was configured on the builder.
+    pub async fn record_usage(
+        &self,
+        session_id: Option<&str>,
+        completion: &ChatCompletion,
+        latency: Duration,
+    ) -> Result<()> {
+        let (prompt_tokens, completion_tokens, total_tokens) = completion
+            .usage
+            .as_ref()
+            .map(|u| (u.prompt_tokens, u.completion_tokens, u.total_tokens))
+            .unwrap_or((0, 0, 0));
+
+        let estimated_cost = self
+            .pricing
+            .get(&completion.model)
+            .map(|pricing| pricing.estimate(prompt_tokens, completion_tokens));
+
+        let conn = self.conn.lock().await;
+        conn.execute(
+            "INSERT INTO usage_records
+                 (recorded_at, session_id, model, prompt_tokens, completion_tokens, total_tokens, estimated_cost, latency_ms)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)",
+            params![
+                Utc::now().to_rfc3339(),
+                session_id,
+                completion.model,
+                prompt_tokens,
+                completion_tokens,
+                total_tokens,
+                estimated_cost,
+                latency.as_millis() as i64
+            ],
+        ).map_err(|e| GrokError::session("record usage", e))?;
+
+        Ok(())
+    }
+
+    /// The versioned prompt templates kept in this database
+    pub fn prompts(&self) -> PromptStore {
+        PromptStore {
+            conn: self.conn.clone(),
+        }
+    }
+
+    /// Load audit records in the order they were written
+    ///
+    /// With `exchange_id`, only the records of that request attempt are loaded.
+    pub async fn audit_log(
+        &self,
+        exchange_id: Option<&str>,
+        since: Option<DateTime<Utc>>,
+    ) -> Result<Vec<AuditRecord>> {
+        let conn = self.conn.lock().await;
+        let mut stmt = conn.prepare(
+            "SELECT exchange_id, request_id, endpoint, kind, status, sequence, body, recorded_at FROM audit_log
+             WHERE (?1 IS NULL OR exchange_id = ?1) AND (?2 IS NULL OR recorded_at >= ?2)
+             ORDER BY id",
+        ).map_err(|e| GrokError::session("prepare statement", e))?;
+
+        let rows = stmt
+            .query_map(
+                params![exchange_id, since.map(|since| since.to_rfc3339())],
+                |row| {
+                    Ok((
+                        row.get::<_, String>(0)?,
+                        row.get::<_, Option<String>>(1)?,
+                        row.get::<_, String>(2)?,
+                        row.get::<_, String>(3)?,
+                        row.get::<_, Option<u16>>(4)?,
+                        row.get::<_, u32>(5)?,
+                        row.get::<_, String>(6)?,
+                        row.get::<_, String>(7)?,
+                    ))
+                },
+            )
+            .and_then(|rows| rows.collect::<std::result::Result<Vec<_>, _>>())
+            .map_err(|e| GrokError::session("read audit log", e))?;
+
+        rows.into_iter()
+            .map(
+                |(exchange_id, request_id, endpoint, kind, status, sequence, body, recorded_at)| {
+                    Ok(AuditRecord {
+                        exchange_id,
+                        request_id,
+                        endpoint,
+                        kind: kind.parse()?,
+                        status,
+                        sequence,
+                        body,
+                        recorded_at: parse_timestamp(&recorded_at)?,
+                    })
+                },
+            )
+            .collect()
+    }
+
+    /// Roll up recorded usage per UTC day, oldest first
+    pub async fn usage_by_day(&self, since: Option<DateTime<Utc>>) -> Result<Vec<UsageRollup>> {
+        self.usage_rollup("substr(recorded_at, 1, 10)", since).await
+    }
+
+    /// Roll up recorded usage per session
+    ///
+    /// Completions made outside a session are grouped under an empty key.
+    pub async fn usage_by_session(&self, since: Option<DateTime<Utc>>) -> Result<Vec<UsageRollup>> {
+        self.usage_rollup("COALESCE(session_id, '')", since).await
+    }
+
+    /// Usage grouped by the tenant of each completion's session, optionally since a point in time
+    ///
+    /// Completions outside any tenant's sessions are grouped under an empty key.
+    pub async fn usage_by_tenant(&self, since: Option<DateTime<Utc>>) -> Result<Vec<UsageRollup>> {
+        self.usage_rollup(
+            "COALESCE((SELECT s.tenant FROM sessions s WHERE s.id = usage_records.session_id), '')",
+            since,
+        )
+        .await
+    }
+
+    async fn usage_rollup(
+        &self,
+        group_by: &str,
+        since: Option<DateTime<Utc>>,
+    ) -> Result<Vec<UsageRollup>> {
+        let sql = format!(
+            "SELECT {group_by} AS key, COUNT(*), SUM(prompt_tokens), SUM(completion_tokens), SUM(total_tokens),
+                    SUM(estimated_cost), AVG(latency_ms)
+             FROM usage_records
+             WHERE recorded_at >= ?1
+             GROUP BY key
+             ORDER BY key",
+        );
+        let since = since.map(|dt| dt.to_rfc3339()).unwrap_or_default();
+
+        let conn = self.conn.lock().await;
+        let mut stmt = conn
+            .prepare(&sql)
+            .map_err(|e| GrokError::session("prepare statement", e))?;
+
+        let rollups = stmt
+            .query_map(params![since], |row| {
+                Ok(UsageRollup {
+                    key: row.get(0)?,
+                    requests: row.get::<_, i64>(1)? as u64,
+                    prompt_tokens: row.get::<_, i64>(2)? as u64,
+                    completion_tokens: row.get::<_, i64>(3)? as u64,
+                    total_tokens: row.get::<_, i64>(4)? as u64,
+                    estimated_cost: row.get(5)?,
+                    average_latency: Duration::from_millis(row.get::<_, f64>(6)? as u64),
+                })
+            })
+            .and_then(|rows| rows.collect::<std::result::Result<Vec<_>, _>>())
+            .map_err(|e| GrokError::session("query usage", e))?;
+
+        Ok(rollups)
+    }
+
+    /// Re-encrypt the database under a new SQLCipher key
+    #[cfg(feature = "sqlcipher")]
+    pub async fn rekey(&self, key: impl Into<String>) -> Result<()> {
+        let conn = self.conn.lock().await;
+        conn.pragma_update(None, "rekey", key.into())
+            .map_err(|e| GrokError::session("change encryption key", e))
+    }
+
+    /// Get changes recorded after `cursor`, oldest first
+    ///
+    /// Pass `0` to read the whole feed, or the `seq` of the last change seen
+    /// to resume. Changes made by any process sharing the database appear here.
+    pub async fn changes_since(&self, cursor: i64) -> Result<Vec<Change>> {
+        let conn = self.conn.lock().await;
+        let mut stmt = conn.prepare(
+            "SELECT seq, entity, entity_id, operation, detail, changed_at FROM changelog WHERE seq > ?1 ORDER BY seq",
+        ).map_err(|e| GrokError::session("prepare statement", e))?;
+
+        let rows = stmt
+            .query_map(params![cursor], |row| {
+                Ok((
+                    row.get::<_, i64>(0)?,
+                    row.get::<_, String>(1)?,
+                    row.get::<_, String>(2)?,
+                    row.get::<_, String>(3)?,
+                    row.get::<_, Option<String>>(4)?,
+                    row.get::<_, String>(5)?,
+                ))
+            })
+            .and_then(|rows| rows.collect::<std::result::Result<Vec<_>, _>>())
+            .map_err(|e| GrokError::session("read changes", e))?;
+
+        rows.into_iter()
+            .map(|(seq, entity, entity_id, operation, detail, changed_at)| {
+                Ok(Change {
+                    seq,
+                    entity: ChangeEntity::from_str(&entity)?,
+                    entity_id,
+                    operation: ChangeOperation::from_str(&operation)?,
+                    detail,
+                    changed_at: parse_timestamp(&changed_at)?,
+                })
+            })
+            .collect()
+    }
+
+    /// Get the cursor of the most recent change, for starting a feed at "now"
+    pub async fn latest_change(&self) -> Result<i64> {
+        let conn = self.conn.lock().await;
+        conn.query_row("SELECT COALESCE(MAX(seq), 0) FROM changelog", [], |row| {
+            row.get(0)
+        })
+        .map_err(|e| GrokError::session("read changes", e))
+    }
+
+    /// Delete changes up to and including `cursor` once every reader is past it
+    pub async fn trim_changes(&self, cursor: i64) -> Result<usize> {
+        let conn = self.conn.lock().await;
+        conn.execute("DELETE FROM changelog WHERE seq <= ?1", params![cursor])
+            .map_err(|e| GrokError::session("trim changes", e))
+    }
+
+    /// Stream changes after `cursor`, polling the database every `poll_interval`
+    ///
+    /// The stream never ends on its own; errors are yielded and polling continues.
+    pub fn watch(
+        &self,
+        cursor: i64,
+        poll_interval: Duration,
+    ) -> impl Stream<Item = Result<Change>> + '_ {
+        let state = (cursor, VecDeque::new(), false);
+        futures::stream::unfold(
+            state,
+            move |(mut cursor, mut pending, mut wait)| async move {
+                loop {
+                    if let Some(change) = pending.pop_front() {
+                        return Some((Ok(change), (cursor, pending, wait)));
+                    }
+
+                    if wait {
+                        tokio::time::sleep(poll_interval).await;
+                    }
+
+                    match self.changes_since(cursor).await {
+                        Ok(changes) => {
+                            wait = changes.is_empty();
+                            if let Some(last) = changes.last() {
+                                cursor = last.seq;
+                            }
+                            pending.extend(changes);
+                        }
+                        Err(e) => return Some((Err(e), (cursor, pending, true))),
+                    }
+                }
+            },
+        )
+    }
+
+    /// Save a collection to storage
+    pub async fn save_collection(&self, collection: &crate::collections::Collection) -> Result<()> {
+        self.save_collections(std::slice::from_ref(collection))
+            .await
+    }
+
+    /// Save many collections in a single transaction
+    #[cfg_attr(feature = "tracing", tracing::instrument(name = "grok.storage.save_collections", skip_all, fields(collections = collections.len())))]
+    pub async fn save_collections<C: Borrow<crate::collections::Collection>>(
+        &self,
+        collections: &[C],
+    ) -> Result<()> {
+        let conn = self.conn.lock().await;
+        let tx = conn
+            .unchecked_transaction()
+            .map_err(|e| GrokError::collection("begin transaction", e))?;
+
+        for collection in collections {
+            Self::write_collection(&tx, collection.borrow())?;
+        }
+
+        tx.commit()
+            .map_err(|e| GrokError::collection("commit collections", e))?;
+
+        Ok(())
+    }
+
+    /// Import conversations from a ChatGPT data export or OpenAI-format transcripts
+    ///
+    /// Accepts ChatGPT's `conversations.json`, a JSON array of `{"messages": [...]}`
+    /// transcripts, a single transcript, or JSONL with one transcript per line
+    /// (as written by `export_jsonl`). Each conversation becomes a new session.
+    pub async fn import_openai_export<P: AsRef<Path>>(&self, path: P) -> Result<ImportReport> {
+        self.import_openai_export_with_options(path, &ImportOptions::default())
+            .await
+    }
+
+    /// Import conversations with explicit options
+    pub async fn import_openai_export_with_options<P: AsRef<Path>>(
+        &self,
+        path: P,
+        options: &ImportOptions,
+    ) -> Result<ImportReport> {
+        // Reading and parsing a large export would otherwise stall the runtime
+        let path = path.as_ref().to_path_buf();
+        let conversations = tokio::task::spawn_blocking(move || {
+            let text = std::fs::read_to_string(&path)
+                .map_err(|e| GrokError::session("read export file", e))?;
+            parse_openai_export(&text)
+        })
+        .await
+        .map_err(|e| GrokError::session("read export file", e))??;
+
+        let conn = self.conn.lock().await;
+        let tx = conn
+            .unchecked_transaction()
+            .map_err(|e| GrokError::session("begin transaction", e))?;
+
+        let now = Utc::now();
+        let mut report = ImportReport::default();
+        let mut total_messages = 0;
+        for conversation in conversations {
+            if conversation.messages.is_empty() {
+                report.skipped += 1;
+                continue;
+            }
+
+            let id = uuid::Uuid::new_v4().to_string();
+            let created_at = conversation.created_at.unwrap_or(now);
+            let row = SessionRow {
+                id: &id,
+                model: options.model,
+                created_at,
+                title: conversation.title.as_deref(),
+                updated_at: conversation.updated_at.unwrap_or(created_at),
+                tools: &[],
+                prompt: None,
+                tenant: None,
+                interrupted: &[],
+                written: None,
+            };
+            Self::write_session_row(&tx, &row, &conversation.messages, self.message_storage)?;
+
+            total_messages += conversation.messages.len();
+            report.session_ids.push(id);
+        }
+
+        if let Some(ref name) = options.collection_name {
+            let collection_id = uuid::Uuid::new_v4().to_string();
+            tx.execute(
+                "INSERT INTO collections
+                     (id, name, description, created_at, tags, updated_at, session_count, total_messages, total_tokens)
+                 VALUES (?1, ?2, NULL, ?3, '[]', ?3, ?4, ?5, 0)",
+                params![
+                    collection_id,
+                    name,
+                    now.to_rfc3339(),
+                    report.session_ids.len() as i64,
+                    total_messages as i64
+                ],
+            ).map_err(|e| GrokError::collection("save collection", e))?;
+
+            for session_id in &report.session_ids {
+                tx.execute(
+                    "INSERT INTO collection_sessions (collection_id, session_id, added_at) VALUES (?1, ?2, ?3)",
+                    params![collection_id, session_id, now.to_rfc3339()],
+                ).map_err(|e| GrokError::collection("save collection session", e))?;
+            }
+
+            report.collection_id = Some(collection_id);
+        }
+
+        tx.commit()
+            .map_err(|e| GrokError::session("commit import", e))?;
+
+        Ok(report)
+    }
+
+    /// Write one collection row and its session associations
+    fn write_collection(
+        conn: &Connection,
+        collection: &crate::collections::Collection,
+    ) -> Result<()> {
+        let metadata = collection.metadata();
+        let tags_json = serde_json::to_string(&metadata.tags).map_err(|e| {
+            GrokError::collection("serialize tags", e).with_id(collection.id.as_str())
+        })?;
+
+        // An upsert rather than INSERT OR REPLACE, which would delete the row and
+        // lean on the cascade (off without foreign keys) to drop removed links
+        conn.execute(
+            "INSERT INTO collections
+                 (id, name, description, created_at, tags, updated_at, session_count, total_messages, total_tokens, tenant)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10)
+             ON CONFLICT(id) DO UPDATE SET
+                 name = excluded.name,
+                 description = excluded.description,
+                 tags = excluded.tags,
+                 updated_at = excluded.updated_at,
+                 session_count = excluded.session_count,
+                 total_messages = excluded.total_messages,
+                 total_tokens = excluded.total_tokens,
+                 tenant = excluded.tenant",
+            params![
+                collection.id,
+                metadata.name,
+                metadata.description,
+                metadata.created_at.to_rfc3339(),
+                tags_json,
+                metadata.updated_at.to_rfc3339(),
+                metadata.session_count as i64,
+                metadata.total_messages as i64,
+                metadata.total_tokens as i64,
+                metadata.tenant
+            ],
+        ).map_err(|e| GrokError::collection("save collection", e).with_id(collection.id.as_str()))?;
+
+        // Sync session associations, keeping when existing ones were added
+        let session_ids = collection.session_ids();
+        conn.execute(
+            "DELETE FROM collection_sessions
+             WHERE collection_id = ?1 AND session_id NOT IN (SELECT value FROM json_each(?2))",
+            params![collection.id, serde_json::to_string(&session_ids)?],
+        )
+        .map_err(|e| {
+            GrokError::collection("clear collection sessions", e).with_id(collection.id.as_str())
+        })?;
+
+        let added_at = chrono::Utc::now().to_rfc3339();
+        for session_id in session_ids {
+            conn.execute(
+                "INSERT INTO collection_sessions (collection_id, session_id, added_at) VALUES (?1, ?2, ?3)
+                 ON CONFLICT(collection_id, session_id) DO NOTHING",
+                params![collection.id, session_id, added_at],
+            ).map_err(|e| GrokError::collection("save collection session", e).with_id(collection.id.as_str()))?;
+        }
+
+        Ok(())
+    }
+
+    /// Load a collection from storage
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(name = "grok.storage.load_collection", skip(self))
+    )]
+    pub async fn load_collection(
+        &self,
+        collection_id: &str,
+    ) -> Result<Option<crate::collections::Collection>> {
+        self.load_collection_scoped(collection_id, None).await
+    }
+
+    /// Load a collection by ID, if it belongs to `tenant`
+    ///
+    /// Another tenant's collection is treated as missing and none of it is read.
+    pub async fn load_collection_for(
+        &self,
+        tenant: &str,
+        collection_id: &str,
+    ) -> Result<Option<crate::collections::Collection>> {
+        self.load_collection_scoped(collection_id, Some(tenant))
+            .await
+    }
+
+    async fn load_collection_scoped(
+        &self,
+        collection_id: &str,
+        tenant: Option<&str>,
+    ) -> Result<Option<crate::collections::Collection>> {
+        let conn = self.conn.lock().await;
+
+        // Load collection metadata
+        let collection_data = conn.query_row(
+            "SELECT id, name, description, created_at, tags, updated_at, session_count, total_messages, total_tokens, tenant
+             FROM collections WHERE id = ?1 AND (?2 IS NULL OR tenant = ?2)",
+            params![collection_id, tenant],
+            |row| {
+                let id: String = row.get(0)?;
+                let name: String = row.get(1)?;
+                let description: Option<String> = row.get(2)?;
+                let created_at_str: String = row.get(3)?;
+                let tags_json: String = row.get(4)?;
+                let updated_at_str: Option<String> = row.get(5)?;
+                let session_count: i64 = row.get(6)?;
+                let total_messages: i64 = row.get(7)?;
+                let total_tokens: i64 = row.get(8)?;
+                let tenant: Option<String> = row.get(9)?;
 
                 let created_at = chrono::DateTime::parse_from_rfc3339(&created_at_str)
                     .map_err(|_| rusqlite::Error::InvalidColumnType(3, "created_at".to_string(), rusqlite::types::Type::Text))?
                     .with_timezone(&chrono::Utc);
 
-                Ok((id, name, description, created_at))
+                let tags: Vec<String> = serde_json::from_str(&tags_json)
+                    .map_err(|_| rusqlite::Error::InvalidColumnType(4, "tags".to_string(), rusqlite::types::Type::Text))?;
+
+                let updated_at = match updated_at_str {
+                    Some(value) => chrono::DateTime::parse_from_rfc3339(&value)
+                        .map_err(|_| rusqlite::Error::InvalidColumnType(5, "updated_at".to_string(), rusqlite::types::Type::Text))?
+                        .with_timezone(&chrono::Utc),
+                    None => created_at,
+                };
+
+                let metadata = crate::collections::CollectionMetadata {
+                    name,
+                    description,
+                    created_at,
+                    updated_at,
+                    tags,
+                    session_count: session_count as usize,
+                    total_messages: total_messages as usize,
+                    total_tokens: total_tokens as u64,
+                    tenant,
+                };
+
+                Ok((id, metadata))
             }
-        ).optional().map_err(|e| GrokError::Collection(format!("Failed to load collection: {}", e)))?;
+        ).optional().map_err(|e| GrokError::collection("load collection", e).with_id(collection_id))?;
 
-        if let Some((id, name, description, created_at)) = collection_data {
+        if let Some((id, metadata)) = collection_data {
             // Load associated session IDs
             let mut stmt = conn.prepare("SELECT session_id FROM collection_sessions WHERE collection_id = ?1 ORDER BY added_at")
-                .map_err(|e| GrokError::Collection(format!("Failed to prepare statement: {}", e)))?;
+                .map_err(|e| GrokError::collection("prepare statement", e))?;
 
-            let session_ids = stmt.query_map(params![collection_id], |row| row.get(0))?
-                .collect::<std::result::Result<Vec<String>, _>>()
-                .map_err(|e| GrokError::Collection(format!("Failed to load collection sessions: {}", e)))?;
+            let session_ids = stmt
+                .query_map(params![collection_id], |row| row.get(0))
+                .and_then(|rows| rows.collect::<std::result::Result<Vec<String>, _>>())
+                .map_err(|e| {
+                    GrokError::collection("load collection sessions", e).with_id(collection_id)
+                })?;
 
-            let collection = crate::collections::Collection::restore(id, name, description, created_at, session_ids);
+            let collection = crate::collections::Collection::restore(id, metadata, session_ids);
             Ok(Some(collection))
         } else {
             Ok(None)
@@ -244,32 +2368,1480 @@ impl SqliteStorage {
     }
 
     /// Delete a collection from storage
+    ///
+    /// Its session links are removed with it; the sessions themselves are kept.
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(name = "grok.storage.delete_collection", skip(self))
+    )]
     pub async fn delete_collection(&self, collection_id: &str) -> Result<()> {
-        let conn = self.conn.read().await;
-        conn.execute(
+        let conn = self.conn.lock().await;
+        let tx = conn
+            .unchecked_transaction()
+            .map_err(|e| GrokError::collection("begin transaction", e))?;
+
+        tx.execute(
+            "DELETE FROM collection_sessions WHERE collection_id = ?1",
+            params![collection_id],
+        )
+        .map_err(|e| GrokError::collection("remove collection links", e).with_id(collection_id))?;
+        tx.execute(
             "DELETE FROM collections WHERE id = ?1",
             params![collection_id],
-        ).map_err(|e| GrokError::Collection(format!("Failed to delete collection: {}", e)))?;
+        )
+        .map_err(|e| GrokError::collection("delete collection", e).with_id(collection_id))?;
 
-        Ok(())
+        tx.commit().map_err(|e| {
+            GrokError::collection("commit collection delete", e).with_id(collection_id)
+        })
     }
 
     /// List all collection IDs
     pub async fn list_collections(&self) -> Result<Vec<String>> {
-        let conn = self.conn.read().await;
-        let mut stmt = conn.prepare("SELECT id FROM collections ORDER BY created_at DESC")
-            .map_err(|e| GrokError::Collection(format!("Failed to prepare statement: {}", e)))?;
+        let conn = self.conn.lock().await;
+        let mut stmt = conn
+            .prepare("SELECT id FROM collections ORDER BY created_at DESC")
+            .map_err(|e| GrokError::collection("prepare statement", e))?;
 
-        let ids = stmt.query_map([], |row| row.get(0))?
-            .collect::<std::result::Result<Vec<String>, _>>()
-            .map_err(|e| GrokError::Collection(format!("Failed to list collections: {}", e)))?;
+        let ids = stmt
+            .query_map([], |row| row.get(0))
+            .and_then(|rows| rows.collect::<std::result::Result<Vec<String>, _>>())
+            .map_err(|e| GrokError::collection("list collections", e))?;
 
         Ok(ids)
     }
+
+    /// List the IDs of the collections belonging to `tenant`, newest first
+    pub async fn list_collections_for(&self, tenant: &str) -> Result<Vec<String>> {
+        let conn = self.conn.lock().await;
+        let mut stmt = conn
+            .prepare("SELECT id FROM collections WHERE tenant = ?1 ORDER BY created_at DESC")
+            .map_err(|e| GrokError::collection("prepare statement", e))?;
+
+        let ids = stmt
+            .query_map(params![tenant], |row| row.get(0))
+            .and_then(|rows| rows.collect::<std::result::Result<Vec<String>, _>>())
+            .map_err(|e| GrokError::collection("list collections", e))?;
+
+        Ok(ids)
+    }
+
+    /// Count what a tenant has stored
+    pub async fn tenant_stats(&self, tenant: &str) -> Result<TenantStats> {
+        let conn = self.conn.lock().await;
+        let map_err = |e: rusqlite::Error| GrokError::session("read tenant stats", e);
+
+        let (sessions, messages): (i64, i64) = conn
+            .query_row(
+                "SELECT COUNT(*), COALESCE(SUM(message_count), 0) FROM sessions WHERE tenant = ?1",
+                params![tenant],
+                |row| Ok((row.get(0)?, row.get(1)?)),
+            )
+            .map_err(map_err)?;
+        let collections: i64 = conn
+            .query_row(
+                "SELECT COUNT(*) FROM collections WHERE tenant = ?1",
+                params![tenant],
+                |row| row.get(0),
+            )
+            .map_err(map_err)?;
+        let (requests, total_tokens): (i64, i64) = conn
+            .query_row(
+                "SELECT COUNT(*), COALESCE(SUM(u.total_tokens), 0)
+                 FROM usage_records u JOIN sessions s ON s.id = u.session_id
+                 WHERE s.tenant = ?1",
+                params![tenant],
+                |row| Ok((row.get(0)?, row.get(1)?)),
+            )
+            .map_err(map_err)?;
+
+        Ok(TenantStats {
+            sessions: sessions as usize,
+            messages: messages as usize,
+            collections: collections as usize,
+            requests: requests as u64,
+            total_tokens: total_tokens as u64,
+        })
+    }
+}
+
+#[async_trait]
+impl Storage for SqliteStorage {
+    async fn save_session(&self, session: &Session) -> Result<()> {
+        SqliteStorage::save_session(self, session).await
+    }
+
+    async fn load_session(&self, session_id: &str) -> Result<Option<Session>> {
+        SqliteStorage::load_session(self, session_id).await
+    }
+
+    async fn delete_session(&self, session_id: &str) -> Result<()> {
+        SqliteStorage::delete_session(self, session_id).await
+    }
+
+    async fn list_sessions(&self) -> Result<Vec<String>> {
+        SqliteStorage::list_sessions(self).await
+    }
+
+    async fn load_session_for(&self, tenant: &str, session_id: &str) -> Result<Option<Session>> {
+        SqliteStorage::load_session_for(self, tenant, session_id).await
+    }
+
+    async fn list_sessions_for(&self, tenant: &str) -> Result<Vec<String>> {
+        SqliteStorage::list_sessions_for(self, tenant).await
+    }
+
+    async fn save_collection(&self, collection: &crate::collections::Collection) -> Result<()> {
+        SqliteStorage::save_collection(self, collection).await
+    }
+
+    async fn load_collection(
+        &self,
+        collection_id: &str,
+    ) -> Result<Option<crate::collections::Collection>> {
+        SqliteStorage::load_collection(self, collection_id).await
+    }
+
+    async fn delete_collection(&self, collection_id: &str) -> Result<()> {
+        SqliteStorage::delete_collection(self, collection_id).await
+    }
+
+    async fn list_collections(&self) -> Result<Vec<String>> {
+        SqliteStorage::list_collections(self).await
+    }
+
+    async fn load_collection_for(
+        &self,
+        tenant: &str,
+        collection_id: &str,
+    ) -> Result<Option<crate::collections::Collection>> {
+        SqliteStorage::load_collection_for(self, tenant, collection_id).await
+    }
+
+    async fn list_collections_for(&self, tenant: &str) -> Result<Vec<String>> {
+        SqliteStorage::list_collections_for(self, tenant).await
+    }
+
+    async fn save_partial_reply(
+        &self,
+        session_id: &str,
+        index: usize,
+        message: &Message,
+    ) -> Result<()> {
+        SqliteStorage::save_partial_reply(self, session_id, index, message).await
+    }
+
+    async fn record_usage(
+        &self,
+        session_id: Option<&str>,
+        completion: &ChatCompletion,
+        latency: Duration,
+    ) -> Result<()> {
+        SqliteStorage::record_usage(self, session_id, completion, latency).await
+    }
+
+    async fn session_usage(&self, session_id: &str) -> Result<Option<crate::replay::ReplayUsage>> {
+        Ok(self
+            .usage_by_session(None)
+            .await?
+            .into_iter()
+            .find(|rollup| rollup.key == session_id)
+            .map(|rollup| crate::replay::ReplayUsage {
+                completions: rollup.requests,
+                prompt_tokens: rollup.prompt_tokens,
+                completion_tokens: rollup.completion_tokens,
+                average_latency: rollup.average_latency,
+            }))
+    }
+}
+
+/// Records are kept in the `audit_log` table
+#[async_trait]
+impl AuditSink for SqliteStorage {
+    async fn record_audit(&self, records: &[AuditRecord]) -> Result<()> {
+        let conn = self.conn.lock().await;
+        let tx = conn
+            .unchecked_transaction()
+            .map_err(|e| GrokError::session("begin transaction", e))?;
+
+        for record in records {
+            tx.execute(
+                "INSERT INTO audit_log (exchange_id, request_id, endpoint, kind, status, sequence, body, recorded_at)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)",
+                params![
+                    record.exchange_id,
+                    record.request_id,
+                    record.endpoint,
+                    record.kind.as_str(),
+                    record.status,
+                    record.sequence,
+                    record.body,
+                    record.recorded_at.to_rfc3339()
+                ],
+            ).map_err(|e| GrokError::session("record audit entry", e))?;
+        }
+
+        tx.commit()
+            .map_err(|e| GrokError::session("commit audit entries", e))
+    }
+
+    async fn prune_audit(&self, cutoff: DateTime<Utc>) -> Result<usize> {
+        let conn = self.conn.lock().await;
+        conn.execute(
+            "DELETE FROM audit_log WHERE recorded_at < ?1",
+            params![cutoff.to_rfc3339()],
+        )
+        .map_err(|e| GrokError::session("prune audit log", e))
+    }
+}
+
+/// Named, versioned prompt templates, kept in the same database as sessions
+///
+/// Every `publish` adds a new version and makes it the active one, which
+/// `get` returns for a bare name. Earlier versions stay available as
+/// `name@version` and can be made active again with `activate` or `rollback`.
+#[derive(Debug, Clone)]
+pub struct PromptStore {
+    conn: Arc<Mutex<Connection>>,
+}
+
+impl PromptStore {
+    /// Store a new version of a prompt and make it active
+    pub async fn publish(
+        &self,
+        name: &str,
+        template: impl Into<String>,
+        description: Option<&str>,
+    ) -> Result<Prompt> {
+        if name.is_empty() || name.contains('@') {
+            return Err(GrokError::InvalidConfig(format!(
+                "Invalid prompt name '{}'",
+                name
+            )));
+        }
+        let template = template.into();
+        let created_at = Utc::now();
+
+        let conn = self.conn.lock().await;
+        let tx = conn
+            .unchecked_transaction()
+            .map_err(|e| GrokError::session("begin transaction", e))?;
+
+        let version: u32 = tx
+            .query_row(
+                "SELECT COALESCE(MAX(version), 0) + 1 FROM prompts WHERE name = ?1",
+                params![name],
+                |row| row.get(0),
+            )
+            .map_err(|e| GrokError::session("read prompt versions", e))?;
+        tx.execute(
+            "INSERT INTO prompts (name, version, template, description, created_at) VALUES (?1, ?2, ?3, ?4, ?5)",
+            params![name, version, template, description, created_at.to_rfc3339()],
+        ).map_err(|e| GrokError::session("save prompt", e))?;
+        Self::write_active(&tx, name, version)?;
+
+        tx.commit()
+            .map_err(|e| GrokError::session("commit prompt", e))?;
+
+        Ok(Prompt {
+            name: name.to_string(),
+            version,
+            template,
+            description: description.map(str::to_string),
+            created_at,
+        })
+    }
+
+    /// Load a prompt by `name@version`, or the active version by `name`
+    pub async fn get(&self, reference: &str) -> Result<Prompt> {
+        let conn = self.conn.lock().await;
+        let (name, version) = match reference.rsplit_once('@') {
+            Some((name, version)) => {
+                let version = version.parse().map_err(|_| {
+                    GrokError::InvalidConfig(format!("Invalid prompt version in '{}'", reference))
+                })?;
+                (name, version)
+            }
+            None => (reference, Self::read_active(&conn, reference)?),
+        };
+
+        conn.query_row(
+            "SELECT name, version, template, description, created_at FROM prompts WHERE name = ?1 AND version = ?2",
+            params![name, version],
+            Self::prompt_from_row,
+        )
+        .optional()
+        .map_err(|e| GrokError::session("load prompt", e))?
+        .ok_or_else(|| GrokError::NotFound { entity: Entity::Prompt, id: reference.to_string() })?
+        .into_prompt()
+    }
+
+    /// Every version of a prompt, oldest first
+    pub async fn versions(&self, name: &str) -> Result<Vec<Prompt>> {
+        let conn = self.conn.lock().await;
+        let mut stmt = conn.prepare(
+            "SELECT name, version, template, description, created_at FROM prompts WHERE name = ?1 ORDER BY version",
+        ).map_err(|e| GrokError::session("prepare statement", e))?;
+
+        let rows = stmt
+            .query_map(params![name], Self::prompt_from_row)
+            .and_then(|rows| rows.collect::<std::result::Result<Vec<_>, _>>())
+            .map_err(|e| GrokError::session("load prompt versions", e))?;
+
+        rows.into_iter().map(PromptRow::into_prompt).collect()
+    }
+
+    /// Names of every stored prompt, sorted
+    pub async fn list(&self) -> Result<Vec<String>> {
+        let conn = self.conn.lock().await;
+        let mut stmt = conn
+            .prepare("SELECT name FROM prompt_heads ORDER BY name")
+            .map_err(|e| GrokError::session("prepare statement", e))?;
+
+        let names = stmt
+            .query_map([], |row| row.get(0))
+            .and_then(|rows| rows.collect::<std::result::Result<Vec<_>, _>>())
+            .map_err(|e| GrokError::session("list prompts", e))?;
+
+        Ok(names)
+    }
+
+    /// Make a stored version the one a bare name refers to
+    pub async fn activate(&self, name: &str, version: u32) -> Result<()> {
+        let conn = self.conn.lock().await;
+        let exists = conn
+            .query_row(
+                "SELECT 1 FROM prompts WHERE name = ?1 AND version = ?2",
+                params![name, version],
+                |_| Ok(()),
+            )
+            .optional()
+            .map_err(|e| GrokError::session("load prompt", e))?;
+        if exists.is_none() {
+            return Err(GrokError::NotFound {
+                entity: Entity::Prompt,
+                id: format!("{}@{}", name, version),
+            });
+        }
+
+        Self::write_active(&conn, name, version)
+    }
+
+    /// Make the version before the active one active again, returning it
+    pub async fn rollback(&self, name: &str) -> Result<Prompt> {
+        let previous: Option<u32> = {
+            let conn = self.conn.lock().await;
+            let active = Self::read_active(&conn, name)?;
+            conn.query_row(
+                "SELECT MAX(version) FROM prompts WHERE name = ?1 AND version < ?2",
+                params![name, active],
+                |row| row.get(0),
+            )
+            .map_err(|e| GrokError::session("read prompt versions", e))?
+        };
+        let Some(previous) = previous else {
+            return Err(GrokError::InvalidConfig(format!(
+                "Prompt '{}' has no earlier version to roll back to",
+                name
+            )));
+        };
+
+        self.activate(name, previous).await?;
+        self.get(&format!("{}@{}", name, previous)).await
+    }
+
+    fn read_active(conn: &Connection, name: &str) -> Result<u32> {
+        conn.query_row(
+            "SELECT version FROM prompt_heads WHERE name = ?1",
+            params![name],
+            |row| row.get(0),
+        )
+        .optional()
+        .map_err(|e| GrokError::session("load prompt", e))?
+        .ok_or_else(|| GrokError::NotFound {
+            entity: Entity::Prompt,
+            id: name.to_string(),
+        })
+    }
+
+    fn write_active(conn: &Connection, name: &str, version: u32) -> Result<()> {
+        conn.execute(
+            "INSERT INTO prompt_heads (name, version) VALUES (?1, ?2)
+             ON CONFLICT(name) DO UPDATE SET version = excluded.version",
+            params![name, version],
+        )
+        .map_err(|e| GrokError::session("activate prompt", e))?;
+        Ok(())
+    }
+
+    fn prompt_from_row(row: &rusqlite::Row<'_>) -> rusqlite::Result<PromptRow> {
+        Ok(PromptRow {
+            name: row.get(0)?,
+            version: row.get(1)?,
+            template: row.get(2)?,
+            description: row.get(3)?,
+            created_at: row.get(4)?,
+        })
+    }
+}
+
+/// A `prompts` row before its timestamp is parsed
+struct PromptRow {
+    name: String,
+    version: u32,
+    template: String,
+    description: Option<String>,
+    created_at: String,
+}
+
+impl PromptRow {
+    fn into_prompt(self) -> Result<Prompt> {
+        Ok(Prompt {
+            created_at: parse_timestamp(&self.created_at)?,
+            name: self.name,
+            version: self.version,
+            template: self.template,
+            description: self.description,
+        })
+    }
+}
+
+/// One version of a stored prompt template
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Prompt {
+    /// Name shared by every version
+    pub name: String,
+    /// Version number, counting up from 1
+    pub version: u32,
+    /// Template text, with `{{variable}}` placeholders
+    pub template: String,
+    /// What changed in this version, or what the prompt is for
+    pub description: Option<String>,
+    /// When this version was published
+    pub created_at: DateTime<Utc>,
+}
+
+impl Prompt {
+    /// The `name@version` reference recorded on sessions started from this prompt
+    pub fn reference(&self) -> String {
+        format!("{}@{}", self.name, self.version)
+    }
+
+    /// Fill in the template's `{{variable}}` placeholders
+    ///
+    /// Fails if the template uses a variable that isn't given.
+    pub fn render(&self, variables: &[(&str, &str)]) -> Result<String> {
+        let mut rendered = String::with_capacity(self.template.len());
+        let mut rest = self.template.as_str();
+        while let Some(start) = rest.find("{{") {
+            let Some(end) = rest[start + 2..].find("}}") else {
+                break;
+            };
+            let key = rest[start + 2..start + 2 + end].trim();
+            let value = variables
+                .iter()
+                .find(|(name, _)| *name == key)
+                .map(|(_, value)| *value)
+                .ok_or_else(|| {
+                    GrokError::InvalidConfig(format!(
+                        "Prompt '{}' needs a value for '{}'",
+                        self.reference(),
+                        key
+                    ))
+                })?;
+            rendered.push_str(&rest[..start]);
+            rendered.push_str(value);
+            rest = &rest[start + 2 + end + 2..];
+        }
+        rendered.push_str(rest);
+        Ok(rendered)
+    }
+}
+
+/// Records are kept in the `rag_chunks` table and searched exhaustively
+#[async_trait]
+impl VectorStore for SqliteStorage {
+    async fn upsert(&self, records: Vec<VectorRecord>) -> Result<()> {
+        let conn = self.conn.lock().await;
+        let tx = conn
+            .unchecked_transaction()
+            .map_err(|e| GrokError::Retrieval(format!("Failed to begin transaction: {}", e)))?;
+
+        for record in &records {
+            let embedding: Vec<u8> = record
+                .embedding
+                .iter()
+                .flat_map(|x| x.to_le_bytes())
+                .collect();
+            let metadata = serde_json::to_string(&record.metadata)?;
+            tx.execute(
+                "INSERT OR REPLACE INTO rag_chunks (id, text, embedding, metadata) VALUES (?1, ?2, ?3, ?4)",
+                params![record.id, record.text, embedding, metadata],
+            ).map_err(|e| GrokError::Retrieval(format!("Failed to store record: {}", e)))?;
+        }
+
+        tx.commit()
+            .map_err(|e| GrokError::Retrieval(format!("Failed to commit records: {}", e)))?;
+        Ok(())
+    }
+
+    async fn query(
+        &self,
+        embedding: &[f32],
+        k: usize,
+        filter: Option<&MetadataFilter>,
+    ) -> Result<Vec<VectorMatch>> {
+        let records = self.vector_records().await?;
+        Ok(crate::vector::nearest(
+            records.into_iter(),
+            embedding,
+            k,
+            filter,
+        ))
+    }
+
+    async fn delete(&self, filter: &MetadataFilter) -> Result<()> {
+        let ids: Vec<String> = self
+            .vector_records()
+            .await?
+            .into_iter()
+            .filter(|record| filter.matches(&record.metadata))
+            .map(|record| record.id)
+            .collect();
+
+        let conn = self.conn.lock().await;
+        let tx = conn
+            .unchecked_transaction()
+            .map_err(|e| GrokError::Retrieval(format!("Failed to begin transaction: {}", e)))?;
+        for id in &ids {
+            tx.execute("DELETE FROM rag_chunks WHERE id = ?1", params![id])
+                .map_err(|e| GrokError::Retrieval(format!("Failed to delete record: {}", e)))?;
+        }
+        tx.commit()
+            .map_err(|e| GrokError::Retrieval(format!("Failed to commit deletion: {}", e)))?;
+        Ok(())
+    }
+}
+
+impl SqliteStorage {
+    /// Load every stored vector record
+    async fn vector_records(&self) -> Result<Vec<VectorRecord>> {
+        let conn = self.conn.lock().await;
+        let mut stmt = conn
+            .prepare("SELECT id, text, embedding, metadata FROM rag_chunks")
+            .map_err(|e| GrokError::Retrieval(format!("Failed to prepare statement: {}", e)))?;
+
+        let rows = stmt
+            .query_map([], |row| {
+                let bytes: Vec<u8> = row.get(2)?;
+                let metadata: String = row.get(3)?;
+                Ok((row.get(0)?, row.get(1)?, bytes, metadata))
+            })
+            .and_then(|rows| {
+                rows.collect::<std::result::Result<Vec<(String, String, Vec<u8>, String)>, _>>()
+            })
+            .map_err(|e| GrokError::Retrieval(format!("Failed to load records: {}", e)))?;
+
+        rows.into_iter()
+            .map(|(id, text, bytes, metadata)| {
+                Ok(VectorRecord {
+                    id,
+                    text,
+                    embedding: bytes
+                        .chunks_exact(4)
+                        .map(|b| f32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+                        .collect(),
+                    metadata: serde_json::from_str(&metadata)?,
+                })
+            })
+            .collect()
+    }
+}
+
+/// Columns of a session row, borrowed from a `Session` or an imported conversation
+struct SessionRow<'a> {
+    id: &'a str,
+    model: Model,
+    created_at: DateTime<Utc>,
+    title: Option<&'a str>,
+    updated_at: DateTime<Utc>,
+    tools: &'a [Tool],
+    prompt: Option<&'a str>,
+    tenant: Option<&'a str>,
+    interrupted: &'a [usize],
+    /// Messages known to be stored already, from this storage's last save
+    written: Option<usize>,
+}
+
+/// A conversation read from an export file
+struct ImportedConversation {
+    title: Option<String>,
+    created_at: Option<DateTime<Utc>>,
+    updated_at: Option<DateTime<Utc>>,
+    messages: Vec<Message>,
+}
+
+/// Parse every conversation in a ChatGPT export or OpenAI-format transcript file
+fn parse_openai_export(text: &str) -> Result<Vec<ImportedConversation>> {
+    let values = match serde_json::from_str::<serde_json::Value>(text) {
+        Ok(serde_json::Value::Array(items)) => items,
+        Ok(value) => vec![value],
+        // Not a single JSON document, so try one transcript per line
+        Err(_) => text
+            .lines()
+            .filter(|line| !line.trim().is_empty())
+            .map(serde_json::from_str)
+            .collect::<std::result::Result<Vec<_>, _>>()
+            .map_err(|e| GrokError::session("parse export file", e))?,
+    };
+
+    values.iter().map(parse_conversation).collect()
+}
+
+/// Parse one conversation, in either ChatGPT's tree format or as a flat message list
+fn parse_conversation(value: &serde_json::Value) -> Result<ImportedConversation> {
+    let title = value["title"].as_str().map(str::to_string);
+    let created_at = value["create_time"].as_f64().and_then(timestamp_from_epoch);
+    let updated_at = value["update_time"].as_f64().and_then(timestamp_from_epoch);
+
+    let messages = if let Some(mapping) = value["mapping"].as_object() {
+        chatgpt_thread(mapping, value["current_node"].as_str())
+    } else if let Some(messages) = value["messages"].as_array() {
+        messages.iter().filter_map(openai_message).collect()
+    } else {
+        return Err(GrokError::session(
+            "parse export file",
+            "conversation has neither `mapping` nor `messages`",
+        ));
+    };
+
+    Ok(ImportedConversation {
+        title,
+        created_at,
+        updated_at,
+        messages,
+    })
+}
+
+/// Flatten ChatGPT's message tree into the thread ending at `current_node`
+///
+/// Branches from edited or regenerated messages are dropped, as are hidden
+/// system messages and tool outputs, which have no matching tool calls.
+fn chatgpt_thread(
+    mapping: &serde_json::Map<String, serde_json::Value>,
+    current_node: Option<&str>,
+) -> Vec<Message> {
+    // Without a current node, follow the first child from the root
+    let leaf = current_node.map(str::to_string).or_else(|| {
+        let mut node = mapping
+            .iter()
+            .find(|(_, n)| n["parent"].is_null())?
+            .0
+            .clone();
+        let mut seen = HashSet::from([node.clone()]);
+        while let Some(child) = mapping.get(&node)?["children"][0].as_str() {
+            if !seen.insert(child.to_string()) {
+                break;
+            }
+            node = child.to_string();
+        }
+        Some(node)
+    });
+
+    // A malformed export can link nodes in a loop, so each is visited once
+    let mut visited = HashSet::new();
+    let mut messages = Vec::new();
+    let mut next = leaf;
+    while let Some(node) = next
+        .filter(|id| visited.insert(id.clone()))
+        .and_then(|id| mapping.get(&id))
+    {
+        let message = &node["message"];
+        let hidden =
+            message["metadata"]["is_visually_hidden_from_conversation"].as_bool() == Some(true);
+        let role = match message["author"]["role"].as_str() {
+            Some("user") => Some(Role::User),
+            Some("assistant") => Some(Role::Assistant),
+            Some("system") => Some(Role::System),
+            _ => None,
+        };
+
+        if let (Some(role), false) = (role, hidden) {
+            let content = message_text(&message["content"]);
+            if !content.is_empty() {
+                messages.push(Message {
+                    role,
+                    content: content.into(),
+                    tool_calls: None,
+                    tool_call_id: None,
+                    name: None,
+                });
+            }
+        }
+
+        next = node["parent"].as_str().map(str::to_string);
+    }
+
+    messages.reverse();
+    messages
+}
+
+/// Convert an OpenAI chat message object, skipping ones with an unknown role
+fn openai_message(value: &serde_json::Value) -> Option<Message> {
+    let role = serde_json::from_value(value["role"].clone()).ok()?;
+    Some(Message {
+        role,
+        content: message_text(&value["content"]).into(),
+        tool_calls: serde_json::from_value(value["tool_calls"].clone()).ok(),
+        tool_call_id: value["tool_call_id"].as_str().map(str::to_string),
+        name: value["name"].as_str().map(str::to_string),
+    })
+}
+
+/// Extract the text of a message's content, which may be a string, a list of
+/// parts, or ChatGPT's `{"parts": [...]}` / `{"text": ...}` objects
+fn message_text(content: &serde_json::Value) -> String {
+    match content {
+        serde_json::Value::String(text) => text.clone(),
+        serde_json::Value::Array(parts) => parts
+            .iter()
+            .filter_map(|part| part.as_str().or_else(|| part["text"].as_str()))
+            .collect::<Vec<_>>()
+            .join("\n"),
+        serde_json::Value::Object(_) if content["parts"].is_array() => {
+            message_text(&content["parts"])
+        }
+        serde_json::Value::Object(_) => content["text"].as_str().unwrap_or_default().to_string(),
+        _ => String::new(),
+    }
+}
+
+/// Convert fractional Unix seconds, as used by ChatGPT exports
+fn timestamp_from_epoch(seconds: f64) -> Option<DateTime<Utc>> {
+    DateTime::from_timestamp(seconds.trunc() as i64, (seconds.fract() * 1e9) as u32)
+}
+
+/// Parse an RFC 3339 timestamp stored in the database
+fn parse_timestamp(value: &str) -> Result<DateTime<Utc>> {
+    DateTime::parse_from_rfc3339(value)
+        .map(|dt| dt.with_timezone(&Utc))
+        .map_err(|e| GrokError::session(format!("parse stored timestamp '{}'", value), e))
+}
+
+/// Kind of record a change refers to
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChangeEntity {
+    /// A session row; `entity_id` is the session ID
+    Session,
+    /// A stored message; `entity_id` is the session ID, `detail` the message index
+    Message,
+    /// A collection row; `entity_id` is the collection ID
+    Collection,
+    /// A collection membership; `entity_id` is the collection ID, `detail` the session ID
+    CollectionSession,
+}
+
+impl ChangeEntity {
+    fn from_str(value: &str) -> Result<Self> {
+        match value {
+            "session" => Ok(ChangeEntity::Session),
+            "message" => Ok(ChangeEntity::Message),
+            "collection" => Ok(ChangeEntity::Collection),
+            "collection_session" => Ok(ChangeEntity::CollectionSession),
+            other => Err(GrokError::session(
+                "read changes",
+                format!("unknown change entity '{}'", other),
+            )),
+        }
+    }
+}
+
+/// Kind of write a change records
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChangeOperation {
+    /// A row was created
+    Insert,
+    /// A row was modified
+    Update,
+    /// A row was removed
+    Delete,
+}
+
+impl ChangeOperation {
+    fn from_str(value: &str) -> Result<Self> {
+        match value {
+            "insert" => Ok(ChangeOperation::Insert),
+            "update" => Ok(ChangeOperation::Update),
+            "delete" => Ok(ChangeOperation::Delete),
+            other => Err(GrokError::session(
+                "read changes",
+                format!("unknown change operation '{}'", other),
+            )),
+        }
+    }
+}
+
+/// An entry in the storage change feed
+#[derive(Debug, Clone)]
+pub struct Change {
+    /// Monotonic sequence number; use it as the cursor for the next read
+    pub seq: i64,
+    /// Kind of record that changed
+    pub entity: ChangeEntity,
+    /// ID of the changed session or collection
+    pub entity_id: String,
+    /// Kind of write
+    pub operation: ChangeOperation,
+    /// Extra detail depending on the entity (message index or member session ID)
+    pub detail: Option<String>,
+    /// When the change was written
+    pub changed_at: DateTime<Utc>,
+}
+
+/// Aggregated usage over a group of completions
+#[derive(Debug, Clone)]
+pub struct UsageRollup {
+    /// Group key: a `YYYY-MM-DD` day, a session ID or a tenant
+    pub key: String,
+    /// Number of completions
+    pub requests: u64,
+    /// Prompt tokens used
+    pub prompt_tokens: u64,
+    /// Completion tokens used
+    pub completion_tokens: u64,
+    /// Total tokens used
+    pub total_tokens: u64,
+    /// Estimated cost, if pricing was known for any of the completions
+    pub estimated_cost: Option<f64>,
+    /// Mean request latency
+    pub average_latency: Duration,
+}
+
+/// Retention policy for pruning stored sessions
+///
+/// Criteria combine: a session is pruned only if it matches all of the set ones.
+#[derive(Debug, Clone, Default)]
+pub struct PrunePolicy {
+    /// Prune sessions with no activity for at least this long
+    pub older_than: Option<Duration>,
+    /// Always keep this many of the most recently active sessions
+    pub keep_last_n: Option<usize>,
+    /// Only prune sessions that don't belong to any collection
+    pub orphaned_only: bool,
+}
+
+/// Size and shape of the database, from `SqliteStorage::stats`
+#[derive(Debug, Clone)]
+pub struct StorageStats {
+    /// Size of the database in bytes, excluding any WAL file
+    pub file_size: u64,
+    /// Bytes held by free pages that `VACUUM` would reclaim
+    pub free_bytes: u64,
+    /// Row count of each table
+    pub table_rows: Vec<(String, u64)>,
+    /// The ten largest sessions by stored bytes, largest first
+    pub largest_sessions: Vec<SessionSize>,
+    /// Indexes defined on the tables
+    pub indexes: Vec<IndexStats>,
+    /// Whether the full-text index holds one entry per stored message
+    pub search_index_in_sync: bool,
+}
+
+/// What a tenant has stored, from `SqliteStorage::tenant_stats`
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct TenantStats {
+    /// Number of sessions
+    pub sessions: usize,
+    /// Number of messages across the sessions
+    pub messages: usize,
+    /// Number of collections
+    pub collections: usize,
+    /// Completions recorded for the sessions
+    pub requests: u64,
+    /// Tokens used by those completions
+    pub total_tokens: u64,
+}
+
+/// Storage used by a single session
+#[derive(Debug, Clone)]
+pub struct SessionSize {
+    /// Session ID
+    pub session_id: String,
+    /// Number of messages in the session
+    pub message_count: usize,
+    /// Approximate bytes of message content, tool calls and tool specs
+    pub bytes: u64,
+}
+
+/// An index defined in the database
+#[derive(Debug, Clone)]
+pub struct IndexStats {
+    /// Index name
+    pub name: String,
+    /// Table the index belongs to
+    pub table: String,
+}
+
+/// Result of `SqliteStorage::verify`
+#[derive(Debug, Clone, Default)]
+pub struct VerifyReport {
+    /// Problems reported by `PRAGMA integrity_check`
+    pub integrity_errors: Vec<String>,
+    /// Rows referencing missing parents
+    pub foreign_key_violations: Vec<String>,
+    /// Whether the full-text index passed its own integrity check
+    pub search_index_ok: bool,
+    /// Sessions whose stored message count disagrees with their message rows
+    pub message_count_mismatches: Vec<String>,
+}
+
+impl VerifyReport {
+    /// Whether every check passed
+    pub fn is_ok(&self) -> bool {
+        self.integrity_errors.is_empty()
+            && self.foreign_key_violations.is_empty()
+            && self.search_index_ok
+            && self.message_count_mismatches.is_empty()
+    }
+}
+
+/// Options for `SqliteStorage::check_and_repair`
+#[derive(Debug, Clone, Default)]
+pub struct RepairOptions {
+    /// Fix what's found instead of only reporting it
+    pub repair: bool,
+    /// When repairing, also delete sessions that can't be read
+    pub delete_unreadable_sessions: bool,
+}
+
+/// Result of `SqliteStorage::check_and_repair`
+#[derive(Debug, Clone, Default)]
+pub struct RepairReport {
+    /// `(collection_id, session_id)` links to a missing session or collection
+    pub orphaned_collection_links: Vec<(String, String)>,
+    /// Missing sessions that still have message rows
+    pub orphaned_messages: Vec<String>,
+    /// Missing sessions that still have search index entries
+    pub orphaned_search_entries: Vec<String>,
+    /// Sessions whose stored messages or tools don't parse
+    pub unreadable_sessions: Vec<String>,
+    /// Sessions whose stored message count disagrees with their message rows
+    pub message_count_mismatches: Vec<String>,
+    /// Collections whose stored session count disagrees with their links
+    pub collection_count_mismatches: Vec<String>,
+    /// Whether the problems found were fixed
+    pub repaired: bool,
+}
+
+impl RepairReport {
+    /// Whether nothing was found
+    pub fn is_clean(&self) -> bool {
+        self.orphaned_collection_links.is_empty()
+            && self.orphaned_messages.is_empty()
+            && self.orphaned_search_entries.is_empty()
+            && self.unreadable_sessions.is_empty()
+            && self.message_count_mismatches.is_empty()
+            && self.collection_count_mismatches.is_empty()
+    }
+}
+
+/// Options for importing exported conversations
+#[derive(Debug, Clone)]
+pub struct ImportOptions {
+    /// Model recorded on the imported sessions
+    pub model: Model,
+    /// Name of a new collection to group the imported sessions into
+    pub collection_name: Option<String>,
+}
+
+impl Default for ImportOptions {
+    fn default() -> Self {
+        Self {
+            model: Model::Grok4FastReasoning,
+            collection_name: None,
+        }
+    }
+}
+
+/// Result of an import
+#[derive(Debug, Clone, Default)]
+pub struct ImportReport {
+    /// IDs of the sessions created, in file order
+    pub session_ids: Vec<String>,
+    /// ID of the collection created, if one was requested
+    pub collection_id: Option<String>,
+    /// Number of conversations skipped because they had no messages
+    pub skipped: usize,
+}
+
+/// Result of a prune operation
+#[derive(Debug, Clone, Default)]
+pub struct PruneReport {
+    /// IDs of the deleted sessions
+    pub removed_sessions: Vec<String>,
+    /// Number of collection membership rows removed
+    pub removed_collection_links: usize,
+}
+
+/// Options for exporting conversations as fine-tuning data
+#[derive(Debug, Clone, Default)]
+pub struct ExportOptions {
+    /// Which sessions to export (tag, collection, date range, ...)
+    ///
+    /// Pagination applies too, which is useful for exporting in batches.
+    pub query: SessionQuery,
+    /// Drop tool result messages and the tool calls that produced them
+    pub drop_tool_messages: bool,
+    /// Omit system prompts from the exported conversations
+    pub redact_system_prompts: bool,
+}
+
+impl ExportOptions {
+    /// Apply the message filters to one conversation
+    pub(crate) fn apply(&self, messages: Vec<Message>) -> Vec<Message> {
+        messages
+            .into_iter()
+            .filter(|msg| !(self.redact_system_prompts && msg.role == Role::System))
+            .filter(|msg| !(self.drop_tool_messages && msg.role == Role::Tool))
+            .filter_map(|mut msg| {
+                if self.drop_tool_messages
+                    && msg.tool_calls.take().is_some()
+                    && msg.content.is_empty()
+                {
+                    // An assistant turn that only requested tools has nothing left to train on
+                    return None;
+                }
+                Some(msg)
+            })
+            .collect()
+    }
+}
+
+/// A full-text search hit within a stored session
+#[derive(Debug, Clone)]
+pub struct MessageMatch {
+    /// Session containing the message
+    pub session_id: String,
+    /// Position of the message in the session history
+    pub message_index: usize,
+    /// Role of the message sender
+    pub role: Option<Role>,
+    /// Excerpt around the match, with matched terms wrapped in `[` `]`
+    pub snippet: String,
+}
+
+/// A session loaded from storage with its tools rebound to a registry
+#[derive(Debug)]
+pub struct RestoredSession {
+    /// The restored session
+    pub session: Session,
+    /// Names of stored tools with no executor in the registry
+    pub unbound_tools: Vec<String>,
+}
+
+/// Sort order for session listings
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SessionSort {
+    /// Newest sessions first
+    #[default]
+    CreatedDesc,
+    /// Oldest sessions first
+    CreatedAsc,
+    /// Most recently active sessions first
+    UpdatedDesc,
+    /// Least recently active sessions first
+    UpdatedAsc,
+    /// Alphabetical by title (untitled sessions last)
+    TitleAsc,
+}
+
+impl SessionSort {
+    fn order_by(&self) -> &'static str {
+        match self {
+            SessionSort::CreatedDesc => "created_at DESC",
+            SessionSort::CreatedAsc => "created_at ASC",
+            SessionSort::UpdatedDesc => "COALESCE(updated_at, created_at) DESC",
+            SessionSort::UpdatedAsc => "COALESCE(updated_at, created_at) ASC",
+            SessionSort::TitleAsc => "title IS NULL, title COLLATE NOCASE ASC",
+        }
+    }
+}
+
+/// Filters and pagination for listing stored sessions
+#[derive(Debug, Clone, Default)]
+pub struct SessionQuery {
+    /// Only sessions created at or after this time
+    pub created_after: Option<DateTime<Utc>>,
+    /// Only sessions created before this time
+    pub created_before: Option<DateTime<Utc>>,
+    /// Only sessions updated at or after this time
+    pub updated_after: Option<DateTime<Utc>>,
+    /// Only sessions updated before this time
+    pub updated_before: Option<DateTime<Utc>>,
+    /// Only sessions using this model
+    pub model: Option<Model>,
+    /// Only sessions whose title contains this text (case-insensitive)
+    pub title_contains: Option<String>,
+    /// Only sessions belonging to this collection
+    pub collection_id: Option<String>,
+    /// Only sessions belonging to a collection with this tag
+    pub tag: Option<String>,
+    /// Only sessions rated at least this high; unrated sessions are excluded
+    pub min_rating: Option<i64>,
+    /// Only sessions started from this prompt, as `name@version`, or any version of it by `name`
+    pub prompt: Option<String>,
+    /// Only sessions belonging to this tenant
+    pub tenant: Option<String>,
+    /// Maximum number of rows to return
+    pub limit: Option<usize>,
+    /// Number of rows to skip
+    pub offset: Option<usize>,
+    /// Sort order
+    pub sort: SessionSort,
+}
+
+/// Lightweight summary of a stored session
+#[derive(Debug, Clone)]
+pub struct SessionSummary {
+    /// Session ID
+    pub id: String,
+    /// Human-readable title
+    pub title: Option<String>,
+    /// Model used by the session
+    pub model: Model,
+    /// Number of messages in the session
+    pub message_count: usize,
+    /// Creation timestamp
+    pub created_at: DateTime<Utc>,
+    /// Last activity timestamp
+    pub updated_at: DateTime<Utc>,
+    /// The `name@version` of the stored prompt the session started from
+    pub prompt: Option<String>,
+    /// Tenant the session belongs to, if it's scoped to one
+    pub tenant: Option<String>,
+}
+
+/// How session message histories are laid out in the database
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum MessageStorage {
+    /// One row per message; saves only append new messages and rows are queryable
+    #[default]
+    Normalized,
+    /// The whole history as a JSON blob on the session row (the original layout)
+    Blob,
+}
+
+/// SQLite journal modes
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum JournalMode {
+    /// Rollback journal deleted at the end of each transaction (SQLite default)
+    Delete,
+    /// Rollback journal truncated instead of deleted
+    Truncate,
+    /// Rollback journal header zeroed instead of deleted
+    Persist,
+    /// Rollback journal kept in memory
+    Memory,
+    /// Write-ahead log, allowing readers to run concurrently with a writer
+    Wal,
+    /// No rollback journal
+    Off,
+}
+
+impl JournalMode {
+    /// Get the pragma value for this journal mode
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            JournalMode::Delete => "DELETE",
+            JournalMode::Truncate => "TRUNCATE",
+            JournalMode::Persist => "PERSIST",
+            JournalMode::Memory => "MEMORY",
+            JournalMode::Wal => "WAL",
+            JournalMode::Off => "OFF",
+        }
+    }
+}
+
+/// SQLite `synchronous` settings
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Synchronous {
+    /// Hand data to the OS without syncing
+    Off,
+    /// Sync at the most critical moments (safe with WAL)
+    Normal,
+    /// Sync after every transaction (SQLite default)
+    Full,
+    /// Like `Full`, also syncing the journal directory
+    Extra,
+}
+
+impl Synchronous {
+    /// Get the pragma value for this setting
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Synchronous::Off => "OFF",
+            Synchronous::Normal => "NORMAL",
+            Synchronous::Full => "FULL",
+            Synchronous::Extra => "EXTRA",
+        }
+    }
+}
+
+/// Builder for creating a SqliteStorage with custom connection settings
+///
+/// Settings left unset keep SQLite's own defaults.
+#[derive(Debug, Clone, Default)]
+pub struct SqliteStorageBuilder {
+    path: Option<PathBuf>,
+    in_memory: bool,
+    journal_mode: Option<JournalMode>,
+    busy_timeout: Option<Duration>,
+    synchronous: Option<Synchronous>,
+    cache_size: Option<i64>,
+    foreign_keys: Option<bool>,
+    pricing: HashMap<String, ModelPricing>,
+    message_storage: MessageStorage,
+    #[cfg(feature = "sqlcipher")]
+    key: Option<EncryptionKey>,
+}
+
+/// Source of the SQLCipher key for an encrypted database
+#[cfg(feature = "sqlcipher")]
+#[derive(Clone)]
+enum EncryptionKey {
+    Static(String),
+    Provider(Arc<dyn Fn() -> Result<String> + Send + Sync>),
+}
+
+#[cfg(feature = "sqlcipher")]
+impl EncryptionKey {
+    fn resolve(&self) -> Result<String> {
+        match self {
+            EncryptionKey::Static(key) => Ok(key.clone()),
+            EncryptionKey::Provider(provider) => provider(),
+        }
+    }
+}
+
+#[cfg(feature = "sqlcipher")]
+impl std::fmt::Debug for EncryptionKey {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        // Never print key material
+        match self {
+            EncryptionKey::Static(_) => f.write_str("Static(<redacted>)"),
+            EncryptionKey::Provider(_) => f.write_str("Provider(<callback>)"),
+        }
+    }
+}
+
+impl SqliteStorageBuilder {
+    /// Create a new builder
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set the database file path
+    pub fn path(mut self, path: impl Into<PathBuf>) -> Self {
+        self.path = Some(path.into());
+        self.in_memory = false;
+        self
+    }
+
+    /// Use an in-memory database instead of a file
+    pub fn in_memory(mut self) -> Self {
+        self.path = None;
+        self.in_memory = true;
+        self
+    }
+
+    /// Set the journal mode (use `JournalMode::Wal` for concurrent readers)
+    ///
+    /// `build` fails if the database can't use it, as with WAL in memory.
+    pub fn journal_mode(mut self, journal_mode: JournalMode) -> Self {
+        self.journal_mode = Some(journal_mode);
+        self
+    }
+
+    /// Set how long to wait on a locked database before failing with `database is locked`
+    pub fn busy_timeout(mut self, busy_timeout: Duration) -> Self {
+        self.busy_timeout = Some(busy_timeout);
+        self
+    }
+
+    /// Set the `synchronous` durability level
+    pub fn synchronous(mut self, synchronous: Synchronous) -> Self {
+        self.synchronous = Some(synchronous);
+        self
+    }
+
+    /// Set the page cache size
+    ///
+    /// Positive values are a number of pages, negative values a size in KiB,
+    /// following SQLite's `cache_size` pragma.
+    pub fn cache_size(mut self, cache_size: i64) -> Self {
+        self.cache_size = Some(cache_size);
+        self
+    }
+
+    /// Enable or disable foreign key enforcement
+    pub fn foreign_keys(mut self, enabled: bool) -> Self {
+        self.foreign_keys = Some(enabled);
+        self
+    }
+
+    /// Choose how message histories are stored
+    pub fn message_storage(mut self, message_storage: MessageStorage) -> Self {
+        self.message_storage = message_storage;
+        self
+    }
+
+    /// Set the pricing used to estimate the cost of recorded usage for a model
+    pub fn model_pricing(mut self, model: Model, pricing: ModelPricing) -> Self {
+        self.pricing.insert(model.as_str().to_string(), pricing);
+        self
+    }
+
+    /// Encrypt the database with SQLCipher using this key
+    #[cfg(feature = "sqlcipher")]
+    pub fn key(mut self, key: impl Into<String>) -> Self {
+        self.key = Some(EncryptionKey::Static(key.into()));
+        self
+    }
+
+    /// Encrypt the database with SQLCipher, fetching the key when the database is opened
+    ///
+    /// Use this to pull the key from an OS keychain or secret store rather
+    /// than keeping it in memory for the builder's lifetime.
+    #[cfg(feature = "sqlcipher")]
+    pub fn key_provider<F>(mut self, provider: F) -> Self
+    where
+        F: Fn() -> Result<String> + Send + Sync + 'static,
+    {
+        self.key = Some(EncryptionKey::Provider(Arc::new(provider)));
+        self
+    }
+
+    /// Restore a backup into the configured database file and open it with these settings
+    ///
+    /// Works like `SqliteStorage::restore_from`, except that an encrypted
+    /// backup is read with the configured key and the restored database is
+    /// opened with the configured key and pragmas.
+    pub fn restore_from<P: AsRef<Path>>(self, backup_path: P) -> Result<SqliteStorage> {
+        let path = self.path.clone().ok_or_else(|| {
+            GrokError::InvalidConfig("Restoring a backup requires a database path".to_string())
+        })?;
+
+        // Not read-only: checking FTS5 indexes needs a writable connection,
+        // though nothing is written to the backup
+        let source =
+            Connection::open_with_flags(backup_path.as_ref(), OpenFlags::SQLITE_OPEN_READ_WRITE)
+                .map_err(|e| GrokError::session("open backup", e))?;
+        #[cfg(feature = "sqlcipher")]
+        self.unlock(&source)?;
+
+        let problems = SqliteStorage::run_integrity_check(&source)?;
+        if !problems.is_empty() {
+            return Err(GrokError::session(
+                "restore backup",
+                format!("backup failed integrity check: {}", problems.join("; ")),
+            ));
+        }
+
+        let mut target =
+            Connection::open(&path).map_err(|e| GrokError::session("open database", e))?;
+        #[cfg(feature = "sqlcipher")]
+        self.unlock(&target)?;
+
+        Backup::new(&source, &mut target)
+            .and_then(|backup| backup.run_to_completion(256, Duration::from_millis(0), None))
+            .map_err(|e| GrokError::session("restore backup", e))?;
+        drop(target);
+
+        self.build()
+    }
+
+    /// Apply the SQLCipher key, which has to be the first statement run on the connection
+    #[cfg(feature = "sqlcipher")]
+    fn unlock(&self, conn: &Connection) -> Result<()> {
+        if let Some(key) = &self.key {
+            conn.pragma_update(None, "key", key.resolve()?)
+                .map_err(|e| GrokError::session("set encryption key", e))?;
+
+            // SQLCipher only checks the key once a page is read
+            conn.query_row("SELECT COUNT(*) FROM sqlite_master", [], |row| {
+                row.get::<_, i64>(0)
+            })
+            .map_err(|e| GrokError::session("unlock database (wrong key?)", e))?;
+        }
+        Ok(())
+    }
+
+    /// Build the storage, opening the database and applying the configured pragmas
+    pub fn build(self) -> Result<SqliteStorage> {
+        let conn = if let Some(path) = &self.path {
+            Connection::open(path).map_err(|e| GrokError::session("open database", e))?
+        } else if self.in_memory {
+            Connection::open_in_memory()
+                .map_err(|e| GrokError::session("create in-memory database", e))?
+        } else {
+            return Err(GrokError::InvalidConfig(
+                "Database path or in-memory mode is required".to_string(),
+            ));
+        };
+
+        #[cfg(feature = "sqlcipher")]
+        self.unlock(&conn)?;
+
+        if let Some(busy_timeout) = self.busy_timeout {
+            conn.busy_timeout(busy_timeout)
+                .map_err(|e| GrokError::session("set busy_timeout", e))?;
+        }
+
+        if let Some(journal_mode) = self.journal_mode {
+            // SQLite keeps its current mode rather than failing when the requested one
+            // isn't available, such as WAL on an in-memory database, so read it back
+            let mode: String = conn
+                .pragma_update_and_check(None, "journal_mode", journal_mode.as_str(), |row| {
+                    row.get(0)
+                })
+                .map_err(|e| GrokError::session("set journal_mode", e))?;
+            if !mode.eq_ignore_ascii_case(journal_mode.as_str()) {
+                return Err(GrokError::InvalidConfig(format!(
+                    "Journal mode {} is not available for this database, which uses {}",
+                    journal_mode.as_str(),
+                    mode
+                )));
+            }
+        }
+
+        if let Some(synchronous) = self.synchronous {
+            conn.pragma_update(None, "synchronous", synchronous.as_str())
+                .map_err(|e| GrokError::session("set synchronous", e))?;
+        }
+
+        if let Some(cache_size) = self.cache_size {
+            conn.pragma_update(None, "cache_size", cache_size)
+                .map_err(|e| GrokError::session("set cache_size", e))?;
+        }
+
+        if let Some(foreign_keys) = self.foreign_keys {
+            conn.pragma_update(None, "foreign_keys", foreign_keys)
+                .map_err(|e| GrokError::session("set foreign_keys", e))?;
+        }
+
+        SqliteStorage::create_tables(&conn)?;
+
+        Ok(SqliteStorage {
+            conn: Arc::new(Mutex::new(conn)),
+            pricing: self.pricing,
+            message_storage: self.message_storage,
+            written: Default::default(),
+        })
+    }
 }
 
-/// Persistent session manager that uses SQLite storage
+/// Session manager that persists its sessions to SQLite
 pub type PersistentSessionManager = SessionManager<SqliteStorage>;
 
-/// Persistent collection manager that uses SQLite storage
-pub type PersistentCollectionManager = CollectionManager<SqliteStorage>;
\ No newline at end of file
+/// Collection manager that persists its collections to SQLite
+pub type PersistentCollectionManager = CollectionManager<SqliteStorage>;
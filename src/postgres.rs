@@ -0,0 +1,712 @@
+//! PostgreSQL storage for sessions and collections
+//!
+//! `PostgresStorage` keeps sessions, collections and usage history in a
+//! database that many processes can share, such as stateless app servers
+//! behind a load balancer. Its tables are created in the connection's
+//! `search_path` when the storage is opened; point that at a dedicated schema
+//! (e.g. `options=-csearch_path=grok` in the connection string) to keep them
+//! apart from the application's own tables.
+
+use crate::budget::ModelPricing;
+use crate::chat::{ChatCompletion, Message, Model, Tool};
+use crate::collections::{Collection, CollectionMetadata};
+use crate::error::{GrokError, Result};
+use crate::replay::ReplayUsage;
+use crate::session::Session;
+use crate::storage::Storage;
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use std::collections::HashMap;
+use std::sync::PoisonError;
+use std::time::Duration;
+use tokio::sync::Mutex;
+use tokio_postgres::{Client, Transaction};
+
+/// Tables, created if missing
+const SCHEMA: &str = "
+    CREATE TABLE IF NOT EXISTS sessions (
+        id TEXT PRIMARY KEY,
+        model TEXT NOT NULL,
+        created_at TIMESTAMPTZ NOT NULL,
+        updated_at TIMESTAMPTZ NOT NULL,
+        title TEXT,
+        message_count BIGINT NOT NULL DEFAULT 0,
+        tools JSONB NOT NULL DEFAULT '[]',
+        prompt TEXT,
+        tenant TEXT
+    );
+    CREATE INDEX IF NOT EXISTS idx_sessions_tenant ON sessions (tenant);
+    CREATE TABLE IF NOT EXISTS messages (
+        session_id TEXT NOT NULL REFERENCES sessions (id) ON DELETE CASCADE,
+        idx BIGINT NOT NULL,
+        message JSONB NOT NULL,
+        interrupted BOOLEAN NOT NULL DEFAULT FALSE,
+        created_at TIMESTAMPTZ NOT NULL,
+        PRIMARY KEY (session_id, idx)
+    );
+    CREATE TABLE IF NOT EXISTS collections (
+        id TEXT PRIMARY KEY,
+        name TEXT NOT NULL,
+        description TEXT,
+        created_at TIMESTAMPTZ NOT NULL,
+        updated_at TIMESTAMPTZ NOT NULL,
+        tags JSONB NOT NULL DEFAULT '[]',
+        session_count BIGINT NOT NULL DEFAULT 0,
+        total_messages BIGINT NOT NULL DEFAULT 0,
+        total_tokens BIGINT NOT NULL DEFAULT 0,
+        tenant TEXT
+    );
+    CREATE INDEX IF NOT EXISTS idx_collections_tenant ON collections (tenant);
+    CREATE TABLE IF NOT EXISTS collection_sessions (
+        collection_id TEXT NOT NULL REFERENCES collections (id) ON DELETE CASCADE,
+        session_id TEXT NOT NULL REFERENCES sessions (id) ON DELETE CASCADE,
+        position BIGINT NOT NULL,
+        PRIMARY KEY (collection_id, session_id)
+    );
+    CREATE TABLE IF NOT EXISTS usage_records (
+        id BIGSERIAL PRIMARY KEY,
+        recorded_at TIMESTAMPTZ NOT NULL,
+        session_id TEXT,
+        model TEXT NOT NULL,
+        prompt_tokens BIGINT NOT NULL,
+        completion_tokens BIGINT NOT NULL,
+        total_tokens BIGINT NOT NULL,
+        estimated_cost DOUBLE PRECISION,
+        latency_ms BIGINT NOT NULL
+    );
+    CREATE INDEX IF NOT EXISTS idx_usage_records_session_id ON usage_records (session_id);";
+
+/// Advisory lock key held while creating tables, so replicas starting
+/// together don't race each other
+const SCHEMA_LOCK: i64 = 0x0067_726f_6b73_646b;
+
+/// PostgreSQL-based storage for sessions and collections
+#[derive(Debug)]
+pub struct PostgresStorage {
+    client: Mutex<Client>,
+    pricing: HashMap<String, ModelPricing>,
+    /// Message rows last written for each session, by history version
+    written: std::sync::Mutex<HashMap<String, WrittenHistory>>,
+}
+
+/// The message rows a save wrote for a session's history
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct WrittenHistory {
+    /// `Session::history_version` of the saved history
+    version: u64,
+    /// Number of messages written
+    len: usize,
+}
+
+impl PostgresStorage {
+    /// Store sessions and collections through `client`, creating the tables if needed
+    ///
+    /// The caller connects the client and spawns its connection task.
+    pub async fn new(mut client: Client) -> Result<Self> {
+        let tx = client
+            .transaction()
+            .await
+            .map_err(|e| GrokError::session("begin transaction", e))?;
+        tx.execute("SELECT pg_advisory_xact_lock($1)", &[&SCHEMA_LOCK])
+            .await
+            .map_err(|e| GrokError::session("lock schema", e))?;
+        tx.batch_execute(SCHEMA)
+            .await
+            .map_err(|e| GrokError::session("create tables", e))?;
+        tx.commit()
+            .await
+            .map_err(|e| GrokError::session("commit schema", e))?;
+
+        Ok(Self {
+            client: Mutex::new(client),
+            pricing: HashMap::new(),
+            written: Default::default(),
+        })
+    }
+
+    /// Set the pricing used to estimate the cost of recorded usage for a model
+    pub fn model_pricing(mut self, model: Model, pricing: ModelPricing) -> Self {
+        self.pricing.insert(model.as_str().to_string(), pricing);
+        self
+    }
+
+    /// Append the messages not yet stored, rewriting all of them if the
+    /// stored rows no longer match the history (e.g. after `clear_history`)
+    ///
+    /// When `written` matches the stored row count, only the last stored row
+    /// is read back and compared; otherwise every row is.
+    async fn write_messages(
+        tx: &Transaction<'_>,
+        session_id: &str,
+        messages: &[Message],
+        interrupted: &[usize],
+        written: Option<usize>,
+    ) -> Result<()> {
+        // A partial reply from `save_partial_reply` is replaced by whatever the history now holds
+        tx.execute(
+            "DELETE FROM messages WHERE session_id = $1 AND interrupted
+                 AND idx = (SELECT MAX(idx) FROM messages WHERE session_id = $1)",
+            &[&session_id],
+        )
+        .await
+        .map_err(|e| GrokError::session("clear partial reply", e).with_id(session_id))?;
+
+        let stored_len = tx
+            .query_one(
+                "SELECT COALESCE(MAX(idx) + 1, 0) FROM messages WHERE session_id = $1",
+                &[&session_id],
+            )
+            .await
+            .map_err(|e| GrokError::session("count messages", e).with_id(session_id))?
+            .get::<_, i64>(0) as usize;
+
+        let still_prefix = if stored_len > messages.len() {
+            false
+        } else if written == Some(stored_len) {
+            // The same history was saved here before, so only another writer
+            // could have changed the rows; the last one shows whether it did
+            match stored_len.checked_sub(1) {
+                Some(last) => {
+                    let row = tx
+                        .query_one(
+                            "SELECT message FROM messages WHERE session_id = $1 AND idx = $2",
+                            &[&session_id, &(last as i64)],
+                        )
+                        .await
+                        .map_err(|e| GrokError::session("read messages", e).with_id(session_id))?;
+                    row.get::<_, serde_json::Value>(0) == serde_json::to_value(&messages[last])?
+                }
+                None => true,
+            }
+        } else {
+            // Every stored row is compared, since a rewritten history can end in the same reply
+            let stored = tx
+                .query(
+                    "SELECT message FROM messages WHERE session_id = $1 ORDER BY idx",
+                    &[&session_id],
+                )
+                .await
+                .map_err(|e| GrokError::session("read messages", e).with_id(session_id))?;
+            let mut matches = stored.len() == stored_len;
+            for (row, message) in stored.iter().zip(messages) {
+                if row.get::<_, serde_json::Value>(0) != serde_json::to_value(message)? {
+                    matches = false;
+                    break;
+                }
+            }
+            matches
+        };
+
+        let start = if still_prefix {
+            stored_len
+        } else {
+            tx.execute("DELETE FROM messages WHERE session_id = $1", &[&session_id])
+                .await
+                .map_err(|e| GrokError::session("clear messages", e).with_id(session_id))?;
+            0
+        };
+
+        let statement = tx
+            .prepare(
+                "INSERT INTO messages (session_id, idx, message, interrupted, created_at)
+                 VALUES ($1, $2, $3, $4, $5)",
+            )
+            .await
+            .map_err(|e| GrokError::session("prepare statement", e))?;
+
+        let now = Utc::now();
+        for (idx, message) in messages.iter().enumerate().skip(start) {
+            let json = serde_json::to_value(message)?;
+            tx.execute(
+                &statement,
+                &[
+                    &session_id,
+                    &(idx as i64),
+                    &json,
+                    &interrupted.contains(&idx),
+                    &now,
+                ],
+            )
+            .await
+            .map_err(|e| GrokError::session("store message", e).with_id(session_id))?;
+        }
+
+        Ok(())
+    }
+
+    async fn load_session_scoped(
+        &self,
+        session_id: &str,
+        tenant: Option<&str>,
+    ) -> Result<Option<Session>> {
+        let client = self.client.lock().await;
+        let row = client
+            .query_opt(
+                "SELECT model, created_at, tools, prompt, tenant, title, updated_at FROM sessions
+                 WHERE id = $1 AND ($2::TEXT IS NULL OR tenant = $2)",
+                &[&session_id, &tenant],
+            )
+            .await
+            .map_err(|e| GrokError::session("load session", e).with_id(session_id))?;
+        let Some(row) = row else {
+            return Ok(None);
+        };
+
+        let model: Model = row.get::<_, String>(0).parse()?;
+        let created_at: DateTime<Utc> = row.get(1);
+        let tools: Vec<Tool> = serde_json::from_value(row.get(2))?;
+        let prompt: Option<String> = row.get(3);
+        let tenant: Option<String> = row.get(4);
+        let title: Option<String> = row.get(5);
+        let updated_at: DateTime<Utc> = row.get(6);
+
+        let rows = client
+            .query(
+                "SELECT idx, message, interrupted FROM messages WHERE session_id = $1 ORDER BY idx",
+                &[&session_id],
+            )
+            .await
+            .map_err(|e| GrokError::session("read messages", e).with_id(session_id))?;
+
+        let mut messages: Vec<Message> = Vec::with_capacity(rows.len());
+        let mut interrupted = Vec::new();
+        for row in rows {
+            messages.push(
+                serde_json::from_value(row.get(1))
+                    .map_err(|e| GrokError::session("parse messages", e).with_id(session_id))?,
+            );
+            if row.get(2) {
+                interrupted.push(row.get::<_, i64>(0) as usize);
+            }
+        }
+
+        let mut session = Session::restore(
+            session_id.to_string(),
+            model,
+            title,
+            created_at,
+            updated_at,
+            messages,
+        );
+        session.add_tools(tools);
+        session.set_prompt_reference(prompt);
+        session.set_interrupted(interrupted);
+        if let Some(tenant) = tenant {
+            session.set_tenant(tenant);
+        }
+        Ok(Some(session))
+    }
+
+    async fn load_collection_scoped(
+        &self,
+        collection_id: &str,
+        tenant: Option<&str>,
+    ) -> Result<Option<Collection>> {
+        let client = self.client.lock().await;
+        let row = client
+            .query_opt(
+                "SELECT name, description, created_at, updated_at, tags, session_count,
+                        total_messages, total_tokens, tenant
+                 FROM collections WHERE id = $1 AND ($2::TEXT IS NULL OR tenant = $2)",
+                &[&collection_id, &tenant],
+            )
+            .await
+            .map_err(|e| GrokError::collection("load collection", e).with_id(collection_id))?;
+        let Some(row) = row else {
+            return Ok(None);
+        };
+
+        let metadata = CollectionMetadata {
+            name: row.get(0),
+            description: row.get(1),
+            created_at: row.get(2),
+            updated_at: row.get(3),
+            tags: serde_json::from_value(row.get(4))?,
+            session_count: row.get::<_, i64>(5) as usize,
+            total_messages: row.get::<_, i64>(6) as usize,
+            total_tokens: row.get::<_, i64>(7) as u64,
+            tenant: row.get(8),
+        };
+
+        let session_ids = client
+            .query(
+                "SELECT session_id FROM collection_sessions WHERE collection_id = $1 ORDER BY position",
+                &[&collection_id],
+            )
+            .await
+            .map_err(|e| {
+                GrokError::collection("load collection sessions", e).with_id(collection_id)
+            })?
+            .iter()
+            .map(|row| row.get(0))
+            .collect();
+
+        Ok(Some(Collection::restore(
+            collection_id.to_string(),
+            metadata,
+            session_ids,
+        )))
+    }
+}
+
+#[async_trait]
+impl Storage for PostgresStorage {
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(name = "grok.storage.save_session", skip_all)
+    )]
+    async fn save_session(&self, session: &Session) -> Result<()> {
+        // The version is read after the history, so a rewrite in between
+        // is seen as a new version rather than missed
+        let messages = session.messages().await;
+        let interrupted = session.interrupted().await;
+        let version = session.history_version();
+        let metadata = session.metadata();
+        let model = session.model();
+        let tools = serde_json::to_value(session.tools())?;
+
+        let mut client = self.client.lock().await;
+        let tx = client
+            .transaction()
+            .await
+            .map_err(|e| GrokError::session("begin transaction", e))?;
+
+        // An upsert rather than delete and insert, which would cascade to the
+        // session's messages and collection links
+        tx.execute(
+            "INSERT INTO sessions
+                 (id, model, created_at, updated_at, title, message_count, tools, prompt, tenant)
+             VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9)
+             ON CONFLICT (id) DO UPDATE SET
+                 model = EXCLUDED.model,
+                 updated_at = EXCLUDED.updated_at,
+                 title = EXCLUDED.title,
+                 message_count = EXCLUDED.message_count,
+                 tools = EXCLUDED.tools,
+                 prompt = EXCLUDED.prompt,
+                 tenant = EXCLUDED.tenant",
+            &[
+                &session.id,
+                &model.as_str(),
+                &metadata.created_at,
+                &metadata.updated_at,
+                &metadata.title,
+                &(messages.len() as i64),
+                &tools,
+                &session.prompt(),
+                &session.tenant(),
+            ],
+        )
+        .await
+        .map_err(|e| GrokError::session("save session", e).with_id(session.id.as_str()))?;
+
+        let written = self
+            .written
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .get(session.id())
+            .filter(|written| written.version == version)
+            .map(|written| written.len);
+        Self::write_messages(&tx, &session.id, &messages, &interrupted, written).await?;
+
+        tx.commit()
+            .await
+            .map_err(|e| GrokError::session("commit session", e).with_id(session.id.as_str()))?;
+
+        self.written
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .insert(
+                session.id.clone(),
+                WrittenHistory {
+                    version,
+                    len: messages.len(),
+                },
+            );
+        Ok(())
+    }
+
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(name = "grok.storage.load_session", skip(self))
+    )]
+    async fn load_session(&self, session_id: &str) -> Result<Option<Session>> {
+        self.load_session_scoped(session_id, None).await
+    }
+
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(name = "grok.storage.delete_session", skip(self))
+    )]
+    async fn delete_session(&self, session_id: &str) -> Result<()> {
+        // Messages and collection links go with it; the collections' counts are updated first
+        let mut client = self.client.lock().await;
+        let tx = client
+            .transaction()
+            .await
+            .map_err(|e| GrokError::session("begin transaction", e))?;
+        tx.execute(
+            "UPDATE collections SET
+                 session_count = GREATEST(session_count - 1, 0),
+                 total_messages = GREATEST(total_messages - COALESCE((SELECT message_count FROM sessions WHERE id = $1), 0), 0)
+             WHERE id IN (SELECT collection_id FROM collection_sessions WHERE session_id = $1)",
+            &[&session_id],
+        )
+        .await
+        .map_err(|e| GrokError::collection("update collection counts", e))?;
+        tx.execute("DELETE FROM sessions WHERE id = $1", &[&session_id])
+            .await
+            .map_err(|e| GrokError::session("delete session", e).with_id(session_id))?;
+        tx.commit()
+            .await
+            .map_err(|e| GrokError::session("commit session delete", e).with_id(session_id))
+    }
+
+    async fn list_sessions(&self) -> Result<Vec<String>> {
+        let client = self.client.lock().await;
+        let rows = client
+            .query("SELECT id FROM sessions ORDER BY created_at DESC", &[])
+            .await
+            .map_err(|e| GrokError::session("list sessions", e))?;
+        Ok(rows.iter().map(|row| row.get(0)).collect())
+    }
+
+    async fn load_session_for(&self, tenant: &str, session_id: &str) -> Result<Option<Session>> {
+        self.load_session_scoped(session_id, Some(tenant)).await
+    }
+
+    async fn list_sessions_for(&self, tenant: &str) -> Result<Vec<String>> {
+        let client = self.client.lock().await;
+        let rows = client
+            .query(
+                "SELECT id FROM sessions WHERE tenant = $1 ORDER BY created_at DESC",
+                &[&tenant],
+            )
+            .await
+            .map_err(|e| GrokError::session("list sessions", e))?;
+        Ok(rows.iter().map(|row| row.get(0)).collect())
+    }
+
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(name = "grok.storage.save_collection", skip_all)
+    )]
+    async fn save_collection(&self, collection: &Collection) -> Result<()> {
+        let metadata = collection.metadata();
+        let tags = serde_json::to_value(&metadata.tags)?;
+        let session_ids = collection.session_ids();
+
+        let mut client = self.client.lock().await;
+        let tx = client
+            .transaction()
+            .await
+            .map_err(|e| GrokError::collection("begin transaction", e))?;
+
+        tx.execute(
+            "INSERT INTO collections
+                 (id, name, description, created_at, updated_at, tags, session_count,
+                  total_messages, total_tokens, tenant)
+             VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10)
+             ON CONFLICT (id) DO UPDATE SET
+                 name = EXCLUDED.name,
+                 description = EXCLUDED.description,
+                 updated_at = EXCLUDED.updated_at,
+                 tags = EXCLUDED.tags,
+                 session_count = EXCLUDED.session_count,
+                 total_messages = EXCLUDED.total_messages,
+                 total_tokens = EXCLUDED.total_tokens,
+                 tenant = EXCLUDED.tenant",
+            &[
+                &collection.id,
+                &metadata.name,
+                &metadata.description,
+                &metadata.created_at,
+                &metadata.updated_at,
+                &tags,
+                &(metadata.session_count as i64),
+                &(metadata.total_messages as i64),
+                &(metadata.total_tokens as i64),
+                &metadata.tenant,
+            ],
+        )
+        .await
+        .map_err(|e| GrokError::collection("save collection", e).with_id(collection.id.as_str()))?;
+
+        tx.execute(
+            "DELETE FROM collection_sessions WHERE collection_id = $1 AND NOT (session_id = ANY($2))",
+            &[&collection.id, &session_ids],
+        )
+        .await
+        .map_err(|e| GrokError::collection("clear collection sessions", e).with_id(collection.id.as_str()))?;
+
+        for (position, session_id) in session_ids.iter().enumerate() {
+            tx.execute(
+                "INSERT INTO collection_sessions (collection_id, session_id, position)
+                 VALUES ($1, $2, $3)
+                 ON CONFLICT (collection_id, session_id) DO UPDATE SET position = EXCLUDED.position",
+                &[&collection.id, session_id, &(position as i64)],
+            )
+            .await
+            .map_err(|e| {
+                GrokError::collection("save collection session", e).with_id(collection.id.as_str())
+            })?;
+        }
+
+        tx.commit().await.map_err(|e| {
+            GrokError::collection("commit collection", e).with_id(collection.id.as_str())
+        })
+    }
+
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(name = "grok.storage.load_collection", skip(self))
+    )]
+    async fn load_collection(&self, collection_id: &str) -> Result<Option<Collection>> {
+        self.load_collection_scoped(collection_id, None).await
+    }
+
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(name = "grok.storage.delete_collection", skip(self))
+    )]
+    async fn delete_collection(&self, collection_id: &str) -> Result<()> {
+        let client = self.client.lock().await;
+        client
+            .execute("DELETE FROM collections WHERE id = $1", &[&collection_id])
+            .await
+            .map_err(|e| GrokError::collection("delete collection", e).with_id(collection_id))?;
+        Ok(())
+    }
+
+    async fn list_collections(&self) -> Result<Vec<String>> {
+        let client = self.client.lock().await;
+        let rows = client
+            .query("SELECT id FROM collections ORDER BY created_at DESC", &[])
+            .await
+            .map_err(|e| GrokError::collection("list collections", e))?;
+        Ok(rows.iter().map(|row| row.get(0)).collect())
+    }
+
+    async fn load_collection_for(
+        &self,
+        tenant: &str,
+        collection_id: &str,
+    ) -> Result<Option<Collection>> {
+        self.load_collection_scoped(collection_id, Some(tenant))
+            .await
+    }
+
+    async fn list_collections_for(&self, tenant: &str) -> Result<Vec<String>> {
+        let client = self.client.lock().await;
+        let rows = client
+            .query(
+                "SELECT id FROM collections WHERE tenant = $1 ORDER BY created_at DESC",
+                &[&tenant],
+            )
+            .await
+            .map_err(|e| GrokError::collection("list collections", e))?;
+        Ok(rows.iter().map(|row| row.get(0)).collect())
+    }
+
+    async fn save_partial_reply(
+        &self,
+        session_id: &str,
+        index: usize,
+        message: &Message,
+    ) -> Result<()> {
+        let json = serde_json::to_value(message)?;
+        let now = Utc::now();
+
+        let mut client = self.client.lock().await;
+        let tx = client
+            .transaction()
+            .await
+            .map_err(|e| GrokError::session("begin transaction", e))?;
+
+        tx.execute(
+            "INSERT INTO messages (session_id, idx, message, interrupted, created_at)
+             VALUES ($1, $2, $3, TRUE, $4)
+             ON CONFLICT (session_id, idx) DO UPDATE SET
+                 message = EXCLUDED.message,
+                 interrupted = TRUE",
+            &[&session_id, &(index as i64), &json, &now],
+        )
+        .await
+        .map_err(|e| GrokError::session("store partial reply", e).with_id(session_id))?;
+
+        tx.execute(
+            "UPDATE sessions SET message_count = $2, updated_at = $3 WHERE id = $1",
+            &[&session_id, &(index as i64 + 1), &now],
+        )
+        .await
+        .map_err(|e| GrokError::session("update session", e).with_id(session_id))?;
+
+        tx.commit()
+            .await
+            .map_err(|e| GrokError::session("commit partial reply", e).with_id(session_id))
+    }
+
+    async fn record_usage(
+        &self,
+        session_id: Option<&str>,
+        completion: &ChatCompletion,
+        latency: Duration,
+    ) -> Result<()> {
+        let (prompt_tokens, completion_tokens, total_tokens) = completion
+            .usage
+            .as_ref()
+            .map(|u| (u.prompt_tokens, u.completion_tokens, u.total_tokens))
+            .unwrap_or((0, 0, 0));
+
+        let estimated_cost = self
+            .pricing
+            .get(&completion.model)
+            .map(|pricing| pricing.estimate(prompt_tokens, completion_tokens));
+
+        let client = self.client.lock().await;
+        client
+            .execute(
+                "INSERT INTO usage_records
+                     (recorded_at, session_id, model, prompt_tokens, completion_tokens,
+                      total_tokens, estimated_cost, latency_ms)
+                 VALUES ($1, $2, $3, $4, $5, $6, $7, $8)",
+                &[
+                    &Utc::now(),
+                    &session_id,
+                    &completion.model,
+                    &(prompt_tokens as i64),
+                    &(completion_tokens as i64),
+                    &(total_tokens as i64),
+                    &estimated_cost,
+                    &(latency.as_millis() as i64),
+                ],
+            )
+            .await
+            .map_err(|e| GrokError::session("record usage", e))?;
+        Ok(())
+    }
+
+    async fn session_usage(&self, session_id: &str) -> Result<Option<ReplayUsage>> {
+        let client = self.client.lock().await;
+        let row = client
+            .query_one(
+                "SELECT COUNT(*), COALESCE(SUM(prompt_tokens), 0)::BIGINT,
+                        COALESCE(SUM(completion_tokens), 0)::BIGINT, COALESCE(AVG(latency_ms), 0)::FLOAT8
+                 FROM usage_records WHERE session_id = $1",
+                &[&session_id],
+            )
+            .await
+            .map_err(|e| GrokError::session("query usage", e).with_id(session_id))?;
+
+        let completions = row.get::<_, i64>(0) as u64;
+        if completions == 0 {
+            return Ok(None);
+        }
+        Ok(Some(ReplayUsage {
+            completions,
+            prompt_tokens: row.get::<_, i64>(1) as u64,
+            completion_tokens: row.get::<_, i64>(2) as u64,
+            average_latency: Duration::from_millis(row.get::<_, f64>(3) as u64),
+        }))
+    }
+}
@@ -0,0 +1,106 @@
+//! Backend abstraction for chat completions
+//!
+//! Sessions, session managers and agents talk to the API through
+//! `ChatProvider`, which `Client` implements. Implement it to substitute a
+//! mock, simulator or another provider in tests and local development.
+
+use crate::chat::{ChatChunk, ChatCompletion, Message, Model, Tool};
+use crate::client::ChatOptions;
+use crate::error::{GrokError, Result};
+use crate::Client;
+use async_trait::async_trait;
+use futures::stream::BoxStream;
+use std::sync::Arc;
+
+/// A stream of completion chunks from a provider
+pub type ChunkStream = BoxStream<'static, Result<ChatChunk>>;
+
+/// A backend that answers chat completion requests
+#[async_trait]
+pub trait ChatProvider: std::fmt::Debug + Send + Sync {
+    /// Complete a conversation
+    async fn chat(
+        &self,
+        model: Model,
+        messages: Vec<Arc<Message>>,
+        tools: Option<Vec<Tool>>,
+        options: Option<ChatOptions>,
+    ) -> Result<ChatCompletion>;
+
+    /// Complete a conversation, yielding the response as it's generated
+    async fn chat_stream(
+        &self,
+        model: Model,
+        messages: Vec<Message>,
+        tools: Option<Vec<Tool>>,
+    ) -> Result<ChunkStream>;
+
+    /// Models this provider can serve; all of them by default
+    fn models(&self) -> Vec<Model> {
+        vec![
+            Model::Grok4FastReasoning,
+            Model::Grok4,
+            Model::Grok3,
+            Model::Grok2,
+            Model::Grok1,
+        ]
+    }
+}
+
+#[async_trait]
+impl ChatProvider for Client {
+    async fn chat(
+        &self,
+        model: Model,
+        messages: Vec<Arc<Message>>,
+        tools: Option<Vec<Tool>>,
+        options: Option<ChatOptions>,
+    ) -> Result<ChatCompletion> {
+        self.chat_shared(model, messages, tools, options).await
+    }
+
+    async fn chat_stream(
+        &self,
+        model: Model,
+        messages: Vec<Message>,
+        tools: Option<Vec<Tool>>,
+    ) -> Result<ChunkStream> {
+        use futures::StreamExt;
+
+        let stream = Client::chat_stream(self, model, messages, tools).await?;
+        Ok(stream.boxed())
+    }
+}
+
+/// Provider of sessions restored from storage until they're given a real one
+#[derive(Debug)]
+pub(crate) struct Detached;
+
+#[async_trait]
+impl ChatProvider for Detached {
+    async fn chat(
+        &self,
+        _model: Model,
+        _messages: Vec<Arc<Message>>,
+        _tools: Option<Vec<Tool>>,
+        _options: Option<ChatOptions>,
+    ) -> Result<ChatCompletion> {
+        Err(detached())
+    }
+
+    async fn chat_stream(
+        &self,
+        _model: Model,
+        _messages: Vec<Message>,
+        _tools: Option<Vec<Tool>>,
+    ) -> Result<ChunkStream> {
+        Err(detached())
+    }
+}
+
+fn detached() -> GrokError {
+    GrokError::session(
+        "send messages",
+        "session has no provider; load it through a SessionManager or call set_provider",
+    )
+}
@@ -0,0 +1,310 @@
+//! Retrieval-augmented generation
+//!
+//! Documents are split into chunks, embedded, and stored in a `VectorStore`.
+//! At question time a `Retriever` finds the chunks closest to the query and
+//! adds them to the conversation as context.
+
+use crate::chat::{Message, Role};
+use crate::error::{GrokError, Result};
+use crate::session::Session;
+use crate::vector::{Metadata, MetadataFilter, VectorMatch, VectorRecord, VectorStore};
+use crate::Client;
+use async_trait::async_trait;
+use std::fmt;
+use std::ops::Range;
+use std::sync::Arc;
+
+/// Inputs sent to the embeddings endpoint per request
+const EMBED_BATCH_SIZE: usize = 64;
+
+/// How text is split into chunks
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Chunker {
+    /// Windows of at most `max_tokens` tokens, repeating about `overlap` tokens
+    /// of the previous window
+    ///
+    /// Tokens are estimated at four characters each, and words are never split.
+    Tokens { max_tokens: usize, overlap: usize },
+    /// Groups of `max_sentences` sentences, repeating `overlap` sentences of
+    /// the previous group
+    Sentences {
+        max_sentences: usize,
+        overlap: usize,
+    },
+}
+
+impl Default for Chunker {
+    fn default() -> Self {
+        Chunker::Tokens {
+            max_tokens: 256,
+            overlap: 32,
+        }
+    }
+}
+
+impl Chunker {
+    /// Split `text` into chunks, keeping its original spacing within each chunk
+    pub fn chunk(&self, text: &str) -> Vec<String> {
+        let (units, max, overlap): (Vec<(Range<usize>, usize)>, usize, usize) = match *self {
+            Chunker::Tokens {
+                max_tokens,
+                overlap,
+            } => {
+                let words = word_ranges(text)
+                    .map(|range| {
+                        let tokens = text[range.clone()].chars().count().div_ceil(4);
+                        (range, tokens)
+                    })
+                    .collect();
+                (words, max_tokens, overlap)
+            }
+            Chunker::Sentences {
+                max_sentences,
+                overlap,
+            } => {
+                let sentences = sentence_ranges(text).map(|range| (range, 1)).collect();
+                (sentences, max_sentences, overlap)
+            }
+        };
+        windows(&units, max.max(1), overlap)
+            .into_iter()
+            .map(|range| text[range].to_string())
+            .collect()
+    }
+}
+
+/// Byte ranges of the whitespace-separated words in `text`
+fn word_ranges(text: &str) -> impl Iterator<Item = Range<usize>> + '_ {
+    text.split_whitespace().map(move |word| {
+        let start = word.as_ptr() as usize - text.as_ptr() as usize;
+        start..start + word.len()
+    })
+}
+
+/// Byte ranges of the sentences in `text`, ending at `.`, `!` or `?` before whitespace
+fn sentence_ranges(text: &str) -> impl Iterator<Item = Range<usize>> + '_ {
+    let mut start = None;
+    let mut chars = text.char_indices().peekable();
+    std::iter::from_fn(move || {
+        while let Some((i, c)) = chars.next() {
+            if c.is_whitespace() {
+                continue;
+            }
+            let begin = *start.get_or_insert(i);
+            let at_end = chars.peek().is_none_or(|&(_, next)| next.is_whitespace());
+            if matches!(c, '.' | '!' | '?') && at_end {
+                start = None;
+                return Some(begin..i + c.len_utf8());
+            }
+        }
+        let begin = start.take()?;
+        Some(begin..text.trim_end().len())
+    })
+}
+
+/// Group weighted units into windows of at most `max` weight sharing about `overlap`
+fn windows(units: &[(Range<usize>, usize)], max: usize, overlap: usize) -> Vec<Range<usize>> {
+    let mut windows = Vec::new();
+    let mut start = 0;
+    while start < units.len() {
+        let mut end = start;
+        let mut weight = 0;
+        while end < units.len() && (end == start || weight + units[end].1 <= max) {
+            weight += units[end].1;
+            end += 1;
+        }
+        windows.push(units[start].0.start..units[end - 1].0.end);
+        if end == units.len() {
+            break;
+        }
+
+        // Step back over `overlap` worth of units, but always move forward
+        let mut next = end;
+        let mut shared = 0;
+        while next > start + 1 && shared + units[next - 1].1 <= overlap {
+            next -= 1;
+            shared += units[next].1;
+        }
+        start = next;
+    }
+    windows
+}
+
+/// Turns texts into embedding vectors
+#[async_trait]
+pub trait Embedder: fmt::Debug + Send + Sync {
+    /// Embed each text, returning one vector per input in order
+    async fn embed(&self, texts: &[String]) -> Result<Vec<Vec<f32>>>;
+}
+
+/// Embeds texts with a model of the embeddings endpoint
+#[derive(Debug, Clone)]
+pub struct ClientEmbedder {
+    client: Arc<Client>,
+    model: String,
+}
+
+impl ClientEmbedder {
+    /// Embed with `model` through `client`
+    pub fn new(client: Arc<Client>, model: impl Into<String>) -> Self {
+        Self {
+            client,
+            model: model.into(),
+        }
+    }
+}
+
+#[async_trait]
+impl Embedder for ClientEmbedder {
+    async fn embed(&self, texts: &[String]) -> Result<Vec<Vec<f32>>> {
+        let mut embeddings = Vec::with_capacity(texts.len());
+        for batch in texts.chunks(EMBED_BATCH_SIZE) {
+            embeddings.extend(self.client.embed(&self.model, batch.to_vec()).await?);
+        }
+        Ok(embeddings)
+    }
+}
+
+/// Indexes documents and adds the chunks relevant to a query to conversations
+#[derive(Debug, Clone)]
+pub struct Retriever {
+    embedder: Arc<dyn Embedder>,
+    store: Arc<dyn VectorStore>,
+    chunker: Chunker,
+    filter: Option<MetadataFilter>,
+}
+
+impl Retriever {
+    /// Create a retriever over `store`, chunking with the default `Chunker`
+    pub fn new(embedder: Arc<dyn Embedder>, store: Arc<dyn VectorStore>) -> Self {
+        Self {
+            embedder,
+            store,
+            chunker: Chunker::default(),
+            filter: None,
+        }
+    }
+
+    /// Set how documents are chunked
+    pub fn chunker(mut self, chunker: Chunker) -> Self {
+        self.chunker = chunker;
+        self
+    }
+
+    /// Only retrieve chunks whose metadata matches `filter`
+    pub fn filter(mut self, filter: MetadataFilter) -> Self {
+        self.filter = Some(filter);
+        self
+    }
+
+    /// Chunk, embed and store a document, returning the number of chunks
+    ///
+    /// Chunks are stored with `source` and `position` metadata. Any chunks
+    /// of an earlier version of the same `source` are removed first.
+    pub async fn add_document(&self, source: &str, text: &str) -> Result<usize> {
+        let texts = self.chunker.chunk(text);
+        let embeddings = self.embedder.embed(&texts).await?;
+        if embeddings.len() != texts.len() {
+            return Err(GrokError::Retrieval(format!(
+                "Embedder returned {} vectors for {} chunks",
+                embeddings.len(),
+                texts.len()
+            )));
+        }
+
+        let records: Vec<VectorRecord> = texts
+            .into_iter()
+            .zip(embeddings)
+            .enumerate()
+            .map(|(position, (text, embedding))| {
+                let mut metadata = Metadata::new();
+                metadata.insert("source".to_string(), source.into());
+                metadata.insert("position".to_string(), position.into());
+                VectorRecord {
+                    id: format!("{}#{}", source, position),
+                    text,
+                    embedding,
+                    metadata,
+                }
+            })
+            .collect();
+        let count = records.len();
+        self.remove_document(source).await?;
+        self.store.upsert(records).await?;
+        Ok(count)
+    }
+
+    /// Delete every chunk of a document
+    pub async fn remove_document(&self, source: &str) -> Result<()> {
+        self.store
+            .delete(&MetadataFilter::new().eq("source", source))
+            .await
+    }
+
+    /// The `k` chunks most relevant to `query`, best first
+    pub async fn retrieve(&self, query: &str, k: usize) -> Result<Vec<VectorMatch>> {
+        let embedding = self
+            .embedder
+            .embed(&[query.to_string()])
+            .await?
+            .into_iter()
+            .next()
+            .ok_or_else(|| {
+                GrokError::Retrieval("Embedder returned no vector for the query".to_string())
+            })?;
+        self.store.query(&embedding, k, self.filter.as_ref()).await
+    }
+
+    /// Insert the `k` chunks most relevant to `query` as a system message
+    /// before the last user message, returning the chunks used
+    pub async fn augment(
+        &self,
+        messages: &mut Vec<Message>,
+        query: &str,
+        k: usize,
+    ) -> Result<Vec<VectorMatch>> {
+        let chunks = self.retrieve(query, k).await?;
+        if !chunks.is_empty() {
+            let position = messages
+                .iter()
+                .rposition(|message| matches!(message.role, Role::User))
+                .unwrap_or(messages.len());
+            messages.insert(position, context_message(&chunks));
+        }
+        Ok(chunks)
+    }
+
+    /// Append the `k` chunks most relevant to `query` to a session as a system
+    /// message, to be sent with its next turn
+    pub async fn augment_session(
+        &self,
+        session: &Session,
+        query: &str,
+        k: usize,
+    ) -> Result<Vec<VectorMatch>> {
+        let chunks = self.retrieve(query, k).await?;
+        if !chunks.is_empty() {
+            session.append(context_message(&chunks)).await?;
+        }
+        Ok(chunks)
+    }
+}
+
+/// A system message quoting the chunks, numbered and attributed
+fn context_message(chunks: &[VectorMatch]) -> Message {
+    let mut content = String::from("Use the following context to answer if it is relevant.\n");
+    for (i, chunk) in chunks.iter().enumerate() {
+        content.push_str(&format!("\n[{}]", i + 1));
+        if let Some(source) = chunk.metadata.get("source").and_then(|s| s.as_str()) {
+            content.push_str(&format!(" ({})", source));
+        }
+        content.push_str(&format!("\n{}\n", chunk.text));
+    }
+    Message {
+        role: Role::System,
+        content: content.into(),
+        tool_calls: None,
+        tool_call_id: None,
+        name: None,
+    }
+}
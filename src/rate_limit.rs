@@ -0,0 +1,224 @@
+//! Client-side rate limiting of requests and tokens
+//!
+//! A `RateLimiter` holds requests back until they fit in a requests-per-minute
+//! and tokens-per-minute budget, instead of letting them fail with 429s. It
+//! also follows the quota the API reports in its `x-ratelimit-*` headers: once
+//! the server says a quota is used up, requests wait for it to reset. Share a
+//! limiter between clients with `Arc` to hold them all to one budget.
+
+use crate::chat::RateLimitInfo;
+use std::collections::VecDeque;
+use std::sync::{Mutex, PoisonError};
+use std::time::{Duration, Instant};
+
+/// Period the per-minute limits are counted over
+const WINDOW: Duration = Duration::from_secs(60);
+
+/// Holds requests back to stay under request and token rates
+#[derive(Debug, Default)]
+pub struct RateLimiter {
+    requests_per_minute: Option<u32>,
+    tokens_per_minute: Option<u64>,
+    state: Mutex<State>,
+}
+
+#[derive(Debug, Default)]
+struct State {
+    next_id: u64,
+    /// Requests let through in the last minute, oldest first
+    sent: VecDeque<Sent>,
+    /// Quota last reported by the server, less what has been sent since
+    server: ServerQuota,
+}
+
+#[derive(Debug)]
+struct Sent {
+    id: u64,
+    at: Instant,
+    tokens: u64,
+}
+
+#[derive(Debug, Default)]
+struct ServerQuota {
+    remaining_requests: Option<u64>,
+    requests_reset: Option<Instant>,
+    remaining_tokens: Option<u64>,
+    tokens_reset: Option<Instant>,
+}
+
+/// A request let through by a `RateLimiter`, with the tokens reserved for it
+#[derive(Debug)]
+pub struct RateLimitPermit {
+    id: u64,
+    tokens: u64,
+}
+
+impl RateLimitPermit {
+    /// Tokens reserved for the request
+    pub fn tokens(&self) -> u64 {
+        self.tokens
+    }
+}
+
+impl RateLimiter {
+    /// Create a limiter with no limits of its own, following only server quotas
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Allow at most this many requests per minute
+    pub fn requests_per_minute(mut self, requests: u32) -> Self {
+        self.requests_per_minute = Some(requests);
+        self
+    }
+
+    /// Allow at most this many tokens, prompt and completion, per minute
+    pub fn tokens_per_minute(mut self, tokens: u64) -> Self {
+        self.tokens_per_minute = Some(tokens);
+        self
+    }
+
+    /// Wait until a request using about `tokens` tokens fits, then reserve them
+    ///
+    /// A request larger than the whole token limit is let through alone, once
+    /// nothing else has been sent for a minute.
+    pub async fn acquire(&self, tokens: u64) -> RateLimitPermit {
+        loop {
+            let wait = {
+                let mut state = self.lock();
+                let now = Instant::now();
+                match self.wait_time(&mut state, now, tokens) {
+                    None => return state.send(now, tokens),
+                    Some(wait) => wait,
+                }
+            };
+            #[cfg(feature = "tracing")]
+            tracing::debug!(?wait, tokens, "waiting for rate limit");
+            tokio::time::sleep(wait.max(Duration::from_millis(1))).await;
+        }
+    }
+
+    /// Replace the tokens reserved for a request with what it actually used
+    pub fn settle(&self, permit: RateLimitPermit, tokens: u64) {
+        let mut state = self.lock();
+        if let Some(sent) = state.sent.iter_mut().find(|sent| sent.id == permit.id) {
+            sent.tokens = tokens;
+        }
+    }
+
+    /// Adopt the quota reported in a response's rate-limit headers
+    pub fn observe(&self, info: &RateLimitInfo) {
+        let now = Instant::now();
+        let mut state = self.lock();
+        let server = &mut state.server;
+        if info.remaining_requests.is_some() {
+            server.remaining_requests = info.remaining_requests;
+            server.requests_reset = info.reset_requests.map(|reset| now + reset);
+        }
+        if info.remaining_tokens.is_some() {
+            server.remaining_tokens = info.remaining_tokens;
+            server.tokens_reset = info.reset_tokens.map(|reset| now + reset);
+        }
+    }
+
+    /// Requests and tokens sent in the last minute
+    pub fn used(&self) -> (usize, u64) {
+        let mut state = self.lock();
+        state.prune(Instant::now());
+        let tokens = state.sent.iter().map(|sent| sent.tokens).sum();
+        (state.sent.len(), tokens)
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, State> {
+        self.state.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// How long until a request of `tokens` fits, or `None` if it fits now
+    fn wait_time(&self, state: &mut State, now: Instant, tokens: u64) -> Option<Duration> {
+        state.prune(now);
+        let mut wait = state.server.wait_time(now, tokens);
+        let mut wait_for = |until: Instant| {
+            let until = until.saturating_duration_since(now);
+            wait = Some(wait.map_or(until, |wait: Duration| wait.max(until)));
+        };
+
+        // The oldest requests have to leave the window before this one fits
+        if let Some(limit) = self.requests_per_minute {
+            let limit = limit.max(1) as usize;
+            if state.sent.len() >= limit {
+                wait_for(state.sent[state.sent.len() - limit].at + WINDOW);
+            }
+        }
+        if let Some(limit) = self.tokens_per_minute {
+            let mut used: u64 = state.sent.iter().map(|sent| sent.tokens).sum();
+            if used > 0 && used + tokens > limit {
+                for sent in &state.sent {
+                    used -= sent.tokens;
+                    if used == 0 || used + tokens <= limit {
+                        wait_for(sent.at + WINDOW);
+                        break;
+                    }
+                }
+            }
+        }
+        wait
+    }
+}
+
+impl State {
+    /// Forget requests that have left the window and server quotas that have reset
+    fn prune(&mut self, now: Instant) {
+        while self
+            .sent
+            .front()
+            .is_some_and(|sent| sent.at + WINDOW <= now)
+        {
+            self.sent.pop_front();
+        }
+        let server = &mut self.server;
+        if server.requests_reset.is_some_and(|reset| reset <= now) {
+            server.remaining_requests = None;
+            server.requests_reset = None;
+        }
+        if server.tokens_reset.is_some_and(|reset| reset <= now) {
+            server.remaining_tokens = None;
+            server.tokens_reset = None;
+        }
+    }
+
+    fn send(&mut self, now: Instant, tokens: u64) -> RateLimitPermit {
+        let id = self.next_id;
+        self.next_id += 1;
+        self.sent.push_back(Sent {
+            id,
+            at: now,
+            tokens,
+        });
+        let server = &mut self.server;
+        if let Some(ref mut remaining) = server.remaining_requests {
+            *remaining = remaining.saturating_sub(1);
+        }
+        if let Some(ref mut remaining) = server.remaining_tokens {
+            *remaining = remaining.saturating_sub(tokens);
+        }
+        RateLimitPermit { id, tokens }
+    }
+}
+
+impl ServerQuota {
+    /// How long until the server's quota has room, if it's used up
+    fn wait_time(&self, now: Instant, tokens: u64) -> Option<Duration> {
+        let requests = self
+            .requests_reset
+            .filter(|_| self.remaining_requests == Some(0));
+        let tokens = self.tokens_reset.filter(|_| {
+            self.remaining_tokens
+                .is_some_and(|remaining| remaining < tokens)
+        });
+        requests
+            .into_iter()
+            .chain(tokens)
+            .max()
+            .map(|until| until.saturating_duration_since(now))
+    }
+}
@@ -0,0 +1,259 @@
+//! Masking personal data before it leaves the process
+//!
+//! A `Redactor` replaces emails, phone numbers, card numbers and any custom
+//! patterns with placeholders such as `[EMAIL_1]`, and remembers what each
+//! placeholder stands for so text can be restored for local display.
+//!
+//! As a guardrail it redacts every outgoing message. Added to a `Client`, only
+//! requests are redacted; added to a `Session`, the stored history is redacted
+//! too, so the originals never reach SQLite.
+
+use crate::chat::Message;
+use crate::error::{GrokError, Result};
+use crate::guardrail::{GuardStage, GuardVerdict, Guardrail};
+use async_trait::async_trait;
+use regex::Regex;
+use std::collections::HashMap;
+use std::fmt;
+use std::ops::Range;
+use std::sync::{Mutex, PoisonError};
+
+/// Finds one kind of sensitive data in text
+pub trait Detector: fmt::Debug + Send + Sync {
+    /// Label used in placeholders, such as `EMAIL`
+    fn kind(&self) -> &str;
+
+    /// Byte ranges of every match in `text`
+    fn find(&self, text: &str) -> Vec<Range<usize>>;
+}
+
+/// A detector matching a regular expression
+#[derive(Debug, Clone)]
+pub struct PatternDetector {
+    kind: String,
+    regex: Regex,
+}
+
+impl PatternDetector {
+    /// Create a detector for `pattern`, labelled `kind`
+    pub fn new(kind: impl Into<String>, pattern: &str) -> Result<Self> {
+        let regex = Regex::new(pattern).map_err(|e| {
+            GrokError::InvalidConfig(format!("Failed to compile redaction pattern: {}", e))
+        })?;
+        Ok(Self {
+            kind: kind.into(),
+            regex,
+        })
+    }
+
+    /// Email addresses
+    pub fn email() -> Self {
+        Self::builtin(
+            "EMAIL",
+            r"[A-Za-z0-9._%+-]+@[A-Za-z0-9-]+(?:\.[A-Za-z0-9-]+)*\.[A-Za-z]{2,}",
+        )
+    }
+
+    /// Phone numbers with an optional country code, such as `+1 (555) 123-4567`
+    pub fn phone() -> Self {
+        Self::builtin(
+            "PHONE",
+            r"(?:\+\d{1,3}[ .-]?)?(?:\(\d{2,4}\)|\b\d{2,4})[ .-]?\d{3,4}[ .-]?\d{3,4}\b",
+        )
+    }
+
+    fn builtin(kind: &str, pattern: &str) -> Self {
+        Self::new(kind, pattern).expect("built-in redaction patterns are valid")
+    }
+}
+
+impl Detector for PatternDetector {
+    fn kind(&self) -> &str {
+        &self.kind
+    }
+
+    fn find(&self, text: &str) -> Vec<Range<usize>> {
+        self.regex.find_iter(text).map(|m| m.range()).collect()
+    }
+}
+
+/// Payment card numbers that pass the Luhn check
+#[derive(Debug, Clone)]
+pub struct CardNumberDetector {
+    regex: Regex,
+}
+
+impl Default for CardNumberDetector {
+    fn default() -> Self {
+        Self {
+            regex: Regex::new(r"\b\d(?:[ -]?\d){12,18}\b").expect("card pattern is valid"),
+        }
+    }
+}
+
+impl Detector for CardNumberDetector {
+    fn kind(&self) -> &str {
+        "CARD"
+    }
+
+    fn find(&self, text: &str) -> Vec<Range<usize>> {
+        self.regex
+            .find_iter(text)
+            .filter(|m| luhn_valid(m.as_str()))
+            .map(|m| m.range())
+            .collect()
+    }
+}
+
+fn luhn_valid(number: &str) -> bool {
+    let digits: Vec<u32> = number.chars().filter_map(|c| c.to_digit(10)).collect();
+    let sum: u32 = digits
+        .iter()
+        .rev()
+        .enumerate()
+        .map(|(i, &d)| match (i % 2, d * 2) {
+            (0, _) => d,
+            (_, doubled) if doubled > 9 => doubled - 9,
+            (_, doubled) => doubled,
+        })
+        .sum();
+    sum.is_multiple_of(10)
+}
+
+/// Placeholders handed out so far, in both directions
+#[derive(Debug, Default)]
+struct TokenMap {
+    by_value: HashMap<String, String>,
+    by_token: HashMap<String, String>,
+    counts: HashMap<String, usize>,
+}
+
+impl TokenMap {
+    fn token_for(&mut self, kind: &str, value: &str) -> String {
+        if let Some(token) = self.by_value.get(value) {
+            return token.clone();
+        }
+        let count = self.counts.entry(kind.to_string()).or_insert(0);
+        *count += 1;
+        let token = format!("[{}_{}]", kind, count);
+        self.by_value.insert(value.to_string(), token.clone());
+        self.by_token.insert(token.clone(), value.to_string());
+        token
+    }
+}
+
+/// Replaces sensitive data with reversible placeholders
+///
+/// The same value always gets the same placeholder, so the model can still
+/// tell that two mentions refer to the same thing.
+#[derive(Debug, Default)]
+pub struct Redactor {
+    detectors: Vec<Box<dyn Detector>>,
+    tokens: Mutex<TokenMap>,
+}
+
+impl Redactor {
+    /// Create a redactor without detectors
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Create a redactor for card numbers, emails and phone numbers
+    pub fn with_defaults() -> Self {
+        Self::new()
+            .detector(CardNumberDetector::default())
+            .detector(PatternDetector::email())
+            .detector(PatternDetector::phone())
+    }
+
+    /// Add a detector; where matches overlap, the longest wins, then the earliest added
+    pub fn detector(mut self, detector: impl Detector + 'static) -> Self {
+        self.detectors.push(Box::new(detector));
+        self
+    }
+
+    /// Add a detector for a regular expression
+    pub fn pattern(self, kind: impl Into<String>, pattern: &str) -> Result<Self> {
+        Ok(self.detector(PatternDetector::new(kind, pattern)?))
+    }
+
+    /// Replace everything the detectors find with placeholders
+    pub fn redact(&self, text: &str) -> String {
+        let mut matches: Vec<(Range<usize>, usize)> = self
+            .detectors
+            .iter()
+            .enumerate()
+            .flat_map(|(i, detector)| detector.find(text).into_iter().map(move |r| (r, i)))
+            .filter(|(range, _)| !range.is_empty())
+            .collect();
+        if matches.is_empty() {
+            return text.to_string();
+        }
+        matches.sort_by_key(|(range, i)| (range.start, std::cmp::Reverse(range.end), *i));
+
+        let mut tokens = self.tokens.lock().unwrap_or_else(PoisonError::into_inner);
+        let mut redacted = String::with_capacity(text.len());
+        let mut end = 0;
+        for (range, i) in matches {
+            if range.start < end {
+                continue;
+            }
+            redacted.push_str(&text[end..range.start]);
+            redacted.push_str(&tokens.token_for(self.detectors[i].kind(), &text[range.clone()]));
+            end = range.end;
+        }
+        redacted.push_str(&text[end..]);
+        redacted
+    }
+
+    /// Put the original values back in place of placeholders this redactor handed out
+    pub fn restore(&self, text: &str) -> String {
+        let tokens = self.tokens.lock().unwrap_or_else(PoisonError::into_inner);
+        let mut restored = String::with_capacity(text.len());
+        let mut rest = text;
+        while let Some(start) = rest.find('[') {
+            restored.push_str(&rest[..start]);
+            rest = &rest[start..];
+            let original = rest
+                .find(']')
+                .and_then(|end| tokens.by_token.get(&rest[..=end]).map(|value| (end, value)));
+            match original {
+                Some((end, value)) => {
+                    restored.push_str(value);
+                    rest = &rest[end + 1..];
+                }
+                None => {
+                    restored.push('[');
+                    rest = &rest[1..];
+                }
+            }
+        }
+        restored.push_str(rest);
+        restored
+    }
+
+    /// The placeholders handed out so far and the values they stand for
+    pub fn tokens(&self) -> HashMap<String, String> {
+        self.tokens
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .by_token
+            .clone()
+    }
+}
+
+#[async_trait]
+impl Guardrail for Redactor {
+    async fn check(&self, message: &Message, stage: GuardStage) -> Result<GuardVerdict> {
+        if stage != GuardStage::Request {
+            return Ok(GuardVerdict::Allow);
+        }
+        let text = message.content.text();
+        let redacted = self.redact(&text);
+        if redacted == text {
+            Ok(GuardVerdict::Allow)
+        } else {
+            Ok(GuardVerdict::Redact(redacted))
+        }
+    }
+}
@@ -0,0 +1,256 @@
+//! Re-running stored conversations through another model
+//!
+//! `SessionManager::replay` sends each user turn of a session to a new model,
+//! with the original conversation up to that turn as context, and collects
+//! the new replies next to the original ones. The report compares replies
+//! line by line and sums up token usage and latency on both sides.
+
+use crate::chat::{ChatCompletion, Message, Model, Role};
+use crate::client::ChatOptions;
+use crate::error::Result;
+use crate::provider::ChatProvider;
+use std::fmt;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+/// How to replay a session
+#[derive(Debug, Clone, Default)]
+pub struct ReplayOptions {
+    /// System prompt to use instead of the session's own
+    pub system_prompt: Option<String>,
+    /// Options for every replayed request, such as temperature
+    pub chat_options: Option<ChatOptions>,
+    /// Replay at most this many user turns, from the start
+    pub max_turns: Option<usize>,
+}
+
+/// Token usage and latency of a set of completions
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ReplayUsage {
+    /// Number of completions
+    pub completions: u64,
+    /// Prompt tokens used
+    pub prompt_tokens: u64,
+    /// Completion tokens used
+    pub completion_tokens: u64,
+    /// Mean request latency
+    pub average_latency: Duration,
+}
+
+/// One line of a comparison between the original and the replayed reply
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DiffLine {
+    /// In both replies
+    Same(String),
+    /// Only in the original reply
+    Removed(String),
+    /// Only in the replayed reply
+    Added(String),
+}
+
+/// A user turn and both replies to it
+#[derive(Debug)]
+pub struct ReplayTurn {
+    /// The user message
+    pub user: String,
+    /// The reply stored in the session, if the turn got one
+    pub original: Option<String>,
+    /// The new model's reply
+    pub replayed: ChatCompletion,
+    /// How long the replayed request took
+    pub latency: Duration,
+}
+
+impl ReplayTurn {
+    /// Whether the replies differ, ignoring surrounding whitespace
+    pub fn changed(&self) -> bool {
+        self.original.as_deref().map(str::trim) != Some(self.replayed.message.content.text().trim())
+    }
+
+    /// Compare the replies line by line
+    pub fn diff(&self) -> Vec<DiffLine> {
+        diff_lines(
+            self.original.as_deref().unwrap_or_default(),
+            &self.replayed.message.content.text(),
+        )
+    }
+}
+
+/// The result of replaying a session
+#[derive(Debug)]
+pub struct ReplayReport {
+    /// The replayed session
+    pub session_id: String,
+    /// Model the session was held with
+    pub original_model: Model,
+    /// Model the turns were replayed with
+    pub model: Model,
+    /// Every replayed turn, in order
+    pub turns: Vec<ReplayTurn>,
+    /// Usage recorded for the original conversation, when its storage has any
+    pub original_usage: Option<ReplayUsage>,
+    /// Usage of the replayed turns
+    pub replay_usage: ReplayUsage,
+}
+
+impl ReplayReport {
+    /// Number of turns whose reply changed
+    pub fn changed_turns(&self) -> usize {
+        self.turns.iter().filter(|turn| turn.changed()).count()
+    }
+}
+
+/// Each turn as the two replies side by side, followed by the usage on both sides
+impl fmt::Display for ReplayReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        const WIDTH: usize = 60;
+
+        writeln!(
+            f,
+            "Session {}: {} vs {}",
+            self.session_id, self.original_model, self.model
+        )?;
+        for (index, turn) in self.turns.iter().enumerate() {
+            writeln!(f)?;
+            writeln!(f, "Turn {}: {}", index + 1, turn.user)?;
+            for line in turn.diff() {
+                let (marker, left, right) = match &line {
+                    DiffLine::Same(text) => (' ', text.as_str(), text.as_str()),
+                    DiffLine::Removed(text) => ('<', text.as_str(), ""),
+                    DiffLine::Added(text) => ('>', "", text.as_str()),
+                };
+                writeln!(f, "{:<WIDTH$} {} {}", left, marker, right)?;
+            }
+            writeln!(f, "({:?})", turn.latency)?;
+        }
+
+        writeln!(f)?;
+        let usage = |usage: &ReplayUsage| {
+            format!(
+                "{} completions, {} prompt + {} completion tokens, {:?} average latency",
+                usage.completions,
+                usage.prompt_tokens,
+                usage.completion_tokens,
+                usage.average_latency
+            )
+        };
+        match &self.original_usage {
+            Some(original) => writeln!(f, "Original: {}", usage(original))?,
+            None => writeln!(f, "Original: no usage recorded")?,
+        }
+        writeln!(f, "Replayed: {}", usage(&self.replay_usage))
+    }
+}
+
+/// Send each user turn of `history` to `model`, with the history before it as context
+pub(crate) async fn replay(
+    provider: &dyn ChatProvider,
+    history: &[Arc<Message>],
+    model: Model,
+    options: &ReplayOptions,
+) -> Result<(Vec<ReplayTurn>, ReplayUsage)> {
+    let mut context: Vec<Arc<Message>> = Vec::with_capacity(history.len() + 1);
+    if let Some(ref system_prompt) = options.system_prompt {
+        context.push(Arc::new(Message {
+            role: Role::System,
+            content: system_prompt.clone().into(),
+            tool_calls: None,
+            tool_call_id: None,
+            name: None,
+        }));
+    }
+
+    let mut turns = Vec::new();
+    let mut usage = ReplayUsage::default();
+    let mut total_latency = Duration::ZERO;
+    for (index, message) in history.iter().enumerate() {
+        if options.system_prompt.is_some() && message.role == Role::System {
+            continue;
+        }
+        context.push(message.clone());
+        if message.role != Role::User {
+            continue;
+        }
+        if options.max_turns.is_some_and(|max| turns.len() >= max) {
+            break;
+        }
+
+        let started = Instant::now();
+        let replayed = provider
+            .chat(model, context.clone(), None, options.chat_options.clone())
+            .await?;
+        let latency = started.elapsed();
+
+        usage.completions += 1;
+        total_latency += latency;
+        if let Some(ref reported) = replayed.usage {
+            usage.prompt_tokens += reported.prompt_tokens as u64;
+            usage.completion_tokens += reported.completion_tokens as u64;
+        }
+
+        // The original reply is the last assistant text before the next user turn
+        let original = history[index + 1..]
+            .iter()
+            .take_while(|next| next.role != Role::User)
+            .filter(|next| next.role == Role::Assistant && !next.content.is_empty())
+            .last()
+            .map(|reply| reply.content.to_string());
+
+        turns.push(ReplayTurn {
+            user: message.content.to_string(),
+            original,
+            replayed,
+            latency,
+        });
+    }
+
+    if usage.completions > 0 {
+        usage.average_latency = total_latency / usage.completions as u32;
+    }
+    Ok((turns, usage))
+}
+
+/// Line diff of two texts from their longest common subsequence
+fn diff_lines(original: &str, replayed: &str) -> Vec<DiffLine> {
+    let old: Vec<&str> = original.lines().collect();
+    let new: Vec<&str> = replayed.lines().collect();
+
+    // common[i][j] is the length of the LCS of old[i..] and new[j..]
+    let mut common = vec![vec![0usize; new.len() + 1]; old.len() + 1];
+    for i in (0..old.len()).rev() {
+        for j in (0..new.len()).rev() {
+            common[i][j] = if old[i] == new[j] {
+                common[i + 1][j + 1] + 1
+            } else {
+                common[i + 1][j].max(common[i][j + 1])
+            };
+        }
+    }
+
+    let mut lines = Vec::with_capacity(old.len().max(new.len()));
+    let (mut i, mut j) = (0, 0);
+    while i < old.len() && j < new.len() {
+        if old[i] == new[j] {
+            lines.push(DiffLine::Same(old[i].to_string()));
+            i += 1;
+            j += 1;
+        } else if common[i + 1][j] >= common[i][j + 1] {
+            lines.push(DiffLine::Removed(old[i].to_string()));
+            i += 1;
+        } else {
+            lines.push(DiffLine::Added(new[j].to_string()));
+            j += 1;
+        }
+    }
+    lines.extend(
+        old[i..]
+            .iter()
+            .map(|line| DiffLine::Removed(line.to_string())),
+    );
+    lines.extend(
+        new[j..]
+            .iter()
+            .map(|line| DiffLine::Added(line.to_string())),
+    );
+    lines
+}
@@ -0,0 +1,299 @@
+//! API keys fetched from secret managers
+//!
+//! A client built with `ClientBuilder::api_key_provider` fetches its key from
+//! a `SecretProvider` on first use and again once the refresh interval has
+//! passed, so keys can be rotated without a restart and never have to sit
+//! in environment variables or config files.
+
+use crate::error::{GrokError, Result};
+use async_trait::async_trait;
+use std::fmt;
+use std::sync::{Arc, Mutex, PoisonError};
+use std::time::{Duration, Instant};
+
+/// Fetches a secret, such as the API key, from wherever it's kept
+#[async_trait]
+pub trait SecretProvider: fmt::Debug + Send + Sync {
+    /// The secret's current value
+    async fn fetch(&self) -> Result<String>;
+}
+
+/// A password in the OS keyring: the macOS Keychain, Windows Credential
+/// Manager or the Secret Service on Linux
+#[cfg(feature = "keyring")]
+#[derive(Debug, Clone)]
+pub struct KeyringSecret {
+    service: String,
+    user: String,
+}
+
+#[cfg(feature = "keyring")]
+impl KeyringSecret {
+    /// The entry stored for `user` under `service`
+    pub fn new(service: impl Into<String>, user: impl Into<String>) -> Self {
+        Self {
+            service: service.into(),
+            user: user.into(),
+        }
+    }
+}
+
+#[cfg(feature = "keyring")]
+#[async_trait]
+impl SecretProvider for KeyringSecret {
+    async fn fetch(&self) -> Result<String> {
+        let (service, user) = (self.service.clone(), self.user.clone());
+        let password = tokio::task::spawn_blocking(move || {
+            keyring::Entry::new(&service, &user)?.get_password()
+        })
+        .await
+        .map_err(|e| GrokError::InvalidConfig(format!("Failed to read keyring: {}", e)))?;
+        password.map_err(|e| {
+            GrokError::InvalidConfig(format!(
+                "Failed to read {}/{} from keyring: {}",
+                self.service, self.user, e
+            ))
+        })
+    }
+}
+
+/// A field of a secret in HashiCorp Vault's KV version 2 engine
+#[derive(Clone)]
+pub struct VaultSecret {
+    http_client: reqwest::Client,
+    address: String,
+    token: String,
+    mount: String,
+    path: String,
+    field: String,
+}
+
+impl VaultSecret {
+    /// The `api_key` field of the secret at `path` under the `secret` mount
+    pub fn new(
+        address: impl Into<String>,
+        token: impl Into<String>,
+        path: impl Into<String>,
+    ) -> Self {
+        Self {
+            http_client: reqwest::Client::new(),
+            address: address.into().trim_end_matches('/').to_string(),
+            token: token.into(),
+            mount: "secret".to_string(),
+            path: path.into(),
+            field: "api_key".to_string(),
+        }
+    }
+
+    /// Read from this KV mount instead of `secret`
+    pub fn mount(mut self, mount: impl Into<String>) -> Self {
+        self.mount = mount.into();
+        self
+    }
+
+    /// Read this field of the secret instead of `api_key`
+    pub fn field(mut self, field: impl Into<String>) -> Self {
+        self.field = field.into();
+        self
+    }
+}
+
+impl fmt::Debug for VaultSecret {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("VaultSecret")
+            .field("address", &self.address)
+            .field("mount", &self.mount)
+            .field("path", &self.path)
+            .field("field", &self.field)
+            .finish_non_exhaustive()
+    }
+}
+
+#[async_trait]
+impl SecretProvider for VaultSecret {
+    async fn fetch(&self) -> Result<String> {
+        let url = format!("{}/v1/{}/data/{}", self.address, self.mount, self.path);
+        let response = self
+            .http_client
+            .get(&url)
+            .header("X-Vault-Token", &self.token)
+            .send()
+            .await?;
+        if !response.status().is_success() {
+            return Err(GrokError::InvalidConfig(format!(
+                "Failed to read {} from Vault: status {}",
+                self.path,
+                response.status()
+            )));
+        }
+        let body: serde_json::Value = response.json().await?;
+        body["data"]["data"][&self.field]
+            .as_str()
+            .map(str::to_string)
+            .ok_or_else(|| {
+                GrokError::InvalidConfig(format!(
+                    "Vault secret {} has no string field '{}'",
+                    self.path, self.field
+                ))
+            })
+    }
+}
+
+/// A secret in AWS Secrets Manager, read with the default AWS credential chain
+#[cfg(feature = "aws-secrets")]
+#[derive(Debug)]
+pub struct AwsSecret {
+    secret_id: String,
+    json_key: Option<String>,
+    client: tokio::sync::OnceCell<aws_sdk_secretsmanager::Client>,
+}
+
+#[cfg(feature = "aws-secrets")]
+impl AwsSecret {
+    /// The secret with this name or ARN, used as a whole
+    pub fn new(secret_id: impl Into<String>) -> Self {
+        Self {
+            secret_id: secret_id.into(),
+            json_key: None,
+            client: tokio::sync::OnceCell::new(),
+        }
+    }
+
+    /// Use a client configured elsewhere instead of the default AWS configuration
+    pub fn with_client(
+        secret_id: impl Into<String>,
+        client: aws_sdk_secretsmanager::Client,
+    ) -> Self {
+        Self {
+            client: tokio::sync::OnceCell::new_with(Some(client)),
+            ..Self::new(secret_id)
+        }
+    }
+
+    /// Treat the secret as a JSON object and use the string under `key`
+    pub fn json_key(mut self, key: impl Into<String>) -> Self {
+        self.json_key = Some(key.into());
+        self
+    }
+}
+
+#[cfg(feature = "aws-secrets")]
+#[async_trait]
+impl SecretProvider for AwsSecret {
+    async fn fetch(&self) -> Result<String> {
+        let client = self
+            .client
+            .get_or_init(|| async {
+                let config = aws_config::load_defaults(aws_config::BehaviorVersion::latest()).await;
+                aws_sdk_secretsmanager::Client::new(&config)
+            })
+            .await;
+        let output = client
+            .get_secret_value()
+            .secret_id(&self.secret_id)
+            .send()
+            .await
+            .map_err(|e| {
+                GrokError::InvalidConfig(format!(
+                    "Failed to read {} from Secrets Manager: {}",
+                    self.secret_id,
+                    aws_sdk_secretsmanager::error::DisplayErrorContext(e)
+                ))
+            })?;
+        let secret = output.secret_string().ok_or_else(|| {
+            GrokError::InvalidConfig(format!("Secret {} has no string value", self.secret_id))
+        })?;
+
+        let Some(key) = &self.json_key else {
+            return Ok(secret.to_string());
+        };
+        let object: serde_json::Value = serde_json::from_str(secret)?;
+        object[key].as_str().map(str::to_string).ok_or_else(|| {
+            GrokError::InvalidConfig(format!(
+                "Secret {} has no string key '{}'",
+                self.secret_id, key
+            ))
+        })
+    }
+}
+
+/// A provider's secret, fetched on first use and again once `refresh` has passed
+///
+/// If a refresh fails, the previous value keeps being used until a fetch succeeds.
+pub(crate) struct CachedSecret {
+    provider: Arc<dyn SecretProvider>,
+    refresh: Option<Duration>,
+    current: Mutex<Option<(String, Instant)>>,
+    /// Held while fetching, so concurrent requests share one fetch
+    fetching: tokio::sync::Mutex<()>,
+}
+
+impl CachedSecret {
+    pub(crate) fn new(provider: Arc<dyn SecretProvider>, refresh: Option<Duration>) -> Self {
+        Self {
+            provider,
+            refresh,
+            current: Mutex::new(None),
+            fetching: tokio::sync::Mutex::new(()),
+        }
+    }
+
+    /// The secret, fetching it if there is no fresh value
+    pub(crate) async fn get(&self) -> Result<String> {
+        if let Some(value) = self.fresh() {
+            return Ok(value);
+        }
+        let _fetching = self.fetching.lock().await;
+        // Another request may have fetched it while this one waited
+        if let Some(value) = self.fresh() {
+            return Ok(value);
+        }
+
+        match self.provider.fetch().await {
+            Ok(value) => {
+                *self.lock() = Some((value.clone(), Instant::now()));
+                Ok(value)
+            }
+            Err(error) => match self.cached() {
+                Some(stale) => {
+                    #[cfg(feature = "tracing")]
+                    tracing::warn!(%error, "failed to refresh API key, using the previous one");
+                    Ok(stale)
+                }
+                None => Err(error.context("Failed to fetch API key")),
+            },
+        }
+    }
+
+    /// The last fetched value, however old
+    pub(crate) fn cached(&self) -> Option<String> {
+        self.lock().as_ref().map(|(value, _)| value.clone())
+    }
+
+    /// Forget the value, so the next request fetches it again
+    pub(crate) fn invalidate(&self) {
+        *self.lock() = None;
+    }
+
+    fn fresh(&self) -> Option<String> {
+        let current = self.lock();
+        let (value, fetched) = current.as_ref()?;
+        match self.refresh {
+            Some(refresh) if fetched.elapsed() >= refresh => None,
+            _ => Some(value.clone()),
+        }
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, Option<(String, Instant)>> {
+        self.current.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+impl fmt::Debug for CachedSecret {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("CachedSecret")
+            .field("provider", &self.provider)
+            .field("refresh", &self.refresh)
+            .finish_non_exhaustive()
+    }
+}
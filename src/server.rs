@@ -0,0 +1,250 @@
+//! axum handlers for serving chat over HTTP
+//!
+//! `router` mounts ready-made endpoints on top of a client: JSON completions,
+//! an SSE stream in the API's own event format, and a websocket chat backed
+//! by a session. The handlers can also be mounted individually with
+//! `ChatState` as the router state.
+
+use crate::channel::StreamEvent;
+use crate::chat::{ChatCompletion, Message, Model};
+use crate::error::{Entity, ErrorKind, GrokError, Result};
+use crate::service::{ChatRequest, ChatService};
+use crate::session::{Session, SessionManager};
+use crate::Client;
+use axum::extract::ws::{Message as Frame, WebSocket, WebSocketUpgrade};
+use axum::extract::{Query, State};
+use axum::http::StatusCode;
+use axum::response::sse::{Event, KeepAlive, Sse};
+use axum::response::{IntoResponse, Response};
+use axum::routing::{get, post};
+use axum::{Json, Router};
+use futures::{Stream, StreamExt};
+use serde::{Deserialize, Serialize};
+use std::convert::Infallible;
+use std::sync::Arc;
+use tower_service::Service;
+
+/// State shared by the chat handlers
+#[derive(Debug, Clone)]
+pub struct ChatState {
+    /// Client used for completions
+    pub client: Arc<Client>,
+    /// Sessions behind websocket conversations
+    pub sessions: Arc<SessionManager>,
+}
+
+impl ChatState {
+    /// Use a session manager that's flushed when the client shuts down
+    pub fn new(client: Arc<Client>) -> Self {
+        let sessions = client.session_manager();
+        Self { client, sessions }
+    }
+}
+
+/// The chat handlers under their default paths
+///
+/// - `POST /chat/completions`: a `ChatRequest` body, answered with the completion as JSON
+/// - `POST /chat/stream`: a `ChatRequest` body, answered with server-sent events
+/// - `GET /sessions/ws`: a websocket conversation, see `session_socket`
+pub fn router(state: ChatState) -> Router {
+    Router::new()
+        .route("/chat/completions", post(chat))
+        .route("/chat/stream", post(chat_stream))
+        .route("/sessions/ws", get(session_socket))
+        .with_state(state)
+}
+
+/// Complete a conversation through `ChatService`
+pub async fn chat(
+    State(state): State<ChatState>,
+    Json(request): Json<ChatRequest>,
+) -> Result<Json<ChatCompletion>> {
+    let completion = ChatService::new(state.client).call(request).await?;
+    Ok(Json(completion))
+}
+
+/// Relay a streamed completion as server-sent events
+///
+/// Each chunk is sent as a `data:` event in the API's format and the stream
+/// ends with `data: [DONE]`, so OpenAI-compatible clients can consume it
+/// unchanged. A failure mid-stream is sent as an `error` event.
+pub async fn chat_stream(
+    State(state): State<ChatState>,
+    Json(request): Json<ChatRequest>,
+) -> Result<Sse<impl Stream<Item = std::result::Result<Event, Infallible>>>> {
+    let chunks = state
+        .client
+        .chat_stream_with_options(
+            request.model,
+            request.messages,
+            request.tools,
+            Some(request.options),
+        )
+        .await?;
+
+    let events = chunks
+        .map(|chunk| {
+            let event = chunk.and_then(|chunk| {
+                Event::default()
+                    .json_data(&chunk)
+                    .map_err(|e| GrokError::InvalidConfig(format!("Failed to encode chunk: {}", e)))
+            });
+            Ok(event.unwrap_or_else(|e| Event::default().event("error").data(e.to_string())))
+        })
+        .chain(futures::stream::once(async {
+            Ok(Event::default().data("[DONE]"))
+        }));
+    Ok(Sse::new(events).keep_alive(KeepAlive::default()))
+}
+
+/// Query parameters for `session_socket`
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct SessionParams {
+    /// Continue this session instead of starting a new one
+    pub session: Option<String>,
+    /// Model for a new session; grok-4 by default
+    pub model: Option<Model>,
+}
+
+/// Events sent to websocket clients, as JSON text frames tagged by `type`
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum SessionEvent {
+    /// The session the socket is attached to; sent first
+    Session { id: String },
+    /// Text generated so far in the reply
+    Delta { content: String },
+    /// The completed reply, now part of the session's history
+    Done { message: Message },
+    /// The turn failed; the socket stays open for the next message
+    Error { message: String },
+}
+
+/// Chat over a websocket, one turn per text frame
+///
+/// Each text frame is a user message in the session. The reply streams back
+/// as `delta` events followed by `done`.
+pub async fn session_socket(
+    ws: WebSocketUpgrade,
+    State(state): State<ChatState>,
+    Query(params): Query<SessionParams>,
+) -> Response {
+    let session = match params.session {
+        Some(id) => match state.sessions.load_session(&id).await {
+            Ok(Some(session)) => session,
+            Ok(None) => {
+                return GrokError::NotFound {
+                    entity: Entity::Session,
+                    id,
+                }
+                .into_response()
+            }
+            Err(error) => return error.into_response(),
+        },
+        None => {
+            let model = params.model.unwrap_or(Model::Grok4);
+            state.sessions.create_session(model, None).await
+        }
+    };
+
+    ws.on_upgrade(move |socket| converse(socket, session))
+}
+
+async fn converse(mut socket: WebSocket, session: Arc<Session>) {
+    let id = session.id.clone();
+    if send(&mut socket, &SessionEvent::Session { id })
+        .await
+        .is_err()
+    {
+        return;
+    }
+
+    while let Some(Ok(frame)) = socket.recv().await {
+        let content = match frame {
+            Frame::Text(text) => text.as_str().to_string(),
+            Frame::Close(_) => break,
+            _ => continue,
+        };
+        let event = match turn(&mut socket, &session, content).await {
+            Ok(Some(message)) => SessionEvent::Done { message },
+            // The socket closed mid-reply
+            Ok(None) => break,
+            Err(e) => SessionEvent::Error {
+                message: e.to_string(),
+            },
+        };
+        if send(&mut socket, &event).await.is_err() {
+            break;
+        }
+    }
+}
+
+/// Run one turn, sending deltas as they arrive; `None` if the socket closed
+async fn turn(
+    socket: &mut WebSocket,
+    session: &Session,
+    content: String,
+) -> std::result::Result<Option<Message>, Arc<GrokError>> {
+    let events = session.chat_events(content).await.map_err(Arc::new)?;
+    let mut events = std::pin::pin!(events);
+    while let Some(event) = events.next().await {
+        match event {
+            StreamEvent::Delta(content) => {
+                if send(socket, &SessionEvent::Delta { content })
+                    .await
+                    .is_err()
+                {
+                    return Ok(None);
+                }
+            }
+            StreamEvent::ToolCallDelta(_) => {}
+            StreamEvent::Done(completion) => return Ok(Some(completion.message.clone())),
+            StreamEvent::Error(error) => return Err(error),
+        }
+    }
+    Ok(None)
+}
+
+async fn send(
+    socket: &mut WebSocket,
+    event: &SessionEvent,
+) -> std::result::Result<(), axum::Error> {
+    let json = serde_json::to_string(event).map_err(axum::Error::new)?;
+    socket.send(Frame::Text(json.into())).await
+}
+
+/// Errors become JSON bodies with a status for the caller, not the upstream API
+///
+/// Upstream authentication and server failures are the backend's problem, so
+/// they surface as `502 Bad Gateway` rather than being passed through.
+impl IntoResponse for GrokError {
+    fn into_response(self) -> Response {
+        let kind = self.kind();
+        let status = match kind {
+            ErrorKind::InvalidRequest | ErrorKind::ContextLength | ErrorKind::Tool => {
+                StatusCode::BAD_REQUEST
+            }
+            ErrorKind::NotFound => StatusCode::NOT_FOUND,
+            ErrorKind::ContentBlocked => StatusCode::UNPROCESSABLE_ENTITY,
+            ErrorKind::RateLimit | ErrorKind::Budget => StatusCode::TOO_MANY_REQUESTS,
+            ErrorKind::Timeout => StatusCode::GATEWAY_TIMEOUT,
+            ErrorKind::Shutdown => StatusCode::SERVICE_UNAVAILABLE,
+            ErrorKind::Network
+            | ErrorKind::Server
+            | ErrorKind::Authentication
+            | ErrorKind::Serialization => StatusCode::BAD_GATEWAY,
+            ErrorKind::Config
+            | ErrorKind::Io
+            | ErrorKind::Session
+            | ErrorKind::Collection
+            | ErrorKind::Retrieval => StatusCode::INTERNAL_SERVER_ERROR,
+        };
+        let body = serde_json::json!({
+            "error": {
+                "message": self.to_string(),
+                "kind": format!("{:?}", kind),
+            }
+        });
+        (status, Json(body)).into_response()
+    }
+}
@@ -0,0 +1,95 @@
+//! `tower::Service` adapter for chat completions
+//!
+//! `ChatService` lets the client sit behind tower middleware (timeouts,
+//! concurrency limits, load shedding) or be called from any framework that
+//! speaks `Service`.
+
+use crate::chat::{ChatCompletion, Message, Model, Tool};
+use crate::client::ChatOptions;
+use crate::error::{GrokError, Result};
+use crate::Client;
+use futures::future::BoxFuture;
+use serde::Deserialize;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use tower_service::Service;
+
+/// A chat completion request for `ChatService`
+///
+/// Deserializes from an OpenAI-style request body: `model`, `messages`,
+/// optional `tools` and the `ChatOptions` fields at the top level.
+#[derive(Debug, Clone, Deserialize)]
+pub struct ChatRequest {
+    /// Model to use
+    pub model: Model,
+    /// Messages in the conversation
+    pub messages: Vec<Message>,
+    /// Tools available for function calling
+    #[serde(default)]
+    pub tools: Option<Vec<Tool>>,
+    /// Sampling and formatting options
+    #[serde(flatten)]
+    pub options: ChatOptions,
+}
+
+impl ChatRequest {
+    /// Create a request with default options
+    pub fn new(model: Model, messages: Vec<Message>) -> Self {
+        Self {
+            model,
+            messages,
+            tools: None,
+            options: ChatOptions::default(),
+        }
+    }
+}
+
+/// The client as a `Service<ChatRequest>` answering with completions
+#[derive(Debug, Clone)]
+pub struct ChatService {
+    client: Arc<Client>,
+}
+
+impl ChatService {
+    /// Wrap a shared client
+    pub fn new(client: Arc<Client>) -> Self {
+        Self { client }
+    }
+
+    /// The wrapped client
+    pub fn client(&self) -> &Arc<Client> {
+        &self.client
+    }
+}
+
+impl From<Arc<Client>> for ChatService {
+    fn from(client: Arc<Client>) -> Self {
+        Self::new(client)
+    }
+}
+
+impl Service<ChatRequest> for ChatService {
+    type Response = ChatCompletion;
+    type Error = GrokError;
+    type Future = BoxFuture<'static, Result<ChatCompletion>>;
+
+    /// Always ready; the client queues and retries requests itself
+    fn poll_ready(&mut self, _cx: &mut Context<'_>) -> Poll<Result<()>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn call(&mut self, request: ChatRequest) -> Self::Future {
+        let client = self.client.clone();
+        Box::pin(async move {
+            let messages = request.messages.into_iter().map(Arc::new).collect();
+            client
+                .chat_shared(
+                    request.model,
+                    messages,
+                    request.tools,
+                    Some(request.options),
+                )
+                .await
+        })
+    }
+}
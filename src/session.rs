@@ -1,28 +1,96 @@
 //! Stateful conversation sessions
 
-use crate::chat::{Message, Model, Tool};
-use crate::error::{GrokError, Result};
-use crate::Client;
+use crate::budget::{self, Budget};
+use crate::channel::{self, StreamEvent};
+use crate::chat::{self, ChatChunk, Message, Model, Role, StreamAccumulator, Tool};
+use crate::error::{Entity, GrokError, Result};
+use crate::guardrail::{self, GuardStage, Guardrail};
+#[cfg(feature = "persistence")]
+use crate::persistence::Prompt;
+use crate::provider::{ChatProvider, ChunkStream, Detached};
+use crate::replay::{self, ReplayOptions, ReplayReport};
+use crate::storage::{MemoryStorage, Storage};
+use crate::tools::{ToolContext, ToolRegistry};
+use async_trait::async_trait;
+use futures::stream::BoxStream;
+use futures::{Stream, StreamExt};
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
-use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
 use tokio::sync::RwLock;
 
+/// Source of history versions, unique across every session in the process
+static NEXT_HISTORY_VERSION: AtomicU64 = AtomicU64::new(0);
+
+fn next_history_version() -> u64 {
+    NEXT_HISTORY_VERSION.fetch_add(1, Ordering::Relaxed)
+}
+
 /// A stateful conversation session
 #[derive(Debug)]
 pub struct Session {
     /// Unique session ID
     pub id: String,
-    /// The client used for API calls
-    client: Arc<Client>,
+    /// The provider used for completions
+    provider: Arc<dyn ChatProvider>,
     /// Model to use for this session
     model: Model,
-    /// Conversation history
-    messages: RwLock<Vec<Message>>,
+    /// Models to try, in order, when the session's model fails a turn
+    fallback_models: Vec<Model>,
+    /// Conversation history, shared with outgoing requests
+    messages: RwLock<Vec<Arc<Message>>>,
+    /// Indices of replies in the history that were cut off mid-stream
+    interrupted: RwLock<Vec<usize>>,
+    /// Changes whenever the history is rewritten rather than appended to
+    history_version: AtomicU64,
     /// Available tools
     tools: Vec<Tool>,
     /// Session metadata
-    metadata: SessionMetadata,
+    metadata: Mutex<SessionMetadata>,
+    /// Checks on this session's turns, on top of the provider's own
+    guardrails: Vec<Arc<dyn Guardrail>>,
+    /// Spend limit on this session's turns, on top of the provider's own
+    budget: Option<Arc<Budget>>,
+    /// `name@version` of the stored prompt the conversation started from
+    prompt: Option<String>,
+    /// Tenant the session belongs to, if it's scoped to one
+    tenant: Option<String>,
+    /// Write-through persistence, if attached to storage
+    auto_save: Option<AutoSave>,
+}
+
+/// Settings for write-through persistence of a session
+#[derive(Debug, Clone)]
+pub struct AutoSaveOptions {
+    /// Minimum time between saves triggered by appended messages
+    pub debounce: Duration,
+    /// Save once this many appended messages are pending, regardless of `debounce`
+    pub max_pending: usize,
+}
+
+impl Default for AutoSaveOptions {
+    fn default() -> Self {
+        Self {
+            debounce: Duration::from_millis(500),
+            max_pending: 8,
+        }
+    }
+}
+
+/// Storage attachment and bookkeeping for auto-save
+#[derive(Debug)]
+struct AutoSave {
+    storage: Arc<dyn Storage>,
+    options: AutoSaveOptions,
+    state: Mutex<AutoSaveState>,
+}
+
+#[derive(Debug, Default)]
+struct AutoSaveState {
+    pending: usize,
+    last_saved: Option<Instant>,
 }
 
 /// Session metadata
@@ -42,26 +110,123 @@ pub struct SessionMetadata {
 
 impl Session {
     /// Create a new session
-    pub fn new(client: Arc<Client>, model: Model, title: Option<String>) -> Self {
+    pub fn new(provider: Arc<dyn ChatProvider>, model: Model, title: Option<String>) -> Self {
         let id = uuid::Uuid::new_v4().to_string();
         let now = chrono::Utc::now();
 
         Self {
             id,
-            client,
+            provider,
             model,
+            fallback_models: Vec::new(),
             messages: RwLock::new(Vec::new()),
+            interrupted: RwLock::new(Vec::new()),
+            history_version: AtomicU64::new(next_history_version()),
             tools: Vec::new(),
-            metadata: SessionMetadata {
+            metadata: Mutex::new(SessionMetadata {
                 title,
                 created_at: now,
                 updated_at: now,
                 total_tokens: 0,
                 message_count: 0,
-            },
+            }),
+            guardrails: Vec::new(),
+            budget: None,
+            prompt: None,
+            tenant: None,
+            auto_save: None,
         }
     }
 
+    /// Rebuild a stored session from its history
+    ///
+    /// The session can't chat until it's given a provider with
+    /// `set_provider`; `SessionManager::load_session` does that for you.
+    pub fn restore(
+        id: String,
+        model: Model,
+        title: Option<String>,
+        created_at: chrono::DateTime<chrono::Utc>,
+        updated_at: chrono::DateTime<chrono::Utc>,
+        messages: Vec<Message>,
+    ) -> Self {
+        let mut session = Self::new(Arc::new(Detached), model, None);
+        session.id = id;
+        session.metadata = Mutex::new(SessionMetadata {
+            title,
+            created_at,
+            updated_at,
+            total_tokens: 0,
+            message_count: messages.len(),
+        });
+        session.messages = RwLock::new(messages.into_iter().map(Arc::new).collect());
+        session
+    }
+
+    /// Get the session ID
+    pub fn id(&self) -> &str {
+        &self.id
+    }
+
+    /// Get when the session was created
+    pub fn created_at(&self) -> chrono::DateTime<chrono::Utc> {
+        self.metadata.lock().unwrap().created_at
+    }
+
+    /// Persist this session to storage as it changes
+    ///
+    /// Appended messages and cleared histories are saved in debounced batches,
+    /// and every completed turn (`chat`, `execute_tools`) is flushed, so a
+    /// crash loses at most the turn in flight.
+    pub fn enable_auto_save(&mut self, storage: Arc<dyn Storage>, options: AutoSaveOptions) {
+        self.auto_save = Some(AutoSave {
+            storage,
+            options,
+            state: Mutex::new(AutoSaveState::default()),
+        });
+    }
+
+    /// Save any changes not yet written by auto-save
+    pub async fn flush(&self) -> Result<()> {
+        let Some(auto_save) = &self.auto_save else {
+            return Ok(());
+        };
+
+        let saving = auto_save.state.lock().unwrap().pending;
+        if saving == 0 {
+            return Ok(());
+        }
+
+        auto_save.storage.save_session(self).await?;
+
+        // Changes made while saving may have missed the snapshot, so they stay pending
+        let mut state = auto_save.state.lock().unwrap();
+        state.pending = state.pending.saturating_sub(saving);
+        state.last_saved = Some(Instant::now());
+        Ok(())
+    }
+
+    /// Record a change to the history and save if the debounce window allows
+    async fn auto_save_if_due(&self) -> Result<()> {
+        let Some(auto_save) = &self.auto_save else {
+            return Ok(());
+        };
+
+        let due = {
+            let mut state = auto_save.state.lock().unwrap();
+            state.pending += 1;
+            state.pending >= auto_save.options.max_pending
+                || state
+                    .last_saved
+                    .is_none_or(|saved| saved.elapsed() >= auto_save.options.debounce)
+        };
+
+        if due {
+            self.flush().await?;
+        }
+        Ok(())
+    }
+
     /// Add a tool to the session
     pub fn add_tool(&mut self, tool: Tool) {
         self.tools.push(tool);
@@ -72,58 +237,432 @@ impl Session {
         self.tools.extend(tools);
     }
 
+    /// Check each user message and reply of this session with a guardrail
+    ///
+    /// A blocked user message is not added to the history; a redacted one is
+    /// stored redacted.
+    pub fn add_guardrail(&mut self, guardrail: impl Guardrail + 'static) {
+        self.guardrails.push(Arc::new(guardrail));
+    }
+
+    /// Hold back this session's turns once a token or cost budget is spent
+    ///
+    /// A turn over the budget fails, or waits, before its user message is added.
+    pub fn set_budget(&mut self, budget: Arc<Budget>) {
+        self.budget = Some(budget);
+    }
+
+    /// Try these models, in order, when a turn fails in a way another model
+    /// may not, such as a timeout or a model at capacity
+    ///
+    /// The reply's `model` names the model that answered. Streamed turns are
+    /// not rerouted.
+    pub fn set_fallback_models(&mut self, models: Vec<Model>) {
+        self.fallback_models = models;
+    }
+
+    /// Start the conversation from a stored prompt, rendered as a system message
+    ///
+    /// The prompt's `name@version` is saved with the session, recording which
+    /// version produced the conversation.
+    #[cfg(feature = "persistence")]
+    pub async fn apply_prompt(
+        &mut self,
+        prompt: &Prompt,
+        variables: &[(&str, &str)],
+    ) -> Result<()> {
+        let content = prompt.render(variables)?;
+        self.prompt = Some(prompt.reference());
+        self.append(Message {
+            role: Role::System,
+            content: content.into(),
+            tool_calls: None,
+            tool_call_id: None,
+            name: None,
+        })
+        .await
+    }
+
+    /// The `name@version` of the stored prompt the conversation started from
+    pub fn prompt(&self) -> Option<&str> {
+        self.prompt.as_deref()
+    }
+
+    /// Scope the session to a tenant
+    ///
+    /// Tenant-scoped lookups on managers and storage only find the session
+    /// for this tenant, and it can only join the tenant's collections.
+    pub fn set_tenant(&mut self, tenant: impl Into<String>) {
+        self.tenant = Some(tenant.into());
+    }
+
+    /// The tenant the session belongs to, if it's scoped to one
+    pub fn tenant(&self) -> Option<&str> {
+        self.tenant.as_deref()
+    }
+
+    pub(crate) fn set_prompt_reference(&mut self, prompt: Option<String>) {
+        self.prompt = prompt;
+    }
+
+    /// Chat through another provider, such as after restoring the session from storage
+    pub fn set_provider(&mut self, provider: Arc<dyn ChatProvider>) {
+        self.provider = provider;
+    }
+
+    pub(crate) fn set_interrupted(&mut self, interrupted: Vec<usize>) {
+        self.interrupted = RwLock::new(interrupted);
+    }
+
+    /// Indices in the history of replies that were cut off mid-stream
+    ///
+    /// Interrupted replies are kept in the history, but not sent back to the
+    /// model with later turns.
+    pub async fn interrupted(&self) -> Vec<usize> {
+        self.interrupted.read().await.clone()
+    }
+
+    /// Identifies the history since it was last rewritten
+    ///
+    /// Appends keep the version and `clear_history` changes it, so storage
+    /// that saw the same version only has new messages to write.
+    #[cfg(any(feature = "persistence", feature = "postgres"))]
+    pub(crate) fn history_version(&self) -> u64 {
+        self.history_version.load(Ordering::SeqCst)
+    }
+
+    /// Get the model this session chats with
+    pub fn model(&self) -> Model {
+        self.model
+    }
+
+    /// Get the tools available to this session
+    pub fn tools(&self) -> &[Tool] {
+        &self.tools
+    }
+
     /// Append a message to the conversation
     pub async fn append(&self, message: Message) -> Result<()> {
         let mut messages = self.messages.write().await;
-        messages.push(message);
+        messages.push(Arc::new(message));
         drop(messages);
 
-        let mut metadata = &mut self.metadata;
-        metadata.message_count += 1;
-        metadata.updated_at = chrono::Utc::now();
+        {
+            let mut metadata = self.metadata.lock().unwrap();
+            metadata.message_count += 1;
+            metadata.updated_at = chrono::Utc::now();
+        }
 
+        self.auto_save_if_due().await?;
         Ok(())
     }
 
     /// Send a user message and get assistant response
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(name = "grok.session.turn", skip_all, fields(session_id = %self.id, model = %self.model))
+    )]
     pub async fn chat(&self, content: impl Into<String>) -> Result<crate::chat::ChatCompletion> {
-        let user_message = Message {
-            role: crate::chat::Role::User,
+        let mut annotations = Vec::new();
+        let (messages, tools) = self.begin_turn(content.into(), &mut annotations).await?;
+        let prompt_tokens = self.estimate_prompt_tokens(&messages, tools.as_deref());
+
+        let started = Instant::now();
+        let (model, mut response) = self.request(messages, tools).await?;
+        self.record_spend(model, &response, prompt_tokens);
+        annotations.append(&mut response.annotations);
+        if let Some(redacted) = guardrail::apply(
+            &self.guardrails,
+            &response.message,
+            GuardStage::Response,
+            &mut annotations,
+        )
+        .await?
+        {
+            response.message = redacted;
+        }
+        response.annotations = annotations;
+
+        if let Some(auto_save) = &self.auto_save {
+            auto_save
+                .storage
+                .record_usage(Some(&self.id), &response, started.elapsed())
+                .await?;
+        }
+
+        // Add assistant response to history
+        self.append(response.message.clone()).await?;
+        self.flush().await?;
+
+        Ok(response)
+    }
+
+    /// Send a user message and stream the reply as events
+    ///
+    /// Only the user message is checked by the session's guardrails, since
+    /// the reply reaches the caller as it arrives. The reply is added to the
+    /// history when the stream ends, or marked as interrupted if the stream
+    /// fails part way; a turn that's dropped before then leaves only the user
+    /// message. With auto-save, the reply received so far is also stored as
+    /// it arrives, so a session loaded after a crash mid-stream shows it as
+    /// interrupted.
+    pub async fn chat_events(
+        &self,
+        content: impl Into<String>,
+    ) -> Result<impl Stream<Item = StreamEvent> + '_> {
+        let (messages, tools) = self.begin_turn(content.into(), &mut Vec::new()).await?;
+        let prompt_tokens = self.estimate_prompt_tokens(&messages, tools.as_deref());
+        let messages = messages
+            .iter()
+            .map(|message| message.as_ref().clone())
+            .collect();
+        let chunks = self
+            .provider
+            .chat_stream(self.model, messages, tools)
+            .await?;
+        let chunks = self.save_partial_replies(chunks).await?;
+
+        Ok(channel::events(chunks).then(move |event| async move {
+            let reply = match &event {
+                StreamEvent::Done(completion) => Some((completion.as_ref(), false)),
+                StreamEvent::Error(error) => match error.as_ref() {
+                    GrokError::StreamInterrupted { partial, .. } => Some((partial.as_ref(), true)),
+                    _ => None,
+                },
+                _ => None,
+            };
+            if let Some((completion, interrupted)) = reply {
+                self.record_spend(self.model, completion, prompt_tokens);
+                let message = completion.message.clone();
+                let saved = async {
+                    if interrupted {
+                        self.append_interrupted(message).await?;
+                    } else {
+                        self.append(message).await?;
+                    }
+                    self.flush().await
+                };
+                if let Err(error) = saved.await {
+                    return StreamEvent::Error(Arc::new(error));
+                }
+            }
+            event
+        }))
+    }
+
+    /// Store the reply streamed so far as chunks arrive, at most once per
+    /// auto-save debounce and again when the stream finishes
+    async fn save_partial_replies(
+        &self,
+        chunks: ChunkStream,
+    ) -> Result<BoxStream<'_, Result<ChatChunk>>> {
+        let Some(auto_save) = &self.auto_save else {
+            return Ok(chunks);
+        };
+        // The reply is stored after the user message, so that must be stored first
+        self.flush().await?;
+        let index = self.messages.read().await.len();
+        let draft = Arc::new(Mutex::new((StreamAccumulator::new(), Instant::now())));
+
+        Ok(chunks
+            .then(move |chunk| {
+                let draft = draft.clone();
+                async move {
+                    let chunk = chunk?;
+                    let partial = {
+                        let mut draft = draft.lock().unwrap();
+                        let (accumulator, last_saved) = &mut *draft;
+                        accumulator.push(&chunk);
+                        let finished = chunk
+                            .choices
+                            .first()
+                            .is_some_and(|choice| choice.finish_reason.is_some());
+                        let due = finished || last_saved.elapsed() >= auto_save.options.debounce;
+                        due.then(|| {
+                            *last_saved = Instant::now();
+                            Message {
+                                role: Role::Assistant,
+                                content: accumulator.content().into(),
+                                tool_calls: Some(accumulator.tool_calls().to_vec())
+                                    .filter(|calls| !calls.is_empty()),
+                                tool_call_id: None,
+                                name: None,
+                            }
+                        })
+                    };
+                    if let Some(message) = partial {
+                        auto_save
+                            .storage
+                            .save_partial_reply(&self.id, index, &message)
+                            .await?;
+                    }
+                    Ok(chunk)
+                }
+            })
+            .boxed())
+    }
+
+    /// Append a reply that was cut off mid-stream
+    async fn append_interrupted(&self, message: Message) -> Result<()> {
+        let index = self.messages.read().await.len();
+        self.interrupted.write().await.push(index);
+        self.append(message).await
+    }
+
+    /// The history to send with a request, leaving out interrupted replies
+    async fn request_history(&self) -> Vec<Arc<Message>> {
+        let interrupted = self.interrupted.read().await;
+        self.messages
+            .read()
+            .await
+            .iter()
+            .enumerate()
+            .filter(|(index, _)| !interrupted.contains(index))
+            .map(|(_, message)| message.clone())
+            .collect()
+    }
+
+    /// Send a turn to the session's model, then to each fallback model while
+    /// the failure is one another model may not have, returning the model that answered
+    async fn request(
+        &self,
+        messages: Vec<Arc<Message>>,
+        tools: Option<Vec<Tool>>,
+    ) -> Result<(Model, crate::chat::ChatCompletion)> {
+        let mut error = match self
+            .provider
+            .chat(self.model, messages, tools.clone(), None)
+            .await
+        {
+            Ok(response) => return Ok((self.model, response)),
+            Err(error) => error,
+        };
+
+        for &fallback in self
+            .fallback_models
+            .iter()
+            .filter(|&&fallback| fallback != self.model)
+        {
+            if !error.should_fall_back() {
+                break;
+            }
+            // Fit the history to the fallback's own context window
+            let mut messages = self.request_history().await;
+            if fit_context_window(fallback, &mut messages, tools.as_deref()).is_err() {
+                continue;
+            }
+
+            #[cfg(feature = "tracing")]
+            tracing::warn!(%error, to = %fallback, "falling back to another model");
+
+            match self
+                .provider
+                .chat(fallback, messages, tools.clone(), None)
+                .await
+            {
+                Ok(response) => return Ok((fallback, response)),
+                Err(failure) => error = failure,
+            }
+        }
+        Err(error)
+    }
+
+    /// Prompt size to charge the budget with if the provider doesn't report usage
+    fn estimate_prompt_tokens(&self, messages: &[Arc<Message>], tools: Option<&[Tool]>) -> u32 {
+        match self.budget {
+            Some(_) => chat::estimate_prompt_tokens(messages, tools),
+            None => 0,
+        }
+    }
+
+    /// Count a completed turn by `model` against the session's budget
+    fn record_spend(
+        &self,
+        model: Model,
+        completion: &crate::chat::ChatCompletion,
+        prompt_tokens: u32,
+    ) {
+        let Some(budget) = &self.budget else {
+            return;
+        };
+        let (prompt_tokens, completion_tokens) = match &completion.usage {
+            Some(usage) => (usage.prompt_tokens, usage.completion_tokens),
+            None => (
+                prompt_tokens,
+                budget::estimate_reply_tokens(&completion.message.content.text()),
+            ),
+        };
+        budget.record(model, prompt_tokens, completion_tokens);
+    }
+
+    /// Check and append a user message, returning the request to send for it
+    ///
+    /// Waits for, or fails on, the session's budget before anything is added.
+    async fn begin_turn(
+        &self,
+        content: String,
+        annotations: &mut Vec<String>,
+    ) -> Result<(Vec<Arc<Message>>, Option<Vec<Tool>>)> {
+        if let Some(budget) = &self.budget {
+            budget.acquire().await?;
+        }
+        let mut user_message = Message {
+            role: Role::User,
             content: content.into(),
             tool_calls: None,
             tool_call_id: None,
             name: None,
         };
-
+        if let Some(redacted) = guardrail::apply(
+            &self.guardrails,
+            &user_message,
+            GuardStage::Request,
+            annotations,
+        )
+        .await?
+        {
+            user_message = redacted;
+        }
         self.append(user_message).await?;
 
-        let messages = self.messages.read().await.clone();
+        let mut messages = self.request_history().await;
         let tools = if self.tools.is_empty() {
             None
         } else {
             Some(self.tools.clone())
         };
-
-        let response = self.client.chat(self.model, messages, tools).await?;
-
-        // Add assistant response to history
-        self.append(response.message.clone()).await?;
-
-        Ok(response)
+        fit_context_window(self.model, &mut messages, tools.as_deref())?;
+        Ok((messages, tools))
     }
 
     /// Execute tool calls and continue the conversation
     pub async fn execute_tools(
         &self,
         tool_calls: &[crate::chat::ToolCall],
-        tool_registry: &crate::tools::ToolRegistry,
+        tool_registry: &ToolRegistry,
+    ) -> Result<()> {
+        self.execute_tools_with_context(tool_calls, tool_registry, ToolContext::new())
+            .await
+    }
+
+    /// Execute tool calls, passing `context` to each tool
+    ///
+    /// The context's session ID is set to this session's.
+    pub async fn execute_tools_with_context(
+        &self,
+        tool_calls: &[crate::chat::ToolCall],
+        tool_registry: &ToolRegistry,
+        context: ToolContext,
     ) -> Result<()> {
+        let context = context.with_session_id(self.id.clone());
         for tool_call in tool_calls {
-            let result = tool_registry.execute_tool_call(tool_call).await?;
+            let result = tool_registry
+                .execute_tool_call_with_context(&tool_call.into(), &context)
+                .await?;
 
             let tool_message = Message {
                 role: crate::chat::Role::Tool,
-                content: result.content,
+                content: result.content.into(),
                 tool_calls: None,
                 tool_call_id: Some(result.tool_call_id),
                 name: Some(tool_call.function.name.clone()),
@@ -132,34 +671,50 @@ impl Session {
             self.append(tool_message).await?;
         }
 
-        Ok(())
+        self.flush().await
     }
 
-    /// Get the conversation history
+    /// Get a copy of the conversation history
     pub async fn messages(&self) -> Vec<Message> {
+        self.messages
+            .read()
+            .await
+            .iter()
+            .map(|message| message.as_ref().clone())
+            .collect()
+    }
+
+    /// Get the conversation history without copying the messages
+    pub async fn history(&self) -> Vec<Arc<Message>> {
         self.messages.read().await.clone()
     }
 
-    /// Get session metadata
-    pub fn metadata(&self) -> &SessionMetadata {
-        &self.metadata
+    /// Get a copy of the session metadata
+    pub fn metadata(&self) -> SessionMetadata {
+        self.metadata.lock().unwrap().clone()
     }
 
     /// Clear the conversation history (keep system messages)
     pub async fn clear_history(&self) -> Result<()> {
         let mut messages = self.messages.write().await;
-        let system_messages: Vec<Message> = messages
+        let system_messages: Vec<Arc<Message>> = messages
             .drain(..)
             .filter(|msg| matches!(msg.role, crate::chat::Role::System))
             .collect();
         *messages = system_messages;
+        let message_count = messages.len();
+        self.history_version
+            .store(next_history_version(), Ordering::SeqCst);
         drop(messages);
+        self.interrupted.write().await.clear();
 
-        let mut metadata = &mut self.metadata;
-        metadata.message_count = messages.len();
-        metadata.updated_at = chrono::Utc::now();
+        {
+            let mut metadata = self.metadata.lock().unwrap();
+            metadata.message_count = message_count;
+            metadata.updated_at = chrono::Utc::now();
+        }
 
-        Ok(())
+        self.auto_save_if_due().await
     }
 
     /// Get the number of messages in the session
@@ -168,25 +723,148 @@ impl Session {
     }
 }
 
+/// Leave the oldest turns out of a request until it fits in the model's
+/// context window, keeping system messages and the newest message
+///
+/// Tool results are left out along with the assistant message that called
+/// them. The session's history itself is untouched.
+fn fit_context_window(
+    model: Model,
+    messages: &mut Vec<Arc<Message>>,
+    tools: Option<&[Tool]>,
+) -> Result<()> {
+    let mut overflow = match chat::check_context_window(model, messages, tools, None) {
+        Err(GrokError::ContextLengthExceeded { overflow, .. }) => overflow,
+        result => return result.map(|_| ()),
+    };
+
+    let newest = messages.len() - 1;
+    let mut dropped = vec![false; messages.len()];
+    for (i, message) in messages[..newest].iter().enumerate() {
+        if matches!(message.role, Role::System) {
+            continue;
+        }
+        let orphaned = matches!(message.role, Role::Tool) && i > 0 && dropped[i - 1];
+        if overflow == 0 && !orphaned {
+            break;
+        }
+        let tokens = chat::estimate_prompt_tokens(std::slice::from_ref(message), None);
+        overflow = overflow.saturating_sub(tokens);
+        dropped[i] = true;
+    }
+
+    #[cfg(feature = "tracing")]
+    tracing::debug!(
+        dropped = dropped.iter().filter(|&&d| d).count(),
+        "left old messages out to fit the context window"
+    );
+    let mut index = 0;
+    messages.retain(|_| {
+        index += 1;
+        !dropped[index - 1]
+    });
+    // System messages and the newest message alone may still be too long
+    chat::check_context_window(model, messages, tools, None).map(|_| ())
+}
+
 /// Session manager for handling multiple conversations
+///
+/// With storage, managed sessions are saved as they change and sessions not
+/// in memory are loaded from storage on demand. `S` defaults to
+/// `MemoryStorage`, which managers created with `new` don't use.
 #[derive(Debug)]
-pub struct SessionManager {
-    client: Arc<Client>,
+pub struct SessionManager<S: Storage = MemoryStorage> {
+    provider: Arc<dyn ChatProvider>,
     sessions: RwLock<HashMap<String, Arc<Session>>>,
+    tool_registry: ToolRegistry,
+    storage: Option<(Arc<S>, AutoSaveOptions)>,
 }
 
 impl SessionManager {
-    /// Create a new session manager
-    pub fn new(client: Arc<Client>) -> Self {
+    /// Create a session manager that keeps its sessions in memory only
+    pub fn new(provider: Arc<dyn ChatProvider>) -> Self {
         Self {
-            client,
+            provider,
             sessions: RwLock::new(HashMap::new()),
+            tool_registry: ToolRegistry::new(),
+            storage: None,
         }
     }
+}
+
+impl<S: Storage + 'static> SessionManager<S> {
+    /// Create a session manager that persists its sessions to storage
+    pub fn with_storage(provider: Arc<dyn ChatProvider>, storage: Arc<S>) -> Self {
+        Self::with_auto_save(provider, storage, AutoSaveOptions::default())
+    }
+
+    /// Create a session manager that persists its sessions to storage,
+    /// with custom auto-save settings
+    pub fn with_auto_save(
+        provider: Arc<dyn ChatProvider>,
+        storage: Arc<S>,
+        options: AutoSaveOptions,
+    ) -> Self {
+        Self {
+            provider,
+            sessions: RwLock::new(HashMap::new()),
+            tool_registry: ToolRegistry::new(),
+            storage: Some((storage, options)),
+        }
+    }
+
+    /// Get the storage the manager persists sessions to
+    pub fn storage(&self) -> Option<&Arc<S>> {
+        self.storage.as_ref().map(|(storage, _)| storage)
+    }
+
+    /// Share a tool registry with the sessions this manager creates
+    pub fn set_tool_registry(&mut self, registry: ToolRegistry) {
+        self.tool_registry = registry;
+    }
+
+    /// Get the manager's tool registry
+    ///
+    /// The registry is shared, so tools registered through it at runtime are
+    /// offered to sessions created afterwards.
+    pub fn tool_registry(&self) -> &ToolRegistry {
+        &self.tool_registry
+    }
 
     /// Create a new session
     pub async fn create_session(&self, model: Model, title: Option<String>) -> Arc<Session> {
-        let session = Arc::new(Session::new(self.client.clone(), model, title));
+        self.insert_session(None, model, title).await
+    }
+
+    /// Create a new session belonging to `tenant`
+    pub async fn create_session_for(
+        &self,
+        tenant: &str,
+        model: Model,
+        title: Option<String>,
+    ) -> Arc<Session> {
+        self.insert_session(Some(tenant), model, title).await
+    }
+
+    async fn insert_session(
+        &self,
+        tenant: Option<&str>,
+        model: Model,
+        title: Option<String>,
+    ) -> Arc<Session> {
+        let mut session = Session::new(self.provider.clone(), model, title);
+        if let Some(tenant) = tenant {
+            session.set_tenant(tenant);
+        }
+        session.add_tools(
+            self.tool_registry
+                .api_tools()
+                .into_iter()
+                .map(Into::into)
+                .collect(),
+        );
+        self.attach_storage(&mut session);
+        let session = Arc::new(session);
         let session_id = session.id.clone();
 
         let mut sessions = self.sessions.write().await;
@@ -195,24 +873,180 @@ impl SessionManager {
         session
     }
 
-    /// Get a session by ID
+    /// Save a session to the manager's storage as it changes
+    fn attach_storage(&self, session: &mut Session) {
+        if let Some((storage, options)) = &self.storage {
+            session.enable_auto_save(storage.clone(), options.clone());
+        }
+    }
+
+    /// Get a session in memory by ID
+    ///
+    /// Use `load_session` to also look in storage.
     pub async fn get_session(&self, session_id: &str) -> Option<Arc<Session>> {
         let sessions = self.sessions.read().await;
         sessions.get(session_id).cloned()
     }
 
+    /// Get a session by ID, loading it from storage if it isn't in memory
+    ///
+    /// A loaded session chats through the manager's provider, is saved as it
+    /// changes and stays in memory from then on.
+    pub async fn load_session(&self, session_id: &str) -> Result<Option<Arc<Session>>> {
+        self.load_session_scoped(session_id, None).await
+    }
+
     /// List all sessions
     pub async fn list_sessions(&self) -> Vec<Arc<Session>> {
         let sessions = self.sessions.read().await;
         sessions.values().cloned().collect()
     }
 
-    /// Delete a session
-    pub async fn delete_session(&self, session_id: &str) -> Result<()> {
+    /// Get a session in memory by ID, if it belongs to `tenant`
+    ///
+    /// Use `load_session_for` to also look in storage.
+    pub async fn get_session_for(&self, tenant: &str, session_id: &str) -> Option<Arc<Session>> {
+        self.get_session(session_id)
+            .await
+            .filter(|session| session.tenant() == Some(tenant))
+    }
+
+    /// Get a session by ID, if it belongs to `tenant`, loading it from
+    /// storage if it isn't in memory
+    ///
+    /// Another tenant's session is treated as missing and isn't loaded.
+    pub async fn load_session_for(
+        &self,
+        tenant: &str,
+        session_id: &str,
+    ) -> Result<Option<Arc<Session>>> {
+        self.load_session_scoped(session_id, Some(tenant)).await
+    }
+
+    async fn load_session_scoped(
+        &self,
+        session_id: &str,
+        tenant: Option<&str>,
+    ) -> Result<Option<Arc<Session>>> {
+        if let Some(session) = self.get_session(session_id).await {
+            return Ok(
+                Some(session).filter(|session| tenant.is_none() || session.tenant() == tenant)
+            );
+        }
+        let Some((storage, _)) = &self.storage else {
+            return Ok(None);
+        };
+        let loaded = match tenant {
+            Some(tenant) => storage.load_session_for(tenant, session_id).await?,
+            None => storage.load_session(session_id).await?,
+        };
+        let Some(mut session) = loaded else {
+            return Ok(None);
+        };
+        session.set_provider(self.provider.clone());
+        self.attach_storage(&mut session);
+
+        // Another task may have loaded it meanwhile; keep the first copy
         let mut sessions = self.sessions.write().await;
-        sessions
-            .remove(session_id)
-            .ok_or_else(|| GrokError::Session(format!("Session '{}' not found", session_id)))?;
+        let session = sessions
+            .entry(session_id.to_string())
+            .or_insert_with(|| Arc::new(session))
+            .clone();
+        Ok(Some(session))
+    }
+
+    /// List the sessions belonging to `tenant`, newest first, loading those
+    /// only in storage
+    pub async fn list_sessions_for(&self, tenant: &str) -> Result<Vec<Arc<Session>>> {
+        let mut listed: Vec<_> = self
+            .list_sessions()
+            .await
+            .into_iter()
+            .filter(|session| session.tenant() == Some(tenant))
+            .collect();
+        if let Some((storage, _)) = &self.storage {
+            for session_id in storage.list_sessions_for(tenant).await? {
+                if listed.iter().any(|session| session.id == session_id) {
+                    continue;
+                }
+                if let Some(session) = self.load_session_for(tenant, &session_id).await? {
+                    listed.push(session);
+                }
+            }
+        }
+        listed.sort_by_key(|session| std::cmp::Reverse(session.created_at()));
+        Ok(listed)
+    }
+
+    /// Delete a session belonging to `tenant`
+    ///
+    /// Another tenant's session is reported as not found.
+    pub async fn delete_session_for(&self, tenant: &str, session_id: &str) -> Result<()> {
+        match self.load_session_for(tenant, session_id).await? {
+            Some(_) => self.delete_session(session_id).await,
+            None => Err(GrokError::NotFound {
+                entity: Entity::Session,
+                id: session_id.to_string(),
+            }),
+        }
+    }
+
+    /// Delete a session, along with its stored copy
+    pub async fn delete_session(&self, session_id: &str) -> Result<()> {
+        let removed = self.sessions.write().await.remove(session_id);
+        match &self.storage {
+            Some((storage, _)) => storage.delete_session(session_id).await,
+            None => removed.map(|_| ()).ok_or_else(|| GrokError::NotFound {
+                entity: Entity::Session,
+                id: session_id.to_string(),
+            }),
+        }
+    }
+
+    /// Re-run a session's user turns through another model and compare the replies
+    ///
+    /// Each turn is sent with the original conversation before it as context,
+    /// so replies can be compared turn by turn. The session is loaded from
+    /// storage if it isn't in memory, and the storage supplies the original
+    /// usage when it was recorded. The session itself is left as is.
+    pub async fn replay(
+        &self,
+        session_id: &str,
+        model: Model,
+        options: ReplayOptions,
+    ) -> Result<ReplayReport> {
+        let session = self
+            .load_session(session_id)
+            .await?
+            .ok_or_else(|| GrokError::NotFound {
+                entity: Entity::Session,
+                id: session_id.to_string(),
+            })?;
+
+        let history = session.history().await;
+        let (turns, replay_usage) =
+            replay::replay(self.provider.as_ref(), &history, model, &options).await?;
+
+        let original_usage = match &self.storage {
+            Some((storage, _)) => storage.session_usage(session_id).await?,
+            None => None,
+        };
+
+        Ok(ReplayReport {
+            session_id: session_id.to_string(),
+            original_model: session.model(),
+            model,
+            turns,
+            original_usage,
+            replay_usage,
+        })
+    }
+
+    /// Save any changes to the managed sessions not yet written by auto-save
+    pub async fn flush_all(&self) -> Result<()> {
+        for session in self.list_sessions().await {
+            session.flush().await?;
+        }
         Ok(())
     }
 
@@ -220,8 +1054,8 @@ impl SessionManager {
     pub async fn stats(&self) -> SessionStats {
         let sessions = self.sessions.read().await;
         let total_sessions = sessions.len();
-        let total_messages = sessions.values().map(|s| s.metadata.message_count).sum();
-        let total_tokens = sessions.values().map(|s| s.metadata.total_tokens).sum();
+        let total_messages = sessions.values().map(|s| s.metadata().message_count).sum();
+        let total_tokens = sessions.values().map(|s| s.metadata().total_tokens).sum();
 
         SessionStats {
             total_sessions,
@@ -231,6 +1065,19 @@ impl SessionManager {
     }
 }
 
+/// A session manager a client flushes when it shuts down
+#[async_trait]
+pub(crate) trait FlushAll: Send + Sync + std::fmt::Debug {
+    async fn flush_all(&self) -> Result<()>;
+}
+
+#[async_trait]
+impl<S: Storage + 'static> FlushAll for SessionManager<S> {
+    async fn flush_all(&self) -> Result<()> {
+        SessionManager::flush_all(self).await
+    }
+}
+
 /// Session statistics
 #[derive(Debug, Clone)]
 pub struct SessionStats {
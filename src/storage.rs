@@ -0,0 +1,323 @@
+//! Storage backends for sessions and collections
+//!
+//! `SessionManager` and `CollectionManager` take a `Storage` to persist what
+//! they manage: sessions are saved as they change and loaded back on demand.
+
+use crate::chat::{ChatCompletion, Message, Model, Tool};
+use crate::collections::{Collection, CollectionMetadata};
+use crate::error::Result;
+use crate::replay::ReplayUsage;
+use crate::session::Session;
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use std::cmp::Reverse;
+use std::collections::HashMap;
+use std::time::Duration;
+use tokio::sync::RwLock;
+
+/// A backend that sessions and collections can be persisted to
+#[async_trait]
+pub trait Storage: Send + Sync + std::fmt::Debug {
+    /// Save a session, replacing any stored copy
+    async fn save_session(&self, session: &Session) -> Result<()>;
+
+    /// Load a session by ID
+    async fn load_session(&self, session_id: &str) -> Result<Option<Session>>;
+
+    /// Delete a session
+    async fn delete_session(&self, session_id: &str) -> Result<()>;
+
+    /// List stored session IDs, newest first
+    async fn list_sessions(&self) -> Result<Vec<String>>;
+
+    /// Load a session by ID, if it belongs to `tenant`
+    ///
+    /// Another tenant's session is treated as missing.
+    async fn load_session_for(&self, tenant: &str, session_id: &str) -> Result<Option<Session>>;
+
+    /// List the IDs of the sessions belonging to `tenant`, newest first
+    async fn list_sessions_for(&self, tenant: &str) -> Result<Vec<String>>;
+
+    /// Save a collection, replacing any stored copy
+    async fn save_collection(&self, collection: &Collection) -> Result<()>;
+
+    /// Load a collection by ID
+    async fn load_collection(&self, collection_id: &str) -> Result<Option<Collection>>;
+
+    /// Delete a collection
+    async fn delete_collection(&self, collection_id: &str) -> Result<()>;
+
+    /// List stored collection IDs, newest first
+    async fn list_collections(&self) -> Result<Vec<String>>;
+
+    /// Load a collection by ID, if it belongs to `tenant`
+    ///
+    /// Another tenant's collection is treated as missing.
+    async fn load_collection_for(
+        &self,
+        tenant: &str,
+        collection_id: &str,
+    ) -> Result<Option<Collection>>;
+
+    /// List the IDs of the collections belonging to `tenant`, newest first
+    async fn list_collections_for(&self, tenant: &str) -> Result<Vec<String>>;
+
+    /// Store the reply streamed so far at `index` of a session's history,
+    /// marked as interrupted until the whole session is saved again
+    ///
+    /// Backends that can't store part of a session ignore it.
+    async fn save_partial_reply(
+        &self,
+        _session_id: &str,
+        _index: usize,
+        _message: &Message,
+    ) -> Result<()> {
+        Ok(())
+    }
+
+    /// Record the token usage and latency of a completion
+    ///
+    /// Backends without usage history ignore it.
+    async fn record_usage(
+        &self,
+        _session_id: Option<&str>,
+        _completion: &ChatCompletion,
+        _latency: Duration,
+    ) -> Result<()> {
+        Ok(())
+    }
+
+    /// Usage recorded for a session's completions, if the backend keeps any
+    async fn session_usage(&self, _session_id: &str) -> Result<Option<ReplayUsage>> {
+        Ok(None)
+    }
+}
+
+/// Snapshot of a session held by `MemoryStorage`
+#[derive(Debug, Clone)]
+struct StoredSession {
+    model: Model,
+    title: Option<String>,
+    created_at: DateTime<Utc>,
+    updated_at: DateTime<Utc>,
+    messages: Vec<Message>,
+    interrupted: Vec<usize>,
+    tools: Vec<Tool>,
+    prompt: Option<String>,
+    tenant: Option<String>,
+}
+
+/// Snapshot of a collection held by `MemoryStorage`
+#[derive(Debug, Clone)]
+struct StoredCollection {
+    metadata: CollectionMetadata,
+    session_ids: Vec<String>,
+}
+
+/// In-process storage backed by hash maps
+///
+/// Nothing survives the process, which makes it suited to tests and
+/// ephemeral deployments that still want persistence-aware code paths.
+#[derive(Debug, Default)]
+pub struct MemoryStorage {
+    sessions: RwLock<HashMap<String, StoredSession>>,
+    collections: RwLock<HashMap<String, StoredCollection>>,
+}
+
+impl MemoryStorage {
+    /// Create an empty storage
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Restore a stored session, optionally only if it belongs to `tenant`
+    async fn load_session_scoped(&self, session_id: &str, tenant: Option<&str>) -> Option<Session> {
+        let sessions = self.sessions.read().await;
+        let stored = sessions
+            .get(session_id)
+            .filter(|stored| tenant.is_none() || stored.tenant.as_deref() == tenant)
+            .cloned()?;
+        drop(sessions);
+
+        let mut session = Session::restore(
+            session_id.to_string(),
+            stored.model,
+            stored.title,
+            stored.created_at,
+            stored.updated_at,
+            stored.messages,
+        );
+        session.add_tools(stored.tools);
+        session.set_prompt_reference(stored.prompt);
+        session.set_interrupted(stored.interrupted);
+        if let Some(tenant) = stored.tenant {
+            session.set_tenant(tenant);
+        }
+        Some(session)
+    }
+
+    /// Restore a stored collection, optionally only if it belongs to `tenant`
+    async fn load_collection_scoped(
+        &self,
+        collection_id: &str,
+        tenant: Option<&str>,
+    ) -> Option<Collection> {
+        let collections = self.collections.read().await;
+        collections
+            .get(collection_id)
+            .filter(|stored| tenant.is_none() || stored.metadata.tenant.as_deref() == tenant)
+            .cloned()
+            .map(|stored| {
+                Collection::restore(
+                    collection_id.to_string(),
+                    stored.metadata,
+                    stored.session_ids,
+                )
+            })
+    }
+
+    /// Stored session IDs, newest first, optionally only those belonging to `tenant`
+    async fn session_ids(&self, tenant: Option<&str>) -> Vec<String> {
+        let sessions = self.sessions.read().await;
+        let mut entries: Vec<_> = sessions
+            .iter()
+            .filter(|(_, s)| tenant.is_none() || s.tenant.as_deref() == tenant)
+            .map(|(id, s)| (s.created_at, id.clone()))
+            .collect();
+        entries.sort_by_key(|(created_at, _)| Reverse(*created_at));
+        entries.into_iter().map(|(_, id)| id).collect()
+    }
+
+    /// Stored collection IDs, newest first, optionally only those belonging to `tenant`
+    async fn collection_ids(&self, tenant: Option<&str>) -> Vec<String> {
+        let collections = self.collections.read().await;
+        let mut entries: Vec<_> = collections
+            .iter()
+            .filter(|(_, c)| tenant.is_none() || c.metadata.tenant.as_deref() == tenant)
+            .map(|(id, c)| (c.metadata.created_at, id.clone()))
+            .collect();
+        entries.sort_by_key(|(created_at, _)| Reverse(*created_at));
+        entries.into_iter().map(|(_, id)| id).collect()
+    }
+}
+
+#[async_trait]
+impl Storage for MemoryStorage {
+    async fn save_session(&self, session: &Session) -> Result<()> {
+        let metadata = session.metadata();
+        let stored = StoredSession {
+            model: session.model(),
+            title: metadata.title,
+            created_at: metadata.created_at,
+            updated_at: metadata.updated_at,
+            messages: session.messages().await,
+            interrupted: session.interrupted().await,
+            tools: session.tools().to_vec(),
+            prompt: session.prompt().map(str::to_string),
+            tenant: session.tenant().map(str::to_string),
+        };
+
+        self.sessions
+            .write()
+            .await
+            .insert(session.id.clone(), stored);
+        Ok(())
+    }
+
+    async fn load_session(&self, session_id: &str) -> Result<Option<Session>> {
+        Ok(self.load_session_scoped(session_id, None).await)
+    }
+
+    async fn load_session_for(&self, tenant: &str, session_id: &str) -> Result<Option<Session>> {
+        Ok(self.load_session_scoped(session_id, Some(tenant)).await)
+    }
+
+    async fn delete_session(&self, session_id: &str) -> Result<()> {
+        let removed = self.sessions.write().await.remove(session_id);
+        let message_count = removed.map_or(0, |session| session.messages.len());
+
+        // Drop the session from the collections it belonged to, as the SQL backends do
+        for collection in self.collections.write().await.values_mut() {
+            let before = collection.session_ids.len();
+            collection.session_ids.retain(|id| id != session_id);
+            if collection.session_ids.len() < before {
+                let metadata = &mut collection.metadata;
+                metadata.session_count = collection.session_ids.len();
+                metadata.total_messages = metadata.total_messages.saturating_sub(message_count);
+            }
+        }
+
+        Ok(())
+    }
+
+    async fn list_sessions(&self) -> Result<Vec<String>> {
+        Ok(self.session_ids(None).await)
+    }
+
+    async fn list_sessions_for(&self, tenant: &str) -> Result<Vec<String>> {
+        Ok(self.session_ids(Some(tenant)).await)
+    }
+
+    async fn save_collection(&self, collection: &Collection) -> Result<()> {
+        let stored = StoredCollection {
+            metadata: collection.metadata(),
+            session_ids: collection.session_ids(),
+        };
+
+        self.collections
+            .write()
+            .await
+            .insert(collection.id.clone(), stored);
+        Ok(())
+    }
+
+    async fn load_collection(&self, collection_id: &str) -> Result<Option<Collection>> {
+        Ok(self.load_collection_scoped(collection_id, None).await)
+    }
+
+    async fn load_collection_for(
+        &self,
+        tenant: &str,
+        collection_id: &str,
+    ) -> Result<Option<Collection>> {
+        Ok(self
+            .load_collection_scoped(collection_id, Some(tenant))
+            .await)
+    }
+
+    async fn delete_collection(&self, collection_id: &str) -> Result<()> {
+        self.collections.write().await.remove(collection_id);
+        Ok(())
+    }
+
+    async fn list_collections(&self) -> Result<Vec<String>> {
+        Ok(self.collection_ids(None).await)
+    }
+
+    async fn list_collections_for(&self, tenant: &str) -> Result<Vec<String>> {
+        Ok(self.collection_ids(Some(tenant)).await)
+    }
+
+    async fn save_partial_reply(
+        &self,
+        session_id: &str,
+        index: usize,
+        message: &Message,
+    ) -> Result<()> {
+        let mut sessions = self.sessions.write().await;
+        let Some(stored) = sessions.get_mut(session_id) else {
+            return Ok(());
+        };
+
+        match stored.messages.get_mut(index) {
+            Some(existing) => *existing = message.clone(),
+            None => stored.messages.push(message.clone()),
+        }
+        let index = index.min(stored.messages.len() - 1);
+        if !stored.interrupted.contains(&index) {
+            stored.interrupted.push(index);
+        }
+        stored.updated_at = Utc::now();
+        Ok(())
+    }
+}
@@ -1,8 +1,25 @@
 //! Tool calling functionality
 
 use crate::error::{GrokError, Result};
+use chrono::{DateTime, Utc};
+use futures::future::BoxFuture;
+use schemars::JsonSchema;
+use serde::de::DeserializeOwned;
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use std::any::{Any, TypeId};
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::future::Future;
+use std::marker::PhantomData;
+use std::sync::{Arc, Mutex, PoisonError, RwLock, RwLockReadGuard, RwLockWriteGuard};
+use std::time::{Duration, Instant};
+use tokio::sync::{broadcast, OwnedSemaphorePermit, Semaphore};
+
+#[cfg(feature = "code-exec")]
+pub mod exec;
+#[cfg(feature = "fs-tool")]
+pub mod fs;
+#[cfg(feature = "http-tool")]
+pub mod http;
 
 /// Trait for executable tools
 #[async_trait::async_trait]
@@ -10,10 +27,317 @@ pub trait ToolExecutor: Send + Sync {
     /// Execute the tool with the given arguments
     async fn execute(&self, args: serde_json::Value) -> Result<serde_json::Value>;
 
+    /// Execute the tool with access to the caller's context
+    ///
+    /// Override this instead of relying on globals when the tool needs the
+    /// session, the user or application state. Defaults to `execute`.
+    async fn execute_with_context(
+        &self,
+        args: serde_json::Value,
+        _context: &ToolContext,
+    ) -> Result<serde_json::Value> {
+        self.execute(args).await
+    }
+
+    /// Get the tool specification
+    fn spec(&self) -> ToolSpec;
+}
+
+/// Trait for long-running tools that report progress while they execute
+///
+/// Register with `ToolRegistry::register_streaming`. Progress is published to
+/// the registry's subscribers; see `ToolRegistry::subscribe_progress`.
+#[async_trait::async_trait]
+pub trait StreamingToolExecutor: Send + Sync {
+    /// Execute the tool, reporting progress through `progress`
+    async fn execute(
+        &self,
+        args: serde_json::Value,
+        progress: ProgressSender,
+    ) -> Result<serde_json::Value>;
+
+    /// Execute the tool with access to the caller's context; defaults to `execute`
+    async fn execute_with_context(
+        &self,
+        args: serde_json::Value,
+        progress: ProgressSender,
+        _context: &ToolContext,
+    ) -> Result<serde_json::Value> {
+        self.execute(args, progress).await
+    }
+
     /// Get the tool specification
     fn spec(&self) -> ToolSpec;
 }
 
+/// Information about who a tool is running for, passed to `ToolExecutor::execute_with_context`
+///
+/// Besides the session and user IDs, applications can attach their own state
+/// (database pools, auth tokens) and retrieve it by type.
+#[derive(Clone, Default)]
+pub struct ToolContext {
+    /// ID of the session the tool call came from
+    pub session_id: Option<String>,
+    /// ID of the end user the session belongs to
+    pub user_id: Option<String>,
+    extensions: HashMap<TypeId, Arc<dyn Any + Send + Sync>>,
+}
+
+impl ToolContext {
+    /// Create an empty context
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set the session ID
+    pub fn with_session_id(mut self, session_id: impl Into<String>) -> Self {
+        self.session_id = Some(session_id.into());
+        self
+    }
+
+    /// Set the user ID
+    pub fn with_user_id(mut self, user_id: impl Into<String>) -> Self {
+        self.user_id = Some(user_id.into());
+        self
+    }
+
+    /// Attach a value, replacing any earlier value of the same type
+    pub fn with<T: Send + Sync + 'static>(mut self, value: T) -> Self {
+        self.insert(value);
+        self
+    }
+
+    /// Attach a value, replacing any earlier value of the same type
+    pub fn insert<T: Send + Sync + 'static>(&mut self, value: T) {
+        self.extensions.insert(TypeId::of::<T>(), Arc::new(value));
+    }
+
+    /// Get an attached value by type
+    pub fn get<T: Send + Sync + 'static>(&self) -> Option<&T> {
+        self.extensions
+            .get(&TypeId::of::<T>())
+            .and_then(|value| value.downcast_ref())
+    }
+}
+
+impl std::fmt::Debug for ToolContext {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ToolContext")
+            .field("session_id", &self.session_id)
+            .field("user_id", &self.user_id)
+            .field("extensions", &self.extensions.len())
+            .finish()
+    }
+}
+
+/// Progress event from a running tool
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ToolProgress {
+    /// ID of the tool call being executed
+    pub tool_call_id: String,
+    /// Name of the tool
+    pub tool: String,
+    /// What the tool reported
+    pub event: ToolProgressEvent,
+}
+
+/// Kind of progress reported by a tool
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", content = "data", rename_all = "snake_case")]
+pub enum ToolProgressEvent {
+    /// A line of log output
+    Log(String),
+    /// A partial result
+    Partial(serde_json::Value),
+}
+
+/// Handle a streaming tool uses to report progress for one call
+///
+/// Events are dropped when nobody is subscribed.
+#[derive(Debug, Clone)]
+pub struct ProgressSender {
+    tool_call_id: String,
+    tool: String,
+    sender: Option<broadcast::Sender<ToolProgress>>,
+}
+
+impl ProgressSender {
+    /// A sender whose events go nowhere, for running a streaming tool directly
+    pub fn disconnected() -> Self {
+        Self {
+            tool_call_id: String::new(),
+            tool: String::new(),
+            sender: None,
+        }
+    }
+
+    /// Report a line of log output
+    pub fn log(&self, line: impl Into<String>) {
+        self.send(ToolProgressEvent::Log(line.into()));
+    }
+
+    /// Report a partial result
+    pub fn partial(&self, value: serde_json::Value) {
+        self.send(ToolProgressEvent::Partial(value));
+    }
+
+    fn send(&self, event: ToolProgressEvent) {
+        if let Some(ref sender) = self.sender {
+            // Fails only when there are no subscribers
+            let _ = sender.send(ToolProgress {
+                tool_call_id: self.tool_call_id.clone(),
+                tool: self.tool.clone(),
+                event,
+            });
+        }
+    }
+}
+
+/// Runs a streaming tool as a plain one, for callers of `ToolRegistry::get`
+struct DisconnectedStreamingTool(Arc<dyn StreamingToolExecutor>);
+
+#[async_trait::async_trait]
+impl ToolExecutor for DisconnectedStreamingTool {
+    async fn execute(&self, args: serde_json::Value) -> Result<serde_json::Value> {
+        self.0.execute(args, ProgressSender::disconnected()).await
+    }
+
+    async fn execute_with_context(
+        &self,
+        args: serde_json::Value,
+        context: &ToolContext,
+    ) -> Result<serde_json::Value> {
+        self.0
+            .execute_with_context(args, ProgressSender::disconnected(), context)
+            .await
+    }
+
+    fn spec(&self) -> ToolSpec {
+        self.0.spec()
+    }
+}
+
+/// A tool whose handler takes typed arguments and returns a typed result
+///
+/// The parameter schema is generated from `Args` with schemars, and arguments
+/// that fail to deserialize are reported as `ToolExecution` errors.
+///
+/// ```rust,ignore
+/// #[derive(Deserialize, JsonSchema)]
+/// struct WeatherArgs {
+///     /// Name of the city
+///     city: String,
+/// }
+///
+/// registry.register(TypedTool::new("get_weather", "Get the weather for a city", |args: WeatherArgs| async move {
+///     Ok(format!("Sunny in {}", args.city))
+/// }));
+/// ```
+pub struct TypedTool<Args, Out> {
+    name: String,
+    description: String,
+    strict: bool,
+    handler: Box<dyn Fn(Args) -> BoxFuture<'static, Result<Out>> + Send + Sync>,
+    _marker: PhantomData<fn(Args) -> Out>,
+}
+
+impl<Args, Out> TypedTool<Args, Out>
+where
+    Args: DeserializeOwned + JsonSchema + Send + 'static,
+    Out: Serialize + Send + 'static,
+{
+    /// Create a typed tool from an async handler
+    pub fn new<F, Fut>(name: impl Into<String>, description: impl Into<String>, handler: F) -> Self
+    where
+        F: Fn(Args) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = Result<Out>> + Send + 'static,
+    {
+        Self {
+            name: name.into(),
+            description: description.into(),
+            strict: false,
+            handler: Box::new(move |args| Box::pin(handler(args))),
+            _marker: PhantomData,
+        }
+    }
+
+    /// Generate a strict schema and ask the API to enforce it; see `ToolSpec::into_strict`
+    pub fn strict(mut self) -> Self {
+        self.strict = true;
+        self
+    }
+}
+
+impl<Args, Out> std::fmt::Debug for TypedTool<Args, Out> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("TypedTool")
+            .field("name", &self.name)
+            .field("description", &self.description)
+            .field("strict", &self.strict)
+            .finish_non_exhaustive()
+    }
+}
+
+#[async_trait::async_trait]
+impl<Args, Out> ToolExecutor for TypedTool<Args, Out>
+where
+    Args: DeserializeOwned + JsonSchema + Send + 'static,
+    Out: Serialize + Send + 'static,
+{
+    async fn execute(&self, args: serde_json::Value) -> Result<serde_json::Value> {
+        let args: Args = serde_json::from_value(args).map_err(|e| {
+            GrokError::ToolExecution(format!("Invalid arguments for tool '{}': {}", self.name, e))
+        })?;
+
+        let output = (self.handler)(args).await?;
+
+        serde_json::to_value(output)
+            .map_err(|e| GrokError::ToolExecution(format!("Failed to serialize result: {}", e)))
+    }
+
+    fn spec(&self) -> ToolSpec {
+        let mut parameters = serde_json::to_value(schemars::schema_for!(Args))
+            .unwrap_or_else(|_| serde_json::json!({ "type": "object" }));
+
+        // The API only needs the schema itself, not its metadata
+        if let Some(object) = parameters.as_object_mut() {
+            object.remove("$schema");
+            object.remove("title");
+        }
+
+        let spec = ToolSpec {
+            name: self.name.clone(),
+            description: self.description.clone(),
+            parameters,
+            strict: false,
+        };
+        if self.strict {
+            spec.into_strict()
+        } else {
+            spec
+        }
+    }
+}
+
+/// A tool made from a closure over raw JSON arguments; see `ToolRegistry::register_fn`
+struct FnTool {
+    spec: ToolSpec,
+    handler: Box<
+        dyn Fn(serde_json::Value) -> BoxFuture<'static, Result<serde_json::Value>> + Send + Sync,
+    >,
+}
+
+#[async_trait::async_trait]
+impl ToolExecutor for FnTool {
+    async fn execute(&self, args: serde_json::Value) -> Result<serde_json::Value> {
+        (self.handler)(args).await
+    }
+
+    fn spec(&self) -> ToolSpec {
+        self.spec.clone()
+    }
+}
+
 /// Tool specification for function calling
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ToolSpec {
@@ -23,6 +347,60 @@ pub struct ToolSpec {
     pub description: String,
     /// Parameters schema (JSON Schema)
     pub parameters: serde_json::Value,
+    /// Ask the API to make the model's arguments match the schema exactly
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub strict: bool,
+}
+
+impl ToolSpec {
+    /// Turn on strict mode and tighten the schema to what strict mode requires
+    ///
+    /// Every object schema gets `additionalProperties: false` and lists all of
+    /// its properties as required. Optional properties should accept `null`,
+    /// as schemars generates for `Option` fields.
+    pub fn into_strict(mut self) -> Self {
+        make_strict(&mut self.parameters);
+        self.strict = true;
+        self
+    }
+}
+
+/// Apply strict-mode object rules to a schema and all schemas nested in it
+fn make_strict(schema: &mut serde_json::Value) {
+    let Some(object) = schema.as_object_mut() else {
+        return;
+    };
+
+    if let Some(properties) = object.get("properties").and_then(|p| p.as_object()) {
+        let required: Vec<serde_json::Value> = properties
+            .keys()
+            .map(|key| serde_json::Value::String(key.clone()))
+            .collect();
+        object.insert("required".to_string(), serde_json::Value::Array(required));
+        object.insert(
+            "additionalProperties".to_string(),
+            serde_json::Value::Bool(false),
+        );
+    } else if object.get("type").and_then(|t| t.as_str()) == Some("object") {
+        object.insert(
+            "additionalProperties".to_string(),
+            serde_json::Value::Bool(false),
+        );
+    }
+
+    for key in ["properties", "definitions", "$defs"] {
+        if let Some(children) = object.get_mut(key).and_then(|c| c.as_object_mut()) {
+            children.values_mut().for_each(make_strict);
+        }
+    }
+    for key in ["anyOf", "oneOf", "allOf"] {
+        if let Some(children) = object.get_mut(key).and_then(|c| c.as_array_mut()) {
+            children.iter_mut().for_each(make_strict);
+        }
+    }
+    if let Some(items) = object.get_mut("items") {
+        make_strict(items);
+    }
 }
 
 /// Tool definition for API requests
@@ -48,6 +426,21 @@ impl Tool {
                 name: name.into(),
                 description: description.into(),
                 parameters,
+                strict: false,
+            },
+        }
+    }
+}
+
+impl From<Tool> for crate::chat::Tool {
+    fn from(tool: Tool) -> Self {
+        Self {
+            tool_type: tool.tool_type,
+            function: crate::chat::ToolSpec {
+                name: tool.function.name,
+                description: Some(tool.function.description),
+                parameters: Some(tool.function.parameters),
+                strict: tool.function.strict.then_some(true),
             },
         }
     }
@@ -71,6 +464,18 @@ pub struct ToolFunction {
     pub arguments: String,
 }
 
+impl From<&crate::chat::ToolCall> for ToolCall {
+    fn from(call: &crate::chat::ToolCall) -> Self {
+        Self {
+            id: call.id.clone(),
+            function: ToolFunction {
+                name: call.function.name.clone(),
+                arguments: call.function.arguments.clone(),
+            },
+        }
+    }
+}
+
 /// Result of a tool execution
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ToolResult {
@@ -78,73 +483,912 @@ pub struct ToolResult {
     pub tool_call_id: String,
     /// The result content
     pub content: String,
+    /// The error behind an `{"error": ...}` result reported back to the model
+    #[serde(skip)]
+    pub error: Option<Arc<GrokError>>,
 }
 
-/// Tool registry for managing available tools
+/// Progress events buffered per subscriber
+const PROGRESS_CAPACITY: usize = 256;
+
+/// Upper bounds of the latency histogram buckets
+const LATENCY_BUCKETS: [Duration; 9] = [
+    Duration::from_millis(10),
+    Duration::from_millis(50),
+    Duration::from_millis(100),
+    Duration::from_millis(250),
+    Duration::from_millis(500),
+    Duration::from_secs(1),
+    Duration::from_millis(2500),
+    Duration::from_secs(5),
+    Duration::from_secs(10),
+];
+
+/// Call statistics for one tool
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ToolMetrics {
+    /// Tool name
+    pub name: String,
+    /// Number of calls that reached the tool
+    pub calls: u64,
+    /// Calls that failed validation or execution
+    pub errors: u64,
+    /// Calls answered from the result cache without running the tool
+    pub cache_hits: u64,
+    /// Sum of call latencies
+    pub total_latency: Duration,
+    /// Slowest call
+    pub max_latency: Duration,
+    /// Latency histogram as (upper bound, count) pairs; `None` is the overflow bucket
+    pub latency_histogram: Vec<(Option<Duration>, u64)>,
+    /// Message of the most recent failure
+    pub last_error: Option<String>,
+    /// When the tool was last called
+    pub last_called_at: Option<DateTime<Utc>>,
+}
+
+impl ToolMetrics {
+    fn new(name: &str) -> Self {
+        let mut latency_histogram: Vec<(Option<Duration>, u64)> = LATENCY_BUCKETS
+            .iter()
+            .map(|bound| (Some(*bound), 0))
+            .collect();
+        latency_histogram.push((None, 0));
+        Self {
+            name: name.to_string(),
+            calls: 0,
+            errors: 0,
+            cache_hits: 0,
+            total_latency: Duration::ZERO,
+            max_latency: Duration::ZERO,
+            latency_histogram,
+            last_error: None,
+            last_called_at: None,
+        }
+    }
+
+    fn record(&mut self, latency: Duration, error: Option<&GrokError>) {
+        self.calls += 1;
+        self.total_latency += latency;
+        self.max_latency = self.max_latency.max(latency);
+        if let Some(bucket) = self
+            .latency_histogram
+            .iter_mut()
+            .find(|(bound, _)| bound.is_none_or(|bound| latency <= bound))
+        {
+            bucket.1 += 1;
+        }
+        if let Some(error) = error {
+            self.errors += 1;
+            self.last_error = Some(error.to_string());
+        }
+        self.last_called_at = Some(Utc::now());
+    }
+
+    /// Fraction of calls that failed, from 0.0 to 1.0
+    pub fn error_rate(&self) -> f64 {
+        if self.calls == 0 {
+            0.0
+        } else {
+            self.errors as f64 / self.calls as f64
+        }
+    }
+
+    /// Mean call latency
+    pub fn mean_latency(&self) -> Option<Duration> {
+        u32::try_from(self.calls)
+            .ok()
+            .filter(|calls| *calls > 0)
+            .map(|calls| self.total_latency / calls)
+    }
+}
+
+/// Limits for a tool's result cache
+#[derive(Debug, Clone)]
+pub struct ToolCacheOptions {
+    /// How long a result is reused
+    pub ttl: Duration,
+    /// Maximum number of cached results for the tool; the least recently used is evicted
+    pub capacity: usize,
+}
+
+impl Default for ToolCacheOptions {
+    fn default() -> Self {
+        Self {
+            ttl: Duration::from_secs(300),
+            capacity: 256,
+        }
+    }
+}
+
+#[derive(Debug)]
+struct CachedResult {
+    content: String,
+    expires_at: Instant,
+    last_used: Instant,
+}
+
+/// Successful results of cached tools, keyed by tool name and canonical arguments
+#[derive(Debug, Default)]
+struct ToolCache {
+    options: HashMap<String, ToolCacheOptions>,
+    entries: HashMap<String, HashMap<String, CachedResult>>,
+}
+
+impl ToolCache {
+    /// Cache limits for the tool registered as `name`, set for it or for all its versions
+    fn options_for(&self, name: &str) -> Option<&ToolCacheOptions> {
+        self.options
+            .get(name)
+            .or_else(|| self.options.get(base_name(name)))
+    }
+
+    fn get(&mut self, name: &str, args: &str) -> Option<String> {
+        let entries = self.entries.get_mut(name)?;
+        let now = Instant::now();
+        match entries.get_mut(args) {
+            Some(entry) if entry.expires_at > now => {
+                entry.last_used = now;
+                Some(entry.content.clone())
+            }
+            Some(_) => {
+                entries.remove(args);
+                None
+            }
+            None => None,
+        }
+    }
+
+    fn insert(&mut self, name: &str, args: String, content: String) {
+        let Some(options) = self.options_for(name).cloned() else {
+            return;
+        };
+        let now = Instant::now();
+        let entries = self.entries.entry(name.to_string()).or_default();
+        entries.retain(|_, entry| entry.expires_at > now);
+        if entries.len() >= options.capacity {
+            let oldest = entries
+                .iter()
+                .min_by_key(|(_, entry)| entry.last_used)
+                .map(|(args, _)| args.clone());
+            if let Some(oldest) = oldest {
+                entries.remove(&oldest);
+            }
+        }
+        if options.capacity > 0 {
+            entries.insert(
+                args,
+                CachedResult {
+                    content,
+                    expires_at: now + options.ttl,
+                    last_used: now,
+                },
+            );
+        }
+    }
+}
+
+/// The name of a `name@vN` tool without its version
+fn base_name(name: &str) -> &str {
+    match name.rsplit_once("@v") {
+        Some((base, version)) if version.parse::<u32>().is_ok() => base,
+        _ => name,
+    }
+}
+
+/// Serialize JSON with object keys sorted, so equal arguments give equal cache keys
+fn canonical_json(value: &serde_json::Value) -> String {
+    fn sorted(value: &serde_json::Value) -> serde_json::Value {
+        match value {
+            serde_json::Value::Object(map) => {
+                let mut keys: Vec<&String> = map.keys().collect();
+                keys.sort();
+                serde_json::Value::Object(
+                    keys.into_iter()
+                        .map(|key| (key.clone(), sorted(&map[key])))
+                        .collect(),
+                )
+            }
+            serde_json::Value::Array(items) => {
+                serde_json::Value::Array(items.iter().map(sorted).collect())
+            }
+            other => other.clone(),
+        }
+    }
+    sorted(value).to_string()
+}
+
+/// How tool failures are reported by `ToolRegistry::execute_tool_call`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ToolErrorPolicy {
+    /// Return the failure as an `Err`, ending the turn
+    #[default]
+    Propagate,
+    /// Serialize the failure into the tool result as `{"error": ...}` so the
+    /// model can correct itself; the error is kept in `ToolResult::error`
+    ReturnToModel,
+}
+
+/// Decision on a tool call that requires approval
+#[derive(Debug, Clone)]
+pub enum Approval {
+    /// Run the call as requested
+    Approve,
+    /// Skip the call; the reason is returned to the model as the tool result
+    Deny(String),
+    /// Run this call instead, e.g. with corrected arguments
+    Edit(ToolCall),
+}
+
+/// Gate consulted before running tools that require approval
+#[async_trait::async_trait]
+pub trait ApprovalHandler: Send + Sync {
+    /// Decide whether a tool call may run
+    async fn approve(&self, tool_call: &ToolCall) -> Approval;
+}
+
+#[async_trait::async_trait]
+impl<F, Fut> ApprovalHandler for F
+where
+    F: Fn(ToolCall) -> Fut + Send + Sync,
+    Fut: Future<Output = Approval> + Send,
+{
+    async fn approve(&self, tool_call: &ToolCall) -> Approval {
+        self(tool_call.clone()).await
+    }
+}
+
+/// Limits on how often a tool may run
+#[derive(Debug, Clone, Default)]
+pub struct ToolLimits {
+    /// Maximum calls started in any 60 second window; further calls fail
+    /// with an error telling the model when to retry
+    pub calls_per_minute: Option<u32>,
+    /// Maximum calls running at once; further calls wait for a slot
+    pub max_concurrent: Option<usize>,
+}
+
+/// Enforces a tool's `ToolLimits`
 #[derive(Debug)]
+struct ToolLimiter {
+    limits: ToolLimits,
+    started: Mutex<VecDeque<Instant>>,
+    concurrency: Option<Arc<Semaphore>>,
+}
+
+impl ToolLimiter {
+    fn new(limits: ToolLimits) -> Self {
+        Self {
+            concurrency: limits
+                .max_concurrent
+                .map(|max| Arc::new(Semaphore::new(max))),
+            started: Mutex::new(VecDeque::new()),
+            limits,
+        }
+    }
+
+    /// Wait for a concurrency slot and count the call against the rate limit
+    async fn acquire(&self, name: &str) -> Result<Option<OwnedSemaphorePermit>> {
+        let permit = match self.concurrency {
+            Some(ref semaphore) => Some(semaphore.clone().acquire_owned().await.map_err(|e| {
+                GrokError::ToolExecution(format!("Failed to acquire slot for '{}': {}", name, e))
+            })?),
+            None => None,
+        };
+
+        if let Some(calls_per_minute) = self.limits.calls_per_minute {
+            let window = Duration::from_secs(60);
+            let now = Instant::now();
+            let mut started = self.started.lock().unwrap_or_else(PoisonError::into_inner);
+            while started
+                .front()
+                .is_some_and(|at| now.duration_since(*at) >= window)
+            {
+                started.pop_front();
+            }
+            if started.len() >= calls_per_minute as usize {
+                let retry_after = started
+                    .front()
+                    .map_or(window, |at| window - now.duration_since(*at));
+                return Err(GrokError::ToolExecution(format!(
+                    "Tool '{}' is rate limited to {} calls per minute; retry in {} seconds",
+                    name,
+                    calls_per_minute,
+                    retry_after.as_secs() + 1
+                )));
+            }
+            started.push_back(now);
+        }
+
+        Ok(permit)
+    }
+}
+
+/// Registered tools by name
+type ToolMap = HashMap<String, Arc<RegisteredTool>>;
+
+/// An executor with the spec and compiled parameter schema taken at registration
+struct RegisteredTool {
+    executor: Arc<dyn ToolExecutor>,
+    streaming: Option<Arc<dyn StreamingToolExecutor>>,
+    limiter: Option<Arc<ToolLimiter>>,
+    spec: ToolSpec,
+    /// A schema that fails to compile is reported on every call to the tool
+    schema: std::result::Result<jsonschema::JSONSchema, String>,
+}
+
+impl RegisteredTool {
+    fn new(executor: Arc<dyn ToolExecutor>) -> Self {
+        let spec = executor.spec();
+        Self::with_spec(executor, spec)
+    }
+
+    fn with_spec(executor: Arc<dyn ToolExecutor>, spec: ToolSpec) -> Self {
+        let schema = jsonschema::JSONSchema::compile(&spec.parameters).map_err(|e| e.to_string());
+        Self {
+            executor,
+            streaming: None,
+            limiter: None,
+            spec,
+            schema,
+        }
+    }
+
+    fn streaming(executor: Arc<dyn StreamingToolExecutor>) -> Self {
+        Self {
+            streaming: Some(executor.clone()),
+            ..Self::new(Arc::new(DisconnectedStreamingTool(executor)))
+        }
+    }
+}
+
+/// Tool registry for managing available tools
+///
+/// Cloning is cheap and clones share their tools, so tools registered through
+/// one handle are visible to all of them. The approval and error settings
+/// belong to each handle, as do disabled tools, so per-tenant handles can
+/// turn tools off without affecting each other.
+#[derive(Clone)]
 pub struct ToolRegistry {
-    tools: HashMap<String, Box<dyn ToolExecutor>>,
+    tools: Arc<RwLock<ToolMap>>,
+    metrics: Arc<Mutex<HashMap<String, ToolMetrics>>>,
+    cache: Arc<Mutex<ToolCache>>,
+    progress: broadcast::Sender<ToolProgress>,
+    deprecated: Arc<RwLock<HashMap<String, String>>>,
+    approval_handler: Option<Arc<dyn ApprovalHandler>>,
+    approval_required: HashSet<String>,
+    disabled: HashSet<String>,
+    error_policy: ToolErrorPolicy,
+}
+
+impl std::fmt::Debug for ToolRegistry {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ToolRegistry")
+            .field("tools", &self.read_tools().keys().collect::<Vec<_>>())
+            .field("approval_handler", &self.approval_handler.is_some())
+            .field("approval_required", &self.approval_required)
+            .field("disabled", &self.disabled)
+            .field("error_policy", &self.error_policy)
+            .finish()
+    }
 }
 
 impl ToolRegistry {
     /// Create a new empty tool registry
     pub fn new() -> Self {
         Self {
-            tools: HashMap::new(),
+            tools: Arc::new(RwLock::new(HashMap::new())),
+            metrics: Arc::new(Mutex::new(HashMap::new())),
+            cache: Arc::new(Mutex::new(ToolCache::default())),
+            progress: broadcast::channel(PROGRESS_CAPACITY).0,
+            deprecated: Arc::new(RwLock::new(HashMap::new())),
+            approval_handler: None,
+            approval_required: HashSet::new(),
+            disabled: HashSet::new(),
+            error_policy: ToolErrorPolicy::default(),
         }
     }
 
+    /// Set how tool failures are reported
+    pub fn set_error_policy(&mut self, policy: ToolErrorPolicy) {
+        self.error_policy = policy;
+    }
+
+    /// Set the handler that approves, denies or edits gated tool calls
+    pub fn set_approval_handler<H: ApprovalHandler + 'static>(&mut self, handler: H) {
+        self.approval_handler = Some(Arc::new(handler));
+    }
+
+    /// Require approval before the named tool runs
+    ///
+    /// Calls to a gated tool are denied if no approval handler is set. A bare
+    /// name gates every version of a versioned tool; `name@vN` gates that
+    /// version, including calls that reach it through the bare name.
+    pub fn require_approval(&mut self, name: impl Into<String>) {
+        self.approval_required.insert(name.into());
+    }
+
     /// Register a tool executor
-    pub fn register<T: ToolExecutor + 'static>(&mut self, executor: T) {
-        let spec = executor.spec();
-        self.tools.insert(spec.name.clone(), Box::new(executor));
+    ///
+    /// The tool's spec is read and its parameter schema compiled once, here.
+    pub fn register<T: ToolExecutor + 'static>(&self, executor: T) {
+        let tool = RegisteredTool::new(Arc::new(executor));
+        self.write_tools()
+            .insert(tool.spec.name.clone(), Arc::new(tool));
+    }
+
+    /// Register an async closure as a tool
+    ///
+    /// The closure receives the arguments once they have been validated
+    /// against `parameters`.
+    ///
+    /// ```rust,ignore
+    /// registry.register_fn(
+    ///     "add",
+    ///     "Add two numbers",
+    ///     tool_params!("a": param!(number, "First number"), "b": param!(number, "Second number")),
+    ///     |args| async move {
+    ///         let sum = args["a"].as_f64().unwrap_or(0.0) + args["b"].as_f64().unwrap_or(0.0);
+    ///         Ok(serde_json::json!({ "sum": sum }))
+    ///     },
+    /// );
+    /// ```
+    pub fn register_fn<F, Fut>(
+        &self,
+        name: impl Into<String>,
+        description: impl Into<String>,
+        parameters: serde_json::Value,
+        handler: F,
+    ) where
+        F: Fn(serde_json::Value) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = Result<serde_json::Value>> + Send + 'static,
+    {
+        self.register(FnTool {
+            spec: ToolSpec {
+                name: name.into(),
+                description: description.into(),
+                parameters,
+                strict: false,
+            },
+            handler: Box::new(move |args| Box::pin(handler(args))),
+        });
+    }
+
+    /// Register a tool executor whose calls are limited
+    pub fn register_with_limits<T: ToolExecutor + 'static>(&self, executor: T, limits: ToolLimits) {
+        let mut tool = RegisteredTool::new(Arc::new(executor));
+        tool.limiter = Some(Arc::new(ToolLimiter::new(limits)));
+        self.write_tools()
+            .insert(tool.spec.name.clone(), Arc::new(tool));
+    }
+
+    /// Register one version of a tool under `name@v<version>`
+    ///
+    /// Versions are offered to the model side by side. A call to the bare
+    /// name, such as one from an older transcript, runs the tool registered
+    /// under that name or, failing that, its highest version.
+    pub fn register_version<T: ToolExecutor + 'static>(&self, executor: T, version: u32) {
+        let mut tool = RegisteredTool::new(Arc::new(executor));
+        tool.spec.name = format!("{}@v{}", tool.spec.name, version);
+        self.write_tools()
+            .insert(tool.spec.name.clone(), Arc::new(tool));
+    }
+
+    /// Mark a tool deprecated in favour of `replacement`
+    ///
+    /// The tool keeps working, but its description tells the model to call
+    /// the replacement instead.
+    pub fn deprecate(&self, name: impl Into<String>, replacement: impl Into<String>) {
+        self.deprecated
+            .write()
+            .unwrap_or_else(PoisonError::into_inner)
+            .insert(name.into(), replacement.into());
     }
 
-    /// Get a tool by name
-    pub fn get(&self, name: &str) -> Option<&dyn ToolExecutor> {
-        self.tools.get(name).map(|t| t.as_ref())
+    /// Register a tool that reports progress while it runs
+    pub fn register_streaming<T: StreamingToolExecutor + 'static>(&self, executor: T) {
+        let tool = RegisteredTool::streaming(Arc::new(executor));
+        self.write_tools()
+            .insert(tool.spec.name.clone(), Arc::new(tool));
+    }
+
+    /// Receive progress from streaming tools run through this registry or its clones
+    ///
+    /// Subscribers that fall more than 256 events behind skip the oldest ones.
+    pub fn subscribe_progress(&self) -> broadcast::Receiver<ToolProgress> {
+        self.progress.subscribe()
+    }
+
+    /// Replace the executor registered under the same name, returning the old one
+    ///
+    /// Calls already running keep using the old executor. Limits set at
+    /// registration carry over to the new one.
+    pub fn replace<T: ToolExecutor + 'static>(&self, executor: T) -> Option<Arc<dyn ToolExecutor>> {
+        let mut tool = RegisteredTool::new(Arc::new(executor));
+        self.lock_cache().entries.remove(&tool.spec.name);
+        let mut tools = self.write_tools();
+        tool.limiter = tools
+            .get(&tool.spec.name)
+            .and_then(|old| old.limiter.clone());
+        tools
+            .insert(tool.spec.name.clone(), Arc::new(tool))
+            .map(|old| old.executor.clone())
+    }
+
+    /// Remove a tool from every handle, returning its executor
+    pub fn unregister(&self, name: &str) -> Option<Arc<dyn ToolExecutor>> {
+        self.lock_cache().entries.remove(name);
+        self.deprecated
+            .write()
+            .unwrap_or_else(PoisonError::into_inner)
+            .remove(name);
+        self.write_tools()
+            .remove(name)
+            .map(|old| old.executor.clone())
+    }
+
+    /// Stop offering and running a tool through this handle
+    pub fn disable(&mut self, name: impl Into<String>) {
+        self.disabled.insert(name.into());
+    }
+
+    /// Re-enable a tool disabled through this handle
+    pub fn enable(&mut self, name: &str) {
+        self.disabled.remove(name);
+    }
+
+    /// Whether a tool is registered and not disabled on this handle
+    pub fn is_enabled(&self, name: &str) -> bool {
+        self.lookup(name).is_some()
+    }
+
+    /// Get the spec an enabled tool was registered with
+    pub fn spec(&self, name: &str) -> Option<ToolSpec> {
+        self.lookup(name).map(|(_, tool)| tool.spec.clone())
+    }
+
+    /// Get an enabled tool by name
+    pub fn get(&self, name: &str) -> Option<Arc<dyn ToolExecutor>> {
+        self.lookup(name).map(|(_, tool)| tool.executor.clone())
+    }
+
+    /// Find the enabled tool a call to `name` runs, with the name it is registered under
+    fn lookup(&self, name: &str) -> Option<(String, Arc<RegisteredTool>)> {
+        if self.disabled.contains(name) {
+            return None;
+        }
+        let tools = self.read_tools();
+        if let Some(tool) = tools.get(name) {
+            return Some((name.to_string(), tool.clone()));
+        }
+        tools
+            .iter()
+            .filter_map(|(key, tool)| {
+                let (base, version) = key.rsplit_once("@v")?;
+                (base == name).then_some((version.parse::<u32>().ok()?, key, tool))
+            })
+            .filter(|(_, key, _)| !self.disabled.contains(*key))
+            .max_by_key(|(version, _, _)| *version)
+            .map(|(_, key, tool)| (key.clone(), tool.clone()))
+    }
+
+    // A panic while holding the lock can't leave the map half-updated
+    fn read_tools(&self) -> RwLockReadGuard<'_, ToolMap> {
+        self.tools.read().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    fn write_tools(&self) -> RwLockWriteGuard<'_, ToolMap> {
+        self.tools.write().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Reuse successful results of an idempotent tool called again with the same arguments
+    ///
+    /// The cache is shared by all clones of the registry and ignores the
+    /// `ToolContext`, so only cache tools whose results don't depend on it.
+    /// A bare name caches every version of a versioned tool, each separately.
+    pub fn cache_results(&self, name: impl Into<String>, options: ToolCacheOptions) {
+        self.lock_cache().options.insert(name.into(), options);
+    }
+
+    /// Drop all cached results
+    pub fn clear_cache(&self) {
+        self.lock_cache().entries.clear();
+    }
+
+    fn lock_cache(&self) -> std::sync::MutexGuard<'_, ToolCache> {
+        self.cache.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Call statistics for every tool that has been called, sorted by name
+    ///
+    /// Statistics are shared by all clones of the registry.
+    pub fn metrics(&self) -> Vec<ToolMetrics> {
+        let mut metrics: Vec<ToolMetrics> = self.lock_metrics().values().cloned().collect();
+        metrics.sort_by(|a, b| a.name.cmp(&b.name));
+        metrics
+    }
+
+    /// Call statistics for one tool
+    pub fn tool_metrics(&self, name: &str) -> Option<ToolMetrics> {
+        self.lock_metrics().get(name).cloned()
+    }
+
+    /// Clear all call statistics
+    pub fn reset_metrics(&self) {
+        self.lock_metrics().clear();
+    }
+
+    fn lock_metrics(&self) -> std::sync::MutexGuard<'_, HashMap<String, ToolMetrics>> {
+        self.metrics.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    fn record_call(&self, name: &str, latency: Duration, error: Option<&GrokError>) {
+        self.lock_metrics()
+            .entry(name.to_string())
+            .or_insert_with(|| ToolMetrics::new(name))
+            .record(latency, error);
+
+        #[cfg(feature = "tracing")]
+        match error {
+            Some(error) => {
+                tracing::warn!(tool = name, latency_ms = latency.as_millis() as u64, %error, "tool call failed")
+            }
+            None => tracing::debug!(
+                tool = name,
+                latency_ms = latency.as_millis() as u64,
+                "tool call succeeded"
+            ),
+        }
+
+        #[cfg(feature = "metrics")]
+        {
+            metrics::counter!("grok_tool_calls_total", "tool" => name.to_string()).increment(1);
+            if error.is_some() {
+                metrics::counter!("grok_tool_errors_total", "tool" => name.to_string())
+                    .increment(1);
+            }
+            metrics::histogram!("grok_tool_call_duration_seconds", "tool" => name.to_string())
+                .record(latency.as_secs_f64());
+        }
+    }
+
+    /// Specs of the enabled tools, sorted by name, for storing alongside sessions
+    pub fn export_specs(&self) -> Vec<ToolSpec> {
+        let mut specs: Vec<ToolSpec> = self
+            .read_tools()
+            .iter()
+            .filter(|(name, _)| !self.disabled.contains(*name))
+            .map(|(_, tool)| tool.spec.clone())
+            .collect();
+        specs.sort_by(|a, b| a.name.cmp(&b.name));
+        specs
+    }
+
+    /// Rebuild a registry from exported specs, binding each one to an executor by name
+    ///
+    /// Tools keep the stored spec rather than their executor's current one, so
+    /// stored conversations replay against the surface they were recorded with.
+    /// Fails if `binder` has no executor for one of the names.
+    pub fn from_specs<I, F>(specs: I, mut binder: F) -> Result<Self>
+    where
+        I: IntoIterator<Item = ToolSpec>,
+        F: FnMut(&str) -> Option<Arc<dyn ToolExecutor>>,
+    {
+        let registry = Self::new();
+        {
+            let mut tools = registry.write_tools();
+            for spec in specs {
+                let executor = binder(&spec.name).ok_or_else(|| {
+                    GrokError::ToolExecution(format!("No executor for tool '{}'", spec.name))
+                })?;
+                tools.insert(
+                    spec.name.clone(),
+                    Arc::new(RegisteredTool::with_spec(executor, spec)),
+                );
+            }
+        }
+        Ok(registry)
     }
 
     /// Get all registered tools as API tool definitions
+    ///
+    /// Deprecated tools say which tool replaces them in their description.
+    /// Tools are sorted by name so the request body is the same in every run.
     pub fn api_tools(&self) -> Vec<Tool> {
-        self.tools
-            .values()
-            .map(|executor| {
-                let spec = executor.spec();
-                Tool::new(
-                    spec.name.clone(),
-                    spec.description.clone(),
-                    spec.parameters.clone(),
-                )
+        let deprecated = self
+            .deprecated
+            .read()
+            .unwrap_or_else(PoisonError::into_inner);
+        let mut tools: Vec<Tool> = self
+            .read_tools()
+            .iter()
+            .filter(|(name, _)| !self.disabled.contains(*name))
+            .map(|(name, tool)| {
+                let mut function = tool.spec.clone();
+                if let Some(replacement) = deprecated.get(name) {
+                    function.description = format!(
+                        "{} (Deprecated: use '{}' instead.)",
+                        function.description, replacement
+                    );
+                }
+                Tool {
+                    tool_type: "function".to_string(),
+                    function,
+                }
             })
-            .collect()
+            .collect();
+        tools.sort_by(|a, b| a.function.name.cmp(&b.function.name));
+        tools
     }
 
     /// Execute a tool call
+    ///
+    /// Calls to tools that require approval are first passed to the approval
+    /// handler. A denied call is not run; its result tells the model why.
+    /// Failures are returned or reported to the model per the error policy.
     pub async fn execute_tool_call(&self, tool_call: &ToolCall) -> Result<ToolResult> {
-        let executor = self.get(&tool_call.function.name).ok_or_else(|| {
+        self.execute_tool_call_with_context(tool_call, &ToolContext::default())
+            .await
+    }
+
+    /// Execute a tool call, passing `context` to the tool
+    pub async fn execute_tool_call_with_context(
+        &self,
+        tool_call: &ToolCall,
+        context: &ToolContext,
+    ) -> Result<ToolResult> {
+        match self.execute_approved(tool_call, context).await {
+            Err(error) if self.error_policy == ToolErrorPolicy::ReturnToModel => Ok(ToolResult {
+                tool_call_id: tool_call.id.clone(),
+                content: serde_json::json!({ "error": error.to_string() }).to_string(),
+                error: Some(Arc::new(error)),
+            }),
+            result => result,
+        }
+    }
+
+    /// Run a tool call once any required approval is granted
+    async fn execute_approved(
+        &self,
+        tool_call: &ToolCall,
+        context: &ToolContext,
+    ) -> Result<ToolResult> {
+        // Gating a version or the bare name covers calls that resolve to it either way
+        let name = self
+            .lookup(&tool_call.function.name)
+            .map_or_else(|| tool_call.function.name.clone(), |(name, _)| name);
+        let gated = self.approval_required.contains(&name)
+            || self.approval_required.contains(base_name(&name));
+        if !gated {
+            return self.run_tool_call(tool_call, context).await;
+        }
+
+        let approval = match &self.approval_handler {
+            Some(handler) => handler.approve(tool_call).await,
+            None => Approval::Deny("no approval handler is configured".to_string()),
+        };
+
+        match approval {
+            Approval::Approve => self.run_tool_call(tool_call, context).await,
+            Approval::Deny(reason) => Ok(ToolResult {
+                tool_call_id: tool_call.id.clone(),
+                content: serde_json::json!({ "error": format!("Tool call denied: {}", reason) })
+                    .to_string(),
+                error: None,
+            }),
+            Approval::Edit(edited) => {
+                // The result must still answer the call the model made
+                let result = self.run_tool_call(&edited, context).await?;
+                Ok(ToolResult {
+                    tool_call_id: tool_call.id.clone(),
+                    ..result
+                })
+            }
+        }
+    }
+
+    /// Validate and run a tool call without consulting the approval handler
+    async fn run_tool_call(
+        &self,
+        tool_call: &ToolCall,
+        context: &ToolContext,
+    ) -> Result<ToolResult> {
+        if self.disabled.contains(&tool_call.function.name) {
+            return Err(GrokError::ToolExecution(format!(
+                "Tool '{}' is disabled",
+                tool_call.function.name
+            )));
+        }
+        let (ref name, tool) = self.lookup(&tool_call.function.name).ok_or_else(|| {
             GrokError::ToolExecution(format!("Tool '{}' not found", tool_call.function.name))
         })?;
 
-        let args: serde_json::Value = serde_json::from_str(&tool_call.function.arguments)
-            .map_err(|e| GrokError::ToolExecution(format!("Invalid tool arguments: {}", e)))?;
+        let cache_key = if self.lock_cache().options_for(name).is_some() {
+            serde_json::from_str(&tool_call.function.arguments)
+                .ok()
+                .map(|args| canonical_json(&args))
+        } else {
+            None
+        };
+        if let Some(ref key) = cache_key {
+            let cached = self.lock_cache().get(name, key);
+            if let Some(content) = cached {
+                self.lock_metrics()
+                    .entry(name.clone())
+                    .or_insert_with(|| ToolMetrics::new(name))
+                    .cache_hits += 1;
+                return Ok(ToolResult {
+                    tool_call_id: tool_call.id.clone(),
+                    content,
+                    error: None,
+                });
+            }
+        }
+
+        let _permit = match tool.limiter {
+            Some(ref limiter) => limiter.acquire(name).await?,
+            None => None,
+        };
+
+        let started = Instant::now();
+        let invocation = self.invoke(&tool, tool_call, context);
+        #[cfg(feature = "tracing")]
+        let invocation = tracing::Instrument::instrument(
+            invocation,
+            tracing::debug_span!("grok.tool", tool = %name, call_id = %tool_call.id),
+        );
+        let result = invocation.await;
+        self.record_call(name, started.elapsed(), result.as_ref().err());
+
+        if let (Some(key), Ok(result)) = (cache_key, &result) {
+            self.lock_cache().insert(name, key, result.content.clone());
+        }
+        result
+    }
+
+    /// Validate arguments against the tool's schema and run it
+    async fn invoke(
+        &self,
+        tool: &RegisteredTool,
+        tool_call: &ToolCall,
+        context: &ToolContext,
+    ) -> Result<ToolResult> {
+        let args: serde_json::Value =
+            serde_json::from_str(&tool_call.function.arguments).map_err(|e| {
+                GrokError::InvalidToolArguments {
+                    tool: tool_call.function.name.clone(),
+                    message: format!("not valid JSON: {}", e),
+                }
+            })?;
 
         // Validate arguments against the tool's parameter schema
-        let spec = executor.spec();
-        let schema = jsonschema::JSONSchema::compile(&spec.parameters)
+        let schema = tool
+            .schema
+            .as_ref()
             .map_err(|e| GrokError::ToolExecution(format!("Invalid parameter schema: {}", e)))?;
 
         if let Err(errors) = schema.validate(&args) {
             let error_messages: Vec<String> = errors.map(|e| e.to_string()).collect();
-            return Err(GrokError::ToolExecution(format!(
-                "Tool arguments validation failed: {}",
-                error_messages.join(", ")
-            )));
+            return Err(GrokError::InvalidToolArguments {
+                tool: tool_call.function.name.clone(),
+                message: error_messages.join(", "),
+            });
         }
 
-        let result = executor
-            .execute(args)
-            .await
+        let result = match tool.streaming {
+            Some(ref streaming) => {
+                let progress = ProgressSender {
+                    tool_call_id: tool_call.id.clone(),
+                    tool: tool_call.function.name.clone(),
+                    sender: Some(self.progress.clone()),
+                };
+                streaming
+                    .execute_with_context(args, progress, context)
+                    .await
+            }
+            None => tool.executor.execute_with_context(args, context).await,
+        };
+        let result = result
             .map_err(|e| GrokError::ToolExecution(format!("Tool execution failed: {}", e)))?;
 
         let content = serde_json::to_string(&result)
@@ -153,6 +1397,7 @@ impl ToolRegistry {
         Ok(ToolResult {
             tool_call_id: tool_call.id.clone(),
             content,
+            error: None,
         })
     }
 }
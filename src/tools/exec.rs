@@ -0,0 +1,240 @@
+//! Built-in code execution tool
+//!
+//! Snippets run as ordinary subprocesses of the current user. The working
+//! directory, environment, run time and output size are restricted, but
+//! this is not a security sandbox: code can still reach the network and
+//! anything else the user can. Run it inside a container or VM when the
+//! model may be steered by untrusted input.
+
+use crate::error::{GrokError, Result};
+use crate::tools::{ToolExecutor, ToolSpec};
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+use std::process::Stdio;
+use std::time::Duration;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWriteExt};
+
+/// How to run snippets of one language: the program reads the code on stdin
+#[derive(Debug, Clone)]
+struct Interpreter {
+    program: String,
+    args: Vec<String>,
+}
+
+/// Runs code snippets in a subprocess
+#[derive(Debug, Clone)]
+pub struct CodeExecTool {
+    working_dir: PathBuf,
+    interpreters: BTreeMap<String, Interpreter>,
+    env: BTreeMap<String, String>,
+    timeout: Duration,
+    max_output_bytes: usize,
+}
+
+impl CodeExecTool {
+    /// Create the tool, acknowledging that it runs model-written code on this machine
+    ///
+    /// Snippets run in `working_dir`, which must exist, with a cleared
+    /// environment apart from `PATH`. Python (`python3`) and shell (`sh`) are
+    /// available by default, with a 30 second timeout and 64 KiB of output
+    /// per stream.
+    pub fn i_understand_the_risk(working_dir: impl Into<PathBuf>) -> Result<Self> {
+        let working_dir = working_dir.into();
+        let working_dir = std::fs::canonicalize(&working_dir).map_err(|e| {
+            GrokError::InvalidConfig(format!(
+                "Invalid working directory '{}': {}",
+                working_dir.display(),
+                e
+            ))
+        })?;
+        if !working_dir.is_dir() {
+            return Err(GrokError::InvalidConfig(format!(
+                "Working directory '{}' is not a directory",
+                working_dir.display()
+            )));
+        }
+
+        let mut env = BTreeMap::new();
+        if let Ok(path) = std::env::var("PATH") {
+            env.insert("PATH".to_string(), path);
+        }
+
+        Ok(Self {
+            working_dir,
+            interpreters: BTreeMap::new(),
+            env,
+            timeout: Duration::from_secs(30),
+            max_output_bytes: 64 * 1024,
+        }
+        .language("python", "python3", ["-"])
+        .language("sh", "sh", ["-s"]))
+    }
+
+    /// Add or replace a language; `program` must read the snippet from stdin
+    pub fn language<I, S>(
+        mut self,
+        name: impl Into<String>,
+        program: impl Into<String>,
+        args: I,
+    ) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.interpreters.insert(
+            name.into(),
+            Interpreter {
+                program: program.into(),
+                args: args.into_iter().map(Into::into).collect(),
+            },
+        );
+        self
+    }
+
+    /// Remove a language
+    pub fn without_language(mut self, name: &str) -> Self {
+        self.interpreters.remove(name);
+        self
+    }
+
+    /// Set an environment variable for snippets
+    pub fn env(mut self, key: impl Into<String>, value: impl Into<String>) -> Self {
+        self.env.insert(key.into(), value.into());
+        self
+    }
+
+    /// Set how long a snippet may run before it is killed
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Set how many bytes of stdout and of stderr are kept; the rest is dropped
+    pub fn max_output_bytes(mut self, max_output_bytes: usize) -> Self {
+        self.max_output_bytes = max_output_bytes;
+        self
+    }
+
+    async fn run(&self, interpreter: &Interpreter, code: &str) -> Result<serde_json::Value> {
+        let mut child = tokio::process::Command::new(&interpreter.program)
+            .args(&interpreter.args)
+            .current_dir(&self.working_dir)
+            .env_clear()
+            .envs(&self.env)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .kill_on_drop(true)
+            .spawn()
+            .map_err(|e| {
+                GrokError::ToolExecution(format!(
+                    "Failed to start '{}': {}",
+                    interpreter.program, e
+                ))
+            })?;
+
+        let (Some(mut stdin), Some(stdout), Some(stderr)) =
+            (child.stdin.take(), child.stdout.take(), child.stderr.take())
+        else {
+            return Err(GrokError::ToolExecution(
+                "Failed to open subprocess pipes".to_string(),
+            ));
+        };
+
+        let max_output_bytes = self.max_output_bytes;
+        let work = async {
+            let feed = async move {
+                // The snippet may exit without reading all of its input
+                let _ = stdin.write_all(code.as_bytes()).await;
+            };
+            let (_, stdout, stderr, status) = tokio::join!(
+                feed,
+                read_capped(stdout, max_output_bytes),
+                read_capped(stderr, max_output_bytes),
+                child.wait()
+            );
+            (stdout, stderr, status)
+        };
+
+        let Ok((stdout, stderr, status)) = tokio::time::timeout(self.timeout, work).await else {
+            let _ = child.kill().await;
+            return Ok(serde_json::json!({
+                "exit_code": null,
+                "stdout": "",
+                "stderr": format!("Timed out after {:?}", self.timeout),
+                "timed_out": true,
+                "truncated": false,
+            }));
+        };
+
+        let status = status.map_err(|e| {
+            GrokError::ToolExecution(format!("Failed to wait for subprocess: {}", e))
+        })?;
+        let (stdout, stdout_truncated) = stdout?;
+        let (stderr, stderr_truncated) = stderr?;
+
+        Ok(serde_json::json!({
+            "exit_code": status.code(),
+            "stdout": String::from_utf8_lossy(&stdout),
+            "stderr": String::from_utf8_lossy(&stderr),
+            "timed_out": false,
+            "truncated": stdout_truncated || stderr_truncated,
+        }))
+    }
+}
+
+/// Read a stream to the end, keeping at most `max` bytes
+async fn read_capped<R: AsyncRead + Unpin>(mut reader: R, max: usize) -> Result<(Vec<u8>, bool)> {
+    let mut kept = Vec::new();
+    let mut truncated = false;
+    let mut buf = [0u8; 8192];
+    loop {
+        let read = reader.read(&mut buf).await.map_err(|e| {
+            GrokError::ToolExecution(format!("Failed to read subprocess output: {}", e))
+        })?;
+        if read == 0 {
+            return Ok((kept, truncated));
+        }
+        // Keep draining past the cap so the subprocess doesn't block on a full pipe
+        let room = max - kept.len();
+        if read > room {
+            truncated = true;
+        }
+        kept.extend_from_slice(&buf[..read.min(room)]);
+    }
+}
+
+#[async_trait::async_trait]
+impl ToolExecutor for CodeExecTool {
+    async fn execute(&self, args: serde_json::Value) -> Result<serde_json::Value> {
+        let language = args["language"]
+            .as_str()
+            .ok_or_else(|| GrokError::ToolExecution("Missing language".to_string()))?;
+        let code = args["code"]
+            .as_str()
+            .ok_or_else(|| GrokError::ToolExecution("Missing code".to_string()))?;
+        let interpreter = self.interpreters.get(language).ok_or_else(|| {
+            GrokError::ToolExecution(format!("Language '{}' is not available", language))
+        })?;
+
+        self.run(interpreter, code).await
+    }
+
+    fn spec(&self) -> ToolSpec {
+        let languages: Vec<&str> = self.interpreters.keys().map(String::as_str).collect();
+        ToolSpec {
+            name: "execute_code".to_string(),
+            description: "Run a code snippet and return its exit code, stdout and stderr"
+                .to_string(),
+            parameters: serde_json::json!({
+                "type": "object",
+                "properties": {
+                    "language": { "type": "string", "enum": languages, "description": "Language of the snippet" },
+                    "code": { "type": "string", "description": "Source code to run" }
+                },
+                "required": ["language", "code"]
+            }),
+            strict: false,
+        }
+    }
+}
@@ -0,0 +1,428 @@
+//! Built-in file tools confined to a sandbox directory
+
+use crate::error::{GrokError, Result};
+use crate::tools::{ToolExecutor, ToolRegistry, ToolSpec};
+use std::collections::HashSet;
+use std::path::{Component, Path, PathBuf};
+use std::sync::Arc;
+
+/// Most directory entries returned by one `list_directory` call
+const MAX_LIST_ENTRIES: usize = 1000;
+
+/// A directory that file tools are confined to
+///
+/// Paths given by the model are relative to the root. Absolute paths, `..`
+/// components, symlinked files and symlinks resolving outside the root are
+/// rejected.
+#[derive(Debug, Clone)]
+pub struct Sandbox {
+    inner: Arc<SandboxConfig>,
+}
+
+#[derive(Debug)]
+struct SandboxConfig {
+    root: PathBuf,
+    max_read_bytes: u64,
+    max_write_bytes: usize,
+    allowed_extensions: Option<HashSet<String>>,
+    denied_extensions: HashSet<String>,
+    read_only: bool,
+}
+
+impl Sandbox {
+    /// Create a builder for a sandbox rooted at `root`
+    pub fn builder(root: impl Into<PathBuf>) -> SandboxBuilder {
+        SandboxBuilder::new(root)
+    }
+
+    /// The canonical root directory
+    pub fn root(&self) -> &Path {
+        &self.inner.root
+    }
+
+    /// Tool that reads a file
+    pub fn read_tool(&self) -> ReadFileTool {
+        ReadFileTool {
+            sandbox: self.clone(),
+        }
+    }
+
+    /// Tool that creates or overwrites a file
+    pub fn write_tool(&self) -> WriteFileTool {
+        WriteFileTool {
+            sandbox: self.clone(),
+        }
+    }
+
+    /// Tool that lists a directory
+    pub fn list_tool(&self) -> ListDirectoryTool {
+        ListDirectoryTool {
+            sandbox: self.clone(),
+        }
+    }
+
+    /// Register the read and list tools, plus the write tool unless read-only
+    pub fn register_all(&self, registry: &ToolRegistry) {
+        registry.register(self.read_tool());
+        registry.register(self.list_tool());
+        if !self.inner.read_only {
+            registry.register(self.write_tool());
+        }
+    }
+
+    /// Resolve a model-supplied path to its canonical location inside the sandbox
+    ///
+    /// Symlinked directories are followed and must stay inside the root; a
+    /// symlink as the final component, or one that can't be resolved, is rejected.
+    async fn resolve(&self, path: &str) -> Result<PathBuf> {
+        let outside =
+            || GrokError::ToolExecution(format!("Path '{}' is outside the sandbox", path));
+
+        let mut relative = PathBuf::new();
+        for component in Path::new(path).components() {
+            match component {
+                Component::Normal(name) => relative.push(name),
+                Component::CurDir => {}
+                _ => {
+                    return Err(GrokError::ToolExecution(format!(
+                        "Path '{}' must be relative to the sandbox and must not contain '..'",
+                        path
+                    )))
+                }
+            }
+        }
+
+        let full = self.inner.root.join(&relative);
+        if let Ok(metadata) = tokio::fs::symlink_metadata(&full).await {
+            if metadata.file_type().is_symlink() {
+                return Err(GrokError::ToolExecution(format!(
+                    "Path '{}' is a symlink",
+                    path
+                )));
+            }
+        }
+
+        // Canonicalize the deepest existing ancestor, then re-attach the
+        // components that don't exist yet
+        let mut existing = full.as_path();
+        let mut missing = Vec::new();
+        let canonical = loop {
+            match tokio::fs::canonicalize(existing).await {
+                Ok(canonical) => break canonical,
+                // Something is there but can't be resolved, such as a dangling symlink
+                Err(_) if tokio::fs::symlink_metadata(existing).await.is_ok() => {
+                    return Err(outside())
+                }
+                Err(_) => {
+                    missing.extend(existing.file_name());
+                    existing = existing.parent().ok_or_else(outside)?;
+                }
+            }
+        };
+        if !canonical.starts_with(&self.inner.root) {
+            return Err(outside());
+        }
+
+        Ok(missing
+            .into_iter()
+            .rev()
+            .fold(canonical, |resolved, name| resolved.join(name)))
+    }
+
+    /// Check a file path against the extension allow and deny lists
+    fn check_extension(&self, path: &Path) -> Result<()> {
+        let extension = path
+            .extension()
+            .and_then(|ext| ext.to_str())
+            .map(|ext| ext.to_ascii_lowercase());
+
+        let allowed = match (&extension, &self.inner.allowed_extensions) {
+            (Some(ext), _) if self.inner.denied_extensions.contains(ext) => false,
+            (_, None) => true,
+            (Some(ext), Some(allowed)) => allowed.contains(ext),
+            (None, Some(_)) => false,
+        };
+
+        if allowed {
+            Ok(())
+        } else {
+            Err(GrokError::ToolExecution(format!(
+                "Files with extension '{}' are not allowed",
+                extension.unwrap_or_default()
+            )))
+        }
+    }
+}
+
+fn path_arg(args: &serde_json::Value) -> Result<&str> {
+    args["path"]
+        .as_str()
+        .ok_or_else(|| GrokError::ToolExecution("Missing path".to_string()))
+}
+
+fn io_error(action: &str, path: &str, e: std::io::Error) -> GrokError {
+    GrokError::ToolExecution(format!("Failed to {} '{}': {}", action, path, e))
+}
+
+/// Reads a text file from the sandbox
+#[derive(Debug, Clone)]
+pub struct ReadFileTool {
+    sandbox: Sandbox,
+}
+
+#[async_trait::async_trait]
+impl ToolExecutor for ReadFileTool {
+    async fn execute(&self, args: serde_json::Value) -> Result<serde_json::Value> {
+        let path = path_arg(&args)?;
+        let full = self.sandbox.resolve(path).await?;
+        self.sandbox.check_extension(&full)?;
+
+        let metadata = tokio::fs::metadata(&full)
+            .await
+            .map_err(|e| io_error("read", path, e))?;
+        if metadata.len() > self.sandbox.inner.max_read_bytes {
+            return Err(GrokError::ToolExecution(format!(
+                "'{}' is {} bytes, over the {} byte read limit",
+                path,
+                metadata.len(),
+                self.sandbox.inner.max_read_bytes
+            )));
+        }
+
+        let bytes = tokio::fs::read(&full)
+            .await
+            .map_err(|e| io_error("read", path, e))?;
+        Ok(serde_json::json!({
+            "path": path,
+            "content": String::from_utf8_lossy(&bytes),
+        }))
+    }
+
+    fn spec(&self) -> ToolSpec {
+        ToolSpec {
+            name: "read_file".to_string(),
+            description: "Read a text file from the workspace".to_string(),
+            parameters: serde_json::json!({
+                "type": "object",
+                "properties": {
+                    "path": { "type": "string", "description": "Path relative to the workspace root" }
+                },
+                "required": ["path"]
+            }),
+            strict: false,
+        }
+    }
+}
+
+/// Creates or overwrites a file in the sandbox
+#[derive(Debug, Clone)]
+pub struct WriteFileTool {
+    sandbox: Sandbox,
+}
+
+#[async_trait::async_trait]
+impl ToolExecutor for WriteFileTool {
+    async fn execute(&self, args: serde_json::Value) -> Result<serde_json::Value> {
+        if self.sandbox.inner.read_only {
+            return Err(GrokError::ToolExecution(
+                "The sandbox is read-only".to_string(),
+            ));
+        }
+
+        let path = path_arg(&args)?;
+        let content = args["content"]
+            .as_str()
+            .ok_or_else(|| GrokError::ToolExecution("Missing content".to_string()))?;
+        if content.len() > self.sandbox.inner.max_write_bytes {
+            return Err(GrokError::ToolExecution(format!(
+                "Content is {} bytes, over the {} byte write limit",
+                content.len(),
+                self.sandbox.inner.max_write_bytes
+            )));
+        }
+
+        let full = self.sandbox.resolve(path).await?;
+        self.sandbox.check_extension(&full)?;
+
+        if let Some(parent) = full.parent() {
+            tokio::fs::create_dir_all(parent)
+                .await
+                .map_err(|e| io_error("create directory for", path, e))?;
+        }
+        // Resolve again in case the path changed while its directories were created
+        let full = self.sandbox.resolve(path).await?;
+        self.sandbox.check_extension(&full)?;
+        tokio::fs::write(&full, content)
+            .await
+            .map_err(|e| io_error("write", path, e))?;
+
+        Ok(serde_json::json!({ "path": path, "bytes_written": content.len() }))
+    }
+
+    fn spec(&self) -> ToolSpec {
+        ToolSpec {
+            name: "write_file".to_string(),
+            description: "Create or overwrite a text file in the workspace".to_string(),
+            parameters: serde_json::json!({
+                "type": "object",
+                "properties": {
+                    "path": { "type": "string", "description": "Path relative to the workspace root" },
+                    "content": { "type": "string", "description": "Full new content of the file" }
+                },
+                "required": ["path", "content"]
+            }),
+            strict: false,
+        }
+    }
+}
+
+/// Lists the entries of a directory in the sandbox
+#[derive(Debug, Clone)]
+pub struct ListDirectoryTool {
+    sandbox: Sandbox,
+}
+
+#[async_trait::async_trait]
+impl ToolExecutor for ListDirectoryTool {
+    async fn execute(&self, args: serde_json::Value) -> Result<serde_json::Value> {
+        let path = args["path"].as_str().unwrap_or(".");
+        let full = self.sandbox.resolve(path).await?;
+
+        let mut dir = tokio::fs::read_dir(&full)
+            .await
+            .map_err(|e| io_error("list", path, e))?;
+        let mut entries = Vec::new();
+        let mut truncated = false;
+        while let Some(entry) = dir
+            .next_entry()
+            .await
+            .map_err(|e| io_error("list", path, e))?
+        {
+            if entries.len() == MAX_LIST_ENTRIES {
+                truncated = true;
+                break;
+            }
+            let metadata = entry
+                .metadata()
+                .await
+                .map_err(|e| io_error("list", path, e))?;
+            entries.push(serde_json::json!({
+                "name": entry.file_name().to_string_lossy(),
+                "type": if metadata.is_dir() { "directory" } else { "file" },
+                "size": metadata.len(),
+            }));
+        }
+
+        entries.sort_by(|a, b| a["name"].as_str().cmp(&b["name"].as_str()));
+        Ok(serde_json::json!({ "path": path, "entries": entries, "truncated": truncated }))
+    }
+
+    fn spec(&self) -> ToolSpec {
+        ToolSpec {
+            name: "list_directory".to_string(),
+            description: "List the files and directories in a workspace directory".to_string(),
+            parameters: serde_json::json!({
+                "type": "object",
+                "properties": {
+                    "path": { "type": "string", "description": "Directory relative to the workspace root, '.' by default" }
+                }
+            }),
+            strict: false,
+        }
+    }
+}
+
+/// Builder for a `Sandbox`
+#[derive(Debug, Clone)]
+pub struct SandboxBuilder {
+    root: PathBuf,
+    max_read_bytes: u64,
+    max_write_bytes: usize,
+    allowed_extensions: Option<HashSet<String>>,
+    denied_extensions: HashSet<String>,
+    read_only: bool,
+}
+
+impl SandboxBuilder {
+    /// Create a builder with 1 MiB read and write limits
+    pub fn new(root: impl Into<PathBuf>) -> Self {
+        Self {
+            root: root.into(),
+            max_read_bytes: 1024 * 1024,
+            max_write_bytes: 1024 * 1024,
+            allowed_extensions: None,
+            denied_extensions: HashSet::new(),
+            read_only: false,
+        }
+    }
+
+    /// Set the largest file that can be read
+    pub fn max_read_bytes(mut self, max_read_bytes: u64) -> Self {
+        self.max_read_bytes = max_read_bytes;
+        self
+    }
+
+    /// Set the largest content that can be written
+    pub fn max_write_bytes(mut self, max_write_bytes: usize) -> Self {
+        self.max_write_bytes = max_write_bytes;
+        self
+    }
+
+    /// Only allow files with this extension (without the dot); may be called repeatedly
+    pub fn allow_extension(mut self, extension: impl Into<String>) -> Self {
+        self.allowed_extensions
+            .get_or_insert_with(HashSet::new)
+            .insert(
+                extension
+                    .into()
+                    .trim_start_matches('.')
+                    .to_ascii_lowercase(),
+            );
+        self
+    }
+
+    /// Never allow files with this extension (without the dot)
+    pub fn deny_extension(mut self, extension: impl Into<String>) -> Self {
+        self.denied_extensions.insert(
+            extension
+                .into()
+                .trim_start_matches('.')
+                .to_ascii_lowercase(),
+        );
+        self
+    }
+
+    /// Reject all writes
+    pub fn read_only(mut self, read_only: bool) -> Self {
+        self.read_only = read_only;
+        self
+    }
+
+    /// Build the sandbox; the root directory must already exist
+    pub fn build(self) -> Result<Sandbox> {
+        let root = std::fs::canonicalize(&self.root).map_err(|e| {
+            GrokError::InvalidConfig(format!(
+                "Invalid sandbox root '{}': {}",
+                self.root.display(),
+                e
+            ))
+        })?;
+        if !root.is_dir() {
+            return Err(GrokError::InvalidConfig(format!(
+                "Sandbox root '{}' is not a directory",
+                root.display()
+            )));
+        }
+
+        Ok(Sandbox {
+            inner: Arc::new(SandboxConfig {
+                root,
+                max_read_bytes: self.max_read_bytes,
+                max_write_bytes: self.max_write_bytes,
+                allowed_extensions: self.allowed_extensions,
+                denied_extensions: self.denied_extensions,
+                read_only: self.read_only,
+            }),
+        })
+    }
+}
@@ -0,0 +1,294 @@
+//! Built-in HTTP fetch tool
+
+use crate::error::{GrokError, Result};
+use crate::tools::{ToolExecutor, ToolSpec};
+use reqwest::{Client as HttpClient, Method, Url};
+use std::sync::Arc;
+use std::time::Duration;
+
+/// Fetches web pages for the model, restricted to an allowlist of domains
+///
+/// Redirects are only followed within the allowlist, responses are cut off
+/// at a size cap, and HTML is converted to plain text by default.
+#[derive(Debug, Clone)]
+pub struct HttpFetchTool {
+    http_client: HttpClient,
+    allowed_domains: Arc<Vec<String>>,
+    allow_any_domain: bool,
+    allow_post: bool,
+    max_response_bytes: usize,
+    html_to_text: bool,
+}
+
+impl HttpFetchTool {
+    /// Create a builder for configuring the tool
+    pub fn builder() -> HttpFetchToolBuilder {
+        HttpFetchToolBuilder::new()
+    }
+
+    fn is_allowed(&self, url: &Url) -> bool {
+        if !matches!(url.scheme(), "http" | "https") {
+            return false;
+        }
+        self.allow_any_domain
+            || url
+                .host_str()
+                .is_some_and(|host| domain_allowed(&self.allowed_domains, host))
+    }
+
+    /// Read the response body up to the size cap, reporting whether it was cut off
+    async fn read_capped(&self, mut response: reqwest::Response) -> Result<(Vec<u8>, bool)> {
+        let mut body = Vec::new();
+        while let Some(chunk) = response.chunk().await? {
+            let remaining = self.max_response_bytes - body.len();
+            if chunk.len() > remaining {
+                body.extend_from_slice(&chunk[..remaining]);
+                return Ok((body, true));
+            }
+            body.extend_from_slice(&chunk);
+        }
+        Ok((body, false))
+    }
+}
+
+/// Whether `host` is one of `domains` or a subdomain of one
+fn domain_allowed(domains: &[String], host: &str) -> bool {
+    let host = host.trim_end_matches('.').to_ascii_lowercase();
+    domains.iter().any(|domain| {
+        host == *domain
+            || host
+                .strip_suffix(domain.as_str())
+                .is_some_and(|prefix| prefix.ends_with('.'))
+    })
+}
+
+#[async_trait::async_trait]
+impl ToolExecutor for HttpFetchTool {
+    async fn execute(&self, args: serde_json::Value) -> Result<serde_json::Value> {
+        let url = args["url"]
+            .as_str()
+            .ok_or_else(|| GrokError::ToolExecution("Missing url".to_string()))?;
+        let url =
+            Url::parse(url).map_err(|e| GrokError::ToolExecution(format!("Invalid url: {}", e)))?;
+        if !self.is_allowed(&url) {
+            return Err(GrokError::ToolExecution(format!(
+                "Fetching '{}' is not allowed",
+                url
+            )));
+        }
+
+        let method = match args["method"]
+            .as_str()
+            .unwrap_or("GET")
+            .to_ascii_uppercase()
+            .as_str()
+        {
+            "GET" => Method::GET,
+            "POST" if self.allow_post => Method::POST,
+            other => {
+                return Err(GrokError::ToolExecution(format!(
+                    "Method '{}' is not allowed",
+                    other
+                )))
+            }
+        };
+
+        let mut request = self.http_client.request(method, url);
+        if let Some(headers) = args["headers"].as_object() {
+            for (name, value) in headers {
+                if let Some(value) = value.as_str() {
+                    request = request.header(name.as_str(), value);
+                }
+            }
+        }
+        if let Some(body) = args["body"].as_str() {
+            request = request.body(body.to_string());
+        }
+
+        let response = request.send().await?;
+        let status = response.status().as_u16();
+        let final_url = response.url().to_string();
+        let content_type = response
+            .headers()
+            .get(reqwest::header::CONTENT_TYPE)
+            .and_then(|value| value.to_str().ok())
+            .unwrap_or_default()
+            .to_string();
+
+        let (bytes, truncated) = self.read_capped(response).await?;
+        let body = if self.html_to_text && content_type.contains("html") {
+            html2text::from_read(bytes.as_slice(), 100)
+                .map_err(|e| GrokError::ToolExecution(format!("Failed to convert HTML: {}", e)))?
+        } else {
+            String::from_utf8_lossy(&bytes).into_owned()
+        };
+
+        Ok(serde_json::json!({
+            "status": status,
+            "url": final_url,
+            "content_type": content_type,
+            "body": body,
+            "truncated": truncated,
+        }))
+    }
+
+    fn spec(&self) -> ToolSpec {
+        let methods = if self.allow_post {
+            vec!["GET", "POST"]
+        } else {
+            vec!["GET"]
+        };
+        ToolSpec {
+            name: "http_fetch".to_string(),
+            description: "Fetch a URL over HTTP and return the response body as text".to_string(),
+            parameters: serde_json::json!({
+                "type": "object",
+                "properties": {
+                    "url": { "type": "string", "description": "Absolute http(s) URL to fetch" },
+                    "method": { "type": "string", "enum": methods, "description": "HTTP method, GET by default" },
+                    "headers": {
+                        "type": "object",
+                        "additionalProperties": { "type": "string" },
+                        "description": "Request headers"
+                    },
+                    "body": { "type": "string", "description": "Request body for POST" }
+                },
+                "required": ["url"]
+            }),
+            strict: false,
+        }
+    }
+}
+
+/// Builder for an `HttpFetchTool`
+#[derive(Debug, Clone)]
+pub struct HttpFetchToolBuilder {
+    allowed_domains: Vec<String>,
+    allow_any_domain: bool,
+    allow_post: bool,
+    max_response_bytes: usize,
+    timeout: Duration,
+    max_redirects: usize,
+    html_to_text: bool,
+    user_agent: Option<String>,
+}
+
+impl HttpFetchToolBuilder {
+    /// Create a builder with conservative defaults: GET only, 1 MiB, 10 second timeout
+    pub fn new() -> Self {
+        Self {
+            allowed_domains: Vec::new(),
+            allow_any_domain: false,
+            allow_post: false,
+            max_response_bytes: 1024 * 1024,
+            timeout: Duration::from_secs(10),
+            max_redirects: 5,
+            html_to_text: true,
+            user_agent: None,
+        }
+    }
+
+    /// Allow fetching from a domain and its subdomains
+    pub fn allow_domain(mut self, domain: impl Into<String>) -> Self {
+        self.allowed_domains
+            .push(domain.into().trim_end_matches('.').to_ascii_lowercase());
+        self
+    }
+
+    /// Allow fetching from any domain
+    ///
+    /// Only use this when the model cannot be steered by untrusted input.
+    pub fn allow_any_domain(mut self) -> Self {
+        self.allow_any_domain = true;
+        self
+    }
+
+    /// Allow POST requests as well as GET
+    pub fn allow_post(mut self, allow: bool) -> Self {
+        self.allow_post = allow;
+        self
+    }
+
+    /// Set the maximum number of response bytes read; the rest is dropped
+    pub fn max_response_bytes(mut self, max_response_bytes: usize) -> Self {
+        self.max_response_bytes = max_response_bytes;
+        self
+    }
+
+    /// Set the timeout for the whole request
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Set the maximum number of redirects followed
+    pub fn max_redirects(mut self, max_redirects: usize) -> Self {
+        self.max_redirects = max_redirects;
+        self
+    }
+
+    /// Convert HTML responses to plain text (on by default)
+    pub fn html_to_text(mut self, enabled: bool) -> Self {
+        self.html_to_text = enabled;
+        self
+    }
+
+    /// Set the user agent sent with requests
+    pub fn user_agent(mut self, user_agent: impl Into<String>) -> Self {
+        self.user_agent = Some(user_agent.into());
+        self
+    }
+
+    /// Build the tool
+    pub fn build(self) -> Result<HttpFetchTool> {
+        if self.allowed_domains.is_empty() && !self.allow_any_domain {
+            return Err(GrokError::InvalidConfig(
+                "HttpFetchTool needs at least one allowed domain".to_string(),
+            ));
+        }
+
+        let allowed_domains = Arc::new(self.allowed_domains);
+        let allow_any_domain = self.allow_any_domain;
+        let max_redirects = self.max_redirects;
+        let redirect_domains = allowed_domains.clone();
+
+        // Redirects must not escape the allowlist
+        let redirect_policy = reqwest::redirect::Policy::custom(move |attempt| {
+            let allowed = allow_any_domain
+                || attempt
+                    .url()
+                    .host_str()
+                    .is_some_and(|host| domain_allowed(&redirect_domains, host));
+            if attempt.previous().len() >= max_redirects {
+                attempt.error("too many redirects")
+            } else if !allowed || !matches!(attempt.url().scheme(), "http" | "https") {
+                attempt.stop()
+            } else {
+                attempt.follow()
+            }
+        });
+
+        let mut http_client_builder = HttpClient::builder()
+            .timeout(self.timeout)
+            .redirect(redirect_policy);
+        if let Some(user_agent) = self.user_agent {
+            http_client_builder = http_client_builder.user_agent(user_agent);
+        }
+        let http_client = http_client_builder.build().map_err(GrokError::Http)?;
+
+        Ok(HttpFetchTool {
+            http_client,
+            allowed_domains,
+            allow_any_domain,
+            allow_post: self.allow_post,
+            max_response_bytes: self.max_response_bytes,
+            html_to_text: self.html_to_text,
+        })
+    }
+}
+
+impl Default for HttpFetchToolBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
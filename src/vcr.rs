@@ -0,0 +1,207 @@
+//! Record and replay API interactions
+//!
+//! A `Vcr` in record mode sends requests as usual and writes each request and
+//! response, including streamed SSE bodies, to a JSON fixture file. In replay
+//! mode it answers requests from that file without touching the network, so
+//! tests and demos run deterministically and without an API key.
+
+use crate::error::{GrokError, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::future::Future;
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, PoisonError};
+
+/// A recorded request and the response it received
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Interaction {
+    /// API endpoint, such as `/chat/completions`
+    pub endpoint: String,
+    /// Request body
+    pub request: serde_json::Value,
+    /// HTTP status of the response
+    pub status: u16,
+    /// Response headers
+    #[serde(default)]
+    pub headers: BTreeMap<String, String>,
+    /// Response body as received; SSE transcripts for streamed requests
+    pub body: String,
+}
+
+/// The contents of a fixture file
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Cassette {
+    /// Interactions in the order they were recorded
+    pub interactions: Vec<Interaction>,
+}
+
+#[derive(Debug)]
+enum Mode {
+    Record,
+    /// Which interactions have already been served
+    Replay(Vec<bool>),
+}
+
+/// Records API interactions to a fixture file, or replays them from one
+#[derive(Debug)]
+pub struct Vcr {
+    path: PathBuf,
+    mode: Mutex<(Mode, Cassette)>,
+}
+
+impl Vcr {
+    /// Send requests to the API and record them to `path`, replacing its contents
+    ///
+    /// Streamed responses are read to the end before being recorded, so they
+    /// arrive all at once while recording.
+    pub fn record(path: impl Into<PathBuf>) -> Self {
+        Self {
+            path: path.into(),
+            mode: Mutex::new((Mode::Record, Cassette::default())),
+        }
+    }
+
+    /// Answer requests from the interactions recorded in `path`
+    ///
+    /// Each request is served the first unused interaction with the same
+    /// endpoint and body. Requests with no recording fail with `InvalidConfig`.
+    pub fn replay(path: impl Into<PathBuf>) -> Result<Self> {
+        let path = path.into();
+        let json = std::fs::read_to_string(&path).map_err(|e| {
+            GrokError::InvalidConfig(format!(
+                "Failed to read cassette '{}': {}",
+                path.display(),
+                e
+            ))
+        })?;
+        let cassette: Cassette = serde_json::from_str(&json).map_err(|e| {
+            GrokError::InvalidConfig(format!(
+                "Failed to parse cassette '{}': {}",
+                path.display(),
+                e
+            ))
+        })?;
+        Ok(Self::from_cassette(path, cassette))
+    }
+
+    /// Answer requests from an in-memory cassette
+    pub fn from_cassette(path: impl Into<PathBuf>, cassette: Cassette) -> Self {
+        let used = vec![false; cassette.interactions.len()];
+        Self {
+            path: path.into(),
+            mode: Mutex::new((Mode::Replay(used), cassette)),
+        }
+    }
+
+    /// The fixture file
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// The interactions recorded or loaded so far
+    pub fn cassette(&self) -> Cassette {
+        self.lock().1.clone()
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, (Mode, Cassette)> {
+        self.mode.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Run `send` and record its response, or answer from the cassette without running it
+    pub(crate) async fn exchange<F>(
+        &self,
+        endpoint: &str,
+        request: serde_json::Value,
+        send: F,
+    ) -> Result<reqwest::Response>
+    where
+        F: Future<Output = Result<reqwest::Response>>,
+    {
+        if let Some(interaction) = self.take_recorded(endpoint, &request)? {
+            return response_from(&interaction);
+        }
+
+        let response = send.await?;
+        let status = response.status().as_u16();
+        // Framing headers describe the original connection, not the stored body
+        let headers = response
+            .headers()
+            .iter()
+            .filter(|(name, _)| {
+                !matches!(
+                    name.as_str(),
+                    "content-length" | "transfer-encoding" | "connection" | "content-encoding"
+                )
+            })
+            .filter_map(|(name, value)| Some((name.to_string(), value.to_str().ok()?.to_string())))
+            .collect();
+        let body = response.text().await.map_err(GrokError::Http)?;
+
+        let interaction = Interaction {
+            endpoint: endpoint.to_string(),
+            request,
+            status,
+            headers,
+            body,
+        };
+        let response = response_from(&interaction)?;
+        self.save(interaction)?;
+        Ok(response)
+    }
+
+    /// Find the recording for a request in replay mode; `None` in record mode
+    fn take_recorded(
+        &self,
+        endpoint: &str,
+        request: &serde_json::Value,
+    ) -> Result<Option<Interaction>> {
+        let mut guard = self.lock();
+        let (ref mut mode, ref cassette) = *guard;
+        let Mode::Replay(used) = mode else {
+            return Ok(None);
+        };
+
+        let index = cassette
+            .interactions
+            .iter()
+            .enumerate()
+            .find(|(i, interaction)| {
+                !used[*i] && interaction.endpoint == endpoint && interaction.request == *request
+            })
+            .map(|(i, _)| i)
+            .ok_or_else(|| {
+                GrokError::InvalidConfig(format!(
+                    "No recorded response for {} in cassette '{}'",
+                    endpoint,
+                    self.path.display()
+                ))
+            })?;
+        used[index] = true;
+        Ok(Some(cassette.interactions[index].clone()))
+    }
+
+    fn save(&self, interaction: Interaction) -> Result<()> {
+        let mut guard = self.lock();
+        guard.1.interactions.push(interaction);
+        let json = serde_json::to_string_pretty(&guard.1)?;
+        std::fs::write(&self.path, json).map_err(|e| {
+            GrokError::InvalidConfig(format!(
+                "Failed to write cassette '{}': {}",
+                self.path.display(),
+                e
+            ))
+        })
+    }
+}
+
+/// Rebuild a response from a recording
+fn response_from(interaction: &Interaction) -> Result<reqwest::Response> {
+    let mut builder = http::Response::builder().status(interaction.status);
+    for (name, value) in &interaction.headers {
+        builder = builder.header(name.as_str(), value.as_str());
+    }
+    let response = builder
+        .body(interaction.body.clone())
+        .map_err(|e| GrokError::InvalidConfig(format!("Invalid recorded response: {}", e)))?;
+    Ok(reqwest::Response::from(response))
+}
@@ -0,0 +1,472 @@
+//! Pluggable storage for embeddings
+//!
+//! `VectorStore` is the storage behind semantic search and the `rag`
+//! `Retriever`. `MemoryVectorStore` and `SqliteStorage` are built in; the
+//! `qdrant` and `pgvector` features add adapters for those databases.
+
+use crate::error::Result;
+use async_trait::async_trait;
+use std::fmt;
+use tokio::sync::RwLock;
+
+/// Metadata stored with a record, such as `{"source": "handbook.md"}`
+pub type Metadata = serde_json::Map<String, serde_json::Value>;
+
+/// A text, its embedding and its metadata
+#[derive(Debug, Clone, PartialEq)]
+pub struct VectorRecord {
+    /// Unique ID; upserting a record with the same ID replaces it
+    pub id: String,
+    /// The text that was embedded
+    pub text: String,
+    /// Embedding of `text`
+    pub embedding: Vec<f32>,
+    /// Fields to filter on
+    pub metadata: Metadata,
+}
+
+/// A record found by a query, with its similarity to the query
+#[derive(Debug, Clone, PartialEq)]
+pub struct VectorMatch {
+    /// ID of the matching record
+    pub id: String,
+    /// Its text
+    pub text: String,
+    /// Its metadata
+    pub metadata: Metadata,
+    /// Cosine similarity to the query, from -1 to 1
+    pub score: f32,
+}
+
+/// Selects records whose metadata has all of the given values
+///
+/// An empty filter selects every record.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct MetadataFilter {
+    /// Required metadata values by key
+    pub equals: Metadata,
+}
+
+impl MetadataFilter {
+    /// Create a filter selecting every record
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Also require `key` to equal `value`
+    pub fn eq(mut self, key: impl Into<String>, value: impl Into<serde_json::Value>) -> Self {
+        self.equals.insert(key.into(), value.into());
+        self
+    }
+
+    /// Whether `metadata` has every required value
+    pub fn matches(&self, metadata: &Metadata) -> bool {
+        self.equals
+            .iter()
+            .all(|(key, value)| metadata.get(key) == Some(value))
+    }
+}
+
+/// Stores embeddings and finds the ones nearest to a query
+#[async_trait]
+pub trait VectorStore: fmt::Debug + Send + Sync {
+    /// Insert records, replacing any with the same ID
+    async fn upsert(&self, records: Vec<VectorRecord>) -> Result<()>;
+
+    /// The `k` records most similar to `embedding` that match `filter`, best first
+    async fn query(
+        &self,
+        embedding: &[f32],
+        k: usize,
+        filter: Option<&MetadataFilter>,
+    ) -> Result<Vec<VectorMatch>>;
+
+    /// Delete every record matching `filter`
+    async fn delete(&self, filter: &MetadataFilter) -> Result<()>;
+}
+
+/// A vector store held in memory, searched exhaustively
+#[derive(Debug, Default)]
+pub struct MemoryVectorStore {
+    records: RwLock<Vec<VectorRecord>>,
+}
+
+impl MemoryVectorStore {
+    /// Create an empty store
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Number of records in the store
+    pub async fn len(&self) -> usize {
+        self.records.read().await.len()
+    }
+
+    /// Whether the store has no records
+    pub async fn is_empty(&self) -> bool {
+        self.records.read().await.is_empty()
+    }
+}
+
+#[async_trait]
+impl VectorStore for MemoryVectorStore {
+    async fn upsert(&self, records: Vec<VectorRecord>) -> Result<()> {
+        let mut stored = self.records.write().await;
+        for record in records {
+            match stored.iter_mut().find(|existing| existing.id == record.id) {
+                Some(existing) => *existing = record,
+                None => stored.push(record),
+            }
+        }
+        Ok(())
+    }
+
+    async fn query(
+        &self,
+        embedding: &[f32],
+        k: usize,
+        filter: Option<&MetadataFilter>,
+    ) -> Result<Vec<VectorMatch>> {
+        let records = self.records.read().await;
+        Ok(nearest(records.iter().cloned(), embedding, k, filter))
+    }
+
+    async fn delete(&self, filter: &MetadataFilter) -> Result<()> {
+        self.records
+            .write()
+            .await
+            .retain(|record| !filter.matches(&record.metadata));
+        Ok(())
+    }
+}
+
+/// Exhaustive search: score every matching record and keep the best `k`
+pub(crate) fn nearest(
+    records: impl Iterator<Item = VectorRecord>,
+    embedding: &[f32],
+    k: usize,
+    filter: Option<&MetadataFilter>,
+) -> Vec<VectorMatch> {
+    let mut matches: Vec<VectorMatch> = records
+        .filter(|record| filter.is_none_or(|filter| filter.matches(&record.metadata)))
+        .map(|record| VectorMatch {
+            score: cosine_similarity(embedding, &record.embedding),
+            id: record.id,
+            text: record.text,
+            metadata: record.metadata,
+        })
+        .collect();
+    matches.sort_by(|a, b| b.score.total_cmp(&a.score));
+    matches.truncate(k);
+    matches
+}
+
+/// Cosine similarity of two vectors; 0 if either is zero or their lengths differ
+pub fn cosine_similarity(a: &[f32], b: &[f32]) -> f32 {
+    if a.len() != b.len() {
+        return 0.0;
+    }
+    let (mut dot, mut norm_a, mut norm_b) = (0.0f32, 0.0f32, 0.0f32);
+    for (x, y) in a.iter().zip(b) {
+        dot += x * y;
+        norm_a += x * x;
+        norm_b += y * y;
+    }
+    if norm_a == 0.0 || norm_b == 0.0 {
+        return 0.0;
+    }
+    dot / (norm_a.sqrt() * norm_b.sqrt())
+}
+
+/// `VectorStore` backed by a Qdrant collection
+#[cfg(feature = "qdrant")]
+pub mod qdrant {
+    use super::{Metadata, MetadataFilter, VectorMatch, VectorRecord, VectorStore};
+    use crate::error::{GrokError, Result};
+    use ::qdrant_client::qdrant::{
+        Condition, CreateCollectionBuilder, DeletePointsBuilder, Distance, Filter, PointStruct,
+        QueryPointsBuilder, UpsertPointsBuilder, VectorParamsBuilder,
+    };
+    use ::qdrant_client::{Payload, Qdrant};
+    use async_trait::async_trait;
+    use std::fmt;
+
+    /// Payload key holding the record ID, since Qdrant point IDs must be UUIDs
+    const ID_KEY: &str = "_id";
+    /// Payload key holding the record text
+    const TEXT_KEY: &str = "_text";
+
+    /// Records stored as points of one collection, with metadata as payload fields
+    #[derive(Clone)]
+    pub struct QdrantStore {
+        client: Qdrant,
+        collection: String,
+    }
+
+    impl fmt::Debug for QdrantStore {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            f.debug_struct("QdrantStore")
+                .field("collection", &self.collection)
+                .finish_non_exhaustive()
+        }
+    }
+
+    impl QdrantStore {
+        /// Store records in `collection`
+        pub fn new(client: Qdrant, collection: impl Into<String>) -> Self {
+            Self {
+                client,
+                collection: collection.into(),
+            }
+        }
+
+        /// Create the collection for `dimensions`-long vectors with cosine distance if it doesn't exist
+        pub async fn ensure_collection(&self, dimensions: u64) -> Result<()> {
+            let exists = self
+                .client
+                .collection_exists(&self.collection)
+                .await
+                .map_err(|e| GrokError::Retrieval(format!("Failed to check collection: {}", e)))?;
+            if !exists {
+                self.client
+                    .create_collection(
+                        CreateCollectionBuilder::new(&self.collection)
+                            .vectors_config(VectorParamsBuilder::new(dimensions, Distance::Cosine)),
+                    )
+                    .await
+                    .map_err(|e| {
+                        GrokError::Retrieval(format!("Failed to create collection: {}", e))
+                    })?;
+            }
+            Ok(())
+        }
+    }
+
+    /// A stable UUID for a record ID
+    fn point_id(id: &str) -> String {
+        uuid::Uuid::new_v5(&uuid::Uuid::NAMESPACE_OID, id.as_bytes()).to_string()
+    }
+
+    fn qdrant_filter(filter: &MetadataFilter) -> Result<Filter> {
+        let conditions = filter
+            .equals
+            .iter()
+            .map(|(key, value)| match value {
+                serde_json::Value::String(s) => Ok(Condition::matches(key.as_str(), s.clone())),
+                serde_json::Value::Bool(b) => Ok(Condition::matches(key.as_str(), *b)),
+                serde_json::Value::Number(n) if n.is_i64() => Ok(Condition::matches(
+                    key.as_str(),
+                    n.as_i64().unwrap_or_default(),
+                )),
+                _ => Err(GrokError::InvalidConfig(format!(
+                    "Qdrant can only filter '{}' on a string, boolean or integer",
+                    key
+                ))),
+            })
+            .collect::<Result<Vec<_>>>()?;
+        Ok(Filter::must(conditions))
+    }
+
+    #[async_trait]
+    impl VectorStore for QdrantStore {
+        async fn upsert(&self, records: Vec<VectorRecord>) -> Result<()> {
+            let points: Vec<PointStruct> = records
+                .into_iter()
+                .map(|record| {
+                    let mut payload = record.metadata;
+                    payload.insert(ID_KEY.to_string(), record.id.clone().into());
+                    payload.insert(TEXT_KEY.to_string(), record.text.into());
+                    PointStruct::new(
+                        point_id(&record.id),
+                        record.embedding,
+                        Payload::from(payload),
+                    )
+                })
+                .collect();
+            self.client
+                .upsert_points(UpsertPointsBuilder::new(&self.collection, points).wait(true))
+                .await
+                .map_err(|e| GrokError::Retrieval(format!("Failed to upsert points: {}", e)))?;
+            Ok(())
+        }
+
+        async fn query(
+            &self,
+            embedding: &[f32],
+            k: usize,
+            filter: Option<&MetadataFilter>,
+        ) -> Result<Vec<VectorMatch>> {
+            let mut request = QueryPointsBuilder::new(&self.collection)
+                .query(embedding.to_vec())
+                .limit(k as u64)
+                .with_payload(true);
+            if let Some(filter) = filter {
+                request = request.filter(qdrant_filter(filter)?);
+            }
+            let response = self
+                .client
+                .query(request)
+                .await
+                .map_err(|e| GrokError::Retrieval(format!("Failed to query points: {}", e)))?;
+
+            Ok(response
+                .result
+                .into_iter()
+                .map(|point| {
+                    let mut metadata: Metadata = point
+                        .payload
+                        .into_iter()
+                        .map(|(key, value)| (key, value.into_json()))
+                        .collect();
+                    let text = metadata.remove(TEXT_KEY);
+                    let id = metadata.remove(ID_KEY);
+                    VectorMatch {
+                        id: id
+                            .and_then(|id| id.as_str().map(str::to_string))
+                            .unwrap_or_default(),
+                        text: text
+                            .and_then(|text| text.as_str().map(str::to_string))
+                            .unwrap_or_default(),
+                        metadata,
+                        score: point.score,
+                    }
+                })
+                .collect())
+        }
+
+        async fn delete(&self, filter: &MetadataFilter) -> Result<()> {
+            self.client
+                .delete_points(
+                    DeletePointsBuilder::new(&self.collection)
+                        .points(qdrant_filter(filter)?)
+                        .wait(true),
+                )
+                .await
+                .map_err(|e| GrokError::Retrieval(format!("Failed to delete points: {}", e)))?;
+            Ok(())
+        }
+    }
+}
+
+/// `VectorStore` backed by a PostgreSQL table with the pgvector extension
+#[cfg(feature = "pgvector")]
+pub mod pgvector {
+    use super::{Metadata, MetadataFilter, VectorMatch, VectorRecord, VectorStore};
+    use crate::error::{GrokError, Result};
+    use ::pgvector::Vector;
+    use async_trait::async_trait;
+
+    /// Records stored as rows of `(id, text, metadata JSONB, embedding vector)`
+    #[derive(Debug)]
+    pub struct PgVectorStore {
+        client: tokio_postgres::Client,
+        table: String,
+    }
+
+    impl PgVectorStore {
+        /// Store records in `table`, creating it and the `vector` extension if needed
+        pub async fn new(
+            client: tokio_postgres::Client,
+            table: impl Into<String>,
+            dimensions: usize,
+        ) -> Result<Self> {
+            let table = table.into();
+            let valid =
+                table.chars().all(|c| c.is_ascii_alphanumeric() || c == '_') && !table.is_empty();
+            if !valid {
+                return Err(GrokError::InvalidConfig(format!(
+                    "Invalid table name '{}'",
+                    table
+                )));
+            }
+
+            client
+                .batch_execute(&format!(
+                    "CREATE EXTENSION IF NOT EXISTS vector;
+                     CREATE TABLE IF NOT EXISTS {} (
+                         id TEXT PRIMARY KEY,
+                         text TEXT NOT NULL,
+                         metadata JSONB NOT NULL DEFAULT '{{}}',
+                         embedding vector({}) NOT NULL
+                     );",
+                    table, dimensions
+                ))
+                .await
+                .map_err(|e| GrokError::Retrieval(format!("Failed to create table: {}", e)))?;
+            Ok(Self { client, table })
+        }
+    }
+
+    #[async_trait]
+    impl VectorStore for PgVectorStore {
+        async fn upsert(&self, records: Vec<VectorRecord>) -> Result<()> {
+            let statement = format!(
+                "INSERT INTO {} (id, text, metadata, embedding) VALUES ($1, $2, $3, $4)
+                 ON CONFLICT (id) DO UPDATE
+                 SET text = EXCLUDED.text, metadata = EXCLUDED.metadata, embedding = EXCLUDED.embedding",
+                self.table
+            );
+            for record in records {
+                let metadata = serde_json::Value::Object(record.metadata);
+                let embedding = Vector::from(record.embedding);
+                self.client
+                    .execute(
+                        &statement,
+                        &[&record.id, &record.text, &metadata, &embedding],
+                    )
+                    .await
+                    .map_err(|e| GrokError::Retrieval(format!("Failed to upsert record: {}", e)))?;
+            }
+            Ok(())
+        }
+
+        async fn query(
+            &self,
+            embedding: &[f32],
+            k: usize,
+            filter: Option<&MetadataFilter>,
+        ) -> Result<Vec<VectorMatch>> {
+            let statement = format!(
+                "SELECT id, text, metadata, 1 - (embedding <=> $1) AS score FROM {}
+                 WHERE metadata @> $2
+                 ORDER BY embedding <=> $1
+                 LIMIT $3",
+                self.table
+            );
+            let embedding = Vector::from(embedding.to_vec());
+            let filter = serde_json::Value::Object(filter.cloned().unwrap_or_default().equals);
+            let rows = self
+                .client
+                .query(&statement, &[&embedding, &filter, &(k as i64)])
+                .await
+                .map_err(|e| GrokError::Retrieval(format!("Failed to query records: {}", e)))?;
+
+            rows.into_iter()
+                .map(|row| {
+                    let metadata: serde_json::Value = row.try_get(2)?;
+                    let score: f64 = row.try_get(3)?;
+                    Ok(VectorMatch {
+                        id: row.try_get(0)?,
+                        text: row.try_get(1)?,
+                        metadata: match metadata {
+                            serde_json::Value::Object(metadata) => metadata,
+                            _ => Metadata::new(),
+                        },
+                        score: score as f32,
+                    })
+                })
+                .collect::<std::result::Result<Vec<_>, tokio_postgres::Error>>()
+                .map_err(|e| GrokError::Retrieval(format!("Failed to read records: {}", e)))
+        }
+
+        async fn delete(&self, filter: &MetadataFilter) -> Result<()> {
+            let statement = format!("DELETE FROM {} WHERE metadata @> $1", self.table);
+            let filter = serde_json::Value::Object(filter.equals.clone());
+            self.client
+                .execute(&statement, &[&filter])
+                .await
+                .map_err(|e| GrokError::Retrieval(format!("Failed to delete records: {}", e)))?;
+            Ok(())
+        }
+    }
+}
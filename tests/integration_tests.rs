@@ -3,10 +3,18 @@
 #[cfg(test)]
 mod tests {
     use async_trait::async_trait;
-    use grok_rust_sdk::chat::{Message, Model, Role};
-    use grok_rust_sdk::error::GrokError;
-    use grok_rust_sdk::tools::{ToolExecutor, ToolRegistry, ToolSpec};
-    use serde_json;
+    use grok_rust_sdk::chat::{collect_completion, ChatChunk, Message, Model, Role};
+    use grok_rust_sdk::error::{ApiError, Entity, ErrorKind, GrokError, ResultExt, TimeoutPhase};
+    #[cfg(feature = "persistence")]
+    use grok_rust_sdk::persistence::{
+        JournalMode, SessionQuery, SessionSort, SqliteStorage, SqliteStorageBuilder, Synchronous,
+    };
+    use grok_rust_sdk::storage::{MemoryStorage, Storage};
+    use grok_rust_sdk::tools::{
+        Approval, ProgressSender, StreamingToolExecutor, ToolCacheOptions, ToolCall, ToolContext,
+        ToolErrorPolicy, ToolExecutor, ToolFunction, ToolLimits, ToolProgressEvent, ToolRegistry,
+        ToolSpec, TypedTool,
+    };
 
     #[derive(Debug)]
     struct MockTool;
@@ -28,13 +36,14 @@ mod tests {
                     },
                     "required": ["input"]
                 }),
+                strict: false,
             }
         }
     }
 
     #[tokio::test]
     async fn test_tool_registry() {
-        let mut registry = ToolRegistry::new();
+        let registry = ToolRegistry::new();
         registry.register(MockTool);
 
         let tools = registry.api_tools();
@@ -42,9 +51,391 @@ mod tests {
         assert_eq!(tools[0].function.name, "mock_tool");
     }
 
+    #[tokio::test]
+    async fn test_tool_registry_clones_share_tools() {
+        let registry = std::sync::Arc::new(ToolRegistry::new());
+        let shared = registry.clone();
+        let handle = ToolRegistry::clone(&registry);
+
+        tokio::spawn(async move { shared.register(MockTool) })
+            .await
+            .unwrap();
+
+        assert!(registry.get("mock_tool").is_some());
+        assert_eq!(handle.api_tools().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_cached_tool_results() {
+        let registry = ToolRegistry::new();
+        registry.register(MockTool);
+        registry.cache_results("mock_tool", ToolCacheOptions::default());
+
+        for (id, arguments) in [
+            ("call_1", r#"{"input": "x", "n": 1}"#),
+            ("call_2", r#"{"n": 1, "input": "x"}"#),
+        ] {
+            let call = ToolCall {
+                id: id.to_string(),
+                function: ToolFunction {
+                    name: "mock_tool".to_string(),
+                    arguments: arguments.to_string(),
+                },
+            };
+            let result = registry.execute_tool_call(&call).await.unwrap();
+            assert_eq!(result.tool_call_id, id);
+        }
+
+        let metrics = registry.tool_metrics("mock_tool").unwrap();
+        assert_eq!(metrics.calls, 1);
+        assert_eq!(metrics.cache_hits, 1);
+    }
+
+    #[tokio::test]
+    async fn test_tool_metrics_and_reset() {
+        let registry = ToolRegistry::new();
+        registry.register(MockTool);
+        registry.cache_results("mock_tool", ToolCacheOptions::default());
+
+        let call = |id: &str, arguments: &str| ToolCall {
+            id: id.to_string(),
+            function: ToolFunction {
+                name: "mock_tool".to_string(),
+                arguments: arguments.to_string(),
+            },
+        };
+        registry
+            .execute_tool_call(&call("call_1", r#"{"input": "x"}"#))
+            .await
+            .unwrap();
+        registry
+            .execute_tool_call(&call("call_2", "{}"))
+            .await
+            .unwrap_err();
+        registry
+            .execute_tool_call(&call("call_3", r#"{"input": "x"}"#))
+            .await
+            .unwrap();
+
+        let metrics = registry.tool_metrics("mock_tool").unwrap();
+        assert_eq!(metrics.calls, 2);
+        assert_eq!(metrics.errors, 1);
+        assert_eq!(metrics.cache_hits, 1);
+        assert!(metrics.last_error.is_some());
+        assert!(metrics.last_called_at.is_some());
+
+        // Nine bounded buckets plus the overflow, with both quick calls in the first
+        let histogram = &metrics.latency_histogram;
+        assert_eq!(histogram.len(), 10);
+        assert_eq!(
+            histogram[0],
+            (Some(std::time::Duration::from_millis(10)), 2)
+        );
+        assert_eq!(histogram[9].0, None);
+        assert_eq!(histogram.iter().map(|(_, count)| count).sum::<u64>(), 2);
+        assert!(histogram
+            .windows(2)
+            .all(|pair| pair[1].0.is_none() || pair[0].0 < pair[1].0));
+
+        registry.reset_metrics();
+        assert!(registry.tool_metrics("mock_tool").is_none());
+        assert!(registry.metrics().is_empty());
+
+        // Counting starts over after a reset
+        registry
+            .execute_tool_call(&call("call_4", r#"{"input": "x"}"#))
+            .await
+            .unwrap();
+        let metrics = registry.tool_metrics("mock_tool").unwrap();
+        assert_eq!(
+            (metrics.calls, metrics.errors, metrics.cache_hits),
+            (0, 0, 1)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_tool_rate_limit() {
+        let registry = ToolRegistry::new();
+        registry.register_with_limits(
+            MockTool,
+            ToolLimits {
+                calls_per_minute: Some(1),
+                max_concurrent: Some(1),
+            },
+        );
+
+        let call = ToolCall {
+            id: "call_1".to_string(),
+            function: ToolFunction {
+                name: "mock_tool".to_string(),
+                arguments: r#"{"input": "x"}"#.to_string(),
+            },
+        };
+        assert!(registry.execute_tool_call(&call).await.is_ok());
+
+        let limited = registry.execute_tool_call(&call).await.unwrap_err();
+        assert!(limited.to_string().contains("rate limited"));
+    }
+
+    struct WhoAmITool;
+
+    #[async_trait]
+    impl ToolExecutor for WhoAmITool {
+        async fn execute(&self, _args: serde_json::Value) -> Result<serde_json::Value, GrokError> {
+            Ok(serde_json::Value::Null)
+        }
+
+        async fn execute_with_context(
+            &self,
+            _args: serde_json::Value,
+            context: &ToolContext,
+        ) -> Result<serde_json::Value, GrokError> {
+            let tenant = context.get::<&'static str>().copied();
+            Ok(serde_json::json!({ "user": context.user_id, "tenant": tenant }))
+        }
+
+        fn spec(&self) -> ToolSpec {
+            ToolSpec {
+                name: "whoami".to_string(),
+                description: "Report the current user".to_string(),
+                parameters: serde_json::json!({ "type": "object", "properties": {} }),
+                strict: false,
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_tool_context_reaches_executor() {
+        let registry = ToolRegistry::new();
+        registry.register(WhoAmITool);
+
+        let call = ToolCall {
+            id: "call_1".to_string(),
+            function: ToolFunction {
+                name: "whoami".to_string(),
+                arguments: "{}".to_string(),
+            },
+        };
+        let context = ToolContext::new().with_user_id("user-7").with("acme");
+        let result = registry
+            .execute_tool_call_with_context(&call, &context)
+            .await
+            .unwrap();
+        assert_eq!(result.content, r#"{"tenant":"acme","user":"user-7"}"#);
+    }
+
+    struct CrawlTool;
+
+    #[async_trait]
+    impl StreamingToolExecutor for CrawlTool {
+        async fn execute(
+            &self,
+            _args: serde_json::Value,
+            progress: ProgressSender,
+        ) -> Result<serde_json::Value, GrokError> {
+            progress.log("fetching page 1");
+            progress.partial(serde_json::json!({ "pages": 1 }));
+            Ok(serde_json::json!({ "pages": 2 }))
+        }
+
+        fn spec(&self) -> ToolSpec {
+            ToolSpec {
+                name: "crawl".to_string(),
+                description: "Crawl a site".to_string(),
+                parameters: serde_json::json!({ "type": "object", "properties": {} }),
+                strict: false,
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_streaming_tool_progress() {
+        let registry = ToolRegistry::new();
+        registry.register_streaming(CrawlTool);
+        let mut progress = registry.subscribe_progress();
+
+        let call = ToolCall {
+            id: "call_1".to_string(),
+            function: ToolFunction {
+                name: "crawl".to_string(),
+                arguments: "{}".to_string(),
+            },
+        };
+        let result = registry.execute_tool_call(&call).await.unwrap();
+        assert_eq!(result.content, r#"{"pages":2}"#);
+
+        let first = progress.recv().await.unwrap();
+        assert_eq!(first.tool_call_id, "call_1");
+        assert!(
+            matches!(first.event, ToolProgressEvent::Log(ref line) if line == "fetching page 1")
+        );
+        let second = progress.recv().await.unwrap();
+        assert!(matches!(second.event, ToolProgressEvent::Partial(_)));
+    }
+
+    #[tokio::test]
+    async fn test_disable_and_unregister_tools() {
+        let registry = ToolRegistry::new();
+        registry.register(MockTool);
+
+        let mut tenant = registry.clone();
+        tenant.disable("mock_tool");
+        assert!(tenant.api_tools().is_empty());
+        assert!(registry.is_enabled("mock_tool"));
+
+        let call = ToolCall {
+            id: "call_1".to_string(),
+            function: ToolFunction {
+                name: "mock_tool".to_string(),
+                arguments: r#"{"input": "x"}"#.to_string(),
+            },
+        };
+        assert!(tenant.execute_tool_call(&call).await.is_err());
+
+        tenant.enable("mock_tool");
+        assert!(tenant.execute_tool_call(&call).await.is_ok());
+
+        assert!(registry.replace(MockTool).is_some());
+        assert!(registry.unregister("mock_tool").is_some());
+        assert!(tenant.get("mock_tool").is_none());
+    }
+
+    #[tokio::test]
+    async fn test_replaced_tool_uses_new_schema() {
+        /// `mock_tool` with the given parameter schema
+        struct SchemaTool(serde_json::Value);
+
+        #[async_trait]
+        impl ToolExecutor for SchemaTool {
+            async fn execute(
+                &self,
+                args: serde_json::Value,
+            ) -> Result<serde_json::Value, GrokError> {
+                Ok(args)
+            }
+
+            fn spec(&self) -> ToolSpec {
+                ToolSpec {
+                    name: "mock_tool".to_string(),
+                    description: "A tool whose schema changes".to_string(),
+                    parameters: self.0.clone(),
+                    strict: false,
+                }
+            }
+        }
+
+        let schema = |field: &str, field_type: &str| {
+            serde_json::json!({
+                "type": "object",
+                "properties": { field: { "type": field_type } },
+                "required": [field],
+                "additionalProperties": false
+            })
+        };
+        let call = |arguments: &str| ToolCall {
+            id: "call_1".to_string(),
+            function: ToolFunction {
+                name: "mock_tool".to_string(),
+                arguments: arguments.to_string(),
+            },
+        };
+
+        let registry = ToolRegistry::new();
+        registry.register(SchemaTool(schema("a", "string")));
+        let only_b = call(r#"{"b": 1}"#);
+        assert!(matches!(
+            registry.execute_tool_call(&only_b).await,
+            Err(GrokError::InvalidToolArguments { .. })
+        ));
+
+        assert!(registry
+            .replace(SchemaTool(schema("b", "integer")))
+            .is_some());
+        let result = registry.execute_tool_call(&only_b).await.unwrap();
+        assert_eq!(result.content, r#"{"b":1}"#);
+        assert!(matches!(
+            registry.execute_tool_call(&call(r#"{"a": "x"}"#)).await,
+            Err(GrokError::InvalidToolArguments { .. })
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_tool_versions() {
+        let registry = ToolRegistry::new();
+        registry.register_version(MockTool, 1);
+        registry.register_version(MockTool, 2);
+        registry.deprecate("mock_tool@v1", "mock_tool@v2");
+
+        let tools = registry.api_tools();
+        assert_eq!(tools.len(), 2);
+        let v1 = tools
+            .iter()
+            .find(|tool| tool.function.name == "mock_tool@v1")
+            .unwrap();
+        assert!(v1.function.description.contains("use 'mock_tool@v2'"));
+
+        // The bare name runs the highest version
+        let call = ToolCall {
+            id: "call_1".to_string(),
+            function: ToolFunction {
+                name: "mock_tool".to_string(),
+                arguments: r#"{"input": "x"}"#.to_string(),
+            },
+        };
+        assert!(registry.execute_tool_call(&call).await.is_ok());
+        assert_eq!(registry.tool_metrics("mock_tool@v2").unwrap().calls, 1);
+        assert!(registry.tool_metrics("mock_tool@v1").is_none());
+    }
+
+    #[tokio::test]
+    async fn test_register_fn() {
+        let registry = ToolRegistry::new();
+        registry.register_fn(
+            "add",
+            "Add two numbers",
+            grok_rust_sdk::tool_params!(
+                "a": grok_rust_sdk::param!(number, "First number"),
+                "b": grok_rust_sdk::param!(number, "Second number")
+            ),
+            |args| async move {
+                let sum = args["a"].as_f64().unwrap_or(0.0) + args["b"].as_f64().unwrap_or(0.0);
+                Ok(serde_json::json!({ "sum": sum }))
+            },
+        );
+
+        let call = ToolCall {
+            id: "call_1".to_string(),
+            function: ToolFunction {
+                name: "add".to_string(),
+                arguments: r#"{"a": 2, "b": 3}"#.to_string(),
+            },
+        };
+        let result = registry.execute_tool_call(&call).await.unwrap();
+        assert_eq!(result.content, r#"{"sum":5.0}"#);
+    }
+
+    #[test]
+    fn test_tool_specs_round_trip() {
+        let registry = ToolRegistry::new();
+        registry.register(MockTool);
+
+        let stored = serde_json::to_string(&registry.export_specs()).unwrap();
+        let specs: Vec<ToolSpec> = serde_json::from_str(&stored).unwrap();
+
+        let restored = ToolRegistry::from_specs(specs.clone(), |name| match name {
+            "mock_tool" => Some(std::sync::Arc::new(MockTool) as std::sync::Arc<dyn ToolExecutor>),
+            _ => None,
+        })
+        .unwrap();
+        assert!(restored.is_enabled("mock_tool"));
+        assert_eq!(restored.export_specs()[0].name, "mock_tool");
+
+        assert!(ToolRegistry::from_specs(specs, |_| None).is_err());
+    }
+
     #[tokio::test]
     async fn test_tool_execution() {
-        let mut registry = ToolRegistry::new();
+        let registry = ToolRegistry::new();
         registry.register(MockTool);
 
         let tool_call = grok_rust_sdk::tools::ToolCall {
@@ -61,6 +452,523 @@ mod tests {
         let parsed: serde_json::Value = serde_json::from_str(&result.content).unwrap();
         assert_eq!(parsed["result"], "mock_response");
         assert_eq!(parsed["input"]["input"], "test_value");
+
+        let invalid = grok_rust_sdk::tools::ToolCall {
+            id: "test-call-124".to_string(),
+            function: grok_rust_sdk::tools::ToolFunction {
+                name: "mock_tool".to_string(),
+                arguments: "{}".to_string(),
+            },
+        };
+        let result = registry.execute_tool_call(&invalid).await;
+        assert!(matches!(
+            result,
+            Err(GrokError::InvalidToolArguments { .. })
+        ));
+
+        let metrics = registry.tool_metrics("mock_tool").unwrap();
+        assert_eq!(metrics.calls, 2);
+        assert_eq!(metrics.errors, 1);
+        assert_eq!(metrics.error_rate(), 0.5);
+        assert!(metrics.last_error.is_some());
+        assert_eq!(
+            metrics
+                .latency_histogram
+                .iter()
+                .map(|(_, count)| count)
+                .sum::<u64>(),
+            2
+        );
+    }
+
+    #[tokio::test]
+    async fn test_tool_approval_denied() {
+        let mut registry = ToolRegistry::new();
+        registry.register(MockTool);
+        registry.require_approval("mock_tool");
+        registry.set_approval_handler(|_call: ToolCall| async {
+            Approval::Deny("not today".to_string())
+        });
+
+        let call = ToolCall {
+            id: "call_1".to_string(),
+            function: ToolFunction {
+                name: "mock_tool".to_string(),
+                arguments: r#"{"input": "x"}"#.to_string(),
+            },
+        };
+
+        let result = registry.execute_tool_call(&call).await.unwrap();
+        assert_eq!(result.tool_call_id, "call_1");
+        assert!(result.content.contains("not today"));
+    }
+
+    #[tokio::test]
+    async fn test_tool_approval_covers_versions() {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+        use std::sync::Arc;
+
+        let call = |name: &str| ToolCall {
+            id: "call_1".to_string(),
+            function: ToolFunction {
+                name: name.to_string(),
+                arguments: r#"{"input": "x"}"#.to_string(),
+            },
+        };
+        let gated = |name: &str| {
+            let asked = Arc::new(AtomicUsize::new(0));
+            let mut registry = ToolRegistry::new();
+            registry.register_version(MockTool, 1);
+            registry.register_version(MockTool, 2);
+            registry.require_approval(name);
+            let counter = asked.clone();
+            registry.set_approval_handler(move |_call: ToolCall| {
+                counter.fetch_add(1, Ordering::SeqCst);
+                async { Approval::Deny("not today".to_string()) }
+            });
+            (registry, asked)
+        };
+
+        // Gating a version covers calls by the bare name that resolve to it
+        let (registry, asked) = gated("mock_tool@v2");
+        for name in ["mock_tool", "mock_tool@v2"] {
+            let result = registry.execute_tool_call(&call(name)).await.unwrap();
+            assert!(result.content.contains("not today"));
+        }
+        assert_eq!(asked.load(Ordering::SeqCst), 2);
+        assert!(registry.tool_metrics("mock_tool@v2").is_none());
+        // Other versions aren't gated
+        let result = registry
+            .execute_tool_call(&call("mock_tool@v1"))
+            .await
+            .unwrap();
+        assert!(!result.content.contains("not today"));
+
+        // Gating the bare name covers every version
+        let (registry, asked) = gated("mock_tool");
+        for name in ["mock_tool", "mock_tool@v1", "mock_tool@v2"] {
+            let result = registry.execute_tool_call(&call(name)).await.unwrap();
+            assert!(result.content.contains("not today"));
+        }
+        assert_eq!(asked.load(Ordering::SeqCst), 3);
+    }
+
+    #[tokio::test]
+    async fn test_cached_results_of_versioned_tools() {
+        use grok_rust_sdk::tools::ToolCacheOptions;
+
+        let registry = ToolRegistry::new();
+        registry.register_version(MockTool, 1);
+        registry.register_version(MockTool, 2);
+        registry.cache_results("mock_tool", ToolCacheOptions::default());
+
+        let call = |name: &str| ToolCall {
+            id: "call_1".to_string(),
+            function: ToolFunction {
+                name: name.to_string(),
+                arguments: r#"{"input": "x"}"#.to_string(),
+            },
+        };
+        // The bare name and the version it resolves to share entries
+        for name in ["mock_tool", "mock_tool@v2", "mock_tool@v1"] {
+            registry.execute_tool_call(&call(name)).await.unwrap();
+        }
+        let v2 = registry.tool_metrics("mock_tool@v2").unwrap();
+        assert_eq!((v2.calls, v2.cache_hits), (1, 1));
+        assert_eq!(registry.tool_metrics("mock_tool@v1").unwrap().calls, 1);
+    }
+
+    #[tokio::test]
+    async fn test_tool_errors_returned_to_model() {
+        let mut registry = ToolRegistry::new();
+        registry.set_error_policy(ToolErrorPolicy::ReturnToModel);
+
+        let call = ToolCall {
+            id: "call_1".to_string(),
+            function: ToolFunction {
+                name: "missing_tool".to_string(),
+                arguments: "{}".to_string(),
+            },
+        };
+
+        let result = registry.execute_tool_call(&call).await.unwrap();
+        let content: serde_json::Value = serde_json::from_str(&result.content).unwrap();
+        assert!(content["error"].as_str().unwrap().contains("missing_tool"));
+        assert!(matches!(
+            result.error.as_deref(),
+            Some(GrokError::ToolExecution(_))
+        ));
+    }
+
+    #[cfg(feature = "mcp")]
+    #[tokio::test]
+    async fn test_serve_mcp_lists_and_calls_tools() {
+        use grok_rust_sdk::mcp::McpTransport;
+        use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+
+        let registry = ToolRegistry::new();
+        registry.register(MockTool);
+
+        let (client, server) = tokio::io::duplex(4096);
+        let (server_read, server_write) = tokio::io::split(server);
+        let server = tokio::spawn(async move {
+            registry
+                .serve_mcp(McpTransport::new(server_read, server_write))
+                .await
+        });
+
+        let (client_read, mut client_write) = tokio::io::split(client);
+        let mut responses = BufReader::new(client_read).lines();
+        client_write.write_all(concat!(
+            r#"{"jsonrpc":"2.0","id":1,"method":"initialize","params":{}}"#, "\n",
+            r#"{"jsonrpc":"2.0","method":"notifications/initialized"}"#, "\n",
+            r#"{"jsonrpc":"2.0","id":2,"method":"tools/list"}"#, "\n",
+            r#"{"jsonrpc":"2.0","id":3,"method":"tools/call","params":{"name":"mock_tool","arguments":{"input":"hi"}}}"#, "\n",
+        ).as_bytes()).await.unwrap();
+
+        let mut replies = Vec::new();
+        for _ in 0..3 {
+            let line = responses.next_line().await.unwrap().unwrap();
+            replies.push(serde_json::from_str::<serde_json::Value>(&line).unwrap());
+        }
+
+        assert_eq!(
+            replies[0]["result"]["capabilities"]["tools"]["listChanged"],
+            false
+        );
+        assert_eq!(replies[1]["result"]["tools"][0]["name"], "mock_tool");
+        assert_eq!(replies[2]["id"], 3);
+        assert_eq!(replies[2]["result"]["isError"], false);
+
+        // Closing both halves ends the server loop
+        drop(responses);
+        drop(client_write);
+        server.await.unwrap().unwrap();
+    }
+
+    #[cfg(feature = "http-tool")]
+    #[tokio::test]
+    async fn test_http_fetch_tool_enforces_allowlist() {
+        use grok_rust_sdk::tools::http::HttpFetchTool;
+
+        assert!(HttpFetchTool::builder().build().is_err());
+
+        let tool = HttpFetchTool::builder()
+            .allow_domain("example.com")
+            .build()
+            .unwrap();
+        for url in [
+            "https://evil.com/",
+            "https://notexample.com/",
+            "file:///etc/passwd",
+        ] {
+            let result = tool.execute(serde_json::json!({ "url": url })).await;
+            assert!(
+                matches!(result, Err(GrokError::ToolExecution(_))),
+                "{} was allowed",
+                url
+            );
+        }
+
+        let result = tool
+            .execute(serde_json::json!({ "url": "https://example.com/", "method": "POST" }))
+            .await;
+        assert!(result.is_err());
+    }
+
+    #[cfg(feature = "fs-tool")]
+    #[tokio::test]
+    async fn test_sandboxed_file_tools() {
+        use grok_rust_sdk::tools::fs::Sandbox;
+
+        let root = std::env::temp_dir().join(format!("grok-sandbox-{}", std::process::id()));
+        std::fs::create_dir_all(&root).unwrap();
+        let sandbox = Sandbox::builder(&root)
+            .allow_extension("txt")
+            .max_write_bytes(16)
+            .build()
+            .unwrap();
+
+        let write = sandbox.write_tool();
+        let read = sandbox.read_tool();
+        write
+            .execute(serde_json::json!({ "path": "notes/a.txt", "content": "hello" }))
+            .await
+            .unwrap();
+        let result = read
+            .execute(serde_json::json!({ "path": "notes/a.txt" }))
+            .await
+            .unwrap();
+        assert_eq!(result["content"], "hello");
+
+        let listing = sandbox
+            .list_tool()
+            .execute(serde_json::json!({}))
+            .await
+            .unwrap();
+        assert_eq!(listing["entries"][0]["name"], "notes");
+
+        for path in [
+            "../escape.txt",
+            "/etc/passwd",
+            "notes/../../x.txt",
+            "script.sh",
+        ] {
+            let result = read.execute(serde_json::json!({ "path": path })).await;
+            assert!(
+                matches!(result, Err(GrokError::ToolExecution(_))),
+                "{} was allowed",
+                path
+            );
+        }
+        let too_big = write
+            .execute(serde_json::json!({ "path": "big.txt", "content": "x".repeat(17) }))
+            .await;
+        assert!(too_big.is_err());
+
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[cfg(all(feature = "fs-tool", unix))]
+    #[tokio::test]
+    async fn test_sandbox_rejects_symlink_escapes() {
+        use grok_rust_sdk::tools::fs::Sandbox;
+        use std::os::unix::fs::symlink;
+
+        let base = std::env::temp_dir().join(format!("grok-symlinks-{}", std::process::id()));
+        let root = base.join("root");
+        let outside = base.join("outside");
+        std::fs::create_dir_all(&root).unwrap();
+        std::fs::create_dir_all(&outside).unwrap();
+        std::fs::write(outside.join("secret.txt"), "secret").unwrap();
+        std::fs::write(root.join("script.sh"), "echo hi").unwrap();
+
+        // Dangling, to a file outside, to a directory outside, and renaming a denied extension
+        symlink(outside.join("created.txt"), root.join("dangling.txt")).unwrap();
+        symlink(outside.join("secret.txt"), root.join("leak.txt")).unwrap();
+        symlink(&outside, root.join("linked")).unwrap();
+        symlink(root.join("script.sh"), root.join("script.txt")).unwrap();
+
+        let sandbox = Sandbox::builder(&root)
+            .allow_extension("txt")
+            .build()
+            .unwrap();
+        let read = sandbox.read_tool();
+        let write = sandbox.write_tool();
+
+        for path in [
+            "dangling.txt",
+            "leak.txt",
+            "linked/secret.txt",
+            "script.txt",
+        ] {
+            let result = read.execute(serde_json::json!({ "path": path })).await;
+            assert!(
+                matches!(result, Err(GrokError::ToolExecution(_))),
+                "reading {} was allowed",
+                path
+            );
+        }
+        for path in ["dangling.txt", "linked/new.txt", "linked/sub/new.txt"] {
+            let result = write
+                .execute(serde_json::json!({ "path": path, "content": "pwned" }))
+                .await;
+            assert!(
+                matches!(result, Err(GrokError::ToolExecution(_))),
+                "writing {} was allowed",
+                path
+            );
+        }
+        assert!(!outside.join("created.txt").exists());
+        assert!(!outside.join("new.txt").exists());
+        assert!(!outside.join("sub").exists());
+
+        std::fs::remove_dir_all(&base).unwrap();
+    }
+
+    #[cfg(all(feature = "code-exec", unix))]
+    #[tokio::test]
+    async fn test_code_exec_tool_limits() {
+        use grok_rust_sdk::tools::exec::CodeExecTool;
+
+        let tool = CodeExecTool::i_understand_the_risk(std::env::temp_dir())
+            .unwrap()
+            .timeout(std::time::Duration::from_millis(500))
+            .max_output_bytes(8);
+
+        let result = tool
+            .execute(serde_json::json!({ "language": "sh", "code": "echo hello world; exit 3" }))
+            .await
+            .unwrap();
+        assert_eq!(result["exit_code"], 3);
+        assert_eq!(result["stdout"], "hello wo");
+        assert_eq!(result["truncated"], true);
+
+        let result = tool
+            .execute(serde_json::json!({ "language": "sh", "code": "sleep 5" }))
+            .await
+            .unwrap();
+        assert_eq!(result["timed_out"], true);
+
+        let result = tool
+            .execute(serde_json::json!({ "language": "ruby", "code": "puts 1" }))
+            .await;
+        assert!(result.is_err());
+    }
+
+    #[derive(serde::Deserialize, schemars::JsonSchema)]
+    struct EchoArgs {
+        text: String,
+        times: Option<u32>,
+    }
+
+    #[tokio::test]
+    async fn test_typed_tool() {
+        let tool = TypedTool::new("echo", "Repeat text", |args: EchoArgs| async move {
+            Ok(args.text.repeat(args.times.unwrap_or(1) as usize))
+        });
+
+        let spec = tool.spec();
+        assert_eq!(spec.parameters["type"], "object");
+        assert_eq!(spec.parameters["required"], serde_json::json!(["text"]));
+
+        let result = tool
+            .execute(serde_json::json!({"text": "ab", "times": 2}))
+            .await
+            .unwrap();
+        assert_eq!(result, "abab");
+
+        let error = tool
+            .execute(serde_json::json!({"times": 2}))
+            .await
+            .unwrap_err();
+        assert!(matches!(error, GrokError::ToolExecution(_)));
+    }
+
+    #[cfg(feature = "macros")]
+    #[derive(serde::Deserialize, schemars::JsonSchema)]
+    #[serde(rename_all = "lowercase")]
+    enum Unit {
+        Celsius,
+        Fahrenheit,
+    }
+
+    /// Convert a temperature
+    #[cfg(feature = "macros")]
+    #[grok_rust_sdk::grok_tool]
+    async fn convert(degrees: f64, to: Unit) -> f64 {
+        match to {
+            Unit::Celsius => (degrees - 32.0) / 1.8,
+            Unit::Fahrenheit => degrees * 1.8 + 32.0,
+        }
+    }
+
+    /// Repeat text
+    #[cfg(feature = "macros")]
+    #[grok_rust_sdk::grok_tool]
+    async fn echo(#[args] args: EchoArgs) -> String {
+        args.text.repeat(args.times.unwrap_or(1) as usize)
+    }
+
+    /// Join words
+    #[cfg(feature = "macros")]
+    #[grok_rust_sdk::grok_tool]
+    fn join(
+        words: &[String],
+        separator: Option<&str>,
+        unit: Option<&Unit>,
+        prefix: &str,
+    ) -> String {
+        let suffix = match unit {
+            Some(Unit::Celsius) => "C",
+            Some(Unit::Fahrenheit) => "F",
+            None => "",
+        };
+        format!(
+            "{}{}{}",
+            prefix,
+            words.join(separator.unwrap_or(" ")),
+            suffix
+        )
+    }
+
+    #[cfg(feature = "macros")]
+    #[tokio::test]
+    async fn test_grok_tool_typed_parameters() {
+        let spec = Convert.spec();
+        assert_eq!(
+            spec.parameters["properties"]["to"]["enum"],
+            serde_json::json!(["celsius", "fahrenheit"])
+        );
+        let result = Convert
+            .execute(serde_json::json!({"degrees": 100.0, "to": "celsius"}))
+            .await
+            .unwrap();
+        assert!((result.as_f64().unwrap() - 37.78).abs() < 0.01);
+
+        let spec = Echo.spec();
+        assert_eq!(spec.name, "echo");
+        assert_eq!(spec.parameters["required"], serde_json::json!(["text"]));
+        assert!(spec.parameters.get("$schema").is_none());
+        let result = Echo
+            .execute(serde_json::json!({"text": "ab", "times": 2}))
+            .await
+            .unwrap();
+        assert_eq!(result, "abab");
+
+        let error = Echo
+            .execute(serde_json::json!({"times": 2}))
+            .await
+            .unwrap_err();
+        assert!(error
+            .to_string()
+            .contains("Invalid arguments for tool 'echo'"));
+
+        // Borrowed parameters are bound to owned values
+        let spec = Join.spec();
+        assert_eq!(
+            spec.parameters["properties"]["words"],
+            serde_json::json!({"type": "array", "items": {"type": "string"}})
+        );
+        assert_eq!(spec.parameters["properties"]["prefix"]["type"], "string");
+        assert_eq!(
+            spec.parameters["required"],
+            serde_json::json!(["words", "prefix"])
+        );
+        let result = Join
+            .execute(serde_json::json!({"words": ["a", "b"], "separator": "-", "unit": "celsius", "prefix": ">"}))
+            .await
+            .unwrap();
+        assert_eq!(result, ">a-bC");
+        let result = Join
+            .execute(serde_json::json!({"words": ["a", "b"], "prefix": ""}))
+            .await
+            .unwrap();
+        assert_eq!(result, "a b");
+    }
+
+    #[test]
+    fn test_strict_tool_schema() {
+        let tool = TypedTool::new("echo", "Repeat text", |args: EchoArgs| async move {
+            Ok(args.text)
+        })
+        .strict();
+
+        let spec = tool.spec();
+        assert!(spec.strict);
+        assert_eq!(spec.parameters["additionalProperties"], false);
+        assert_eq!(
+            spec.parameters["required"],
+            serde_json::json!(["text", "times"])
+        );
+
+        let tool: grok_rust_sdk::chat::Tool = grok_rust_sdk::tools::Tool {
+            tool_type: "function".to_string(),
+            function: spec,
+        }
+        .into();
+        let json = serde_json::to_value(&tool).unwrap();
+        assert_eq!(json["function"]["strict"], true);
     }
 
     #[test]
@@ -76,7 +984,7 @@ mod tests {
     fn test_message_creation() {
         let message = Message {
             role: Role::User,
-            content: "Hello, world!".to_string(),
+            content: "Hello, world!".into(),
             tool_calls: None,
             tool_call_id: None,
             name: None,
@@ -86,6 +994,6041 @@ mod tests {
         assert_eq!(message.content, "Hello, world!");
     }
 
+    #[test]
+    fn test_multimodal_content_serialization() {
+        use grok_rust_sdk::chat::{ContentPart, ImageDetail, MessageContent};
+
+        // Text-only content stays a plain string on the wire
+        let text = Message {
+            role: Role::User,
+            content: "Hi".into(),
+            tool_calls: None,
+            tool_call_id: None,
+            name: None,
+        };
+        assert_eq!(serde_json::to_value(&text).unwrap()["content"], "Hi");
+
+        let message = Message::user_with_image("What is this?", "https://example.com/cat.png");
+        let value = serde_json::to_value(&message).unwrap();
+        assert_eq!(
+            value["content"],
+            serde_json::json!([
+                { "type": "text", "text": "What is this?" },
+                { "type": "image_url", "image_url": { "url": "https://example.com/cat.png" } }
+            ])
+        );
+        assert_eq!(message.content.text(), "What is this?");
+        assert_eq!(message.content.image_count(), 1);
+
+        // Base64 images travel as data URLs and come back as base64 parts
+        let inline = Message::user_with_parts(vec![
+            ContentPart::text("Describe"),
+            ContentPart::base64_image("image/png", "iVBORw0KGgo=").with_detail(ImageDetail::Low),
+        ]);
+        let value = serde_json::to_value(&inline).unwrap();
+        assert_eq!(
+            value["content"][1]["image_url"],
+            serde_json::json!({ "url": "data:image/png;base64,iVBORw0KGgo=", "detail": "low" })
+        );
+        let parsed: Message = serde_json::from_value(value).unwrap();
+        assert_eq!(parsed.content, inline.content);
+        assert!(matches!(
+            parsed.content,
+            MessageContent::Parts(ref parts) if matches!(parts[1], ContentPart::Base64Image { .. })
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_session_history_is_shared() {
+        let client = std::sync::Arc::new(grok_rust_sdk::Client::new("test-key").unwrap());
+        let session = grok_rust_sdk::session::Session::new(client, Model::Grok4, None);
+        session
+            .append(Message {
+                role: Role::User,
+                content: "Hello".into(),
+                tool_calls: None,
+                tool_call_id: None,
+                name: None,
+            })
+            .await
+            .unwrap();
+
+        let first = session.history().await;
+        let second = session.history().await;
+        assert!(std::sync::Arc::ptr_eq(&first[0], &second[0]));
+        assert_eq!(session.messages().await[0].content, "Hello");
+    }
+
+    #[tokio::test]
+    async fn test_session_with_mock_provider() {
+        use grok_rust_sdk::chat::{ChatCompletion, Tool};
+        use grok_rust_sdk::client::ChatOptions;
+        use grok_rust_sdk::provider::{ChatProvider, ChunkStream};
+        use std::sync::Arc;
+
+        /// Echoes the last message back
+        #[derive(Debug)]
+        struct Echo;
+
+        #[async_trait]
+        impl ChatProvider for Echo {
+            async fn chat(
+                &self,
+                model: Model,
+                messages: Vec<Arc<Message>>,
+                _tools: Option<Vec<Tool>>,
+                _options: Option<ChatOptions>,
+            ) -> grok_rust_sdk::Result<ChatCompletion> {
+                Ok(ChatCompletion {
+                    id: "echo".to_string(),
+                    model: model.as_str().to_string(),
+                    usage: None,
+                    message: Message {
+                        role: Role::Assistant,
+                        content: messages.last().unwrap().content.clone(),
+                        tool_calls: None,
+                        tool_call_id: None,
+                        name: None,
+                    },
+                    finish_reason: Some("stop".to_string()),
+                    timings: None,
+                    annotations: Vec::new(),
+                    rate_limit: None,
+                })
+            }
+
+            async fn chat_stream(
+                &self,
+                _model: Model,
+                _messages: Vec<Message>,
+                _tools: Option<Vec<Tool>>,
+            ) -> grok_rust_sdk::Result<ChunkStream> {
+                Ok(Box::pin(futures::stream::empty()))
+            }
+        }
+
+        let session = grok_rust_sdk::session::Session::new(Arc::new(Echo), Model::Grok4, None);
+        let completion = session.chat("ping").await.unwrap();
+        assert_eq!(completion.message.content, "ping");
+        assert_eq!(session.message_count().await, 2);
+    }
+
+    #[tokio::test]
+    async fn test_context_window_check_and_session_truncation() {
+        use grok_rust_sdk::chat::{check_context_window, ChatCompletion, Tool};
+        use grok_rust_sdk::client::ChatOptions;
+        use grok_rust_sdk::provider::{ChatProvider, ChunkStream};
+        use std::sync::Arc;
+
+        /// Replies with the number of messages it was sent
+        #[derive(Debug)]
+        struct CountMessages;
+
+        #[async_trait]
+        impl ChatProvider for CountMessages {
+            async fn chat(
+                &self,
+                model: Model,
+                messages: Vec<Arc<Message>>,
+                _tools: Option<Vec<Tool>>,
+                _options: Option<ChatOptions>,
+            ) -> grok_rust_sdk::Result<ChatCompletion> {
+                Ok(ChatCompletion {
+                    id: "count".to_string(),
+                    model: model.as_str().to_string(),
+                    usage: None,
+                    message: Message {
+                        role: Role::Assistant,
+                        content: messages.len().to_string().into(),
+                        tool_calls: None,
+                        tool_call_id: None,
+                        name: None,
+                    },
+                    finish_reason: Some("stop".to_string()),
+                    timings: None,
+                    annotations: Vec::new(),
+                    rate_limit: None,
+                })
+            }
+
+            async fn chat_stream(
+                &self,
+                _model: Model,
+                _messages: Vec<Message>,
+                _tools: Option<Vec<Tool>>,
+            ) -> grok_rust_sdk::Result<ChunkStream> {
+                Ok(Box::pin(futures::stream::empty()))
+            }
+        }
+
+        let message = |role: Role, content: String| Message {
+            role,
+            content: content.into(),
+            tool_calls: None,
+            tool_call_id: None,
+            name: None,
+        };
+
+        // About 10,004 tokens against Grok-1's 8,192
+        let long = vec![Arc::new(message(Role::User, "x".repeat(40_000)))];
+        let err = check_context_window(Model::Grok1, &long, None, Some(100)).unwrap_err();
+        assert!(err.is_context_length());
+        assert!(matches!(
+            err,
+            GrokError::ContextLengthExceeded { overflow: 1912, .. }
+        ));
+        assert!(check_context_window(Model::Grok4, &long, None, Some(100)).is_ok());
+
+        // Three turns of about 3,004 tokens each only fit once the oldest is left out
+        let session =
+            grok_rust_sdk::session::Session::new(Arc::new(CountMessages), Model::Grok1, None);
+        session
+            .append(message(Role::System, "Be brief.".to_string()))
+            .await
+            .unwrap();
+        for role in [Role::User, Role::Assistant, Role::User] {
+            session
+                .append(message(role, "y".repeat(12_000)))
+                .await
+                .unwrap();
+        }
+        let completion = session.chat("ping").await.unwrap();
+        assert_eq!(completion.message.content, "4");
+        assert_eq!(session.message_count().await, 6);
+
+        let err = session.chat("z".repeat(40_000)).await.unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::ContextLength);
+    }
+
+    #[tokio::test]
+    async fn test_session_guardrail_blocks_and_redacts() {
+        use grok_rust_sdk::chat::{ChatCompletion, Tool};
+        use grok_rust_sdk::client::ChatOptions;
+        use grok_rust_sdk::guardrail::{GuardStage, GuardVerdict, Guardrail};
+        use grok_rust_sdk::provider::{ChatProvider, ChunkStream};
+        use std::sync::Arc;
+
+        #[derive(Debug)]
+        struct Echo;
+
+        #[async_trait]
+        impl ChatProvider for Echo {
+            async fn chat(
+                &self,
+                model: Model,
+                messages: Vec<Arc<Message>>,
+                _tools: Option<Vec<Tool>>,
+                _options: Option<ChatOptions>,
+            ) -> grok_rust_sdk::Result<ChatCompletion> {
+                Ok(ChatCompletion {
+                    id: "echo".to_string(),
+                    model: model.as_str().to_string(),
+                    usage: None,
+                    message: Message {
+                        role: Role::Assistant,
+                        content: messages.last().unwrap().content.clone(),
+                        tool_calls: None,
+                        tool_call_id: None,
+                        name: None,
+                    },
+                    finish_reason: Some("stop".to_string()),
+                    timings: None,
+                    annotations: Vec::new(),
+                    rate_limit: None,
+                })
+            }
+
+            async fn chat_stream(
+                &self,
+                _model: Model,
+                _messages: Vec<Message>,
+                _tools: Option<Vec<Tool>>,
+            ) -> grok_rust_sdk::Result<ChunkStream> {
+                Ok(Box::pin(futures::stream::empty()))
+            }
+        }
+
+        /// Blocks requests mentioning "forbidden" and masks "secret" in replies
+        #[derive(Debug)]
+        struct Policy;
+
+        #[async_trait]
+        impl Guardrail for Policy {
+            async fn check(
+                &self,
+                message: &Message,
+                stage: GuardStage,
+            ) -> grok_rust_sdk::Result<GuardVerdict> {
+                Ok(match stage {
+                    GuardStage::Request if message.content.text().contains("forbidden") => {
+                        GuardVerdict::Block("forbidden topic".to_string())
+                    }
+                    GuardStage::Response if message.content.text().contains("secret") => {
+                        GuardVerdict::Redact(message.content.text().replace("secret", "******"))
+                    }
+                    _ => GuardVerdict::Annotate(format!("{} checked", stage)),
+                })
+            }
+        }
+
+        let mut session = grok_rust_sdk::session::Session::new(Arc::new(Echo), Model::Grok4, None);
+        session.add_guardrail(Policy);
+
+        let error = session.chat("something forbidden").await.unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::ContentBlocked);
+        assert_eq!(session.message_count().await, 0);
+
+        let completion = session.chat("tell me the secret").await.unwrap();
+        assert_eq!(completion.message.content, "tell me the ******");
+        assert_eq!(completion.annotations, vec!["request checked"]);
+        assert_eq!(session.messages().await[1].content, "tell me the ******");
+    }
+
+    #[cfg(feature = "redact")]
+    #[test]
+    fn test_redactor_masks_and_restores() {
+        use grok_rust_sdk::redact::Redactor;
+
+        let redactor = Redactor::with_defaults()
+            .pattern("ORDER", r"ORD-\d{6}")
+            .unwrap();
+        let text = "Mail ada@example.com or call +1 (555) 123-4567 about ORD-123456, \
+                    card 4111 1111 1111 1111. Again: ada@example.com";
+        let redacted = redactor.redact(text);
+        assert_eq!(
+            redacted,
+            "Mail [EMAIL_1] or call [PHONE_1] about [ORDER_1], \
+             card [CARD_1]. Again: [EMAIL_1]"
+        );
+        assert_eq!(redactor.restore(&redacted), text);
+        assert_eq!(redactor.redact("order 1234 5678"), "order 1234 5678");
+    }
+
+    #[tokio::test]
+    async fn test_retriever_augments_with_nearest_chunks() {
+        use grok_rust_sdk::rag::{Chunker, Embedder, Retriever};
+        use grok_rust_sdk::vector::MemoryVectorStore;
+        use std::sync::Arc;
+
+        /// Embeds text as counts of a few keywords
+        #[derive(Debug)]
+        struct Keywords;
+
+        #[async_trait]
+        impl Embedder for Keywords {
+            async fn embed(&self, texts: &[String]) -> grok_rust_sdk::Result<Vec<Vec<f32>>> {
+                Ok(texts
+                    .iter()
+                    .map(|text| {
+                        ["rust", "sqlite", "tokio"]
+                            .iter()
+                            .map(|word| text.to_lowercase().matches(word).count() as f32)
+                            .collect()
+                    })
+                    .collect())
+            }
+        }
+
+        let retriever = Retriever::new(Arc::new(Keywords), Arc::new(MemoryVectorStore::new()))
+            .chunker(Chunker::Sentences {
+                max_sentences: 1,
+                overlap: 0,
+            });
+        let added = retriever
+            .add_document(
+                "notes.md",
+                "Rust is a systems language. SQLite stores the data. Tokio runs the tasks.",
+            )
+            .await
+            .unwrap();
+        assert_eq!(added, 3);
+
+        let mut messages = vec![Message {
+            role: Role::User,
+            content: "Where is the data kept?".into(),
+            tool_calls: None,
+            tool_call_id: None,
+            name: None,
+        }];
+        let chunks = retriever
+            .augment(&mut messages, "sqlite storage", 1)
+            .await
+            .unwrap();
+        assert_eq!(chunks[0].id, "notes.md#1");
+        assert_eq!(messages.len(), 2);
+        assert!(matches!(messages[0].role, Role::System));
+        assert!(messages[0]
+            .content
+            .text()
+            .contains("SQLite stores the data."));
+    }
+
+    #[cfg(feature = "persistence")]
+    #[tokio::test]
+    async fn test_sqlite_vector_store_filters_by_metadata() {
+        use grok_rust_sdk::vector::{Metadata, MetadataFilter, VectorRecord, VectorStore};
+
+        let record = |id: &str, embedding: Vec<f32>, lang: &str| {
+            let mut metadata = Metadata::new();
+            metadata.insert("lang".to_string(), lang.into());
+            VectorRecord {
+                id: id.to_string(),
+                text: format!("text of {}", id),
+                embedding,
+                metadata,
+            }
+        };
+
+        let storage = SqliteStorage::in_memory().unwrap();
+        storage
+            .upsert(vec![
+                record("a", vec![1.0, 0.0], "en"),
+                record("b", vec![0.9, 0.1], "de"),
+                record("c", vec![0.0, 1.0], "en"),
+            ])
+            .await
+            .unwrap();
+
+        let all = storage.query(&[1.0, 0.0], 2, None).await.unwrap();
+        assert_eq!(
+            all.iter().map(|m| m.id.as_str()).collect::<Vec<_>>(),
+            ["a", "b"]
+        );
+
+        let english = MetadataFilter::new().eq("lang", "en");
+        let matches = storage.query(&[0.9, 0.1], 5, Some(&english)).await.unwrap();
+        assert_eq!(
+            matches.iter().map(|m| m.id.as_str()).collect::<Vec<_>>(),
+            ["a", "c"]
+        );
+
+        storage.delete(&english).await.unwrap();
+        let remaining = storage.query(&[1.0, 0.0], 5, None).await.unwrap();
+        assert_eq!(remaining.len(), 1);
+        assert_eq!(remaining[0].text, "text of b");
+    }
+
+    #[cfg(feature = "eval")]
+    #[tokio::test]
+    async fn test_eval_suite_report() {
+        use grok_rust_sdk::chat::{ChatCompletion, Tool};
+        use grok_rust_sdk::client::ChatOptions;
+        use grok_rust_sdk::eval::{EvalSuite, Evaluator, ModelTarget};
+        use grok_rust_sdk::provider::{ChatProvider, ChunkStream};
+        use std::sync::Arc;
+
+        /// Always answers with the same sentence
+        #[derive(Debug)]
+        struct Canned;
+
+        #[async_trait]
+        impl ChatProvider for Canned {
+            async fn chat(
+                &self,
+                model: Model,
+                _messages: Vec<Arc<Message>>,
+                _tools: Option<Vec<Tool>>,
+                _options: Option<ChatOptions>,
+            ) -> grok_rust_sdk::Result<ChatCompletion> {
+                Ok(ChatCompletion {
+                    id: "canned".to_string(),
+                    model: model.as_str().to_string(),
+                    usage: None,
+                    message: Message {
+                        role: Role::Assistant,
+                        content: "The capital of France is Paris.".into(),
+                        tool_calls: None,
+                        tool_call_id: None,
+                        name: None,
+                    },
+                    finish_reason: Some("stop".to_string()),
+                    timings: None,
+                    annotations: Vec::new(),
+                    rate_limit: None,
+                })
+            }
+
+            async fn chat_stream(
+                &self,
+                _model: Model,
+                _messages: Vec<Message>,
+                _tools: Option<Vec<Tool>>,
+            ) -> grok_rust_sdk::Result<ChunkStream> {
+                Ok(Box::pin(futures::stream::empty()))
+            }
+        }
+
+        let suite = EvalSuite::from_json(
+            r#"{
+                "name": "geography",
+                "cases": [
+                    {
+                        "name": "france",
+                        "messages": [{"role": "user", "content": "Capital of France?"}],
+                        "assertions": [
+                            {"type": "contains", "value": "paris", "ignore_case": true},
+                            {"type": "regex", "pattern": "^The capital"}
+                        ]
+                    },
+                    {
+                        "name": "spain",
+                        "messages": [{"role": "user", "content": "Capital of Spain?"}],
+                        "assertions": [{"type": "not_contains", "value": "Paris"}]
+                    }
+                ]
+            }"#,
+        )
+        .unwrap();
+
+        let target = ModelTarget::new(Arc::new(Canned), Model::Grok4);
+        let report = Evaluator::new().concurrency(2).run(&suite, &target).await;
+        assert_eq!((report.passed, report.failed, report.errored), (1, 1, 0));
+        assert_eq!(report.cases[0].name, "france");
+        assert!(!report.all_passed());
+        assert!(report.to_json().unwrap().contains("\"pass_rate\": 0.5"));
+    }
+
+    #[tokio::test]
+    async fn test_extract_repairs_invalid_reply() {
+        use grok_rust_sdk::chat::{ChatCompletion, Tool};
+        use grok_rust_sdk::client::ChatOptions;
+        use grok_rust_sdk::extract::{extract, ExtractInput, ExtractOptions};
+        use grok_rust_sdk::provider::{ChatProvider, ChunkStream};
+        use std::sync::atomic::{AtomicUsize, Ordering};
+        use std::sync::Arc;
+
+        /// Answers with broken JSON first, then valid JSON
+        #[derive(Debug, Default)]
+        struct Sloppy {
+            calls: AtomicUsize,
+        }
+
+        #[async_trait]
+        impl ChatProvider for Sloppy {
+            async fn chat(
+                &self,
+                model: Model,
+                messages: Vec<Arc<Message>>,
+                _tools: Option<Vec<Tool>>,
+                options: Option<ChatOptions>,
+            ) -> grok_rust_sdk::Result<ChatCompletion> {
+                assert!(options.unwrap().response_format.is_some());
+                let content = if self.calls.fetch_add(1, Ordering::SeqCst) == 0 {
+                    r#"{"name": "Ada""#.to_string()
+                } else {
+                    assert!(messages
+                        .last()
+                        .unwrap()
+                        .content
+                        .text()
+                        .contains("could not be parsed"));
+                    "```json\n{\"name\": \"Ada\", \"age\": 36}\n```".to_string()
+                };
+                Ok(ChatCompletion {
+                    id: "sloppy".to_string(),
+                    model: model.as_str().to_string(),
+                    usage: None,
+                    message: Message {
+                        role: Role::Assistant,
+                        content: content.into(),
+                        tool_calls: None,
+                        tool_call_id: None,
+                        name: None,
+                    },
+                    finish_reason: Some("stop".to_string()),
+                    timings: None,
+                    annotations: Vec::new(),
+                    rate_limit: None,
+                })
+            }
+
+            async fn chat_stream(
+                &self,
+                _model: Model,
+                _messages: Vec<Message>,
+                _tools: Option<Vec<Tool>>,
+            ) -> grok_rust_sdk::Result<ChunkStream> {
+                Ok(Box::pin(futures::stream::empty()))
+            }
+        }
+
+        #[derive(Debug, serde::Deserialize, schemars::JsonSchema)]
+        struct Person {
+            name: String,
+            age: Option<u32>,
+        }
+
+        let provider = Sloppy::default();
+        let input = ExtractInput::from("Ada Lovelace died at 36");
+        let person: Person = extract(&provider, input.clone(), ExtractOptions::default())
+            .await
+            .unwrap();
+        assert_eq!(person.name, "Ada");
+        assert_eq!(person.age, Some(36));
+
+        let options = ExtractOptions {
+            repair_attempts: 0,
+            ..Default::default()
+        };
+        let result = extract::<Person>(&Sloppy::default(), input, options).await;
+        assert_eq!(result.unwrap_err().kind(), ErrorKind::Serialization);
+    }
+
+    /// Answers with scripted replies in order, counting the requests
+    #[derive(Debug, Default)]
+    struct ScriptedReplies {
+        replies: std::sync::Mutex<std::collections::VecDeque<&'static str>>,
+        calls: std::sync::atomic::AtomicUsize,
+    }
+
+    impl ScriptedReplies {
+        fn new(replies: impl IntoIterator<Item = &'static str>) -> Self {
+            Self {
+                replies: std::sync::Mutex::new(replies.into_iter().collect()),
+                calls: Default::default(),
+            }
+        }
+
+        fn calls(&self) -> usize {
+            self.calls.load(std::sync::atomic::Ordering::SeqCst)
+        }
+    }
+
+    #[async_trait]
+    impl grok_rust_sdk::provider::ChatProvider for ScriptedReplies {
+        async fn chat(
+            &self,
+            model: Model,
+            _messages: Vec<std::sync::Arc<Message>>,
+            _tools: Option<Vec<grok_rust_sdk::chat::Tool>>,
+            _options: Option<grok_rust_sdk::client::ChatOptions>,
+        ) -> grok_rust_sdk::Result<grok_rust_sdk::chat::ChatCompletion> {
+            self.calls.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+            let reply = self.replies.lock().unwrap().pop_front().unwrap();
+            Ok(grok_rust_sdk::chat::ChatCompletion {
+                id: "scripted".to_string(),
+                model: model.as_str().to_string(),
+                usage: None,
+                message: Message {
+                    role: Role::Assistant,
+                    content: reply.into(),
+                    tool_calls: None,
+                    tool_call_id: None,
+                    name: None,
+                },
+                finish_reason: Some("stop".to_string()),
+                timings: None,
+                annotations: Vec::new(),
+                rate_limit: None,
+            })
+        }
+
+        async fn chat_stream(
+            &self,
+            _model: Model,
+            _messages: Vec<Message>,
+            _tools: Option<Vec<grok_rust_sdk::chat::Tool>>,
+        ) -> grok_rust_sdk::Result<grok_rust_sdk::provider::ChunkStream> {
+            Ok(Box::pin(futures::stream::empty()))
+        }
+    }
+
+    #[tokio::test]
+    async fn test_classify_rejects_bad_label_sets() {
+        use grok_rust_sdk::classify::{classify, classify_multi};
+        use grok_rust_sdk::extract::ExtractOptions;
+
+        let provider = ScriptedReplies::default();
+        for labels in [&[][..], &["spam", "ham", "spam"][..]] {
+            let err = classify(&provider, "Hi".into(), labels, ExtractOptions::default())
+                .await
+                .unwrap_err();
+            assert!(matches!(err, GrokError::InvalidConfig(_)), "{:?}", err);
+            let err = classify_multi(&provider, "Hi".into(), labels, ExtractOptions::default())
+                .await
+                .unwrap_err();
+            assert!(matches!(err, GrokError::InvalidConfig(_)), "{:?}", err);
+        }
+        assert_eq!(provider.calls(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_classify_checks_replies() {
+        use grok_rust_sdk::classify::{classify, Classification};
+        use grok_rust_sdk::extract::ExtractOptions;
+
+        let labels = ["spam", "ham"];
+        let no_repairs = || ExtractOptions {
+            repair_attempts: 0,
+            ..Default::default()
+        };
+
+        // A label outside the set is sent back for repair
+        let provider = ScriptedReplies::new([
+            r#"{"label": "eggs"}"#,
+            r#"{"label": "ham", "confidence": 0.8}"#,
+        ]);
+        let classification = classify(&provider, "Hi".into(), &labels, ExtractOptions::default())
+            .await
+            .unwrap();
+        assert_eq!(
+            classification,
+            Classification {
+                label: "ham".to_string(),
+                confidence: Some(0.8),
+            }
+        );
+        assert_eq!(provider.calls(), 2);
+
+        let provider = ScriptedReplies::new([r#"{"label": "eggs"}"#]);
+        let err = classify(&provider, "Hi".into(), &labels, no_repairs())
+            .await
+            .unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::Serialization);
+        assert!(err.to_string().contains("'eggs' is not one of"), "{}", err);
+
+        // Confidences outside 0..=1 are rejected, not clamped
+        for reply in [
+            r#"{"label": "spam", "confidence": 1.5}"#,
+            r#"{"label": "spam", "confidence": -0.1}"#,
+        ] {
+            let provider = ScriptedReplies::new([reply]);
+            let err = classify(&provider, "Hi".into(), &labels, no_repairs())
+                .await
+                .unwrap_err();
+            assert!(err.to_string().contains("not between 0 and 1"), "{}", err);
+        }
+        let provider = ScriptedReplies::new([r#"{"label": "spam", "confidence": 1}"#]);
+        let classification = classify(&provider, "Hi".into(), &labels, no_repairs())
+            .await
+            .unwrap();
+        assert_eq!(classification.confidence, Some(1.0));
+    }
+
+    #[tokio::test]
+    async fn test_classify_multi_dedups_labels() {
+        use grok_rust_sdk::classify::classify_multi;
+        use grok_rust_sdk::extract::ExtractOptions;
+
+        let labels = ["billing", "bug", "urgent"];
+        let provider = ScriptedReplies::new([r#"{"labels": [
+            {"label": "urgent", "confidence": 0.9},
+            {"label": "bug"},
+            {"label": "urgent", "confidence": 0.4}
+        ]}"#]);
+        let result = classify_multi(&provider, "Hi".into(), &labels, ExtractOptions::default())
+            .await
+            .unwrap();
+        let found: Vec<(&str, Option<f64>)> = result
+            .iter()
+            .map(|c| (c.label.as_str(), c.confidence))
+            .collect();
+        assert_eq!(found, [("urgent", Some(0.9)), ("bug", None)]);
+
+        // Any label outside the set fails the whole reply
+        let provider =
+            ScriptedReplies::new([r#"{"labels": [{"label": "bug"}, {"label": "feature"}]}"#]);
+        let options = ExtractOptions {
+            repair_attempts: 0,
+            ..Default::default()
+        };
+        let err = classify_multi(&provider, "Hi".into(), &labels, options)
+            .await
+            .unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::Serialization);
+    }
+
+    #[test]
+    fn test_client_debug_masks_api_key() {
+        let client = grok_rust_sdk::Client::new("xai-0123456789abcdef").unwrap();
+        assert!(!format!("{:?}", client).contains("0123456789"));
+        assert_eq!(client.masked_key(), "xai-...cdef");
+    }
+
+    #[tokio::test]
+    async fn test_api_key_fetched_from_provider_once() {
+        use grok_rust_sdk::secrets::SecretProvider;
+        use grok_rust_sdk::vcr::{Cassette, Interaction, Vcr};
+        use std::sync::atomic::{AtomicUsize, Ordering};
+        use std::sync::Arc;
+
+        /// Hands out a fixed key, counting fetches
+        #[derive(Debug, Default)]
+        struct Counting(Arc<AtomicUsize>);
+
+        #[async_trait]
+        impl SecretProvider for Counting {
+            async fn fetch(&self) -> grok_rust_sdk::Result<String> {
+                self.0.fetch_add(1, Ordering::SeqCst);
+                Ok("xai-0123456789abcdef".to_string())
+            }
+        }
+
+        let interaction = Interaction {
+            endpoint: "/chat/completions".to_string(),
+            request: serde_json::json!({
+                "model": "grok-4",
+                "messages": [{ "role": "user", "content": "Hi" }]
+            }),
+            status: 200,
+            headers: Default::default(),
+            body: serde_json::json!({
+                "id": "cmpl-1",
+                "object": "chat.completion",
+                "created": 0,
+                "model": "grok-4",
+                "choices": [{
+                    "index": 0,
+                    "message": { "role": "assistant", "content": "Hello!" },
+                    "finish_reason": "stop"
+                }]
+            })
+            .to_string(),
+        };
+        let cassette = Cassette {
+            interactions: vec![interaction],
+        };
+        let provider = Counting::default();
+        let fetches = provider.0.clone();
+        let client = grok_rust_sdk::Client::builder()
+            .api_key_provider(provider)
+            .vcr(Vcr::from_cassette("chat.json", cassette))
+            .build()
+            .unwrap();
+        assert_eq!(fetches.load(Ordering::SeqCst), 0);
+        assert_eq!(client.masked_key(), "****");
+
+        let messages = vec![Message {
+            role: Role::User,
+            content: "Hi".into(),
+            tool_calls: None,
+            tool_call_id: None,
+            name: None,
+        }];
+        client
+            .chat(Model::Grok4, messages.clone(), None)
+            .await
+            .unwrap();
+        let _ = client.chat(Model::Grok4, messages, None).await;
+        assert_eq!(fetches.load(Ordering::SeqCst), 1);
+        assert_eq!(client.masked_key(), "xai-...cdef");
+    }
+
+    #[test]
+    fn test_api_error_parsing() {
+        let error = ApiError::from_response(
+            400,
+            r#"{"error": {"message": "Prompt is too long", "type": "invalid_request_error", "param": "messages", "code": "context_length_exceeded"}}"#,
+        );
+        assert_eq!(error.message, "Prompt is too long");
+        assert_eq!(error.code.as_deref(), Some("context_length_exceeded"));
+        assert_eq!(error.error_type.as_deref(), Some("invalid_request_error"));
+        assert_eq!(error.param.as_deref(), Some("messages"));
+
+        let error = ApiError::from_response(502, "Bad Gateway");
+        assert_eq!(error.message, "Bad Gateway");
+        assert_eq!(error.code, None);
+    }
+
+    #[tokio::test]
+    async fn test_interrupted_stream_keeps_partial_output() {
+        let chunk = |content: &str| -> Result<ChatChunk, GrokError> {
+            Ok(serde_json::from_value(serde_json::json!({
+                "id": "chatcmpl-1",
+                "object": "chat.completion.chunk",
+                "created": 0,
+                "model": "grok-4",
+                "choices": [{ "index": 0, "delta": { "content": content }, "finish_reason": null }]
+            }))
+            .unwrap())
+        };
+        let stream = futures::stream::iter(vec![
+            chunk("Hello, "),
+            chunk("wor"),
+            Err(GrokError::Timeout {
+                elapsed: std::time::Duration::from_secs(5),
+                phase: TimeoutPhase::StreamIdle,
+            }),
+        ]);
+
+        let Err(GrokError::StreamInterrupted { partial, error }) = collect_completion(stream).await
+        else {
+            panic!("expected an interrupted stream");
+        };
+        assert_eq!(partial.message.content, "Hello, wor");
+        assert!(partial.timings.is_some());
+        assert!(error.is_timeout());
+    }
+
+    #[tokio::test]
+    async fn test_stream_to_writer_and_token_callback() {
+        use grok_rust_sdk::chat::{collect_completion_with, stream_to_writer};
+
+        let chunk = |content: &str| -> Result<ChatChunk, GrokError> {
+            Ok(serde_json::from_value(serde_json::json!({
+                "id": "chatcmpl-1",
+                "object": "chat.completion.chunk",
+                "created": 0,
+                "model": "grok-4",
+                "choices": [{ "index": 0, "delta": { "content": content }, "finish_reason": null }]
+            }))
+            .unwrap())
+        };
+
+        let mut output = Vec::new();
+        let stream = futures::stream::iter(vec![chunk("Hello, "), chunk("world!")]);
+        let completion = stream_to_writer(stream, &mut output).await.unwrap();
+        assert_eq!(output, b"Hello, world!");
+        assert_eq!(completion.message.content, "Hello, world!");
+
+        let mut tokens = Vec::new();
+        let stream = futures::stream::iter(vec![chunk("Hello, "), chunk("world!")]);
+        collect_completion_with(stream, |token| tokens.push(token.to_string()))
+            .await
+            .unwrap();
+        assert_eq!(tokens, ["Hello, ", "world!"]);
+    }
+
+    #[tokio::test]
+    async fn test_stream_events_through_channels() {
+        use futures::StreamExt;
+        use grok_rust_sdk::channel::{
+            from_broadcast, from_mpsc, into_broadcast, into_mpsc, StreamEvent,
+        };
+
+        let chunk = |content: &str| -> Result<ChatChunk, GrokError> {
+            Ok(serde_json::from_value(serde_json::json!({
+                "id": "chatcmpl-1",
+                "object": "chat.completion.chunk",
+                "created": 0,
+                "model": "grok-4",
+                "choices": [{ "index": 0, "delta": { "content": content }, "finish_reason": null }]
+            }))
+            .unwrap())
+        };
+
+        let stream = futures::stream::iter(vec![chunk("Hello, "), chunk("world!")]);
+        let events: Vec<StreamEvent> = from_mpsc(into_mpsc(stream, 1)).collect().await;
+        assert!(matches!(&events[0], StreamEvent::Delta(text) if text == "Hello, "));
+        assert!(matches!(&events[1], StreamEvent::Delta(text) if text == "world!"));
+        let StreamEvent::Done(completion) = &events[2] else {
+            panic!("expected the stream to finish");
+        };
+        assert_eq!(completion.message.content, "Hello, world!");
+        assert_eq!(events.len(), 3);
+
+        let stream = futures::stream::iter(vec![
+            chunk("Hel"),
+            Err(GrokError::Timeout {
+                elapsed: std::time::Duration::from_secs(5),
+                phase: TimeoutPhase::StreamIdle,
+            }),
+        ]);
+        let receiver = into_broadcast(stream, 8);
+        let second = receiver.resubscribe();
+        for receiver in [receiver, second] {
+            let events: Vec<StreamEvent> = from_broadcast(receiver).collect().await;
+            assert_eq!(events.len(), 2);
+            let StreamEvent::Error(error) = &events[1] else {
+                panic!("expected the stream to fail");
+            };
+            assert!(matches!(
+                error.as_ref(),
+                GrokError::StreamInterrupted { partial, .. } if partial.message.content == "Hel"
+            ));
+        }
+    }
+
+    #[test]
+    fn test_chat_options_validation() {
+        let options = grok_rust_sdk::client::ChatOptions {
+            temperature: Some(3.0),
+            top_p: Some(0.9),
+            max_tokens: Some(0),
+            ..Default::default()
+        };
+        let Err(GrokError::Validation(errors)) = options.validate() else {
+            panic!("expected a validation error");
+        };
+        let fields: Vec<&str> = errors.iter().map(|e| e.field.as_str()).collect();
+        assert_eq!(fields, ["temperature", "max_tokens"]);
+        assert_eq!(errors[0].actual, "3");
+    }
+
+    #[tokio::test]
+    async fn test_client_clones_keep_http_configuration() {
+        use std::io::{BufRead, BufReader, Write};
+
+        // A one-shot server that reports the request headers it received
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        let server = std::thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            let mut reader = BufReader::new(stream.try_clone().unwrap());
+            let mut headers = Vec::new();
+            let mut content_length = 0;
+            loop {
+                let mut line = String::new();
+                reader.read_line(&mut line).unwrap();
+                let line = line.trim_end().to_ascii_lowercase();
+                if line.is_empty() {
+                    break;
+                }
+                if let Some(length) = line.strip_prefix("content-length:") {
+                    content_length = length.trim().parse().unwrap();
+                }
+                headers.push(line);
+            }
+            let mut body = vec![0; content_length];
+            std::io::Read::read_exact(&mut reader, &mut body).unwrap();
+
+            let response = serde_json::json!({
+                "id": "cmpl-1",
+                "object": "chat.completion",
+                "created": 0,
+                "model": "grok-4",
+                "choices": [{
+                    "index": 0,
+                    "message": { "role": "assistant", "content": "Hello!" },
+                    "finish_reason": "stop"
+                }]
+            })
+            .to_string();
+            write!(
+                &stream,
+                "HTTP/1.1 200 OK\r\ncontent-type: application/json\r\ncontent-length: {}\r\n\r\n{}",
+                response.len(),
+                response
+            )
+            .unwrap();
+            headers
+        });
+
+        let client = grok_rust_sdk::Client::builder()
+            .api_key("test-key")
+            .base_url(format!("http://{}", address))
+            .user_agent("MyApp/1.0")
+            .max_retries(0)
+            .build()
+            .unwrap();
+        let messages = vec![Message {
+            role: Role::User,
+            content: "Hi".into(),
+            tool_calls: None,
+            tool_call_id: None,
+            name: None,
+        }];
+        let completion = client
+            .clone()
+            .chat(Model::Grok4, messages, None)
+            .await
+            .unwrap();
+        assert_eq!(completion.message.content, "Hello!");
+
+        let headers = server.join().unwrap();
+        assert!(headers.contains(&"user-agent: myapp/1.0".to_string()));
+    }
+
+    #[tokio::test]
+    async fn test_middleware_changes_requests_and_sees_responses() {
+        use grok_rust_sdk::middleware::{OutgoingRequest, RequestInterceptor, ResponseInfo};
+        use grok_rust_sdk::vcr::{Cassette, Interaction, Vcr};
+        use std::sync::{Arc, Mutex};
+
+        /// Tags requests with a tenant and keeps the request IDs of responses
+        #[derive(Debug, Default)]
+        struct Tenant(Mutex<Vec<(u16, String)>>);
+
+        #[async_trait]
+        impl RequestInterceptor for Tenant {
+            async fn before_request(
+                &self,
+                request: &mut OutgoingRequest,
+            ) -> grok_rust_sdk::Result<()> {
+                if request.body.get("messages").is_none() {
+                    return Err(GrokError::InvalidConfig("not a chat".to_string()));
+                }
+                request.headers.insert("x-tenant", "acme".parse().unwrap());
+                request.body["user"] = "acme".into();
+                Ok(())
+            }
+
+            async fn after_response(&self, response: &ResponseInfo<'_>) {
+                let request_id = response.headers["x-request-id"].to_str().unwrap();
+                self.0
+                    .lock()
+                    .unwrap()
+                    .push((response.status, request_id.to_string()));
+            }
+        }
+
+        // The recording only matches the request as the interceptor rewrote it
+        let interaction = Interaction {
+            endpoint: "/chat/completions".to_string(),
+            request: serde_json::json!({
+                "model": "grok-4",
+                "messages": [{ "role": "user", "content": "Hi" }],
+                "user": "acme"
+            }),
+            status: 200,
+            headers: [("x-request-id".to_string(), "req-7".to_string())].into(),
+            body: serde_json::json!({
+                "id": "cmpl-1",
+                "object": "chat.completion",
+                "created": 0,
+                "model": "grok-4",
+                "choices": [{
+                    "index": 0,
+                    "message": { "role": "assistant", "content": "Hello!" },
+                    "finish_reason": "stop"
+                }]
+            })
+            .to_string(),
+        };
+        let tenant = Arc::new(Tenant::default());
+        let client = grok_rust_sdk::Client::builder()
+            .api_key("unused")
+            .vcr(Vcr::from_cassette(
+                "tenant.json",
+                Cassette {
+                    interactions: vec![interaction],
+                },
+            ))
+            .with_middleware(tenant.clone())
+            .max_retries(0)
+            .build()
+            .unwrap();
+
+        let messages = vec![Message {
+            role: Role::User,
+            content: "Hi".into(),
+            tool_calls: None,
+            tool_call_id: None,
+            name: None,
+        }];
+        let completion = client.chat(Model::Grok4, messages, None).await.unwrap();
+        assert_eq!(completion.message.content, "Hello!");
+        assert_eq!(*tenant.0.lock().unwrap(), [(200, "req-7".to_string())]);
+
+        // A failing interceptor stops the request before it's sent
+        let err = client
+            .embed("grok-embed", vec!["Hi".to_string()])
+            .await
+            .unwrap_err();
+        assert!(matches!(err, GrokError::InvalidConfig(ref message) if message == "not a chat"));
+        assert_eq!(tenant.0.lock().unwrap().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_vcr_replays_requests_with_several_tools() {
+        use grok_rust_sdk::tools::Tool;
+        use grok_rust_sdk::vcr::{Cassette, Interaction, Vcr};
+
+        let names = ["weather", "add", "lookup", "clock"];
+        let registry = ToolRegistry::new();
+        for name in names {
+            registry.register_fn(
+                name,
+                format!("The {} tool", name),
+                serde_json::json!({ "type": "object", "properties": {} }),
+                |_| async { Ok(serde_json::json!({})) },
+            );
+        }
+
+        // The recording lists the tools by name, whatever order the registry holds them in
+        let mut sorted = names;
+        sorted.sort();
+        let tools: Vec<Tool> = sorted
+            .iter()
+            .map(|name| {
+                Tool::new(
+                    *name,
+                    format!("The {} tool", name),
+                    serde_json::json!({ "type": "object", "properties": {} }),
+                )
+            })
+            .collect();
+        let interaction = Interaction {
+            endpoint: "/chat/completions".to_string(),
+            request: serde_json::json!({
+                "model": "grok-4",
+                "messages": [{ "role": "user", "content": "Hi" }],
+                "tools": tools
+            }),
+            status: 200,
+            headers: Default::default(),
+            body: serde_json::json!({
+                "id": "cmpl-1",
+                "object": "chat.completion",
+                "created": 0,
+                "model": "grok-4",
+                "choices": [{
+                    "index": 0,
+                    "message": { "role": "assistant", "content": "Hello!" },
+                    "finish_reason": "stop"
+                }]
+            })
+            .to_string(),
+        };
+        let client = grok_rust_sdk::Client::builder()
+            .api_key("unused")
+            .vcr(Vcr::from_cassette(
+                "tools.json",
+                Cassette {
+                    interactions: vec![interaction],
+                },
+            ))
+            .build()
+            .unwrap();
+
+        let messages = vec![Message {
+            role: Role::User,
+            content: "Hi".into(),
+            tool_calls: None,
+            tool_call_id: None,
+            name: None,
+        }];
+        let completion = client
+            .chat(
+                Model::Grok4,
+                messages,
+                Some(registry.api_tools().into_iter().map(Into::into).collect()),
+            )
+            .await
+            .unwrap();
+        assert_eq!(completion.message.content, "Hello!");
+    }
+
+    #[tokio::test]
+    async fn test_vcr_replay() {
+        use grok_rust_sdk::vcr::{Cassette, Interaction, Vcr};
+
+        let interaction = Interaction {
+            endpoint: "/chat/completions".to_string(),
+            request: serde_json::json!({
+                "model": "grok-4",
+                "messages": [{ "role": "user", "content": "Hi" }]
+            }),
+            status: 200,
+            headers: [
+                ("x-ratelimit-remaining-requests", "59"),
+                ("x-ratelimit-remaining-tokens", "149000"),
+                ("x-ratelimit-reset-tokens", "1m30s"),
+            ]
+            .into_iter()
+            .map(|(name, value)| (name.to_string(), value.to_string()))
+            .collect(),
+            body: serde_json::json!({
+                "id": "cmpl-1",
+                "object": "chat.completion",
+                "created": 0,
+                "model": "grok-4",
+                "choices": [{
+                    "index": 0,
+                    "message": { "role": "assistant", "content": "Hello!" },
+                    "finish_reason": "stop"
+                }]
+            })
+            .to_string(),
+        };
+        let cassette = Cassette {
+            interactions: vec![interaction],
+        };
+        let client = grok_rust_sdk::Client::builder()
+            .api_key("unused")
+            .vcr(Vcr::from_cassette("chat.json", cassette))
+            .build()
+            .unwrap();
+
+        let messages = vec![Message {
+            role: Role::User,
+            content: "Hi".into(),
+            tool_calls: None,
+            tool_call_id: None,
+            name: None,
+        }];
+        let completion = client
+            .chat(Model::Grok4, messages.clone(), None)
+            .await
+            .unwrap();
+        assert_eq!(completion.message.content, "Hello!");
+        let rate_limit = completion.rate_limit.unwrap();
+        assert_eq!(rate_limit.remaining_requests, Some(59));
+        assert_eq!(rate_limit.remaining_tokens, Some(149000));
+        assert_eq!(
+            rate_limit.reset_tokens,
+            Some(std::time::Duration::from_secs(90))
+        );
+        assert_eq!(rate_limit.limit_requests, None);
+        assert_eq!(client.last_rate_limit(), Some(rate_limit));
+
+        // Each recording is served once
+        let err = client.chat(Model::Grok4, messages, None).await.unwrap_err();
+        assert!(matches!(err, GrokError::InvalidConfig(_)));
+    }
+
+    #[tokio::test]
+    async fn test_chat_stream_with_options() {
+        use futures::StreamExt;
+        use grok_rust_sdk::client::ChatOptions;
+        use grok_rust_sdk::vcr::{Cassette, Interaction, Vcr};
+
+        let event = serde_json::json!({
+            "id": "chatcmpl-1",
+            "object": "chat.completion.chunk",
+            "created": 0,
+            "model": "grok-4",
+            "choices": [{ "index": 0, "delta": { "content": "Hi" }, "finish_reason": null }]
+        });
+        // The recording only matches a request carrying the options
+        let cassette = Cassette {
+            interactions: vec![Interaction {
+                endpoint: "/chat/completions".to_string(),
+                request: serde_json::json!({
+                    "model": "grok-4",
+                    "messages": [{ "role": "user", "content": "Hi" }],
+                    "max_tokens": 16,
+                    "temperature": 0.5,
+                    "stop": ["\n"],
+                    "stream": true
+                }),
+                status: 200,
+                headers: Default::default(),
+                body: format!("data: {event}\n\ndata: [DONE]\n\n"),
+            }],
+        };
+        let client = grok_rust_sdk::Client::builder()
+            .api_key("unused")
+            .vcr(Vcr::from_cassette("stream-options.json", cassette))
+            .build()
+            .unwrap();
+
+        let messages = vec![Message {
+            role: Role::User,
+            content: "Hi".into(),
+            tool_calls: None,
+            tool_call_id: None,
+            name: None,
+        }];
+        let options = ChatOptions {
+            max_tokens: Some(16),
+            temperature: Some(0.5),
+            stop: Some(vec!["\n".to_string()]),
+            ..Default::default()
+        };
+        let chunks: Vec<_> = client
+            .chat_stream_with_options(Model::Grok4, messages.clone(), None, Some(options))
+            .await
+            .unwrap()
+            .collect()
+            .await;
+        assert_eq!(chunks.len(), 1);
+        assert_eq!(
+            chunks[0].as_ref().unwrap().choices[0]
+                .delta
+                .content
+                .as_deref(),
+            Some("Hi")
+        );
+
+        // Options are validated as for non-streaming requests
+        let invalid = ChatOptions {
+            temperature: Some(3.0),
+            ..Default::default()
+        };
+        let err = client
+            .chat_stream_with_options(Model::Grok4, messages, None, Some(invalid))
+            .await
+            .err()
+            .unwrap();
+        assert!(matches!(err, GrokError::Validation(_)));
+    }
+
+    #[tokio::test]
+    async fn test_stream_buffer_limit() {
+        use futures::StreamExt;
+        use grok_rust_sdk::vcr::{Cassette, Interaction, Vcr};
+
+        let event = serde_json::json!({
+            "id": "chatcmpl-1",
+            "object": "chat.completion.chunk",
+            "created": 0,
+            "model": "grok-4",
+            "choices": [{ "index": 0, "delta": { "content": "Hi" }, "finish_reason": null }]
+        });
+        // A complete event, then one that never ends
+        let body = format!("data: {}\n\ndata: {}", event, "x".repeat(512));
+        let cassette = Cassette {
+            interactions: vec![Interaction {
+                endpoint: "/chat/completions".to_string(),
+                request: serde_json::json!({
+                    "model": "grok-4",
+                    "messages": [{ "role": "user", "content": "Hi" }],
+                    "stream": true
+                }),
+                status: 200,
+                headers: Default::default(),
+                body,
+            }],
+        };
+        let client = grok_rust_sdk::Client::builder()
+            .api_key("unused")
+            .vcr(Vcr::from_cassette("stream.json", cassette))
+            .max_stream_buffer(256)
+            .build()
+            .unwrap();
+
+        let message = Message {
+            role: Role::User,
+            content: "Hi".into(),
+            tool_calls: None,
+            tool_call_id: None,
+            name: None,
+        };
+        let items: Vec<_> = client
+            .chat_stream(Model::Grok4, vec![message], None)
+            .await
+            .unwrap()
+            .collect()
+            .await;
+        assert_eq!(items.len(), 2);
+        assert!(items[0].is_ok());
+        assert!(matches!(
+            items[1],
+            Err(GrokError::StreamBufferOverflow { limit: 256 })
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_slow_stream_consumer_overflow() {
+        use futures::StreamExt;
+        use grok_rust_sdk::client::StreamOverflowPolicy;
+        use grok_rust_sdk::vcr::{Cassette, Interaction, Vcr};
+
+        let event = serde_json::json!({
+            "id": "chatcmpl-1",
+            "object": "chat.completion.chunk",
+            "created": 0,
+            "model": "grok-4",
+            "choices": [{ "index": 0, "delta": { "content": "Hi" }, "finish_reason": null }]
+        });
+        let cassette = Cassette {
+            interactions: vec![Interaction {
+                endpoint: "/chat/completions".to_string(),
+                request: serde_json::json!({
+                    "model": "grok-4",
+                    "messages": [{ "role": "user", "content": "Hi" }],
+                    "stream": true
+                }),
+                status: 200,
+                headers: Default::default(),
+                body: format!("data: {event}\n\ndata: {event}\n\ndata: {event}\n\n"),
+            }],
+        };
+        let client = grok_rust_sdk::Client::builder()
+            .api_key("unused")
+            .vcr(Vcr::from_cassette("stream.json", cassette))
+            .stream_capacity(1)
+            .stream_overflow_policy(StreamOverflowPolicy::Fail)
+            .build()
+            .unwrap();
+
+        let message = Message {
+            role: Role::User,
+            content: "Hi".into(),
+            tool_calls: None,
+            tool_call_id: None,
+            name: None,
+        };
+        let stream = client
+            .chat_stream(Model::Grok4, vec![message], None)
+            .await
+            .unwrap();
+        // Let the stream get ahead of the consumer
+        tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+
+        let items: Vec<_> = stream.collect().await;
+        assert_eq!(items.len(), 2);
+        assert!(items[0].is_ok());
+        assert!(matches!(
+            items[1],
+            Err(GrokError::StreamLagged { capacity: 1 })
+        ));
+    }
+
+    /// Read one HTTP request from a local socket, returning its body
+    fn read_request_body(stream: &std::net::TcpStream) -> Vec<u8> {
+        use std::io::{BufRead, BufReader, Read};
+
+        let mut reader = BufReader::new(stream);
+        let mut content_length = 0;
+        loop {
+            let mut line = String::new();
+            reader.read_line(&mut line).unwrap();
+            let line = line.trim_end().to_ascii_lowercase();
+            if line.is_empty() {
+                break;
+            }
+            if let Some(length) = line.strip_prefix("content-length:") {
+                content_length = length.trim().parse().unwrap();
+            }
+        }
+        let mut body = vec![0; content_length];
+        reader.read_exact(&mut body).unwrap();
+        body
+    }
+
+    /// Serve one streamed chat completion with the given SSE body from a local socket
+    fn serve_sse(body: String) -> std::net::SocketAddr {
+        use std::io::Write;
+
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        std::thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            read_request_body(&stream);
+
+            write!(
+                &stream,
+                "HTTP/1.1 200 OK\r\ncontent-type: text/event-stream\r\ncontent-length: {}\r\n\r\n{}",
+                body.len(),
+                body
+            )
+            .unwrap();
+        });
+        address
+    }
+
+    /// Stream a completion from a local server under the given decode policy
+    async fn stream_items(
+        body: String,
+        policy: grok_rust_sdk::client::StreamDecodePolicy,
+    ) -> Vec<grok_rust_sdk::Result<grok_rust_sdk::chat::ChatChunk>> {
+        use futures::StreamExt;
+
+        let address = serve_sse(body);
+        let client = grok_rust_sdk::Client::builder()
+            .api_key("test-key")
+            .base_url(format!("http://{}", address))
+            .max_retries(0)
+            .stream_decode_policy(policy)
+            .build()
+            .unwrap();
+        client
+            .chat_stream(Model::Grok4, vec![user_message("Hi")], None)
+            .await
+            .unwrap()
+            .collect()
+            .await
+    }
+
+    fn stream_event(content: &str) -> String {
+        serde_json::json!({
+            "id": "chatcmpl-1",
+            "object": "chat.completion.chunk",
+            "created": 0,
+            "model": "grok-4",
+            "choices": [{ "index": 0, "delta": { "content": content }, "finish_reason": null }]
+        })
+        .to_string()
+    }
+
+    #[tokio::test]
+    async fn test_stream_decode_policy() {
+        use grok_rust_sdk::client::StreamDecodePolicy;
+
+        let body = format!(
+            "data: {}\n\ndata: {{\"unexpected\": true}}\n\ndata: {}\n\ndata: [DONE]\n\n",
+            stream_event("Hello"),
+            stream_event(" world")
+        );
+
+        // The malformed chunk is reported, then streaming carries on
+        let items = stream_items(body.clone(), StreamDecodePolicy::Continue).await;
+        assert_eq!(items.len(), 3);
+        assert_eq!(
+            items[0].as_ref().unwrap().choices[0]
+                .delta
+                .content
+                .as_deref(),
+            Some("Hello")
+        );
+        let Err(GrokError::StreamDecode { data, .. }) = &items[1] else {
+            panic!("expected a decode error, got {:?}", items[1]);
+        };
+        assert_eq!(data, "{\"unexpected\": true}");
+        assert_eq!(
+            items[2].as_ref().unwrap().choices[0]
+                .delta
+                .content
+                .as_deref(),
+            Some(" world")
+        );
+
+        // The malformed chunk ends the stream
+        let items = stream_items(body, StreamDecodePolicy::Fail).await;
+        assert_eq!(items.len(), 2);
+        assert!(items[0].is_ok());
+        assert!(matches!(items[1], Err(GrokError::StreamDecode { .. })));
+    }
+
+    #[tokio::test]
+    async fn test_stream_error_event() {
+        use grok_rust_sdk::client::StreamDecodePolicy;
+
+        let error = serde_json::json!({
+            "error": {
+                "message": "The server is overloaded",
+                "type": "server_error",
+                "code": "overloaded"
+            }
+        });
+        let body = format!("data: {}\n\ndata: {}\n\n", stream_event("Hello"), error);
+
+        let items = stream_items(body, StreamDecodePolicy::Fail).await;
+        assert_eq!(items.len(), 2);
+        assert!(items[0].is_ok());
+        let Err(GrokError::Api(api_error)) = &items[1] else {
+            panic!("expected an API error, got {:?}", items[1]);
+        };
+        assert_eq!(api_error.message, "The server is overloaded");
+        assert_eq!(api_error.error_type.as_deref(), Some("server_error"));
+        assert_eq!(api_error.code.as_deref(), Some("overloaded"));
+    }
+
+    #[tokio::test]
+    async fn test_shutdown_refuses_new_requests() {
+        let client = grok_rust_sdk::Client::new("test-key").unwrap();
+        let clone = client.clone();
+        client
+            .shutdown(std::time::Duration::from_secs(1))
+            .await
+            .unwrap();
+
+        let message = Message {
+            role: Role::User,
+            content: "Hi".into(),
+            tool_calls: None,
+            tool_call_id: None,
+            name: None,
+        };
+        let err = clone
+            .chat(Model::Grok4, vec![message], None)
+            .await
+            .unwrap_err();
+        assert!(matches!(err, GrokError::ShuttingDown));
+        assert_eq!(err.kind(), ErrorKind::Shutdown);
+    }
+
+    #[tokio::test]
+    async fn test_retry_budget_limits_retries() {
+        use grok_rust_sdk::client::RetryBudget;
+        use grok_rust_sdk::vcr::{Cassette, Interaction, Vcr};
+
+        let request = serde_json::json!({
+            "model": "grok-4",
+            "messages": [{ "role": "user", "content": "Hi" }]
+        });
+        let unavailable = Interaction {
+            endpoint: "/chat/completions".to_string(),
+            request: request.clone(),
+            status: 503,
+            headers: Default::default(),
+            body: r#"{"error": {"message": "overloaded"}}"#.to_string(),
+        };
+        let ok = Interaction {
+            status: 200,
+            body: serde_json::json!({
+                "id": "cmpl-1",
+                "object": "chat.completion",
+                "created": 0,
+                "model": "grok-4",
+                "choices": [{
+                    "index": 0,
+                    "message": { "role": "assistant", "content": "Hello!" },
+                    "finish_reason": "stop"
+                }]
+            })
+            .to_string(),
+            ..unavailable.clone()
+        };
+        let cassette = Cassette {
+            interactions: vec![unavailable.clone(), unavailable, ok],
+        };
+        // Enough budget for one retry, but the request needs two
+        let client = grok_rust_sdk::Client::builder()
+            .api_key("unused")
+            .vcr(Vcr::from_cassette("retry.json", cassette))
+            .retry_delay(std::time::Duration::from_millis(1))
+            .retry_budget(Some(RetryBudget {
+                max_tokens: 1.0,
+                ratio: 0.0,
+            }))
+            .build()
+            .unwrap();
+
+        let message = Message {
+            role: Role::User,
+            content: "Hi".into(),
+            tool_calls: None,
+            tool_call_id: None,
+            name: None,
+        };
+        let err = client
+            .chat(Model::Grok4, vec![message], None)
+            .await
+            .unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::Server);
+    }
+
+    #[tokio::test]
+    async fn test_retry_waits_as_long_as_rate_limit_asks() {
+        use grok_rust_sdk::vcr::{Cassette, Interaction, Vcr};
+        use std::time::Duration;
+
+        let headers = |pairs: &[(&str, &str)]| {
+            pairs
+                .iter()
+                .map(|(name, value)| (name.to_string(), value.to_string()))
+                .collect()
+        };
+        let limited = Interaction {
+            endpoint: "/chat/completions".to_string(),
+            request: serde_json::json!({
+                "model": "grok-4",
+                "messages": [{ "role": "user", "content": "Hi" }]
+            }),
+            status: 429,
+            headers: headers(&[("retry-after", "0")]),
+            body: r#"{"error": {"message": "slow down"}}"#.to_string(),
+        };
+        let ok = Interaction {
+            status: 200,
+            headers: Default::default(),
+            body: serde_json::json!({
+                "id": "cmpl-1",
+                "object": "chat.completion",
+                "created": 0,
+                "model": "grok-4",
+                "choices": [{
+                    "index": 0,
+                    "message": { "role": "assistant", "content": "Hello!" },
+                    "finish_reason": "stop"
+                }]
+            })
+            .to_string(),
+            ..limited.clone()
+        };
+        let message = Message {
+            role: Role::User,
+            content: "Hi".into(),
+            tool_calls: None,
+            tool_call_id: None,
+            name: None,
+        };
+
+        // Backing off a minute would miss the deadline; Retry-After says not to wait
+        let client = grok_rust_sdk::Client::builder()
+            .api_key("unused")
+            .vcr(Vcr::from_cassette(
+                "retry-after.json",
+                Cassette {
+                    interactions: vec![limited.clone(), ok],
+                },
+            ))
+            .retry_delay(Duration::from_secs(60))
+            .request_deadline(Duration::from_secs(5))
+            .build()
+            .unwrap();
+        let completion = client
+            .chat(Model::Grok4, vec![message.clone()], None)
+            .await
+            .unwrap();
+        assert_eq!(completion.message.content, "Hello!");
+
+        // Without Retry-After, the reset of the exhausted quota is used
+        let quota_only = Interaction {
+            headers: headers(&[
+                ("x-ratelimit-remaining-requests", "0"),
+                ("x-ratelimit-reset-requests", "1.5s"),
+                ("x-ratelimit-remaining-tokens", "9000"),
+                ("x-ratelimit-reset-tokens", "1m"),
+            ]),
+            ..limited.clone()
+        };
+        let client = grok_rust_sdk::Client::builder()
+            .api_key("unused")
+            .vcr(Vcr::from_cassette(
+                "quota.json",
+                Cassette {
+                    interactions: vec![quota_only],
+                },
+            ))
+            .max_retries(0)
+            .build()
+            .unwrap();
+        let err = client
+            .chat(Model::Grok4, vec![message.clone()], None)
+            .await
+            .unwrap_err();
+        assert!(err.is_rate_limit());
+        assert_eq!(err.retry_after(), Some(Duration::from_secs(2)));
+
+        // A wait longer than the retry delay cap is returned instead of slept through
+        let day = Interaction {
+            headers: headers(&[("retry-after", "86400")]),
+            ..limited
+        };
+        let client = grok_rust_sdk::Client::builder()
+            .api_key("unused")
+            .vcr(Vcr::from_cassette(
+                "retry-after-day.json",
+                Cassette {
+                    interactions: vec![day],
+                },
+            ))
+            .build()
+            .unwrap();
+        let err = tokio::time::timeout(
+            Duration::from_secs(5),
+            client.chat(Model::Grok4, vec![message], None),
+        )
+        .await
+        .unwrap()
+        .unwrap_err();
+        assert!(err.is_rate_limit());
+        assert_eq!(err.retry_after(), Some(Duration::from_secs(86400)));
+    }
+
+    #[tokio::test]
+    async fn test_error_status_mapping() {
+        use grok_rust_sdk::vcr::{Cassette, Interaction, Vcr};
+
+        let fail = |status: u16, headers: &[(&str, &str)], message: &str| {
+            let interaction = Interaction {
+                endpoint: "/chat/completions".to_string(),
+                request: serde_json::json!({
+                    "model": "grok-4",
+                    "messages": [{ "role": "user", "content": "Hi" }]
+                }),
+                status,
+                headers: headers
+                    .iter()
+                    .map(|(name, value)| (name.to_string(), value.to_string()))
+                    .collect(),
+                body: serde_json::json!({ "error": { "message": message } }).to_string(),
+            };
+            let client = grok_rust_sdk::Client::builder()
+                .api_key("unused")
+                .request_id("client-req")
+                .vcr(Vcr::from_cassette(
+                    "status.json",
+                    Cassette {
+                        interactions: vec![interaction],
+                    },
+                ))
+                .max_retries(0)
+                .build()
+                .unwrap();
+            async move {
+                client
+                    .chat(Model::Grok4, vec![user_message("Hi")], None)
+                    .await
+                    .unwrap_err()
+            }
+        };
+
+        // 401 and 403 keep the server's request ID, falling back to the client's
+        let err = fail(401, &[("x-request-id", "req-401")], "Incorrect API key").await;
+        match &err {
+            GrokError::Authentication {
+                message,
+                request_id,
+            } => {
+                assert_eq!(message, "Incorrect API key");
+                assert_eq!(request_id.as_deref(), Some("req-401"));
+            }
+            other => panic!("expected an authentication error, got {:?}", other),
+        }
+        assert!(err.is_auth() && !err.is_retryable());
+        assert_eq!(
+            err.to_string(),
+            "Authentication error: Incorrect API key (request ID: req-401)"
+        );
+
+        let err = fail(403, &[], "Key lacks access to grok-4").await;
+        assert!(matches!(
+            err,
+            GrokError::Authentication { ref request_id, .. }
+                if request_id.as_deref() == Some("client-req")
+        ));
+
+        // 429 carries the Retry-After delay and the rate-limit headers
+        let err = fail(
+            429,
+            &[
+                ("retry-after", "7"),
+                ("x-request-id", "req-429"),
+                ("x-ratelimit-remaining-tokens", "0"),
+            ],
+            "Slow down",
+        )
+        .await;
+        match &err {
+            GrokError::RateLimit {
+                retry_after,
+                request_id,
+                headers,
+            } => {
+                assert_eq!(*retry_after, Some(7));
+                assert_eq!(request_id.as_deref(), Some("req-429"));
+                assert_eq!(
+                    headers
+                        .get("x-ratelimit-remaining-tokens")
+                        .map(String::as_str),
+                    Some("0")
+                );
+            }
+            other => panic!("expected a rate limit error, got {:?}", other),
+        }
+        assert!(err.is_rate_limit() && err.is_retryable());
+
+        // Other statuses stay structured API errors
+        let err = fail(400, &[("x-request-id", "req-400")], "Bad temperature").await;
+        match err {
+            GrokError::Api(api) => {
+                assert_eq!((api.status, api.message.as_str()), (400, "Bad temperature"));
+                assert_eq!(api.request_id.as_deref(), Some("req-400"));
+            }
+            other => panic!("expected an API error, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_error_kinds() {
+        let context = GrokError::Api(Box::new(ApiError::from_response(
+            400,
+            r#"{"error": {"message": "Prompt is too long", "code": "context_length_exceeded"}}"#,
+        )));
+        assert_eq!(context.kind(), ErrorKind::ContextLength);
+        assert!(context.is_context_length());
+        assert!(!context.is_retryable());
+
+        assert!(GrokError::Api(Box::new(ApiError::new(429, "slow down"))).is_rate_limit());
+        assert!(GrokError::Api(Box::new(ApiError::new(503, "unavailable"))).is_retryable());
+        assert!(GrokError::Api(Box::new(ApiError::new(401, "bad key"))).is_auth());
+        let limited = GrokError::RateLimit {
+            retry_after: Some(2),
+            request_id: Some("req-42".to_string()),
+            headers: Default::default(),
+        };
+        assert!(limited.to_string().contains("request ID: req-42"));
+
+        let timeout = GrokError::Timeout {
+            elapsed: std::time::Duration::from_secs(30),
+            phase: TimeoutPhase::StreamIdle,
+        };
+        assert!(timeout.is_timeout() && timeout.is_retryable());
+
+        let missing: Result<(), GrokError> = Err(GrokError::NotFound {
+            entity: Entity::Session,
+            id: "abc".to_string(),
+        });
+        let error = missing.context("Loading transcript").unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "Loading transcript: Session 'abc' not found"
+        );
+        assert_eq!(error.kind(), ErrorKind::NotFound);
+        assert!(std::error::Error::source(&error).is_some());
+        let failed = GrokError::Session {
+            op: "load session".to_string(),
+            id: Some("abc".to_string()),
+            source: "disk I/O error".into(),
+        };
+        assert_eq!(failed.kind(), ErrorKind::Session);
+        assert_eq!(
+            failed.to_string(),
+            "Session 'abc': failed to load session: disk I/O error"
+        );
+        assert!(std::error::Error::source(&failed).is_some());
+    }
+
+    #[tokio::test]
+    async fn test_collection_errors_name_the_collection() {
+        use grok_rust_sdk::collections::Collection;
+        use grok_rust_sdk::session::Session;
+        use std::sync::Arc;
+
+        let now = chrono::Utc::now();
+        let mut session =
+            Session::restore("s-1".to_string(), Model::Grok4, None, now, now, Vec::new());
+        session.set_tenant("acme");
+        let collection = Collection::new("shared", None, vec![]);
+
+        let error = collection.add_session(Arc::new(session)).await.unwrap_err();
+        match error {
+            GrokError::Collection { op, id, source } => {
+                assert_eq!(op, "add session");
+                assert_eq!(id.as_deref(), Some(collection.id.as_str()));
+                assert!(source.to_string().contains("'s-1'"));
+            }
+            other => panic!("expected a collection error, got {:?}", other),
+        }
+    }
+
+    #[cfg(feature = "persistence")]
+    #[test]
+    fn test_storage_builder_requires_location() {
+        let result = SqliteStorageBuilder::new().build();
+        assert!(matches!(result, Err(GrokError::InvalidConfig(_))));
+    }
+
+    #[cfg(feature = "persistence")]
+    #[test]
+    fn test_storage_builder_applies_pragmas() {
+        let storage = SqliteStorageBuilder::new()
+            .in_memory()
+            .journal_mode(JournalMode::Memory)
+            .synchronous(Synchronous::Normal)
+            .busy_timeout(std::time::Duration::from_secs(1))
+            .cache_size(-2000)
+            .foreign_keys(true)
+            .build();
+        assert!(storage.is_ok());
+
+        // In-memory databases can't use WAL, and SQLite keeps its mode instead
+        let result = SqliteStorageBuilder::new()
+            .in_memory()
+            .journal_mode(JournalMode::Wal)
+            .build();
+        assert!(matches!(result, Err(GrokError::InvalidConfig(_))));
+    }
+
+    #[cfg(feature = "persistence")]
+    #[tokio::test]
+    async fn test_query_sessions_empty_storage() {
+        let storage = SqliteStorage::in_memory().unwrap();
+        let query = SessionQuery {
+            model: Some(Model::Grok4),
+            title_contains: Some("rust".to_string()),
+            limit: Some(10),
+            sort: SessionSort::UpdatedDesc,
+            ..Default::default()
+        };
+
+        let summaries = storage.query_sessions(&query).await.unwrap();
+        assert!(summaries.is_empty());
+    }
+
+    /// A user message with the given text
+    fn user_message(text: &str) -> Message {
+        Message {
+            role: Role::User,
+            content: text.into(),
+            tool_calls: None,
+            tool_call_id: None,
+            name: None,
+        }
+    }
+
+    #[cfg(feature = "persistence")]
+    #[tokio::test]
+    async fn test_query_sessions_filters_sorts_and_pages() {
+        use grok_rust_sdk::collections::Collection;
+        use grok_rust_sdk::session::Session;
+        use std::sync::Arc;
+
+        let client = Arc::new(grok_rust_sdk::Client::new("test-key").unwrap());
+        let storage = SqliteStorage::in_memory().unwrap();
+        let mut created = Vec::new();
+        for (model, title) in [
+            (Model::Grok4, Some("Rust tips")),
+            (Model::Grok3, Some("rust async")),
+            (Model::Grok4, None),
+        ] {
+            let session = Arc::new(Session::new(
+                client.clone(),
+                model,
+                title.map(str::to_string),
+            ));
+            session.append(user_message("Hello")).await.unwrap();
+            storage.save_session(&session).await.unwrap();
+            created.push(session);
+            tokio::time::sleep(std::time::Duration::from_millis(5)).await;
+        }
+        let [tips, rust_async, untitled] = &created[..] else {
+            unreachable!()
+        };
+
+        // The oldest session becomes the most recently active
+        tips.append(user_message("More please")).await.unwrap();
+        storage.save_session(tips).await.unwrap();
+
+        let collection = Collection::new("code", None, vec!["code".to_string()]);
+        collection.add_session(tips.clone()).await.unwrap();
+        collection.add_session(rust_async.clone()).await.unwrap();
+        storage.save_collection(&collection).await.unwrap();
+
+        let ids = |query: SessionQuery| {
+            let storage = &storage;
+            async move {
+                storage
+                    .query_sessions(&query)
+                    .await
+                    .unwrap()
+                    .into_iter()
+                    .map(|summary| summary.id)
+                    .collect::<Vec<_>>()
+            }
+        };
+        let all = |ids: &[&Arc<Session>]| ids.iter().map(|s| s.id.clone()).collect::<Vec<_>>();
+
+        // Sorting
+        assert_eq!(
+            ids(SessionQuery::default()).await,
+            all(&[untitled, rust_async, tips])
+        );
+        let sorted = |sort| SessionQuery {
+            sort,
+            ..Default::default()
+        };
+        assert_eq!(
+            ids(sorted(SessionSort::CreatedAsc)).await,
+            all(&[tips, rust_async, untitled])
+        );
+        assert_eq!(
+            ids(sorted(SessionSort::UpdatedDesc)).await,
+            all(&[tips, untitled, rust_async])
+        );
+        assert_eq!(
+            ids(sorted(SessionSort::UpdatedAsc)).await,
+            all(&[rust_async, untitled, tips])
+        );
+        assert_eq!(
+            ids(sorted(SessionSort::TitleAsc)).await,
+            all(&[rust_async, tips, untitled])
+        );
+
+        // Filters
+        let by_model = SessionQuery {
+            model: Some(Model::Grok4),
+            sort: SessionSort::CreatedAsc,
+            ..Default::default()
+        };
+        assert_eq!(ids(by_model).await, all(&[tips, untitled]));
+        let by_title = SessionQuery {
+            title_contains: Some("RUST".to_string()),
+            sort: SessionSort::CreatedAsc,
+            ..Default::default()
+        };
+        assert_eq!(ids(by_title).await, all(&[tips, rust_async]));
+        let combined = SessionQuery {
+            model: Some(Model::Grok4),
+            title_contains: Some("rust".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(ids(combined).await, all(&[tips]));
+        let by_time = SessionQuery {
+            created_after: Some(rust_async.created_at()),
+            ..Default::default()
+        };
+        assert_eq!(ids(by_time).await, all(&[untitled, rust_async]));
+
+        // Collection membership, and tags through the collection
+        let in_collection = SessionQuery {
+            collection_id: Some(collection.id.clone()),
+            sort: SessionSort::CreatedAsc,
+            ..Default::default()
+        };
+        assert_eq!(ids(in_collection).await, all(&[tips, rust_async]));
+        let tagged = SessionQuery {
+            tag: Some("code".to_string()),
+            sort: SessionSort::CreatedAsc,
+            ..Default::default()
+        };
+        assert_eq!(ids(tagged).await, all(&[tips, rust_async]));
+        let untagged = SessionQuery {
+            tag: Some("cod".to_string()),
+            ..Default::default()
+        };
+        assert!(ids(untagged).await.is_empty());
+
+        // Pagination
+        let page = |offset| SessionQuery {
+            limit: Some(2),
+            offset: Some(offset),
+            ..Default::default()
+        };
+        assert_eq!(ids(page(0)).await, all(&[untitled, rust_async]));
+        assert_eq!(ids(page(2)).await, all(&[tips]));
+        assert!(ids(page(3)).await.is_empty());
+        let skipped = SessionQuery {
+            offset: Some(1),
+            ..Default::default()
+        };
+        assert_eq!(ids(skipped).await, all(&[rust_async, tips]));
+
+        let summaries = storage
+            .query_sessions(&sorted(SessionSort::CreatedAsc))
+            .await
+            .unwrap();
+        assert_eq!(summaries[0].title.as_deref(), Some("Rust tips"));
+        assert_eq!(summaries[0].message_count, 2);
+        assert_eq!(summaries[0].model, Model::Grok4);
+    }
+
+    #[cfg(feature = "persistence")]
+    #[tokio::test]
+    async fn test_storage_stats_and_verify() {
+        let storage = SqliteStorage::in_memory().unwrap();
+
+        let stats = storage.stats().await.unwrap();
+        assert!(stats.file_size > 0);
+        assert!(stats.largest_sessions.is_empty());
+        assert!(stats.search_index_in_sync);
+        assert!(stats
+            .table_rows
+            .iter()
+            .any(|(table, rows)| table == "sessions" && *rows == 0));
+
+        assert!(storage.verify().await.unwrap().is_ok());
+    }
+
+    #[cfg(feature = "persistence")]
+    #[tokio::test]
+    async fn test_storage_stats_of_seeded_sessions() {
+        use grok_rust_sdk::session::Session;
+        use std::sync::Arc;
+
+        let path = std::env::temp_dir().join(format!("grok-stats-{}.db", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let storage = SqliteStorage::new(&path).unwrap();
+
+        let client = Arc::new(grok_rust_sdk::Client::new("test-key").unwrap());
+        let small = Session::new(client.clone(), Model::Grok4, None);
+        small.append(user_message("Hi")).await.unwrap();
+        let large = Session::new(client, Model::Grok4, None);
+        for _ in 0..3 {
+            large
+                .append(user_message(&"long ".repeat(200)))
+                .await
+                .unwrap();
+        }
+        storage.save_sessions(&[&small, &large]).await.unwrap();
+
+        let stats = storage.stats().await.unwrap();
+        let rows = |table: &str| {
+            stats
+                .table_rows
+                .iter()
+                .find(|(name, _)| name == table)
+                .map(|(_, rows)| *rows)
+        };
+        assert_eq!(rows("sessions"), Some(2));
+        assert_eq!(rows("messages"), Some(4));
+        assert!(stats
+            .table_rows
+            .iter()
+            .all(|(name, _)| !name.starts_with("message_fts_")));
+        let largest: Vec<_> = stats
+            .largest_sessions
+            .iter()
+            .map(|size| (size.session_id.as_str(), size.message_count))
+            .collect();
+        assert_eq!(largest, [(large.id(), 3), (small.id(), 1)]);
+        assert!(stats.largest_sessions[0].bytes >= 3000);
+        assert!(stats
+            .indexes
+            .iter()
+            .any(|index| index.name == "idx_sessions_tenant" && index.table == "sessions"));
+        assert!(stats.search_index_in_sync);
+        assert!(storage.verify().await.unwrap().is_ok());
+
+        // A stored count that disagrees with the rows shows up in both reports
+        let conn = rusqlite::Connection::open(&path).unwrap();
+        conn.execute(
+            "UPDATE sessions SET message_count = 7 WHERE id = ?1",
+            [small.id()],
+        )
+        .unwrap();
+        drop(conn);
+        assert!(!storage.stats().await.unwrap().search_index_in_sync);
+        let report = storage.verify().await.unwrap();
+        assert!(!report.is_ok());
+        assert_eq!(report.message_count_mismatches, [small.id()]);
+        assert!(report.integrity_errors.is_empty());
+        assert!(report.search_index_ok);
+
+        // Indexed text that no longer matches the index fails the search index check
+        let conn = rusqlite::Connection::open(&path).unwrap();
+        conn.execute("UPDATE message_fts_content SET c0 = 'tampered'", [])
+            .unwrap();
+        drop(conn);
+        assert!(!storage.verify().await.unwrap().search_index_ok);
+
+        // Failing to run the check at all is an error rather than a broken index
+        let conn = rusqlite::Connection::open(&path).unwrap();
+        conn.execute("DROP TABLE message_fts", []).unwrap();
+        drop(conn);
+        assert!(matches!(
+            storage.verify().await,
+            Err(GrokError::Session { .. })
+        ));
+
+        drop(storage);
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    /// Storage on the database in `DATABASE_URL`, or `None` when it's unset
+    #[cfg(feature = "postgres")]
+    async fn postgres_storage() -> Option<grok_rust_sdk::postgres::PostgresStorage> {
+        let url = std::env::var("DATABASE_URL").ok()?;
+        let (pg, connection) = tokio_postgres::connect(&url, tokio_postgres::NoTls)
+            .await
+            .unwrap();
+        tokio::spawn(connection);
+        Some(
+            grok_rust_sdk::postgres::PostgresStorage::new(pg)
+                .await
+                .unwrap(),
+        )
+    }
+
+    /// Save a session, reload it, save the reload as auto-save would and check nothing is lost
+    async fn assert_session_round_trip<S: Storage>(storage: &S) {
+        use grok_rust_sdk::session::Session;
+
+        let created_at = "2025-01-02T03:04:05Z".parse().unwrap();
+        let mut session = Session::restore(
+            uuid::Uuid::new_v4().to_string(),
+            Model::Grok3,
+            Some("Trip plans".to_string()),
+            created_at,
+            created_at,
+            Vec::new(),
+        );
+        session.set_tenant("acme");
+        #[cfg(feature = "persistence")]
+        {
+            let prompt = grok_rust_sdk::persistence::Prompt {
+                name: "travel".to_string(),
+                version: 3,
+                template: "Plan trips to {{city}}".to_string(),
+                description: None,
+                created_at,
+            };
+            session
+                .apply_prompt(&prompt, &[("city", "Oslo")])
+                .await
+                .unwrap();
+        }
+        session.append(user_message("Hello")).await.unwrap();
+        session
+            .append(Message {
+                role: Role::Assistant,
+                ..user_message("Hi there")
+            })
+            .await
+            .unwrap();
+        let updated_at = session.metadata().updated_at;
+        let history = serde_json::to_value(session.messages().await).unwrap();
+        storage.save_session(&session).await.unwrap();
+
+        let loaded = storage.load_session(&session.id).await.unwrap().unwrap();
+        storage.save_session(&loaded).await.unwrap();
+        let loaded = storage.load_session(&session.id).await.unwrap().unwrap();
+
+        assert_eq!(loaded.model(), Model::Grok3);
+        assert_eq!(loaded.tenant(), Some("acme"));
+        assert_eq!(loaded.prompt(), session.prompt());
+        let metadata = loaded.metadata();
+        assert_eq!(metadata.title.as_deref(), Some("Trip plans"));
+        assert_eq!(metadata.created_at, created_at);
+        // Postgres keeps microseconds
+        assert_eq!((metadata.updated_at - updated_at).num_milliseconds(), 0);
+        assert_eq!(
+            serde_json::to_value(loaded.messages().await).unwrap(),
+            history
+        );
+        assert!(loaded.interrupted().await.is_empty());
+
+        // A partial reply stays marked as interrupted through another save
+        let index = loaded.message_count().await;
+        let partial = Message {
+            role: Role::Assistant,
+            ..user_message("Partial")
+        };
+        storage
+            .save_partial_reply(&session.id, index, &partial)
+            .await
+            .unwrap();
+        let loaded = storage.load_session(&session.id).await.unwrap().unwrap();
+        storage.save_session(&loaded).await.unwrap();
+        let loaded = storage.load_session(&session.id).await.unwrap().unwrap();
+        assert_eq!(loaded.interrupted().await, [index]);
+        let messages = loaded.messages().await;
+        assert_eq!(messages.len(), index + 1);
+        assert_eq!(messages[index].content, "Partial");
+
+        storage.delete_session(&session.id).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_session_round_trip_on_every_backend() {
+        assert_session_round_trip(&MemoryStorage::new()).await;
+        #[cfg(feature = "persistence")]
+        assert_session_round_trip(&SqliteStorage::in_memory().unwrap()).await;
+        #[cfg(feature = "postgres")]
+        if let Some(storage) = postgres_storage().await {
+            assert_session_round_trip(&storage).await;
+        }
+    }
+
+    /// Save a history, rewrite it to end in the same reply, and check the rewrite is kept
+    async fn assert_rewritten_history_is_saved<S: Storage>(storage: &S) {
+        use grok_rust_sdk::session::Session;
+
+        let reply = |text: &str| Message {
+            role: Role::Assistant,
+            ..user_message(text)
+        };
+        let now = chrono::Utc::now();
+        let session = Session::restore(
+            uuid::Uuid::new_v4().to_string(),
+            Model::Grok4,
+            None,
+            now,
+            now,
+            vec![
+                Message {
+                    role: Role::System,
+                    ..user_message("Be brief")
+                },
+                user_message("first question"),
+                reply("OK"),
+            ],
+        );
+        storage.save_session(&session).await.unwrap();
+
+        session.clear_history().await.unwrap();
+        session
+            .append(user_message("second question"))
+            .await
+            .unwrap();
+        session.append(reply("OK")).await.unwrap();
+        storage.save_session(&session).await.unwrap();
+
+        let loaded = storage.load_session(&session.id).await.unwrap().unwrap();
+        let contents: Vec<_> = loaded
+            .messages()
+            .await
+            .iter()
+            .map(|m| m.content.text().into_owned())
+            .collect();
+        assert_eq!(contents, ["Be brief", "second question", "OK"]);
+        storage.delete_session(&session.id).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_rewritten_history_ending_in_same_reply() {
+        assert_rewritten_history_is_saved(&MemoryStorage::new()).await;
+        #[cfg(feature = "persistence")]
+        assert_rewritten_history_is_saved(&SqliteStorage::in_memory().unwrap()).await;
+        #[cfg(feature = "postgres")]
+        if let Some(storage) = postgres_storage().await {
+            assert_rewritten_history_is_saved(&storage).await;
+        }
+    }
+
+    /// Delete a session and then its collection, checking the collection
+    /// forgets the session and the session outlives the collection
+    ///
+    /// Returns the collection and the deleted session's IDs.
+    async fn assert_deletes_update_collections<S: Storage>(storage: &S) -> (String, String) {
+        use grok_rust_sdk::collections::Collection;
+        use grok_rust_sdk::session::Session;
+        use std::sync::Arc;
+
+        let session = |contents: &[&str]| {
+            let now = chrono::Utc::now();
+            Arc::new(Session::restore(
+                uuid::Uuid::new_v4().to_string(),
+                Model::Grok4,
+                None,
+                now,
+                now,
+                contents
+                    .iter()
+                    .map(|content| user_message(content))
+                    .collect(),
+            ))
+        };
+        let deleted = session(&["one", "two"]);
+        let kept = session(&["three"]);
+        let collection = Collection::new("notes", None, vec![]);
+        for session in [&deleted, &kept] {
+            storage.save_session(session).await.unwrap();
+            collection.add_session(session.clone()).await.unwrap();
+        }
+        storage.save_collection(&collection).await.unwrap();
+
+        storage.delete_session(deleted.id()).await.unwrap();
+        let loaded = storage
+            .load_collection(&collection.id)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(loaded.session_ids(), vec![kept.id.clone()]);
+        assert_eq!(loaded.metadata().session_count, 1);
+        assert_eq!(loaded.metadata().total_messages, 1);
+
+        storage.delete_collection(&collection.id).await.unwrap();
+        assert!(storage
+            .load_collection(&collection.id)
+            .await
+            .unwrap()
+            .is_none());
+        assert!(storage.load_session(kept.id()).await.unwrap().is_some());
+        storage.delete_session(kept.id()).await.unwrap();
+
+        (collection.id.clone(), deleted.id.clone())
+    }
+
+    #[tokio::test]
+    async fn test_deletes_update_collections() {
+        assert_deletes_update_collections(&MemoryStorage::new()).await;
+        #[cfg(feature = "postgres")]
+        if let Some(storage) = postgres_storage().await {
+            assert_deletes_update_collections(&storage).await;
+        }
+
+        // Foreign keys are off by default, so no cascade cleans up after SQLite
+        #[cfg(feature = "persistence")]
+        {
+            let path = std::env::temp_dir().join(format!("grok-deletes-{}.db", std::process::id()));
+            let _ = std::fs::remove_file(&path);
+            let storage = SqliteStorage::new(&path).unwrap();
+            let (collection_id, session_id) = assert_deletes_update_collections(&storage).await;
+            drop(storage);
+
+            let conn = rusqlite::Connection::open(&path).unwrap();
+            let links: i64 = conn
+                .query_row(
+                    "SELECT COUNT(*) FROM collection_sessions
+                     WHERE collection_id = ?1 OR session_id = ?2",
+                    [&collection_id, &session_id],
+                    |row| row.get(0),
+                )
+                .unwrap();
+            assert_eq!(links, 0);
+            drop(conn);
+            std::fs::remove_file(&path).unwrap();
+        }
+    }
+
+    /// Count a session's normalized message rows in the database at `path`
+    #[cfg(feature = "persistence")]
+    fn message_rows(path: &std::path::Path, session_id: &str) -> Vec<(i64, String)> {
+        let conn = rusqlite::Connection::open(path).unwrap();
+        let mut stmt = conn
+            .prepare("SELECT idx, content FROM messages WHERE session_id = ?1 ORDER BY idx")
+            .unwrap();
+        let rows = stmt
+            .query_map([session_id], |row| Ok((row.get(0)?, row.get(1)?)))
+            .unwrap();
+        rows.map(|row| row.unwrap()).collect()
+    }
+
+    #[cfg(feature = "persistence")]
+    #[tokio::test]
+    async fn test_resaving_collection_syncs_links_in_place() {
+        use grok_rust_sdk::collections::Collection;
+        use grok_rust_sdk::persistence::{ChangeEntity, ChangeOperation};
+        use grok_rust_sdk::session::Session;
+        use std::sync::Arc;
+
+        let path = std::env::temp_dir().join(format!("grok-links-{}.db", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        // Without foreign keys nothing cascades, so removed links must be deleted explicitly
+        let storage = SqliteStorage::builder()
+            .path(&path)
+            .foreign_keys(false)
+            .build()
+            .unwrap();
+        let session = |id: &str| {
+            let now = chrono::Utc::now();
+            Arc::new(Session::restore(
+                id.to_string(),
+                Model::Grok4,
+                None,
+                now,
+                now,
+                vec![user_message("Hello")],
+            ))
+        };
+        let (kept, removed) = (session("kept"), session("removed"));
+        storage.save_sessions(&[&*kept, &*removed]).await.unwrap();
+        let collection = Collection::new("filed", None, vec![]);
+        collection.add_session(kept.clone()).await.unwrap();
+        collection.add_session(removed.clone()).await.unwrap();
+        storage.save_collection(&collection).await.unwrap();
+
+        let added_at = || {
+            let conn = rusqlite::Connection::open(&path).unwrap();
+            let mut stmt = conn
+                .prepare("SELECT session_id, added_at FROM collection_sessions ORDER BY session_id")
+                .unwrap();
+            let rows = stmt
+                .query_map([], |row| Ok((row.get(0)?, row.get(1)?)))
+                .unwrap();
+            rows.map(|row| row.unwrap())
+                .collect::<Vec<(String, String)>>()
+        };
+        let before = added_at();
+        let cursor = storage.latest_change().await.unwrap();
+
+        collection.remove_session("removed").await.unwrap();
+        storage.save_collection(&collection).await.unwrap();
+
+        let loaded = storage
+            .load_collection(&collection.id)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(loaded.session_ids(), ["kept"]);
+        assert_eq!(added_at(), before[..1]);
+
+        // The row is updated in place rather than deleted and reinserted
+        let changes: Vec<_> = storage
+            .changes_since(cursor)
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|c| (c.entity, c.operation, c.detail))
+            .collect();
+        assert_eq!(
+            changes,
+            [
+                (ChangeEntity::Collection, ChangeOperation::Update, None),
+                (
+                    ChangeEntity::CollectionSession,
+                    ChangeOperation::Delete,
+                    Some("removed".to_string())
+                ),
+            ]
+        );
+
+        drop(storage);
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[cfg(feature = "persistence")]
+    #[tokio::test]
+    async fn test_migration_backfills_v1_collection_metadata() {
+        let path = std::env::temp_dir().join(format!("grok-v1-{}.db", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+
+        // The schema as of migration 1, with a collection of two sessions
+        let conn = rusqlite::Connection::open(&path).unwrap();
+        conn.execute_batch(
+            "CREATE TABLE sessions (
+                 id TEXT PRIMARY KEY, model TEXT NOT NULL, created_at TEXT NOT NULL,
+                 messages TEXT NOT NULL, title TEXT, updated_at TEXT,
+                 message_count INTEGER NOT NULL DEFAULT 0
+             );
+             CREATE TABLE collections (
+                 id TEXT PRIMARY KEY, name TEXT NOT NULL, description TEXT,
+                 created_at TEXT NOT NULL, tags TEXT NOT NULL DEFAULT '[]'
+             );
+             CREATE TABLE collection_sessions (
+                 collection_id TEXT NOT NULL, session_id TEXT NOT NULL, added_at TEXT NOT NULL,
+                 PRIMARY KEY (collection_id, session_id)
+             );
+             INSERT INTO sessions (id, model, created_at, messages, updated_at, message_count)
+                 VALUES ('one', 'grok-4', '2024-01-01T00:00:00+00:00',
+                         '[{\"role\":\"user\",\"content\":\"Hello\"},
+                           {\"role\":\"assistant\",\"content\":\"Hi there\"}]',
+                         '2024-01-01T00:00:00+00:00', 2),
+                        ('two', 'grok-4', '2024-01-02T00:00:00+00:00',
+                         '[{\"role\":\"user\",\"content\":\"Bye\"}]',
+                         '2024-01-02T00:00:00+00:00', 1),
+                        ('loose', 'grok-4', '2024-01-03T00:00:00+00:00', '[]',
+                         '2024-01-03T00:00:00+00:00', 0);
+             INSERT INTO collections (id, name, created_at, tags)
+                 VALUES ('archive', 'Archive', '2024-01-01T00:00:00+00:00', '[\"old\"]'),
+                        ('empty', 'Empty', '2024-01-01T00:00:00+00:00', '[]');
+             INSERT INTO collection_sessions (collection_id, session_id, added_at)
+                 VALUES ('archive', 'one', '2024-01-01T00:00:00+00:00'),
+                        ('archive', 'two', '2024-01-02T00:00:00+00:00');
+             PRAGMA user_version = 1;",
+        )
+        .unwrap();
+        drop(conn);
+
+        let storage = SqliteStorage::new(&path).unwrap();
+        let archive = storage.load_collection("archive").await.unwrap().unwrap();
+        let metadata = archive.metadata();
+        assert_eq!(metadata.session_count, 2);
+        assert_eq!(metadata.total_messages, 3);
+        assert_eq!(metadata.total_tokens, 0);
+        assert_eq!(metadata.updated_at, metadata.created_at);
+        assert_eq!(metadata.tags, vec!["old".to_string()]);
+
+        let empty = storage.load_collection("empty").await.unwrap().unwrap();
+        assert_eq!(
+            (
+                empty.metadata().session_count,
+                empty.metadata().total_messages
+            ),
+            (0, 0)
+        );
+
+        drop(storage);
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[cfg(feature = "persistence")]
+    #[tokio::test]
+    async fn test_migration_moves_v5_histories_into_rows() {
+        let path = std::env::temp_dir().join(format!("grok-v5-{}.db", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+
+        // The schema as of migration 5, with a session whose history is still a blob
+        let conn = rusqlite::Connection::open(&path).unwrap();
+        conn.execute_batch(
+            "CREATE TABLE sessions (
+                 id TEXT PRIMARY KEY, model TEXT NOT NULL, created_at TEXT NOT NULL,
+                 messages TEXT NOT NULL, title TEXT, updated_at TEXT,
+                 message_count INTEGER NOT NULL DEFAULT 0, tools TEXT NOT NULL DEFAULT '[]'
+             );
+             CREATE TABLE collections (
+                 id TEXT PRIMARY KEY, name TEXT NOT NULL, description TEXT,
+                 created_at TEXT NOT NULL, tags TEXT NOT NULL DEFAULT '[]', updated_at TEXT,
+                 session_count INTEGER NOT NULL DEFAULT 0,
+                 total_messages INTEGER NOT NULL DEFAULT 0,
+                 total_tokens INTEGER NOT NULL DEFAULT 0
+             );
+             CREATE TABLE collection_sessions (
+                 collection_id TEXT NOT NULL, session_id TEXT NOT NULL, added_at TEXT NOT NULL,
+                 PRIMARY KEY (collection_id, session_id)
+             );
+             CREATE VIRTUAL TABLE message_fts USING fts5(
+                 content, session_id UNINDEXED, message_index UNINDEXED, role UNINDEXED
+             );
+             CREATE TABLE usage_records (
+                 id INTEGER PRIMARY KEY AUTOINCREMENT, recorded_at TEXT NOT NULL, session_id TEXT,
+                 model TEXT NOT NULL, prompt_tokens INTEGER NOT NULL,
+                 completion_tokens INTEGER NOT NULL, total_tokens INTEGER NOT NULL,
+                 estimated_cost REAL, latency_ms INTEGER NOT NULL
+             );
+             INSERT INTO sessions (id, model, created_at, messages, title, updated_at, message_count)
+                 VALUES ('legacy', 'grok-4', '2024-01-01T00:00:00+00:00',
+                         '[{\"role\":\"user\",\"content\":\"Hello\"},
+                           {\"role\":\"assistant\",\"content\":\"Hi there\"}]',
+                         'Old chat', '2024-01-02T00:00:00+00:00', 2);
+             PRAGMA user_version = 5;",
+        )
+        .unwrap();
+        drop(conn);
+
+        let storage = SqliteStorage::new(&path).unwrap();
+        let session = storage.load_session("legacy").await.unwrap().unwrap();
+        let messages = session.messages().await;
+        assert_eq!(messages.len(), 2);
+        assert_eq!(messages[0].role, Role::User);
+        assert_eq!(messages[0].content.text(), "Hello");
+        assert_eq!(messages[1].role, Role::Assistant);
+        assert_eq!(messages[1].content.text(), "Hi there");
+        assert_eq!(
+            message_rows(&path, "legacy"),
+            vec![(0, "Hello".to_string()), (1, "Hi there".to_string())]
+        );
+
+        // The migrated history keeps growing as rows
+        session
+            .append(Message {
+                role: Role::User,
+                content: "Still there?".into(),
+                tool_calls: None,
+                tool_call_id: None,
+                name: None,
+            })
+            .await
+            .unwrap();
+        storage.save_session(&session).await.unwrap();
+        assert_eq!(message_rows(&path, "legacy").len(), 3);
+        let reloaded = storage.load_session("legacy").await.unwrap().unwrap();
+        assert_eq!(reloaded.message_count().await, 3);
+
+        drop(storage);
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[cfg(feature = "persistence")]
+    #[tokio::test]
+    async fn test_saving_appends_and_rewrites_message_rows() {
+        let path = std::env::temp_dir().join(format!("grok-rows-{}.db", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let storage = SqliteStorage::new(&path).unwrap();
+        let client = std::sync::Arc::new(grok_rust_sdk::Client::new("test-key").unwrap());
+        let session = grok_rust_sdk::session::Session::new(client, Model::Grok4, None);
+        let message = |content: &str| Message {
+            role: Role::User,
+            content: content.into(),
+            tool_calls: None,
+            tool_call_id: None,
+            name: None,
+        };
+        let rows = |contents: &[&str]| -> Vec<(i64, String)> {
+            contents
+                .iter()
+                .enumerate()
+                .map(|(idx, content)| (idx as i64, content.to_string()))
+                .collect()
+        };
+
+        session.append(message("one")).await.unwrap();
+        storage.save_session(&session).await.unwrap();
+        session.append(message("two")).await.unwrap();
+        storage.save_session(&session).await.unwrap();
+        assert_eq!(message_rows(&path, &session.id), rows(&["one", "two"]));
+
+        // Saving an unchanged history writes nothing new
+        storage.save_session(&session).await.unwrap();
+        assert_eq!(message_rows(&path, &session.id), rows(&["one", "two"]));
+
+        // Later saves of a history this storage wrote only compare the last
+        // stored row, so an edit to an earlier row by another writer goes unread
+        let edit_first_row = || {
+            let conn = rusqlite::Connection::open(&path).unwrap();
+            conn.execute(
+                "UPDATE messages SET content = 'edited' WHERE session_id = ?1 AND idx = 0",
+                [&session.id],
+            )
+            .unwrap();
+        };
+        edit_first_row();
+        session.append(message("three")).await.unwrap();
+        storage.save_session(&session).await.unwrap();
+        assert_eq!(
+            message_rows(&path, &session.id),
+            rows(&["edited", "two", "three"])
+        );
+
+        // A storage that hasn't written this history compares every row
+        let other = SqliteStorage::new(&path).unwrap();
+        other.save_session(&session).await.unwrap();
+        assert_eq!(
+            message_rows(&path, &session.id),
+            rows(&["one", "two", "three"])
+        );
+        drop(other);
+
+        // A cleared history no longer matches the stored rows, so they're rewritten
+        session.clear_history().await.unwrap();
+        session.append(message("fresh")).await.unwrap();
+        storage.save_session(&session).await.unwrap();
+        assert_eq!(message_rows(&path, &session.id), rows(&["fresh"]));
+        let loaded = storage.load_session(&session.id).await.unwrap().unwrap();
+        let messages = loaded.messages().await;
+        assert_eq!(messages.len(), 1);
+        assert_eq!(messages[0].content.text(), "fresh");
+
+        drop(storage);
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    /// Save a one-message session, back the storage up to `backup` and return the session ID
+    #[cfg(feature = "persistence")]
+    async fn save_and_back_up(storage: &SqliteStorage, backup: &std::path::Path) -> String {
+        let client = std::sync::Arc::new(grok_rust_sdk::Client::new("test-key").unwrap());
+        let session = grok_rust_sdk::session::Session::new(client, Model::Grok4, None);
+        session
+            .append(Message {
+                role: Role::User,
+                content: "Back me up".into(),
+                tool_calls: None,
+                tool_call_id: None,
+                name: None,
+            })
+            .await
+            .unwrap();
+        storage.save_session(&session).await.unwrap();
+        storage.backup_to(backup).await.unwrap();
+        session.id.clone()
+    }
+
+    #[cfg(feature = "persistence")]
+    #[tokio::test]
+    async fn test_backup_and_restore() {
+        let dir = std::env::temp_dir().join(format!("grok-restore-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let backup = dir.join("backup.db");
+
+        let storage = SqliteStorage::in_memory().unwrap();
+        let session_id = save_and_back_up(&storage, &backup).await;
+        assert!(storage.backup_to(&backup).await.is_err());
+
+        let restored = SqliteStorage::restore_from(&backup, dir.join("restored.db")).unwrap();
+        let session = restored.load_session(&session_id).await.unwrap().unwrap();
+        assert_eq!(session.messages().await[0].content.text(), "Back me up");
+
+        // Restoring through a builder keeps its settings
+        let restored = SqliteStorage::builder()
+            .path(dir.join("tuned.db"))
+            .journal_mode(JournalMode::Wal)
+            .restore_from(&backup)
+            .unwrap();
+        assert!(restored.load_session(&session_id).await.unwrap().is_some());
+        assert!(SqliteStorage::builder()
+            .in_memory()
+            .restore_from(&backup)
+            .is_err());
+
+        std::fs::write(dir.join("corrupt.db"), b"not a database").unwrap();
+        assert!(SqliteStorage::restore_from(dir.join("corrupt.db"), dir.join("x.db")).is_err());
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[cfg(feature = "sqlcipher")]
+    #[tokio::test]
+    async fn test_restore_encrypted_backup_with_key() {
+        let dir = std::env::temp_dir().join(format!("grok-restore-key-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let backup = dir.join("backup.db");
+
+        let storage = SqliteStorage::builder()
+            .path(dir.join("source.db"))
+            .key("correct horse")
+            .build()
+            .unwrap();
+        let session_id = save_and_back_up(&storage, &backup).await;
+
+        // Without the key the backup can't even be checked
+        assert!(SqliteStorage::restore_from(&backup, dir.join("plain.db")).is_err());
+
+        let restored = SqliteStorage::builder()
+            .path(dir.join("restored.db"))
+            .key("correct horse")
+            .restore_from(&backup)
+            .unwrap();
+        assert!(restored.load_session(&session_id).await.unwrap().is_some());
+        drop(restored);
+
+        // The restored copy is still encrypted
+        assert!(SqliteStorage::new(dir.join("restored.db")).is_err());
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[cfg(feature = "persistence")]
+    #[tokio::test]
+    async fn test_check_and_repair_orphaned_links() {
+        use grok_rust_sdk::collections::Collection;
+        use grok_rust_sdk::persistence::RepairOptions;
+
+        let path = std::env::temp_dir().join(format!("grok-orphans-{}.db", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let storage = SqliteStorage::builder()
+            .path(&path)
+            .foreign_keys(false)
+            .build()
+            .unwrap();
+        let client = std::sync::Arc::new(grok_rust_sdk::Client::new("test-key").unwrap());
+        let session = std::sync::Arc::new(grok_rust_sdk::session::Session::new(
+            client,
+            Model::Grok4,
+            None,
+        ));
+        let collection = Collection::new("kept", None, vec![]);
+        collection.add_session(session.clone()).await.unwrap();
+        storage.save_session(&session).await.unwrap();
+        storage.save_collection(&collection).await.unwrap();
+        assert!(storage
+            .check_and_repair(&RepairOptions::default())
+            .await
+            .unwrap()
+            .is_clean());
+
+        // A session removed behind the storage's back, with foreign keys off,
+        // leaves the collection's link behind
+        let raw = rusqlite::Connection::open(&path).unwrap();
+        raw.pragma_update(None, "foreign_keys", false).unwrap();
+        raw.execute("DELETE FROM sessions WHERE id = ?1", [&session.id])
+            .unwrap();
+        drop(raw);
+        let report = storage
+            .check_and_repair(&RepairOptions::default())
+            .await
+            .unwrap();
+        assert_eq!(
+            report.orphaned_collection_links,
+            vec![(collection.id.clone(), session.id.clone())]
+        );
+        assert!(!report.repaired);
+
+        let options = RepairOptions {
+            repair: true,
+            ..Default::default()
+        };
+        assert!(storage.check_and_repair(&options).await.unwrap().repaired);
+        assert!(storage
+            .check_and_repair(&RepairOptions::default())
+            .await
+            .unwrap()
+            .is_clean());
+        let reloaded = storage
+            .load_collection(&collection.id)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(reloaded.metadata().session_count, 0);
+
+        drop(storage);
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[cfg(feature = "persistence")]
+    #[tokio::test]
+    async fn test_partial_stream_replies_are_kept_as_interrupted() {
+        use futures::StreamExt;
+        use grok_rust_sdk::channel::StreamEvent;
+        use grok_rust_sdk::chat::{ChatCompletion, Tool};
+        use grok_rust_sdk::client::ChatOptions;
+        use grok_rust_sdk::provider::{ChatProvider, ChunkStream};
+        use grok_rust_sdk::session::{AutoSaveOptions, Session};
+        use std::sync::Arc;
+        use std::time::Duration;
+
+        fn chunk(content: &str) -> grok_rust_sdk::Result<ChatChunk> {
+            Ok(serde_json::from_value(serde_json::json!({
+                "id": "chatcmpl-1",
+                "object": "chat.completion.chunk",
+                "created": 0,
+                "model": "grok-4",
+                "choices": [{ "index": 0, "delta": { "content": content }, "finish_reason": null }]
+            }))
+            .unwrap())
+        }
+
+        /// Streams part of a reply, then hangs or fails
+        #[derive(Debug)]
+        struct CutOff {
+            fail: bool,
+        }
+
+        #[async_trait]
+        impl ChatProvider for CutOff {
+            async fn chat(
+                &self,
+                _model: Model,
+                _messages: Vec<Arc<Message>>,
+                _tools: Option<Vec<Tool>>,
+                _options: Option<ChatOptions>,
+            ) -> grok_rust_sdk::Result<ChatCompletion> {
+                Err(GrokError::InvalidConfig("streaming only".to_string()))
+            }
+
+            async fn chat_stream(
+                &self,
+                _model: Model,
+                _messages: Vec<Message>,
+                _tools: Option<Vec<Tool>>,
+            ) -> grok_rust_sdk::Result<ChunkStream> {
+                let partial = futures::stream::iter(vec![chunk("Hello, "), chunk("wor")]);
+                if self.fail {
+                    let error = GrokError::Timeout {
+                        elapsed: Duration::from_secs(1),
+                        phase: TimeoutPhase::StreamIdle,
+                    };
+                    Ok(partial
+                        .chain(futures::stream::iter(vec![Err(error)]))
+                        .boxed())
+                } else {
+                    Ok(partial.chain(futures::stream::pending()).boxed())
+                }
+            }
+        }
+
+        let storage = Arc::new(SqliteStorage::in_memory().unwrap());
+        let options = AutoSaveOptions {
+            debounce: Duration::ZERO,
+            max_pending: 1,
+        };
+
+        // The process stops mid-stream: the stored session keeps the partial reply
+        let mut session = Session::new(Arc::new(CutOff { fail: false }), Model::Grok4, None);
+        session.enable_auto_save(storage.clone(), options.clone());
+        let events = session.chat_events("hi").await.unwrap();
+        let received: Vec<StreamEvent> = events.take(2).collect().await;
+        assert_eq!(received.len(), 2);
+
+        let restored = storage.load_session(&session.id).await.unwrap().unwrap();
+        let messages = restored.messages().await;
+        assert_eq!(messages.len(), 2);
+        assert_eq!(messages[1].content, "Hello, wor");
+        assert_eq!(restored.interrupted().await, vec![1]);
+
+        // A stream that fails leaves the partial reply in the history, marked
+        let mut session = Session::new(Arc::new(CutOff { fail: true }), Model::Grok4, None);
+        session.enable_auto_save(storage.clone(), options);
+        let events: Vec<StreamEvent> = session.chat_events("hi").await.unwrap().collect().await;
+        assert!(matches!(events.last(), Some(StreamEvent::Error(_))));
+        assert_eq!(session.interrupted().await, vec![1]);
+        assert_eq!(session.messages().await[1].content, "Hello, wor");
+
+        let restored = storage.load_session(&session.id).await.unwrap().unwrap();
+        assert_eq!(restored.message_count().await, 2);
+        assert_eq!(restored.interrupted().await, vec![1]);
+    }
+
+    #[cfg(feature = "persistence")]
+    #[tokio::test]
+    async fn test_prompt_versions_and_session_references() {
+        let storage = SqliteStorage::in_memory().unwrap();
+        let prompts = storage.prompts();
+
+        prompts
+            .publish("support", "You help {{ product }} users.", None)
+            .await
+            .unwrap();
+        let latest = prompts
+            .publish(
+                "support",
+                "You help {{product}} users. Be brief.",
+                Some("Shorter replies"),
+            )
+            .await
+            .unwrap();
+        assert_eq!(latest.reference(), "support@2");
+        assert_eq!(prompts.get("support").await.unwrap().version, 2);
+
+        let restored = prompts.rollback("support").await.unwrap();
+        assert_eq!(restored.version, 1);
+        assert_eq!(prompts.get("support").await.unwrap(), restored);
+        assert!(prompts.rollback("support").await.is_err());
+        assert!(matches!(
+            prompts.get("support@9").await,
+            Err(GrokError::NotFound {
+                entity: Entity::Prompt,
+                ..
+            })
+        ));
+        assert!(restored.render(&[]).is_err());
+
+        let client = std::sync::Arc::new(grok_rust_sdk::Client::new("test-key").unwrap());
+        let mut session = grok_rust_sdk::session::Session::new(client, Model::Grok4, None);
+        session
+            .apply_prompt(&restored, &[("product", "Grok")])
+            .await
+            .unwrap();
+        assert_eq!(session.messages().await[0].content, "You help Grok users.");
+        storage.save_session(&session).await.unwrap();
+
+        let query = SessionQuery {
+            prompt: Some("support".to_string()),
+            ..Default::default()
+        };
+        let summaries = storage.query_sessions(&query).await.unwrap();
+        assert_eq!(summaries.len(), 1);
+        assert_eq!(summaries[0].prompt.as_deref(), Some("support@1"));
+    }
+
+    #[cfg(feature = "persistence")]
+    #[tokio::test]
+    async fn test_session_tools_are_stored_and_rebound() {
+        use grok_rust_sdk::session::Session;
+        use std::sync::Arc;
+
+        let registry = ToolRegistry::new();
+        registry.register(MockTool);
+        let client = Arc::new(grok_rust_sdk::Client::new("test-key").unwrap());
+        let mut session = Session::new(client, Model::Grok4, None);
+        session.add_tools(registry.api_tools().into_iter().map(Into::into).collect());
+        let storage = SqliteStorage::in_memory().unwrap();
+        storage.save_session(&session).await.unwrap();
+
+        let loaded = storage.load_session(session.id()).await.unwrap().unwrap();
+        assert_eq!(loaded.tools().len(), 1);
+        assert_eq!(loaded.tools()[0].function.name, "mock_tool");
+        assert_eq!(
+            loaded.tools()[0].function.parameters.as_ref().unwrap()["required"],
+            serde_json::json!(["input"])
+        );
+
+        // Stored tools take the registry's current spec, and missing ones are reported
+        let restored = storage
+            .load_session_with_tools(session.id(), &registry)
+            .await
+            .unwrap()
+            .unwrap();
+        assert!(restored.unbound_tools.is_empty());
+        assert_eq!(
+            restored.session.tools()[0].function.description.as_deref(),
+            Some("A mock tool for testing")
+        );
+        let restored = storage
+            .load_session_with_tools(session.id(), &ToolRegistry::new())
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(restored.unbound_tools, ["mock_tool"]);
+        assert!(restored.session.tools().is_empty());
+        assert!(storage
+            .load_session_with_tools("missing", &registry)
+            .await
+            .unwrap()
+            .is_none());
+    }
+
+    #[cfg(feature = "persistence")]
+    #[tokio::test]
+    async fn test_search_messages() {
+        use grok_rust_sdk::collections::Collection;
+        use grok_rust_sdk::session::Session;
+        use std::sync::Arc;
+
+        let client = Arc::new(grok_rust_sdk::Client::new("test-key").unwrap());
+        let storage = SqliteStorage::in_memory().unwrap();
+        let rust = Arc::new(Session::new(client.clone(), Model::Grok4, None));
+        rust.append(user_message("How do I parse JSON in Rust?"))
+            .await
+            .unwrap();
+        rust.append(Message {
+            role: Role::Assistant,
+            content: "Use serde_json to parse it.".into(),
+            tool_calls: None,
+            tool_call_id: None,
+            name: None,
+        })
+        .await
+        .unwrap();
+        let cooking = Session::new(client, Model::Grok4, None);
+        cooking
+            .append(user_message("How do I bake bread?"))
+            .await
+            .unwrap();
+        storage.save_sessions(&[&*rust, &cooking]).await.unwrap();
+
+        let matches = storage.search_messages("parse").await.unwrap();
+        assert_eq!(matches.len(), 2);
+        assert!(matches.iter().all(|m| m.session_id == rust.id));
+        let reply = matches.iter().find(|m| m.message_index == 1).unwrap();
+        assert_eq!(reply.role, Some(Role::Assistant));
+        assert!(reply.snippet.contains("[parse]"));
+
+        // FTS5 query syntax
+        assert_eq!(
+            storage
+                .search_messages("bake AND bread")
+                .await
+                .unwrap()
+                .len(),
+            1
+        );
+        assert_eq!(
+            storage.search_messages("bak*").await.unwrap()[0].session_id,
+            cooking.id
+        );
+        assert!(storage
+            .search_messages("\"bread JSON\"")
+            .await
+            .unwrap()
+            .is_empty());
+        assert_eq!(storage.search_messages("How").await.unwrap().len(), 2);
+
+        // Scoped to the sessions of a collection
+        let collection = Collection::new("code", None, vec![]);
+        collection.add_session(rust.clone()).await.unwrap();
+        storage.save_collection(&collection).await.unwrap();
+        let matches = storage
+            .search_collection_messages(&collection.id, "How")
+            .await
+            .unwrap();
+        assert_eq!(matches.len(), 1);
+        assert_eq!(matches[0].session_id, rust.id);
+
+        // The index follows the stored history
+        rust.clear_history().await.unwrap();
+        storage.save_session(&rust).await.unwrap();
+        assert!(storage.search_messages("parse").await.unwrap().is_empty());
+        storage.delete_session(&cooking.id).await.unwrap();
+        assert!(storage.search_messages("bread").await.unwrap().is_empty());
+        assert!(storage.stats().await.unwrap().search_index_in_sync);
+    }
+
+    #[cfg(feature = "persistence")]
+    #[tokio::test]
+    async fn test_export_jsonl_for_fine_tuning() {
+        use grok_rust_sdk::chat::{ToolCall as ChatToolCall, ToolFunction as ChatToolFunction};
+        use grok_rust_sdk::collections::Collection;
+        use grok_rust_sdk::persistence::ExportOptions;
+        use grok_rust_sdk::session::Session;
+        use std::sync::Arc;
+
+        let message = |role, content: &str| Message {
+            role,
+            content: content.into(),
+            tool_calls: None,
+            tool_call_id: None,
+            name: None,
+        };
+        let client = Arc::new(grok_rust_sdk::Client::new("test-key").unwrap());
+        let weather = Arc::new(Session::new(client.clone(), Model::Grok4, None));
+        weather
+            .append(message(Role::System, "You are terse."))
+            .await
+            .unwrap();
+        weather
+            .append(message(Role::User, "Weather in Oslo?"))
+            .await
+            .unwrap();
+        weather
+            .append(Message {
+                tool_calls: Some(vec![ChatToolCall {
+                    id: "call-1".to_string(),
+                    function: ChatToolFunction {
+                        name: "weather".to_string(),
+                        arguments: r#"{"city":"Oslo"}"#.to_string(),
+                    },
+                }]),
+                ..message(Role::Assistant, "")
+            })
+            .await
+            .unwrap();
+        weather
+            .append(Message {
+                tool_call_id: Some("call-1".to_string()),
+                ..message(Role::Tool, r#"{"temp":3}"#)
+            })
+            .await
+            .unwrap();
+        weather
+            .append(message(Role::Assistant, "3°C."))
+            .await
+            .unwrap();
+        let greeting = Session::new(client.clone(), Model::Grok4, None);
+        greeting.append(message(Role::User, "Hi")).await.unwrap();
+        let empty = Session::new(client, Model::Grok4, None);
+
+        let storage = SqliteStorage::in_memory().unwrap();
+        storage
+            .save_sessions(&[&*weather, &greeting, &empty])
+            .await
+            .unwrap();
+        let collection = Collection::new("weather", None, vec![]);
+        collection.add_session(weather.clone()).await.unwrap();
+        storage.save_collection(&collection).await.unwrap();
+
+        let path = std::env::temp_dir().join(format!("grok-export-{}.jsonl", std::process::id()));
+        let export = |options: ExportOptions| {
+            let (storage, path) = (&storage, &path);
+            async move {
+                let count = storage.export_jsonl(&options, path).await.unwrap();
+                let lines: Vec<serde_json::Value> = std::fs::read_to_string(path)
+                    .unwrap()
+                    .lines()
+                    .map(|line| serde_json::from_str(line).unwrap())
+                    .collect();
+                assert_eq!(lines.len(), count);
+                lines
+            }
+        };
+
+        // Empty conversations are skipped
+        let lines = export(ExportOptions::default()).await;
+        assert_eq!(lines.len(), 2);
+        assert!(lines.iter().all(|line| line["messages"].is_array()));
+
+        let lines = export(ExportOptions {
+            query: SessionQuery {
+                collection_id: Some(collection.id.clone()),
+                ..Default::default()
+            },
+            ..Default::default()
+        })
+        .await;
+        assert_eq!(lines.len(), 1);
+        let messages = lines[0]["messages"].as_array().unwrap();
+        assert_eq!(messages.len(), 5);
+        assert_eq!(messages[2]["tool_calls"][0]["id"], "call-1");
+        assert_eq!(messages[3]["tool_call_id"], "call-1");
+
+        // Tool traffic and system prompts can be left out
+        let lines = export(ExportOptions {
+            query: SessionQuery {
+                collection_id: Some(collection.id.clone()),
+                ..Default::default()
+            },
+            drop_tool_messages: true,
+            redact_system_prompts: true,
+        })
+        .await;
+        assert_eq!(
+            lines[0]["messages"],
+            serde_json::json!([
+                {"role": "user", "content": "Weather in Oslo?"},
+                {"role": "assistant", "content": "3°C."},
+            ])
+        );
+
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[cfg(feature = "persistence")]
+    #[tokio::test]
+    async fn test_prune_by_retention_policy() {
+        use grok_rust_sdk::collections::Collection;
+        use grok_rust_sdk::persistence::PrunePolicy;
+        use grok_rust_sdk::session::Session;
+        use std::sync::Arc;
+        use std::time::Duration;
+
+        let days_ago = |days| {
+            let at = chrono::Utc::now() - chrono::Duration::days(days);
+            Arc::new(Session::restore(
+                uuid::Uuid::new_v4().to_string(),
+                Model::Grok4,
+                None,
+                at,
+                at,
+                vec![user_message("Hello")],
+            ))
+        };
+        let (stale, filed, recent) = (days_ago(2), days_ago(3), days_ago(0));
+        let storage = SqliteStorage::in_memory().unwrap();
+        storage
+            .save_sessions(&[&*stale, &*filed, &*recent])
+            .await
+            .unwrap();
+        let collection = Collection::new("kept", None, vec![]);
+        collection.add_session(filed.clone()).await.unwrap();
+        storage.save_collection(&collection).await.unwrap();
+
+        assert!(matches!(
+            storage.prune(&PrunePolicy::default()).await,
+            Err(GrokError::InvalidConfig(_))
+        ));
+
+        // Criteria combine: old sessions outside any collection
+        let report = storage
+            .prune(&PrunePolicy {
+                older_than: Some(Duration::from_secs(24 * 60 * 60)),
+                orphaned_only: true,
+                ..Default::default()
+            })
+            .await
+            .unwrap();
+        assert_eq!(report.removed_sessions, [stale.id.as_str()]);
+        assert_eq!(report.removed_collection_links, 0);
+
+        // Pruning a collected session unlinks it and recounts the collection
+        let report = storage
+            .prune(&PrunePolicy {
+                keep_last_n: Some(1),
+                ..Default::default()
+            })
+            .await
+            .unwrap();
+        assert_eq!(report.removed_sessions, [filed.id.as_str()]);
+        assert_eq!(report.removed_collection_links, 1);
+        assert_eq!(storage.list_sessions().await.unwrap(), [recent.id.as_str()]);
+        let collection = storage
+            .load_collection(&collection.id)
+            .await
+            .unwrap()
+            .unwrap();
+        assert!(collection.session_ids().is_empty());
+        assert_eq!(collection.metadata().session_count, 0);
+        assert_eq!(storage.search_messages("Hello").await.unwrap().len(), 1);
+        assert!(storage.verify().await.unwrap().is_ok());
+    }
+
+    #[cfg(feature = "persistence")]
+    #[tokio::test]
+    async fn test_save_sessions_is_all_or_nothing() {
+        use grok_rust_sdk::session::{Session, SessionManager};
+        use std::sync::Arc;
+
+        let path = std::env::temp_dir().join(format!("grok-batch-{}.db", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let storage = SqliteStorage::new(&path).unwrap();
+
+        // Make the database reject one session of the batch
+        let conn = rusqlite::Connection::open(&path).unwrap();
+        conn.execute_batch(
+            "CREATE TRIGGER reject_poison BEFORE INSERT ON sessions WHEN NEW.id = 'poison'
+             BEGIN SELECT RAISE(ABORT, 'rejected'); END;",
+        )
+        .unwrap();
+        drop(conn);
+
+        let session = |id: &str| {
+            let now = chrono::Utc::now();
+            Session::restore(
+                id.to_string(),
+                Model::Grok4,
+                None,
+                now,
+                now,
+                vec![user_message("Hello")],
+            )
+        };
+        let batch = [session("first"), session("poison"), session("last")];
+        assert!(storage.save_sessions(&batch).await.is_err());
+        assert!(storage.list_sessions().await.unwrap().is_empty());
+        assert!(storage.search_messages("Hello").await.unwrap().is_empty());
+
+        storage.save_sessions(&batch[..1]).await.unwrap();
+        assert_eq!(storage.list_sessions().await.unwrap(), ["first"]);
+
+        // Everything a manager holds goes in one batch
+        let client = Arc::new(grok_rust_sdk::Client::new("test-key").unwrap());
+        let manager = SessionManager::new(client);
+        for _ in 0..3 {
+            manager.create_session(Model::Grok4, None).await;
+        }
+        assert_eq!(storage.save_all(&manager).await.unwrap(), 3);
+        assert_eq!(storage.list_sessions().await.unwrap().len(), 4);
+
+        drop(storage);
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[cfg(feature = "persistence")]
+    #[tokio::test]
+    async fn test_partial_load_of_missing_session() {
+        let storage = SqliteStorage::in_memory().unwrap();
+
+        assert!(storage
+            .load_session_meta("missing")
+            .await
+            .unwrap()
+            .is_none());
+        assert!(storage
+            .load_messages("missing", 0..20)
+            .await
+            .unwrap()
+            .is_empty());
+    }
+
+    #[cfg(feature = "persistence")]
+    #[tokio::test]
+    async fn test_partial_load_of_stored_session() {
+        use grok_rust_sdk::session::Session;
+        use std::sync::Arc;
+
+        let client = Arc::new(grok_rust_sdk::Client::new("test-key").unwrap());
+        let session = Session::new(client, Model::Grok3, Some("Counting".to_string()));
+        for i in 0..5 {
+            session.append(user_message(&i.to_string())).await.unwrap();
+        }
+        let storage = SqliteStorage::in_memory().unwrap();
+        storage.save_session(&session).await.unwrap();
+
+        let meta = storage
+            .load_session_meta(session.id())
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(meta.id, session.id());
+        assert_eq!(meta.title.as_deref(), Some("Counting"));
+        assert_eq!(meta.model, Model::Grok3);
+        assert_eq!(meta.message_count, 5);
+        assert_eq!(meta.created_at, session.created_at());
+
+        let texts = |messages: Vec<Message>| -> Vec<String> {
+            messages
+                .iter()
+                .map(|m| m.content.text().into_owned())
+                .collect()
+        };
+        let id = session.id();
+        assert_eq!(
+            texts(storage.load_messages(id, 1..3).await.unwrap()),
+            ["1", "2"]
+        );
+        assert_eq!(
+            texts(storage.load_messages(id, 3..).await.unwrap()),
+            ["3", "4"]
+        );
+        assert_eq!(
+            texts(storage.load_messages(id, ..=1).await.unwrap()),
+            ["0", "1"]
+        );
+        assert_eq!(storage.load_messages(id, ..).await.unwrap().len(), 5);
+        assert!(storage.load_messages(id, 10..20).await.unwrap().is_empty());
+    }
+
+    #[cfg(feature = "persistence")]
+    #[tokio::test]
+    async fn test_import_openai_transcripts() {
+        let path = std::env::temp_dir().join(format!("grok-import-{}.jsonl", std::process::id()));
+        std::fs::write(
+            &path,
+            concat!(
+                r#"{"messages":[{"role":"user","content":"Hi"},{"role":"assistant","content":"Hello!"}]}"#, "\n",
+                r#"{"messages":[]}"#, "\n",
+            ),
+        ).unwrap();
+
+        let storage = SqliteStorage::in_memory().unwrap();
+        let report = storage.import_openai_export(&path).await.unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(report.session_ids.len(), 1);
+        assert_eq!(report.skipped, 1);
+
+        let messages = storage
+            .load_messages(&report.session_ids[0], ..)
+            .await
+            .unwrap();
+        assert_eq!(messages.len(), 2);
+        assert_eq!(messages[1].content, "Hello!");
+    }
+
+    #[cfg(feature = "persistence")]
+    #[tokio::test]
+    async fn test_import_chatgpt_export_with_cyclic_nodes() {
+        let node = |id: &str, parent: &str, children: &[&str], text: &str| {
+            let message = serde_json::json!({
+                "author": { "role": "user" },
+                "content": { "content_type": "text", "parts": [text] },
+            });
+            (
+                id.to_string(),
+                serde_json::json!({ "id": id, "parent": parent, "children": children, "message": message }),
+            )
+        };
+        let mut looped_parents: serde_json::Map<_, _> = [
+            node("a", "b", &["b"], "first"),
+            node("b", "a", &["a"], "second"),
+        ]
+        .into_iter()
+        .collect();
+        looped_parents.insert(
+            "root".to_string(),
+            serde_json::json!({ "id": "root", "parent": null, "children": ["a"] }),
+        );
+        let looped_children: serde_json::Map<_, _> = [
+            node("x", "root", &["y"], "third"),
+            node("y", "x", &["x"], "fourth"),
+        ]
+        .into_iter()
+        .chain([(
+            "root".to_string(),
+            serde_json::json!({ "id": "root", "parent": null, "children": ["x"] }),
+        )])
+        .collect();
+        let export = serde_json::json!([
+            { "title": "Parents", "mapping": looped_parents, "current_node": "b" },
+            { "title": "Children", "mapping": looped_children },
+        ]);
+
+        let path = std::env::temp_dir().join(format!("grok-cycle-{}.json", std::process::id()));
+        std::fs::write(&path, export.to_string()).unwrap();
+        let storage = SqliteStorage::in_memory().unwrap();
+        let report = storage.import_openai_export(&path).await.unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(report.session_ids.len(), 2);
+        let thread = |messages: Vec<Message>| -> Vec<String> {
+            messages
+                .iter()
+                .map(|m| m.content.text().into_owned())
+                .collect()
+        };
+        let parents = storage
+            .load_messages(&report.session_ids[0], ..)
+            .await
+            .unwrap();
+        assert_eq!(thread(parents), ["first", "second"]);
+        let children = storage
+            .load_messages(&report.session_ids[1], ..)
+            .await
+            .unwrap();
+        assert_eq!(thread(children), ["third", "fourth"]);
+    }
+
+    #[tokio::test]
+    async fn test_memory_storage_round_trip() {
+        use grok_rust_sdk::collections::Collection;
+
+        let storage: Box<dyn Storage> = Box::new(MemoryStorage::new());
+        assert!(storage.load_session("missing").await.unwrap().is_none());
+        assert!(storage.load_collection("missing").await.unwrap().is_none());
+
+        let client = std::sync::Arc::new(grok_rust_sdk::Client::new("test-key").unwrap());
+        let mut session = grok_rust_sdk::session::Session::new(client, Model::Grok4, None);
+        session.set_tenant("acme");
+        session
+            .append(Message {
+                role: Role::User,
+                content: "Hello".into(),
+                tool_calls: None,
+                tool_call_id: None,
+                name: None,
+            })
+            .await
+            .unwrap();
+        let session = std::sync::Arc::new(session);
+        storage.save_session(&session).await.unwrap();
+
+        let loaded = storage.load_session(session.id()).await.unwrap().unwrap();
+        assert_eq!(loaded.id(), session.id());
+        assert_eq!(loaded.model(), Model::Grok4);
+        assert_eq!(loaded.created_at(), session.created_at());
+        assert_eq!(loaded.tenant(), Some("acme"));
+        assert_eq!(loaded.metadata().message_count, 1);
+        assert_eq!(loaded.messages().await[0].content.text(), "Hello");
+        assert_eq!(
+            storage.list_sessions().await.unwrap(),
+            vec![session.id.clone()]
+        );
+
+        let mut collection = Collection::new("notes", Some("kept".to_string()), vec![]);
+        collection.set_tenant("acme");
+        collection.add_session(session.clone()).await.unwrap();
+        storage.save_collection(&collection).await.unwrap();
+
+        let loaded = storage
+            .load_collection(&collection.id)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(loaded.id, collection.id);
+        assert_eq!(loaded.metadata().name, "notes");
+        assert_eq!(loaded.tenant().as_deref(), Some("acme"));
+        assert_eq!(loaded.metadata().session_count, 1);
+        assert_eq!(loaded.session_ids(), vec![session.id.clone()]);
+        assert_eq!(
+            storage.list_collections().await.unwrap(),
+            vec![collection.id.clone()]
+        );
+
+        // Another tenant sees neither
+        assert!(storage
+            .load_session_for("acme", session.id())
+            .await
+            .unwrap()
+            .is_some());
+        assert!(storage
+            .load_session_for("other", session.id())
+            .await
+            .unwrap()
+            .is_none());
+        assert!(storage
+            .load_collection_for("other", &collection.id)
+            .await
+            .unwrap()
+            .is_none());
+        assert_eq!(
+            storage.list_sessions_for("acme").await.unwrap(),
+            vec![session.id.clone()]
+        );
+        assert!(storage
+            .list_collections_for("other")
+            .await
+            .unwrap()
+            .is_empty());
+
+        storage.delete_session(session.id()).await.unwrap();
+        assert!(storage.load_session(session.id()).await.unwrap().is_none());
+        assert!(storage.list_sessions().await.unwrap().is_empty());
+        let loaded = storage
+            .load_collection(&collection.id)
+            .await
+            .unwrap()
+            .unwrap();
+        assert!(loaded.session_ids().is_empty());
+
+        storage.delete_collection(&collection.id).await.unwrap();
+        assert!(storage
+            .load_collection(&collection.id)
+            .await
+            .unwrap()
+            .is_none());
+        assert!(storage.list_collections().await.unwrap().is_empty());
+    }
+
+    /// Runs against the database in `DATABASE_URL`, and is skipped when it's unset
+    #[cfg(feature = "postgres")]
+    #[tokio::test]
+    async fn test_postgres_storage_round_trip() {
+        use grok_rust_sdk::collections::Collection;
+
+        let Some(storage) = postgres_storage().await else {
+            return;
+        };
+
+        let client = std::sync::Arc::new(grok_rust_sdk::Client::new("test-key").unwrap());
+        let mut session = grok_rust_sdk::session::Session::new(client, Model::Grok4, None);
+        session.set_tenant("acme");
+        for content in ["Hello", "Hi there"] {
+            session
+                .append(Message {
+                    role: Role::User,
+                    content: content.into(),
+                    tool_calls: None,
+                    tool_call_id: None,
+                    name: None,
+                })
+                .await
+                .unwrap();
+        }
+        let session = std::sync::Arc::new(session);
+        storage.save_session(&session).await.unwrap();
+
+        let loaded = storage.load_session(session.id()).await.unwrap().unwrap();
+        assert_eq!(loaded.model(), Model::Grok4);
+        assert_eq!(loaded.tenant(), Some("acme"));
+        let messages = loaded.messages().await;
+        let contents: Vec<_> = messages
+            .iter()
+            .map(|m| m.content.text().into_owned())
+            .collect();
+        assert_eq!(contents, ["Hello", "Hi there"]);
+
+        let mut collection = Collection::new("notes", None, vec![]);
+        collection.set_tenant("acme");
+        collection.add_session(session.clone()).await.unwrap();
+        storage.save_collection(&collection).await.unwrap();
+
+        let loaded = storage
+            .load_collection(&collection.id)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(loaded.metadata().name, "notes");
+        assert_eq!(loaded.session_ids(), vec![session.id.clone()]);
+
+        // Another tenant sees neither
+        assert!(storage
+            .load_session_for("acme", session.id())
+            .await
+            .unwrap()
+            .is_some());
+        assert!(storage
+            .load_session_for("other", session.id())
+            .await
+            .unwrap()
+            .is_none());
+        assert!(storage
+            .load_collection_for("other", &collection.id)
+            .await
+            .unwrap()
+            .is_none());
+        assert!(storage
+            .list_sessions_for("acme")
+            .await
+            .unwrap()
+            .contains(&session.id));
+        assert!(!storage
+            .list_collections_for("other")
+            .await
+            .unwrap()
+            .contains(&collection.id));
+
+        storage.delete_collection(&collection.id).await.unwrap();
+        storage.delete_session(session.id()).await.unwrap();
+        assert!(storage.load_session(session.id()).await.unwrap().is_none());
+        assert!(storage
+            .load_collection(&collection.id)
+            .await
+            .unwrap()
+            .is_none());
+    }
+
+    #[cfg(feature = "postgres")]
+    #[tokio::test]
+    async fn test_postgres_appends_message_rows() {
+        let Some(storage) = postgres_storage().await else {
+            return;
+        };
+        let url = std::env::var("DATABASE_URL").unwrap();
+        let (pg, connection) = tokio_postgres::connect(&url, tokio_postgres::NoTls)
+            .await
+            .unwrap();
+        tokio::spawn(connection);
+
+        let client = std::sync::Arc::new(grok_rust_sdk::Client::new("test-key").unwrap());
+        let session = grok_rust_sdk::session::Session::new(client, Model::Grok4, None);
+        let contents = || async {
+            pg.query(
+                "SELECT message->>'content' FROM messages WHERE session_id = $1 ORDER BY idx",
+                &[&session.id],
+            )
+            .await
+            .unwrap()
+            .iter()
+            .map(|row| row.get::<_, String>(0))
+            .collect::<Vec<_>>()
+        };
+
+        for content in ["one", "two"] {
+            session.append(user_message(content)).await.unwrap();
+            storage.save_session(&session).await.unwrap();
+        }
+        assert_eq!(contents().await, ["one", "two"]);
+
+        // Later saves of a history this storage wrote only compare the last
+        // stored row, so an edit to an earlier row by another writer goes unread
+        pg.execute(
+            "UPDATE messages SET message = jsonb_set(message, '{content}', '\"edited\"')
+             WHERE session_id = $1 AND idx = 0",
+            &[&session.id],
+        )
+        .await
+        .unwrap();
+        session.append(user_message("three")).await.unwrap();
+        storage.save_session(&session).await.unwrap();
+        assert_eq!(contents().await, ["edited", "two", "three"]);
+
+        // A storage that hasn't written this history compares every row
+        let other = postgres_storage().await.unwrap();
+        other.save_session(&session).await.unwrap();
+        assert_eq!(contents().await, ["one", "two", "three"]);
+
+        // A cleared history is rewritten
+        session.clear_history().await.unwrap();
+        session.append(user_message("fresh")).await.unwrap();
+        storage.save_session(&session).await.unwrap();
+        assert_eq!(contents().await, ["fresh"]);
+
+        storage.delete_session(session.id()).await.unwrap();
+    }
+
+    #[cfg(feature = "persistence")]
+    #[tokio::test]
+    async fn test_dataset_from_rated_sessions() {
+        use grok_rust_sdk::dataset::{write_jsonl, DatasetBuilder};
+
+        let storage = SqliteStorage::in_memory().unwrap();
+        let client = std::sync::Arc::new(grok_rust_sdk::Client::new("test-key").unwrap());
+        let message = |role: Role, content: &str| Message {
+            role,
+            content: content.into(),
+            tool_calls: None,
+            tool_call_id: None,
+            name: None,
+        };
+
+        for (question, rating) in [
+            ("alice@example.com?", 5),
+            ("alice@example.com?", 4),
+            ("bob?", 1),
+        ] {
+            let session = grok_rust_sdk::session::Session::new(client.clone(), Model::Grok4, None);
+            for msg in [
+                message(Role::System, "Be brief."),
+                message(Role::User, question),
+                message(Role::Assistant, "Yes."),
+                message(Role::User, "Thanks"),
+            ] {
+                session.append(msg).await.unwrap();
+            }
+            storage.save_session(&session).await.unwrap();
+            storage
+                .rate_session(&session.id, Some(rating))
+                .await
+                .unwrap();
+        }
+        assert!(storage.rate_session("missing", Some(1)).await.is_err());
+
+        let dataset = DatasetBuilder::new()
+            .min_rating(4)
+            .strip_system_prompts()
+            .anonymize(|text| text.replace("alice@example.com", "[EMAIL]"))
+            .build(&storage)
+            .await
+            .unwrap();
+
+        // Two sessions rate highly enough, and they're identical
+        assert_eq!(dataset.stats.selected, 2);
+        assert_eq!(dataset.stats.duplicates, 1);
+        assert_eq!(dataset.train.len(), 1);
+        assert!(dataset.validation.is_empty());
+
+        // Trailing turns after the last reply are cut off
+        let example = &dataset.train[0];
+        assert_eq!(example.len(), 2);
+        assert_eq!(example[0].content, "[EMAIL]?");
+        assert_eq!(example.last().unwrap().role, Role::Assistant);
+
+        let mut jsonl = Vec::new();
+        write_jsonl(&dataset.train, &mut jsonl).unwrap();
+        let line: serde_json::Value =
+            serde_json::from_str(String::from_utf8(jsonl).unwrap().trim()).unwrap();
+        assert_eq!(line["messages"][1]["content"], "Yes.");
+
+        // Every conversation lands in one of the sets, the same one each time
+        let split = DatasetBuilder::new()
+            .deduplicate(false)
+            .validation_split(0.5)
+            .seed(7);
+        let first = split.build(&storage).await.unwrap();
+        let second = split.build(&storage).await.unwrap();
+        assert_eq!(first.train.len() + first.validation.len(), 3);
+        assert_eq!(
+            serde_json::to_string(&first.validation).unwrap(),
+            serde_json::to_string(&second.validation).unwrap()
+        );
+        assert!(DatasetBuilder::new()
+            .validation_split(1.0)
+            .build(&storage)
+            .await
+            .is_err());
+    }
+
+    #[tokio::test]
+    async fn test_orchestrator_supervisor_routing() {
+        use grok_rust_sdk::agent::{Agent, AgentOptions};
+        use grok_rust_sdk::chat::{ChatCompletion, Tool};
+        use grok_rust_sdk::client::ChatOptions;
+        use grok_rust_sdk::orchestrator::{Orchestrator, USER};
+        use grok_rust_sdk::provider::{ChatProvider, ChunkStream};
+        use grok_rust_sdk::tools::ToolRegistry;
+        use std::sync::{Arc, Mutex};
+
+        /// Replies with scripted answers in order
+        #[derive(Debug)]
+        struct Scripted(Mutex<Vec<&'static str>>);
+
+        #[async_trait]
+        impl ChatProvider for Scripted {
+            async fn chat(
+                &self,
+                model: Model,
+                _messages: Vec<Arc<Message>>,
+                _tools: Option<Vec<Tool>>,
+                _options: Option<ChatOptions>,
+            ) -> grok_rust_sdk::Result<ChatCompletion> {
+                let content = self.0.lock().unwrap().remove(0);
+                Ok(ChatCompletion {
+                    id: "scripted".to_string(),
+                    model: model.as_str().to_string(),
+                    usage: None,
+                    message: Message {
+                        role: Role::Assistant,
+                        content: content.into(),
+                        tool_calls: None,
+                        tool_call_id: None,
+                        name: None,
+                    },
+                    finish_reason: Some("stop".to_string()),
+                    timings: None,
+                    annotations: Vec::new(),
+                    rate_limit: None,
+                })
+            }
+
+            async fn chat_stream(
+                &self,
+                _model: Model,
+                _messages: Vec<Message>,
+                _tools: Option<Vec<Tool>>,
+            ) -> grok_rust_sdk::Result<ChunkStream> {
+                Ok(Box::pin(futures::stream::empty()))
+            }
+        }
+
+        let provider = Arc::new(Scripted(Mutex::new(vec![
+            "NEXT math: add 2 and 2",
+            "4",
+            "FINISH: 2 + 2 = 4",
+            "NEXT poet: write a haiku",
+        ])));
+        let agent = |model| {
+            Agent::new(
+                provider.clone(),
+                model,
+                ToolRegistry::new(),
+                AgentOptions::default(),
+            )
+        };
+        let mut orchestrator = Orchestrator::new()
+            .with_agent("supervisor", agent(Model::Grok4))
+            .with_agent("math", agent(Model::Grok3));
+
+        let answer = orchestrator
+            .supervise("supervisor", "What's 2 + 2?", 5)
+            .await
+            .unwrap();
+        assert_eq!(answer.as_deref(), Some("2 + 2 = 4"));
+
+        let route: Vec<_> = orchestrator
+            .transcript()
+            .iter()
+            .map(|entry| (entry.from.as_str(), entry.to.as_deref()))
+            .collect();
+        assert_eq!(
+            route,
+            [
+                (USER, Some("supervisor")),
+                ("supervisor", Some("math")),
+                ("math", Some("supervisor")),
+                ("supervisor", Some(USER)),
+            ]
+        );
+        assert_eq!(orchestrator.transcript()[2].content, "4");
+
+        // Routing to an agent that doesn't exist fails instead of guessing
+        orchestrator.reset();
+        let err = orchestrator
+            .supervise("supervisor", "Write a poem", 5)
+            .await
+            .unwrap_err();
+        assert!(matches!(err, GrokError::NotFound { .. }));
+    }
+
+    #[tokio::test]
+    async fn test_agent_continues_conversation() {
+        use grok_rust_sdk::agent::{Agent, AgentOptions, StopReason};
+        use grok_rust_sdk::chat::{
+            ChatCompletion, Tool, ToolCall as ChatToolCall, ToolFunction as ChatToolFunction,
+        };
+        use grok_rust_sdk::client::ChatOptions;
+        use grok_rust_sdk::provider::{ChatProvider, ChunkStream};
+        use std::sync::{Arc, Mutex};
+
+        /// Calls the mock tool once, then answers with what it saw
+        #[derive(Debug, Default)]
+        struct ToolThenAnswer(Mutex<Vec<Vec<Role>>>);
+
+        #[async_trait]
+        impl ChatProvider for ToolThenAnswer {
+            async fn chat(
+                &self,
+                model: Model,
+                messages: Vec<Arc<Message>>,
+                _tools: Option<Vec<Tool>>,
+                _options: Option<ChatOptions>,
+            ) -> grok_rust_sdk::Result<ChatCompletion> {
+                self.0.lock().unwrap().push(
+                    messages
+                        .iter()
+                        .map(|message| message.role.clone())
+                        .collect(),
+                );
+                let last = messages.last().unwrap();
+                let (content, tool_calls) = if last.role == Role::Tool {
+                    (last.content.to_string(), None)
+                } else {
+                    let call = ChatToolCall {
+                        id: "call-1".to_string(),
+                        function: ChatToolFunction {
+                            name: "mock_tool".to_string(),
+                            arguments: r#"{"input": "ping"}"#.to_string(),
+                        },
+                    };
+                    (String::new(), Some(vec![call]))
+                };
+                Ok(ChatCompletion {
+                    id: "agent".to_string(),
+                    model: model.as_str().to_string(),
+                    usage: None,
+                    message: Message {
+                        role: Role::Assistant,
+                        content: content.into(),
+                        tool_calls,
+                        tool_call_id: None,
+                        name: None,
+                    },
+                    finish_reason: Some("stop".to_string()),
+                    timings: None,
+                    annotations: Vec::new(),
+                    rate_limit: None,
+                })
+            }
+
+            async fn chat_stream(
+                &self,
+                _model: Model,
+                _messages: Vec<Message>,
+                _tools: Option<Vec<Tool>>,
+            ) -> grok_rust_sdk::Result<ChunkStream> {
+                Ok(Box::pin(futures::stream::empty()))
+            }
+        }
+
+        let registry = ToolRegistry::new();
+        registry.register(MockTool);
+        let provider = Arc::new(ToolThenAnswer::default());
+        let agent = Agent::new(
+            provider.clone(),
+            Model::Grok4,
+            registry,
+            AgentOptions {
+                system_prompt: Some("Use tools.".to_string()),
+                ..Default::default()
+            },
+        );
+
+        let history = vec![
+            Message {
+                role: Role::User,
+                content: "Hi".into(),
+                tool_calls: None,
+                tool_call_id: None,
+                name: None,
+            },
+            Message {
+                role: Role::Assistant,
+                content: "Hello!".into(),
+                tool_calls: None,
+                tool_call_id: None,
+                name: None,
+            },
+            Message {
+                role: Role::User,
+                content: "Call the tool".into(),
+                tool_calls: None,
+                tool_call_id: None,
+                name: None,
+            },
+        ];
+        let run = agent.run_messages(history).await.unwrap();
+
+        assert_eq!(run.stop_reason, StopReason::Completed);
+        assert_eq!(run.steps.len(), 2);
+        assert!(run.output.unwrap().contains("mock_response"));
+
+        // The system prompt goes first and the history is sent as given
+        let requests = provider.0.lock().unwrap();
+        assert_eq!(
+            requests[0],
+            [Role::System, Role::User, Role::Assistant, Role::User]
+        );
+        assert_eq!(requests[1].last(), Some(&Role::Tool));
+        assert_eq!(run.messages.len(), 7);
+    }
+
+    /// Answer one chat completion request per reply message from a local
+    /// socket; the server thread returns the request bodies it received
+    fn serve_completions(
+        replies: Vec<serde_json::Value>,
+    ) -> (
+        std::net::SocketAddr,
+        std::thread::JoinHandle<Vec<serde_json::Value>>,
+    ) {
+        use std::io::Write;
+
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        let server = std::thread::spawn(move || {
+            let mut requests = Vec::new();
+            for message in replies {
+                let (stream, _) = listener.accept().unwrap();
+                requests.push(serde_json::from_slice(&read_request_body(&stream)).unwrap());
+
+                let response = serde_json::json!({
+                    "id": "cmpl-1",
+                    "object": "chat.completion",
+                    "created": 0,
+                    "model": "grok-4",
+                    "choices": [{ "index": 0, "message": message, "finish_reason": "stop" }]
+                })
+                .to_string();
+                write!(
+                    &stream,
+                    "HTTP/1.1 200 OK\r\ncontent-type: application/json\r\nconnection: close\r\ncontent-length: {}\r\n\r\n{}",
+                    response.len(),
+                    response
+                )
+                .unwrap();
+            }
+            requests
+        });
+        (address, server)
+    }
+
+    fn mock_tool_call(id: &str, arguments: &str) -> serde_json::Value {
+        serde_json::json!({
+            "role": "assistant",
+            "content": "",
+            "tool_calls": [{
+                "id": id,
+                "type": "function",
+                "function": { "name": "mock_tool", "arguments": arguments }
+            }]
+        })
+    }
+
+    fn auto_tools_client(address: std::net::SocketAddr) -> grok_rust_sdk::Client {
+        grok_rust_sdk::Client::builder()
+            .api_key("test-key")
+            .base_url(format!("http://{}", address))
+            .max_retries(0)
+            .build()
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_chat_with_auto_tools_round_trip() {
+        let (address, server) = serve_completions(vec![
+            mock_tool_call("call-1", r#"{"input": "ping"}"#),
+            serde_json::json!({ "role": "assistant", "content": "pong" }),
+        ]);
+        let registry = ToolRegistry::new();
+        registry.register(MockTool);
+
+        let result = auto_tools_client(address)
+            .chat_with_auto_tools(Model::Grok4, vec![user_message("Ping")], &registry, 5)
+            .await
+            .unwrap();
+        assert_eq!(
+            result.stop_reason,
+            grok_rust_sdk::agent::StopReason::Completed
+        );
+        assert_eq!(result.completion.message.content.to_string(), "pong");
+        assert_eq!(result.tool_calls.len(), 1);
+        assert_eq!(result.tool_calls[0].0.id, "call-1");
+        let output: serde_json::Value =
+            serde_json::from_str(&result.tool_calls[0].1.content).unwrap();
+        assert_eq!(output["input"]["input"], "ping");
+        let roles: Vec<Role> = result.messages.iter().map(|m| m.role.clone()).collect();
+        assert_eq!(
+            roles,
+            [Role::User, Role::Assistant, Role::Tool, Role::Assistant]
+        );
+
+        // The tool's result went back to the model with its call ID
+        let requests = server.join().unwrap();
+        assert_eq!(requests.len(), 2);
+        assert_eq!(requests[0]["tools"][0]["function"]["name"], "mock_tool");
+        let tool_message = &requests[1]["messages"][2];
+        assert_eq!(tool_message["role"], "tool");
+        assert_eq!(tool_message["tool_call_id"], "call-1");
+    }
+
+    #[tokio::test]
+    async fn test_chat_with_auto_tools_stops_at_max_iterations() {
+        let (address, server) = serve_completions(vec![
+            mock_tool_call("call-1", r#"{"input": "ping"}"#),
+            mock_tool_call("call-2", r#"{"input": "ping"}"#),
+        ]);
+        let registry = ToolRegistry::new();
+        registry.register(MockTool);
+
+        let result = auto_tools_client(address)
+            .chat_with_auto_tools(Model::Grok4, vec![user_message("Ping")], &registry, 2)
+            .await
+            .unwrap();
+        assert_eq!(
+            result.stop_reason,
+            grok_rust_sdk::agent::StopReason::MaxIterations
+        );
+        // The last completion's tool call is left unrun
+        assert_eq!(result.tool_calls.len(), 1);
+        assert_eq!(result.tool_calls[0].0.id, "call-1");
+        assert!(result.completion.message.tool_calls.is_some());
+        assert_eq!(server.join().unwrap().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_chat_with_auto_tools_error_policy() {
+        use grok_rust_sdk::tools::ToolErrorPolicy;
+
+        // By default a failing tool call ends the loop
+        let (address, server) = serve_completions(vec![mock_tool_call("call-1", "{}")]);
+        let registry = ToolRegistry::new();
+        registry.register(MockTool);
+        let err = auto_tools_client(address)
+            .chat_with_auto_tools(Model::Grok4, vec![user_message("Ping")], &registry, 5)
+            .await
+            .unwrap_err();
+        assert!(matches!(err, GrokError::InvalidToolArguments { .. }));
+        assert_eq!(server.join().unwrap().len(), 1);
+
+        // Reported to the model, the failure lets it answer anyway
+        let (address, server) = serve_completions(vec![
+            mock_tool_call("call-1", "{}"),
+            serde_json::json!({ "role": "assistant", "content": "Sorry" }),
+        ]);
+        let mut registry = ToolRegistry::new();
+        registry.register(MockTool);
+        registry.set_error_policy(ToolErrorPolicy::ReturnToModel);
+        let result = auto_tools_client(address)
+            .chat_with_auto_tools(Model::Grok4, vec![user_message("Ping")], &registry, 5)
+            .await
+            .unwrap();
+        assert_eq!(
+            result.stop_reason,
+            grok_rust_sdk::agent::StopReason::Completed
+        );
+        assert!(result.tool_calls[0].1.error.is_some());
+
+        let requests = server.join().unwrap();
+        let tool_message = &requests[1]["messages"][2];
+        let content: serde_json::Value =
+            serde_json::from_str(tool_message["content"].as_str().unwrap()).unwrap();
+        assert!(content["error"].is_string());
+    }
+
+    /// Calls `mock_tool` with scripted arguments, then with valid ones once
+    /// the script runs out; `None` in the script answers instead
+    #[derive(Debug, Default)]
+    struct ScriptedToolCalls {
+        arguments: std::sync::Mutex<std::collections::VecDeque<Option<&'static str>>>,
+        requests: std::sync::Mutex<Vec<Vec<Message>>>,
+    }
+
+    impl ScriptedToolCalls {
+        fn new(arguments: impl IntoIterator<Item = Option<&'static str>>) -> Self {
+            Self {
+                arguments: std::sync::Mutex::new(arguments.into_iter().collect()),
+                requests: Default::default(),
+            }
+        }
+    }
+
+    #[async_trait]
+    impl grok_rust_sdk::provider::ChatProvider for ScriptedToolCalls {
+        async fn chat(
+            &self,
+            model: Model,
+            messages: Vec<std::sync::Arc<Message>>,
+            _tools: Option<Vec<grok_rust_sdk::chat::Tool>>,
+            _options: Option<grok_rust_sdk::client::ChatOptions>,
+        ) -> grok_rust_sdk::Result<grok_rust_sdk::chat::ChatCompletion> {
+            let mut requests = self.requests.lock().unwrap();
+            requests.push(messages.iter().map(|m| Message::clone(m)).collect());
+            let arguments = self
+                .arguments
+                .lock()
+                .unwrap()
+                .pop_front()
+                .unwrap_or(Some(r#"{"input": "ping"}"#));
+            let tool_calls = arguments.map(|arguments| {
+                vec![grok_rust_sdk::chat::ToolCall {
+                    id: format!("call-{}", requests.len()),
+                    function: grok_rust_sdk::chat::ToolFunction {
+                        name: "mock_tool".to_string(),
+                        arguments: arguments.to_string(),
+                    },
+                }]
+            });
+            let content = if tool_calls.is_some() { "" } else { "done" };
+            Ok(grok_rust_sdk::chat::ChatCompletion {
+                id: "scripted".to_string(),
+                model: model.as_str().to_string(),
+                usage: Some(grok_rust_sdk::chat::Usage {
+                    prompt_tokens: 60,
+                    completion_tokens: 40,
+                    total_tokens: 100,
+                }),
+                message: Message {
+                    role: Role::Assistant,
+                    content: content.into(),
+                    tool_calls,
+                    tool_call_id: None,
+                    name: None,
+                },
+                finish_reason: Some("stop".to_string()),
+                timings: None,
+                annotations: Vec::new(),
+                rate_limit: None,
+            })
+        }
+
+        async fn chat_stream(
+            &self,
+            _model: Model,
+            _messages: Vec<Message>,
+            _tools: Option<Vec<grok_rust_sdk::chat::Tool>>,
+        ) -> grok_rust_sdk::Result<grok_rust_sdk::provider::ChunkStream> {
+            Ok(Box::pin(futures::stream::empty()))
+        }
+    }
+
+    fn scripted_agent(
+        provider: std::sync::Arc<ScriptedToolCalls>,
+        options: grok_rust_sdk::agent::AgentOptions,
+    ) -> grok_rust_sdk::agent::Agent {
+        let registry = ToolRegistry::new();
+        registry.register(MockTool);
+        grok_rust_sdk::agent::Agent::new(provider, Model::Grok4, registry, options)
+    }
+
+    #[tokio::test]
+    async fn test_session_auto_save_batches_appends() {
+        use grok_rust_sdk::session::{AutoSaveOptions, Session};
+        use std::sync::Arc;
+        use std::time::Duration;
+
+        let storage = Arc::new(MemoryStorage::new());
+        let provider = Arc::new(ScriptedToolCalls::new([None]));
+        let mut session = Session::new(provider, Model::Grok4, None);
+        session.enable_auto_save(
+            storage.clone(),
+            AutoSaveOptions {
+                debounce: Duration::from_secs(3600),
+                max_pending: 3,
+            },
+        );
+        let stored = || {
+            let (storage, id) = (storage.clone(), session.id().to_string());
+            async move {
+                match storage.load_session(&id).await.unwrap() {
+                    Some(session) => session.message_count().await,
+                    None => 0,
+                }
+            }
+        };
+
+        // The first append saves at once, then saves wait for the debounce
+        // window or enough pending messages
+        session.append(user_message("one")).await.unwrap();
+        assert_eq!(stored().await, 1);
+        session.append(user_message("two")).await.unwrap();
+        session.append(user_message("three")).await.unwrap();
+        assert_eq!(stored().await, 1);
+        session.append(user_message("four")).await.unwrap();
+        assert_eq!(stored().await, 4);
+
+        session.append(user_message("five")).await.unwrap();
+        assert_eq!(stored().await, 4);
+        session.flush().await.unwrap();
+        assert_eq!(stored().await, 5);
+
+        // A completed turn is always flushed
+        session.chat("six").await.unwrap();
+        assert_eq!(stored().await, 7);
+
+        // Clearing the history is a pending change like an append
+        session.clear_history().await.unwrap();
+        assert_eq!(stored().await, 7);
+        session.flush().await.unwrap();
+        assert_eq!(stored().await, 0);
+    }
+
+    #[tokio::test]
+    async fn test_session_auto_save_keeps_appends_made_while_saving() {
+        use grok_rust_sdk::collections::Collection;
+        use grok_rust_sdk::session::{AutoSaveOptions, Session};
+        use std::sync::atomic::{AtomicBool, Ordering};
+        use std::sync::Arc;
+        use std::time::Duration;
+        use tokio::sync::Notify;
+
+        /// Holds a save open after taking its snapshot, once armed
+        #[derive(Debug, Default)]
+        struct SlowSaves {
+            inner: MemoryStorage,
+            armed: AtomicBool,
+            saved: Notify,
+            release: Notify,
+        }
+
+        #[async_trait]
+        impl Storage for SlowSaves {
+            async fn save_session(&self, session: &Session) -> grok_rust_sdk::Result<()> {
+                self.inner.save_session(session).await?;
+                if self.armed.swap(false, Ordering::SeqCst) {
+                    self.saved.notify_one();
+                    self.release.notified().await;
+                }
+                Ok(())
+            }
+
+            async fn load_session(&self, id: &str) -> grok_rust_sdk::Result<Option<Session>> {
+                self.inner.load_session(id).await
+            }
+
+            async fn delete_session(&self, id: &str) -> grok_rust_sdk::Result<()> {
+                self.inner.delete_session(id).await
+            }
+
+            async fn list_sessions(&self) -> grok_rust_sdk::Result<Vec<String>> {
+                self.inner.list_sessions().await
+            }
+
+            async fn load_session_for(
+                &self,
+                tenant: &str,
+                id: &str,
+            ) -> grok_rust_sdk::Result<Option<Session>> {
+                self.inner.load_session_for(tenant, id).await
+            }
+
+            async fn list_sessions_for(&self, tenant: &str) -> grok_rust_sdk::Result<Vec<String>> {
+                self.inner.list_sessions_for(tenant).await
+            }
+
+            async fn save_collection(&self, collection: &Collection) -> grok_rust_sdk::Result<()> {
+                self.inner.save_collection(collection).await
+            }
+
+            async fn load_collection(&self, id: &str) -> grok_rust_sdk::Result<Option<Collection>> {
+                self.inner.load_collection(id).await
+            }
+
+            async fn delete_collection(&self, id: &str) -> grok_rust_sdk::Result<()> {
+                self.inner.delete_collection(id).await
+            }
+
+            async fn list_collections(&self) -> grok_rust_sdk::Result<Vec<String>> {
+                self.inner.list_collections().await
+            }
+
+            async fn load_collection_for(
+                &self,
+                tenant: &str,
+                id: &str,
+            ) -> grok_rust_sdk::Result<Option<Collection>> {
+                self.inner.load_collection_for(tenant, id).await
+            }
+
+            async fn list_collections_for(
+                &self,
+                tenant: &str,
+            ) -> grok_rust_sdk::Result<Vec<String>> {
+                self.inner.list_collections_for(tenant).await
+            }
+        }
+
+        let storage = Arc::new(SlowSaves::default());
+        let provider = Arc::new(ScriptedToolCalls::new([None]));
+        let mut session = Session::new(provider, Model::Grok4, None);
+        session.enable_auto_save(
+            storage.clone(),
+            AutoSaveOptions {
+                debounce: Duration::from_secs(3600),
+                max_pending: 100,
+            },
+        );
+        let stored = || async {
+            let id = session.id();
+            match storage.load_session(id).await.unwrap() {
+                Some(session) => session.message_count().await,
+                None => 0,
+            }
+        };
+
+        session.append(user_message("one")).await.unwrap();
+        session.append(user_message("two")).await.unwrap();
+        assert_eq!(stored().await, 1);
+
+        // "three" arrives after the flush took its snapshot
+        storage.armed.store(true, Ordering::SeqCst);
+        let (flushed, ()) = tokio::join!(session.flush(), async {
+            storage.saved.notified().await;
+            session.append(user_message("three")).await.unwrap();
+            storage.release.notify_one();
+        });
+        flushed.unwrap();
+        assert_eq!(stored().await, 2);
+
+        // so it's still pending and the next flush writes it
+        session.flush().await.unwrap();
+        assert_eq!(stored().await, 3);
+    }
+
+    #[cfg(feature = "persistence")]
+    #[tokio::test]
+    async fn test_change_feed_across_connections() {
+        use futures::StreamExt;
+        use grok_rust_sdk::collections::Collection;
+        use grok_rust_sdk::persistence::{ChangeEntity, ChangeOperation};
+        use grok_rust_sdk::session::Session;
+        use std::sync::Arc;
+        use std::time::Duration;
+
+        // Two handles on one file stand in for two processes
+        let path = std::env::temp_dir().join(format!("grok-changes-{}.db", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let writer = SqliteStorage::new(&path).unwrap();
+        let reader = SqliteStorage::new(&path).unwrap();
+        assert_eq!(reader.latest_change().await.unwrap(), 0);
+
+        let now = chrono::Utc::now();
+        let session = Arc::new(Session::restore(
+            "watched".to_string(),
+            Model::Grok4,
+            None,
+            now,
+            now,
+            vec![user_message("Hello"), user_message("Again")],
+        ));
+        writer.save_session(&session).await.unwrap();
+        let collection = Collection::new("filed", None, vec![]);
+        collection.add_session(session.clone()).await.unwrap();
+        writer.save_collection(&collection).await.unwrap();
+
+        let changes = reader.changes_since(0).await.unwrap();
+        let summary: Vec<_> = changes
+            .iter()
+            .map(|c| (c.entity, c.entity_id.clone(), c.operation, c.detail.clone()))
+            .collect();
+        assert_eq!(
+            summary,
+            [
+                (
+                    ChangeEntity::Session,
+                    "watched".to_string(),
+                    ChangeOperation::Insert,
+                    None
+                ),
+                (
+                    ChangeEntity::Message,
+                    "watched".to_string(),
+                    ChangeOperation::Insert,
+                    Some("0".to_string())
+                ),
+                (
+                    ChangeEntity::Message,
+                    "watched".to_string(),
+                    ChangeOperation::Insert,
+                    Some("1".to_string())
+                ),
+                (
+                    ChangeEntity::Collection,
+                    collection.id.clone(),
+                    ChangeOperation::Insert,
+                    None
+                ),
+                (
+                    ChangeEntity::CollectionSession,
+                    collection.id.clone(),
+                    ChangeOperation::Insert,
+                    Some("watched".to_string())
+                ),
+            ]
+        );
+        assert!(changes.windows(2).all(|pair| pair[0].seq < pair[1].seq));
+
+        // Resuming from a cursor yields only later changes
+        let cursor = reader.latest_change().await.unwrap();
+        assert_eq!(cursor, changes.last().unwrap().seq);
+        assert!(reader.changes_since(cursor).await.unwrap().is_empty());
+
+        // The watch stream polls until the other handle writes
+        let stream = reader.watch(cursor, Duration::from_millis(10));
+        futures::pin_mut!(stream);
+        let (seen, _) = tokio::join!(
+            tokio::time::timeout(Duration::from_secs(5), stream.next()),
+            async {
+                tokio::time::sleep(Duration::from_millis(50)).await;
+                session.append(user_message("Later")).await.unwrap();
+                writer.save_session(&session).await.unwrap();
+            }
+        );
+        let update = seen.unwrap().unwrap().unwrap();
+        assert_eq!(
+            (update.entity, update.operation),
+            (ChangeEntity::Session, ChangeOperation::Update)
+        );
+        let message = stream.next().await.unwrap().unwrap();
+        assert_eq!(
+            (message.entity, message.detail.as_deref()),
+            (ChangeEntity::Message, Some("2"))
+        );
+
+        // Trimming drops entries readers have consumed
+        assert_eq!(reader.trim_changes(cursor).await.unwrap(), 5);
+        assert_eq!(reader.changes_since(0).await.unwrap().len(), 2);
+
+        // Messages rewritten in place or removed are recorded too
+        let cursor = reader.latest_change().await.unwrap();
+        let mut partial = user_message("Par");
+        partial.role = Role::Assistant;
+        writer
+            .save_partial_reply("watched", 3, &partial)
+            .await
+            .unwrap();
+        partial.content = "Partial".into();
+        writer
+            .save_partial_reply("watched", 3, &partial)
+            .await
+            .unwrap();
+        writer.delete_session("watched").await.unwrap();
+        let messages: Vec<_> = reader
+            .changes_since(cursor)
+            .await
+            .unwrap()
+            .into_iter()
+            .filter(|c| c.entity == ChangeEntity::Message)
+            .map(|c| (c.operation, c.detail.unwrap()))
+            .collect();
+        assert_eq!(
+            messages,
+            [
+                (ChangeOperation::Insert, "3".to_string()),
+                (ChangeOperation::Update, "3".to_string()),
+                (ChangeOperation::Delete, "0".to_string()),
+                (ChangeOperation::Delete, "1".to_string()),
+                (ChangeOperation::Delete, "2".to_string()),
+                (ChangeOperation::Delete, "3".to_string()),
+            ]
+        );
+
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[cfg(feature = "persistence")]
+    #[tokio::test]
+    async fn test_usage_history_rollups() {
+        use grok_rust_sdk::budget::ModelPricing;
+        use grok_rust_sdk::session::SessionManager;
+        use std::sync::Arc;
+        use std::time::Duration;
+
+        let storage = Arc::new(
+            SqliteStorage::builder()
+                .in_memory()
+                .model_pricing(
+                    Model::Grok4,
+                    ModelPricing {
+                        prompt_per_million: 1.0,
+                        completion_per_million: 2.0,
+                    },
+                )
+                .build()
+                .unwrap(),
+        );
+
+        // Completed turns of a session with storage are recorded
+        let provider = Arc::new(ScriptedToolCalls::new([None, None, None]));
+        let sessions = SessionManager::with_storage(provider.clone(), storage.clone());
+        let session = sessions.create_session(Model::Grok4, None).await;
+        session.chat("one").await.unwrap();
+        session.chat("two").await.unwrap();
+
+        // As are completions made outside any session, priced or not
+        let completion = grok_rust_sdk::provider::ChatProvider::chat(
+            provider.as_ref(),
+            Model::Grok3,
+            vec![Arc::new(user_message("three"))],
+            None,
+            None,
+        )
+        .await
+        .unwrap();
+        storage
+            .record_usage(None, &completion, Duration::from_millis(300))
+            .await
+            .unwrap();
+
+        let by_session = storage.usage_by_session(None).await.unwrap();
+        let keys: Vec<_> = by_session.iter().map(|r| r.key.as_str()).collect();
+        assert_eq!(keys, ["", session.id.as_str()]);
+        let (outside, within) = (&by_session[0], &by_session[1]);
+        assert_eq!(
+            (
+                within.requests,
+                within.prompt_tokens,
+                within.completion_tokens,
+                within.total_tokens
+            ),
+            (2, 120, 80, 200)
+        );
+        let cost = within.estimated_cost.unwrap();
+        assert!((cost - 2.0 * 140.0 / 1_000_000.0).abs() < 1e-12);
+        assert_eq!(outside.requests, 1);
+        assert!(outside.estimated_cost.is_none());
+        assert_eq!(outside.average_latency, Duration::from_millis(300));
+
+        let by_day = storage.usage_by_day(None).await.unwrap();
+        assert_eq!(by_day.len(), 1);
+        assert_eq!(
+            by_day[0].key,
+            chrono::Utc::now().format("%Y-%m-%d").to_string()
+        );
+        assert_eq!((by_day[0].requests, by_day[0].total_tokens), (3, 300));
+        let later = chrono::Utc::now() + chrono::Duration::hours(1);
+        assert!(storage.usage_by_day(Some(later)).await.unwrap().is_empty());
+
+        // Replays compare against what the storage recorded
+        let usage = Storage::session_usage(storage.as_ref(), &session.id)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!((usage.completions, usage.prompt_tokens), (2, 120));
+    }
+
+    #[tokio::test]
+    async fn test_agent_stops_at_max_iterations() {
+        use grok_rust_sdk::agent::{AgentOptions, StopReason};
+
+        let provider = std::sync::Arc::new(ScriptedToolCalls::default());
+        let agent = scripted_agent(
+            provider.clone(),
+            AgentOptions {
+                max_iterations: 3,
+                ..Default::default()
+            },
+        );
+        let run = agent.run("Keep calling the tool").await.unwrap();
+
+        assert_eq!(run.stop_reason, StopReason::MaxIterations);
+        assert_eq!(run.steps.len(), 3);
+        assert!(run.output.is_none());
+        assert_eq!(
+            (run.prompt_tokens, run.completion_tokens, run.total_tokens),
+            (180, 120, 300)
+        );
+
+        // Each tool result is fed back before the next model call
+        let requests = provider.requests.lock().unwrap();
+        assert_eq!(requests.len(), 3);
+        for (i, request) in requests.iter().enumerate().skip(1) {
+            let result = request.last().unwrap();
+            assert_eq!(result.role, Role::Tool);
+            assert_eq!(
+                result.tool_call_id.as_deref(),
+                Some(format!("call-{}", i).as_str())
+            );
+            assert_eq!(result.name.as_deref(), Some("mock_tool"));
+            assert!(result.content.text().contains("mock_response"));
+            assert_eq!(request.len(), 1 + 2 * i);
+        }
+        assert_eq!(run.messages.len(), 7);
+        assert_eq!(run.steps[2].tool_calls[0].1.tool_call_id, "call-3");
+    }
+
+    #[tokio::test]
+    async fn test_agent_stops_at_token_budget() {
+        use grok_rust_sdk::agent::{AgentOptions, StopReason};
+
+        let provider = std::sync::Arc::new(ScriptedToolCalls::default());
+        let agent = scripted_agent(
+            provider.clone(),
+            AgentOptions {
+                token_budget: Some(250),
+                ..Default::default()
+            },
+        );
+        let run = agent.run("Keep calling the tool").await.unwrap();
+
+        // The budget is checked before each call, so the call crossing it still runs
+        assert_eq!(run.stop_reason, StopReason::TokenBudget);
+        assert_eq!(run.steps.len(), 3);
+        assert_eq!(run.total_tokens, 300);
+        assert!(run.steps.iter().all(|step| step.total_tokens == 100));
+        assert_eq!(provider.requests.lock().unwrap().len(), 3);
+    }
+
+    #[tokio::test]
+    async fn test_agent_repairs_invalid_arguments() {
+        use grok_rust_sdk::agent::{AgentOptions, StopReason};
+
+        let options = AgentOptions {
+            max_argument_repairs: Some(2),
+            ..Default::default()
+        };
+
+        // Failures only count while they're in a row
+        let provider = std::sync::Arc::new(ScriptedToolCalls::new([
+            Some("{}"),
+            Some("not json"),
+            Some(r#"{"input": "ping"}"#),
+            Some("{}"),
+            Some("{}"),
+            None,
+        ]));
+        let run = scripted_agent(provider.clone(), options.clone())
+            .run("Call the tool")
+            .await
+            .unwrap();
+        assert_eq!(run.stop_reason, StopReason::Completed);
+        assert_eq!(run.output.as_deref(), Some("done"));
+        assert_eq!(run.steps.len(), 6);
+
+        let (call, result) = &run.steps[0].tool_calls[0];
+        assert_eq!(result.tool_call_id, call.id);
+        assert!(matches!(
+            result.error.as_deref(),
+            Some(GrokError::InvalidToolArguments { .. })
+        ));
+        let feedback = provider.requests.lock().unwrap()[1].last().unwrap().clone();
+        assert_eq!(feedback.role, Role::Tool);
+        assert!(feedback
+            .content
+            .text()
+            .contains("Call the tool again with corrected arguments"));
+        assert!(run.steps[2].tool_calls[0].1.error.is_none());
+
+        // A third invalid call in a row fails the run
+        let provider = std::sync::Arc::new(ScriptedToolCalls::new([Some("{}"); 3]));
+        let error = scripted_agent(provider.clone(), options)
+            .run("Call the tool")
+            .await
+            .unwrap_err();
+        assert!(matches!(
+            error,
+            GrokError::InvalidToolArguments { ref tool, .. } if tool == "mock_tool"
+        ));
+        assert_eq!(provider.requests.lock().unwrap().len(), 3);
+    }
+
+    #[cfg(feature = "persistence")]
+    #[tokio::test]
+    async fn test_audit_log_records_streamed_exchange() {
+        use futures::StreamExt;
+        use grok_rust_sdk::audit::{AuditKind, Auditor};
+        use grok_rust_sdk::vcr::{Cassette, Interaction, Vcr};
+        use std::sync::Arc;
+
+        let event = serde_json::json!({
+            "id": "chatcmpl-1",
+            "object": "chat.completion.chunk",
+            "created": 0,
+            "model": "grok-4",
+            "choices": [{ "index": 0, "delta": { "content": "Hi" }, "finish_reason": null }]
+        });
+        let cassette = Cassette {
+            interactions: vec![Interaction {
+                endpoint: "/chat/completions".to_string(),
+                request: serde_json::json!({
+                    "model": "grok-4",
+                    "messages": [{ "role": "user", "content": "my secret" }],
+                    "stream": true
+                }),
+                status: 200,
+                headers: [("x-request-id".to_string(), "req-1".to_string())]
+                    .into_iter()
+                    .collect(),
+                body: format!("data: {}\n\ndata: [DONE]\n\n", event),
+            }],
+        };
+        let storage = Arc::new(SqliteStorage::in_memory().unwrap());
+        let auditor = Auditor::new(storage.clone())
+            .redact(|body| body.replace("secret", "[REDACTED]"))
+            .retention(std::time::Duration::from_secs(3600));
+        let client = grok_rust_sdk::Client::builder()
+            .api_key("unused")
+            .vcr(Vcr::from_cassette("audit.json", cassette))
+            .audit(auditor)
+            .build()
+            .unwrap();
+
+        let message = Message {
+            role: Role::User,
+            content: "my secret".into(),
+            tool_calls: None,
+            tool_call_id: None,
+            name: None,
+        };
+        let items: Vec<_> = client
+            .chat_stream(Model::Grok4, vec![message], None)
+            .await
+            .unwrap()
+            .collect()
+            .await;
+        assert_eq!(items.len(), 1);
+
+        let records = storage.audit_log(None, None).await.unwrap();
+        let kinds: Vec<_> = records.iter().map(|record| record.kind).collect();
+        assert_eq!(
+            kinds,
+            [
+                AuditKind::Request,
+                AuditKind::StreamFrame,
+                AuditKind::StreamFrame
+            ]
+        );
+        assert!(records[0].body.contains("my [REDACTED]"));
+        assert_eq!(records[0].request_id, None);
+        assert_eq!(records[1].body, event.to_string());
+        assert_eq!(records[2].body, "[DONE]");
+        assert_eq!(records[2].request_id.as_deref(), Some("req-1"));
+        assert_eq!(records[2].status, Some(200));
+        assert!(records
+            .iter()
+            .all(|record| record.exchange_id == records[0].exchange_id));
+
+        let exchange = storage
+            .audit_log(Some(&records[0].exchange_id), None)
+            .await
+            .unwrap();
+        assert_eq!(exchange.len(), 3);
+    }
+
+    #[tokio::test]
+    async fn test_schema_validation_of_structured_reply() {
+        use grok_rust_sdk::client::ChatOptions;
+        use grok_rust_sdk::vcr::{Cassette, Interaction, Vcr};
+
+        let response_format = serde_json::json!({
+            "type": "json_schema",
+            "json_schema": {
+                "name": "person",
+                "schema": {
+                    "type": "object",
+                    "properties": { "age": { "type": "integer" } },
+                    "required": ["age"]
+                }
+            }
+        });
+        let cassette = Cassette {
+            interactions: vec![Interaction {
+                endpoint: "/chat/completions".to_string(),
+                request: serde_json::json!({
+                    "model": "grok-4",
+                    "messages": [{ "role": "user", "content": "How old?" }],
+                    "response_format": response_format.clone()
+                }),
+                status: 200,
+                headers: Default::default(),
+                body: serde_json::json!({
+                    "id": "cmpl-1",
+                    "object": "chat.completion",
+                    "created": 0,
+                    "model": "grok-4",
+                    "choices": [{
+                        "index": 0,
+                        "message": { "role": "assistant", "content": "{\"age\": \"old\"}" },
+                        "finish_reason": "stop"
+                    }]
+                })
+                .to_string(),
+            }],
+        };
+        let client = grok_rust_sdk::Client::builder()
+            .api_key("unused")
+            .vcr(Vcr::from_cassette("schema.json", cassette))
+            .build()
+            .unwrap();
+
+        let messages = vec![Message {
+            role: Role::User,
+            content: "How old?".into(),
+            tool_calls: None,
+            tool_call_id: None,
+            name: None,
+        }];
+        let options = ChatOptions {
+            response_format: Some(response_format),
+            schema_repair_attempts: Some(0),
+            ..Default::default()
+        };
+        let err = client
+            .chat_with_options(Model::Grok4, messages, None, Some(options))
+            .await
+            .unwrap_err();
+        let GrokError::SchemaValidation { payload, errors } = err else {
+            panic!("expected a schema validation error, got {:?}", err);
+        };
+        assert_eq!(payload, r#"{"age": "old"}"#);
+        assert_eq!(errors.len(), 1);
+        assert!(errors[0].starts_with("/age: "));
+    }
+
+    #[tokio::test]
+    async fn test_chat_stream_with_progress_callback() {
+        use grok_rust_sdk::chat::StreamProgress;
+        use grok_rust_sdk::vcr::{Cassette, Interaction, Vcr};
+
+        let chunk = |delta: serde_json::Value| {
+            serde_json::json!({
+                "id": "chatcmpl-1",
+                "object": "chat.completion.chunk",
+                "created": 0,
+                "model": "grok-4",
+                "choices": [{ "index": 0, "delta": delta, "finish_reason": null }]
+            })
+        };
+        let body = format!(
+            "data: {}\n\ndata: {}\n\ndata: [DONE]\n\n",
+            chunk(serde_json::json!({ "content": "Checking" })),
+            chunk(serde_json::json!({
+                "tool_calls": [{ "index": 0, "id": "call_1", "function": { "name": "lookup" } }]
+            })),
+        );
+        let cassette = Cassette {
+            interactions: vec![Interaction {
+                endpoint: "/chat/completions".to_string(),
+                request: serde_json::json!({
+                    "model": "grok-4",
+                    "messages": [{ "role": "user", "content": "Hi" }],
+                    "stream": true
+                }),
+                status: 200,
+                headers: Default::default(),
+                body,
+            }],
+        };
+        let client = grok_rust_sdk::Client::builder()
+            .api_key("unused")
+            .vcr(Vcr::from_cassette("progress.json", cassette))
+            .build()
+            .unwrap();
+
+        let message = Message {
+            role: Role::User,
+            content: "Hi".into(),
+            tool_calls: None,
+            tool_call_id: None,
+            name: None,
+        };
+        let mut seen = Vec::new();
+        let completion = client
+            .chat_stream_with(Model::Grok4, vec![message], None, |event| {
+                seen.push(match event {
+                    StreamProgress::Start => "start".to_string(),
+                    StreamProgress::Token(text) => text.to_string(),
+                    StreamProgress::ToolCallProgress(delta) => {
+                        delta.function.as_ref().unwrap().name.clone().unwrap()
+                    }
+                    StreamProgress::Finish(completion) => {
+                        format!("finish: {}", completion.message.content)
+                    }
+                })
+            })
+            .await
+            .unwrap();
+        assert_eq!(seen, ["start", "Checking", "lookup", "finish: Checking"]);
+        assert_eq!(completion.message.tool_calls.unwrap().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_rate_limiter_holds_requests_back() {
+        use grok_rust_sdk::chat::RateLimitInfo;
+        use grok_rust_sdk::rate_limit::RateLimiter;
+        use std::time::{Duration, Instant};
+
+        let limiter = RateLimiter::new()
+            .requests_per_minute(2)
+            .tokens_per_minute(150);
+
+        // Reservations are corrected to the usage actually reported
+        let permit = limiter.acquire(100).await;
+        assert_eq!(limiter.used(), (1, 100));
+        limiter.settle(permit, 20);
+        limiter.acquire(100).await;
+        assert_eq!(limiter.used(), (2, 120));
+
+        // Both limits are now reached, so the next request has to wait
+        let third = tokio::time::timeout(Duration::from_millis(50), limiter.acquire(10)).await;
+        assert!(third.is_err());
+
+        // A quota the server reports as used up holds requests until it resets
+        let limiter = RateLimiter::new();
+        limiter.observe(&RateLimitInfo {
+            remaining_requests: Some(0),
+            reset_requests: Some(Duration::from_millis(100)),
+            ..Default::default()
+        });
+        let started = Instant::now();
+        limiter.acquire(1).await;
+        assert!(started.elapsed() >= Duration::from_millis(90));
+    }
+
+    #[tokio::test]
+    async fn test_session_budget_blocks_turns() {
+        use grok_rust_sdk::budget::{Budget, BudgetLimit, BudgetWindow};
+        use grok_rust_sdk::chat::{ChatCompletion, Tool, Usage};
+        use grok_rust_sdk::client::ChatOptions;
+        use grok_rust_sdk::provider::{ChatProvider, ChunkStream};
+        use std::sync::Arc;
+
+        /// Replies with a fixed usage of 30 tokens
+        #[derive(Debug)]
+        struct Costly;
+
+        #[async_trait]
+        impl ChatProvider for Costly {
+            async fn chat(
+                &self,
+                model: Model,
+                _messages: Vec<Arc<Message>>,
+                _tools: Option<Vec<Tool>>,
+                _options: Option<ChatOptions>,
+            ) -> grok_rust_sdk::Result<ChatCompletion> {
+                Ok(ChatCompletion {
+                    id: "costly".to_string(),
+                    model: model.as_str().to_string(),
+                    usage: Some(Usage {
+                        prompt_tokens: 20,
+                        completion_tokens: 10,
+                        total_tokens: 30,
+                    }),
+                    message: Message {
+                        role: Role::Assistant,
+                        content: "ok".into(),
+                        tool_calls: None,
+                        tool_call_id: None,
+                        name: None,
+                    },
+                    finish_reason: Some("stop".to_string()),
+                    timings: None,
+                    annotations: Vec::new(),
+                    rate_limit: None,
+                })
+            }
+
+            async fn chat_stream(
+                &self,
+                _model: Model,
+                _messages: Vec<Message>,
+                _tools: Option<Vec<Tool>>,
+            ) -> grok_rust_sdk::Result<ChunkStream> {
+                Ok(Box::pin(futures::stream::empty()))
+            }
+        }
+
+        let budget = Arc::new(Budget::new().max_tokens(50, BudgetWindow::Hour));
+        let mut session =
+            grok_rust_sdk::session::Session::new(Arc::new(Costly), Model::Grok4, None);
+        session.set_budget(budget.clone());
+
+        session.chat("one").await.unwrap();
+        session.chat("two").await.unwrap();
+        assert_eq!(budget.spent(BudgetWindow::Hour).tokens, 60);
+
+        // The second turn crossed the limit, so the third is refused before it's added
+        match session.chat("three").await {
+            Err(GrokError::BudgetExceeded {
+                limit: BudgetLimit::Tokens(50),
+                window: BudgetWindow::Hour,
+                resets_in,
+            }) => assert!(resets_in <= BudgetWindow::Hour.duration()),
+            other => panic!("expected BudgetExceeded, got {:?}", other.map(|c| c.id)),
+        }
+        assert_eq!(session.message_count().await, 4);
+    }
+
+    #[tokio::test]
+    async fn test_replay_session_against_another_model() {
+        use grok_rust_sdk::chat::{ChatCompletion, Tool};
+        use grok_rust_sdk::client::ChatOptions;
+        use grok_rust_sdk::provider::{ChatProvider, ChunkStream};
+        use grok_rust_sdk::replay::{DiffLine, ReplayOptions};
+        use grok_rust_sdk::session::SessionManager;
+        use std::sync::Arc;
+
+        /// Answers with the model name and the number of messages it was sent
+        #[derive(Debug)]
+        struct ByModel;
+
+        #[async_trait]
+        impl ChatProvider for ByModel {
+            async fn chat(
+                &self,
+                model: Model,
+                messages: Vec<Arc<Message>>,
+                _tools: Option<Vec<Tool>>,
+                _options: Option<ChatOptions>,
+            ) -> grok_rust_sdk::Result<ChatCompletion> {
+                Ok(ChatCompletion {
+                    id: "by-model".to_string(),
+                    model: model.as_str().to_string(),
+                    usage: None,
+                    message: Message {
+                        role: Role::Assistant,
+                        content: format!("Seen {}\nfrom {}", messages.len(), model).into(),
+                        tool_calls: None,
+                        tool_call_id: None,
+                        name: None,
+                    },
+                    finish_reason: Some("stop".to_string()),
+                    timings: None,
+                    annotations: Vec::new(),
+                    rate_limit: None,
+                })
+            }
+
+            async fn chat_stream(
+                &self,
+                _model: Model,
+                _messages: Vec<Message>,
+                _tools: Option<Vec<Tool>>,
+            ) -> grok_rust_sdk::Result<ChunkStream> {
+                Ok(Box::pin(futures::stream::empty()))
+            }
+        }
+
+        let manager = SessionManager::new(Arc::new(ByModel));
+        let session = manager.create_session(Model::Grok3, None).await;
+        session.chat("first").await.unwrap();
+        session.chat("second").await.unwrap();
+
+        let report = manager
+            .replay(&session.id, Model::Grok4, ReplayOptions::default())
+            .await
+            .unwrap();
+        assert_eq!(report.original_model, Model::Grok3);
+        assert_eq!(report.turns.len(), 2);
+        assert_eq!(report.changed_turns(), 2);
+        assert_eq!(report.replay_usage.completions, 2);
+
+        // Each turn sees the original conversation up to it, nothing after
+        let second = &report.turns[1];
+        assert_eq!(second.user, "second");
+        assert_eq!(
+            second.diff(),
+            vec![
+                DiffLine::Same("Seen 3".to_string()),
+                DiffLine::Removed(format!("from {}", Model::Grok3)),
+                DiffLine::Added(format!("from {}", Model::Grok4)),
+            ]
+        );
+        assert_eq!(session.message_count().await, 4);
+
+        assert!(manager
+            .replay("missing", Model::Grok4, ReplayOptions::default())
+            .await
+            .is_err());
+    }
+
+    #[tokio::test]
+    async fn test_session_falls_back_to_next_model() {
+        use grok_rust_sdk::chat::{ChatCompletion, Tool};
+        use grok_rust_sdk::client::ChatOptions;
+        use grok_rust_sdk::provider::{ChatProvider, ChunkStream};
+        use grok_rust_sdk::session::Session;
+        use std::sync::Arc;
+
+        /// Grok-4 is at capacity, Grok-2 rejects every request, the rest answer
+        #[derive(Debug)]
+        struct Overloaded;
+
+        #[async_trait]
+        impl ChatProvider for Overloaded {
+            async fn chat(
+                &self,
+                model: Model,
+                _messages: Vec<Arc<Message>>,
+                _tools: Option<Vec<Tool>>,
+                _options: Option<ChatOptions>,
+            ) -> grok_rust_sdk::Result<ChatCompletion> {
+                match model {
+                    Model::Grok4 => Err(GrokError::Api(Box::new(ApiError::new(
+                        503,
+                        "model at capacity",
+                    )))),
+                    Model::Grok2 => {
+                        Err(GrokError::Api(Box::new(ApiError::new(400, "bad request"))))
+                    }
+                    _ => Ok(ChatCompletion {
+                        id: "fallback".to_string(),
+                        model: model.as_str().to_string(),
+                        usage: None,
+                        message: Message {
+                            role: Role::Assistant,
+                            content: "answered".into(),
+                            tool_calls: None,
+                            tool_call_id: None,
+                            name: None,
+                        },
+                        finish_reason: Some("stop".to_string()),
+                        timings: None,
+                        annotations: Vec::new(),
+                        rate_limit: None,
+                    }),
+                }
+            }
+
+            async fn chat_stream(
+                &self,
+                _model: Model,
+                _messages: Vec<Message>,
+                _tools: Option<Vec<Tool>>,
+            ) -> grok_rust_sdk::Result<ChunkStream> {
+                Ok(Box::pin(futures::stream::empty()))
+            }
+        }
+
+        assert!(
+            GrokError::Api(Box::new(ApiError::new(503, "model at capacity"))).should_fall_back()
+        );
+        assert!(!GrokError::Api(Box::new(ApiError::new(400, "bad request"))).should_fall_back());
+
+        let mut session = Session::new(Arc::new(Overloaded), Model::Grok4, None);
+        session.set_fallback_models(vec![Model::Grok4FastReasoning, Model::Grok3]);
+        let reply = session.chat("hello").await.unwrap();
+        assert_eq!(reply.model, Model::Grok4FastReasoning.as_str());
+        assert_eq!(session.message_count().await, 2);
+
+        // Errors another model would repeat end the chain
+        let mut session = Session::new(Arc::new(Overloaded), Model::Grok2, None);
+        session.set_fallback_models(vec![Model::Grok3]);
+        assert_eq!(
+            session.chat("hello").await.unwrap_err().kind(),
+            ErrorKind::InvalidRequest
+        );
+
+        // Without fallbacks the model's own failure is returned
+        let session = Session::new(Arc::new(Overloaded), Model::Grok4, None);
+        assert_eq!(
+            session.chat("hello").await.unwrap_err().kind(),
+            ErrorKind::Server
+        );
+    }
+
+    #[tokio::test]
+    async fn test_managers_persist_to_storage() {
+        use grok_rust_sdk::collections::CollectionManager;
+        use grok_rust_sdk::session::SessionManager;
+        use std::sync::Arc;
+
+        let client = Arc::new(grok_rust_sdk::Client::new("test-key").unwrap());
+        let storage = Arc::new(MemoryStorage::new());
+        let sessions = Arc::new(SessionManager::with_storage(
+            client.clone(),
+            storage.clone(),
+        ));
+        let session = sessions
+            .create_session(Model::Grok4, Some("Saved".to_string()))
+            .await;
+        session
+            .append(Message {
+                role: Role::User,
+                content: "Remember me".into(),
+                tool_calls: None,
+                tool_call_id: None,
+                name: None,
+            })
+            .await
+            .unwrap();
+        assert_eq!(
+            storage.list_sessions().await.unwrap(),
+            vec![session.id.clone()]
+        );
+
+        let collections = CollectionManager::new(sessions.clone());
+        let collection = collections
+            .create_collection("saved", None, vec![])
+            .await
+            .unwrap();
+        collection.add_session(session.clone()).await.unwrap();
+        assert!(storage
+            .load_collection(&collection.id)
+            .await
+            .unwrap()
+            .is_some());
+
+        // A fresh manager over the same storage loads the session on demand
+        let restarted = SessionManager::with_storage(client, storage.clone());
+        assert!(restarted.get_session(&session.id).await.is_none());
+        let loaded = restarted.load_session(&session.id).await.unwrap().unwrap();
+        assert_eq!(loaded.messages().await[0].content, "Remember me");
+        assert!(restarted.get_session(&session.id).await.is_some());
+
+        restarted.delete_session(&session.id).await.unwrap();
+        assert!(storage.list_sessions().await.unwrap().is_empty());
+        assert!(restarted.load_session(&session.id).await.unwrap().is_none());
+    }
+
+    #[cfg(feature = "persistence")]
+    #[tokio::test]
+    async fn test_tenants_are_isolated() {
+        use grok_rust_sdk::collections::CollectionManager;
+        use grok_rust_sdk::session::SessionManager;
+        use std::sync::Arc;
+
+        let client = Arc::new(grok_rust_sdk::Client::new("test-key").unwrap());
+        let sessions = Arc::new(SessionManager::new(client));
+        let acme = sessions
+            .create_session_for("acme", Model::Grok4, None)
+            .await;
+        let globex = sessions
+            .create_session_for("globex", Model::Grok4, None)
+            .await;
+        assert!(sessions.get_session_for("acme", &acme.id).await.is_some());
+        assert!(sessions.get_session_for("globex", &acme.id).await.is_none());
+        assert_eq!(sessions.list_sessions_for("acme").await.unwrap().len(), 1);
+        assert!(sessions
+            .delete_session_for("globex", &acme.id)
+            .await
+            .is_err());
+
+        let collections = CollectionManager::new(sessions.clone());
+        let collection = collections
+            .create_collection_for("acme", "support", None, vec![])
+            .await
+            .unwrap();
+        collection.add_session(acme.clone()).await.unwrap();
+        assert!(collection.add_session(globex.clone()).await.is_err());
+        assert!(collections
+            .get_collection_for("globex", &collection.id)
+            .await
+            .is_none());
+        assert_eq!(
+            collections
+                .stats_for("globex")
+                .await
+                .unwrap()
+                .total_collections,
+            0
+        );
+
+        let storage = SqliteStorage::in_memory().unwrap();
+        acme.append(Message {
+            role: Role::User,
+            content: "My invoice is overdue".into(),
+            tool_calls: None,
+            tool_call_id: None,
+            name: None,
+        })
+        .await
+        .unwrap();
+        storage.save_session(&acme).await.unwrap();
+        storage.save_session(&globex).await.unwrap();
+        storage.save_collection(&collection).await.unwrap();
+
+        let loaded = storage.load_session_for("acme", &acme.id).await.unwrap();
+        assert_eq!(loaded.unwrap().tenant(), Some("acme"));
+        assert!(storage
+            .load_session_for("globex", &acme.id)
+            .await
+            .unwrap()
+            .is_none());
+        assert!(storage
+            .load_collection_for("globex", &collection.id)
+            .await
+            .unwrap()
+            .is_none());
+
+        let query = SessionQuery {
+            tenant: Some("globex".to_string()),
+            ..Default::default()
+        };
+        let summaries = storage.query_sessions(&query).await.unwrap();
+        assert_eq!(summaries.len(), 1);
+        assert_eq!(summaries[0].id, globex.id);
+
+        assert_eq!(
+            storage
+                .search_messages_for("acme", "invoice")
+                .await
+                .unwrap()
+                .len(),
+            1
+        );
+        assert!(storage
+            .search_messages_for("globex", "invoice")
+            .await
+            .unwrap()
+            .is_empty());
+
+        let stats = storage.tenant_stats("acme").await.unwrap();
+        assert_eq!(
+            (stats.sessions, stats.messages, stats.collections),
+            (1, 1, 1)
+        );
+    }
+
+    #[cfg(feature = "persistence")]
+    #[tokio::test]
+    async fn test_tenant_lookups_reach_storage() {
+        use grok_rust_sdk::collections::CollectionManager;
+        use grok_rust_sdk::session::SessionManager;
+        use std::sync::Arc;
+
+        let client = Arc::new(grok_rust_sdk::Client::new("test-key").unwrap());
+        let storage = Arc::new(SqliteStorage::in_memory().unwrap());
+        let sessions = Arc::new(SessionManager::with_storage(
+            client.clone(),
+            storage.clone(),
+        ));
+        let acme = sessions
+            .create_session_for("acme", Model::Grok4, None)
+            .await;
+        let collection = CollectionManager::new(sessions.clone())
+            .create_collection_for("acme", "support", None, vec![])
+            .await
+            .unwrap();
+        collection.add_session(acme.clone()).await.unwrap();
+        sessions.flush_all().await.unwrap();
+
+        // A fresh manager over the same storage has nothing in memory
+        let sessions = Arc::new(SessionManager::with_storage(client, storage.clone()));
+        let collections = CollectionManager::new(sessions.clone());
+        assert!(sessions
+            .load_session_for("globex", &acme.id)
+            .await
+            .unwrap()
+            .is_none());
+        assert!(sessions.get_session(&acme.id).await.is_none());
+        assert!(collections
+            .load_collection_for("globex", &collection.id)
+            .await
+            .unwrap()
+            .is_none());
+        assert!(sessions
+            .delete_session_for("globex", &acme.id)
+            .await
+            .is_err());
+
+        let listed = sessions.list_sessions_for("acme").await.unwrap();
+        assert_eq!(listed.len(), 1);
+        assert_eq!(listed[0].id, acme.id);
+        assert!(sessions
+            .list_sessions_for("globex")
+            .await
+            .unwrap()
+            .is_empty());
+        let stats = collections.stats_for("acme").await.unwrap();
+        assert_eq!((stats.total_collections, stats.total_sessions), (1, 1));
+
+        collections
+            .delete_collection_for("acme", &collection.id)
+            .await
+            .unwrap();
+        sessions.delete_session_for("acme", &acme.id).await.unwrap();
+        assert!(storage.list_sessions_for("acme").await.unwrap().is_empty());
+        assert!(storage.list_collections().await.unwrap().is_empty());
+    }
+
+    #[cfg(feature = "openai")]
+    #[test]
+    fn test_openai_message_round_trip() {
+        use async_openai::types::{
+            ChatCompletionRequestDeveloperMessage, ChatCompletionRequestFunctionMessage,
+            ChatCompletionRequestMessage, ChatCompletionRequestMessageContentPartAudio,
+            ChatCompletionRequestMessageContentPartText, ChatCompletionRequestUserMessage,
+            ChatCompletionRequestUserMessageContent, ChatCompletionRequestUserMessageContentPart,
+            ImageDetail as OpenAiImageDetail,
+        };
+        use grok_rust_sdk::chat::{ContentPart, ImageDetail, ToolCall, ToolFunction};
+
+        let message = |role, content: grok_rust_sdk::chat::MessageContent| Message {
+            role,
+            content,
+            tool_calls: None,
+            tool_call_id: None,
+            name: None,
+        };
+        let messages = [
+            Message {
+                name: Some("setup".to_string()),
+                ..message(Role::System, "Be brief".into())
+            },
+            message(
+                Role::User,
+                grok_rust_sdk::chat::MessageContent::Parts(vec![
+                    ContentPart::text("What are these?"),
+                    ContentPart::image_url("https://example.com/cat.png")
+                        .with_detail(ImageDetail::High),
+                    ContentPart::base64_image("image/png", "aGk="),
+                ]),
+            ),
+            Message {
+                tool_calls: Some(vec![ToolCall {
+                    id: "call-1".to_string(),
+                    function: ToolFunction {
+                        name: "mock_tool".to_string(),
+                        arguments: r#"{"input":"ping"}"#.to_string(),
+                    },
+                }]),
+                ..message(Role::Assistant, "".into())
+            },
+            Message {
+                tool_call_id: Some("call-1".to_string()),
+                ..message(Role::Tool, "pong".into())
+            },
+        ];
+
+        for original in messages {
+            let converted = ChatCompletionRequestMessage::from(original.clone());
+            let back = Message::try_from(converted).unwrap();
+            assert_eq!(
+                serde_json::to_value(&back).unwrap(),
+                serde_json::to_value(&original).unwrap()
+            );
+        }
+
+        // Images become parts with their detail; base64 images become data URLs
+        let user = ChatCompletionRequestMessage::from(message(
+            Role::User,
+            grok_rust_sdk::chat::MessageContent::Parts(vec![
+                ContentPart::image_url("https://example.com/cat.png").with_detail(ImageDetail::Low),
+                ContentPart::base64_image("image/png", "aGk="),
+            ]),
+        ));
+        let ChatCompletionRequestMessage::User(ChatCompletionRequestUserMessage {
+            content: ChatCompletionRequestUserMessageContent::Array(parts),
+            ..
+        }) = user
+        else {
+            panic!("expected a user message with parts, got {:?}", user);
+        };
+        let urls: Vec<_> = parts
+            .iter()
+            .map(|part| match part {
+                ChatCompletionRequestUserMessageContentPart::ImageUrl(image) => {
+                    (image.image_url.url.as_str(), image.image_url.detail.clone())
+                }
+                other => panic!("expected an image part, got {:?}", other),
+            })
+            .collect();
+        assert_eq!(
+            urls,
+            [
+                ("https://example.com/cat.png", Some(OpenAiImageDetail::Low)),
+                ("data:image/png;base64,aGk=", None),
+            ]
+        );
+
+        // A tool-calling turn without text has no content
+        let ChatCompletionRequestMessage::Assistant(assistant) =
+            ChatCompletionRequestMessage::from(Message {
+                tool_calls: Some(Vec::new()),
+                ..message(Role::Assistant, "".into())
+            })
+        else {
+            panic!("expected an assistant message");
+        };
+        assert!(assistant.content.is_none());
+
+        // Developer messages become system messages; text-only parts are joined
+        let developer =
+            ChatCompletionRequestMessage::Developer(ChatCompletionRequestDeveloperMessage {
+                content: "Use metric units".into(),
+                name: None,
+            });
+        let back = Message::try_from(developer).unwrap();
+        assert_eq!(
+            (back.role, back.content.text()),
+            (Role::System, "Use metric units".into())
+        );
+
+        let text = |text: &str| {
+            ChatCompletionRequestUserMessageContentPart::Text(
+                ChatCompletionRequestMessageContentPartText {
+                    text: text.to_string(),
+                },
+            )
+        };
+        let joined = ChatCompletionRequestMessage::User(ChatCompletionRequestUserMessage {
+            content: ChatCompletionRequestUserMessageContent::Array(vec![
+                text("Hello, "),
+                text("world"),
+            ]),
+            name: None,
+        });
+        assert_eq!(Message::try_from(joined).unwrap().content, "Hello, world");
+
+        // Audio and deprecated function messages can't be represented
+        let audio = ChatCompletionRequestMessage::User(ChatCompletionRequestUserMessage {
+            content: ChatCompletionRequestUserMessageContent::Array(vec![
+                text("Listen"),
+                ChatCompletionRequestUserMessageContentPart::InputAudio(
+                    ChatCompletionRequestMessageContentPartAudio::default(),
+                ),
+            ]),
+            name: None,
+        });
+        assert!(matches!(
+            Message::try_from(audio),
+            Err(GrokError::InvalidConfig(_))
+        ));
+        let function =
+            ChatCompletionRequestMessage::Function(ChatCompletionRequestFunctionMessage {
+                content: Some("42".to_string()),
+                name: "answer".to_string(),
+            });
+        assert!(matches!(
+            Message::try_from(function),
+            Err(GrokError::InvalidConfig(_))
+        ));
+    }
+
+    #[cfg(feature = "openai")]
+    #[test]
+    fn test_openai_completion_round_trip() {
+        use async_openai::types::{CreateChatCompletionResponse, FinishReason};
+        use grok_rust_sdk::chat::{ChatCompletion, ToolCall, ToolFunction, Usage};
+
+        let completion = |finish_reason: &str| ChatCompletion {
+            id: "cmpl-1".to_string(),
+            model: "grok-4".to_string(),
+            usage: Some(Usage {
+                prompt_tokens: 10,
+                completion_tokens: 5,
+                total_tokens: 15,
+            }),
+            message: Message {
+                role: Role::Assistant,
+                content: "Checking".into(),
+                tool_calls: Some(vec![ToolCall {
+                    id: "call-1".to_string(),
+                    function: ToolFunction {
+                        name: "mock_tool".to_string(),
+                        arguments: r#"{"input":"ping"}"#.to_string(),
+                    },
+                }]),
+                tool_call_id: None,
+                name: None,
+            },
+            finish_reason: Some(finish_reason.to_string()),
+            timings: None,
+            annotations: Vec::new(),
+            rate_limit: None,
+        };
+
+        let original = completion("tool_calls");
+        let response = CreateChatCompletionResponse::from(completion("tool_calls"));
+        assert_eq!(response.object, "chat.completion");
+        assert_eq!(response.choices.len(), 1);
+        assert_eq!(
+            response.choices[0].finish_reason,
+            Some(FinishReason::ToolCalls)
+        );
+
+        let back = ChatCompletion::try_from(response).unwrap();
+        assert_eq!(
+            (back.id.as_str(), back.model.as_str()),
+            ("cmpl-1", "grok-4")
+        );
+        assert_eq!(back.finish_reason.as_deref(), Some("tool_calls"));
+        let usage = back.usage.unwrap();
+        assert_eq!(
+            (
+                usage.prompt_tokens,
+                usage.completion_tokens,
+                usage.total_tokens
+            ),
+            (10, 5, 15)
+        );
+        assert_eq!(
+            serde_json::to_value(&back.message).unwrap(),
+            serde_json::to_value(&original.message).unwrap()
+        );
+
+        // Finish reasons OpenAI doesn't know are dropped
+        let response = CreateChatCompletionResponse::from(completion("end_turn"));
+        assert_eq!(response.choices[0].finish_reason, None);
+
+        // A refusal stands in for missing content
+        let mut refused = response.clone();
+        refused.choices[0].message.content = None;
+        refused.choices[0].message.refusal = Some("I can't help with that".to_string());
+        let back = ChatCompletion::try_from(refused).unwrap();
+        assert_eq!(back.message.content, "I can't help with that");
+        assert_eq!(back.finish_reason, None);
+
+        let mut empty = response;
+        empty.choices.clear();
+        assert!(matches!(
+            ChatCompletion::try_from(empty),
+            Err(GrokError::InvalidConfig(_))
+        ));
+    }
+
+    #[cfg(feature = "axum")]
+    #[tokio::test]
+    async fn test_server_chat_stream() {
+        use axum::body::Body;
+        use axum::http::{header, Request, StatusCode};
+        use grok_rust_sdk::server::{router, ChatState};
+        use grok_rust_sdk::vcr::{Cassette, Interaction, Vcr};
+        use tower::ServiceExt;
+
+        let chunk = |content: &str, finish_reason: Option<&str>| {
+            serde_json::json!({
+                "id": "chatcmpl-1",
+                "object": "chat.completion.chunk",
+                "created": 0,
+                "model": "grok-4",
+                "choices": [{
+                    "index": 0,
+                    "delta": { "content": content },
+                    "finish_reason": finish_reason
+                }]
+            })
+        };
+        let body = format!(
+            "data: {}\n\ndata: {}\n\ndata: [DONE]\n\n",
+            chunk("Hel", None),
+            chunk("lo!", Some("stop"))
+        );
+        let cassette = Cassette {
+            interactions: vec![Interaction {
+                endpoint: "/chat/completions".to_string(),
+                request: serde_json::json!({
+                    "model": "grok-4",
+                    "messages": [{ "role": "user", "content": "Hi" }],
+                    "stream": true
+                }),
+                status: 200,
+                headers: Default::default(),
+                body,
+            }],
+        };
+        let client = grok_rust_sdk::Client::builder()
+            .api_key("unused")
+            .vcr(Vcr::from_cassette("server-stream.json", cassette))
+            .build()
+            .unwrap();
+        let app = router(ChatState::new(std::sync::Arc::new(client)));
+
+        let request = Request::post("/chat/stream")
+            .header(header::CONTENT_TYPE, "application/json")
+            .body(Body::from(
+                serde_json::json!({
+                    "model": "grok-4",
+                    "messages": [{ "role": "user", "content": "Hi" }]
+                })
+                .to_string(),
+            ))
+            .unwrap();
+        let response = app.oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(
+            response.headers()[header::CONTENT_TYPE],
+            "text/event-stream"
+        );
+
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let body = String::from_utf8(body.to_vec()).unwrap();
+        let events: Vec<_> = body
+            .lines()
+            .filter_map(|line| line.strip_prefix("data: "))
+            .collect();
+        assert_eq!(events.len(), 3);
+        let contents: Vec<_> = events[..2]
+            .iter()
+            .map(|data| {
+                let chunk: serde_json::Value = serde_json::from_str(data).unwrap();
+                chunk["choices"][0]["delta"]["content"]
+                    .as_str()
+                    .unwrap()
+                    .to_string()
+            })
+            .collect();
+        assert_eq!(contents, ["Hel", "lo!"]);
+        assert_eq!(events[2], "[DONE]");
+    }
+
+    #[cfg(feature = "axum")]
+    #[tokio::test]
+    async fn test_server_unknown_session_is_not_found() {
+        use axum::body::Body;
+        use axum::http::{header, Request, StatusCode};
+        use grok_rust_sdk::server::{router, ChatState};
+        use tower::ServiceExt;
+
+        let client = grok_rust_sdk::Client::new("unused").unwrap();
+        let app = router(ChatState::new(std::sync::Arc::new(client)));
+
+        let mut request = Request::get("/sessions/ws?session=missing")
+            .header(header::CONNECTION, "upgrade")
+            .header(header::UPGRADE, "websocket")
+            .header(header::SEC_WEBSOCKET_VERSION, "13")
+            .header(header::SEC_WEBSOCKET_KEY, "dGhlIHNhbXBsZSBub25jZQ==")
+            .body(Body::empty())
+            .unwrap();
+        // Outside a real server there is no connection to upgrade; an empty
+        // handle lets the handler run and answer before upgrading
+        let on_upgrade = hyper::upgrade::on(&mut request);
+        request.extensions_mut().insert(on_upgrade);
+
+        let response = app.oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(body["error"]["kind"], "NotFound");
+        assert_eq!(body["error"]["message"], "Session 'missing' not found");
+    }
+
     // Note: Integration tests with actual API calls would require XAI_API_KEY
     // and are not included here to avoid requiring API keys for basic testing
 }